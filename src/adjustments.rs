@@ -0,0 +1,57 @@
+use image::RgbaImage;
+
+/// Display adjustments applied after normalization and transforms, the image data is not changed
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Adjustments {
+    /// Exposure in stops, each stop doubles the values
+    pub exposure: f32,
+    /// Added to the values, -1.0..=1.0 of the full range
+    pub brightness: f32,
+    /// -1.0..=1.0, scales the values around mid gray
+    pub contrast: f32,
+    /// Gamma of the output curve, values above 1.0 brighten the midtones
+    pub gamma: f32,
+    /// 0.0 is grayscale, 1.0 unchanged, 2.0 doubles the chroma
+    pub saturation: f32,
+}
+
+impl Default for Adjustments {
+    fn default() -> Self {
+        Self { exposure: 0.0, brightness: 0.0, contrast: 0.0, gamma: 1.0, saturation: 1.0 }
+    }
+}
+
+impl Adjustments {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Tone curve of exposure, contrast, brightness and gamma for one 8-bit value
+    fn curve(&self, value: u8) -> u8 {
+        let mut v = value as f32 / 255.0 * 2f32.powf(self.exposure);
+        // Contrast -1 flattens to mid gray, +1 quadruples the slope
+        let slope = if self.contrast >= 0.0 { 1.0 + 3.0 * self.contrast } else { 1.0 + self.contrast };
+        v = (v - 0.5) * slope + 0.5 + self.brightness;
+        v = v.clamp(0.0, 1.0).powf(1.0 / self.gamma.max(0.01));
+        (v * 255.0).round() as u8
+    }
+
+    /// Apply the adjustments to the color channels, alpha is kept
+    pub fn apply(&self, image: &mut RgbaImage) {
+        if self.is_identity() {
+            return;
+        }
+        let lut: Vec<u8> = (0..=255).map(|v| self.curve(v)).collect();
+        let saturate = self.saturation != 1.0;
+        for pixel in image.pixels_mut() {
+            let [r, g, b] = [lut[pixel[0] as usize], lut[pixel[1] as usize], lut[pixel[2] as usize]];
+            if saturate {
+                let luma = 0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32;
+                let mix = |c: u8| (luma + (c as f32 - luma) * self.saturation).round().clamp(0.0, 255.0) as u8;
+                pixel.0 = [mix(r), mix(g), mix(b), pixel[3]];
+            } else {
+                pixel.0 = [r, g, b, pixel[3]];
+            }
+        }
+    }
+}
@@ -0,0 +1,423 @@
+//! Vector annotations drawn over the image, e.g. to mark defects when reviewing. They are kept
+//! in image pixel coordinates in a JSON sidecar next to the image and can be burned into an export.
+
+use ab_glyph::{Font, FontArc, PxScale, ScaleFont};
+use anyhow::{anyhow, Context};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::orientation::ViewMapping;
+
+/// Position in image pixels, fractional so shapes stay smooth when zoomed in
+pub type Point = [f32; 2];
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Shape {
+    Rectangle { min: Point, max: Point },
+    Arrow { from: Point, to: Point },
+    /// Text with its top left corner at `position`, `size` is the font height in pixels
+    Text { position: Point, text: String, size: f32 },
+    Freehand { points: Vec<Point> },
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Annotation {
+    #[serde(flatten)]
+    pub shape: Shape,
+    /// sRGBA, not premultiplied
+    pub color: [u8; 4],
+    /// Line width in image pixels
+    pub width: f32,
+}
+
+impl Annotation {
+    /// Line segments the shape is drawn with, text has none
+    pub fn segments(&self) -> Vec<(Point, Point)> {
+        match &self.shape {
+            Shape::Rectangle { min, max } => {
+                let corners = [*min, [max[0], min[1]], *max, [min[0], max[1]]];
+                (0..4).map(|i| (corners[i], corners[(i + 1) % 4])).collect()
+            }
+            Shape::Arrow { from, to } => {
+                let mut segments = vec![(*from, *to)];
+                segments.extend(arrow_head(*from, *to, self.width).map(|tip| (*to, tip)));
+                segments
+            }
+            Shape::Text { .. } => Vec::new(),
+            Shape::Freehand { points } if points.len() == 1 => vec![(points[0], points[0])],
+            Shape::Freehand { points } => points.windows(2).map(|pair| (pair[0], pair[1])).collect(),
+        }
+    }
+
+    /// Bounding box as (min, max), text estimated from its length
+    pub fn bounds(&self) -> (Point, Point) {
+        let points: Vec<Point> = match &self.shape {
+            Shape::Text { position, text, size } => {
+                let width = text.chars().count() as f32 * size * 0.55;
+                vec![*position, [position[0] + width, position[1] + size]]
+            }
+            _ => self.segments().into_iter().flat_map(|(a, b)| [a, b]).collect(),
+        };
+        points.iter().fold(([f32::INFINITY; 2], [f32::NEG_INFINITY; 2]), |(min, max), p| {
+            ([min[0].min(p[0]), min[1].min(p[1])], [max[0].max(p[0]), max[1].max(p[1])])
+        })
+    }
+
+    /// Whether `point` is on the shape, within `tolerance` pixels of a line or inside text
+    pub fn hit(&self, point: Point, tolerance: f32) -> bool {
+        if let Shape::Text { .. } = self.shape {
+            let (min, max) = self.bounds();
+            return (min[0] - tolerance..=max[0] + tolerance).contains(&point[0])
+                && (min[1] - tolerance..=max[1] + tolerance).contains(&point[1]);
+        }
+        let reach = tolerance + self.width / 2.0;
+        self.segments().iter().any(|&(a, b)| segment_distance(point, a, b) <= reach)
+    }
+
+    pub fn translate(&mut self, delta: Point) {
+        let shift = |p: &mut Point| {
+            p[0] += delta[0];
+            p[1] += delta[1];
+        };
+        match &mut self.shape {
+            Shape::Rectangle { min, max } => [min, max].into_iter().for_each(shift),
+            Shape::Arrow { from, to } => [from, to].into_iter().for_each(shift),
+            Shape::Text { position, .. } => shift(position),
+            Shape::Freehand { points } => points.iter_mut().for_each(shift),
+        }
+    }
+
+    /// The annotation with its points moved by `map` and its width and text size multiplied by
+    /// `scale`, e.g. to draw it into a rendering of the view
+    pub fn mapped(&self, map: impl Fn(Point) -> Point, scale: f32) -> Annotation {
+        let shape = match &self.shape {
+            Shape::Rectangle { min, max } => {
+                // Turned views swap the corners
+                let (a, b) = (map(*min), map(*max));
+                Shape::Rectangle { min: [a[0].min(b[0]), a[1].min(b[1])], max: [a[0].max(b[0]), a[1].max(b[1])] }
+            }
+            Shape::Arrow { from, to } => Shape::Arrow { from: map(*from), to: map(*to) },
+            Shape::Text { position, text, size } => Shape::Text { position: map(*position), text: text.clone(), size: size * scale },
+            Shape::Freehand { points } => Shape::Freehand { points: points.iter().map(|p| map(*p)).collect() },
+        };
+        Annotation { shape, color: self.color, width: self.width * scale }
+    }
+
+    /// Too small to keep, e.g. a click with the rectangle tool
+    pub fn is_degenerate(&self) -> bool {
+        match &self.shape {
+            Shape::Rectangle { min, max } => (max[0] - min[0]).abs() < 1.0 || (max[1] - min[1]).abs() < 1.0,
+            Shape::Arrow { from, to } => distance(*from, *to) < 1.0,
+            Shape::Text { text, .. } => text.trim().is_empty(),
+            Shape::Freehand { points } => points.is_empty(),
+        }
+    }
+
+    pub fn color32(&self) -> egui::Color32 {
+        let [r, g, b, a] = self.color;
+        egui::Color32::from_rgba_unmultiplied(r, g, b, a)
+    }
+}
+
+impl Annotation {
+    /// Draw on screen where `view` shows the image
+    pub fn paint(&self, painter: &egui::Painter, view: &ViewMapping) {
+        let to_screen = |p: Point| view.to_screen(egui::pos2(p[0], p[1]));
+        let scale = view.scale;
+        let color = self.color32();
+        let stroke = egui::Stroke::new((self.width * scale).max(1.0), color);
+        match &self.shape {
+            Shape::Rectangle { min, max } => {
+                let rect = egui::Rect::from_two_pos(to_screen(*min), to_screen(*max));
+                painter.rect_stroke(rect, egui::CornerRadius::ZERO, stroke, egui::StrokeKind::Middle);
+            }
+            Shape::Text { position, text, size } => {
+                painter.text(to_screen(*position), egui::Align2::LEFT_TOP, text, egui::FontId::proportional((size * scale).max(1.0)), color);
+            }
+            Shape::Freehand { points } if points.len() == 1 => {
+                painter.circle_filled(to_screen(points[0]), stroke.width / 2.0, color);
+            }
+            Shape::Freehand { points } => {
+                painter.add(egui::Shape::line(points.iter().map(|p| to_screen(*p)).collect(), stroke));
+            }
+            Shape::Arrow { .. } => {
+                for (a, b) in self.segments() {
+                    painter.line_segment([to_screen(a), to_screen(b)], stroke);
+                }
+            }
+        }
+    }
+}
+
+/// End points of the two strokes of an arrow head at `to`
+pub fn arrow_head(from: Point, to: Point, width: f32) -> [Point; 2] {
+    let length = distance(from, to);
+    if length < f32::EPSILON {
+        return [to, to];
+    }
+    let head = (4.0 * width).max(10.0).min(length * 0.5);
+    let (dx, dy) = ((from[0] - to[0]) / length, (from[1] - to[1]) / length);
+    // Rotate the backwards direction by ±30°
+    let (sin, cos) = (0.5, 0.866);
+    [
+        [to[0] + head * (dx * cos - dy * sin), to[1] + head * (dx * sin + dy * cos)],
+        [to[0] + head * (dx * cos + dy * sin), to[1] + head * (-dx * sin + dy * cos)],
+    ]
+}
+
+fn distance(a: Point, b: Point) -> f32 {
+    (a[0] - b[0]).hypot(a[1] - b[1])
+}
+
+/// Distance of `p` to the segment from `a` to `b`
+fn segment_distance(p: Point, a: Point, b: Point) -> f32 {
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let length_sq = dx * dx + dy * dy;
+    let t = if length_sq > 0.0 { (((p[0] - a[0]) * dx + (p[1] - a[1]) * dy) / length_sq).clamp(0.0, 1.0) } else { 0.0 };
+    distance(p, [a[0] + t * dx, a[1] + t * dy])
+}
+
+/// Tool of the annotation editor
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum AnnotationTool {
+    /// Click selects, drag moves the selected annotation
+    Select,
+    Rectangle,
+    Arrow,
+    Text,
+    Freehand,
+}
+
+impl AnnotationTool {
+    pub const ALL: [AnnotationTool; 5] = [
+        AnnotationTool::Select,
+        AnnotationTool::Rectangle,
+        AnnotationTool::Arrow,
+        AnnotationTool::Text,
+        AnnotationTool::Freehand,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnnotationTool::Select => "Select",
+            AnnotationTool::Rectangle => "Rectangle",
+            AnnotationTool::Arrow => "Arrow",
+            AnnotationTool::Text => "Text",
+            AnnotationTool::Freehand => "Freehand",
+        }
+    }
+}
+
+/// State of the annotation editor
+pub struct AnnotationEditor {
+    pub tool: AnnotationTool,
+    /// Style of new annotations
+    pub color: egui::Color32,
+    pub width: f32,
+    pub text: String,
+    pub text_size: f32,
+    /// Index into the annotations of the image
+    pub selected: Option<usize>,
+    /// Annotation being drawn
+    pub drawing: Option<Annotation>,
+    /// Last pointer position while moving the selected annotation
+    pub move_from: Option<Point>,
+}
+
+impl Default for AnnotationEditor {
+    fn default() -> Self {
+        Self {
+            tool: AnnotationTool::Rectangle,
+            color: egui::Color32::from_rgb(255, 40, 40),
+            width: 3.0,
+            text: String::new(),
+            text_size: 24.0,
+            selected: None,
+            drawing: None,
+            move_from: None,
+        }
+    }
+}
+
+impl AnnotationEditor {
+    /// Forget the selection and the shape being drawn, e.g. when another image is shown
+    pub fn cancel(&mut self) {
+        self.selected = None;
+        self.drawing = None;
+        self.move_from = None;
+    }
+
+    /// New annotation of the current tool starting at `point`
+    pub fn start(&self, point: Point) -> Option<Annotation> {
+        let shape = match self.tool {
+            AnnotationTool::Select => return None,
+            AnnotationTool::Rectangle => Shape::Rectangle { min: point, max: point },
+            AnnotationTool::Arrow => Shape::Arrow { from: point, to: point },
+            AnnotationTool::Text => Shape::Text { position: point, text: self.text.clone(), size: self.text_size },
+            AnnotationTool::Freehand => Shape::Freehand { points: vec![point] },
+        };
+        Some(Annotation { shape, color: self.color.to_srgba_unmultiplied(), width: self.width })
+    }
+
+    /// Take the finished annotation, `None` when it is too small to keep
+    pub fn finish(&mut self) -> Option<Annotation> {
+        let mut annotation = self.drawing.take()?;
+        if let Shape::Rectangle { min, max } = &mut annotation.shape {
+            (*min, *max) = ([min[0].min(max[0]), min[1].min(max[1])], [min[0].max(max[0]), min[1].max(max[1])]);
+        }
+        (!annotation.is_degenerate()).then_some(annotation)
+    }
+
+    /// Extend the annotation being drawn to `point`. `min_step` is the smallest distance
+    /// between freehand points.
+    pub fn extend(&mut self, point: Point, min_step: f32) {
+        let Some(annotation) = &mut self.drawing else {
+            return;
+        };
+        match &mut annotation.shape {
+            Shape::Rectangle { max, .. } => *max = point,
+            Shape::Arrow { to, .. } => *to = point,
+            Shape::Text { position, .. } => *position = point,
+            Shape::Freehand { points } => {
+                if points.last().is_none_or(|last| distance(*last, point) >= min_step) {
+                    points.push(point);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Sidecar {
+    version: u32,
+    annotations: Vec<Annotation>,
+}
+
+/// Sidecar of an image, `photo.jpg.annotations.json`
+pub fn sidecar_path(image: &Path) -> PathBuf {
+    let mut path = image.as_os_str().to_owned();
+    path.push(".annotations.json");
+    PathBuf::from(path)
+}
+
+/// Annotations from the sidecar, none when there is no sidecar
+pub fn load(image: &Path) -> anyhow::Result<Vec<Annotation>> {
+    let path = sidecar_path(image);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+    };
+    let sidecar: Sidecar = serde_json::from_str(&text).with_context(|| format!("Invalid annotations in {:?}", path))?;
+    Ok(sidecar.annotations)
+}
+
+/// Write the sidecar, an empty list removes it
+pub fn save(image: &Path, annotations: &[Annotation]) -> anyhow::Result<()> {
+    let path = sidecar_path(image);
+    if annotations.is_empty() {
+        return match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e).with_context(|| format!("Failed to remove {:?}", path)),
+            _ => Ok(()),
+        };
+    }
+    let sidecar = Sidecar { version: 1, annotations: annotations.to_vec() };
+    fs::write(&path, serde_json::to_string_pretty(&sidecar)?).with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// Font of the burned-in text, the proportional font of egui so it matches the screen
+fn text_font() -> anyhow::Result<FontArc> {
+    let definitions = egui::FontDefinitions::default();
+    let name = definitions.families.get(&egui::FontFamily::Proportional)
+        .and_then(|names| names.first())
+        .ok_or_else(|| anyhow!("No proportional font"))?;
+    let data = definitions.font_data.get(name).ok_or_else(|| anyhow!("Font {} is missing", name))?;
+    FontArc::try_from_vec(data.font.to_vec()).map_err(|e| anyhow!("Invalid font {}: {}", name, e))
+}
+
+/// Draw the annotations into an image
+pub fn burn_in(image: &mut RgbaImage, annotations: &[Annotation]) -> anyhow::Result<()> {
+    let font = if annotations.iter().any(|a| matches!(a.shape, Shape::Text { .. })) { Some(text_font()?) } else { None };
+    for annotation in annotations {
+        // Coverage of the shape over its bounding box, so overlapping segments are not blended twice
+        let (min, max) = match (&annotation.shape, &font) {
+            (Shape::Text { position, text, size }, Some(font)) => {
+                let scaled = font.as_scaled(PxScale::from(*size));
+                let width: f32 = text.chars().map(|c| scaled.h_advance(font.glyph_id(c))).sum();
+                (*position, [position[0] + width, position[1] + scaled.height()])
+            }
+            _ => annotation.bounds(),
+        };
+        let margin = annotation.width / 2.0 + 1.0;
+        let x0 = (min[0] - margin).floor().max(0.0) as u32;
+        let y0 = (min[1] - margin).floor().max(0.0) as u32;
+        let x1 = ((max[0] + margin).ceil().max(0.0) as u32).min(image.width());
+        let y1 = ((max[1] + margin).ceil().max(0.0) as u32).min(image.height());
+        if x1 <= x0 || y1 <= y0 {
+            continue;
+        }
+        let box_width = (x1 - x0) as usize;
+        let mut coverage = vec![0.0f32; box_width * (y1 - y0) as usize];
+
+        if let (Shape::Text { position, text, size }, Some(font)) = (&annotation.shape, &font) {
+            let scaled = font.as_scaled(PxScale::from(*size));
+            let mut caret = ab_glyph::point(position[0], position[1] + scaled.ascent());
+            let mut previous = None;
+            for c in text.chars() {
+                let id = font.glyph_id(c);
+                if let Some(previous) = previous {
+                    caret.x += scaled.kern(previous, id);
+                }
+                let glyph = id.with_scale_and_position(*size, caret);
+                caret.x += scaled.h_advance(id);
+                previous = Some(id);
+                let Some(outline) = font.outline_glyph(glyph) else {
+                    continue;
+                };
+                let bounds = outline.px_bounds();
+                outline.draw(|gx, gy, value| {
+                    let (x, y) = (bounds.min.x as i64 + gx as i64, bounds.min.y as i64 + gy as i64);
+                    if (x0 as i64..x1 as i64).contains(&x) && (y0 as i64..y1 as i64).contains(&y) {
+                        let cell = &mut coverage[(y - y0 as i64) as usize * box_width + (x - x0 as i64) as usize];
+                        *cell = cell.max(value);
+                    }
+                });
+            }
+        }
+
+        let radius = annotation.width / 2.0;
+        for (a, b) in annotation.segments() {
+            let sx0 = ((a[0].min(b[0]) - radius - 1.0).floor().max(x0 as f32) as u32).min(x1);
+            let sx1 = ((a[0].max(b[0]) + radius + 1.0).ceil().max(0.0) as u32).min(x1);
+            let sy0 = ((a[1].min(b[1]) - radius - 1.0).floor().max(y0 as f32) as u32).min(y1);
+            let sy1 = ((a[1].max(b[1]) + radius + 1.0).ceil().max(0.0) as u32).min(y1);
+            for y in sy0..sy1 {
+                for x in sx0..sx1 {
+                    let d = segment_distance([x as f32 + 0.5, y as f32 + 0.5], a, b);
+                    // Antialiased edge one pixel wide
+                    let value = (radius - d + 0.5).clamp(0.0, 1.0);
+                    let cell = &mut coverage[(y - y0) as usize * box_width + (x - x0) as usize];
+                    *cell = cell.max(value);
+                }
+            }
+        }
+
+        let [r, g, b, a] = annotation.color;
+        for (index, value) in coverage.into_iter().enumerate() {
+            let alpha = value * a as f32 / 255.0;
+            if alpha <= 0.0 {
+                continue;
+            }
+            let (x, y) = (x0 + (index % box_width) as u32, y0 + (index / box_width) as u32);
+            let pixel = image.get_pixel_mut(x, y);
+            for (channel, target) in [r, g, b].into_iter().zip(pixel.0.iter_mut()) {
+                *target = (channel as f32 * alpha + *target as f32 * (1.0 - alpha)).round() as u8;
+            }
+            pixel.0[3] = (255.0 * alpha + pixel.0[3] as f32 * (1.0 - alpha)).round() as u8;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,255 @@
+use anyhow::Context;
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageFormat};
+use log::{info, warn};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread;
+
+/// Function used by the worker threads to decode an image
+pub type ImageLoader = fn(&Path) -> anyhow::Result<DynamicImage>;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum BatchFormat {
+    Png,
+    Jpeg,
+    WebP,
+    Tiff,
+    Bmp,
+}
+
+impl BatchFormat {
+    pub const ALL: [BatchFormat; 5] = [BatchFormat::Png, BatchFormat::Jpeg, BatchFormat::WebP, BatchFormat::Tiff, BatchFormat::Bmp];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BatchFormat::Png => "PNG",
+            BatchFormat::Jpeg => "JPEG",
+            BatchFormat::WebP => "WebP (lossless)",
+            BatchFormat::Tiff => "TIFF",
+            BatchFormat::Bmp => "BMP",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            BatchFormat::Png => "png",
+            BatchFormat::Jpeg => "jpg",
+            BatchFormat::WebP => "webp",
+            BatchFormat::Tiff => "tif",
+            BatchFormat::Bmp => "bmp",
+        }
+    }
+
+    fn image_format(&self) -> ImageFormat {
+        match self {
+            BatchFormat::Png => ImageFormat::Png,
+            BatchFormat::Jpeg => ImageFormat::Jpeg,
+            BatchFormat::WebP => ImageFormat::WebP,
+            BatchFormat::Tiff => ImageFormat::Tiff,
+            BatchFormat::Bmp => ImageFormat::Bmp,
+        }
+    }
+
+    /// Convert to a color type the encoder accepts, keeping 16-bit and float where it can
+    fn prepare(&self, img: DynamicImage) -> DynamicImage {
+        let alpha = img.color().has_alpha();
+        let float = matches!(img, DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_));
+        match self {
+            BatchFormat::Jpeg => DynamicImage::ImageRgb8(img.to_rgb8()),
+            BatchFormat::WebP | BatchFormat::Bmp if alpha => DynamicImage::ImageRgba8(img.to_rgba8()),
+            BatchFormat::WebP | BatchFormat::Bmp => DynamicImage::ImageRgb8(img.to_rgb8()),
+            BatchFormat::Png if float && alpha => DynamicImage::ImageRgba16(img.to_rgba16()),
+            BatchFormat::Png if float => DynamicImage::ImageRgb16(img.to_rgb16()),
+            BatchFormat::Png | BatchFormat::Tiff => img,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct BatchOptions {
+    pub format: BatchFormat,
+    /// JPEG quality, 1 to 100
+    pub quality: u8,
+    /// Longest side of the output, larger images are scaled down. 0 keeps the size.
+    pub max_size: u32,
+    /// Replace files of the same name in the output folder instead of skipping them
+    pub overwrite: bool,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            format: BatchFormat::Jpeg,
+            quality: 90,
+            max_size: 0,
+            overwrite: false,
+        }
+    }
+}
+
+/// Outcome of a batch conversion
+#[derive(Default)]
+pub struct BatchSummary {
+    pub converted: usize,
+    /// Output existed and overwriting was off, or the output would replace its source
+    pub skipped: usize,
+    pub failed: Vec<(PathBuf, String)>,
+    pub cancelled: bool,
+}
+
+impl BatchSummary {
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{} converted, {} skipped, {} failed", self.converted, self.skipped, self.failed.len());
+        if self.cancelled {
+            text.push_str(" (cancelled)");
+        }
+        if let Some((path, error)) = self.failed.first() {
+            text.push_str(&format!("\nFirst failure: {}: {}", path.to_string_lossy(), error));
+        }
+        text
+    }
+}
+
+/// A conversion running on worker threads, one per CPU core
+pub struct BatchJob {
+    pub total: usize,
+    progress: Arc<AtomicUsize>,
+    cancel: Arc<AtomicBool>,
+    result_rx: Receiver<BatchSummary>,
+}
+
+impl BatchJob {
+    pub fn start(ctx: &egui::Context, files: Vec<PathBuf>, output_dir: PathBuf, options: BatchOptions, loader: ImageLoader) -> Self {
+        let total = files.len();
+        let progress = Arc::new(AtomicUsize::new(0));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (result_tx, result_rx) = channel();
+        let ctx = ctx.clone();
+        info!("Converting {} images to {} in {:?}", total, options.format.as_str(), output_dir);
+
+        let (thread_progress, thread_cancel) = (Arc::clone(&progress), Arc::clone(&cancel));
+        thread::spawn(move || {
+            let outputs = output_paths(&files, &output_dir, options.format);
+            // Folders of the inputs, where no existing image is ever replaced
+            let source_dirs: Arc<HashSet<PathBuf>> = Arc::new(files.iter().filter_map(|path| path.parent()?.canonicalize().ok()).collect());
+            let files: Arc<Vec<(PathBuf, PathBuf)>> = Arc::new(files.into_iter().zip(outputs).collect());
+            let next = Arc::new(AtomicUsize::new(0));
+            let workers = thread::available_parallelism().map_or(1, |n| n.get()).min(files.len()).max(1);
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    let (files, next, source_dirs) = (Arc::clone(&files), Arc::clone(&next), Arc::clone(&source_dirs));
+                    let (progress, cancel, ctx) = (Arc::clone(&thread_progress), Arc::clone(&thread_cancel), ctx.clone());
+                    thread::spawn(move || {
+                        let mut summary = BatchSummary::default();
+                        // Each worker takes the next unconverted file until none are left
+                        loop {
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            let Some((path, output)) = files.get(index) else {
+                                break;
+                            };
+                            if cancel.load(Ordering::Relaxed) {
+                                break;
+                            }
+                            match convert(path, output, &source_dirs, options, loader) {
+                                Ok(true) => summary.converted += 1,
+                                Ok(false) => summary.skipped += 1,
+                                Err(e) => {
+                                    warn!("Converting {:?} failed: {:#}", path, e);
+                                    summary.failed.push((path.clone(), format!("{:#}", e)));
+                                }
+                            }
+                            progress.fetch_add(1, Ordering::Relaxed);
+                            ctx.request_repaint();
+                        }
+                        summary
+                    })
+                })
+                .collect();
+
+            let mut summary = BatchSummary::default();
+            for handle in handles {
+                if let Ok(part) = handle.join() {
+                    summary.converted += part.converted;
+                    summary.skipped += part.skipped;
+                    summary.failed.extend(part.failed);
+                }
+            }
+            summary.cancelled = thread_cancel.load(Ordering::Relaxed);
+            info!("Batch conversion done: {}", summary.to_text());
+            let _ = result_tx.send(summary);
+            ctx.request_repaint();
+        });
+
+        Self { total, progress, cancel, result_rx }
+    }
+
+    /// Number of files processed so far
+    pub fn progress(&self) -> usize {
+        self.progress.load(Ordering::Relaxed)
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// The summary once all workers have finished
+    pub fn poll(&self) -> Option<BatchSummary> {
+        self.result_rx.try_recv().ok()
+    }
+}
+
+/// Output file of every input in `output_dir`, the file name with the extension of the format.
+/// Inputs whose names differ only in the extension, like `photo.png` and `photo.jpg`, keep it
+/// in front of the new one (`photo.png.jpg`, `photo.jpg.jpg`) so they do not write the same file.
+fn output_paths(files: &[PathBuf], output_dir: &Path, format: BatchFormat) -> Vec<PathBuf> {
+    // Case-insensitive file systems treat Photo and photo as the same name
+    let stem = |path: &PathBuf| path.file_stem().map(|stem| stem.to_string_lossy().to_lowercase());
+    let mut counts = std::collections::HashMap::new();
+    for path in files {
+        *counts.entry(stem(path)).or_insert(0) += 1;
+    }
+    files.iter()
+        .map(|path| {
+            let mut name = if counts[&stem(path)] > 1 { path.file_name() } else { path.file_stem() }.unwrap_or_default().to_os_string();
+            name.push(".");
+            name.push(format.extension());
+            output_dir.join(name)
+        })
+        .collect()
+}
+
+/// Convert one file into `output`. Returns false when it was skipped.
+fn convert(path: &Path, output: &Path, source_dirs: &HashSet<PathBuf>, options: BatchOptions, loader: ImageLoader) -> anyhow::Result<bool> {
+    if path.file_name().is_none() {
+        anyhow::bail!("{:?} has no file name", path);
+    }
+    if output.exists() {
+        // Never replace an image next to the sources, even with overwriting on. It may be a
+        // source itself or one left out of the batch, e.g. by the star filter.
+        let beside_source = output.parent().and_then(|dir| dir.canonicalize().ok()).is_some_and(|dir| source_dirs.contains(&dir));
+        if beside_source || !options.overwrite {
+            return Ok(false);
+        }
+    }
+
+    let mut img = loader(path)?;
+    if options.max_size > 0 && img.width().max(img.height()) > options.max_size {
+        img = img.resize(options.max_size, options.max_size, image::imageops::FilterType::Lanczos3);
+    }
+    let img = options.format.prepare(img);
+
+    if options.format == BatchFormat::Jpeg {
+        let file = File::create(output).with_context(|| format!("Failed to create {:?}", output))?;
+        let encoder = JpegEncoder::new_with_quality(BufWriter::new(file), options.quality.clamp(1, 100));
+        img.write_with_encoder(encoder).with_context(|| format!("Failed to write {:?}", output))?;
+    } else {
+        img.save_with_format(output, options.format.image_format()).with_context(|| format!("Failed to write {:?}", output))?;
+    }
+    Ok(true)
+}
@@ -0,0 +1,298 @@
+//! Conversion without a window, e.g. `image_viewer --normalize min_max --colormap viridis in.tif
+//! -o out.png`. The input is decoded like in the viewer and written as it would be displayed, so
+//! exports are reproducible from scripts. An input of `-` reads the image from stdin.
+
+use anyhow::{anyhow, bail, Context};
+use image::{DynamicImage, GenericImageView, ImageFormat};
+use log::info;
+use serde::Serialize;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::config::Config;
+use crate::properties::{self, ExifSummary};
+use crate::transforms::{Transform, TransformKind, TransformRegistry};
+use crate::{colormap, nifti, paths, ChannelType, ImageViewerApp};
+
+pub const USAGE: &str = "\
+Usage: image_viewer [--convert] [options] <input> -o <output>
+
+The input `-` reads the image from stdin.
+
+Options:
+  -o, --output <path>         File to write, the format follows the extension
+  --normalize <id>[:p1,...]   Normalization, e.g. min_max or percentile:1,99
+  --apply <id>[:p1,...]       Pipeline step, may be repeated, e.g. gamma:0.8 or gaussian_blur:2
+  --colormap <name>           Built-in or saved colormap, e.g. viridis
+  --channel <name>            rgb, luminance, red, green, blue or alpha";
+
+/// Whether the command line asks for `--probe`
+pub fn is_probe(args: &[OsString]) -> bool {
+    args.iter().skip(1).any(|arg| arg == "--probe")
+}
+
+/// Options that switch to a conversion instead of opening the viewer
+const CONVERT_FLAGS: [&str; 7] = ["--convert", "-o", "--output", "--normalize", "--apply", "--colormap", "--channel"];
+
+/// Transform id with parameter values, missing ones keep their defaults
+type StepSpec = (String, Vec<f32>);
+
+#[derive(Debug, Default)]
+pub struct ConvertOptions {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub normalize: Option<StepSpec>,
+    /// Pipeline steps in the order they are applied
+    pub steps: Vec<StepSpec>,
+    pub colormap: Option<String>,
+    pub channel: Option<String>,
+}
+
+/// Whether the command line asks for a conversion
+pub fn is_convert(args: &[OsString]) -> bool {
+    args.iter().skip(1).any(|arg| arg.to_str().is_some_and(|arg| CONVERT_FLAGS.contains(&arg)))
+}
+
+/// Parse the arguments after the program name
+pub fn parse(args: &[OsString]) -> anyhow::Result<ConvertOptions> {
+    let mut options = ConvertOptions::default();
+    let (mut input, mut output) = (None, None);
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next().ok_or_else(|| anyhow!("{} needs a value", flag));
+        match arg.to_str() {
+            Some("--convert") => {}
+            Some(flag @ ("-o" | "--output")) => output = Some(PathBuf::from(value(flag)?)),
+            Some(flag @ "--normalize") => options.normalize = Some(step_spec(value(flag)?)?),
+            Some(flag @ "--apply") => options.steps.push(step_spec(value(flag)?)?),
+            Some(flag @ "--colormap") => options.colormap = Some(utf8(value(flag)?)?.to_string()),
+            Some(flag @ "--channel") => options.channel = Some(utf8(value(flag)?)?.to_string()),
+            Some(flag) if flag.starts_with('-') && flag.len() > 1 => bail!("Unknown option {}", flag),
+            _ if input.is_some() => bail!("Only one input file can be converted"),
+            _ => input = Some(PathBuf::from(arg)),
+        }
+    }
+    options.input = input.ok_or_else(|| anyhow!("No input file given"))?;
+    options.output = output.ok_or_else(|| anyhow!("No output file given, use -o <path>"))?;
+    Ok(options)
+}
+
+fn utf8(value: &OsString) -> anyhow::Result<&str> {
+    value.to_str().ok_or_else(|| anyhow!("{:?} is not valid UTF-8", value))
+}
+
+/// `id` or `id:p1,p2,...`
+fn step_spec(value: &OsString) -> anyhow::Result<StepSpec> {
+    let value = utf8(value)?;
+    let (id, params) = value.split_once(':').unwrap_or((value, ""));
+    let params = params.split(',')
+        .filter(|param| !param.trim().is_empty())
+        .map(|param| param.trim().parse().map_err(|_| anyhow!("Invalid parameter \"{}\" of {}", param, id)))
+        .collect::<anyhow::Result<_>>()?;
+    Ok((id.to_string(), params))
+}
+
+/// Names compared without case and separators, so `minmax` and `Min-Max` find `min_max`
+fn same_name(a: &str, b: &str) -> bool {
+    let key = |name: &str| name.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect::<String>();
+    key(a) == key(b)
+}
+
+/// Transform of one of `kinds` by id or name, the error lists the valid ids
+fn find_transform<'a>(registry: &'a TransformRegistry, kinds: &[TransformKind], name: &str) -> anyhow::Result<&'a dyn Transform> {
+    let candidates: Vec<&dyn Transform> = kinds.iter().flat_map(|&kind| registry.of_kind(kind)).collect();
+    candidates.iter()
+        .find(|transform| same_name(transform.id(), name) || same_name(transform.name(), name))
+        .copied()
+        .ok_or_else(|| {
+            let ids: Vec<&str> = candidates.iter().map(|transform| transform.id()).collect();
+            anyhow!("Unknown transform \"{}\", use one of: {}", name, ids.join(", "))
+        })
+}
+
+fn check_param_count(transform: &dyn Transform, params: &[f32]) -> anyhow::Result<()> {
+    if params.len() > transform.params().len() {
+        let names: Vec<&str> = transform.params().iter().map(|param| param.name).collect();
+        bail!("{} takes {} parameter(s) ({}), got {}", transform.id(), names.len(), names.join(", "), params.len());
+    }
+    Ok(())
+}
+
+/// Save an image piped to stdin to a temporary file, so it loads like any other file. The loaders
+/// pick the decoder by the extension, which is taken from the detected format.
+pub fn read_stdin() -> anyhow::Result<PathBuf> {
+    let mut bytes = Vec::new();
+    std::io::stdin().read_to_end(&mut bytes).context("Failed to read stdin")?;
+    if bytes.is_empty() {
+        bail!("No image data on stdin");
+    }
+    let format = image::guess_format(&bytes).map_err(|_| anyhow!("The data on stdin is not a recognized image format"))?;
+    let extension = format.extensions_str().first().copied().unwrap_or("img");
+    let path = env::temp_dir().join(format!("image_viewer_stdin_{}.{}", process::id(), extension));
+    fs::write(&path, &bytes).with_context(|| format!("Failed to write {:?}", path))?;
+    info!("Read {} bytes of {:?} from stdin into {:?}", bytes.len(), format, path);
+    Ok(path)
+}
+
+/// Decode `path` into the document, like the viewer opens it
+fn open(app: &mut ImageViewerApp, path: &Path) -> anyhow::Result<()> {
+    let volume = if nifti::is_nifti_file(path) { Some(nifti::load_volume(path)?) } else { None };
+    let decoded = match &volume {
+        Some(volume) => (ImageViewerApp::volume_slice(volume), None),
+        None => app.decode_file(path).with_context(|| format!("Failed to open {:?}", path))?,
+    };
+    app.show_decoded(path, decoded, volume);
+    Ok(())
+}
+
+/// Decode the input, apply the display settings and write the displayed image
+pub fn run(options: &ConvertOptions) -> anyhow::Result<()> {
+    let mut app = ImageViewerApp { config: Config::load(), ..Default::default() };
+
+    if let Some((name, params)) = &options.normalize {
+        let transform = find_transform(&app.transforms, &[TransformKind::Normalization], name)?;
+        check_param_count(transform, params)?;
+        let id = transform.id();
+        app.doc.normalization = id;
+        app.transforms.set_params(id, params);
+    }
+    for (name, params) in &options.steps {
+        let transform = find_transform(&app.transforms, &[TransformKind::Operation, TransformKind::Filter], name)?;
+        check_param_count(transform, params)?;
+        app.doc.pipeline.push(transform);
+        if let Some(step) = app.doc.pipeline.steps.last_mut() {
+            step.params.iter_mut().zip(params).for_each(|(param, &value)| *param = value);
+        }
+    }
+    if let Some(name) = &options.colormap {
+        let colormaps: Vec<String> = colormap::builtin_colormaps().into_iter()
+            .chain(app.config.colormaps.iter().cloned())
+            .map(|colormap| colormap.name)
+            .collect();
+        let found = colormaps.iter().find(|colormap| same_name(colormap, name))
+            .ok_or_else(|| anyhow!("Unknown colormap \"{}\", use one of: {}", name, colormaps.join(", ")))?;
+        app.doc.colormap = Some(found.clone());
+    }
+    if let Some(name) = &options.channel {
+        let channels = [ChannelType::Rgb, ChannelType::Luminance, ChannelType::Red, ChannelType::Green, ChannelType::Blue, ChannelType::Alpha];
+        app.doc.channel = channels.into_iter().find(|channel| same_name(channel.as_str(), name))
+            .ok_or_else(|| anyhow!("Unknown channel \"{}\", use rgb, luminance, red, green, blue or alpha", name))?;
+    }
+
+    let from_stdin = options.input == Path::new("-");
+    let input = if from_stdin { read_stdin()? } else { options.input.clone() };
+    let path = paths::normalize(&input);
+    open(&mut app, &path)?;
+    if from_stdin {
+        let _ = fs::remove_file(&path);
+    }
+    let img = app.doc.image.clone().ok_or_else(|| anyhow!("{:?} has no image", path))?;
+
+    let displayed = app.display_image(img).into_rgba8();
+    // JPEG has no alpha, other formats keep it only where it is used
+    let jpeg = ImageFormat::from_path(&options.output).is_ok_and(|format| format == ImageFormat::Jpeg);
+    let displayed = if jpeg || displayed.pixels().all(|pixel| pixel[3] == u8::MAX) {
+        DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(displayed).into_rgb8())
+    } else {
+        DynamicImage::ImageRgba8(displayed)
+    };
+    displayed.save(&options.output).with_context(|| format!("Failed to write {:?}", options.output))?;
+    info!("Converted {:?} to {:?}", path, options.output);
+    Ok(())
+}
+
+/// What `--probe` prints for a file
+#[derive(Serialize)]
+struct Probe {
+    path: PathBuf,
+    format: String,
+    width: u32,
+    height: u32,
+    /// Full size of a huge image that is loaded at reduced resolution
+    reduced_from: Option<(u32, u32)>,
+    color_type: String,
+    channels: u32,
+    bit_depth: u32,
+    floating_point: bool,
+    /// Range of floating point data
+    data_range: Option<(f32, f32)>,
+    pages: usize,
+    compression: Option<String>,
+    resolution: Option<Resolution>,
+    /// EXIF orientation, 1 when the image is stored upright
+    orientation: u8,
+    exif: ExifSummary,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ProbeResult {
+    Loaded(Box<Probe>),
+    Failed { path: PathBuf, error: String },
+}
+
+#[derive(Serialize)]
+struct Resolution {
+    x: f64,
+    y: f64,
+    unit: &'static str,
+}
+
+fn probe_file(input: &Path) -> anyhow::Result<Probe> {
+    let mut app = ImageViewerApp { config: Config::load(), ..Default::default() };
+    let from_stdin = input == Path::new("-");
+    let path = if from_stdin { read_stdin()? } else { paths::normalize(input) };
+    let result = open(&mut app, &path).map(|()| properties::read(&path));
+    if from_stdin {
+        let _ = fs::remove_file(&path);
+    }
+    let file = result?;
+    let img = app.doc.image.as_ref().ok_or_else(|| anyhow!("{:?} has no image", path))?;
+    let fp_channels = app.doc.original_fp_channels.filter(|_| app.doc.original_fp_data.is_some());
+    let color = img.color();
+    let (width, height) = app.doc.original_fp_dimensions.unwrap_or(img.dimensions());
+    Ok(Probe {
+        path: if from_stdin { PathBuf::from("-") } else { path },
+        format: file.format,
+        width,
+        height,
+        reduced_from: app.doc.reduced_from,
+        color_type: app.sample_type_text(img),
+        channels: fp_channels.unwrap_or(color.channel_count() as u32),
+        bit_depth: if fp_channels.is_some() { 32 } else { (color.bits_per_pixel() / color.channel_count() as u16) as u32 },
+        floating_point: app.doc.is_floating_point_image,
+        data_range: app.doc.original_data_range,
+        pages: match (&app.doc.frames, &app.doc.volume) {
+            (Some(frames), _) => frames.len(),
+            (_, Some(volume)) => volume.slice_count(),
+            _ => file.pages.unwrap_or(1),
+        },
+        compression: file.compression,
+        resolution: file.resolution.map(|(x, y, unit)| Resolution { x, y, unit }),
+        orientation: app.doc.file_orientation.to_exif(),
+        exif: file.exif,
+    })
+}
+
+/// Print the properties of the files as JSON, an object for one file and an array for several.
+/// Files that fail to load get an `error` instead. Returns false if any failed.
+pub fn probe(args: &[OsString]) -> anyhow::Result<bool> {
+    let inputs: Vec<PathBuf> = args.iter().skip(1).filter(|arg| *arg != "--probe").map(PathBuf::from).collect();
+    if inputs.is_empty() {
+        bail!("No file given, use --probe <file>...");
+    }
+    let results: Vec<ProbeResult> = inputs.iter().map(|input| match probe_file(input) {
+        Ok(probe) => ProbeResult::Loaded(Box::new(probe)),
+        Err(e) => ProbeResult::Failed { path: input.clone(), error: format!("{:#}", e) },
+    }).collect();
+    let json = match results.as_slice() {
+        [result] => serde_json::to_string_pretty(result)?,
+        results => serde_json::to_string_pretty(results)?,
+    };
+    println!("{}", json);
+    Ok(results.iter().all(|result| matches!(result, ProbeResult::Loaded(_))))
+}
@@ -0,0 +1,128 @@
+//! Highlighting of pixels at the ends of the data range and of NaN and infinite values, which
+//! makes clipped highlights and shadows and hot or dead sensor pixels stand out.
+
+use image::DynamicImage;
+
+use crate::image_processing::pixel_values;
+
+/// Flagged pixels kept as positions, so they are marked even when zoomed out too far to see them
+pub const MAX_MARKERS: usize = 4096;
+
+/// Why a pixel is highlighted, a pixel with several reasons takes the first
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Flag {
+    /// NaN or infinite value in floating point data
+    NonFinite,
+    /// A channel at the maximum of the data
+    High,
+    /// A channel at the minimum of the data
+    Low,
+}
+
+impl Flag {
+    pub fn color(self) -> egui::Color32 {
+        match self {
+            Flag::NonFinite => egui::Color32::YELLOW,
+            Flag::High => egui::Color32::from_rgb(255, 0, 255),
+            Flag::Low => egui::Color32::from_rgb(0, 255, 255),
+        }
+    }
+}
+
+/// Flag of a pixel with the color channel values `values`
+fn classify(values: &[f32], range: Option<(f32, f32)>) -> Option<Flag> {
+    if values.iter().any(|value| !value.is_finite()) {
+        return Some(Flag::NonFinite);
+    }
+    let (min, max) = range?;
+    if values.contains(&max) {
+        Some(Flag::High)
+    } else if values.contains(&min) {
+        Some(Flag::Low)
+    } else {
+        None
+    }
+}
+
+/// Highlighted pixels of an image
+pub struct ClippingMap {
+    /// Finite minimum and maximum of the color channels
+    pub range: Option<(f32, f32)>,
+    pub high: usize,
+    pub low: usize,
+    pub non_finite: usize,
+    /// Flagged pixels in their colors, transparent elsewhere
+    pub mask: egui::ColorImage,
+    /// Positions of the flagged pixels, `None` if there are more than `MAX_MARKERS`
+    pub markers: Option<Vec<(u32, u32, Flag)>>,
+}
+
+impl ClippingMap {
+    /// Map of interleaved floating point data; alpha of 4 channel data is left out
+    pub fn from_fp(data: &[f32], width: u32, height: u32, channels: usize) -> Self {
+        let color_channels = if channels == 4 { 3 } else { channels };
+        let pixel = |x: u32, y: u32| {
+            let base = (y as usize * width as usize + x as usize) * channels;
+            data.get(base..base + color_channels).unwrap_or_default()
+        };
+        let range = data.chunks_exact(channels.max(1))
+            .flat_map(|pixel| &pixel[..color_channels])
+            .filter(|value| value.is_finite())
+            .fold(None, |range: Option<(f32, f32)>, &value| Some(range.map_or((value, value), |(min, max)| (min.min(value), max.max(value)))));
+        Self::build(width, height, range, |x, y| classify(pixel(x, y), range))
+    }
+
+    /// Map of an image with integer samples, in the units of the image
+    pub fn from_image(img: &DynamicImage) -> Self {
+        let (width, height) = (img.width(), img.height());
+        let color = |x: u32, y: u32| {
+            let [r, g, b, _] = pixel_values(img, x, y);
+            [r as f32, g as f32, b as f32]
+        };
+        let range = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| color(x, y))
+            .fold(None, |range: Option<(f32, f32)>, value| Some(range.map_or((value, value), |(min, max)| (min.min(value), max.max(value)))));
+        Self::build(width, height, range, |x, y| classify(&color(x, y), range))
+    }
+
+    fn build(width: u32, height: u32, range: Option<(f32, f32)>, flag: impl Fn(u32, u32) -> Option<Flag>) -> Self {
+        let mut map = Self {
+            range,
+            high: 0,
+            low: 0,
+            non_finite: 0,
+            mask: egui::ColorImage::new([width as usize, height as usize], egui::Color32::TRANSPARENT),
+            markers: Some(Vec::new()),
+        };
+        for y in 0..height {
+            for x in 0..width {
+                let Some(flag) = flag(x, y) else {
+                    continue;
+                };
+                match flag {
+                    Flag::NonFinite => map.non_finite += 1,
+                    Flag::High => map.high += 1,
+                    Flag::Low => map.low += 1,
+                }
+                map.mask.pixels[y as usize * width as usize + x as usize] = flag.color();
+                if let Some(markers) = &mut map.markers {
+                    if markers.len() < MAX_MARKERS {
+                        markers.push((x, y, flag));
+                    } else {
+                        map.markers = None;
+                    }
+                }
+            }
+        }
+        map
+    }
+
+    /// Counts of the flagged pixels, e.g. "12 at max, 3 at min, 1 NaN/Inf"
+    pub fn summary(&self) -> String {
+        let mut parts = vec![format!("{} at max", self.high), format!("{} at min", self.low)];
+        if self.non_finite > 0 {
+            parts.push(format!("{} NaN/Inf", self.non_finite));
+        }
+        parts.join(", ")
+    }
+}
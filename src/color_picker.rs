@@ -0,0 +1,109 @@
+//! Readouts of a color picked with the pixel tool in the common color spaces. Samples are taken
+//! as sRGB; XYZ and Lab use the D65 white point with Y of white at 100.
+
+use crate::palette;
+
+/// Reference white of D65 in XYZ
+const WHITE_D65: [f32; 3] = [95.047, 100.0, 108.883];
+
+/// sRGB component in 0..=1 to linear light
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn to_linear(color: [u8; 3]) -> [f32; 3] {
+    color.map(|channel| srgb_to_linear(channel as f32 / 255.0))
+}
+
+/// Hue in degrees, saturation and value in percent
+pub fn to_hsv(color: [u8; 3]) -> [f32; 3] {
+    let [r, g, b] = color.map(|channel| channel as f32 / 255.0);
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let saturation = if max > 0.0 { (max - min) / max } else { 0.0 };
+    [hue(r, g, b, max, min), saturation * 100.0, max * 100.0]
+}
+
+/// Hue in degrees, saturation and lightness in percent
+pub fn to_hsl(color: [u8; 3]) -> [f32; 3] {
+    let [r, g, b] = color.map(|channel| channel as f32 / 255.0);
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let lightness = (max + min) / 2.0;
+    let saturation = if max > min { (max - min) / (1.0 - (2.0 * lightness - 1.0).abs()) } else { 0.0 };
+    [hue(r, g, b, max, min), saturation * 100.0, lightness * 100.0]
+}
+
+fn hue(r: f32, g: f32, b: f32, max: f32, min: f32) -> f32 {
+    let chroma = max - min;
+    if chroma <= 0.0 {
+        return 0.0;
+    }
+    let sector = if max == r {
+        ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    sector * 60.0
+}
+
+pub fn to_xyz(color: [u8; 3]) -> [f32; 3] {
+    let [r, g, b] = to_linear(color);
+    [
+        (0.4124564 * r + 0.3575761 * g + 0.1804375 * b) * 100.0,
+        (0.2126729 * r + 0.7151522 * g + 0.0721750 * b) * 100.0,
+        (0.0193339 * r + 0.119192 * g + 0.9503041 * b) * 100.0,
+    ]
+}
+
+/// CIE L*a*b*
+pub fn to_lab(color: [u8; 3]) -> [f32; 3] {
+    let xyz = to_xyz(color);
+    let [x, y, z] = [0, 1, 2].map(|axis| {
+        let t = xyz[axis] / WHITE_D65[axis];
+        // Linear near black, where the cube root is too steep
+        let delta: f32 = 6.0 / 29.0;
+        if t > delta.powi(3) { t.cbrt() } else { t / (3.0 * delta * delta) + 4.0 / 29.0 }
+    });
+    [116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z)]
+}
+
+/// Name and text of each representation, in the order they are listed
+pub fn readouts(color: [u8; 3]) -> [(&'static str, String); 7] {
+    let [r, g, b] = color;
+    let linear = to_linear(color);
+    let hsv = to_hsv(color);
+    let hsl = to_hsl(color);
+    let lab = to_lab(color);
+    let xyz = to_xyz(color);
+    [
+        ("Hex", palette::hex(color)),
+        ("sRGB", format!("rgb({}, {}, {})", r, g, b)),
+        ("Linear RGB", format!("{:.4}, {:.4}, {:.4}", linear[0], linear[1], linear[2])),
+        ("HSV", format!("hsv({:.0}°, {:.1}%, {:.1}%)", hsv[0], hsv[1], hsv[2])),
+        ("HSL", format!("hsl({:.0}°, {:.1}%, {:.1}%)", hsl[0], hsl[1], hsl[2])),
+        ("Lab", format!("lab({:.2}, {:.2}, {:.2})", lab[0], lab[1], lab[2])),
+        ("XYZ", format!("xyz({:.2}, {:.2}, {:.2})", xyz[0], xyz[1], xyz[2])),
+    ]
+}
+
+/// Swatch of the color and its readouts, each with a button copying it
+pub fn ui(ui: &mut egui::Ui, color: [u8; 3]) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(ui.available_width().max(160.0), 36.0), egui::Sense::hover());
+    ui.painter().rect_filled(rect, egui::CornerRadius::same(3), egui::Color32::from_rgb(color[0], color[1], color[2]));
+    ui.painter().rect_stroke(rect, egui::CornerRadius::same(3), egui::Stroke::new(1.0, egui::Color32::GRAY), egui::StrokeKind::Inside);
+    egui::Grid::new("picked_color").num_columns(3).spacing([12.0, 4.0]).show(ui, |ui| {
+        for (name, text) in readouts(color) {
+            ui.label(format!("{}:", name));
+            ui.add(egui::Label::new(egui::RichText::new(&text).monospace()).selectable(true));
+            if ui.small_button("Copy").on_hover_text(format!("Copy the {} value", name)).clicked() {
+                ui.ctx().copy_text(text);
+            }
+            ui.end_row();
+        }
+    });
+}
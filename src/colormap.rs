@@ -0,0 +1,212 @@
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Maximum deviation (in 8-bit levels) allowed when reducing an imported LUT to control points
+const IMPORT_TOLERANCE: f32 = 2.0;
+
+/// Control point of a gradient, `position` in 0..=1
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ColorStop {
+    pub position: f32,
+    pub color: [u8; 3],
+}
+
+/// Named gradient used to false-color single channel data
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Colormap {
+    pub name: String,
+    pub stops: Vec<ColorStop>,
+}
+
+impl Colormap {
+    fn from_hex(name: &str, colors: &[u32]) -> Self {
+        let last = (colors.len() - 1) as f32;
+        let stops = colors
+            .iter()
+            .enumerate()
+            .map(|(i, &hex)| ColorStop {
+                position: i as f32 / last,
+                color: [(hex >> 16) as u8, (hex >> 8) as u8, hex as u8],
+            })
+            .collect();
+        Self { name: name.to_string(), stops }
+    }
+
+    /// Color at `t` in 0..=1, linearly interpolated between the control points
+    pub fn sample(&self, t: f32) -> [u8; 3] {
+        let mut stops = self.stops.clone();
+        stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+        sample_sorted(&stops, t)
+    }
+
+    /// 256 entry lookup table
+    pub fn lut(&self) -> [[u8; 3]; 256] {
+        let mut stops = self.stops.clone();
+        stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+        let mut lut = [[0u8; 3]; 256];
+        for (i, entry) in lut.iter_mut().enumerate() {
+            *entry = sample_sorted(&stops, i as f32 / 255.0);
+        }
+        lut
+    }
+
+    /// Reduce a table of evenly spaced colors to the control points needed to reproduce it
+    pub fn from_table(name: &str, table: &[[u8; 3]]) -> anyhow::Result<Self> {
+        if table.len() < 2 {
+            bail!("A colormap needs at least two entries, found {}", table.len());
+        }
+        let last = (table.len() - 1) as f32;
+        let stop = |i: usize| ColorStop { position: i as f32 / last, color: table[i] };
+
+        // Greedily extend each linear segment as long as it reproduces every entry it spans
+        let mut stops = vec![stop(0)];
+        let mut start = 0;
+        for end in 2..table.len() {
+            let fits = (start + 1..end).all(|i| {
+                let t = (i - start) as f32 / (end - start) as f32;
+                (0..3).all(|c| {
+                    let expected = table[start][c] as f32 + (table[end][c] as f32 - table[start][c] as f32) * t;
+                    (expected - table[i][c] as f32).abs() <= IMPORT_TOLERANCE
+                })
+            });
+            if !fits {
+                start = end - 1;
+                stops.push(stop(start));
+            }
+        }
+        stops.push(stop(table.len() - 1));
+        Ok(Self { name: name.to_string(), stops })
+    }
+}
+
+fn sample_sorted(stops: &[ColorStop], t: f32) -> [u8; 3] {
+    let (Some(first), Some(last)) = (stops.first(), stops.last()) else {
+        return [0, 0, 0];
+    };
+    if t <= first.position {
+        return first.color;
+    }
+    for pair in stops.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        if t <= b.position {
+            let span = b.position - a.position;
+            let f = if span > 0.0 { (t - a.position) / span } else { 1.0 };
+            return [0, 1, 2].map(|c| (a.color[c] as f32 + (b.color[c] as f32 - a.color[c] as f32) * f).round() as u8);
+        }
+    }
+    last.color
+}
+
+/// Colormaps that ship with the viewer, these cannot be edited or deleted
+pub fn builtin_colormaps() -> Vec<Colormap> {
+    vec![
+        Colormap::from_hex("Gray", &[0x000000, 0xffffff]),
+        Colormap::from_hex("Viridis", &[0x440154, 0x472c7a, 0x3b528b, 0x2c728e, 0x21918c, 0x28ae80, 0x5ec962, 0xaddc30, 0xfde725]),
+        Colormap::from_hex("Inferno", &[0x000004, 0x1f0c48, 0x550f6d, 0x88226a, 0xba3655, 0xe35933, 0xf98e09, 0xf9c932, 0xfcffa4]),
+        Colormap::from_hex("Magma", &[0x000004, 0x1c1044, 0x4f127b, 0x812581, 0xb5367a, 0xe55064, 0xfb8761, 0xfec287, 0xfcfdbf]),
+        Colormap::from_hex("Jet", &[0x000080, 0x0000ff, 0x0080ff, 0x00ffff, 0x80ff80, 0xffff00, 0xff8000, 0xff0000, 0x800000]),
+        Colormap::from_hex("Hot", &[0x000000, 0x800000, 0xff0000, 0xff8000, 0xffff00, 0xffffff]),
+    ]
+}
+
+/// Import a LUT file: ImageJ `.lut` (binary or text), CSV/text tables of RGB triplets, or `.cube`
+pub fn import_lut(path: &Path) -> anyhow::Result<Colormap> {
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "Imported".to_string());
+    let extension = path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let data = fs::read(path)?;
+
+    match extension.as_str() {
+        "cube" => parse_cube(&name, &String::from_utf8_lossy(&data)),
+        // Binary ImageJ LUTs are 768 bytes of planar R, G, B or the same with a 32 byte NIH header
+        "lut" if data.len() == 768 || data.len() == 800 => {
+            let planes = &data[data.len() - 768..];
+            let table: Vec<[u8; 3]> = (0..256).map(|i| [planes[i], planes[256 + i], planes[512 + i]]).collect();
+            Colormap::from_table(&name, &table)
+        }
+        _ => Colormap::from_table(&name, &parse_table(&String::from_utf8_lossy(&data))?),
+    }
+}
+
+/// Rows of RGB values separated by commas, semicolons or whitespace.
+/// Rows that do not parse (headers) are skipped, a leading index column is ignored.
+/// Values are 0..=255, or 0..=1 if no value exceeds 1.
+fn parse_table(text: &str) -> anyhow::Result<Vec<[u8; 3]>> {
+    let rows: Vec<[f32; 3]> = text
+        .lines()
+        .filter_map(|line| {
+            let values: Option<Vec<f32>> = line
+                .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+                .filter(|field| !field.is_empty())
+                .map(|field| field.parse().ok())
+                .collect();
+            match values?.as_slice() {
+                [.., r, g, b] => Some([*r, *g, *b]),
+                _ => None,
+            }
+        })
+        .collect();
+    if rows.is_empty() {
+        bail!("No RGB rows found");
+    }
+
+    let max = rows.iter().flatten().fold(0.0f32, |a, &b| a.max(b));
+    let scale = if max <= 1.0 { 255.0 } else { 1.0 };
+    Ok(rows.iter().map(|row| row.map(|v| (v * scale).round().clamp(0.0, 255.0) as u8)).collect())
+}
+
+/// Largest 3D LUT edge, real cube files use at most 65
+const MAX_CUBE_SIZE: usize = 256;
+
+/// Adobe/Resolve `.cube` LUT. 1D LUTs are used as they are, for 3D LUTs the gray axis
+/// (equal R, G and B input) becomes the colormap.
+fn parse_cube(default_name: &str, text: &str) -> anyhow::Result<Colormap> {
+    let mut name = default_name.to_string();
+    let mut size_1d = None;
+    let mut size_3d = None;
+    let mut entries: Vec<[f32; 3]> = Vec::new();
+
+    let parse_triplet = |fields: &[&str]| -> Option<[f32; 3]> {
+        match fields {
+            [r, g, b] => Some([r.parse().ok()?, g.parse().ok()?, b.parse().ok()?]),
+            _ => None,
+        }
+    };
+
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields[0] {
+            "TITLE" => name = line["TITLE".len()..].trim().trim_matches('"').to_string(),
+            "LUT_1D_SIZE" => size_1d = fields.get(1).and_then(|v| v.parse::<usize>().ok()),
+            "LUT_3D_SIZE" => size_3d = fields.get(1).and_then(|v| v.parse::<usize>().ok()),
+            // DOMAIN_MIN/MAX and unknown keywords do not affect the output colors
+            _ => entries.extend(parse_triplet(&fields)),
+        }
+    }
+
+    if let Some(size) = size_3d.filter(|&size| size > MAX_CUBE_SIZE) {
+        bail!("Cube file LUT_3D_SIZE {} is larger than {}", size, MAX_CUBE_SIZE);
+    }
+    let table: Vec<[f32; 3]> = match (size_1d, size_3d) {
+        (Some(size), _) if entries.len() >= size => entries[..size].to_vec(),
+        (_, Some(size)) if size.checked_pow(3).is_some_and(|count| entries.len() >= count) => {
+            // Red changes fastest
+            (0..size).map(|i| entries[i + i * size + i * size * size]).collect()
+        }
+        _ => bail!("Cube file has no LUT_1D_SIZE or LUT_3D_SIZE matching its {} entries", entries.len()),
+    };
+
+    // Output values are 0..=1
+    let table: Vec<[u8; 3]> = table
+        .iter()
+        .map(|entry| entry.map(|v| (v * 255.0).round().clamp(0.0, 255.0) as u8))
+        .collect();
+    Colormap::from_table(&name, &table)
+}
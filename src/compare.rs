@@ -0,0 +1,169 @@
+use image::{DynamicImage, GenericImageView};
+use log::warn;
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+use crate::frames::FrameStack;
+use crate::image_processing::{abs_difference, difference_heatmap, quality_metrics, signed_difference, DiffStats, QualityMetrics};
+
+/// What the viewer shows while comparing image A (the loaded image) with image B
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum CompareView {
+    /// Shows A or B, the blink key switches between them
+    Blink,
+    AbsDifference,
+    SignedDifference,
+    /// Pixels differing by more than the tolerance, colored by the size of the difference
+    Heatmap,
+    /// A on one side of a draggable divider, B on the other
+    Wipe,
+    /// A and B in alternating squares
+    Checkerboard,
+}
+
+impl CompareView {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompareView::Blink => "A/B blink",
+            CompareView::AbsDifference => "|A − B|",
+            CompareView::SignedDifference => "A − B (signed)",
+            CompareView::Heatmap => "Diff heatmap",
+            CompareView::Wipe => "Wipe",
+            CompareView::Checkerboard => "Checkerboard",
+        }
+    }
+}
+
+/// Second image loaded for comparison against the current one
+pub struct CompareState {
+    pub path: PathBuf,
+    pub image: DynamicImage,
+    /// Frames of B when it is animated or a stack, `image` is the current one
+    pub frames: Option<FrameStack>,
+    /// Step B's frames together with A's, so the same frame index is compared
+    pub frames_locked: bool,
+    pub view: CompareView,
+    /// In blink view: whether B is currently shown instead of A
+    pub showing_b: bool,
+    /// In heatmap view: differences up to this many 8-bit levels are ignored
+    pub tolerance: u8,
+    /// In heatmap view: which of R, G, B are compared
+    pub channels: [bool; 3],
+    /// Statistics of the last computed heatmap
+    pub stats: Cell<Option<DiffStats>>,
+    /// In wipe view: position of the divider across the image, 0 to 1
+    pub wipe: f32,
+    /// In wipe view: the divider runs horizontally with B below it, else vertically with B on the right
+    pub wipe_horizontal: bool,
+    /// In checkerboard view: side of the squares in screen points
+    pub checker_size: f32,
+    /// B through the display pipeline, for the views showing A and B together
+    pub texture_b: Option<egui::TextureHandle>,
+    /// PSNR, SSIM and mean absolute error of the last finished computation
+    pub metrics: Option<QualityMetrics>,
+    /// Metrics being computed on a worker thread
+    metrics_rx: Option<Receiver<QualityMetrics>>,
+}
+
+impl CompareState {
+    pub fn new(path: &Path, image: DynamicImage, frames: Option<FrameStack>) -> Self {
+        let image = frames.as_ref().map_or(image, |frames| frames.current().clone());
+        Self {
+            path: path.to_path_buf(),
+            image,
+            frames,
+            frames_locked: true,
+            view: CompareView::Blink,
+            showing_b: false,
+            tolerance: 0,
+            channels: [true; 3],
+            stats: Cell::new(None),
+            wipe: 0.5,
+            wipe_horizontal: false,
+            checker_size: 64.0,
+            texture_b: None,
+            metrics: None,
+            metrics_rx: None,
+        }
+    }
+
+    /// Show frame `index` of B, clamped to its frame count. Returns whether the frame changed.
+    pub fn set_frame(&mut self, index: usize) -> bool {
+        let Some(frames) = &mut self.frames else {
+            return false;
+        };
+        if !frames.set_index(index) {
+            return false;
+        }
+        self.image = frames.current().clone();
+        true
+    }
+
+    /// B with the dimensions of A, differences are only defined pixel by pixel
+    pub fn b_matching(&self, a: &DynamicImage) -> DynamicImage {
+        if a.dimensions() == self.image.dimensions() {
+            self.image.clone()
+        } else {
+            let (width, height) = a.dimensions();
+            warn!(
+                "Compared images differ in size ({:?} vs {:?}), resizing B",
+                a.dimensions(),
+                self.image.dimensions()
+            );
+            self.image.resize_exact(width, height, image::imageops::FilterType::Nearest)
+        }
+    }
+
+    /// Compute the quality metrics of B against `a` on a worker thread. A running computation is
+    /// abandoned, the last metrics stay shown until the new ones arrive.
+    pub fn start_metrics(&mut self, ctx: &egui::Context, a: &DynamicImage) {
+        let (a, b) = (a.clone(), self.b_matching(a));
+        let (tx, rx) = channel();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            let _ = tx.send(quality_metrics(&a, &b));
+            ctx.request_repaint();
+        });
+        self.metrics_rx = Some(rx);
+    }
+
+    /// Take the metrics once the worker is done
+    pub fn poll_metrics(&mut self) {
+        if let Some(metrics) = self.metrics_rx.as_ref().and_then(|rx| rx.try_recv().ok()) {
+            self.metrics = Some(metrics);
+            self.metrics_rx = None;
+        }
+    }
+
+    pub fn computing_metrics(&self) -> bool {
+        self.metrics_rx.is_some()
+    }
+
+    /// Image to display for the current view, computed at the size of `a`
+    pub fn view_image(&self, a: &DynamicImage) -> DynamicImage {
+        match self.view {
+            CompareView::Blink if self.showing_b => self.b_matching(a),
+            CompareView::Blink | CompareView::Wipe | CompareView::Checkerboard => a.clone(),
+            CompareView::AbsDifference => abs_difference(a, &self.b_matching(a)),
+            CompareView::SignedDifference => signed_difference(a, &self.b_matching(a)),
+            CompareView::Heatmap => {
+                let (heatmap, stats) = difference_heatmap(a, &self.b_matching(a), self.tolerance, self.channels);
+                self.stats.set(Some(stats));
+                heatmap
+            }
+        }
+    }
+
+    /// Whether the view draws B over A, from `texture_b`
+    pub fn shows_both(&self) -> bool {
+        matches!(self.view, CompareView::Wipe | CompareView::Checkerboard)
+    }
+
+    /// Whether the normalization pipeline should run on the view image;
+    /// the signed difference and the heatmap are already color mapped
+    pub fn uses_pipeline(&self) -> bool {
+        !matches!(self.view, CompareView::SignedDifference | CompareView::Heatmap)
+    }
+}
@@ -0,0 +1,353 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use crate::colormap::Colormap;
+use crate::keybindings::Keybindings;
+use crate::naming::ExportNaming;
+use crate::tonemap::ToneMapper;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const CONFIG_FILE_NAME: &str = "config.json";
+
+/// What the viewer does when started without an image argument
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug, Default)]
+pub enum StartupBehavior {
+    #[default]
+    Empty,
+    OpenFileDialog,
+    LastSession,
+}
+
+impl StartupBehavior {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StartupBehavior::Empty => "Start empty",
+            StartupBehavior::OpenFileDialog => "Open file dialog",
+            StartupBehavior::LastSession => "Open last session",
+        }
+    }
+}
+
+/// What is drawn behind transparent parts of the image
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug, Default)]
+pub enum Background {
+    #[default]
+    Checkerboard,
+    Black,
+    White,
+    Custom,
+}
+
+impl Background {
+    pub const ALL: [Background; 4] = [Background::Checkerboard, Background::Black, Background::White, Background::Custom];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Background::Checkerboard => "Checkerboard",
+            Background::Black => "Black",
+            Background::White => "White",
+            Background::Custom => "Custom",
+        }
+    }
+}
+
+/// Pixels averaged by the pixel tool and the color picker, larger squares even out sensor noise
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug, Default)]
+pub enum PixelSample {
+    #[default]
+    Single,
+    Square3,
+    Square5,
+    Square11,
+}
+
+impl PixelSample {
+    pub const ALL: [PixelSample; 4] = [PixelSample::Single, PixelSample::Square3, PixelSample::Square5, PixelSample::Square11];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PixelSample::Single => "1×1",
+            PixelSample::Square3 => "3×3",
+            PixelSample::Square5 => "5×5",
+            PixelSample::Square11 => "11×11",
+        }
+    }
+
+    /// Pixels on each side of the center
+    pub fn radius(&self) -> u32 {
+        match self {
+            PixelSample::Single => 0,
+            PixelSample::Square3 => 1,
+            PixelSample::Square5 => 2,
+            PixelSample::Square11 => 5,
+        }
+    }
+}
+
+/// Filter used to shrink the image to the display size when zoomed out
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug, Default)]
+pub enum DownscaleFilter {
+    #[default]
+    Lanczos3,
+    CatmullRom,
+    Triangle,
+    Nearest,
+}
+
+impl DownscaleFilter {
+    pub const ALL: [DownscaleFilter; 4] = [
+        DownscaleFilter::Lanczos3,
+        DownscaleFilter::CatmullRom,
+        DownscaleFilter::Triangle,
+        DownscaleFilter::Nearest,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DownscaleFilter::Lanczos3 => "Lanczos3 (sharpest, slowest)",
+            DownscaleFilter::CatmullRom => "Catmull-Rom",
+            DownscaleFilter::Triangle => "Triangle (bilinear)",
+            DownscaleFilter::Nearest => "Nearest (fastest)",
+        }
+    }
+
+    pub fn filter_type(&self) -> image::imageops::FilterType {
+        match self {
+            DownscaleFilter::Lanczos3 => image::imageops::FilterType::Lanczos3,
+            DownscaleFilter::CatmullRom => image::imageops::FilterType::CatmullRom,
+            DownscaleFilter::Triangle => image::imageops::FilterType::Triangle,
+            DownscaleFilter::Nearest => image::imageops::FilterType::Nearest,
+        }
+    }
+}
+
+/// Texture sampling when the image is displayed larger than its size
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug, Default)]
+pub enum MagnificationFilter {
+    /// Smooth up to `NEAREST_MIN_SCALE`, crisp pixels from there on
+    #[default]
+    Auto,
+    Linear,
+    Nearest,
+}
+
+impl MagnificationFilter {
+    pub const ALL: [MagnificationFilter; 3] = [MagnificationFilter::Auto, MagnificationFilter::Linear, MagnificationFilter::Nearest];
+
+    /// Zoom from which `Auto` shows crisp pixels
+    pub const NEAREST_MIN_SCALE: f32 = 4.0;
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MagnificationFilter::Auto => "Auto (crisp from 4×)",
+            MagnificationFilter::Linear => "Smooth (linear)",
+            MagnificationFilter::Nearest => "Crisp pixels (nearest)",
+        }
+    }
+
+    /// Texture filter at a display scale
+    pub fn texture_filter(&self, scale: f32) -> egui::TextureFilter {
+        match self {
+            MagnificationFilter::Auto if scale >= Self::NEAREST_MIN_SCALE => egui::TextureFilter::Nearest,
+            MagnificationFilter::Auto | MagnificationFilter::Linear => egui::TextureFilter::Linear,
+            MagnificationFilter::Nearest => egui::TextureFilter::Nearest,
+        }
+    }
+}
+
+/// Light or dark look of the interface
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug, Default)]
+pub enum Theme {
+    /// Follows the dark mode setting of the OS
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::System, Theme::Light, Theme::Dark];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Theme::System => "System",
+            Theme::Light => "Light",
+            Theme::Dark => "Dark",
+        }
+    }
+
+    pub fn preference(&self) -> egui::ThemePreference {
+        match self {
+            Theme::System => egui::ThemePreference::System,
+            Theme::Light => egui::ThemePreference::Light,
+            Theme::Dark => egui::ThemePreference::Dark,
+        }
+    }
+}
+
+/// Size of the interface relative to the size the OS asks for
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(transparent)]
+pub struct UiScale(pub f32);
+
+impl UiScale {
+    pub const MIN: f32 = 0.5;
+    pub const MAX: f32 = 3.0;
+}
+
+impl Default for UiScale {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// Memory the decoded image cache may use, in megabytes. 0 turns the cache off.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(transparent)]
+pub struct CacheBudget(pub u32);
+
+impl CacheBudget {
+    pub const MAX: u32 = 65536;
+
+    pub fn bytes(&self) -> usize {
+        self.0 as usize * 1024 * 1024
+    }
+}
+
+impl Default for CacheBudget {
+    fn default() -> Self {
+        Self(1024)
+    }
+}
+
+/// sRGB color of floating point pixels with NaN or infinite values
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(transparent)]
+pub struct NonFiniteColor(pub [u8; 3]);
+
+impl Default for NonFiniteColor {
+    fn default() -> Self {
+        Self([255, 0, 255])
+    }
+}
+
+/// User settings persisted as JSON in the platform config directory
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    /// Start directory of the Open dialog when no image was opened yet
+    #[serde(with = "crate::paths::serde_option")]
+    pub default_directory: Option<PathBuf>,
+    pub startup: StartupBehavior,
+    /// Start a new viewer for every launched file instead of opening it in the running one
+    pub multiple_instances: bool,
+    /// Image that was open when the viewer was closed
+    #[serde(with = "crate::paths::serde_option")]
+    pub last_session: Option<PathBuf>,
+    /// User defined and imported colormaps
+    pub colormaps: Vec<Colormap>,
+    /// Convert the displayed image from sRGB to the monitor's ICC profile
+    pub color_management: bool,
+    /// ICC profile to use instead of the one reported by the OS
+    #[serde(with = "crate::paths::serde_option")]
+    pub display_profile: Option<PathBuf>,
+    /// Keyboard shortcuts that differ from the defaults
+    pub keybindings: Keybindings,
+    /// Do not flag nearly black, nearly white, flat and bordered images in folders
+    pub skip_classification: bool,
+    /// Do not reload the open image when it changes on disk or rescan its folder when files come and go
+    pub disable_auto_reload: bool,
+    /// Move files to the trash with the Delete key without asking first
+    pub skip_delete_confirmation: bool,
+    /// Command that opens a file in an external editor, `{path}` is replaced by the file
+    pub external_editor: String,
+    /// Background behind transparent pixels
+    pub background: Background,
+    /// Color of the custom background as sRGB
+    pub background_color: [u8; 3],
+    /// Filter used to resize the image when it is displayed smaller than its size
+    pub downscale_filter: DownscaleFilter,
+    /// Upload the full resolution image and let the GPU scale it instead of resizing on the CPU
+    pub disable_downscaling: bool,
+    /// Filter used to enlarge the image when zoomed in
+    pub magnification: MagnificationFilter,
+    /// Memory kept for recently decoded images
+    pub image_cache: CacheBudget,
+    /// Memory kept for undoing rotations, flips, crops and resizes
+    pub undo_memory: CacheBudget,
+    /// Compute histograms of large images on the CPU instead of the GPU
+    pub cpu_histogram: bool,
+    /// Do not outline the pixels when zoomed in 8× or more
+    pub hide_pixel_grid: bool,
+    /// Print the value of every pixel inside its cell when zoomed in far enough
+    pub show_pixel_values: bool,
+    /// Square averaged by the pixel tool and the color picker
+    pub pixel_sample: PixelSample,
+    /// Color of NaN and infinite values in floating point images
+    pub non_finite_color: NonFiniteColor,
+    /// Tone mapping curve of EXR and Radiance HDR images
+    pub tone_mapper: ToneMapper,
+    /// File name templates of crop, FFT and frame exports
+    pub export_naming: ExportNaming,
+    /// Light, dark or the theme of the OS
+    pub theme: Theme,
+    /// Color of the view around the image as sRGB, the panel color of the theme when unset
+    pub canvas_color: Option<[u8; 3]>,
+    /// Zoom of the interface, not of the image
+    pub ui_scale: UiScale,
+}
+
+impl Config {
+    /// Location of the config file, e.g. `~/.config/image_viewer/config.json` on Linux
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("image_viewer").join(CONFIG_FILE_NAME))
+    }
+
+    /// Load the config, falling back to defaults when it is missing or unreadable
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        match fs::read_to_string(&path) {
+            Ok(content) => match serde_json::from_str(&content) {
+                Ok(config) => {
+                    info!("Loaded config from {:?}", path);
+                    config
+                }
+                Err(e) => {
+                    warn!("Invalid config file {:?}, using defaults: {}", path, e);
+                    Self::default()
+                }
+            },
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path().ok_or_else(|| anyhow::anyhow!("No config directory on this platform"))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        info!("Saved config to {:?}", path);
+        Ok(())
+    }
+
+    /// Directory the Open dialog starts in when there is no last opened folder
+    pub fn start_directory(&self) -> PathBuf {
+        match &self.default_directory {
+            Some(dir) if dir.is_dir() => dir.clone(),
+            _ => platform_default_directory(),
+        }
+    }
+}
+
+/// The user's Pictures folder, then home, then the working directory.
+/// Uses the platform APIs, so it also works on Windows where HOME is not set.
+pub fn platform_default_directory() -> PathBuf {
+    dirs::picture_dir()
+        .filter(|dir| dir.is_dir())
+        .or_else(|| dirs::home_dir().filter(|dir| dir.is_dir()))
+        .unwrap_or_else(|| env::current_dir().unwrap_or_default())
+}
@@ -0,0 +1,123 @@
+use crate::PixelRect;
+
+/// Aspect ratio the crop rectangle is locked to
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum AspectRatio {
+    Free,
+    Square,
+    ThreeTwo,
+    FourThree,
+    SixteenNine,
+    Custom,
+}
+
+impl AspectRatio {
+    pub const ALL: [AspectRatio; 6] = [
+        AspectRatio::Free,
+        AspectRatio::Square,
+        AspectRatio::ThreeTwo,
+        AspectRatio::FourThree,
+        AspectRatio::SixteenNine,
+        AspectRatio::Custom,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AspectRatio::Free => "Free",
+            AspectRatio::Square => "1:1",
+            AspectRatio::ThreeTwo => "3:2",
+            AspectRatio::FourThree => "4:3",
+            AspectRatio::SixteenNine => "16:9",
+            AspectRatio::Custom => "Custom",
+        }
+    }
+}
+
+/// State of the interactive crop tool
+pub struct CropTool {
+    pub rect: Option<PixelRect>,
+    pub aspect: AspectRatio,
+    /// Width and height of the custom ratio
+    pub custom: (u32, u32),
+    /// Swap the ratio, e.g. 2:3 instead of 3:2
+    pub portrait: bool,
+    pub show_thirds: bool,
+    /// Pixel where the current drag started
+    pub drag_start: Option<(u32, u32)>,
+}
+
+impl Default for CropTool {
+    fn default() -> Self {
+        Self {
+            rect: None,
+            aspect: AspectRatio::Free,
+            custom: (4, 5),
+            portrait: false,
+            show_thirds: true,
+            drag_start: None,
+        }
+    }
+}
+
+impl CropTool {
+    /// Locked width / height ratio, None when free
+    pub fn ratio(&self) -> Option<f32> {
+        let (w, h) = match self.aspect {
+            AspectRatio::Free => return None,
+            AspectRatio::Square => (1, 1),
+            AspectRatio::ThreeTwo => (3, 2),
+            AspectRatio::FourThree => (4, 3),
+            AspectRatio::SixteenNine => (16, 9),
+            AspectRatio::Custom => (self.custom.0.max(1), self.custom.1.max(1)),
+        };
+        Some(if self.portrait { h as f32 / w as f32 } else { w as f32 / h as f32 })
+    }
+
+    /// Shrink the larger side of a size so it matches the locked ratio
+    fn fit_ratio(&self, width: u32, height: u32) -> (u32, u32) {
+        match self.ratio() {
+            Some(ratio) if width as f32 > height as f32 * ratio => ((height as f32 * ratio).round() as u32, height),
+            Some(ratio) => (width, (width as f32 / ratio).round() as u32),
+            None => (width, height),
+        }
+    }
+
+    /// Rectangle spanned by dragging from `start` to `current`, locked to the ratio and kept inside the image
+    pub fn drag_rect(&self, start: (u32, u32), current: (u32, u32), image_width: u32, image_height: u32) -> PixelRect {
+        let (sx, sy) = start;
+        let (right, down) = (current.0 >= sx, current.1 >= sy);
+        let max_w = if right { image_width - sx } else { sx };
+        let max_h = if down { image_height - sy } else { sy };
+        let (w, h) = self.fit_ratio(current.0.abs_diff(sx).min(max_w), current.1.abs_diff(sy).min(max_h));
+
+        let (x0, x1) = if right { (sx, sx + w) } else { (sx - w, sx) };
+        let (y0, y1) = if down { (sy, sy + h) } else { (sy - h, sy) };
+        [x0, y0, x1, y1]
+    }
+
+    /// Adjust a rectangle to the ratio around its center and move it inside the image
+    pub fn constrain(&self, rect: PixelRect, image_width: u32, image_height: u32) -> PixelRect {
+        let [x0, y0, x1, y1] = rect;
+        let (w, h) = self.fit_ratio(x1.saturating_sub(x0).min(image_width), y1.saturating_sub(y0).min(image_height));
+        let (w, h) = (w.min(image_width), h.min(image_height));
+        let cx = (x0 + x1) / 2;
+        let cy = (y0 + y1) / 2;
+        let x = cx.saturating_sub(w / 2).min(image_width - w);
+        let y = cy.saturating_sub(h / 2).min(image_height - h);
+        [x, y, x + w, y + h]
+    }
+}
+
+/// Copy a rectangle out of an interleaved floating point buffer
+pub fn crop_fp(data: &[f32], width: u32, channels: usize, rect: PixelRect) -> Vec<f32> {
+    let [x0, y0, x1, y1] = rect;
+    let mut output = Vec::with_capacity((x1 - x0) as usize * (y1 - y0) as usize * channels);
+    for y in y0..y1 {
+        let start = (y as usize * width as usize + x0 as usize) * channels;
+        let end = start + (x1 - x0) as usize * channels;
+        if let Some(row) = data.get(start..end) {
+            output.extend_from_slice(row);
+        }
+    }
+    output
+}
@@ -0,0 +1,132 @@
+use anyhow::anyhow;
+use moxcms::{ColorProfile, Layout, Transform8BitExecutor, TransformOptions};
+use std::sync::Arc;
+
+/// Converts the sRGB output of the display pipeline into the color space of a monitor
+pub struct DisplayTransform {
+    transform: Arc<Transform8BitExecutor>,
+    /// The ICC profile the transform was built from, to detect when the monitor changes
+    icc: Vec<u8>,
+}
+
+impl DisplayTransform {
+    pub fn new(icc: Vec<u8>) -> anyhow::Result<Self> {
+        let display = ColorProfile::new_from_slice(&icc).map_err(|e| anyhow!("Invalid display profile: {:?}", e))?;
+        let transform = ColorProfile::new_srgb()
+            .create_transform_8bit(Layout::Rgba, &display, Layout::Rgba, TransformOptions::default())
+            .map_err(|e| anyhow!("Cannot convert to display profile: {:?}", e))?;
+        Ok(Self { transform, icc })
+    }
+
+    pub fn icc(&self) -> &[u8] {
+        &self.icc
+    }
+
+    /// Transform an image in place, alpha is left untouched
+    pub fn apply(&self, image: &mut egui::ColorImage) -> anyhow::Result<()> {
+        let src: Vec<u8> = image.pixels.iter().flat_map(|p| p.to_srgba_unmultiplied()).collect();
+        let mut dst = vec![0u8; src.len()];
+        self.transform
+            .transform(&src, &mut dst)
+            .map_err(|e| anyhow!("Display transform failed: {:?}", e))?;
+        *image = egui::ColorImage::from_rgba_unmultiplied(image.size, &dst);
+        Ok(())
+    }
+}
+
+/// ICC profile the OS assigns to the monitor containing `point` (physical pixels).
+/// `Ok(None)` when the monitor has no profile or the platform is not supported.
+pub fn query_display_profile(point: Option<(i32, i32)>) -> anyhow::Result<Option<Vec<u8>>> {
+    platform::query_display_profile(point)
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use x11rb::connection::Connection;
+    use x11rb::protocol::randr::ConnectionExt as _;
+    use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _};
+
+    /// X11 "ICC Profiles in X" convention: `_ICC_PROFILE` on the root window for the first
+    /// monitor, `_ICC_PROFILE_<n>` for monitor n. Set by colord, KDE, GNOME and most calibration tools.
+    pub fn query_display_profile(point: Option<(i32, i32)>) -> anyhow::Result<Option<Vec<u8>>> {
+        if std::env::var_os("DISPLAY").is_none() {
+            // Wayland without XWayland has no equivalent protocol yet
+            return Ok(None);
+        }
+        let (conn, screen) = x11rb::connect(None)?;
+        let root = conn.setup().roots[screen].root;
+
+        let monitor_index = match point {
+            Some((x, y)) => conn
+                .randr_get_monitors(root, true)?
+                .reply()?
+                .monitors
+                .iter()
+                .position(|m| {
+                    let (mx, my) = (m.x as i32, m.y as i32);
+                    x >= mx && x < mx + m.width as i32 && y >= my && y < my + m.height as i32
+                })
+                .unwrap_or(0),
+            None => 0,
+        };
+
+        let atom_name = match monitor_index {
+            0 => "_ICC_PROFILE".to_string(),
+            n => format!("_ICC_PROFILE_{}", n),
+        };
+        let atom = conn.intern_atom(true, atom_name.as_bytes())?.reply()?.atom;
+        if atom == x11rb::NONE {
+            return Ok(None);
+        }
+        let property = conn
+            .get_property(false, root, atom, AtomEnum::ANY, 0, u32::MAX / 4)?
+            .reply()?;
+        Ok(Some(property.value).filter(|icc| !icc.is_empty()))
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ptr;
+    use windows_sys::Win32::Foundation::POINT;
+    use windows_sys::Win32::Graphics::Gdi::{
+        CreateDCW, DeleteDC, GetMonitorInfoW, MonitorFromPoint, MONITORINFO, MONITORINFOEXW, MONITOR_DEFAULTTONEAREST,
+    };
+    use windows_sys::Win32::UI::ColorSystem::GetICMProfileW;
+
+    /// Profile assigned in Color Management for the monitor nearest to `point`
+    pub fn query_display_profile(point: Option<(i32, i32)>) -> anyhow::Result<Option<Vec<u8>>> {
+        let (x, y) = point.unwrap_or((0, 0));
+        // SAFETY: plain Win32 calls with correctly sized, initialized buffers; the DC is released below
+        let path = unsafe {
+            let monitor = MonitorFromPoint(POINT { x, y }, MONITOR_DEFAULTTONEAREST);
+            let mut info: MONITORINFOEXW = std::mem::zeroed();
+            info.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+            if GetMonitorInfoW(monitor, &mut info as *mut MONITORINFOEXW as *mut MONITORINFO) == 0 {
+                return Ok(None);
+            }
+            let dc = CreateDCW(info.szDevice.as_ptr(), info.szDevice.as_ptr(), ptr::null(), ptr::null());
+            if dc.is_null() {
+                return Ok(None);
+            }
+            let mut buffer = [0u16; 260];
+            let mut size = buffer.len() as u32;
+            let found = GetICMProfileW(dc, &mut size, buffer.as_mut_ptr()) != 0;
+            DeleteDC(dc);
+            if !found {
+                return Ok(None);
+            }
+            let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+            String::from_utf16_lossy(&buffer[..len])
+        };
+        Ok(Some(std::fs::read(path)?))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod platform {
+    /// No OS query on this platform, a profile can still be chosen in the settings
+    pub fn query_display_profile(_point: Option<(i32, i32)>) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+}
@@ -0,0 +1,276 @@
+//! The open file dragged out of the window into file managers, chat apps and editors. The
+//! window system only offers dropping into windows, so the drag is driven here: on X11 by a
+//! small XDND source with its own connection that follows the pointer until the button is
+//! released, on Windows by the shell's drag loop. Wayland and macOS are not supported.
+
+use std::path::Path;
+
+/// Start dragging `path` to other applications while the primary button is held. On X11 the
+/// drag runs in the background, on Windows the call returns after the drop.
+pub fn start(path: &Path) -> anyhow::Result<()> {
+    platform::start(&std::path::absolute(path)?)
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use crate::thumbnail_store::file_uri;
+    use anyhow::bail;
+    use log::warn;
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{
+        Atom, AtomEnum, ClientMessageEvent, ConnectionExt as _, CreateWindowAux, EventMask, KeyButMask, PropMode,
+        SelectionNotifyEvent, SelectionRequestEvent, Window, WindowClass, SELECTION_NOTIFY_EVENT,
+    };
+    use x11rb::protocol::Event;
+    use x11rb::rust_connection::RustConnection;
+    use x11rb::wrapper::ConnectionExt as _;
+    use x11rb::{COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT, CURRENT_TIME, NONE};
+
+    /// Highest XDND version spoken here
+    const XDND_VERSION: u32 = 5;
+    /// How long a target may take to fetch the file after the drop
+    const FINISH_TIMEOUT: Duration = Duration::from_secs(5);
+
+    x11rb::atom_manager! {
+        Atoms: AtomsCookie {
+            XdndAware,
+            XdndSelection,
+            XdndEnter,
+            XdndPosition,
+            XdndStatus,
+            XdndLeave,
+            XdndDrop,
+            XdndFinished,
+            XdndActionCopy,
+            TARGETS,
+            UriList: b"text/uri-list",
+        }
+    }
+
+    pub fn start(path: &Path) -> anyhow::Result<()> {
+        // winit prefers Wayland whenever it is available, X11 windows cannot reach its clients
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            bail!("Dragging files out of the window is only supported on X11, not on Wayland");
+        }
+        let drag = Drag::new(format!("{}\r\n", file_uri(path)))?;
+        std::thread::spawn(move || {
+            if let Err(e) = drag.run() {
+                warn!("Dragging the file out failed: {:#}", e);
+            }
+        });
+        Ok(())
+    }
+
+    /// Source side of XDND, an unmapped window that owns the selection with the file URI
+    struct Drag {
+        conn: RustConnection,
+        root: Window,
+        window: Window,
+        atoms: Atoms,
+        uri: String,
+    }
+
+    impl Drag {
+        fn new(uri: String) -> anyhow::Result<Self> {
+            let (conn, screen) = x11rb::connect(None)?;
+            let root = conn.setup().roots[screen].root;
+            let window = conn.generate_id()?;
+            conn.create_window(COPY_DEPTH_FROM_PARENT, window, root, 0, 0, 1, 1, 0, WindowClass::INPUT_ONLY, COPY_FROM_PARENT, &CreateWindowAux::new())?;
+            let atoms = Atoms::new(&conn)?.reply()?;
+            conn.set_selection_owner(window, atoms.XdndSelection, CURRENT_TIME)?;
+            conn.flush()?;
+            Ok(Self { conn, root, window, atoms, uri })
+        }
+
+        /// Follow the pointer, telling the windows under it about the drag, until the button is released
+        fn run(self) -> anyhow::Result<()> {
+            let atoms = self.atoms;
+            let mut target: Option<Window> = None;
+            let mut accepted = false;
+            // XDND allows one position message at a time, the next waits for the status
+            let mut waiting = false;
+            let mut sent = None;
+            loop {
+                while let Some(event) = self.conn.poll_for_event()? {
+                    match event {
+                        Event::ClientMessage(event) if event.type_ == atoms.XdndStatus => {
+                            let data = event.data.as_data32();
+                            if target == Some(data[0]) {
+                                accepted = data[1] & 1 != 0;
+                                waiting = false;
+                            }
+                        }
+                        Event::SelectionRequest(request) => self.answer(&request)?,
+                        _ => {}
+                    }
+                }
+                let pointer = self.conn.query_pointer(self.root)?.reply()?;
+                let over = self.target_at(pointer.root_x, pointer.root_y)?;
+                if over.map(|(window, _)| window) != target {
+                    if let Some(window) = target {
+                        self.send(window, atoms.XdndLeave, [self.window, 0, 0, 0, 0])?;
+                    }
+                    if let Some((window, version)) = over {
+                        self.send(window, atoms.XdndEnter, [self.window, version << 24, atoms.UriList, NONE, NONE])?;
+                    }
+                    target = over.map(|(window, _)| window);
+                    (accepted, waiting, sent) = (false, false, None);
+                }
+                if !pointer.mask.contains(KeyButMask::BUTTON1) {
+                    break;
+                }
+                let position = (pointer.root_x, pointer.root_y);
+                if let Some(window) = target.filter(|_| !waiting && sent != Some(position)) {
+                    let packed = ((position.0 as u32) << 16) | (position.1 as u32 & 0xffff);
+                    self.send(window, atoms.XdndPosition, [self.window, 0, packed, CURRENT_TIME, atoms.XdndActionCopy])?;
+                    (waiting, sent) = (true, Some(position));
+                }
+                self.conn.flush()?;
+                std::thread::sleep(Duration::from_millis(10));
+            }
+
+            match target {
+                Some(window) if accepted => {
+                    self.send(window, atoms.XdndDrop, [self.window, 0, CURRENT_TIME, 0, 0])?;
+                    self.conn.flush()?;
+                    self.wait_finished(window)
+                }
+                Some(window) => {
+                    self.send(window, atoms.XdndLeave, [self.window, 0, 0, 0, 0])?;
+                    self.conn.flush()?;
+                    Ok(())
+                }
+                None => Ok(()),
+            }
+        }
+
+        /// Hand out the file until the target reports the drop as finished
+        fn wait_finished(&self, target: Window) -> anyhow::Result<()> {
+            let deadline = Instant::now() + FINISH_TIMEOUT;
+            while Instant::now() < deadline {
+                while let Some(event) = self.conn.poll_for_event()? {
+                    match event {
+                        Event::ClientMessage(event) if event.type_ == self.atoms.XdndFinished && event.data.as_data32()[0] == target => return Ok(()),
+                        Event::SelectionRequest(request) => self.answer(&request)?,
+                        _ => {}
+                    }
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            bail!("The drop target did not finish within {} s", FINISH_TIMEOUT.as_secs())
+        }
+
+        /// Innermost window under the root position that accepts drops, with the XDND version to use
+        fn target_at(&self, x: i16, y: i16) -> anyhow::Result<Option<(Window, u32)>> {
+            let mut window = self.root;
+            loop {
+                let child = self.conn.translate_coordinates(self.root, window, x, y)?.reply()?.child;
+                if child == NONE {
+                    return Ok(None);
+                }
+                let aware = self.conn.get_property(false, child, self.atoms.XdndAware, AtomEnum::ATOM, 0, 1)?.reply()?;
+                if let Some(version) = aware.value32().and_then(|mut values| values.next()) {
+                    return Ok(Some((child, version.min(XDND_VERSION))));
+                }
+                window = child;
+            }
+        }
+
+        fn send(&self, window: Window, kind: Atom, data: [u32; 5]) -> anyhow::Result<()> {
+            self.conn.send_event(false, window, EventMask::NO_EVENT, ClientMessageEvent::new(32, window, kind, data))?;
+            Ok(())
+        }
+
+        /// Store the file URI, or the list of offered types, in the property the target asked for
+        fn answer(&self, request: &SelectionRequestEvent) -> anyhow::Result<()> {
+            // Obsolete clients leave the property empty and expect the target name instead
+            let property = if request.property == NONE { request.target } else { request.property };
+            let stored = if request.target == self.atoms.UriList {
+                self.conn.change_property8(PropMode::REPLACE, request.requestor, property, self.atoms.UriList, self.uri.as_bytes())?;
+                true
+            } else if request.target == self.atoms.TARGETS {
+                self.conn.change_property32(PropMode::REPLACE, request.requestor, property, AtomEnum::ATOM, &[self.atoms.TARGETS, self.atoms.UriList])?;
+                true
+            } else {
+                false
+            };
+            let notify = SelectionNotifyEvent {
+                response_type: SELECTION_NOTIFY_EVENT,
+                sequence: 0,
+                time: request.time,
+                requestor: request.requestor,
+                selection: request.selection,
+                target: request.target,
+                property: if stored { property } else { NONE },
+            };
+            self.conn.send_event(false, request.requestor, EventMask::NO_EVENT, notify)?;
+            self.conn.flush()?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ffi::c_void;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+    use std::ptr::{null, null_mut};
+    use windows_sys::core::GUID;
+    use windows_sys::Win32::System::Ole::{DROPEFFECT, DROPEFFECT_COPY};
+    use windows_sys::Win32::UI::Shell::{ILCreateFromPathW, ILFree, SHCreateDataObject, SHDoDragDrop};
+    use windows_sys::Win32::UI::WindowsAndMessaging::GetForegroundWindow;
+
+    const IID_IDATAOBJECT: GUID = GUID::from_u128(0x0000010e_0000_0000_c000_000000000046);
+
+    /// Start of the vtable every COM object has
+    #[repr(C)]
+    struct IUnknownVtbl {
+        _query_interface: usize,
+        _add_ref: usize,
+        release: unsafe extern "system" fn(*mut c_void) -> u32,
+    }
+
+    /// A shell data object for the file, dragged with the default drop source of the shell
+    pub fn start(path: &Path) -> anyhow::Result<()> {
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+        // SAFETY: the null-terminated path outlives the call, the item list is freed once the data object holds a copy
+        let mut data: *mut c_void = null_mut();
+        let created = unsafe {
+            let item = ILCreateFromPathW(wide.as_ptr());
+            if item.is_null() {
+                anyhow::bail!("The shell has no item for {:?}", path);
+            }
+            let items = [item.cast_const()];
+            let created = SHCreateDataObject(null(), 1, items.as_ptr(), null_mut(), &IID_IDATAOBJECT, &mut data);
+            ILFree(item);
+            created
+        };
+        if created < 0 {
+            return Err(std::io::Error::from_raw_os_error(created).into());
+        }
+        let mut effect: DROPEFFECT = 0;
+        // SAFETY: `data` is a valid IDataObject that is released once the drag loop returns
+        let dragged = unsafe {
+            let dragged = SHDoDragDrop(GetForegroundWindow(), data, null_mut(), DROPEFFECT_COPY, &mut effect);
+            let vtable = *(data as *const *const IUnknownVtbl);
+            ((*vtable).release)(data);
+            dragged
+        };
+        if dragged < 0 {
+            return Err(std::io::Error::from_raw_os_error(dragged).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod platform {
+    use std::path::Path;
+
+    pub fn start(_path: &Path) -> anyhow::Result<()> {
+        anyhow::bail!("Dragging files out of the window is not supported on this platform")
+    }
+}
@@ -0,0 +1,261 @@
+use image::{DynamicImage, ImageFormat, ImageReader};
+use log::debug;
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// IFDs nested deeper than this are not searched, guards against offset loops in broken files
+const MAX_IFD_DEPTH: usize = 4;
+/// Upper bound of the IFDs visited in one file
+const MAX_IFDS: usize = 32;
+
+/// Embedded JPEG preview of a file: the EXIF thumbnail of a JPEG, or the preview images
+/// stored in TIFF based RAW files (DNG, NEF, CR2, ARW, ORF, RW2, PEF) and Fuji RAF files.
+/// Returns the smallest preview at least `min_size` pixels on its longer side, else the
+/// largest one, rotated to the file's EXIF orientation. `Ok(None)` when there is none.
+pub fn load_embedded_preview(path: &Path, min_size: u32) -> anyhow::Result<Option<DynamicImage>> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; 92];
+    let header_len = file.read(&mut header)?;
+    let header = &header[..header_len];
+
+    let (mut candidates, orientation) = if header.starts_with(&[0xFF, 0xD8]) {
+        match find_exif(&mut file)? {
+            Some(tiff_base) => TiffReader::new(&mut file, tiff_base)?.map(|mut tiff| tiff.previews()).unwrap_or_default(),
+            None => return Ok(None),
+        }
+    } else if header.starts_with(b"FUJIFILMCCD-RAW") && header.len() >= 92 {
+        // RAF: big endian offset and length of the full size preview JPEG
+        let offset = u32::from_be_bytes([header[84], header[85], header[86], header[87]]) as u64;
+        let length = u32::from_be_bytes([header[88], header[89], header[90], header[91]]) as u64;
+        (vec![(offset, length)], 1)
+    } else {
+        match TiffReader::new(&mut file, 0)? {
+            Some(mut tiff) => tiff.previews(),
+            None => return Ok(None),
+        }
+    };
+
+    // Smaller previews decode faster, the byte length is a good proxy for the size
+    candidates.sort_by_key(|&(_, length)| length);
+    candidates.dedup();
+    let mut chosen = None;
+    for &(offset, length) in &candidates {
+        let Some(jpeg) = read_jpeg(&mut file, offset, length)? else {
+            continue;
+        };
+        let (width, height) = ImageReader::with_format(Cursor::new(&jpeg), ImageFormat::Jpeg).into_dimensions()?;
+        let large_enough = width.max(height) >= min_size;
+        chosen = Some(jpeg);
+        if large_enough {
+            break;
+        }
+    }
+    let Some(jpeg) = chosen else {
+        return Ok(None);
+    };
+
+    let mut preview = image::load_from_memory_with_format(&jpeg, ImageFormat::Jpeg)?;
+    // EXIF thumbnails are often letterboxed to 160×120, cut them to the aspect ratio of the image
+    if header.starts_with(&[0xFF, 0xD8]) {
+        if let Ok(dimensions) = ImageReader::open(path)?.with_guessed_format()?.into_dimensions() {
+            preview = crop_to_aspect(preview, dimensions);
+        }
+    }
+    if let Some(orientation) = image::metadata::Orientation::from_exif(orientation as u8) {
+        preview.apply_orientation(orientation);
+    }
+    debug!("Using {}x{} embedded preview of {:?}", preview.width(), preview.height(), path);
+    Ok(Some(preview))
+}
+
+/// Bytes of a candidate preview if they hold a JPEG
+fn read_jpeg(file: &mut File, offset: u64, length: u64) -> anyhow::Result<Option<Vec<u8>>> {
+    let file_len = file.metadata()?.len();
+    if length < 4 || offset.saturating_add(length) > file_len {
+        return Ok(None);
+    }
+    let mut data = vec![0u8; length as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut data)?;
+    Ok(data.starts_with(&[0xFF, 0xD8]).then_some(data))
+}
+
+/// Center crop removing the bars a thumbnail got from a different aspect ratio
+fn crop_to_aspect(preview: DynamicImage, (width, height): (u32, u32)) -> DynamicImage {
+    let (pw, ph) = (preview.width(), preview.height());
+    if width == 0 || height == 0 || pw == 0 || ph == 0 {
+        return preview;
+    }
+    let target = width as f32 / height as f32;
+    let current = pw as f32 / ph as f32;
+    if (target / current - 1.0).abs() < 0.02 {
+        preview
+    } else if target > current {
+        let new_height = ((pw as f32 / target).round() as u32).clamp(1, ph);
+        preview.crop_imm(0, (ph - new_height) / 2, pw, new_height)
+    } else {
+        let new_width = ((ph as f32 * target).round() as u32).clamp(1, pw);
+        preview.crop_imm((pw - new_width) / 2, 0, new_width, ph)
+    }
+}
+
+/// File offset of the TIFF structure inside the EXIF APP1 segment of a JPEG
+fn find_exif(file: &mut File) -> anyhow::Result<Option<u64>> {
+    let mut pos = 2u64;
+    loop {
+        let mut marker = [0u8; 4];
+        file.seek(SeekFrom::Start(pos))?;
+        if file.read_exact(&mut marker).is_err() || marker[0] != 0xFF {
+            return Ok(None);
+        }
+        if marker[1] == 0xDA || marker[1] == 0xD9 {
+            // Start of scan, no more metadata segments
+            return Ok(None);
+        }
+        let length = u16::from_be_bytes([marker[2], marker[3]]) as u64;
+        if marker[1] == 0xE1 {
+            let mut signature = [0u8; 6];
+            if file.read_exact(&mut signature).is_ok() && &signature == b"Exif\0\0" {
+                return Ok(Some(pos + 10));
+            }
+        }
+        pos += 2 + length;
+    }
+}
+
+/// Minimal reader for the IFDs of a TIFF structure starting at `base` in a file
+struct TiffReader<'a> {
+    file: &'a mut File,
+    base: u64,
+    little_endian: bool,
+    visited: usize,
+}
+
+/// Entry of an IFD: tag, field type, count and the raw 4 byte value/offset field
+#[derive(Clone, Copy)]
+struct IfdEntry {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    value: [u8; 4],
+}
+
+impl<'a> TiffReader<'a> {
+    fn new(file: &'a mut File, base: u64) -> anyhow::Result<Option<Self>> {
+        let mut header = [0u8; 2];
+        file.seek(SeekFrom::Start(base))?;
+        if file.read_exact(&mut header).is_err() {
+            return Ok(None);
+        }
+        // Olympus (ORF) and Panasonic (RW2) use their own magic number after the byte order mark
+        let little_endian = match &header {
+            b"II" => true,
+            b"MM" => false,
+            _ => return Ok(None),
+        };
+        Ok(Some(Self { file, base, little_endian, visited: 0 }))
+    }
+
+    fn u16(&self, bytes: [u8; 2]) -> u16 {
+        if self.little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) }
+    }
+
+    fn u32(&self, bytes: [u8; 4]) -> u32 {
+        if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) }
+    }
+
+    fn read_u32_at(&mut self, offset: u64) -> anyhow::Result<u32> {
+        let mut bytes = [0u8; 4];
+        self.file.seek(SeekFrom::Start(self.base + offset))?;
+        self.file.read_exact(&mut bytes)?;
+        Ok(self.u32(bytes))
+    }
+
+    /// First value of a SHORT or LONG entry
+    fn value(&self, entry: &IfdEntry) -> u32 {
+        match entry.field_type {
+            3 => self.u16([entry.value[0], entry.value[1]]) as u32,
+            _ => self.u32(entry.value),
+        }
+    }
+
+    /// All values of a LONG or IFD entry, e.g. the offsets of the SubIFDs
+    fn values(&mut self, entry: &IfdEntry) -> anyhow::Result<Vec<u32>> {
+        if entry.count <= 1 {
+            return Ok(vec![self.value(entry)]);
+        }
+        let offset = self.u32(entry.value) as u64;
+        (0..entry.count.min(16) as u64).map(|i| self.read_u32_at(offset + i * 4)).collect()
+    }
+
+    /// Entries of the IFD at `offset` and the offset of the next IFD
+    fn read_ifd(&mut self, offset: u64) -> anyhow::Result<(Vec<IfdEntry>, u32)> {
+        let mut count = [0u8; 2];
+        self.file.seek(SeekFrom::Start(self.base + offset))?;
+        self.file.read_exact(&mut count)?;
+        let count = self.u16(count).min(1024) as usize;
+        let mut raw = vec![0u8; count * 12 + 4];
+        self.file.read_exact(&mut raw)?;
+        let entries = raw
+            .chunks_exact(12)
+            .map(|e| IfdEntry {
+                tag: self.u16([e[0], e[1]]),
+                field_type: self.u16([e[2], e[3]]),
+                count: self.u32([e[4], e[5], e[6], e[7]]),
+                value: [e[8], e[9], e[10], e[11]],
+            })
+            .collect();
+        let next = self.u32([raw[count * 12], raw[count * 12 + 1], raw[count * 12 + 2], raw[count * 12 + 3]]);
+        Ok((entries, next))
+    }
+
+    /// JPEG previews as (file offset, length), and the orientation of IFD0
+    fn previews(&mut self) -> (Vec<(u64, u64)>, u32) {
+        let mut previews = Vec::new();
+        let mut orientation = 1;
+        let first = self.read_u32_at(4).unwrap_or(0);
+        if let Err(e) = self.collect(first, 0, &mut previews, &mut orientation) {
+            debug!("Stopped reading TIFF structure: {}", e);
+        }
+        (previews, orientation)
+    }
+
+    fn collect(&mut self, offset: u32, depth: usize, previews: &mut Vec<(u64, u64)>, orientation: &mut u32) -> anyhow::Result<()> {
+        let mut next = offset;
+        let mut index = 0;
+        while next != 0 && depth < MAX_IFD_DEPTH && self.visited < MAX_IFDS {
+            self.visited += 1;
+            let (entries, following) = self.read_ifd(next as u64)?;
+            let find = |tag: u16| entries.iter().find(|e| e.tag == tag);
+
+            if depth == 0 && index == 0 {
+                if let Some(entry) = find(0x0112) {
+                    *orientation = self.value(entry);
+                }
+            }
+            // JPEGInterchangeFormat / JPEGInterchangeFormatLength: EXIF thumbnails, NEF and ARW previews
+            if let (Some(start), Some(length)) = (find(0x0201), find(0x0202)) {
+                previews.push((self.base + self.value(start) as u64, self.value(length) as u64));
+            }
+            // Single strip JPEG images: CR2 previews (old-style JPEG) and reduced resolution DNG previews
+            let compression = find(0x0103).map(|e| self.value(e));
+            let reduced = find(0x00FE).is_some_and(|e| self.value(e) & 1 == 1);
+            if let (Some(strip), Some(length)) = (find(0x0111), find(0x0117)) {
+                if strip.count == 1 && (compression == Some(6) || (compression == Some(7) && reduced)) {
+                    previews.push((self.base + self.value(strip) as u64, self.value(length) as u64));
+                }
+            }
+            if let Some(sub_ifds) = find(0x014A) {
+                let sub_ifds = *sub_ifds;
+                for sub in self.values(&sub_ifds)? {
+                    self.collect(sub, depth + 1, previews, orientation)?;
+                }
+            }
+
+            next = following;
+            index += 1;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+use crate::PixelRect;
+use image::DynamicImage;
+use rustfft::num_complex::Complex;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Write an array in NumPy `.npy` format (version 1.0, C order)
+fn write_npy(path: &Path, descr: &str, shape: &[usize], data: &[u8]) -> anyhow::Result<()> {
+    let shape_str = match shape {
+        [n] => format!("({},)", n),
+        _ => format!("({})", shape.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(", ")),
+    };
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", descr, shape_str);
+    // Magic (6) + version (2) + header length (2) + header must be a multiple of 64, ending in newline
+    let unpadded = 10 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    writer.write_all(data)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write a float32 array of the given shape as `.npy`
+pub fn write_npy_f32(path: &Path, shape: &[usize], data: &[f32]) -> anyhow::Result<()> {
+    let bytes: Vec<u8> = data.iter().flat_map(|v| v.to_le_bytes()).collect();
+    write_npy(path, "<f4", shape, &bytes)
+}
+
+/// Write a complex64 array of the given shape as `.npy`
+pub fn write_npy_complex(path: &Path, shape: &[usize], data: &[Complex<f32>]) -> anyhow::Result<()> {
+    let bytes: Vec<u8> = data
+        .iter()
+        .flat_map(|c| c.re.to_le_bytes().into_iter().chain(c.im.to_le_bytes()))
+        .collect();
+    write_npy(path, "<c8", shape, &bytes)
+}
+
+/// Write a row-major 2D array as comma separated values, one image row per line
+pub fn write_csv_f32(path: &Path, width: usize, data: &[f32]) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for row in data.chunks(width.max(1)) {
+        let line = row.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(",");
+        writeln!(writer, "{}", line)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Pixel values of an image region, row-major with `channels` values per pixel
+pub struct PixelRegion {
+    pub width: usize,
+    pub height: usize,
+    pub channels: usize,
+    /// NumPy dtype of the source data
+    pub dtype: &'static str,
+    pub values: Vec<f32>,
+}
+
+impl PixelRegion {
+    /// Values of a region of the decoded image, in its native sample type
+    pub fn from_image(image: &DynamicImage, rect: PixelRect) -> Self {
+        let color = image.color();
+        let channels = color.channel_count() as usize;
+        let sample_size = color.bytes_per_pixel() as usize / channels;
+        let (dtype, read): (&'static str, fn(&[u8]) -> f32) = match sample_size {
+            1 => ("uint8", |b| b[0] as f32),
+            2 => ("uint16", |b| u16::from_ne_bytes([b[0], b[1]]) as f32),
+            _ => ("float32", |b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]])),
+        };
+
+        let [x0, y0, x1, y1] = rect;
+        let bytes = image.as_bytes();
+        let row_bytes = image.width() as usize * channels * sample_size;
+        let mut values = Vec::with_capacity((x1 - x0) as usize * (y1 - y0) as usize * channels);
+        for y in y0 as usize..y1 as usize {
+            let start = y * row_bytes + x0 as usize * channels * sample_size;
+            let end = y * row_bytes + x1 as usize * channels * sample_size;
+            values.extend(bytes[start..end].chunks_exact(sample_size).map(read));
+        }
+        Self { width: (x1 - x0) as usize, height: (y1 - y0) as usize, channels, dtype, values }
+    }
+
+    /// Values of a region of interleaved floating point data
+    pub fn from_fp(data: &[f32], width: u32, channels: usize, rect: PixelRect) -> Self {
+        let [x0, y0, x1, y1] = rect;
+        Self {
+            width: (x1 - x0) as usize,
+            height: (y1 - y0) as usize,
+            channels,
+            dtype: "float32",
+            values: crate::crop::crop_fp(data, width, channels, rect),
+        }
+    }
+
+    /// Values formatted to a common width, so columns line up
+    fn formatted(&self) -> (Vec<String>, usize) {
+        let text: Vec<String> = self.values.iter().map(|v| v.to_string()).collect();
+        let width = text.iter().map(String::len).max().unwrap_or(0);
+        (text, width)
+    }
+
+    /// Whitespace separated matrix, one image row per line. Multi-channel regions get one block per channel.
+    pub fn to_text(&self) -> String {
+        let (text, width) = self.formatted();
+        let mut output = String::new();
+        for channel in 0..self.channels {
+            if self.channels > 1 {
+                if channel > 0 {
+                    output.push('\n');
+                }
+                output.push_str(&format!("# channel {}\n", channel));
+            }
+            for y in 0..self.height {
+                let row: Vec<String> = (0..self.width)
+                    .map(|x| format!("{:>width$}", text[(y * self.width + x) * self.channels + channel], width = width))
+                    .collect();
+                output.push_str(row.join(" ").trim_end());
+                output.push('\n');
+            }
+        }
+        output
+    }
+
+    /// `np.array(...)` literal of shape (height, width) or (height, width, channels)
+    pub fn to_numpy(&self) -> String {
+        let (text, width) = self.formatted();
+        let pixel = |index: usize| -> String {
+            let values: Vec<String> = text[index * self.channels..(index + 1) * self.channels]
+                .iter()
+                .map(|v| format!("{:>width$}", v, width = width))
+                .collect();
+            if self.channels == 1 {
+                values[0].clone()
+            } else {
+                format!("[{}]", values.join(", "))
+            }
+        };
+        let rows: Vec<String> = (0..self.height)
+            .map(|y| {
+                let pixels: Vec<String> = (0..self.width).map(|x| pixel(y * self.width + x)).collect();
+                format!("    [{}],", pixels.join(", "))
+            })
+            .collect();
+        format!("np.array([\n{}\n], dtype=np.{})\n", rows.join("\n"), self.dtype)
+    }
+}
+
+/// Which files the FFT export writes
+#[derive(Clone, Copy)]
+pub struct FftExportOptions {
+    pub spectrum_png: bool,
+    pub magnitude_npy: bool,
+    pub complex_npy: bool,
+    pub magnitude_csv: bool,
+}
+
+impl Default for FftExportOptions {
+    fn default() -> Self {
+        Self {
+            spectrum_png: true,
+            magnitude_npy: true,
+            complex_npy: false,
+            magnitude_csv: false,
+        }
+    }
+}
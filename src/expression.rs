@@ -0,0 +1,431 @@
+//! Per-pixel expressions deriving a single value from the channels, e.g. `(r - b) / (r + b)` for
+//! NDVI-style indices. The channels are read in the units of the data: 8-bit or 16-bit levels, or
+//! the original floating point values.
+
+use anyhow::{anyhow, bail};
+use std::sync::{Arc, Mutex};
+
+use crate::transforms::ExpressionView;
+
+/// Examples offered in the tool, with a description
+pub const PRESETS: [(&str, &str); 5] = [
+    ("(r - b) / (r + b)", "Normalized difference of red and blue"),
+    ("(g - r) / (g + r)", "Normalized difference of green and red"),
+    ("r / max(g, 1)", "Ratio of red to green"),
+    ("0.2126 * r + 0.7152 * g + 0.0722 * b", "Rec.709 luma"),
+    ("sqrt(r^2 + g^2 + b^2)", "Length of the RGB vector"),
+];
+
+#[derive(Clone, Copy, Debug)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Function {
+    Abs,
+    Sqrt,
+    Ln,
+    Log10,
+    Exp,
+    Min,
+    Max,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<(Function, usize)> {
+        Some(match name {
+            "abs" => (Function::Abs, 1),
+            "sqrt" => (Function::Sqrt, 1),
+            "ln" | "log" => (Function::Ln, 1),
+            "log10" => (Function::Log10, 1),
+            "exp" => (Function::Exp, 1),
+            "min" => (Function::Min, 2),
+            "max" => (Function::Max, 2),
+            _ => return None,
+        })
+    }
+}
+
+/// Parsed expression
+#[derive(Clone, Debug)]
+pub enum Expr {
+    Number(f32),
+    /// Index into the RGBA samples
+    Channel(usize),
+    Negate(Box<Expr>),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+impl Expr {
+    /// Value for a pixel with the RGBA samples `pixel`
+    pub fn eval(&self, pixel: &[f32; 4]) -> f32 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Channel(index) => pixel[*index],
+            Expr::Negate(operand) => -operand.eval(pixel),
+            Expr::Binary(operator, a, b) => {
+                let (a, b) = (a.eval(pixel), b.eval(pixel));
+                match operator {
+                    Operator::Add => a + b,
+                    Operator::Subtract => a - b,
+                    Operator::Multiply => a * b,
+                    Operator::Divide => a / b,
+                    Operator::Power => a.powf(b),
+                }
+            }
+            Expr::Call(function, args) => {
+                let arg = |index: usize| args[index].eval(pixel);
+                match function {
+                    Function::Abs => arg(0).abs(),
+                    Function::Sqrt => arg(0).sqrt(),
+                    Function::Ln => arg(0).ln(),
+                    Function::Log10 => arg(0).log10(),
+                    Function::Exp => arg(0).exp(),
+                    Function::Min => arg(0).min(arg(1)),
+                    Function::Max => arg(0).max(arg(1)),
+                }
+            }
+        }
+    }
+}
+
+/// Parse an expression of the channels `r`, `g`, `b` and `a`, numbers, `+ - * / ^`, parentheses
+/// and the functions abs, sqrt, ln, log10, exp, min and max
+pub fn parse(text: &str) -> anyhow::Result<Expr> {
+    let mut parser = Parser { tokens: tokenize(text)?, position: 0 };
+    let expr = parser.sum()?;
+    match parser.tokens.get(parser.position) {
+        None => Ok(expr),
+        Some(token) => bail!("Unexpected {}", token.describe()),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f32),
+    Name(String),
+    Symbol(char),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Number(value) => format!("number {}", value),
+            Token::Name(name) => format!("\"{}\"", name),
+            Token::Symbol(symbol) => format!("\"{}\"", symbol),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            let mut exponent = false;
+            while let Some(&(i, c)) = chars.peek() {
+                // Digits, a decimal point and an exponent like 1e-3. The e only belongs to the number
+                // when digits follow, so a stray one is reported as a name.
+                let rest = &text[i + 1..];
+                let exponent_digits = rest.strip_prefix(['+', '-']).unwrap_or(rest).starts_with(|c: char| c.is_ascii_digit());
+                if (c == 'e' || c == 'E') && !exponent && exponent_digits {
+                    exponent = true;
+                    chars.next();
+                    end = i + 1;
+                    if let Some(&(i, sign @ ('+' | '-'))) = chars.peek() {
+                        chars.next();
+                        end = i + sign.len_utf8();
+                    }
+                    continue;
+                }
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let number = &text[start..end];
+            tokens.push(Token::Number(number.parse().map_err(|_| anyhow!("Invalid number \"{}\"", number))?));
+        } else if c.is_ascii_alphabetic() {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Name(text[start..end].to_lowercase()));
+        } else if "+-*/^(),".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else {
+            bail!("Unexpected character \"{}\"", c);
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens, one method per precedence level
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.tokens.get(self.position) == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> anyhow::Result<()> {
+        if self.eat(symbol) {
+            return Ok(());
+        }
+        match self.tokens.get(self.position) {
+            Some(token) => bail!("Expected \"{}\", found {}", symbol, token.describe()),
+            None => bail!("Expected \"{}\" at the end", symbol),
+        }
+    }
+
+    /// Terms joined by + and -
+    fn sum(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.product()?;
+        loop {
+            let operator = if self.eat('+') {
+                Operator::Add
+            } else if self.eat('-') {
+                Operator::Subtract
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::Binary(operator, Box::new(expr), Box::new(self.product()?));
+        }
+    }
+
+    /// Factors joined by * and /
+    fn product(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.unary()?;
+        loop {
+            let operator = if self.eat('*') {
+                Operator::Multiply
+            } else if self.eat('/') {
+                Operator::Divide
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::Binary(operator, Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    /// Negation binds weaker than the power, `-r^2` is `-(r^2)`
+    fn unary(&mut self) -> anyhow::Result<Expr> {
+        if self.eat('-') {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        let base = self.primary()?;
+        if self.eat('^') {
+            // Right associative, 2^3^2 is 2^(3^2)
+            return Ok(Expr::Binary(Operator::Power, Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> anyhow::Result<Expr> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::Symbol('(')) => {
+                let expr = self.sum()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some(Token::Name(name)) => {
+                if let Some((function, arity)) = Function::from_name(&name) {
+                    self.expect('(')?;
+                    let mut args = vec![self.sum()?];
+                    while self.eat(',') {
+                        args.push(self.sum()?);
+                    }
+                    self.expect(')')?;
+                    if args.len() != arity {
+                        bail!("{} takes {} argument{}, got {}", name, arity, if arity == 1 { "" } else { "s" }, args.len());
+                    }
+                    return Ok(Expr::Call(function, args));
+                }
+                match name.as_str() {
+                    "r" => Ok(Expr::Channel(0)),
+                    "g" => Ok(Expr::Channel(1)),
+                    "b" => Ok(Expr::Channel(2)),
+                    "a" => Ok(Expr::Channel(3)),
+                    "pi" => Ok(Expr::Number(std::f32::consts::PI)),
+                    _ => bail!("Unknown name \"{}\", use r, g, b, a or a function", name),
+                }
+            }
+            Some(token) => bail!("Unexpected {}", token.describe()),
+            None => bail!("Unexpected end of the expression"),
+        }
+    }
+}
+
+/// State of the expression tool
+pub struct ExpressionTool {
+    pub text: String,
+    /// Last expression that parsed, shown while the text has an error
+    pub expr: Option<Arc<Expr>>,
+    pub error: Option<String>,
+    /// Values mapped to black and white, `None` stretches the result's own range
+    pub range: Option<[f32; 2]>,
+    /// Finite range of the last displayed result
+    pub result: Arc<Mutex<Option<(f32, f32)>>>,
+}
+
+impl Default for ExpressionTool {
+    fn default() -> Self {
+        let text = PRESETS[0].0.to_string();
+        let expr = parse(&text).ok().map(Arc::new);
+        Self { text, expr, error: None, range: None, result: Arc::new(Mutex::new(None)) }
+    }
+}
+
+impl ExpressionTool {
+    /// Display step evaluating the last valid expression
+    pub fn view(&self) -> Option<ExpressionView> {
+        Some(ExpressionView { expr: Arc::clone(self.expr.as_ref()?), range: self.range, result: Arc::clone(&self.result) })
+    }
+
+    /// Expression editor, presets and the display range. Returns true if the displayed image changes.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            let edited = ui.add(egui::TextEdit::singleline(&mut self.text).desired_width(280.0).code_editor()).changed();
+            let mut preset = None;
+            ui.menu_button("Presets", |ui| {
+                for (text, description) in PRESETS {
+                    if ui.button(text).on_hover_text(description).clicked() {
+                        preset = Some(text);
+                        ui.close_menu();
+                    }
+                }
+            });
+            if let Some(text) = preset {
+                self.text = text.to_string();
+            }
+            if edited || preset.is_some() {
+                match parse(&self.text) {
+                    Ok(expr) => {
+                        self.expr = Some(Arc::new(expr));
+                        self.error = None;
+                        changed = true;
+                    }
+                    Err(e) => self.error = Some(e.to_string()),
+                }
+            }
+        });
+        match &self.error {
+            Some(error) => ui.colored_label(ui.visuals().error_fg_color, error),
+            None => ui.weak("Channels r, g, b, a; + - * / ^; abs, sqrt, ln, log10, exp, min, max"),
+        };
+
+        ui.separator();
+        let result = self.result.lock().ok().and_then(|result| *result);
+        ui.horizontal(|ui| {
+            let mut automatic = self.range.is_none();
+            if ui.checkbox(&mut automatic, "Automatic range").changed() {
+                self.range = if automatic { None } else { Some(result.map_or([0.0, 1.0], |(min, max)| [min, max])) };
+                changed = true;
+            }
+            if let Some([low, high]) = &mut self.range {
+                ui.label("Black:");
+                changed |= ui.add(egui::DragValue::new(low).speed(0.01)).changed();
+                ui.label("White:");
+                changed |= ui.add(egui::DragValue::new(high).speed(0.01)).changed();
+            }
+        });
+        match result {
+            Some((min, max)) => ui.label(format!("Result from {:.4} to {:.4}", min, max)),
+            None => ui.weak("No finite result"),
+        };
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(text: &str, pixel: [f32; 4]) -> f32 {
+        parse(text).unwrap().eval(&pixel)
+    }
+
+    fn error(text: &str) -> String {
+        parse(text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn presets_parse() {
+        let pixel = [3.0, 4.0, 1.0, 255.0];
+        let expected = [0.5, 1.0 / 7.0, 0.75, 0.2126 * 3.0 + 0.7152 * 4.0 + 0.0722, 26f32.sqrt()];
+        for ((text, _), expected) in PRESETS.iter().zip(expected) {
+            assert!((eval(text, pixel) - expected).abs() < 1e-5, "{}", text);
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        let pixel = [3.0, 0.0, 0.0, 0.0];
+        assert_eq!(eval("-r^2", pixel), -9.0);
+        assert_eq!(eval("(-r)^2", pixel), 9.0);
+        assert_eq!(eval("1 + 2 * 3", pixel), 7.0);
+        assert_eq!(eval("2 * r^2", pixel), 18.0);
+        assert_eq!(eval("-2 * -r", pixel), 6.0);
+    }
+
+    #[test]
+    fn associativity() {
+        let pixel = [0.0; 4];
+        assert_eq!(eval("2^3^2", pixel), 512.0);
+        assert_eq!(eval("8 - 3 - 2", pixel), 3.0);
+        assert_eq!(eval("8 / 4 / 2", pixel), 1.0);
+    }
+
+    #[test]
+    fn numbers() {
+        let pixel = [0.0; 4];
+        assert_eq!(eval("1e3", pixel), 1000.0);
+        assert_eq!(eval("2.5E-1", pixel), 0.25);
+        assert_eq!(eval("1e+2 + .5", pixel), 100.5);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("2e"), "Unexpected \"e\"");
+        assert_eq!(error("2 * e"), "Unknown name \"e\", use r, g, b, a or a function");
+        assert_eq!(error("1.2.3"), "Invalid number \"1.2.3\"");
+        assert_eq!(error("min(r)"), "min takes 2 arguments, got 1");
+        assert_eq!(error("sqrt(r, g)"), "sqrt takes 1 argument, got 2");
+        assert_eq!(error("(r + g"), "Expected \")\" at the end");
+        assert_eq!(error("r g"), "Unexpected \"g\"");
+        assert_eq!(error("r +"), "Unexpected end of the expression");
+        assert_eq!(error("r % 2"), "Unexpected character \"%\"");
+        assert_eq!(error("abs r"), "Expected \"(\", found \"r\"");
+    }
+}
@@ -0,0 +1,262 @@
+//! Spectrum of the image in its own window. Circles drawn on it mask frequencies and the
+//! inverse FFT previews the image filtered through them.
+
+use image::DynamicImage;
+use rustfft::num_complex::Complex;
+use std::f32::consts::{PI, TAU};
+
+use crate::image_processing::{fft_shift, fft_spectrum, inverse_fft};
+
+/// Longest side the spectrum is computed at, larger images are reduced first
+const MAX_SPECTRUM_SIZE: u32 = 1024;
+
+/// What the spectrum image shows of the complex coefficients
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum SpectrumDisplay {
+    Magnitude,
+    Phase,
+}
+
+/// How the masks filter the spectrum
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum MaskMode {
+    /// Remove the frequencies inside the masks
+    Block,
+    /// Keep only the frequencies inside the masks
+    Pass,
+}
+
+/// Circle in frequency space. The spectrum of a real image is point symmetric, so the circle
+/// mirrored through the zero frequency is masked as well.
+#[derive(Clone, Copy, Debug)]
+pub struct FrequencyMask {
+    /// Offset from the zero frequency in spectrum pixels
+    pub center: [f32; 2],
+    pub radius: f32,
+}
+
+impl FrequencyMask {
+    fn contains(&self, offset: [f32; 2]) -> bool {
+        let within = |center: [f32; 2]| (offset[0] - center[0]).hypot(offset[1] - center[1]) <= self.radius;
+        within(self.center) || within([-self.center[0], -self.center[1]])
+    }
+}
+
+struct Spectrum {
+    width: u32,
+    height: u32,
+    hamming: bool,
+    /// Row-major with the zero frequency at the center
+    coefficients: Vec<Complex<f32>>,
+}
+
+/// State of the FFT window
+pub struct FftView {
+    pub display: SpectrumDisplay,
+    pub log_scale: bool,
+    pub mask_mode: MaskMode,
+    pub masks: Vec<FrequencyMask>,
+    spectrum: Option<Spectrum>,
+    spectrum_texture: Option<egui::TextureHandle>,
+    filtered_texture: Option<egui::TextureHandle>,
+    /// Center of the mask being drawn, in spectrum pixels
+    drag_start: Option<egui::Pos2>,
+}
+
+impl Default for FftView {
+    fn default() -> Self {
+        Self {
+            display: SpectrumDisplay::Magnitude,
+            log_scale: true,
+            mask_mode: MaskMode::Block,
+            masks: Vec::new(),
+            spectrum: None,
+            spectrum_texture: None,
+            filtered_texture: None,
+            drag_start: None,
+        }
+    }
+}
+
+impl FftView {
+    /// Drop the spectrum, the image it was computed from changed
+    pub fn invalidate(&mut self) {
+        self.spectrum = None;
+        self.spectrum_texture = None;
+        self.filtered_texture = None;
+        self.drag_start = None;
+    }
+
+    /// Whether `set_image` has to be called before showing the window
+    pub fn needs_spectrum(&self, hamming: bool) -> bool {
+        self.spectrum.as_ref().is_none_or(|spectrum| spectrum.hamming != hamming)
+    }
+
+    /// Compute the spectrum of the (normalized) image. The masks are kept while the size stays the same.
+    pub fn set_image(&mut self, img: &DynamicImage, hamming: bool) {
+        let reduced;
+        let img = if img.width().max(img.height()) > MAX_SPECTRUM_SIZE {
+            reduced = img.thumbnail(MAX_SPECTRUM_SIZE, MAX_SPECTRUM_SIZE);
+            &reduced
+        } else {
+            img
+        };
+        let (width, height) = (img.width(), img.height());
+        if self.spectrum.as_ref().is_some_and(|spectrum| (spectrum.width, spectrum.height) != (width, height)) {
+            self.masks.clear();
+        }
+        self.invalidate();
+        if width == 0 || height == 0 {
+            return;
+        }
+        let coefficients = fft_shift(&fft_spectrum(img, hamming));
+        self.spectrum = Some(Spectrum { width, height, hamming, coefficients });
+    }
+
+    /// Display and mask settings, in a horizontal row
+    pub fn controls_ui(&mut self, ui: &mut egui::Ui) {
+        let mut display_changed = false;
+        display_changed |= ui.radio_value(&mut self.display, SpectrumDisplay::Magnitude, "Magnitude").changed();
+        display_changed |= ui.radio_value(&mut self.display, SpectrumDisplay::Phase, "Phase").changed();
+        display_changed |= ui.add_enabled(self.display == SpectrumDisplay::Magnitude, egui::Checkbox::new(&mut self.log_scale, "Log scale")).changed();
+        if display_changed {
+            self.spectrum_texture = None;
+        }
+
+        ui.separator();
+        ui.label("Masks:");
+        let mut mask_changed = false;
+        mask_changed |= ui.radio_value(&mut self.mask_mode, MaskMode::Block, "Block")
+            .on_hover_text("Remove the frequencies inside the circles").changed();
+        mask_changed |= ui.radio_value(&mut self.mask_mode, MaskMode::Pass, "Pass")
+            .on_hover_text("Keep only the frequencies inside the circles").changed();
+        if ui.add_enabled(!self.masks.is_empty(), egui::Button::new("Clear")).clicked() {
+            self.masks.clear();
+            mask_changed = true;
+        }
+        if mask_changed {
+            self.filtered_texture = None;
+        }
+    }
+
+    /// The spectrum with the masks next to the filtered image, both fit into the available space
+    pub fn images_ui(&mut self, ui: &mut egui::Ui) {
+        let Some(spectrum) = &self.spectrum else {
+            ui.centered_and_justified(|ui| ui.label("No image"));
+            return;
+        };
+        let (width, height) = (spectrum.width, spectrum.height);
+        let spectrum_texture = self.spectrum_texture
+            .get_or_insert_with(|| ui.ctx().load_texture("fft-spectrum", spectrum_image(spectrum, self.display, self.log_scale), egui::TextureOptions::NEAREST))
+            .clone();
+        let filtered_texture = self.filtered_texture
+            .get_or_insert_with(|| ui.ctx().load_texture("fft-filtered", filtered_image(spectrum, &self.masks, self.mask_mode), egui::TextureOptions::LINEAR))
+            .clone();
+
+        let spacing = ui.spacing().item_spacing;
+        let available = ui.available_size() - egui::vec2(spacing.x, 2.0 * ui.text_style_height(&egui::TextStyle::Body) + 2.0 * spacing.y);
+        let scale = (available.x / 2.0 / width as f32).min(available.y / height as f32).max(0.01);
+        let size = egui::vec2(width as f32, height as f32) * scale;
+        let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+
+        ui.horizontal_top(|ui| {
+            ui.vertical(|ui| {
+                ui.label(match self.display {
+                    SpectrumDisplay::Magnitude => "Magnitude",
+                    SpectrumDisplay::Phase => "Phase",
+                });
+                let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+                let painter = ui.painter_at(rect);
+                painter.image(spectrum_texture.id(), rect, uv, egui::Color32::WHITE);
+
+                // Positions relative to the zero frequency, in spectrum pixels
+                let dc = rect.min + egui::vec2((width / 2) as f32, (height / 2) as f32) * scale;
+                let to_offset = |pos: egui::Pos2| (pos - dc) / scale;
+                let to_screen = |offset: egui::Vec2| dc + offset * scale;
+
+                let pointer = response.interact_pointer_pos().or(response.hover_pos());
+                if response.drag_started() {
+                    self.drag_start = pointer.map(|pos| to_offset(pos).to_pos2());
+                }
+                let drawn = self.drag_start.zip(pointer).map(|(center, pos)| FrequencyMask {
+                    center: [center.x, center.y],
+                    radius: (to_offset(pos) - center.to_vec2()).length(),
+                });
+                if response.drag_stopped() {
+                    if let Some(mask) = drawn.filter(|mask| mask.radius >= 1.0) {
+                        self.masks.push(mask);
+                        self.filtered_texture = None;
+                    }
+                    self.drag_start = None;
+                }
+                if response.secondary_clicked() {
+                    if let Some(offset) = pointer.map(to_offset) {
+                        if let Some(index) = self.masks.iter().rposition(|mask| mask.contains([offset.x, offset.y])) {
+                            self.masks.remove(index);
+                            self.filtered_texture = None;
+                        }
+                    }
+                }
+
+                let stroke = egui::Stroke::new(1.5, egui::Color32::from_rgb(255, 200, 0));
+                for mask in self.masks.iter().chain(drawn.as_ref().filter(|_| self.drag_start.is_some())) {
+                    let center = egui::vec2(mask.center[0], mask.center[1]);
+                    painter.circle_stroke(to_screen(center), mask.radius * scale, stroke);
+                    painter.circle_stroke(to_screen(-center), mask.radius * scale, stroke);
+                }
+
+                let hover = match response.hover_pos().map(to_offset) {
+                    Some(offset) => format!(
+                        "Frequency {:.3}, {:.3} cycles/pixel",
+                        offset.x / width as f32,
+                        offset.y / height as f32
+                    ),
+                    None => format!("{}×{}", width, height),
+                };
+                ui.weak(hover);
+                response.on_hover_text("Drag to mask a circle of frequencies, right-click a circle to remove it");
+            });
+            ui.vertical(|ui| {
+                ui.label("Inverse FFT");
+                ui.add(egui::Image::new((filtered_texture.id(), size)));
+                ui.weak(match (self.masks.len(), self.mask_mode) {
+                    (0, _) => "No masks, unfiltered".to_string(),
+                    (n, MaskMode::Block) => format!("{} blocked", n),
+                    (n, MaskMode::Pass) => format!("{} passed", n),
+                });
+            });
+        });
+    }
+}
+
+/// Grayscale image of the magnitude (scaled to its maximum) or the phase of the spectrum
+fn spectrum_image(spectrum: &Spectrum, display: SpectrumDisplay, log_scale: bool) -> egui::ColorImage {
+    let values: Vec<f32> = spectrum.coefficients.iter().map(|c| match display {
+        SpectrumDisplay::Magnitude if log_scale => (c.norm() + 1.0).log10(),
+        SpectrumDisplay::Magnitude => c.norm(),
+        SpectrumDisplay::Phase => (c.arg() + PI) / TAU,
+    }).collect();
+    let max = match display {
+        SpectrumDisplay::Magnitude => values.iter().fold(0.0f32, |max, &v| max.max(v)),
+        SpectrumDisplay::Phase => 1.0,
+    };
+    let gray: Vec<u8> = values.iter().map(|&v| if max > 0.0 { (v / max * 255.0) as u8 } else { 0 }).collect();
+    egui::ColorImage::from_gray([spectrum.width as usize, spectrum.height as usize], &gray)
+}
+
+/// The image back from the spectrum with the masked frequencies removed
+fn filtered_image(spectrum: &Spectrum, masks: &[FrequencyMask], mode: MaskMode) -> egui::ColorImage {
+    let width = spectrum.width as usize;
+    let dc = [(spectrum.width / 2) as f32, (spectrum.height / 2) as f32];
+    let masked: Vec<Complex<f32>> = spectrum.coefficients.iter().enumerate().map(|(i, &c)| {
+        let offset = [(i % width) as f32 - dc[0], (i / width) as f32 - dc[1]];
+        let inside = masks.iter().any(|mask| mask.contains(offset));
+        let keep = match mode {
+            MaskMode::Block => !inside,
+            MaskMode::Pass => inside || masks.is_empty(),
+        };
+        if keep { c } else { Complex::new(0.0, 0.0) }
+    }).collect();
+    let gray = inverse_fft(&masked, spectrum.width, spectrum.height, spectrum.hamming).into_luma8();
+    egui::ColorImage::from_gray([width, spectrum.height as usize], gray.as_raw())
+}
@@ -0,0 +1,319 @@
+//! `--register-file-associations` makes the viewer the default application for the image types it
+//! reads, `--unregister` takes that back. On Linux this installs a desktop entry with its icon and
+//! edits the user's `mimeapps.list`; on Windows it registers a ProgID, the extensions' "Open with"
+//! entries and the application's capabilities for the current user, and Windows asks once which
+//! application opens a type. Both work without administrator rights.
+
+use std::ffi::OsString;
+
+/// MIME types with their extensions, as the desktop and the registry know them
+const FILE_TYPES: [(&str, &[&str]); 20] = [
+    ("image/png", &["png"]),
+    ("image/jpeg", &["jpg", "jpeg"]),
+    ("image/gif", &["gif"]),
+    ("image/bmp", &["bmp"]),
+    ("image/tiff", &["tif", "tiff"]),
+    ("image/webp", &["webp"]),
+    ("image/avif", &["avif"]),
+    ("image/vnd.radiance", &["hdr"]),
+    ("image/x-exr", &["exr"]),
+    ("image/x-qoi", &["qoi"]),
+    ("image/x-dds", &["dds"]),
+    ("image/x-tga", &["tga"]),
+    ("image/x-portable-anymap", &["pnm"]),
+    ("image/vnd.microsoft.icon", &["ico"]),
+    ("image/x-adobe-dng", &["dng"]),
+    ("image/x-canon-cr2", &["cr2"]),
+    ("image/x-nikon-nef", &["nef"]),
+    ("image/x-sony-arw", &["arw"]),
+    ("image/x-fuji-raf", &["raf"]),
+    ("image/x-olympus-orf", &["orf"]),
+];
+
+/// `Some(true)` for `--register-file-associations`, `Some(false)` for `--unregister`
+pub fn requested(args: &[OsString]) -> Option<bool> {
+    args.iter().skip(1).find_map(|arg| match arg.to_str() {
+        Some("--register-file-associations") => Some(true),
+        Some("--unregister") => Some(false),
+        _ => None,
+    })
+}
+
+/// Register or unregister, printing what was done
+pub fn run(register: bool) -> anyhow::Result<()> {
+    let exe = std::env::current_exe()?;
+    if register {
+        platform::register(&exe)?;
+        println!("Registered {:?} for {} image types", exe, FILE_TYPES.len());
+    } else {
+        platform::unregister()?;
+        println!("Removed the file associations of the viewer");
+    }
+    Ok(())
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod platform {
+    use super::FILE_TYPES;
+    use anyhow::Context;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    /// Name of the desktop entry, the one `install_desktop.sh` installs too
+    const DESKTOP_ID: &str = "image_viewer.desktop";
+    /// Sections of `mimeapps.list` naming the viewer
+    const DEFAULTS: &str = "[Default Applications]";
+    const ADDED: &str = "[Added Associations]";
+
+    fn data_dir() -> anyhow::Result<PathBuf> {
+        dirs::data_dir().context("No data folder for the desktop entry")
+    }
+
+    fn mimeapps_path() -> anyhow::Result<PathBuf> {
+        Ok(dirs::config_dir().context("No config folder for mimeapps.list")?.join("mimeapps.list"))
+    }
+
+    pub fn register(exe: &Path) -> anyhow::Result<()> {
+        let applications = data_dir()?.join("applications");
+        fs::create_dir_all(&applications)?;
+        let mime_types: String = FILE_TYPES.iter().map(|(mime, _)| format!("{};", mime)).collect();
+        let entry = format!(
+            "[Desktop Entry]\nType=Application\nName=Image Viewer\nGenericName=Image Viewer\n\
+             Comment=A simple image viewer built with Rust\nExec={} %F\nIcon=image_viewer\nTerminal=false\n\
+             Categories=Graphics;Photography;Viewer;\nMimeType={}\nStartupNotify=true\n\
+             Keywords=image;viewer;photo;picture;graphics;\n",
+            exec_argument(&exe.to_string_lossy()),
+            mime_types
+        );
+        fs::write(applications.join(DESKTOP_ID), entry)?;
+
+        let icons = data_dir()?.join("icons/hicolor/256x256/apps");
+        fs::create_dir_all(&icons)?;
+        fs::write(icons.join("image_viewer.png"), crate::ICON)?;
+
+        update_mimeapps(true)?;
+        refresh(&applications);
+        Ok(())
+    }
+
+    pub fn unregister() -> anyhow::Result<()> {
+        let applications = data_dir()?.join("applications");
+        for file in [applications.join(DESKTOP_ID), data_dir()?.join("icons/hicolor/256x256/apps/image_viewer.png")] {
+            match fs::remove_file(&file) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e).with_context(|| format!("Failed to remove {:?}", file)),
+                _ => {}
+            }
+        }
+        update_mimeapps(false)?;
+        refresh(&applications);
+        Ok(())
+    }
+
+    fn update_mimeapps(register: bool) -> anyhow::Result<()> {
+        let path = mimeapps_path()?;
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+        };
+        let types: Vec<&str> = FILE_TYPES.iter().map(|(mime, _)| *mime).collect();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, edit_mimeapps(&text, &types, register)).with_context(|| format!("Failed to write {:?}", path))
+    }
+
+    /// `mimeapps.list` with the viewer as the default and first added application of `types`,
+    /// or without it anywhere. Other sections, keys and applications are kept as they are.
+    fn edit_mimeapps(text: &str, types: &[&str], register: bool) -> String {
+        // Lines before the first header are kept under an empty header
+        let mut sections: Vec<(String, Vec<String>)> = vec![(String::new(), Vec::new())];
+        for line in text.lines() {
+            if line.trim_start().starts_with('[') {
+                sections.push((line.trim().to_string(), Vec::new()));
+            } else if let Some((_, lines)) = sections.last_mut() {
+                lines.push(line.to_string());
+            }
+        }
+        let existing = sections.len();
+        for header in [DEFAULTS, ADDED] {
+            if register && !sections.iter().any(|(name, _)| name == header) {
+                sections.push((header.to_string(), Vec::new()));
+            }
+        }
+
+        for (header, lines) in sections.iter_mut().filter(|(header, _)| header == DEFAULTS || header == ADDED) {
+            let mut seen = Vec::new();
+            lines.retain_mut(|line| {
+                let Some((key, value)) = line.split_once('=') else {
+                    return true;
+                };
+                let key = key.trim();
+                if !types.contains(&key) {
+                    return true;
+                }
+                seen.push(key.to_string());
+                let mut apps: Vec<&str> = value.split(';').map(str::trim).filter(|app| !app.is_empty() && *app != DESKTOP_ID).collect();
+                if register {
+                    apps.insert(0, DESKTOP_ID);
+                    if header == DEFAULTS {
+                        apps.truncate(1);
+                    }
+                }
+                let keep = !apps.is_empty();
+                *line = format!("{}={};", key, apps.join(";"));
+                keep
+            });
+            if register {
+                // New keys go after the last entry, before trailing blank lines
+                let end = lines.iter().rposition(|line| !line.trim().is_empty()).map_or(0, |index| index + 1);
+                let missing = types.iter().filter(|mime| !seen.iter().any(|key| key == *mime)).map(|mime| format!("{}={};", mime, DESKTOP_ID));
+                lines.splice(end..end, missing);
+            }
+        }
+
+        let mut output = String::new();
+        for (index, (header, lines)) in sections.into_iter().enumerate() {
+            if !header.is_empty() {
+                // Added sections are set apart by a blank line
+                if index >= existing && !output.is_empty() && !output.ends_with("\n\n") {
+                    output.push('\n');
+                }
+                output.push_str(&header);
+                output.push('\n');
+            }
+            for line in lines {
+                output.push_str(&line);
+                output.push('\n');
+            }
+        }
+        output
+    }
+
+    /// Quote a path for the Exec key of a desktop entry, where `%` starts a field code
+    fn exec_argument(path: &str) -> String {
+        let path = &path.replace('%', "%%");
+        if !path.contains(|c: char| c.is_whitespace() || "\"'\\`$<>|&;()*?#~".contains(c)) {
+            return path.to_string();
+        }
+        let escaped: String = path.chars().flat_map(|c| match c {
+            '"' | '`' | '$' | '\\' => vec!['\\', c],
+            _ => vec![c],
+        }).collect();
+        // The desktop entry format escapes the backslashes once more
+        format!("\"{}\"", escaped.replace('\\', "\\\\"))
+    }
+
+    /// Let the menus and file managers see the change, the tools are optional
+    fn refresh(applications: &Path) {
+        let _ = Command::new("update-desktop-database").arg(applications).output();
+        let _ = Command::new("gtk-update-icon-cache").arg("-f").arg("-t").arg(applications.with_file_name("icons/hicolor")).output();
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::FILE_TYPES;
+    use std::path::Path;
+    use std::ptr;
+    use windows_sys::Win32::Foundation::ERROR_FILE_NOT_FOUND;
+    use windows_sys::Win32::System::Registry::{RegDeleteKeyValueW, RegDeleteTreeW, RegSetKeyValueW, HKEY_CURRENT_USER, REG_SZ};
+    use windows_sys::Win32::UI::Shell::{SHChangeNotify, SHCNE_ASSOCCHANGED, SHCNF_IDLIST};
+
+    const PROG_ID: &str = "ImageViewer.Image";
+    const APPLICATION: &str = "ImageViewer";
+    const CAPABILITIES: &str = "Software\\ImageViewer\\Capabilities";
+
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    /// Set a string value of a key under HKEY_CURRENT_USER, `None` is the default value
+    fn set_value(key: &str, name: Option<&str>, value: &str) -> anyhow::Result<()> {
+        let (key_w, value_w) = (wide(key), wide(value));
+        let name_w = name.map(wide);
+        // SAFETY: null-terminated UTF-16 strings that outlive the call, the size includes the terminator
+        let status = unsafe {
+            RegSetKeyValueW(
+                HKEY_CURRENT_USER,
+                key_w.as_ptr(),
+                name_w.as_ref().map_or(ptr::null(), |name| name.as_ptr()),
+                REG_SZ,
+                value_w.as_ptr().cast(),
+                (value_w.len() * 2) as u32,
+            )
+        };
+        if status != 0 {
+            anyhow::bail!("Failed to write {}: {}", key, std::io::Error::from_raw_os_error(status as i32));
+        }
+        Ok(())
+    }
+
+    /// Remove a key with its subkeys or a value, missing ones are fine
+    fn delete(key: &str, value: Option<&str>) -> anyhow::Result<()> {
+        let (key_w, value_w) = (wide(key), value.map(wide));
+        // SAFETY: null-terminated UTF-16 strings that outlive the call
+        let status = unsafe {
+            match &value_w {
+                Some(value) => RegDeleteKeyValueW(HKEY_CURRENT_USER, key_w.as_ptr(), value.as_ptr()),
+                None => RegDeleteTreeW(HKEY_CURRENT_USER, key_w.as_ptr()),
+            }
+        };
+        if status != 0 && status != ERROR_FILE_NOT_FOUND {
+            anyhow::bail!("Failed to remove {}: {}", key, std::io::Error::from_raw_os_error(status as i32));
+        }
+        Ok(())
+    }
+
+    fn extensions() -> impl Iterator<Item = &'static str> {
+        FILE_TYPES.iter().flat_map(|(_, extensions)| extensions.iter().copied())
+    }
+
+    fn notify() {
+        // SAFETY: no items are passed with this event
+        unsafe { SHChangeNotify(SHCNE_ASSOCCHANGED, SHCNF_IDLIST, ptr::null(), ptr::null()) };
+    }
+
+    pub fn register(exe: &Path) -> anyhow::Result<()> {
+        let exe = exe.to_string_lossy();
+        let prog_id = format!("Software\\Classes\\{}", PROG_ID);
+        set_value(&prog_id, None, "Image")?;
+        set_value(&format!("{}\\DefaultIcon", prog_id), None, &format!("\"{}\",0", exe))?;
+        set_value(&format!("{}\\shell\\open\\command", prog_id), None, &format!("\"{}\" \"%1\"", exe))?;
+        set_value(CAPABILITIES, Some("ApplicationName"), "Image Viewer")?;
+        set_value(CAPABILITIES, Some("ApplicationDescription"), "A simple image viewer built with Rust")?;
+        for extension in extensions() {
+            set_value(&format!("Software\\Classes\\.{}\\OpenWithProgids", extension), Some(PROG_ID), "")?;
+            set_value(&format!("{}\\FileAssociations", CAPABILITIES), Some(&format!(".{}", extension)), PROG_ID)?;
+        }
+        set_value("Software\\RegisteredApplications", Some(APPLICATION), CAPABILITIES)?;
+        notify();
+        println!("Choose Image Viewer for the image types in Settings > Apps > Default apps");
+        Ok(())
+    }
+
+    pub fn unregister() -> anyhow::Result<()> {
+        for extension in extensions() {
+            delete(&format!("Software\\Classes\\.{}\\OpenWithProgids", extension), Some(PROG_ID))?;
+        }
+        delete("Software\\RegisteredApplications", Some(APPLICATION))?;
+        delete("Software\\ImageViewer", None)?;
+        delete(&format!("Software\\Classes\\{}", PROG_ID), None)?;
+        notify();
+        Ok(())
+    }
+}
+
+#[cfg(not(any(all(unix, not(target_os = "macos")), windows)))]
+mod platform {
+    use std::path::Path;
+
+    pub fn register(_exe: &Path) -> anyhow::Result<()> {
+        anyhow::bail!("File associations come from the app bundle on this platform, use Open With > Change All in Finder")
+    }
+
+    pub fn unregister() -> anyhow::Result<()> {
+        anyhow::bail!("File associations come from the app bundle on this platform, use Open With > Change All in Finder")
+    }
+}
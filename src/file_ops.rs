@@ -0,0 +1,141 @@
+use anyhow::{bail, Context};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Send a file to the trash or recycle bin of the OS
+pub fn move_to_trash(path: &Path) -> anyhow::Result<()> {
+    trash::delete(path).with_context(|| format!("Failed to move {:?} to the trash", path))
+}
+
+/// Move a file into `folder`, keeping its name. Existing files are not overwritten.
+pub fn move_to_folder(path: &Path, folder: &Path) -> anyhow::Result<PathBuf> {
+    let name = path.file_name().with_context(|| format!("{:?} has no file name", path))?;
+    let target = folder.join(name);
+    if target.exists() {
+        bail!("{:?} already exists", target);
+    }
+    if fs::rename(path, &target).is_err() {
+        // Renaming fails across file systems, copy and remove instead
+        fs::copy(path, &target).with_context(|| format!("Failed to copy {:?} to {:?}", path, target))?;
+        if let Err(e) = fs::remove_file(path) {
+            let _ = fs::remove_file(&target);
+            return Err(e).with_context(|| format!("Failed to remove {:?} after copying it", path));
+        }
+    }
+    Ok(target)
+}
+
+/// Path of `path` renamed to `name` in the same folder, or why the name cannot be used
+pub fn renamed_path(path: &Path, name: &str) -> Result<PathBuf, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("The name is empty".to_string());
+    }
+    if name.contains(['/', '\\']) || name == "." || name == ".." {
+        return Err("The name cannot contain a folder".to_string());
+    }
+    let target = path.with_file_name(name);
+    // A name that only differs in case is the same file on case-insensitive file systems
+    let same_file = fs::canonicalize(&target).ok().is_some_and(|target| fs::canonicalize(path).ok() == Some(target));
+    if target.exists() && !same_file {
+        return Err(format!("{} already exists", name));
+    }
+    Ok(target)
+}
+
+/// Rename a file within its folder, refusing names of existing files
+pub fn rename(path: &Path, name: &str) -> anyhow::Result<PathBuf> {
+    let target = renamed_path(path, name).map_err(anyhow::Error::msg)?;
+    fs::rename(path, &target).with_context(|| format!("Failed to rename {:?} to {:?}", path, target))?;
+    Ok(target)
+}
+
+/// Show the file selected in the file manager of the OS. On Linux the file manager is asked over
+/// D-Bus, which Nautilus, Dolphin, Nemo, Caja and Thunar answer; otherwise its folder is opened.
+pub fn reveal_in_file_manager(path: &Path) -> anyhow::Result<()> {
+    #[cfg(windows)]
+    {
+        // Explorer exits with an error code even when it shows the file
+        let mut argument = std::ffi::OsString::from("/select,");
+        argument.push(path);
+        Command::new("explorer").arg(argument).spawn().context("Failed to start Explorer")?;
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let status = Command::new("open").arg("-R").arg(path).status().context("Failed to run open")?;
+        if !status.success() {
+            bail!("Finder could not show {:?}", path);
+        }
+    }
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        let uri = crate::thumbnail_store::file_uri(&std::path::absolute(path)?);
+        let shown = Command::new("dbus-send")
+            .args(["--session", "--print-reply", "--dest=org.freedesktop.FileManager1", "--type=method_call"])
+            .args(["/org/freedesktop/FileManager1", "org.freedesktop.FileManager1.ShowItems"])
+            .arg(format!("array:string:{}", uri))
+            .arg("string:")
+            .output()
+            .is_ok_and(|output| output.status.success());
+        if !shown {
+            let folder = path.parent().with_context(|| format!("{:?} has no folder", path))?;
+            Command::new("xdg-open").arg(folder).spawn().context("Failed to run xdg-open")?;
+        }
+    }
+    Ok(())
+}
+
+/// Open a file with the external editor command from the settings, e.g. `gimp` or
+/// `"C:\Program Files\GIMP 2\bin\gimp-2.10.exe" --new-instance`. `{path}` in the command is
+/// replaced by the file, without it the file is the last argument.
+pub fn open_in_editor(command: &str, path: &Path) -> anyhow::Result<()> {
+    let mut words = split_command(command)?.into_iter();
+    let Some(program) = words.next() else {
+        bail!("No external editor is set, choose one in Settings");
+    };
+    let mut command = Command::new(&program);
+    let mut placed = false;
+    for word in words {
+        if word.contains("{path}") {
+            placed = true;
+            let (before, after) = word.split_once("{path}").unwrap_or_default();
+            let mut argument = std::ffi::OsString::from(before);
+            argument.push(path);
+            argument.push(after);
+            command.arg(argument);
+        } else {
+            command.arg(word);
+        }
+    }
+    if !placed {
+        command.arg(path);
+    }
+    command.spawn().with_context(|| format!("Failed to start {}", program))?;
+    Ok(())
+}
+
+/// Words of a command line, with double or single quotes around words containing spaces.
+/// Backslashes are kept as they are, so Windows paths need no escaping.
+fn split_command(text: &str) -> anyhow::Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in text.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => word.get_or_insert_with(String::new).push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            None if c.is_whitespace() => words.extend(word.take()),
+            None => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        bail!("Unclosed quote in the editor command {:?}", text);
+    }
+    words.extend(word);
+    Ok(words)
+}
@@ -0,0 +1,103 @@
+use anyhow::{anyhow, bail};
+use image::codecs::gif::GifDecoder;
+use image::{AnimationDecoder, DynamicImage, ImageBuffer};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::ColorType;
+
+/// Frames of an animated GIF or pages of a multi-page TIFF, one of them shown at a time
+#[derive(Clone)]
+pub struct FrameStack {
+    frames: Vec<DynamicImage>,
+    index: usize,
+}
+
+impl FrameStack {
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &DynamicImage> {
+        self.frames.iter()
+    }
+
+    pub fn current(&self) -> &DynamicImage {
+        &self.frames[self.index]
+    }
+
+    /// Select a frame, clamped to the last one. Returns whether the frame changed.
+    pub fn set_index(&mut self, index: usize) -> bool {
+        let index = index.min(self.frames.len() - 1);
+        let changed = index != self.index;
+        self.index = index;
+        changed
+    }
+
+    /// Frame `step` frames away from the current one, wrapping around at both ends
+    pub fn stepped(&self, step: i32) -> usize {
+        (self.index as i64 + step as i64).rem_euclid(self.frames.len() as i64) as usize
+    }
+
+    /// Apply an edit of the shown frame (rotation, crop) to all frames
+    pub fn map(&mut self, f: impl Fn(&DynamicImage) -> DynamicImage) {
+        for frame in &mut self.frames {
+            *frame = f(frame);
+        }
+    }
+}
+
+/// Whether the file can hold more than one frame
+fn is_stack_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| ext == "gif" || ext == "tif" || ext == "tiff")
+}
+
+/// All frames of an animated GIF or multi-page TIFF. `Ok(None)` for single-frame files.
+pub fn load_frames(path: &Path) -> anyhow::Result<Option<FrameStack>> {
+    if !is_stack_file(path) {
+        return Ok(None);
+    }
+    let frames = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gif")) {
+        let decoder = GifDecoder::new(BufReader::new(File::open(path)?))?;
+        // Composited frames, with the disposal of the previous frame applied
+        decoder.into_frames().collect_frames()?
+            .into_iter()
+            .map(|frame| DynamicImage::ImageRgba8(frame.into_buffer()))
+            .collect()
+    } else {
+        let mut decoder = Decoder::new(BufReader::new(File::open(path)?))?;
+        if !decoder.more_images() {
+            return Ok(None);
+        }
+        let mut pages = vec![tiff_page(&mut decoder)?];
+        while decoder.more_images() {
+            decoder.next_image()?;
+            pages.push(tiff_page(&mut decoder)?);
+        }
+        pages
+    };
+    Ok((frames.len() > 1).then_some(FrameStack { frames, index: 0 }))
+}
+
+/// Decode the current page of a TIFF, integer samples only
+fn tiff_page(decoder: &mut Decoder<BufReader<File>>) -> anyhow::Result<DynamicImage> {
+    let (width, height) = decoder.dimensions()?;
+    let colortype = decoder.colortype()?;
+    let buffer_error = || anyhow!("TIFF page data does not match {}x{} {:?}", width, height, colortype);
+    Ok(match (colortype, decoder.read_image()?) {
+        (ColorType::Gray(8), DecodingResult::U8(data)) => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, data).ok_or_else(buffer_error)?),
+        (ColorType::Gray(16), DecodingResult::U16(data)) => DynamicImage::ImageLuma16(ImageBuffer::from_raw(width, height, data).ok_or_else(buffer_error)?),
+        (ColorType::RGB(8), DecodingResult::U8(data)) => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, data).ok_or_else(buffer_error)?),
+        (ColorType::RGB(16), DecodingResult::U16(data)) => DynamicImage::ImageRgb16(ImageBuffer::from_raw(width, height, data).ok_or_else(buffer_error)?),
+        (ColorType::RGBA(8), DecodingResult::U8(data)) => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, data).ok_or_else(buffer_error)?),
+        (ColorType::RGBA(16), DecodingResult::U16(data)) => DynamicImage::ImageRgba16(ImageBuffer::from_raw(width, height, data).ok_or_else(buffer_error)?),
+        (colortype, _) => bail!("TIFF pages of type {:?} cannot be browsed as a stack", colortype),
+    })
+}
@@ -0,0 +1,135 @@
+//! Contact sheet of the folder: a scrollable grid of thumbnails shown in place of the image. The
+//! selection is kept by path, so it survives files leaving the folder list, and the selected
+//! files can be opened, moved to the trash or moved to another folder together.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Selection and keyboard focus of the gallery
+#[derive(Default)]
+pub struct Gallery {
+    selected: HashSet<PathBuf>,
+    /// Cell the keyboard moves from
+    focus: Option<PathBuf>,
+    /// Cell Shift extends the selection from
+    anchor: Option<PathBuf>,
+    /// The focus moved by keyboard and has to be scrolled into view
+    pub scroll_to_focus: bool,
+    /// Cells per row in the last frame, for the up and down keys
+    pub columns: usize,
+    /// Scroll position and height of the grid in the last frame
+    pub scroll_offset: f32,
+    pub view_height: f32,
+}
+
+impl Gallery {
+    /// Gallery with the shown image focused and selected
+    pub fn new(current: Option<&Path>) -> Self {
+        let mut gallery = Self { columns: 1, scroll_to_focus: true, ..Self::default() };
+        if let Some(current) = current {
+            gallery.select_only(current);
+        }
+        gallery
+    }
+
+    pub fn is_selected(&self, path: &Path) -> bool {
+        self.selected.contains(path)
+    }
+
+    pub fn is_focused(&self, path: &Path) -> bool {
+        self.focus.as_deref() == Some(path)
+    }
+
+    pub fn focus(&self) -> Option<&Path> {
+        self.focus.as_deref()
+    }
+
+    /// Selected files in the order of `items`
+    pub fn selection(&self, items: &[PathBuf]) -> Vec<PathBuf> {
+        items.iter().filter(|path| self.selected.contains(*path)).cloned().collect()
+    }
+
+    fn select_only(&mut self, path: &Path) {
+        self.selected = HashSet::from([path.to_path_buf()]);
+        self.focus = Some(path.to_path_buf());
+        self.anchor = Some(path.to_path_buf());
+    }
+
+    /// Select the cells from the anchor to `index`
+    fn select_range(&mut self, items: &[PathBuf], index: usize) {
+        let anchor = self.anchor.as_ref().and_then(|anchor| items.iter().position(|path| path == anchor)).unwrap_or(index);
+        let (start, end) = (anchor.min(index), anchor.max(index));
+        self.selected = items[start..=end].iter().cloned().collect();
+        self.focus = Some(items[index].clone());
+    }
+
+    /// Click on cell `index`: Ctrl toggles it, Shift selects the range from the last clicked
+    /// cell, a plain click selects only it
+    pub fn click(&mut self, items: &[PathBuf], index: usize, modifiers: egui::Modifiers) {
+        let path = &items[index];
+        if modifiers.shift {
+            self.select_range(items, index);
+        } else if modifiers.command {
+            if !self.selected.remove(path) {
+                self.selected.insert(path.clone());
+            }
+            self.focus = Some(path.clone());
+            self.anchor = Some(path.clone());
+        } else {
+            self.select_only(path);
+        }
+    }
+
+    /// Move the focus by `step` cells, clamped to the grid. With `extend` the selection grows
+    /// from the anchor, otherwise only the new cell is selected.
+    pub fn move_focus(&mut self, items: &[PathBuf], step: isize, extend: bool) {
+        if items.is_empty() {
+            return;
+        }
+        let current = self.focus.as_ref().and_then(|focus| items.iter().position(|path| path == focus));
+        let index = match current {
+            Some(current) => current.saturating_add_signed(step).min(items.len() - 1),
+            None => 0,
+        };
+        if extend {
+            self.select_range(items, index);
+        } else {
+            self.select_only(&items[index]);
+        }
+        self.scroll_to_focus = true;
+    }
+
+    /// Scroll offset that brings the focused row into view after a keyboard move, rows are
+    /// `row_pitch` apart and `row_height` high
+    pub fn focus_scroll(&mut self, items: &[PathBuf], row_pitch: f32, row_height: f32) -> Option<f32> {
+        if !std::mem::take(&mut self.scroll_to_focus) {
+            return None;
+        }
+        let index = self.focus.as_ref().and_then(|focus| items.iter().position(|path| path == focus))?;
+        let top = (index / self.columns.max(1)) as f32 * row_pitch;
+        if top < self.scroll_offset || self.view_height <= 0.0 {
+            Some(top)
+        } else if top + row_height > self.scroll_offset + self.view_height {
+            Some(top + row_height - self.view_height)
+        } else {
+            None
+        }
+    }
+
+    pub fn select_all(&mut self, items: &[PathBuf]) {
+        self.selected = items.iter().cloned().collect();
+    }
+
+    /// Forget files that left the folder list; a removed focus moves to the cell now at its index
+    pub fn retain(&mut self, items: &[PathBuf], focus_index: Option<usize>) {
+        let present: HashSet<&Path> = items.iter().map(|path| path.as_path()).collect();
+        self.selected.retain(|path| present.contains(path.as_path()));
+        if self.focus.as_deref().is_some_and(|focus| !present.contains(focus)) {
+            self.focus = None;
+            self.anchor = None;
+            if let Some(path) = focus_index.and_then(|index| items.get(index.min(items.len().saturating_sub(1)))) {
+                self.select_only(path);
+            }
+        }
+    }
+}
@@ -0,0 +1,267 @@
+//! Golden-image tests for the loading and display pipeline.
+//!
+//! Every fixture in `tests/fixtures` is decoded with the regular loader, run through each
+//! registered transform and hashed; the hashes are compared against `tests/golden/hashes.txt`.
+//!
+//! Run with `cargo test --features golden-tests`. After an intended change of the output,
+//! regenerate the goldens with `UPDATE_GOLDEN=1 cargo test --features golden-tests` and review
+//! the diff of `hashes.txt`. Fixtures are regenerated with
+//! `cargo test --features golden-tests generate_fixtures -- --ignored`.
+
+use crate::image_processing::fp_image;
+use crate::transforms::{TransformKind, TransformRegistry};
+use crate::ImageViewerApp;
+use image::imageops::FilterType;
+use crate::tiff_format::UnsupportedCompression;
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, GenericImageView, ImageBuffer, Luma, Rgb, Rgba};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tiff::encoder::compression::{Deflate, Lzw, Packbits};
+use tiff::encoder::{colortype, TiffEncoder};
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures")
+}
+
+fn golden_file() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join("hashes.txt")
+}
+
+/// FNV-1a over the dimensions, color type and raw bytes of an image
+fn image_hash(img: &DynamicImage) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let (width, height) = img.dimensions();
+    let header = format!("{}x{}:{:?}", width, height, img.color());
+    for &byte in header.as_bytes().iter().chain(img.as_bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+fn fp_hash(data: &[f32]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for value in data {
+        for byte in value.to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+/// Output hashes of every fixture through the loader and each transform, keyed by "fixture/step"
+fn compute_hashes() -> BTreeMap<String, String> {
+    let registry = TransformRegistry::default();
+    let normalization_ids: Vec<&'static str> = registry.of_kind(TransformKind::Normalization).map(|t| t.id()).collect();
+    let transform_ids: Vec<&'static str> = registry
+        .of_kind(TransformKind::Normalization)
+        .chain(registry.of_kind(TransformKind::Analysis))
+        .chain(registry.of_kind(TransformKind::Operation))
+        .chain(registry.of_kind(TransformKind::Filter))
+        .map(|t| t.id())
+        .collect();
+
+    let mut fixtures: Vec<PathBuf> = fs::read_dir(fixtures_dir())
+        .expect("fixtures directory is missing")
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect();
+    fixtures.sort();
+
+    let mut hashes = BTreeMap::new();
+    for path in fixtures {
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        let (img, is_fp, data_range, fp_data, fp_dimensions, fp_channels) = ImageViewerApp::load_image_with_fallback(&path)
+            .unwrap_or_else(|e| panic!("failed to load fixture {}: {}", name, e));
+
+        hashes.insert(format!("{}/decoded", name), image_hash(&img));
+        // Normalizations of FP images read the original data, like the display does
+        let mut fp_source = None;
+        if is_fp {
+            let fp_data = fp_data.expect("floating point image without FP data");
+            let (min, max) = data_range.expect("floating point image without data range");
+            hashes.insert(format!("{}/fp_data", name), fp_hash(&fp_data));
+            hashes.insert(format!("{}/fp_range", name), format!("{:.6}..{:.6}", min, max));
+            let (width, height) = fp_dimensions.expect("floating point image without dimensions");
+            let channels = fp_channels.expect("floating point image without channel count");
+            fp_source = fp_image(&fp_data, width, height, channels, (width, height), FilterType::Triangle);
+        }
+        for id in &transform_ids {
+            let input = match &fp_source {
+                Some(source) if normalization_ids.contains(id) && *id != "none" => source.clone(),
+                _ => img.clone(),
+            };
+            let output = registry.apply(id, input);
+            hashes.insert(format!("{}/{}", name, id), image_hash(&output));
+        }
+    }
+    hashes
+}
+
+fn read_golden() -> BTreeMap<String, String> {
+    let content = fs::read_to_string(golden_file()).unwrap_or_default();
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once(' '))
+        .map(|(key, value)| (key.to_string(), value.trim().to_string()))
+        .collect()
+}
+
+fn write_golden(hashes: &BTreeMap<String, String>) {
+    let mut content = String::from("# Generated by golden_tests.rs, regenerate with UPDATE_GOLDEN=1\n");
+    for (key, value) in hashes {
+        content.push_str(&format!("{} {}\n", key, value));
+    }
+    fs::create_dir_all(golden_file().parent().unwrap()).unwrap();
+    fs::write(golden_file(), content).unwrap();
+}
+
+#[test]
+fn pipeline_matches_golden_hashes() {
+    let actual = compute_hashes();
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        write_golden(&actual);
+        return;
+    }
+
+    let expected = read_golden();
+    let mut mismatches = Vec::new();
+    for key in expected.keys().chain(actual.keys()).collect::<std::collections::BTreeSet<_>>() {
+        let (want, got) = (expected.get(key), actual.get(key));
+        if want != got {
+            mismatches.push(format!("{}: expected {:?}, got {:?}", key, want, got));
+        }
+    }
+    assert!(
+        mismatches.is_empty(),
+        "pipeline output differs from golden hashes (UPDATE_GOLDEN=1 to accept):\n{}",
+        mismatches.join("\n")
+    );
+}
+
+/// Deterministic test pattern: diagonal gradient with a bright square and a dark stripe
+fn pattern(x: u32, y: u32, width: u32, height: u32) -> f32 {
+    let gradient = (x as f32 / (width - 1) as f32 + y as f32 / (height - 1) as f32) / 2.0;
+    if (8..16).contains(&x) && (8..16).contains(&y) {
+        1.0
+    } else if (30..34).contains(&x) {
+        0.05
+    } else {
+        gradient
+    }
+}
+
+fn write_tiff_f32<C: tiff::encoder::colortype::ColorType<Inner = f32>>(path: &Path, width: u32, height: u32, data: &[f32]) {
+    let file = fs::File::create(path).unwrap();
+    let mut encoder = tiff::encoder::TiffEncoder::new(file).unwrap();
+    encoder.write_image::<C>(width, height, data).unwrap();
+}
+
+/// Baseline little-endian TIFF with the 8-bit samples in one strip of `data`, stored as given.
+/// Writes compression schemes the tiff encoder does not have, like JPEG.
+fn write_tiff_strip(path: &Path, width: u32, height: u32, samples: u16, compression: u16, photometric: u16, data: &[u8]) {
+    // Header, the IFD with 9 entries, then the bits per sample and the strip
+    let bits_offset = 8 + 2 + 9 * 12 + 4;
+    let strip_offset = bits_offset + 2 * samples as u32;
+    let (short, long) = (3u16, 4u16);
+    let entries: [(u16, u16, u32, u32); 9] = [
+        (256, long, 1, width),
+        (257, long, 1, height),
+        (258, short, samples as u32, if samples > 2 { bits_offset } else { 8 | 8 << 16 }),
+        (259, short, 1, compression as u32),
+        (262, short, 1, photometric as u32),
+        (273, long, 1, strip_offset),
+        (277, short, 1, samples as u32),
+        (278, long, 1, height),
+        (279, long, 1, data.len() as u32),
+    ];
+    let mut bytes = b"II*\0".to_vec();
+    bytes.extend(8u32.to_le_bytes());
+    bytes.extend((entries.len() as u16).to_le_bytes());
+    for (tag, kind, count, value) in entries {
+        bytes.extend(tag.to_le_bytes());
+        bytes.extend(kind.to_le_bytes());
+        bytes.extend(count.to_le_bytes());
+        bytes.extend(value.to_le_bytes());
+    }
+    bytes.extend(0u32.to_le_bytes());
+    for _ in 0..samples {
+        bytes.extend(8u16.to_le_bytes());
+    }
+    bytes.extend_from_slice(data);
+    fs::write(path, bytes).unwrap();
+}
+
+#[test]
+fn unsupported_compression_is_named() {
+    let path = std::env::temp_dir().join("image_viewer_zstd.tif");
+    write_tiff_strip(&path, 4, 4, 1, 50000, 1, &[0; 16]);
+    let error = ImageViewerApp::load_image_with_fallback(&path).expect_err("Zstandard TIFF loaded");
+    let unsupported = error.downcast_ref::<UnsupportedCompression>().expect("error does not name the compression");
+    assert_eq!(unsupported.code, 50000);
+    assert!(error.to_string().contains("Zstandard"), "{}", error);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+#[ignore]
+fn generate_fixtures() {
+    let (width, height) = (64u32, 48u32);
+    let dir = fixtures_dir();
+    fs::create_dir_all(&dir).unwrap();
+
+    let gray_u8 = ImageBuffer::from_fn(width, height, |x, y| Luma([(pattern(x, y, width, height) * 255.0) as u8]));
+    DynamicImage::ImageLuma8(gray_u8).save(dir.join("gray_u8.png")).unwrap();
+
+    let gray_u16 = ImageBuffer::from_fn(width, height, |x, y| Luma([(pattern(x, y, width, height) * 65535.0) as u16]));
+    DynamicImage::ImageLuma16(gray_u16).save(dir.join("gray_u16.tif")).unwrap();
+
+    let rgba_u8 = ImageBuffer::from_fn(width, height, |x, y| {
+        let v = pattern(x, y, width, height);
+        Rgba([(v * 255.0) as u8, ((1.0 - v) * 255.0) as u8, (x * 4) as u8, if x < 4 { 0 } else { 255 }])
+    });
+    DynamicImage::ImageRgba8(rgba_u8).save(dir.join("rgba_u8.png")).unwrap();
+
+    let gray_f32: Vec<f32> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| pattern(x, y, width, height) * 1000.0 - 250.0))
+        .collect();
+    write_tiff_f32::<tiff::encoder::colortype::Gray32Float>(&dir.join("gray_f32.tif"), width, height, &gray_f32);
+
+    let rgb_f32: Vec<f32> = (0..height)
+        .flat_map(|y| {
+            (0..width).flat_map(move |x| {
+                let v = pattern(x, y, width, height);
+                [v * 2.0, 1.0 - v, x as f32 * 0.01]
+            })
+        })
+        .collect();
+    write_tiff_f32::<tiff::encoder::colortype::RGB32Float>(&dir.join("rgb_f32.tif"), width, height, &rgb_f32);
+
+    // The compression schemes and the BigTIFF layout the loader supports
+    let rgb_u8 = ImageBuffer::from_fn(width, height, |x, y| {
+        let v = pattern(x, y, width, height);
+        Rgb([(v * 255.0) as u8, ((1.0 - v) * 255.0) as u8, (x * 4) as u8])
+    });
+    let file = fs::File::create(dir.join("rgb_u8_lzw.tif")).unwrap();
+    TiffEncoder::new(file).unwrap()
+        .write_image_with_compression::<colortype::RGB8, _>(width, height, Lzw, rgb_u8.as_raw()).unwrap();
+    let gray_u8: Vec<u8> = (0..height).flat_map(|y| (0..width).map(move |x| (pattern(x, y, width, height) * 255.0) as u8)).collect();
+    let file = fs::File::create(dir.join("gray_u8_deflate.tif")).unwrap();
+    TiffEncoder::new(file).unwrap()
+        .write_image_with_compression::<colortype::Gray8, _>(width, height, Deflate::default(), &gray_u8).unwrap();
+    let rgba_u8 = DynamicImage::ImageRgb8(rgb_u8.clone()).into_rgba8();
+    let file = fs::File::create(dir.join("rgba_u8_packbits.tif")).unwrap();
+    TiffEncoder::new(file).unwrap()
+        .write_image_with_compression::<colortype::RGBA8, _>(width, height, Packbits, rgba_u8.as_raw()).unwrap();
+    let gray_u16: Vec<u16> = (0..height).flat_map(|y| (0..width).map(move |x| (pattern(x, y, width, height) * 65535.0) as u16)).collect();
+    let file = fs::File::create(dir.join("gray_u16_bigtiff.tif")).unwrap();
+    TiffEncoder::new_big(file).unwrap()
+        .write_image_with_compression::<colortype::Gray16, _>(width, height, Lzw, &gray_u16).unwrap();
+    // JPEG in TIFF is stored as YCbCr, the usual layout of scanners and slide images
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, 90).encode_image(&rgb_u8).unwrap();
+    write_tiff_strip(&dir.join("rgb_u8_jpeg.tif"), width, height, 3, 7, 6, &jpeg);
+}
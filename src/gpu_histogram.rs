@@ -0,0 +1,618 @@
+//! Histograms computed on the GPU, for images where the CPU loop takes seconds.
+//!
+//! The image is uploaded in tiles; every pixel is drawn as one point per channel into a
+//! float framebuffer whose texels are the bins, summed by additive blending. A second pass
+//! with max blending finds the value range. Results are read back through a pixel buffer
+//! and polled with a fence on later frames, so the UI keeps running meanwhile. Tiles hold
+//! at most 2^24 pixels, the largest count a float bin adds up exactly.
+
+use eframe::egui_glow;
+use eframe::glow::{self, HasContext};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use crate::PixelRect;
+
+/// Largest tile side, and tiles are limited to this many bytes of uploaded data
+const TILE_SIZE: u32 = 4096;
+const TILE_BYTES: usize = 64 << 20;
+/// Width of the bin texture, bins continue on the next row
+const BIN_COLUMNS: usize = 256;
+/// Texels per channel the sums are spread over, which keeps float rounding errors small
+const SUM_TEXELS: usize = 64;
+
+const VERTEX_SHADER: &str = r#"
+uniform sampler2D u_image;
+uniform int u_width;
+uniform int u_channels;
+uniform float u_scale;
+uniform vec2 u_range;
+uniform int u_bins;
+uniform int u_rows;
+uniform int u_mode;
+uniform int u_alpha_mode;
+uniform int u_direct;
+uniform vec2 u_target;
+out vec4 v_value;
+
+// Mode 0: bins and sums, 6 points per pixel. Mode 1: weighted min/max, mode 2: unweighted
+// min/max, 3 points per pixel.
+void main() {
+    int per_pixel = u_mode == 0 ? 6 : 3;
+    int pixel = gl_VertexID / per_pixel;
+    int item = gl_VertexID - pixel * per_pixel;
+    int channel = item % 3;
+    vec4 texel = texelFetch(u_image, ivec2(pixel % u_width, pixel / u_width), 0);
+    vec4 rgba = texel;
+    if (u_channels == 1) {
+        rgba = vec4(texel.rrr, 1.0);
+    } else if (u_channels == 2) {
+        rgba = vec4(texel.rrr, texel.g);
+    } else if (u_channels == 3) {
+        rgba = vec4(texel.rgb, 1.0);
+    }
+    float value = rgba[channel] * u_scale;
+    if (u_scale > 1.0) {
+        value = floor(value + 0.5);
+    }
+    float alpha = clamp(rgba.a, 0.0, 1.0);
+    float weight = 1.0;
+    if (u_mode != 2 && u_alpha_mode == 1) {
+        weight = alpha > 0.0 ? 1.0 : 0.0;
+    } else if (u_mode != 2 && u_alpha_mode == 2) {
+        weight = alpha;
+    }
+
+    vec2 target;
+    if (u_mode == 0 && item < 3) {
+        int bin;
+        if (u_direct == 1) {
+            bin = int(value);
+        } else {
+            float range = u_range.y - u_range.x;
+            float normalized = range > 1.1920929e-7 ? clamp((value - u_range.x) / range, 0.0, 1.0) : 0.5;
+            bin = int(normalized * float(u_bins - 1));
+        }
+        target = vec2(float(bin % 256), float(bin / 256 + channel * u_rows));
+        v_value = vec4(weight, 0.0, 0.0, 0.0);
+    } else if (u_mode == 0) {
+        // Spread over SUM_TEXELS texels per channel
+        target = vec2(float(channel * 64 + pixel % 64), float(3 * u_rows));
+        v_value = vec4(value * weight, value * value * weight, weight, 0.0);
+    } else {
+        target = vec2(float(channel), 0.0);
+        v_value = vec4(value, -value, 0.0, 0.0);
+    }
+    // Pixels without weight and NaNs are moved outside the target
+    bool skip = weight <= 0.0 || isnan(value);
+    gl_Position = skip ? vec4(2.0, 2.0, 0.0, 1.0) : vec4((target + 0.5) / u_target * 2.0 - 1.0, 0.0, 1.0);
+    gl_PointSize = 1.0;
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+in vec4 v_value;
+out vec4 out_value;
+
+void main() {
+    out_value = v_value;
+}
+"#;
+
+/// Sample type of the histogram source
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SampleType {
+    U8,
+    U16,
+    F32,
+}
+
+impl SampleType {
+    fn size(self) -> usize {
+        match self {
+            SampleType::U8 => 1,
+            SampleType::U16 => 2,
+            SampleType::F32 => 4,
+        }
+    }
+}
+
+/// Pixel data the tiles are copied from, interleaved with the request's channel count
+pub enum SourceData<'a> {
+    /// 8 or 16-bit samples in native byte order
+    Bytes(&'a [u8]),
+    F32(&'a [f32]),
+}
+
+/// What to compute: the image layout, the region and how values are binned
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct HistogramRequest {
+    pub width: u32,
+    pub height: u32,
+    /// 1 gray, 2 gray + alpha, 3 RGB, 4 RGBA
+    pub channels: u32,
+    pub sample: SampleType,
+    pub region: PixelRect,
+    pub bins: usize,
+    /// Data range of the bins; `None` finds the range of the whole image first
+    pub range: Option<(f32, f32)>,
+    /// 0 counts all pixels, 1 excludes transparent ones, 2 weights by alpha
+    pub alpha_mode: i32,
+}
+
+/// Bin weights and the statistics of the three color channels
+pub struct HistogramResult {
+    pub histograms: Vec<Vec<f32>>,
+    pub range: (f32, f32),
+    pub weight: [f64; 3],
+    pub sum: [f64; 3],
+    pub sum_squares: [f64; 3],
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Phase {
+    /// Value range of the whole image, for 16-bit data
+    Range,
+    Bins,
+}
+
+/// GL objects, created on the first paint callback and kept for the session
+struct GlResources {
+    program: glow::Program,
+    vertex_array: glow::VertexArray,
+    source: glow::Texture,
+    bins: glow::Texture,
+    bins_framebuffer: glow::Framebuffer,
+    bins_rows: usize,
+    range_framebuffer: glow::Framebuffer,
+    readback: glow::Buffer,
+    readback_size: usize,
+}
+
+#[derive(Default)]
+struct State {
+    request: Option<HistogramRequest>,
+    phase: Option<Phase>,
+    /// Tiles of the current phase not copied yet
+    tiles: VecDeque<PixelRect>,
+    /// Copied by the app, uploaded by the next paint callback
+    next_tile: Option<(PixelRect, Vec<u8>)>,
+    /// Readback of a drawn tile waiting for the GPU, with the generation it belongs to
+    in_flight: Option<(glow::Fence, u64)>,
+    /// Incremented by every start, readbacks of older computations are dropped
+    generation: u64,
+    range: (f32, f32),
+    histograms: Vec<Vec<f64>>,
+    weight: [f64; 3],
+    sum: [f64; 3],
+    sum_squares: [f64; 3],
+    min: [f32; 3],
+    max: [f32; 3],
+    outcome: Option<Result<HistogramResult, String>>,
+    resources: Option<GlResources>,
+    /// Set when the GL context cannot run the shaders, the CPU is used from then on
+    unsupported: Option<String>,
+}
+
+// The fence is only touched from the paint callback on the GL thread
+unsafe impl Send for State {}
+
+/// Histogram computation on the GPU, driven from `update` and paint callbacks
+#[derive(Clone, Default)]
+pub struct GpuHistogram {
+    state: Arc<Mutex<State>>,
+}
+
+impl GpuHistogram {
+    /// Whether the GPU can't be used, with the reason
+    pub fn unsupported(&self) -> Option<String> {
+        self.state.lock().ok().and_then(|state| state.unsupported.clone())
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state.lock().is_ok_and(|state| state.phase.is_some())
+    }
+
+    /// Start a computation, replacing one in progress
+    pub fn start(&self, request: HistogramRequest) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.request = Some(request);
+        state.generation += 1;
+        state.next_tile = None;
+        state.outcome = None;
+        state.histograms = vec![vec![0.0; request.bins]; 3];
+        state.weight = [0.0; 3];
+        state.sum = [0.0; 3];
+        state.sum_squares = [0.0; 3];
+        state.min = [f32::INFINITY; 3];
+        state.max = [f32::NEG_INFINITY; 3];
+        match request.range {
+            Some(range) => {
+                state.range = range;
+                state.begin(Phase::Bins, request.region);
+            }
+            None => state.begin(Phase::Range, [0, 0, request.width, request.height]),
+        }
+    }
+
+    pub fn cancel(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.phase = None;
+            state.generation += 1;
+            state.tiles.clear();
+            state.next_tile = None;
+            state.outcome = None;
+        }
+    }
+
+    /// Finished result, or the error that stopped the computation
+    pub fn take_outcome(&self) -> Option<Result<HistogramResult, String>> {
+        self.state.lock().ok().and_then(|mut state| state.outcome.take())
+    }
+
+    /// Copy the next tile from the image for the paint callback, if it has none waiting
+    pub fn feed(&self, data: SourceData) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let Some(request) = state.request else {
+            return;
+        };
+        if state.phase.is_none() || state.next_tile.is_some() {
+            return;
+        }
+        let Some(tile) = state.tiles.pop_front() else {
+            return;
+        };
+        let [x0, y0, x1, y1] = tile;
+        let pixel_size = request.channels as usize * request.sample.size();
+        let row_values = (x1 - x0) as usize * request.channels as usize;
+        let mut bytes = Vec::with_capacity((y1 - y0) as usize * row_values * request.sample.size());
+        for y in y0..y1 {
+            let start = (y as usize * request.width as usize + x0 as usize) * request.channels as usize;
+            match data {
+                SourceData::Bytes(data) => {
+                    let start = start / request.channels as usize * pixel_size;
+                    bytes.extend_from_slice(&data[start..start + row_values * request.sample.size()]);
+                }
+                SourceData::F32(data) => bytes.extend(data[start..start + row_values].iter().flat_map(|v| v.to_ne_bytes())),
+            }
+        }
+        state.next_tile = Some((tile, bytes));
+    }
+
+    /// Paint callback that advances the computation on the GL thread; add it to the
+    /// root viewport every frame while `is_running`
+    pub fn paint_callback(&self, rect: egui::Rect) -> egui::PaintCallback {
+        let this = self.clone();
+        egui::PaintCallback {
+            rect,
+            callback: Arc::new(egui_glow::CallbackFn::new(move |_info, painter| {
+                if let Ok(mut state) = this.state.lock() {
+                    state.step(painter.gl(), painter.intermediate_fbo());
+                }
+            })),
+        }
+    }
+}
+
+impl State {
+    fn begin(&mut self, phase: Phase, region: PixelRect) {
+        let Some(request) = self.request else {
+            return;
+        };
+        self.phase = Some(phase);
+        self.tiles = tiles(region, request.channels as usize * request.sample.size());
+    }
+
+    /// Collect the last readback, then upload and draw the waiting tile
+    fn step(&mut self, gl: &glow::Context, painter_framebuffer: Option<glow::Framebuffer>) {
+        if let Some((fence, generation)) = self.in_flight {
+            if generation != self.generation {
+                unsafe { gl.delete_sync(fence) };
+                self.in_flight = None;
+            }
+        }
+        let (Some(phase), Some(request)) = (self.phase, self.request) else {
+            return;
+        };
+        if self.resources.is_none() && self.unsupported.is_none() {
+            match unsafe { GlResources::new(gl) } {
+                Ok(resources) => self.resources = Some(resources),
+                Err(e) => self.unsupported = Some(e),
+            }
+        }
+        if let Some(reason) = &self.unsupported {
+            self.outcome = Some(Err(reason.clone()));
+            self.phase = None;
+            return;
+        }
+
+        if let Some((fence, _)) = self.in_flight {
+            if unsafe { gl.get_sync_status(fence) } != glow::SIGNALED {
+                return;
+            }
+            unsafe { gl.delete_sync(fence) };
+            self.in_flight = None;
+            self.collect(gl, phase, request);
+        }
+
+        if let Some((tile, bytes)) = self.next_tile.take() {
+            let Some(resources) = self.resources.as_mut() else {
+                return;
+            };
+            match unsafe { resources.draw(gl, painter_framebuffer, phase, &HistogramRequest { range: Some(self.range), ..request }, tile, &bytes) } {
+                Ok(fence) => self.in_flight = Some((fence, self.generation)),
+                Err(e) => {
+                    self.unsupported = Some(e.clone());
+                    self.outcome = Some(Err(e));
+                    self.phase = None;
+                }
+            }
+            return;
+        }
+
+        if self.in_flight.is_none() && self.tiles.is_empty() {
+            match phase {
+                Phase::Range => {
+                    let min = self.min.iter().copied().fold(f32::INFINITY, f32::min);
+                    let max = self.max.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                    self.range = if min <= max { (min, max) } else { (0.0, 0.0) };
+                    self.min = [f32::INFINITY; 3];
+                    self.max = [f32::NEG_INFINITY; 3];
+                    self.begin(Phase::Bins, request.region);
+                }
+                Phase::Bins => {
+                    self.outcome = Some(Ok(HistogramResult {
+                        histograms: self.histograms.iter().map(|h| h.iter().map(|&v| v as f32).collect()).collect(),
+                        range: self.range,
+                        weight: self.weight,
+                        sum: self.sum,
+                        sum_squares: self.sum_squares,
+                        min: self.min,
+                        max: self.max,
+                    }));
+                    self.phase = None;
+                }
+            }
+        }
+    }
+
+    /// Add the read back tile results to the totals
+    fn collect(&mut self, gl: &glow::Context, phase: Phase, request: HistogramRequest) {
+        let Some(resources) = &self.resources else {
+            return;
+        };
+        let values = unsafe { resources.read(gl) };
+        let rows = request.bins.div_ceil(BIN_COLUMNS);
+        let range_offset = match phase {
+            Phase::Range => 0,
+            Phase::Bins => BIN_COLUMNS * (3 * rows + 1) * 4,
+        };
+        if phase == Phase::Bins {
+            for (channel, histogram) in self.histograms.iter_mut().enumerate() {
+                for (bin, total) in histogram.iter_mut().enumerate() {
+                    let texel = (channel * rows + bin / BIN_COLUMNS) * BIN_COLUMNS + bin % BIN_COLUMNS;
+                    *total += values[texel * 4] as f64;
+                }
+                let sums = &values[((3 * rows) * BIN_COLUMNS + channel * SUM_TEXELS) * 4..][..SUM_TEXELS * 4];
+                for texel in sums.chunks_exact(4) {
+                    self.sum[channel] += texel[0] as f64;
+                    self.sum_squares[channel] += texel[1] as f64;
+                    self.weight[channel] += texel[2] as f64;
+                }
+            }
+        }
+        for channel in 0..3 {
+            let texel = &values[range_offset + channel * 4..][..2];
+            // Channels without any drawn pixel keep the clear value
+            if texel[0] > f32::MIN {
+                self.max[channel] = self.max[channel].max(texel[0]);
+                self.min[channel] = self.min[channel].min(0.0 - texel[1]);
+            }
+        }
+    }
+}
+
+/// Tiles of at most `TILE_SIZE` pixels a side and `TILE_BYTES` of data
+fn tiles(region: PixelRect, pixel_size: usize) -> VecDeque<PixelRect> {
+    let [x0, y0, x1, y1] = region;
+    let tile_width = TILE_SIZE.min(x1.saturating_sub(x0)).max(1);
+    let tile_height = ((TILE_BYTES / (tile_width as usize * pixel_size)) as u32).clamp(1, TILE_SIZE);
+    let mut tiles = VecDeque::new();
+    for y in (y0..y1).step_by(tile_height as usize) {
+        for x in (x0..x1).step_by(tile_width as usize) {
+            tiles.push_back([x, y, (x + tile_width).min(x1), (y + tile_height).min(y1)]);
+        }
+    }
+    tiles
+}
+
+impl GlResources {
+    unsafe fn new(gl: &glow::Context) -> Result<Self, String> {
+        let version = gl.version();
+        if version.is_embedded || (version.major, version.minor) < (3, 2) {
+            return Err(format!("OpenGL {}.{}{} has no float blending or fences", version.major, version.minor, if version.is_embedded { " ES" } else { "" }));
+        }
+        let program = gl.create_program()?;
+        let mut shaders = Vec::new();
+        for (kind, source) in [(glow::VERTEX_SHADER, VERTEX_SHADER), (glow::FRAGMENT_SHADER, FRAGMENT_SHADER)] {
+            let shader = gl.create_shader(kind)?;
+            gl.shader_source(shader, &format!("#version 140\n{}", source));
+            gl.compile_shader(shader);
+            if !gl.get_shader_compile_status(shader) {
+                return Err(format!("Histogram shader: {}", gl.get_shader_info_log(shader)));
+            }
+            gl.attach_shader(program, shader);
+            shaders.push(shader);
+        }
+        gl.bind_frag_data_location(program, 0, "out_value");
+        gl.link_program(program);
+        if !gl.get_program_link_status(program) {
+            return Err(format!("Histogram program: {}", gl.get_program_info_log(program)));
+        }
+        for shader in shaders {
+            gl.detach_shader(program, shader);
+            gl.delete_shader(shader);
+        }
+
+        let source = gl.create_texture()?;
+        gl.bind_texture(glow::TEXTURE_2D, Some(source));
+        gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::NEAREST as i32);
+        gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+        // Only read through its framebuffer
+        let (_, range_framebuffer) = float_target(gl, 3, 1)?;
+        let (bins, bins_framebuffer) = float_target(gl, BIN_COLUMNS as i32, 4)?;
+        gl.bind_texture(glow::TEXTURE_2D, None);
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+
+        Ok(Self {
+            program,
+            vertex_array: gl.create_vertex_array()?,
+            source,
+            bins,
+            bins_framebuffer,
+            bins_rows: 1,
+            range_framebuffer,
+            readback: gl.create_buffer()?,
+            readback_size: 0,
+        })
+    }
+
+    /// Upload a tile, draw its passes and start reading the targets back. egui restores its
+    /// own state after the callback except the framebuffer, which is bound again here.
+    unsafe fn draw(&mut self, gl: &glow::Context, painter_framebuffer: Option<glow::Framebuffer>, phase: Phase, request: &HistogramRequest, tile: PixelRect, bytes: &[u8]) -> Result<glow::Fence, String> {
+        let [x0, y0, x1, y1] = tile;
+        let (width, height) = ((x1 - x0) as i32, (y1 - y0) as i32);
+        let (format, internal_format) = match request.channels {
+            1 => (glow::RED, [glow::R8, glow::R16, glow::R32F]),
+            2 => (glow::RG, [glow::RG8, glow::RG16, glow::RG32F]),
+            3 => (glow::RGB, [glow::RGB8, glow::RGB16, glow::RGB32F]),
+            _ => (glow::RGBA, [glow::RGBA8, glow::RGBA16, glow::RGBA32F]),
+        };
+        let (internal_format, data_type, scale) = match request.sample {
+            SampleType::U8 => (internal_format[0], glow::UNSIGNED_BYTE, 255.0),
+            SampleType::U16 => (internal_format[1], glow::UNSIGNED_SHORT, 65535.0),
+            SampleType::F32 => (internal_format[2], glow::FLOAT, 1.0),
+        };
+        gl.active_texture(glow::TEXTURE0);
+        gl.bind_texture(glow::TEXTURE_2D, Some(self.source));
+        gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+        gl.tex_image_2d(glow::TEXTURE_2D, 0, internal_format as i32, width, height, 0, format, data_type, glow::PixelUnpackData::Slice(Some(bytes)));
+
+        let rows = request.bins.div_ceil(BIN_COLUMNS);
+        if rows != self.bins_rows {
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.bins));
+            gl.tex_image_2d(glow::TEXTURE_2D, 0, glow::RGBA32F as i32, BIN_COLUMNS as i32, (3 * rows + 1) as i32, 0, glow::RGBA, glow::FLOAT, glow::PixelUnpackData::Slice(None));
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.source));
+            self.bins_rows = rows;
+        }
+
+        gl.use_program(Some(self.program));
+        gl.bind_vertex_array(Some(self.vertex_array));
+        gl.disable(glow::SCISSOR_TEST);
+        gl.enable(glow::BLEND);
+        gl.blend_func(glow::ONE, glow::ONE);
+        let uniform = |name: &str| gl.get_uniform_location(self.program, name);
+        gl.uniform_1_i32(uniform("u_image").as_ref(), 0);
+        gl.uniform_1_i32(uniform("u_width").as_ref(), width);
+        gl.uniform_1_i32(uniform("u_channels").as_ref(), request.channels as i32);
+        gl.uniform_1_f32(uniform("u_scale").as_ref(), scale);
+        let (min, max) = request.range.unwrap_or_default();
+        gl.uniform_2_f32(uniform("u_range").as_ref(), min, max);
+        gl.uniform_1_i32(uniform("u_bins").as_ref(), request.bins as i32);
+        gl.uniform_1_i32(uniform("u_rows").as_ref(), rows as i32);
+        gl.uniform_1_i32(uniform("u_alpha_mode").as_ref(), request.alpha_mode);
+        gl.uniform_1_i32(uniform("u_direct").as_ref(), (request.sample == SampleType::U8) as i32);
+        let pixels = width * height;
+
+        let bins_size = BIN_COLUMNS * (3 * rows + 1) * 16;
+        if phase == Phase::Bins {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.bins_framebuffer));
+            check_framebuffer(gl)?;
+            gl.viewport(0, 0, BIN_COLUMNS as i32, (3 * rows + 1) as i32);
+            gl.clear_color(0.0, 0.0, 0.0, 0.0);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+            gl.blend_equation(glow::FUNC_ADD);
+            gl.uniform_1_i32(uniform("u_mode").as_ref(), 0);
+            gl.uniform_2_f32(uniform("u_target").as_ref(), BIN_COLUMNS as f32, (3 * rows + 1) as f32);
+            gl.draw_arrays(glow::POINTS, 0, pixels * 6);
+        }
+
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.range_framebuffer));
+        check_framebuffer(gl)?;
+        gl.viewport(0, 0, 3, 1);
+        gl.clear_color(f32::MIN, f32::MIN, 0.0, 0.0);
+        gl.clear(glow::COLOR_BUFFER_BIT);
+        gl.blend_equation(glow::MAX);
+        gl.uniform_1_i32(uniform("u_mode").as_ref(), if phase == Phase::Range { 2 } else { 1 });
+        gl.uniform_2_f32(uniform("u_target").as_ref(), 3.0, 1.0);
+        gl.draw_arrays(glow::POINTS, 0, pixels * 3);
+        gl.blend_equation(glow::FUNC_ADD);
+
+        // Bins (if drawn) followed by the three range texels
+        let range_offset = if phase == Phase::Bins { bins_size } else { 0 };
+        let size = range_offset + 3 * 16;
+        gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(self.readback));
+        if size > self.readback_size {
+            gl.buffer_data_size(glow::PIXEL_PACK_BUFFER, (bins_size + 3 * 16).max(size) as i32, glow::STREAM_READ);
+            self.readback_size = (bins_size + 3 * 16).max(size);
+        }
+        gl.read_pixels(0, 0, 3, 1, glow::RGBA, glow::FLOAT, glow::PixelPackData::BufferOffset(range_offset as u32));
+        if phase == Phase::Bins {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.bins_framebuffer));
+            gl.read_pixels(0, 0, BIN_COLUMNS as i32, (3 * rows + 1) as i32, glow::RGBA, glow::FLOAT, glow::PixelPackData::BufferOffset(0));
+        }
+        gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
+        let fence = gl.fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0);
+
+        gl.bind_framebuffer(glow::FRAMEBUFFER, painter_framebuffer);
+        gl.bind_vertex_array(None);
+        gl.bind_texture(glow::TEXTURE_2D, None);
+        gl.use_program(None);
+        gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+        fence
+    }
+
+    /// Floats of the last readback
+    unsafe fn read(&self, gl: &glow::Context) -> Vec<f32> {
+        gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(self.readback));
+        let pointer = gl.map_buffer_range(glow::PIXEL_PACK_BUFFER, 0, self.readback_size as i32, glow::MAP_READ_BIT);
+        let values = if pointer.is_null() {
+            vec![0.0; self.readback_size / 4]
+        } else {
+            let bytes = std::slice::from_raw_parts(pointer, self.readback_size);
+            let values = bytes.chunks_exact(4).map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]])).collect();
+            gl.unmap_buffer(glow::PIXEL_PACK_BUFFER);
+            values
+        };
+        gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
+        values
+    }
+}
+
+/// RGBA32F texture of the given size attached to a new framebuffer
+unsafe fn float_target(gl: &glow::Context, width: i32, height: i32) -> Result<(glow::Texture, glow::Framebuffer), String> {
+    let texture = gl.create_texture()?;
+    gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::NEAREST as i32);
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+    gl.tex_image_2d(glow::TEXTURE_2D, 0, glow::RGBA32F as i32, width, height, 0, glow::RGBA, glow::FLOAT, glow::PixelUnpackData::Slice(None));
+    let framebuffer = gl.create_framebuffer()?;
+    gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+    gl.framebuffer_texture_2d(glow::FRAMEBUFFER, glow::COLOR_ATTACHMENT0, glow::TEXTURE_2D, Some(texture), 0);
+    check_framebuffer(gl)?;
+    Ok((texture, framebuffer))
+}
+
+unsafe fn check_framebuffer(gl: &glow::Context) -> Result<(), String> {
+    match gl.check_framebuffer_status(glow::FRAMEBUFFER) {
+        glow::FRAMEBUFFER_COMPLETE => Ok(()),
+        status => Err(format!("Float framebuffer incomplete (0x{:x})", status)),
+    }
+}
+
@@ -0,0 +1,176 @@
+use image::{DynamicImage, GrayImage};
+use log::warn;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+/// Function used by the indexing thread to decode an image
+pub type HintLoader = fn(&Path) -> anyhow::Result<DynamicImage>;
+
+/// Edge length the images are reduced to before classification
+const ANALYSIS_SIZE: u32 = 256;
+/// Fraction of an edge a constant band has to cover to count as a border
+const BORDER_FRACTION: f32 = 0.1;
+
+/// Obvious capture failures, detected from the luma histogram and the image edges
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ImageHint {
+    NearlyBlack,
+    NearlyWhite,
+    LowContrast,
+    ConstantBorder,
+}
+
+impl ImageHint {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageHint::NearlyBlack => "Nearly black",
+            ImageHint::NearlyWhite => "Nearly white",
+            ImageHint::LowContrast => "Very low contrast",
+            ImageHint::ConstantBorder => "Large constant border",
+        }
+    }
+
+    /// Short text for the filmstrip badge
+    pub fn badge(&self) -> &'static str {
+        match self {
+            ImageHint::NearlyBlack => "DARK",
+            ImageHint::NearlyWhite => "WHITE",
+            ImageHint::LowContrast => "FLAT",
+            ImageHint::ConstantBorder => "BORDER",
+        }
+    }
+}
+
+/// Luma value below which `fraction` of the pixels lie
+fn percentile(histogram: &[u64; 256], total: u64, fraction: f64) -> u8 {
+    let target = (total as f64 * fraction).ceil() as u64;
+    let mut sum = 0;
+    for (value, &count) in histogram.iter().enumerate() {
+        sum += count;
+        if sum >= target.max(1) {
+            return value as u8;
+        }
+    }
+    255
+}
+
+/// Whether the pixels of a row or column have (almost) the same value
+fn is_constant(pixels: impl Iterator<Item = u8>) -> bool {
+    let (min, max) = pixels.fold((u8::MAX, u8::MIN), |(min, max), v| (min.min(v), max.max(v)));
+    max.saturating_sub(min) <= 4
+}
+
+/// Whether a band of at least BORDER_FRACTION along any edge is constant
+fn has_constant_border(luma: &GrayImage) -> bool {
+    let (width, height) = luma.dimensions();
+    let row = |y: u32| (0..width).map(move |x| luma.get_pixel(x, y)[0]);
+    let column = |x: u32| (0..height).map(move |y| luma.get_pixel(x, y)[0]);
+    let band = |extent: u32| ((extent as f32 * BORDER_FRACTION).ceil() as u32).max(1);
+
+    (0..band(height)).all(|y| is_constant(row(y)))
+        || (0..band(height)).all(|y| is_constant(row(height - 1 - y)))
+        || (0..band(width)).all(|x| is_constant(column(x)))
+        || (0..band(width)).all(|x| is_constant(column(width - 1 - x)))
+}
+
+/// Hints for an image, empty when nothing looks wrong
+pub fn classify(img: &DynamicImage) -> Vec<ImageHint> {
+    let luma = img.thumbnail(ANALYSIS_SIZE, ANALYSIS_SIZE).to_luma8();
+    if luma.width() == 0 || luma.height() == 0 {
+        return Vec::new();
+    }
+
+    let mut histogram = [0u64; 256];
+    for pixel in luma.pixels() {
+        histogram[pixel[0] as usize] += 1;
+    }
+    let total = luma.pixels().len() as u64;
+    let low = percentile(&histogram, total, 0.01);
+    let high = percentile(&histogram, total, 0.99);
+
+    let mut hints = Vec::new();
+    if high <= 24 {
+        hints.push(ImageHint::NearlyBlack);
+    } else if low >= 231 {
+        hints.push(ImageHint::NearlyWhite);
+    } else if high - low < 16 {
+        hints.push(ImageHint::LowContrast);
+    }
+    // A flat image is constant everywhere, a border only matters around content
+    if hints.is_empty() && has_constant_border(&luma) {
+        hints.push(ImageHint::ConstantBorder);
+    }
+    hints
+}
+
+/// Classification of the images of a folder, computed on a background thread
+pub struct HintIndex {
+    hints: HashMap<PathBuf, Vec<ImageHint>>,
+    /// Folder being indexed, a new folder cancels the previous run
+    folder: Option<PathBuf>,
+    cancel: Arc<AtomicBool>,
+    result_tx: Sender<(PathBuf, Vec<ImageHint>)>,
+    result_rx: Receiver<(PathBuf, Vec<ImageHint>)>,
+    loader: HintLoader,
+}
+
+impl HintIndex {
+    pub fn new(loader: HintLoader) -> Self {
+        let (result_tx, result_rx) = channel();
+        Self {
+            hints: HashMap::new(),
+            folder: None,
+            cancel: Arc::new(AtomicBool::new(false)),
+            result_tx,
+            result_rx,
+            loader,
+        }
+    }
+
+    /// Classify the images of a folder that are not known yet, unless that folder is already indexed
+    pub fn index(&mut self, ctx: &egui::Context, paths: &[PathBuf]) {
+        let folder = paths.first().and_then(|path| path.parent()).map(Path::to_path_buf);
+        if folder == self.folder {
+            return;
+        }
+        self.folder = folder;
+        self.cancel.store(true, Ordering::Relaxed);
+        self.cancel = Arc::new(AtomicBool::new(false));
+
+        let pending: Vec<PathBuf> = paths.iter().filter(|path| !self.hints.contains_key(*path)).cloned().collect();
+        let (cancel, result_tx, loader, ctx) = (Arc::clone(&self.cancel), self.result_tx.clone(), self.loader, ctx.clone());
+        thread::spawn(move || {
+            for path in pending {
+                if cancel.load(Ordering::Relaxed) {
+                    break;
+                }
+                let hints = match loader(&path) {
+                    Ok(img) => classify(&img),
+                    Err(e) => {
+                        warn!("Failed to classify {:?}: {}", path, e);
+                        Vec::new()
+                    }
+                };
+                if result_tx.send((path, hints)).is_err() {
+                    break;
+                }
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    /// Collect finished classifications, call once per frame
+    pub fn poll(&mut self) {
+        while let Ok((path, hints)) = self.result_rx.try_recv() {
+            self.hints.insert(path, hints);
+        }
+    }
+
+    pub fn get(&self, path: &Path) -> &[ImageHint] {
+        self.hints.get(path).map(Vec::as_slice).unwrap_or_default()
+    }
+}
@@ -0,0 +1,216 @@
+//! Histograms and scopes counted on a worker thread, so the UI keeps running on large images.
+//!
+//! The worker reads the raw sample buffers of a copy of the image (or of the original floating
+//! point data) in bands of rows. Between bands it reports its progress and stops when a newer
+//! request replaced it. Results are posted straight into the analysis window's shared data.
+
+use image::DynamicImage;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::image_processing::{vectorscope, waveform};
+use crate::{AlphaMode, ChannelStats, HistogramAxis, HistogramData, PixelRect, VECTORSCOPE_SIZE, WAVEFORM_COLUMNS};
+
+/// Rows counted between progress updates
+const BAND_ROWS: u32 = 64;
+
+/// What to count, with copies of the pixel data
+pub struct HistogramInput {
+    pub image: DynamicImage,
+    /// Original floating point samples with their channel count and width, counted instead of the image
+    pub fp_data: Option<(Vec<f32>, usize, u32)>,
+    /// Range of the floating point data, found in a first pass without one
+    pub fp_range: Option<(f32, f32)>,
+    /// Counted region, clamped to the image; the scopes are computed from it too
+    pub region: PixelRect,
+    pub bins: usize,
+    pub alpha_mode: AlphaMode,
+    /// Only compute the scopes, the histogram is counted on the GPU
+    pub scopes_only: bool,
+}
+
+/// The running computation, a new one cancels the previous
+#[derive(Default)]
+pub struct HistogramJob {
+    cancelled: Option<Arc<AtomicBool>>,
+}
+
+impl HistogramJob {
+    /// Count in the background; the viewport `repaint` is repainted on progress
+    pub fn start(&mut self, ctx: &egui::Context, repaint: Option<egui::ViewportId>, input: HistogramInput, shared: &Arc<Mutex<HistogramData>>) {
+        self.cancel(shared);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.cancelled = Some(Arc::clone(&cancelled));
+        if let Ok(mut data) = shared.lock() {
+            data.cpu_progress = Some(0.0);
+        }
+
+        let (ctx, shared) = (ctx.clone(), Arc::clone(shared));
+        let repaint = move || match repaint {
+            Some(viewport) => ctx.request_repaint_of(viewport),
+            None => ctx.request_repaint(),
+        };
+        thread::spawn(move || {
+            // Posts only while this job is current, checked under the lock a newer job takes to cancel it
+            let post = |update: &mut dyn FnMut(&mut HistogramData)| {
+                let mut data = shared.lock().ok()?;
+                if cancelled.load(Ordering::Relaxed) {
+                    return None;
+                }
+                update(&mut data);
+                repaint();
+                Some(())
+            };
+
+            // Scopes are computed on a reduced copy, their resolution is limited anyway
+            let [x0, y0, x1, y1] = input.region;
+            let scope_source = if input.region == [0, 0, input.image.width(), input.image.height()] {
+                input.image.thumbnail(WAVEFORM_COLUMNS, WAVEFORM_COLUMNS)
+            } else {
+                input.image.crop_imm(x0, y0, x1.saturating_sub(x0), y1.saturating_sub(y0)).thumbnail(WAVEFORM_COLUMNS, WAVEFORM_COLUMNS)
+            };
+            let waveform_data = waveform(&scope_source, WAVEFORM_COLUMNS);
+            let vectorscope_data = vectorscope(&scope_source, VECTORSCOPE_SIZE);
+            let mut scopes = Some((waveform_data, vectorscope_data));
+            post(&mut |data| {
+                if let Some((waveform_data, vectorscope_data)) = scopes.take() {
+                    data.waveform = Some(waveform_data);
+                    data.vectorscope = Some(vectorscope_data);
+                    data.waveform_texture = None;
+                    data.vectorscope_texture = None;
+                }
+                if input.scopes_only {
+                    data.cpu_progress = None;
+                }
+            });
+            if input.scopes_only {
+                return;
+            }
+
+            let counted = count(&input, &mut |fraction| post(&mut |data| data.cpu_progress = Some(fraction)).is_some());
+            if let Some((histograms, axis, stats)) = counted {
+                let mut result = Some(histograms);
+                post(&mut |data| {
+                    data.histograms = result.take();
+                    data.axis = axis;
+                    data.stats = Some(stats);
+                    data.cpu_progress = None;
+                });
+            }
+        });
+    }
+
+    /// Stop the running computation, its results are no longer posted
+    pub fn cancel(&mut self, shared: &Arc<Mutex<HistogramData>>) {
+        if let Some(cancelled) = self.cancelled.take() {
+            if let Ok(mut data) = shared.lock() {
+                cancelled.store(true, Ordering::Relaxed);
+                data.cpu_progress = None;
+            }
+        }
+    }
+}
+
+/// Count the histogram of the input region. `progress` gets the fraction of rows done and
+/// returns false to stop early.
+fn count(input: &HistogramInput, progress: &mut dyn FnMut(f32) -> bool) -> Option<(Vec<Vec<f32>>, HistogramAxis, [ChannelStats; 3])> {
+    let [x0, y0, x1, y1] = input.region;
+    let bins = input.bins;
+    let mut histograms = vec![vec![0.0f32; bins]; 3]; // RGB channels
+    let mut stats = [ChannelStats::default(); 3];
+    let alpha_mode = input.alpha_mode;
+
+    // Interleaved samples with their channel count, row width and the value of an opaque alpha
+    let converted;
+    let (samples, channels, width, alpha_max, axis): (Samples, usize, u32, f32, HistogramAxis) = if let Some((fp_data, channels, width)) = &input.fp_data {
+        let (min, max) = input.fp_range.unwrap_or_else(|| {
+            fp_data.iter().filter(|v| v.is_finite()).fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &v| (min.min(v), max.max(v)))
+        });
+        (Samples::F32(fp_data), *channels, *width, 1.0, HistogramAxis { min, max, integer: false })
+    } else if let Some(flat) = input.image.as_flat_samples_u16() {
+        // 16-bit data binned over the actual value range of the whole image
+        let channels = flat.layout.channels as usize;
+        let color_channels = if channels <= 2 { 1 } else { 3 };
+        let (min, max) = flat.samples.chunks_exact(channels)
+            .flat_map(|pixel| pixel.iter().take(color_channels))
+            .fold((u16::MAX, u16::MIN), |(min, max), &v| (min.min(v), max.max(v)));
+        (Samples::U16(flat.samples), channels, input.image.width(), 65535.0, HistogramAxis { min: min as f32, max: max.max(min) as f32, integer: true })
+    } else {
+        let flat = match input.image.as_flat_samples_u8() {
+            Some(flat) => flat,
+            None => {
+                converted = DynamicImage::ImageRgba8(input.image.to_rgba8());
+                converted.as_flat_samples_u8()?
+            }
+        };
+        (Samples::U8(flat.samples), flat.layout.channels as usize, input.image.width(), 255.0, HistogramAxis::default())
+    };
+
+    // 8-bit levels are the bins themselves
+    let direct = matches!(samples, Samples::U8(_));
+    let bin_of = |value: f32| {
+        if direct {
+            return value as usize;
+        }
+        let range = axis.max - axis.min;
+        let normalized = if range > f32::EPSILON {
+            ((value - axis.min) / range).clamp(0.0, 1.0)
+        } else {
+            0.5
+        };
+        (normalized * (bins - 1) as f32) as usize
+    };
+
+    let mut band_start = y0;
+    while band_start < y1 {
+        let band_end = (band_start + BAND_ROWS).min(y1);
+        for y in band_start..band_end {
+            let row = y as usize * width as usize;
+            for x in x0..x1 {
+                let base = (row + x as usize) * channels;
+                let pixel = match &samples {
+                    Samples::U8(data) => rgba(data.get(base..base + channels), alpha_max),
+                    Samples::U16(data) => rgba(data.get(base..base + channels), alpha_max),
+                    Samples::F32(data) => rgba(data.get(base..base + channels), alpha_max),
+                };
+                let Some((values, alpha)) = pixel else {
+                    continue;
+                };
+                let weight = alpha_mode.weight(alpha);
+                if weight > 0.0 {
+                    // NaN and infinity have no bin and would spoil the statistics
+                    for channel in (0..3).filter(|&channel| values[channel].is_finite()) {
+                        histograms[channel][bin_of(values[channel])] += weight;
+                        stats[channel].add(values[channel], weight);
+                    }
+                }
+            }
+        }
+        band_start = band_end;
+        if !progress((band_start - y0) as f32 / (y1 - y0) as f32) {
+            return None;
+        }
+    }
+    Some((histograms, axis, stats))
+}
+
+enum Samples<'a> {
+    U8(&'a [u8]),
+    U16(&'a [u16]),
+    F32(&'a [f32]),
+}
+
+/// RGB values and alpha (0..=1) of a pixel, gray is copied to G and B
+fn rgba<T: Copy + Into<f32>>(pixel: Option<&[T]>, alpha_max: f32) -> Option<([f32; 3], f32)> {
+    let pixel = pixel?;
+    let value = |index: usize| -> f32 { pixel[index].into() };
+    let alpha = |index: usize| (value(index) / alpha_max).clamp(0.0, 1.0);
+    match pixel.len() {
+        1 => Some(([value(0); 3], 1.0)),
+        2 => Some(([value(0); 3], alpha(1))),
+        3 => Some(([value(0), value(1), value(2)], 1.0)),
+        4 => Some(([value(0), value(1), value(2)], alpha(3))),
+        _ => None,
+    }
+}
@@ -0,0 +1,86 @@
+//! Recently decoded images kept in memory, so going back to one skips decoding it again.
+//! Entries are keyed by path and modification time; a changed file is decoded anew.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+struct Entry<T> {
+    path: PathBuf,
+    modified: SystemTime,
+    bytes: usize,
+    value: T,
+}
+
+/// Least recently used cache limited to a number of bytes
+pub struct ImageCache<T> {
+    /// Least recently used first
+    entries: VecDeque<Entry<T>>,
+    budget: usize,
+    used: usize,
+}
+
+impl<T> ImageCache<T> {
+    pub fn new(budget: usize) -> Self {
+        Self { entries: VecDeque::new(), budget, used: 0 }
+    }
+
+    /// The cached value of a file that has not changed since it was stored
+    pub fn get(&mut self, path: &Path) -> Option<&T> {
+        let index = self.entries.iter().position(|entry| entry.path == path)?;
+        let entry = self.entries.remove(index)?;
+        if modified(path) != Some(entry.modified) {
+            self.used -= entry.bytes;
+            return None;
+        }
+        self.entries.push_back(entry);
+        self.entries.back().map(|entry| &entry.value)
+    }
+
+    /// Store a decoded file taking `bytes` of memory, dropping the least recently used ones
+    /// to stay within the budget. Values larger than the whole budget are not stored.
+    pub fn insert(&mut self, path: &Path, value: T, bytes: usize) {
+        self.remove(path);
+        let Some(modified) = modified(path) else {
+            return;
+        };
+        if bytes > self.budget {
+            return;
+        }
+        self.entries.push_back(Entry { path: path.to_path_buf(), modified, bytes, value });
+        self.used += bytes;
+        self.evict();
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        if let Some(index) = self.entries.iter().position(|entry| entry.path == path) {
+            if let Some(entry) = self.entries.remove(index) {
+                self.used -= entry.bytes;
+            }
+        }
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict();
+    }
+
+    /// Number of cached images and the bytes they take
+    pub fn usage(&self) -> (usize, usize) {
+        (self.entries.len(), self.used)
+    }
+
+    fn evict(&mut self) {
+        while self.used > self.budget {
+            let Some(entry) = self.entries.pop_front() else {
+                break;
+            };
+            self.used -= entry.bytes;
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
@@ -1,35 +1,141 @@
-use image::{DynamicImage, ImageBuffer, Rgba, Luma};
+use image::{imageops, DynamicImage, GenericImageView, ImageBuffer, Rgba, Luma};
 use rustfft::{FftPlanner, num_complex::Complex};
 use std::f32::consts::PI;
 
-pub fn min_max_normalize(img: &DynamicImage) -> DynamicImage {
+/// RGBA samples in the units of the image: 8-bit or 16-bit levels, or floating point values.
+/// Normalizations read these, so deep images keep their precision until the 8-bit output.
+pub fn rgba_samples(img: &DynamicImage) -> Vec<[f32; 4]> {
+    if img.as_flat_samples_u8().is_some() {
+        img.to_rgba8().pixels().map(|p| p.0.map(|v| v as f32)).collect()
+    } else if img.as_flat_samples_u16().is_some() {
+        img.to_rgba16().pixels().map(|p| p.0.map(|v| v as f32)).collect()
+    } else {
+        img.to_rgba32f().pixels().map(|p| p.0).collect()
+    }
+}
+
+/// RGBA of one pixel in the units of an integer image, 0..=65535 for 16-bit images instead of
+/// their 8-bit conversion. Gray is repeated in the color channels.
+pub fn pixel_values(img: &DynamicImage, x: u32, y: u32) -> [u16; 4] {
+    match img {
+        DynamicImage::ImageLuma16(buffer) => {
+            let [v] = buffer.get_pixel(x, y).0;
+            [v, v, v, u16::MAX]
+        }
+        DynamicImage::ImageLumaA16(buffer) => {
+            let [v, a] = buffer.get_pixel(x, y).0;
+            [v, v, v, a]
+        }
+        DynamicImage::ImageRgb16(buffer) => {
+            let [r, g, b] = buffer.get_pixel(x, y).0;
+            [r, g, b, u16::MAX]
+        }
+        DynamicImage::ImageRgba16(buffer) => buffer.get_pixel(x, y).0,
+        _ => img.get_pixel(x, y).0.map(u16::from),
+    }
+}
+
+/// Pixels of the square of `radius` around (x, y), cut off at the image border
+pub fn sample_window(x: u32, y: u32, radius: u32, width: u32, height: u32) -> impl Iterator<Item = (u32, u32)> {
+    let (x0, x1) = (x.saturating_sub(radius), x.saturating_add(radius).min(width.saturating_sub(1)));
+    let (y0, y1) = (y.saturating_sub(radius), y.saturating_add(radius).min(height.saturating_sub(1)));
+    (y0..=y1).flat_map(move |y| (x0..=x1).map(move |x| (x, y)))
+}
+
+/// Mean of `pixel_values` over the square of `radius` around (x, y)
+pub fn mean_pixel_values(img: &DynamicImage, x: u32, y: u32, radius: u32) -> [f32; 4] {
+    let (mut sum, mut count) = ([0.0f64; 4], 0);
+    for (x, y) in sample_window(x, y, radius, img.width(), img.height()) {
+        for (total, value) in sum.iter_mut().zip(pixel_values(img, x, y)) {
+            *total += value as f64;
+        }
+        count += 1;
+    }
+    sum.map(|total| (total / count.max(1) as f64) as f32)
+}
+
+/// Mean of each channel of interleaved floating point data over the square of `radius` around
+/// (x, y). Non-finite values are left out, a channel without finite values is NaN.
+pub fn mean_fp_values(data: &[f32], width: u32, height: u32, channels: usize, x: u32, y: u32, radius: u32) -> Vec<f32> {
+    let (mut sum, mut count) = (vec![0.0f64; channels], vec![0u32; channels]);
+    for (x, y) in sample_window(x, y, radius, width, height) {
+        let base = (y as usize * width as usize + x as usize) * channels;
+        let Some(pixel) = data.get(base..base + channels) else {
+            continue;
+        };
+        for (channel, &value) in pixel.iter().enumerate().filter(|(_, value)| value.is_finite()) {
+            sum[channel] += value as f64;
+            count[channel] += 1;
+        }
+    }
+    sum.iter().zip(&count).map(|(&total, &count)| if count > 0 { (total / count as f64) as f32 } else { f32::NAN }).collect()
+}
+
+/// Number of pixels of interleaved floating point data with a NaN or infinite color channel;
+/// alpha of 4 channel data is left out
+pub fn non_finite_pixels(data: &[f32], channels: usize) -> usize {
+    let color_channels = if channels == 4 { 3 } else { channels };
+    data.chunks_exact(channels.max(1)).filter(|pixel| pixel[..color_channels].iter().any(|v| !v.is_finite())).count()
+}
+
+/// Floating point image of original sample data with 1, 3 or 4 interleaved channels, resized to
+/// `size` if it differs. Gray data is repeated in the color channels.
+pub fn fp_image(data: &[f32], width: u32, height: u32, channels: u32, size: (u32, u32), filter: imageops::FilterType) -> Option<DynamicImage> {
+    let resized = |img: DynamicImage| if img.dimensions() == size { img } else { img.resize_exact(size.0, size.1, filter) };
+    match channels {
+        1 => {
+            let gray: ImageBuffer<Luma<f32>, &[f32]> = ImageBuffer::from_raw(width, height, data)?;
+            let values = if (width, height) == size { data.to_vec() } else { imageops::resize(&gray, size.0, size.1, filter).into_raw() };
+            let rgb = values.iter().flat_map(|&v| [v; 3]).collect();
+            Some(DynamicImage::ImageRgb32F(ImageBuffer::from_raw(size.0, size.1, rgb)?))
+        }
+        3 => Some(resized(DynamicImage::ImageRgb32F(ImageBuffer::from_raw(width, height, data.to_vec())?))),
+        4 => Some(resized(DynamicImage::ImageRgba32F(ImageBuffer::from_raw(width, height, data.to_vec())?))),
+        _ => None,
+    }
+}
+
+/// Use the widest range of the color channels for all of them, so their balance is kept
+fn joint_range(min_val: &mut [f32; 4], max_val: &mut [f32; 4]) {
+    let min = min_val[..3].iter().fold(f32::INFINITY, |a, &b| a.min(b));
+    let max = max_val[..3].iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b));
+    min_val[..3].fill(min);
+    max_val[..3].fill(max);
+}
+
+/// Stretch the values to 0-255, with the range of each channel or, unless `per_channel`, the
+/// joint range of the color channels
+pub fn min_max_normalize(img: &DynamicImage, per_channel: bool) -> DynamicImage {
+    let samples = rgba_samples(img);
     let rgba = img.to_rgba8();
     let (width, height) = rgba.dimensions();
     
-    // Find min and max values
-    let mut min_val = [u8::MAX; 4];
-    let mut max_val = [u8::MIN; 4];
+    // Find min and max values, NaN and infinity left out
+    let mut min_val = [f32::INFINITY; 4];
+    let mut max_val = [f32::NEG_INFINITY; 4];
     
-    for pixel in rgba.pixels() {
-        for i in 0..4 {
+    for pixel in &samples {
+        for i in (0..4).filter(|&i| pixel[i].is_finite()) {
             min_val[i] = min_val[i].min(pixel[i]);
             max_val[i] = max_val[i].max(pixel[i]);
         }
     }
+    if !per_channel {
+        joint_range(&mut min_val, &mut max_val);
+    }
     
     // Create normalized image
     let mut output = ImageBuffer::new(width, height);
     
-    for (x, y, pixel) in output.enumerate_pixels_mut() {
-        let input_pixel = rgba.get_pixel(x, y);
+    for ((pixel, input_pixel), preview) in output.pixels_mut().zip(&samples).zip(rgba.pixels()) {
         let mut normalized = [0u8; 4];
         
         for i in 0..4 {
             if max_val[i] > min_val[i] {
-                normalized[i] = (((input_pixel[i] as f32 - min_val[i] as f32) / 
-                    (max_val[i] as f32 - min_val[i] as f32)) * 255.0) as u8;
+                normalized[i] = (((input_pixel[i] - min_val[i]) / 
+                    (max_val[i] - min_val[i])) * 255.0) as u8;
             } else {
-                normalized[i] = input_pixel[i];
+                normalized[i] = preview[i];
             }
         }
         
@@ -39,7 +145,44 @@ pub fn min_max_normalize(img: &DynamicImage) -> DynamicImage {
     DynamicImage::ImageRgba8(output)
 }
 
+/// Stretch the values between the `low` and `high` percentiles to 0-255, clipping the rest.
+/// The percentiles are taken per channel or, unless `per_channel`, jointly over the color channels.
+/// Alpha is kept.
+pub fn percentile_normalize(img: &DynamicImage, low: f32, high: f32, per_channel: bool) -> DynamicImage {
+    let samples = rgba_samples(img);
+    let mut rgba = img.to_rgba8();
+    let percentile = |values: &mut Vec<f32>, percent: f32| -> Option<f32> {
+        if values.is_empty() {
+            return None;
+        }
+        let index = ((percent / 100.0).clamp(0.0, 1.0) * (values.len() - 1) as f32).round() as usize;
+        Some(*values.select_nth_unstable_by(index, |a, b| a.total_cmp(b)).1)
+    };
+    let range = |channels: &[usize]| -> Option<(f32, f32)> {
+        let mut values: Vec<f32> = samples.iter()
+            .flat_map(|pixel| channels.iter().map(move |&i| pixel[i]))
+            .filter(|v| v.is_finite())
+            .collect();
+        Some((percentile(&mut values, low)?, percentile(&mut values, high)?))
+    };
+    let ranges = if per_channel {
+        [range(&[0]), range(&[1]), range(&[2])]
+    } else {
+        [range(&[0, 1, 2]); 3]
+    };
+
+    for (pixel, input_pixel) in rgba.pixels_mut().zip(&samples) {
+        for (i, range) in ranges.iter().enumerate() {
+            if let Some((lo, hi)) = range.filter(|(lo, hi)| hi > lo) {
+                pixel[i] = ((input_pixel[i] - lo) / (hi - lo) * 255.0).clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
 pub fn log_min_max_normalize(img: &DynamicImage) -> DynamicImage {
+    let samples = rgba_samples(img);
     let rgba = img.to_rgba8();
     let (width, height) = rgba.dimensions();
     
@@ -47,10 +190,10 @@ pub fn log_min_max_normalize(img: &DynamicImage) -> DynamicImage {
     let mut min_val = [f32::MAX; 4];
     let mut max_val = [f32::MIN; 4];
     
-    for pixel in rgba.pixels() {
+    for pixel in &samples {
         for i in 0..4 {
-            let val = pixel[i] as f32;
-            if val > 0.0 {  // Only consider non-zero values for log
+            let val = pixel[i];
+            if val > 0.0 && val.is_finite() {  // Only consider non-zero values for log
                 let log_val = val.ln();
                 min_val[i] = min_val[i].min(log_val);
                 max_val[i] = max_val[i].max(log_val);
@@ -61,17 +204,16 @@ pub fn log_min_max_normalize(img: &DynamicImage) -> DynamicImage {
     // Create normalized image
     let mut output = ImageBuffer::new(width, height);
     
-    for (x, y, pixel) in output.enumerate_pixels_mut() {
-        let input_pixel = rgba.get_pixel(x, y);
+    for ((pixel, input_pixel), preview) in output.pixels_mut().zip(&samples).zip(rgba.pixels()) {
         let mut normalized = [0u8; 4];
         
         for i in 0..4 {
-            let val = input_pixel[i] as f32;
+            let val = input_pixel[i];
             if val > 0.0 && max_val[i] > min_val[i] {
                 let log_val = val.ln();
                 normalized[i] = (((log_val - min_val[i]) / (max_val[i] - min_val[i])) * 255.0) as u8;
             } else {
-                normalized[i] = input_pixel[i];
+                normalized[i] = preview[i];
             }
         }
         
@@ -81,20 +223,24 @@ pub fn log_min_max_normalize(img: &DynamicImage) -> DynamicImage {
     DynamicImage::ImageRgba8(output)
 }
 
-pub fn standardize(img: &DynamicImage) -> DynamicImage {
+/// Standardize each channel and map one standard deviation to `spread` levels around `center`
+pub fn standardize(img: &DynamicImage, spread: f32, center: f32) -> DynamicImage {
+    let samples = rgba_samples(img);
     let rgba = img.to_rgba8();
     let (width, height) = rgba.dimensions();
     
-    // Calculate mean and standard deviation for each channel
-    let mut sum = [0f32; 4];
-    let mut sum_sq = [0f32; 4];
-    let total_pixels = (width * height) as f32;
+    // Calculate mean and standard deviation for each channel, in double precision so the
+    // variance of deep data with a large mean does not cancel out. NaN and infinity are left out.
+    let mut sum = [0f64; 4];
+    let mut sum_sq = [0f64; 4];
+    let mut count = [0f64; 4];
     
-    for pixel in rgba.pixels() {
-        for i in 0..4 {
-            let val = pixel[i] as f32;
+    for pixel in &samples {
+        for i in (0..4).filter(|&i| pixel[i].is_finite()) {
+            let val = pixel[i] as f64;
             sum[i] += val;
             sum_sq[i] += val * val;
+            count[i] += 1.0;
         }
     }
     
@@ -102,24 +248,24 @@ pub fn standardize(img: &DynamicImage) -> DynamicImage {
     let mut std = [0f32; 4];
     
     for i in 0..4 {
-        mean[i] = sum[i] / total_pixels;
-        let variance = (sum_sq[i] / total_pixels) - (mean[i] * mean[i]);
-        std[i] = variance.sqrt();
+        let channel_mean = sum[i] / count[i].max(1.0);
+        let variance = (sum_sq[i] / count[i].max(1.0)) - (channel_mean * channel_mean);
+        mean[i] = channel_mean as f32;
+        std[i] = variance.max(0.0).sqrt() as f32;
     }
     
     // Create standardized image
     let mut output = ImageBuffer::new(width, height);
     
-    for (x, y, pixel) in output.enumerate_pixels_mut() {
-        let input_pixel = rgba.get_pixel(x, y);
+    for ((pixel, input_pixel), preview) in output.pixels_mut().zip(&samples).zip(rgba.pixels()) {
         let mut standardized = [0u8; 4];
         
         for i in 0..4 {
             if std[i] > 0.0 {
-                let val = ((input_pixel[i] as f32 - mean[i]) / std[i]) * 50.0 + 127.0;
+                let val = ((input_pixel[i] - mean[i]) / std[i]) * spread + center;
                 standardized[i] = val.clamp(0.0, 255.0) as u8;
             } else {
-                standardized[i] = input_pixel[i];
+                standardized[i] = preview[i];
             }
         }
         
@@ -129,7 +275,197 @@ pub fn standardize(img: &DynamicImage) -> DynamicImage {
     DynamicImage::ImageRgba8(output)
 } 
 
-pub fn fft(img: &DynamicImage) -> DynamicImage {
+pub fn fft(img: &DynamicImage, hamming: bool) -> DynamicImage {
+    let input = fft_spectrum(img, hamming);
+    let (width, height) = img.dimensions();
+
+    let mut max_magnitude = 0.0f32;
+    for row in input.iter() {
+        for value in row.iter() {
+            let magnitude = (value.norm() + 1.0).log10(); // Logaritmická škála pro lepší vizualizaci
+            max_magnitude = max_magnitude.max(magnitude);
+        }
+    }
+    
+    let mut fft_image = ImageBuffer::new(width, height);
+    
+    for y in 0..height {
+        for x in 0..width {
+            let nx = (x + width / 2) % width;
+            let ny = (y + height / 2) % height;
+            
+            let magnitude = (input[y as usize][x as usize].norm() + 1.0).log10();
+            let normalized = (magnitude / max_magnitude * 255.0) as u8;
+            
+            fft_image.put_pixel(nx, ny, Luma([normalized]));
+        }
+    }
+    
+    DynamicImage::ImageLuma8(fft_image)
+}
+
+/// Bins of the color samples, 3 per pixel, and the number of bins. 8-bit levels are the bins,
+/// deeper and floating point images are binned over their value range so narrow data keeps its detail.
+fn color_bins(img: &DynamicImage) -> (Vec<u16>, usize) {
+    if img.as_flat_samples_u8().is_some() {
+        let rgb = img.to_rgb8();
+        return (rgb.as_raw().iter().map(|&v| v as u16).collect(), 256);
+    }
+    const BINS: usize = 4096;
+    let rgb = img.to_rgb32f();
+    let (min, max) = rgb.as_raw().iter().filter(|v| v.is_finite()).fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &v| (min.min(v), max.max(v)));
+    let range = (max - min).max(f32::MIN_POSITIVE);
+    let bins = rgb.as_raw().iter().map(|&v| (((v - min) / range).clamp(0.0, 1.0) * (BINS - 1) as f32) as u16).collect();
+    (bins, BINS)
+}
+
+/// Global histogram equalization of each color channel, alpha is kept
+pub fn equalize(img: &DynamicImage) -> DynamicImage {
+    let (bins, count) = color_bins(img);
+    let mut rgba = img.to_rgba8();
+    let pixels = bins.len() / 3;
+    for channel in 0..3 {
+        let mut histogram = vec![0u32; count];
+        for &bin in bins.iter().skip(channel).step_by(3) {
+            histogram[bin as usize] += 1;
+        }
+        // Cumulative counts, the darkest occupied bin maps to 0
+        let mut cdf = vec![0u32; count];
+        let mut total = 0;
+        for (cumulative, &n) in cdf.iter_mut().zip(&histogram) {
+            total += n;
+            *cumulative = total;
+        }
+        let darkest = cdf.iter().copied().find(|&n| n > 0).unwrap_or(0);
+        let span = (pixels as u32).saturating_sub(darkest).max(1) as f32;
+        for (pixel, &bin) in rgba.pixels_mut().zip(bins.iter().skip(channel).step_by(3)) {
+            pixel[channel] = ((cdf[bin as usize] - darkest) as f32 / span * 255.0).round() as u8;
+        }
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Contrast limited adaptive histogram equalization: each of `tiles`×`tiles` tiles is equalized
+/// with its histogram clipped at `clip_limit` times the mean bin count, and the mappings of the
+/// four nearest tiles are blended bilinearly. Alpha is kept.
+pub fn clahe(img: &DynamicImage, tiles: u32, clip_limit: f32) -> DynamicImage {
+    let (bins, count) = color_bins(img);
+    let mut rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    if width == 0 || height == 0 {
+        return DynamicImage::ImageRgba8(rgba);
+    }
+    let (tiles_x, tiles_y) = (tiles.clamp(1, width), tiles.clamp(1, height));
+    let (tile_width, tile_height) = (width.div_ceil(tiles_x), height.div_ceil(tiles_y));
+    let bin_at = |x: u32, y: u32, channel: usize| bins[(y as usize * width as usize + x as usize) * 3 + channel] as usize;
+
+    // Mapping of every tile and channel to output levels, `luts[(tile * 3 + channel) * count + bin]`
+    let mut luts = vec![0.0f32; (tiles_x * tiles_y) as usize * 3 * count];
+    for ty in 0..tiles_y {
+        for tx in 0..tiles_x {
+            let (x0, y0) = (tx * tile_width, ty * tile_height);
+            let (x1, y1) = ((x0 + tile_width).min(width), (y0 + tile_height).min(height));
+            let pixels = ((x1.saturating_sub(x0)) * (y1.saturating_sub(y0))) as f32;
+            if pixels == 0.0 {
+                continue;
+            }
+            let limit = (clip_limit * pixels / count as f32).max(1.0);
+            for channel in 0..3 {
+                let mut histogram = vec![0.0f32; count];
+                for y in y0..y1 {
+                    for x in x0..x1 {
+                        histogram[bin_at(x, y, channel)] += 1.0;
+                    }
+                }
+                // Counts above the limit are spread over all bins
+                let excess: f32 = histogram.iter_mut().map(|n| {
+                    let over = (*n - limit).max(0.0);
+                    *n -= over;
+                    over
+                }).sum();
+                let share = excess / count as f32;
+                let lut = &mut luts[((ty * tiles_x + tx) as usize * 3 + channel) * count..][..count];
+                let mut total = 0.0;
+                for (level, &n) in lut.iter_mut().zip(&histogram) {
+                    total += n + share;
+                    *level = total / pixels * 255.0;
+                }
+            }
+        }
+    }
+
+    // Position between tile centers: the lower tile index and the weight of the upper one
+    let blend = |position: u32, tile_size: u32, tiles: u32| {
+        let t = ((position as f32 + 0.5) / tile_size as f32 - 0.5).clamp(0.0, (tiles - 1) as f32);
+        let lower = (t.floor() as u32).min(tiles - 1);
+        (lower, (lower + 1).min(tiles - 1), t - lower as f32)
+    };
+    for y in 0..height {
+        let (ty0, ty1, wy) = blend(y, tile_height, tiles_y);
+        for x in 0..width {
+            let (tx0, tx1, wx) = blend(x, tile_width, tiles_x);
+            let pixel = rgba.get_pixel_mut(x, y);
+            for channel in 0..3 {
+                let bin = bin_at(x, y, channel);
+                let level = |tx: u32, ty: u32| luts[((ty * tiles_x + tx) as usize * 3 + channel) * count + bin];
+                let top = level(tx0, ty0) * (1.0 - wx) + level(tx1, ty0) * wx;
+                let bottom = level(tx0, ty1) * (1.0 - wx) + level(tx1, ty1) * wx;
+                pixel[channel] = (top * (1.0 - wy) + bottom * wy).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Otsu's threshold of a 256-bin histogram: the level maximizing the variance between the
+/// levels up to it and those above it
+pub fn otsu_level(histogram: &[u32; 256]) -> u8 {
+    let total: f64 = histogram.iter().map(|&n| n as f64).sum();
+    let sum: f64 = histogram.iter().enumerate().map(|(level, &n)| level as f64 * n as f64).sum();
+    let (mut below, mut below_sum) = (0.0, 0.0);
+    let (mut best, mut best_variance) = (0, -1.0);
+    for (level, &n) in histogram.iter().enumerate() {
+        below += n as f64;
+        below_sum += level as f64 * n as f64;
+        let above = total - below;
+        if below == 0.0 || above == 0.0 {
+            continue;
+        }
+        let difference = below_sum / below - (sum - below_sum) / above;
+        let variance = below * above * difference * difference;
+        if variance > best_variance {
+            best_variance = variance;
+            best = level;
+        }
+    }
+    best as u8
+}
+
+/// Pixels brighter than the mean of the surrounding square of `radius` pixels plus `offset`,
+/// row-major. The means come from a summed-area table, so the radius does not affect the cost.
+pub fn adaptive_threshold(luma: &[u8], width: u32, height: u32, radius: u32, offset: f32) -> Vec<bool> {
+    let (w, h) = (width as usize, height as usize);
+    // sums[(y + 1) * (w + 1) + x + 1] is the sum of the pixels above and left of (x, y), inclusive
+    let mut sums = vec![0u64; (w + 1) * (h + 1)];
+    for y in 0..h {
+        let mut row = 0u64;
+        for x in 0..w {
+            row += luma[y * w + x] as u64;
+            sums[(y + 1) * (w + 1) + x + 1] = sums[y * (w + 1) + x + 1] + row;
+        }
+    }
+    let r = radius as usize;
+    (0..w * h).map(|i| {
+        let (x, y) = (i % w, i / w);
+        let (x0, y0, x1, y1) = (x.saturating_sub(r), y.saturating_sub(r), (x + r + 1).min(w), (y + r + 1).min(h));
+        let sum = sums[y1 * (w + 1) + x1] + sums[y0 * (w + 1) + x0] - sums[y0 * (w + 1) + x1] - sums[y1 * (w + 1) + x0];
+        let mean = sum as f32 / ((x1 - x0) * (y1 - y0)) as f32;
+        luma[i] as f32 > mean + offset
+    }).collect()
+}
+
+/// 2D FFT of the grayscale image, `result[y][x]` with the zero frequency at (0, 0)
+pub fn fft_spectrum(img: &DynamicImage, hamming: bool) -> Vec<Vec<Complex<f32>>> {
     let grayscale = img.to_luma8();
     let (width, height) = grayscale.dimensions();
     
@@ -140,7 +476,7 @@ pub fn fft(img: &DynamicImage) -> DynamicImage {
                 .map(|x| {
                     let pixel = grayscale.get_pixel(x, y)[0] as f32;
                     // Aplikujeme váhovací funkci (windowing function) - Hamming window
-                    let window = 0.54 - 0.46 * (2.0 * PI * x as f32 / (width as f32 - 1.0)).cos();
+                    let window = if hamming { hamming_window(x, width) } else { 1.0 };
                     Complex::new(pixel * window, 0.0)
                 })
                 .collect()
@@ -171,28 +507,465 @@ pub fn fft(img: &DynamicImage) -> DynamicImage {
             input[y][x] = transposed[x][y];
         }
     }
+    
+    input
+}
 
-    let mut max_magnitude = 0.0f32;
-    for y in 0..height as usize {
-        for x in 0..width as usize {
-            let magnitude = (input[y][x].norm() + 1.0).log10(); // Logaritmická škála pro lepší vizualizaci
-            max_magnitude = max_magnitude.max(magnitude);
+/// Flatten a spectrum row-major with the zero frequency moved to the center, as displayed
+pub fn fft_shift(spectrum: &[Vec<Complex<f32>>]) -> Vec<Complex<f32>> {
+    let height = spectrum.len();
+    let width = spectrum.first().map_or(0, |row| row.len());
+    let mut shifted = vec![Complex::new(0.0, 0.0); width * height];
+    for (y, row) in spectrum.iter().enumerate() {
+        for (x, value) in row.iter().enumerate() {
+            let nx = (x + width / 2) % width;
+            let ny = (y + height / 2) % height;
+            shifted[ny * width + nx] = *value;
         }
     }
-    
-    let mut fft_image = ImageBuffer::new(width, height);
+    shifted
+}
+
+/// Weight of column `x` in the Hamming window applied before the transform
+fn hamming_window(x: u32, width: u32) -> f32 {
+    0.54 - 0.46 * (2.0 * PI * x as f32 / (width as f32 - 1.0)).cos()
+}
+
+/// In-place 2D FFT of row-major samples, forward or unnormalized inverse
+fn fft_2d(data: &mut [Complex<f32>], width: usize, height: usize, inverse: bool) {
+    let mut planner = FftPlanner::new();
+    let (row_fft, column_fft) = if inverse {
+        (planner.plan_fft_inverse(width), planner.plan_fft_inverse(height))
+    } else {
+        (planner.plan_fft_forward(width), planner.plan_fft_forward(height))
+    };
+    for row in data.chunks_exact_mut(width) {
+        row_fft.process(row);
+    }
+    let mut column = vec![Complex::new(0.0, 0.0); height];
+    for x in 0..width {
+        for (y, value) in column.iter_mut().enumerate() {
+            *value = data[y * width + x];
+        }
+        column_fft.process(&mut column);
+        for (y, value) in column.iter().enumerate() {
+            data[y * width + x] = *value;
+        }
+    }
+}
+
+/// Grayscale image back from a centered spectrum as returned by `fft_shift`. With `hamming`
+/// the window weights of the forward transform are divided out again.
+pub fn inverse_fft(shifted: &[Complex<f32>], width: u32, height: u32, hamming: bool) -> DynamicImage {
+    let (w, h) = (width as usize, height as usize);
+    // Undo the shift
+    let mut data: Vec<Complex<f32>> = (0..w * h)
+        .map(|i| shifted[((i / w + h / 2) % h) * w + (i % w + w / 2) % w])
+        .collect();
+    fft_2d(&mut data, w, h, true);
+
+    // rustfft does not normalize, the round trip scales by the number of samples
+    let scale = (w * h) as f32;
+    let image = ImageBuffer::from_fn(width, height, |x, y| {
+        let window = if hamming { hamming_window(x, width) } else { 1.0 };
+        let value = data[y as usize * w + x as usize].re / scale / window;
+        Luma([value.round().clamp(0.0, 255.0) as u8])
+    });
+    DynamicImage::ImageLuma8(image)
+}
+
+/// Gaussian blur of the color channels, `sigma` in pixels
+pub fn gaussian_blur(img: &DynamicImage, sigma: f32) -> DynamicImage {
+    if sigma <= 0.0 {
+        return img.clone();
+    }
+    DynamicImage::ImageRgba8(imageops::blur(&img.to_rgba8(), sigma))
+}
+
+/// Sharpen by adding `amount` times the difference to a blurred copy, where it is at least `threshold`
+pub fn unsharp_mask(img: &DynamicImage, sigma: f32, amount: f32, threshold: f32) -> DynamicImage {
+    let mut rgba = img.to_rgba8();
+    if sigma <= 0.0 {
+        return DynamicImage::ImageRgba8(rgba);
+    }
+    let blurred = imageops::blur(&rgba, sigma);
+    for (pixel, blurred) in rgba.pixels_mut().zip(blurred.pixels()) {
+        for channel in 0..3 {
+            let difference = pixel[channel] as f32 - blurred[channel] as f32;
+            if difference.abs() >= threshold {
+                pixel[channel] = (pixel[channel] as f32 + amount * difference).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Luma of the image with edge pixels repeated, for 3×3 kernels
+fn luma_at(luma: &ImageBuffer<Luma<u8>, Vec<u8>>, x: i64, y: i64) -> f32 {
+    let x = x.clamp(0, luma.width() as i64 - 1) as u32;
+    let y = y.clamp(0, luma.height() as i64 - 1) as u32;
+    luma.get_pixel(x, y)[0] as f32
+}
+
+/// Gradient magnitude of the luma with the Sobel operator
+pub fn sobel(img: &DynamicImage) -> DynamicImage {
+    let luma = img.to_luma8();
+    let edges = ImageBuffer::from_fn(luma.width(), luma.height(), |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let at = |dx: i64, dy: i64| luma_at(&luma, x + dx, y + dy);
+        let gx = at(1, -1) + 2.0 * at(1, 0) + at(1, 1) - at(-1, -1) - 2.0 * at(-1, 0) - at(-1, 1);
+        let gy = at(-1, 1) + 2.0 * at(0, 1) + at(1, 1) - at(-1, -1) - 2.0 * at(0, -1) - at(1, -1);
+        // The largest possible magnitude is 4·255·√2
+        Luma([(gx.hypot(gy) / 4.0).min(255.0) as u8])
+    });
+    DynamicImage::ImageLuma8(edges)
+}
+
+/// Absolute Laplacian of the luma, 4-neighbour kernel
+pub fn laplacian(img: &DynamicImage) -> DynamicImage {
+    let luma = img.to_luma8();
+    let edges = ImageBuffer::from_fn(luma.width(), luma.height(), |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let at = |dx: i64, dy: i64| luma_at(&luma, x + dx, y + dy);
+        let value = at(-1, 0) + at(1, 0) + at(0, -1) + at(0, 1) - 4.0 * at(0, 0);
+        Luma([value.abs().min(255.0) as u8])
+    });
+    DynamicImage::ImageLuma8(edges)
+}
+
+/// Median of the color channels over a square of `radius` pixels around each pixel. A histogram
+/// per channel slides along the rows, so the cost grows with the radius, not its square.
+pub fn median(img: &DynamicImage, radius: u32) -> DynamicImage {
+    let source = img.to_rgba8();
+    if radius == 0 {
+        return DynamicImage::ImageRgba8(source);
+    }
+    let (width, height) = source.dimensions();
+    let r = radius as i64;
+    let clamp = |value: i64, size: u32| value.clamp(0, size as i64 - 1) as u32;
+    let mut output = source.clone();
+    let half = ((2 * r + 1) * (2 * r + 1) / 2) as u32;
+    for y in 0..height {
+        let mut histograms = [[0u32; 256]; 3];
+        let update = |histograms: &mut [[u32; 256]; 3], x: i64, added: bool| {
+            for dy in -r..=r {
+                let pixel = source.get_pixel(clamp(x, width), clamp(y as i64 + dy, height));
+                for (channel, histogram) in histograms.iter_mut().enumerate() {
+                    let count = &mut histogram[pixel[channel] as usize];
+                    if added { *count += 1 } else { *count -= 1 }
+                }
+            }
+        };
+        for x in -r..=r {
+            update(&mut histograms, x, true);
+        }
+        for x in 0..width {
+            if x > 0 {
+                update(&mut histograms, x as i64 - r - 1, false);
+                update(&mut histograms, x as i64 + r, true);
+            }
+            let pixel = output.get_pixel_mut(x, y);
+            for (channel, histogram) in histograms.iter().enumerate() {
+                let mut seen = 0;
+                let level = histogram.iter().position(|&count| {
+                    seen += count;
+                    seen > half
+                });
+                pixel[channel] = level.unwrap_or(255) as u8;
+            }
+        }
+    }
+    DynamicImage::ImageRgba8(output)
+}
+
+/// Gaussian low- or high-pass in frequency space, per color channel. The cutoff is the
+/// wavelength in pixels where the response drops to half. The high-pass output is centered
+/// on mid gray.
+pub fn frequency_filter(img: &DynamicImage, cutoff_wavelength: f32, high_pass: bool) -> DynamicImage {
+    let mut rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let (w, h) = (width as usize, height as usize);
+    if w == 0 || h == 0 || cutoff_wavelength <= 0.0 {
+        return DynamicImage::ImageRgba8(rgba);
+    }
+    // Half response at the cutoff frequency, in cycles per pixel
+    let cutoff = 1.0 / cutoff_wavelength;
+    let falloff = 2.0f32.ln() / (cutoff * cutoff);
+    // Frequency of an FFT index, the upper half are the negative frequencies
+    let frequency = |index: usize, size: usize| {
+        let index = if index > size / 2 { index as f32 - size as f32 } else { index as f32 };
+        index / size as f32
+    };
+    let response: Vec<f32> = (0..w * h)
+        .map(|i| {
+            let (fx, fy) = (frequency(i % w, w), frequency(i / w, h));
+            let low = (-(fx * fx + fy * fy) * falloff).exp();
+            if high_pass { 1.0 - low } else { low }
+        })
+        .collect();
+
+    let offset = if high_pass { 128.0 } else { 0.0 };
+    let scale = (w * h) as f32;
+    for channel in 0..3 {
+        let mut data: Vec<Complex<f32>> = rgba.pixels().map(|pixel| Complex::new(pixel[channel] as f32, 0.0)).collect();
+        fft_2d(&mut data, w, h, false);
+        for (value, weight) in data.iter_mut().zip(&response) {
+            *value *= weight;
+        }
+        fft_2d(&mut data, w, h, true);
+        for (pixel, value) in rgba.pixels_mut().zip(&data) {
+            pixel[channel] = (value.re / scale + offset).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Luma waveform monitor: for each output column, a 256-bin histogram of Rec.709 luma.
+/// Returns (columns, counts) where counts is laid out as `counts[level * columns + column]`.
+pub fn waveform(img: &DynamicImage, max_columns: u32) -> (u32, Vec<u32>) {
+    let rgb = img.to_rgb8();
+    let (width, height) = rgb.dimensions();
+    let columns = width.clamp(1, max_columns.max(1));
+    let mut counts = vec![0u32; columns as usize * 256];
     
     for y in 0..height {
         for x in 0..width {
-            let nx = (x + width / 2) % width;
-            let ny = (y + height / 2) % height;
-            
-            let magnitude = (input[y as usize][x as usize].norm() + 1.0).log10();
-            let normalized = (magnitude / max_magnitude * 255.0) as u8;
-            
-            fft_image.put_pixel(nx, ny, Luma([normalized]));
+            let p = rgb.get_pixel(x, y);
+            let luma = 0.2126 * p[0] as f32 + 0.7152 * p[1] as f32 + 0.0722 * p[2] as f32;
+            let level = luma.round().clamp(0.0, 255.0) as usize;
+            let column = (x as u64 * columns as u64 / width.max(1) as u64) as usize;
+            counts[level * columns as usize + column] += 1;
         }
     }
     
-    DynamicImage::ImageLuma8(fft_image)
-}
\ No newline at end of file
+    (columns, counts)
+}
+
+/// Vectorscope: 2D histogram of Rec.709 chroma (Cb horizontal, Cr vertical) on a `size`×`size` grid.
+/// The grid center is neutral gray; Cr grows upwards like on a broadcast scope.
+pub fn vectorscope(img: &DynamicImage, size: u32) -> Vec<u32> {
+    let rgb = img.to_rgb8();
+    let size = size.max(2);
+    let mut counts = vec![0u32; (size * size) as usize];
+    
+    for p in rgb.pixels() {
+        let (cb, cr) = chroma_709(p[0], p[1], p[2]);
+        let (gx, gy) = vectorscope_position(cb, cr, size);
+        counts[(gy * size + gx) as usize] += 1;
+    }
+    
+    counts
+}
+
+/// Rec.709 luma of an 8-bit RGB triplet
+pub fn luma_709(r: u8, g: u8, b: u8) -> u8 {
+    (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32).round() as u8
+}
+
+/// Rec.709 (Cb, Cr) of an 8-bit RGB triplet, each in -0.5..=0.5
+pub fn chroma_709(r: u8, g: u8, b: u8) -> (f32, f32) {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    ((b - y) / 1.8556, (r - y) / 1.5748)
+}
+
+/// Grid cell of a chroma value on a vectorscope of the given size
+pub fn vectorscope_position(cb: f32, cr: f32, size: u32) -> (u32, u32) {
+    let max = (size - 1) as f32;
+    let gx = ((cb + 0.5) * max).round().clamp(0.0, max) as u32;
+    let gy = ((0.5 - cr) * max).round().clamp(0.0, max) as u32;
+    (gx, gy)
+}
+
+/// Per-channel absolute difference |A - B| of two images of equal size
+pub fn abs_difference(a: &DynamicImage, b: &DynamicImage) -> DynamicImage {
+    let a = a.to_rgba8();
+    let b = b.to_rgba8();
+    let (width, height) = a.dimensions();
+    
+    let output = ImageBuffer::from_fn(width, height, |x, y| {
+        let (pa, pb) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        Rgba([
+            pa[0].abs_diff(pb[0]),
+            pa[1].abs_diff(pb[1]),
+            pa[2].abs_diff(pb[2]),
+            255,
+        ])
+    });
+    
+    DynamicImage::ImageRgba8(output)
+}
+
+/// Result of comparing two images pixel by pixel
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DiffStats {
+    /// Pixels that differ by more than the tolerance in a selected channel
+    pub differing: u64,
+    pub total: u64,
+    /// Largest difference over the selected channels, in 8-bit levels
+    pub max_difference: u8,
+}
+
+/// Heatmap of |A - B| over the selected RGB channels of two images of equal size.
+/// Differences at or below `tolerance` are black, larger ones run from dark red over
+/// yellow to white at the largest difference.
+pub fn difference_heatmap(a: &DynamicImage, b: &DynamicImage, tolerance: u8, channels: [bool; 3]) -> (DynamicImage, DiffStats) {
+    let a = a.to_rgba8();
+    let b = b.to_rgba8();
+    let (width, height) = a.dimensions();
+    
+    let differences: Vec<u8> = a.pixels().zip(b.pixels()).map(|(pa, pb)| {
+        (0..3).filter(|&c| channels[c]).map(|c| pa[c].abs_diff(pb[c])).max().unwrap_or(0)
+    }).collect();
+    let stats = DiffStats {
+        differing: differences.iter().filter(|&&d| d > tolerance).count() as u64,
+        total: differences.len() as u64,
+        max_difference: differences.iter().copied().max().unwrap_or(0),
+    };
+    
+    let span = stats.max_difference.saturating_sub(tolerance).max(1) as f32;
+    let mut output = ImageBuffer::new(width, height);
+    for (pixel, d) in output.pixels_mut().zip(differences) {
+        *pixel = if d > tolerance {
+            Rgba(heat_color((d - tolerance) as f32 / span))
+        } else {
+            Rgba([0, 0, 0, 255])
+        };
+    }
+    
+    (DynamicImage::ImageRgba8(output), stats)
+}
+
+/// Dark red (0) over red and yellow to white (1)
+fn heat_color(t: f32) -> [u8; 4] {
+    const STOPS: [(f32, [f32; 3]); 4] = [
+        (0.0, [96.0, 0.0, 0.0]),
+        (0.4, [255.0, 0.0, 0.0]),
+        (0.8, [255.0, 255.0, 0.0]),
+        (1.0, [255.0, 255.0, 255.0]),
+    ];
+    let t = t.clamp(0.0, 1.0);
+    let i = STOPS.iter().rposition(|(position, _)| *position <= t).unwrap_or(0).min(STOPS.len() - 2);
+    let ((p0, c0), (p1, c1)) = (STOPS[i], STOPS[i + 1]);
+    let f = (t - p0) / (p1 - p0);
+    [
+        (c0[0] + (c1[0] - c0[0]) * f) as u8,
+        (c0[1] + (c1[1] - c0[1]) * f) as u8,
+        (c0[2] + (c1[2] - c0[2]) * f) as u8,
+        255,
+    ]
+}
+
+/// Signed luma difference A - B on a diverging blue-white-red colormap.
+/// White is no difference, the largest absolute difference maps to full saturation.
+pub fn signed_difference(a: &DynamicImage, b: &DynamicImage) -> DynamicImage {
+    let a = a.to_luma32f();
+    let b = b.to_luma32f();
+    let (width, height) = a.dimensions();
+    
+    let differences: Vec<f32> = a.pixels().zip(b.pixels()).map(|(pa, pb)| pa[0] - pb[0]).collect();
+    let max_abs = differences.iter().fold(0.0f32, |m, d| m.max(d.abs()));
+    
+    let mut output = ImageBuffer::new(width, height);
+    for (pixel, d) in output.pixels_mut().zip(differences) {
+        let t = if max_abs > 0.0 { d / max_abs } else { 0.0 };
+        *pixel = Rgba(diverging_color(t));
+    }
+    
+    DynamicImage::ImageRgba8(output)
+}
+
+/// Blue (-1) to white (0) to red (+1)
+pub fn diverging_color(t: f32) -> [u8; 4] {
+    let t = t.clamp(-1.0, 1.0);
+    let (r, g, b) = if t < 0.0 {
+        (1.0 + t, 1.0 + t, 1.0)
+    } else {
+        (1.0, 1.0 - t, 1.0 - t)
+    };
+    [(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8, 255]
+}
+
+/// Full-reference quality of B against A
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QualityMetrics {
+    /// Peak signal-to-noise ratio over RGB in dB, infinite for identical images
+    pub psnr: f64,
+    /// Mean structural similarity of the luma, 1 for identical images
+    pub ssim: f64,
+    /// Mean absolute difference over RGB in 8-bit levels
+    pub mae: f64,
+}
+
+/// PSNR, SSIM and mean absolute error of two images of equal size. Samples are compared in the
+/// 0 to 1 range of their type, so 8-bit, 16-bit and floating point images share the scale;
+/// alpha is ignored.
+pub fn quality_metrics(a: &DynamicImage, b: &DynamicImage) -> QualityMetrics {
+    let (rgb_a, rgb_b) = (a.to_rgb32f(), b.to_rgb32f());
+    let count = rgb_a.as_raw().len().max(1) as f64;
+    let (abs_sum, square_sum) = rgb_a.as_raw().iter().zip(rgb_b.as_raw()).fold((0.0f64, 0.0f64), |(abs_sum, square_sum), (&va, &vb)| {
+        let d = (va - vb) as f64;
+        (abs_sum + d.abs(), square_sum + d * d)
+    });
+    let mse = square_sum / count;
+    let psnr = if mse > 0.0 { -10.0 * mse.log10() } else { f64::INFINITY };
+
+    let (width, height) = a.dimensions();
+    let (luma_a, luma_b) = (a.to_luma32f().into_raw(), b.to_luma32f().into_raw());
+    QualityMetrics { psnr, ssim: mean_ssim(&luma_a, &luma_b, width, height), mae: abs_sum / count * 255.0 }
+}
+
+/// Mean SSIM of two luma planes with the 11×11 Gaussian window (σ = 1.5) and constants of
+/// Wang et al. for a dynamic range of 1. The window is clamped at the image edges.
+fn mean_ssim(a: &[f32], b: &[f32], width: u32, height: u32) -> f64 {
+    const C1: f32 = 0.01 * 0.01;
+    const C2: f32 = 0.03 * 0.03;
+    if a.is_empty() {
+        return 1.0;
+    }
+    let mut kernel = [0.0f32; 11];
+    for (i, weight) in kernel.iter_mut().enumerate() {
+        let x = i as f32 - 5.0;
+        *weight = (-x * x / (2.0 * 1.5 * 1.5)).exp();
+    }
+    let total: f32 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|weight| *weight /= total);
+
+    let blur = |data: &[f32]| window_mean(data, width as usize, height as usize, &kernel);
+    let (mean_a, mean_b) = (blur(a), blur(b));
+    let product = |x: &[f32], y: &[f32]| x.iter().zip(y).map(|(vx, vy)| vx * vy).collect::<Vec<f32>>();
+    let (square_a, square_b, cross) = (blur(&product(a, a)), blur(&product(b, b)), blur(&product(a, b)));
+
+    let sum: f64 = (0..a.len()).map(|i| {
+        let (mu_a, mu_b) = (mean_a[i], mean_b[i]);
+        let variance_a = square_a[i] - mu_a * mu_a;
+        let variance_b = square_b[i] - mu_b * mu_b;
+        let covariance = cross[i] - mu_a * mu_b;
+        (((2.0 * mu_a * mu_b + C1) * (2.0 * covariance + C2))
+            / ((mu_a * mu_a + mu_b * mu_b + C1) * (variance_a + variance_b + C2))) as f64
+    }).sum();
+    sum / a.len() as f64
+}
+
+/// Separable weighted mean of a plane, rows first, with coordinates clamped at the edges
+fn window_mean(data: &[f32], width: usize, height: usize, kernel: &[f32]) -> Vec<f32> {
+    let radius = kernel.len() as isize / 2;
+    let clamp = |v: isize, len: usize| v.clamp(0, len as isize - 1) as usize;
+    let mut rows = vec![0.0f32; data.len()];
+    for y in 0..height {
+        for x in 0..width {
+            rows[y * width + x] = kernel.iter().enumerate()
+                .map(|(i, weight)| weight * data[y * width + clamp(x as isize + i as isize - radius, width)])
+                .sum();
+        }
+    }
+    let mut output = vec![0.0f32; data.len()];
+    for y in 0..height {
+        for x in 0..width {
+            output[y * width + x] = kernel.iter().enumerate()
+                .map(|(i, weight)| weight * rows[clamp(y as isize + i as isize - radius, height) * width + x])
+                .sum();
+        }
+    }
+    output
+}
@@ -0,0 +1,194 @@
+//! Single-instance mode: a viewer launched with a path while another one runs hands the path
+//! to the running viewer over a loopback socket and exits. The running viewer publishes its
+//! port and a random token in a file only the user can read; requests without the token are
+//! ignored.
+
+use log::{info, warn};
+use std::collections::hash_map::RandomState;
+use std::ffi::OsString;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+const TIMEOUT: Duration = Duration::from_millis(500);
+/// Largest accepted request, a token and a path
+const MAX_REQUEST: u64 = 64 * 1024;
+
+/// File with the port and token of the running viewer
+fn endpoint_file() -> Option<PathBuf> {
+    dirs::runtime_dir()
+        .or_else(dirs::cache_dir)
+        .map(|dir| dir.join("image_viewer").join("instance"))
+}
+
+/// Hand `path` to a running viewer. Returns whether one accepted it.
+pub fn forward_to_running(path: &Path) -> bool {
+    let Some(file) = endpoint_file() else {
+        return false;
+    };
+    let Some((port, token)) = fs::read_to_string(&file).ok().and_then(|text| {
+        let (port, token) = text.trim().split_once(' ')?;
+        Some((port.parse::<u16>().ok()?, token.to_string()))
+    }) else {
+        return false;
+    };
+    // Absolute, the running viewer has another working directory
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let send = || -> io::Result<bool> {
+        let mut stream = TcpStream::connect_timeout(&SocketAddr::from((Ipv4Addr::LOCALHOST, port)), TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.write_all(format!("{}\n", token).as_bytes())?;
+        stream.write_all(&encode_path(path.as_os_str()))?;
+        stream.shutdown(std::net::Shutdown::Write)?;
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply)?;
+        Ok(reply.trim() == "ok")
+    };
+    match send() {
+        Ok(accepted) => accepted,
+        Err(e) => {
+            // Left behind by a viewer that did not exit cleanly
+            info!("No running viewer at port {}: {}", port, e);
+            false
+        }
+    }
+}
+
+/// Receives paths from viewers launched while this one runs
+pub struct InstanceServer {
+    token: String,
+    paths: Receiver<PathBuf>,
+}
+
+impl InstanceServer {
+    pub fn start(ctx: &egui::Context) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let port = listener.local_addr()?.port();
+        let token = random_token();
+        let file = endpoint_file().ok_or_else(|| io::Error::other("No runtime directory"))?;
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_private(&file, format!("{} {}", port, token).as_bytes())?;
+        info!("Accepting paths from other launches on port {}", port);
+
+        let (tx, paths) = channel();
+        let ctx = ctx.clone();
+        let expected = token.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                match read_request(stream, &expected) {
+                    Ok(Some(path)) => {
+                        if tx.send(path).is_err() {
+                            break;
+                        }
+                        ctx.request_repaint();
+                    }
+                    Ok(None) => warn!("Ignored a request with a wrong token"),
+                    Err(e) => warn!("Reading a request from another launch failed: {}", e),
+                }
+            }
+        });
+        Ok(Self { token, paths })
+    }
+
+    /// Next path sent by another launch
+    pub fn poll(&self) -> Option<PathBuf> {
+        self.paths.try_recv().ok()
+    }
+}
+
+impl Drop for InstanceServer {
+    fn drop(&mut self) {
+        // Only remove the file if a later viewer did not take it over
+        if let Some(file) = endpoint_file() {
+            if fs::read_to_string(&file).is_ok_and(|text| text.trim().ends_with(&self.token)) {
+                let _ = fs::remove_file(file);
+            }
+        }
+    }
+}
+
+/// The path of a request with the right token, `None` for other tokens
+fn read_request(mut stream: TcpStream, token: &str) -> io::Result<Option<PathBuf>> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new((&stream).take(MAX_REQUEST));
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim_end() != token {
+        return Ok(None);
+    }
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let path = PathBuf::from(decode_path(&bytes)?);
+    stream.write_all(b"ok\n")?;
+    Ok(Some(path))
+}
+
+/// Token that other users cannot guess, from the randomly seeded std hasher
+fn random_token() -> String {
+    (0..2)
+        .map(|_| {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u128(SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos());
+            hasher.write_u32(std::process::id());
+            format!("{:016x}", hasher.finish())
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn write_private(file: &Path, contents: &[u8]) -> io::Result<()> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    let mut out = fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(file)?;
+    // The mode only applies to new files, tighten one left behind by an older version
+    out.set_permissions(fs::Permissions::from_mode(0o600))?;
+    out.write_all(contents)
+}
+
+#[cfg(not(unix))]
+fn write_private(file: &Path, contents: &[u8]) -> io::Result<()> {
+    // The file is in the user's profile, which other users cannot read
+    fs::write(file, contents)
+}
+
+/// Path bytes that survive names that are not valid Unicode
+#[cfg(unix)]
+fn encode_path(path: &std::ffi::OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_bytes().to_vec()
+}
+
+#[cfg(unix)]
+fn decode_path(bytes: &[u8]) -> io::Result<OsString> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(std::ffi::OsStr::from_bytes(bytes).to_os_string())
+}
+
+#[cfg(windows)]
+fn encode_path(path: &std::ffi::OsStr) -> Vec<u8> {
+    use std::os::windows::ffi::OsStrExt;
+    path.encode_wide().flat_map(u16::to_le_bytes).collect()
+}
+
+#[cfg(windows)]
+fn decode_path(bytes: &[u8]) -> io::Result<OsString> {
+    use std::os::windows::ffi::OsStringExt;
+    let wide: Vec<u16> = bytes.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]])).collect();
+    Ok(OsString::from_wide(&wide))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn encode_path(path: &std::ffi::OsStr) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn decode_path(bytes: &[u8]) -> io::Result<OsString> {
+    String::from_utf8(bytes.to_vec()).map(OsString::from).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
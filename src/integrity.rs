@@ -0,0 +1,246 @@
+use anyhow::Context;
+use image::DynamicImage;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+/// Function used by the hashing thread to decode an image
+pub type PixelLoader = fn(&Path) -> anyhow::Result<DecodedPixels>;
+
+/// Samples of a decoded image, interleaved
+pub enum Samples {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    F32(Vec<f32>),
+}
+
+/// Pixel data as the viewer decodes it, before any edits or display processing
+pub struct DecodedPixels {
+    pub width: u32,
+    pub height: u32,
+    pub channels: u32,
+    pub samples: Samples,
+}
+
+impl DecodedPixels {
+    pub fn from_image(image: DynamicImage) -> Self {
+        let (width, height, channels) = (image.width(), image.height(), image.color().channel_count() as u32);
+        let samples = match image {
+            DynamicImage::ImageLuma8(_) | DynamicImage::ImageLumaA8(_) | DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgba8(_) => Samples::U8(image.into_bytes()),
+            DynamicImage::ImageLuma16(_) | DynamicImage::ImageLumaA16(_) | DynamicImage::ImageRgb16(_) | DynamicImage::ImageRgba16(_) => {
+                Samples::U16(image.as_bytes().chunks_exact(2).map(|b| u16::from_ne_bytes([b[0], b[1]])).collect())
+            }
+            image => Samples::F32(image.as_bytes().chunks_exact(4).map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]])).collect()),
+        };
+        Self { width, height, channels, samples }
+    }
+
+    /// Layout such as "4000×3000, 3 × 16-bit"
+    pub fn description(&self) -> String {
+        let sample = match self.samples {
+            Samples::U8(_) => "8-bit",
+            Samples::U16(_) => "16-bit",
+            Samples::F32(_) => "32-bit float",
+        };
+        format!("{}×{}, {} × {}", self.width, self.height, self.channels, sample)
+    }
+
+    /// Samples as little-endian bytes, the input of the pixel hashes
+    fn update(&self, digest: &mut impl Digest) {
+        match &self.samples {
+            Samples::U8(samples) => digest.update(samples),
+            Samples::U16(samples) => samples.chunks(1 << 16).for_each(|chunk| digest.update(chunk.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<u8>>())),
+            Samples::F32(samples) => samples.chunks(1 << 16).for_each(|chunk| digest.update(chunk.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<u8>>())),
+        }
+    }
+}
+
+/// MD5 and SHA-256 as lowercase hex
+#[derive(Clone)]
+pub struct Digests {
+    pub md5: String,
+    pub sha256: String,
+}
+
+impl Digests {
+    fn finish(md5: Md5, sha256: Sha256) -> Self {
+        Self { md5: hex(&md5.finalize()), sha256: hex(&sha256.finalize()) }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Hashes of a file and of its decoded pixels
+pub struct IntegrityInfo {
+    pub file_size: u64,
+    pub file: Digests,
+    /// Layout and hashes of the decoded pixels, or why the file could not be decoded
+    pub pixels: Result<(String, Digests), String>,
+}
+
+/// Result of a bit-exact comparison of the decoded pixels of two files
+pub enum CompareOutcome {
+    Identical,
+    DifferentSize((u32, u32), (u32, u32)),
+    DifferentLayout(String, String),
+    /// Number of differing samples, the first differing pixel and the largest difference
+    Different { samples: u64, first: (u32, u32), max_difference: f64 },
+}
+
+impl CompareOutcome {
+    pub fn is_identical(&self) -> bool {
+        matches!(self, CompareOutcome::Identical)
+    }
+
+    pub fn to_text(&self) -> String {
+        match self {
+            CompareOutcome::Identical => "Decoded pixels are bit-exact identical".to_string(),
+            CompareOutcome::DifferentSize(a, b) => format!("Sizes differ: {}×{} vs {}×{}", a.0, a.1, b.0, b.1),
+            CompareOutcome::DifferentLayout(a, b) => format!("Sample layouts differ: {} vs {}", a, b),
+            CompareOutcome::Different { samples, first, max_difference } => format!(
+                "{} samples differ, first at ({}, {}), largest difference {}",
+                samples, first.0, first.1, max_difference
+            ),
+        }
+    }
+}
+
+/// Hash a file and its decoded pixels
+pub fn integrity_info(path: &Path, loader: PixelLoader) -> anyhow::Result<IntegrityInfo> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let (mut md5, mut sha256) = (Md5::new(), Sha256::new());
+    let mut buffer = vec![0; 1 << 20];
+    let mut file_size = 0;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        md5.update(&buffer[..read]);
+        sha256.update(&buffer[..read]);
+        file_size += read as u64;
+    }
+
+    let pixels = loader(path).map(|pixels| {
+        let (mut md5, mut sha256) = (Md5::new(), Sha256::new());
+        pixels.update(&mut md5);
+        pixels.update(&mut sha256);
+        (pixels.description(), Digests::finish(md5, sha256))
+    });
+    Ok(IntegrityInfo { file_size, file: Digests::finish(md5, sha256), pixels: pixels.map_err(|e| e.to_string()) })
+}
+
+/// Compare the decoded pixels of two files sample by sample. Floats compare by their bits.
+pub fn compare_pixels(a: &Path, b: &Path, loader: PixelLoader) -> anyhow::Result<CompareOutcome> {
+    let a = loader(a).with_context(|| format!("Failed to decode {:?}", a))?;
+    let b = loader(b).with_context(|| format!("Failed to decode {:?}", b))?;
+    if (a.width, a.height) != (b.width, b.height) {
+        return Ok(CompareOutcome::DifferentSize((a.width, a.height), (b.width, b.height)));
+    }
+    let differences: Box<dyn Iterator<Item = (usize, f64)>> = match (&a.samples, &b.samples) {
+        _ if a.channels != b.channels => return Ok(CompareOutcome::DifferentLayout(a.description(), b.description())),
+        (Samples::U8(a), Samples::U8(b)) => Box::new(a.iter().zip(b).enumerate().filter(|(_, (a, b))| a != b).map(|(i, (&a, &b))| (i, (a as f64 - b as f64).abs()))),
+        (Samples::U16(a), Samples::U16(b)) => Box::new(a.iter().zip(b).enumerate().filter(|(_, (a, b))| a != b).map(|(i, (&a, &b))| (i, (a as f64 - b as f64).abs()))),
+        (Samples::F32(a), Samples::F32(b)) => Box::new(a.iter().zip(b).enumerate().filter(|(_, (a, b))| a.to_bits() != b.to_bits()).map(|(i, (&a, &b))| (i, (a as f64 - b as f64).abs()))),
+        _ => return Ok(CompareOutcome::DifferentLayout(a.description(), b.description())),
+    };
+
+    let (mut samples, mut first, mut max_difference) = (0, None, 0.0f64);
+    for (index, difference) in differences {
+        samples += 1;
+        first.get_or_insert(index);
+        // NaN differences (bit patterns differ but one is NaN) do not change the maximum
+        max_difference = max_difference.max(difference);
+    }
+    Ok(match first {
+        None => CompareOutcome::Identical,
+        Some(index) => {
+            let pixel = (index / a.channels as usize) as u64;
+            let first = ((pixel % a.width as u64) as u32, (pixel / a.width as u64) as u32);
+            CompareOutcome::Different { samples, first, max_difference }
+        }
+    })
+}
+
+/// Hashes of the open image and the last bit-exact comparison, computed on background threads
+#[derive(Default)]
+pub struct IntegrityState {
+    path: Option<PathBuf>,
+    info: Option<Result<IntegrityInfo, String>>,
+    info_rx: Option<Receiver<anyhow::Result<IntegrityInfo>>>,
+    compare: Option<(PathBuf, Result<CompareOutcome, String>)>,
+    compare_rx: Option<(PathBuf, Receiver<anyhow::Result<CompareOutcome>>)>,
+}
+
+impl IntegrityState {
+    /// Start hashing `path` unless its hashes are known or being computed
+    pub fn request(&mut self, ctx: &egui::Context, path: &Path, loader: PixelLoader) {
+        if self.path.as_deref() == Some(path) {
+            return;
+        }
+        self.path = Some(path.to_path_buf());
+        self.info = None;
+        self.compare = None;
+        self.compare_rx = None;
+        let path = path.to_path_buf();
+        self.info_rx = Some(spawn(ctx, move || integrity_info(&path, loader)));
+    }
+
+    /// Forget the hashes, e.g. after the file was saved over
+    pub fn invalidate(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn compare_with(&mut self, ctx: &egui::Context, other: PathBuf, loader: PixelLoader) {
+        let Some(path) = self.path.clone() else {
+            return;
+        };
+        self.compare = None;
+        let thread_other = other.clone();
+        self.compare_rx = Some((other, spawn(ctx, move || compare_pixels(&path, &thread_other, loader))));
+    }
+
+    /// Take finished results from the threads
+    pub fn poll(&mut self) {
+        if let Some(result) = self.info_rx.as_ref().and_then(|rx| rx.try_recv().ok()) {
+            self.info = Some(result.map_err(|e| e.to_string()));
+            self.info_rx = None;
+        }
+        if let Some(result) = self.compare_rx.as_ref().and_then(|(_, rx)| rx.try_recv().ok()) {
+            if let Some((other, _)) = self.compare_rx.take() {
+                self.compare = Some((other, result.map_err(|e| format!("{:#}", e))));
+            }
+        }
+    }
+
+    /// Hashes of the open file, `None` while they are computed
+    pub fn info(&self) -> Option<&Result<IntegrityInfo, String>> {
+        self.info.as_ref()
+    }
+
+    /// File being compared against, while the comparison runs
+    pub fn comparing(&self) -> Option<&Path> {
+        self.compare_rx.as_ref().map(|(other, _)| other.as_path())
+    }
+
+    pub fn compare_result(&self) -> Option<&(PathBuf, Result<CompareOutcome, String>)> {
+        self.compare.as_ref()
+    }
+}
+
+/// Run `f` on a new thread and repaint when it is done
+fn spawn<T: Send + 'static>(ctx: &egui::Context, f: impl FnOnce() -> T + Send + 'static) -> Receiver<T> {
+    let (tx, rx) = channel();
+    let ctx = ctx.clone();
+    thread::spawn(move || {
+        let _ = tx.send(f());
+        ctx.request_repaint();
+    });
+    rx
+}
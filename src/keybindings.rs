@@ -0,0 +1,328 @@
+use anyhow::{anyhow, bail};
+use egui::{Key, Modifiers};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Actions that can be triggered from the keyboard
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum Action {
+    NextImage,
+    PreviousImage,
+    FirstImage,
+    LastImage,
+    NextFrame,
+    PreviousFrame,
+    ZoomIn,
+    ZoomOut,
+    ResetZoom,
+    PanLeft,
+    PanRight,
+    PanUp,
+    PanDown,
+    FitToWindow,
+    FillWindow,
+    ActualSize,
+    RotateCw,
+    RotateCcw,
+    FlipHorizontal,
+    FlipVertical,
+    ToggleHistogram,
+    TogglePixelTool,
+    BlinkCompare,
+    NextTab,
+    PreviousTab,
+    DeleteFile,
+    MoveFile,
+    RenameFile,
+    ShowInFolder,
+    OpenInEditor,
+    Rate0,
+    Rate1,
+    Rate2,
+    Rate3,
+    Rate4,
+    Rate5,
+    LabelRed,
+    LabelYellow,
+    LabelGreen,
+    LabelBlue,
+    ToggleLoupe,
+    ToggleInfo,
+    Undo,
+    Redo,
+    ToggleGallery,
+}
+
+impl Action {
+    pub const ALL: [Action; 45] = [
+        Action::NextImage,
+        Action::PreviousImage,
+        Action::FirstImage,
+        Action::LastImage,
+        Action::NextFrame,
+        Action::PreviousFrame,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::ResetZoom,
+        Action::PanLeft,
+        Action::PanRight,
+        Action::PanUp,
+        Action::PanDown,
+        Action::FitToWindow,
+        Action::FillWindow,
+        Action::ActualSize,
+        Action::RotateCw,
+        Action::RotateCcw,
+        Action::FlipHorizontal,
+        Action::FlipVertical,
+        Action::ToggleHistogram,
+        Action::TogglePixelTool,
+        Action::BlinkCompare,
+        Action::NextTab,
+        Action::PreviousTab,
+        Action::DeleteFile,
+        Action::MoveFile,
+        Action::RenameFile,
+        Action::ShowInFolder,
+        Action::OpenInEditor,
+        Action::Rate0,
+        Action::Rate1,
+        Action::Rate2,
+        Action::Rate3,
+        Action::Rate4,
+        Action::Rate5,
+        Action::LabelRed,
+        Action::LabelYellow,
+        Action::LabelGreen,
+        Action::LabelBlue,
+        Action::ToggleLoupe,
+        Action::ToggleInfo,
+        Action::Undo,
+        Action::Redo,
+        Action::ToggleGallery,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::NextImage => "Next image",
+            Action::PreviousImage => "Previous image",
+            Action::FirstImage => "First image",
+            Action::LastImage => "Last image",
+            Action::NextFrame => "Next frame / page",
+            Action::PreviousFrame => "Previous frame / page",
+            Action::ZoomIn => "Zoom in",
+            Action::ZoomOut => "Zoom out",
+            Action::ResetZoom => "Reset zoom",
+            Action::PanLeft => "Pan left",
+            Action::PanRight => "Pan right",
+            Action::PanUp => "Pan up",
+            Action::PanDown => "Pan down",
+            Action::FitToWindow => "Fit to window",
+            Action::FillWindow => "Fill window",
+            Action::ActualSize => "Actual size (100%)",
+            Action::RotateCw => "Rotate clockwise",
+            Action::RotateCcw => "Rotate counter-clockwise",
+            Action::FlipHorizontal => "Flip horizontally",
+            Action::FlipVertical => "Flip vertically",
+            Action::ToggleHistogram => "Toggle analysis window",
+            Action::TogglePixelTool => "Toggle pixel info",
+            Action::BlinkCompare => "Blink A/B in compare mode",
+            Action::NextTab => "Next tab",
+            Action::PreviousTab => "Previous tab",
+            Action::DeleteFile => "Move file to trash",
+            Action::MoveFile => "Move file to folder",
+            Action::RenameFile => "Rename file",
+            Action::ShowInFolder => "Show in folder",
+            Action::OpenInEditor => "Open in external editor",
+            Action::Rate0 => "Clear rating",
+            Action::Rate1 => "Rate 1 star",
+            Action::Rate2 => "Rate 2 stars",
+            Action::Rate3 => "Rate 3 stars",
+            Action::Rate4 => "Rate 4 stars",
+            Action::Rate5 => "Rate 5 stars",
+            Action::LabelRed => "Toggle red label",
+            Action::LabelYellow => "Toggle yellow label",
+            Action::LabelGreen => "Toggle green label",
+            Action::LabelBlue => "Toggle blue label",
+            Action::ToggleLoupe => "Toggle magnifier loupe",
+            Action::ToggleInfo => "Toggle image info",
+            Action::Undo => "Undo edit",
+            Action::Redo => "Redo edit",
+            Action::ToggleGallery => "Toggle gallery view",
+        }
+    }
+
+    pub fn default_binding(&self) -> KeyBinding {
+        let key = |key| KeyBinding { key, command: false, shift: false, alt: false };
+        match self {
+            Action::NextImage => key(Key::ArrowRight),
+            Action::PreviousImage => key(Key::ArrowLeft),
+            Action::FirstImage => key(Key::Home),
+            Action::LastImage => key(Key::End),
+            Action::NextFrame => key(Key::Period),
+            Action::PreviousFrame => key(Key::Comma),
+            Action::ZoomIn => key(Key::Plus),
+            Action::ZoomOut => key(Key::Minus),
+            Action::ResetZoom => key(Key::Num0),
+            Action::PanLeft => KeyBinding { shift: true, ..key(Key::ArrowLeft) },
+            Action::PanRight => KeyBinding { shift: true, ..key(Key::ArrowRight) },
+            Action::PanUp => KeyBinding { shift: true, ..key(Key::ArrowUp) },
+            Action::PanDown => KeyBinding { shift: true, ..key(Key::ArrowDown) },
+            Action::FitToWindow => key(Key::F),
+            Action::FillWindow => KeyBinding { shift: true, ..key(Key::F) },
+            Action::ActualSize => key(Key::Num1),
+            Action::RotateCw => key(Key::R),
+            Action::RotateCcw => KeyBinding { shift: true, ..key(Key::R) },
+            Action::FlipHorizontal => key(Key::H),
+            Action::FlipVertical => key(Key::V),
+            Action::ToggleHistogram => key(Key::A),
+            Action::TogglePixelTool => key(Key::P),
+            Action::BlinkCompare => key(Key::B),
+            Action::NextTab => KeyBinding { command: true, ..key(Key::Tab) },
+            Action::PreviousTab => KeyBinding { command: true, shift: true, ..key(Key::Tab) },
+            Action::DeleteFile => key(Key::Delete),
+            Action::MoveFile => key(Key::M),
+            Action::RenameFile => key(Key::F2),
+            Action::ShowInFolder => KeyBinding { command: true, shift: true, ..key(Key::E) },
+            Action::OpenInEditor => key(Key::E),
+            Action::Rate0 => KeyBinding { command: true, ..key(Key::Num0) },
+            Action::Rate1 => KeyBinding { command: true, ..key(Key::Num1) },
+            Action::Rate2 => KeyBinding { command: true, ..key(Key::Num2) },
+            Action::Rate3 => KeyBinding { command: true, ..key(Key::Num3) },
+            Action::Rate4 => KeyBinding { command: true, ..key(Key::Num4) },
+            Action::Rate5 => KeyBinding { command: true, ..key(Key::Num5) },
+            Action::LabelRed => KeyBinding { command: true, ..key(Key::Num6) },
+            Action::LabelYellow => KeyBinding { command: true, ..key(Key::Num7) },
+            Action::LabelGreen => KeyBinding { command: true, ..key(Key::Num8) },
+            Action::LabelBlue => KeyBinding { command: true, ..key(Key::Num9) },
+            Action::ToggleLoupe => key(Key::L),
+            Action::ToggleInfo => key(Key::I),
+            Action::Undo => KeyBinding { command: true, ..key(Key::Z) },
+            Action::Redo => KeyBinding { command: true, shift: true, ..key(Key::Z) },
+            Action::ToggleGallery => key(Key::G),
+        }
+    }
+}
+
+/// A key with the modifiers that have to be held, stored as text like `Ctrl+Shift+R`
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub struct KeyBinding {
+    pub key: Key,
+    /// Ctrl, or Cmd on macOS
+    pub command: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl KeyBinding {
+    pub fn new(key: Key, modifiers: Modifiers) -> Self {
+        Self { key, command: modifiers.command, shift: modifiers.shift, alt: modifiers.alt }
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.command {
+            write!(f, "{}+", if cfg!(target_os = "macos") { "Cmd" } else { "Ctrl" })?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        write!(f, "{}", self.key.name())
+    }
+}
+
+impl TryFrom<String> for KeyBinding {
+    type Error = anyhow::Error;
+
+    fn try_from(text: String) -> anyhow::Result<Self> {
+        // The last part is the key, so "Ctrl++" is not supported, "Ctrl+Plus" is
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let key_name = parts.pop().unwrap_or_default();
+        let key = Key::from_name(key_name).ok_or_else(|| anyhow!("Unknown key {:?} in shortcut {:?}", key_name, text))?;
+        let mut binding = Self { key, command: false, shift: false, alt: false };
+        for modifier in parts {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "cmd" | "command" => binding.command = true,
+                "shift" => binding.shift = true,
+                "alt" | "option" => binding.alt = true,
+                _ => bail!("Unknown modifier {:?} in shortcut {:?}", modifier, text),
+            }
+        }
+        Ok(binding)
+    }
+}
+
+impl From<KeyBinding> for String {
+    fn from(binding: KeyBinding) -> Self {
+        binding.to_string()
+    }
+}
+
+/// Keyboard shortcuts, stored in the config file. Actions missing from the file use their default.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(transparent)]
+pub struct Keybindings {
+    bindings: BTreeMap<Action, KeyBinding>,
+}
+
+impl Keybindings {
+    pub fn get(&self, action: Action) -> KeyBinding {
+        self.bindings.get(&action).copied().unwrap_or_else(|| action.default_binding())
+    }
+
+    pub fn set(&mut self, action: Action, binding: KeyBinding) {
+        if binding == action.default_binding() {
+            self.bindings.remove(&action);
+        } else {
+            self.bindings.insert(action, binding);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.bindings.clear();
+    }
+
+    pub fn is_default(&self, action: Action) -> bool {
+        !self.bindings.contains_key(&action)
+    }
+
+    /// Other action that uses the same shortcut
+    pub fn conflict(&self, action: Action) -> Option<Action> {
+        let binding = self.get(action);
+        Action::ALL.into_iter().find(|&other| other != action && self.get(other) == binding)
+    }
+
+    /// Action bound to a key press. Shift is ignored when no shortcut uses it, so "+" still
+    /// matches on layouts where it needs Shift. Ctrl+= and Ctrl+- zoom and Ctrl+Y redoes unless
+    /// bound to something else.
+    fn action_for(&self, key: Key, modifiers: Modifiers) -> Option<Action> {
+        let pressed = KeyBinding::new(key, modifiers);
+        let find = |binding: KeyBinding| Action::ALL.into_iter().find(|&action| self.get(action) == binding);
+        find(pressed)
+            .or_else(|| if pressed.shift { find(KeyBinding { shift: false, ..pressed }) } else { None })
+            .or(match (key, pressed.command && !pressed.alt) {
+                (Key::Equals | Key::Plus, true) => Some(Action::ZoomIn),
+                (Key::Minus, true) => Some(Action::ZoomOut),
+                (Key::Y, true) => Some(Action::Redo),
+                _ => None,
+            })
+    }
+
+    /// Actions whose shortcut was pressed this frame
+    pub fn triggered(&self, input: &egui::InputState) -> Vec<Action> {
+        input
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                egui::Event::Key { key, pressed: true, modifiers, .. } => self.action_for(*key, *modifiers),
+                _ => None,
+            })
+            .collect()
+    }
+}
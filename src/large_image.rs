@@ -0,0 +1,220 @@
+use anyhow::{anyhow, bail};
+use image::{DynamicImage, ImageBuffer, ImageFormat, ImageReader};
+use log::info;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Instant;
+
+use crate::embedded_preview;
+use crate::orientation;
+use crate::tiled_tiff::TiledTiff;
+
+/// Images with more pixels are decoded at reduced resolution, a full RGBA8 decode would need 1 GiB
+pub const MAX_FULL_DECODE_PIXELS: u64 = 1 << 28;
+/// Images with more pixels show a quick low resolution preview while they are decoded
+pub const PREVIEW_MIN_PIXELS: u64 = 1 << 25;
+/// Embedded thumbnails smaller than this on their longer side are not used as previews
+const PREVIEW_MIN_SIZE: u32 = 1024;
+
+/// Format and dimensions of a PNG or JPEG with more than `min_pixels` pixels
+fn image_info(path: &Path, min_pixels: u64) -> Option<(ImageFormat, u32, u32)> {
+    let reader = ImageReader::open(path).ok()?.with_guessed_format().ok()?;
+    let format = reader.format().filter(|f| matches!(f, ImageFormat::Png | ImageFormat::Jpeg))?;
+    let (width, height) = reader.into_dimensions().ok()?;
+    (width as u64 * height as u64 > min_pixels).then_some((format, width, height))
+}
+
+/// Format and dimensions of a PNG or JPEG too large to decode at full resolution
+fn large_image_info(path: &Path) -> Option<(ImageFormat, u32, u32)> {
+    image_info(path, MAX_FULL_DECODE_PIXELS)
+}
+
+/// Low resolution stand-in for a huge JPEG or PNG that decodes in a fraction of the time: the
+/// embedded EXIF preview or a 1/8 DCT scaled decode of a JPEG, the first Adam7 pass of an
+/// interlaced PNG. Returns it with the dimensions of the file, `None` for smaller files and
+/// PNGs that are not interlaced.
+pub fn quick_preview(path: &Path) -> Option<(DynamicImage, (u32, u32))> {
+    let (format, width, height) = image_info(path, PREVIEW_MIN_PIXELS)?;
+    let mut preview = match format {
+        ImageFormat::Jpeg => {
+            let embedded = embedded_preview::load_embedded_preview(path, PREVIEW_MIN_SIZE).ok().flatten()
+                .filter(|preview| preview.width().max(preview.height()) >= PREVIEW_MIN_SIZE);
+            if let Some(embedded) = embedded {
+                // Already in the file's orientation
+                return Some((embedded, (width, height)));
+            }
+            decode_jpeg_reduced(path, width.div_ceil(8), height.div_ceil(8)).ok()?
+        }
+        _ => decode_png_first_pass(path).ok()??,
+    };
+    if let Some(orientation) = image::metadata::Orientation::from_exif(orientation::read_file_orientation(path).to_exif()) {
+        preview.apply_orientation(orientation);
+    }
+    Some((preview, (width, height)))
+}
+
+/// Pixels of the first Adam7 pass of an interlaced PNG, every 8th pixel of every 8th row.
+/// They come first in the file, so the rest is never decompressed. `Ok(None)` when the PNG
+/// is not interlaced.
+fn decode_png_first_pass(path: &Path) -> anyhow::Result<Option<DynamicImage>> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    if !reader.info().interlaced {
+        return Ok(None);
+    }
+    let (width, height) = (reader.info().width.div_ceil(8), reader.info().height.div_ceil(8));
+    let channels = reader.output_color_type().0.samples();
+    let mut output = Vec::with_capacity(width as usize * height as usize * channels);
+    for _ in 0..height {
+        let row = reader.next_interlaced_row()?.ok_or_else(|| anyhow!("PNG ended in its first pass"))?;
+        output.extend_from_slice(row.data());
+    }
+    Ok(Some(image_from_samples(width, height, channels, output).ok_or_else(|| anyhow!("PNG first pass does not match {}x{}", width, height))?))
+}
+
+/// A full resolution decode running on a worker thread while the preview is shown
+pub struct FullDecode<T> {
+    /// Dimensions of the file
+    pub size: (u32, u32),
+    pub started: Instant,
+    result_rx: Receiver<anyhow::Result<T>>,
+}
+
+impl<T: Send + 'static> FullDecode<T> {
+    pub fn start(size: (u32, u32), decode: impl FnOnce() -> anyhow::Result<T> + Send + 'static) -> Self {
+        let (result_tx, result_rx) = channel();
+        thread::spawn(move || {
+            // Nobody waits for the result once another image is shown
+            let _ = result_tx.send(decode());
+        });
+        Self { size, started: Instant::now(), result_rx }
+    }
+
+    /// The decoded image once the worker is done
+    pub fn poll(&self) -> Option<anyhow::Result<T>> {
+        self.result_rx.try_recv().ok()
+    }
+}
+
+/// Dimensions of the file when it is only shown at reduced resolution
+pub fn reduced_from(path: &Path) -> Option<(u32, u32)> {
+    large_image_info(path).map(|(_, width, height)| (width, height))
+        .or_else(|| TiledTiff::open(path).map(|tiff| tiff.size()))
+}
+
+/// Decode a huge PNG or JPEG at reduced resolution, without holding the full image in memory,
+/// or read the overview of a large TIFF. `Ok(None)` when the file is small enough for the
+/// regular decoder.
+pub fn decode_reduced(path: &Path) -> anyhow::Result<Option<DynamicImage>> {
+    if let Some(tiff) = TiledTiff::open(path) {
+        return tiff.overview().map(Some);
+    }
+    let Some((format, width, height)) = large_image_info(path) else {
+        return Ok(None);
+    };
+    // Integer reduction factor that brings the pixel count under the limit
+    let pixels = width as u64 * height as u64;
+    let factor = ((pixels as f64 / MAX_FULL_DECODE_PIXELS as f64).sqrt().ceil() as u32).max(2);
+    info!("Decoding {}x{} {:?} at 1/{} resolution", width, height, format, factor);
+    let img = match format {
+        ImageFormat::Png => decode_png_reduced(path, factor)?,
+        _ => decode_jpeg_reduced(path, width.div_ceil(factor), height.div_ceil(factor))?,
+    };
+    Ok(Some(img))
+}
+
+/// Stream the PNG row by row, averaging `factor`×`factor` blocks, so only one output row of sums is kept
+fn decode_png_reduced(path: &Path, factor: u32) -> anyhow::Result<DynamicImage> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    if reader.info().interlaced {
+        bail!("Interlaced PNGs of this size cannot be decoded row by row");
+    }
+    let (width, height) = (reader.info().width, reader.info().height);
+    let channels = match reader.output_color_type().0 {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        png::ColorType::Indexed => bail!("Indexed PNG was not expanded"),
+    };
+    let (out_width, out_height) = (width.div_ceil(factor), height.div_ceil(factor));
+    let row_values = out_width as usize * channels;
+    let mut output = Vec::with_capacity(row_values * out_height as usize);
+    let mut sums = vec![0u64; row_values];
+    let mut counts = vec![0u32; out_width as usize];
+
+    let mut y = 0;
+    while let Some(row) = reader.next_row()? {
+        for (x, pixel) in row.data().chunks_exact(channels).enumerate() {
+            let out_x = x / factor as usize;
+            counts[out_x] += 1;
+            for (c, &value) in pixel.iter().enumerate() {
+                sums[out_x * channels + c] += value as u64;
+            }
+        }
+        y += 1;
+        // Flush an output row after `factor` input rows and at the end of the image
+        if y % factor == 0 || y == height {
+            for (x, &count) in counts.iter().enumerate() {
+                let count = count.max(1) as u64;
+                output.extend(sums[x * channels..(x + 1) * channels].iter().map(|&sum| ((sum + count / 2) / count) as u8));
+            }
+            sums.fill(0);
+            counts.fill(0);
+        }
+    }
+
+    image_from_samples(out_width, out_height, channels, output)
+        .ok_or_else(|| anyhow!("Decoded PNG rows do not match {}x{}", out_width, out_height))
+}
+
+/// 8-bit image from interleaved samples with 1 to 4 channels
+fn image_from_samples(width: u32, height: u32, channels: usize, samples: Vec<u8>) -> Option<DynamicImage> {
+    Some(match channels {
+        1 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, samples)?),
+        2 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, samples)?),
+        3 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, samples)?),
+        _ => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, samples)?),
+    })
+}
+
+/// Decode the JPEG with DCT scaling (1/2, 1/4 or 1/8), so the full resolution is never reconstructed
+fn decode_jpeg_reduced(path: &Path, target_width: u32, target_height: u32) -> anyhow::Result<DynamicImage> {
+    let mut decoder = jpeg_decoder::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.read_info()?;
+    let (width, height) = decoder.scale(target_width.min(u16::MAX as u32) as u16, target_height.min(u16::MAX as u32) as u16)?;
+    let pixels = decoder.decode()?;
+    let info = decoder.info().ok_or_else(|| anyhow!("JPEG has no frame header"))?;
+    let (width, height) = (width as u32, height as u32);
+    let buffer_error = || anyhow!("Decoded JPEG data does not match {}x{}", width, height);
+
+    let img = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, pixels).ok_or_else(buffer_error)?),
+        jpeg_decoder::PixelFormat::L16 => {
+            let values = pixels.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]])).collect();
+            DynamicImage::ImageLuma16(ImageBuffer::from_raw(width, height, values).ok_or_else(buffer_error)?)
+        }
+        jpeg_decoder::PixelFormat::RGB24 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, pixels).ok_or_else(buffer_error)?),
+        jpeg_decoder::PixelFormat::CMYK32 => {
+            // Adobe CMYK JPEGs are stored inverted
+            let rgb = pixels.chunks_exact(4)
+                .flat_map(|p| {
+                    let k = p[3] as u32;
+                    [p[0], p[1], p[2]].map(|c| (c as u32 * k / 255) as u8)
+                })
+                .collect();
+            DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, rgb).ok_or_else(buffer_error)?)
+        }
+    };
+    // DCT scaling stops at 1/8, shrink the rest of the way
+    if img.width() as u64 * img.height() as u64 > MAX_FULL_DECODE_PIXELS {
+        return Ok(img.resize(target_width, target_height, image::imageops::FilterType::Triangle));
+    }
+    Ok(img)
+}
@@ -0,0 +1,176 @@
+//! Images stacked over the shown one, each blended with its own mode and opacity and shifted by
+//! whole image pixels to line it up. The layers are composited into the displayed image, so the
+//! loupe, the probes and the export of the view show them as well.
+
+use image::{DynamicImage, RgbaImage};
+use std::path::{Path, PathBuf};
+
+/// How a layer combines with what is below it
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Difference,
+    Screen,
+}
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 4] = [BlendMode::Normal, BlendMode::Multiply, BlendMode::Difference, BlendMode::Screen];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BlendMode::Normal => "Normal",
+            BlendMode::Multiply => "Multiply",
+            BlendMode::Difference => "Difference",
+            BlendMode::Screen => "Screen",
+        }
+    }
+
+    /// Blended value of a channel, before opacity
+    fn blend(self, below: u8, layer: u8) -> u8 {
+        let (below, layer) = (below as u32, layer as u32);
+        (match self {
+            BlendMode::Normal => layer,
+            BlendMode::Multiply => (below * layer + 127) / 255,
+            BlendMode::Difference => below.abs_diff(layer),
+            BlendMode::Screen => 255 - ((255 - below) * (255 - layer) + 127) / 255,
+        }) as u8
+    }
+}
+
+/// An image over the shown one
+struct Layer {
+    path: PathBuf,
+    image: RgbaImage,
+    visible: bool,
+    opacity: f32,
+    blend: BlendMode,
+    /// Position of the layer's top left corner in image pixels
+    offset: [i32; 2],
+}
+
+/// Layers over the shown image, the first one is the lowest
+#[derive(Default)]
+pub struct LayerStack {
+    layers: Vec<Layer>,
+}
+
+impl LayerStack {
+    /// Put an image on top of the stack
+    pub fn push(&mut self, path: &Path, image: DynamicImage) {
+        self.layers.push(Layer {
+            path: path.to_path_buf(),
+            image: image.into_rgba8(),
+            visible: true,
+            opacity: 1.0,
+            blend: BlendMode::Normal,
+            offset: [0, 0],
+        });
+    }
+
+    /// Whether any layer changes the displayed image
+    pub fn is_active(&self) -> bool {
+        self.layers.iter().any(|layer| layer.visible && layer.opacity > 0.0)
+    }
+
+    /// Blend the layers into the displayed image, which shows an image of `image_size` pixels
+    /// at its own resolution. Layer pixels are sampled nearest to each displayed pixel.
+    pub fn composite(&self, displayed: DynamicImage, image_size: (u32, u32)) -> DynamicImage {
+        let layers: Vec<&Layer> = self.layers.iter().filter(|layer| layer.visible && layer.opacity > 0.0).collect();
+        if layers.is_empty() {
+            return displayed;
+        }
+        let mut rgba = displayed.into_rgba8();
+        let (display_width, display_height) = rgba.dimensions();
+        for (x, y, pixel) in rgba.enumerate_pixels_mut() {
+            let image_x = (x as u64 * image_size.0 as u64 / display_width as u64) as i64;
+            let image_y = (y as u64 * image_size.1 as u64 / display_height as u64) as i64;
+            for layer in &layers {
+                let (layer_x, layer_y) = (image_x - layer.offset[0] as i64, image_y - layer.offset[1] as i64);
+                if layer_x < 0 || layer_y < 0 || layer_x >= layer.image.width() as i64 || layer_y >= layer.image.height() as i64 {
+                    continue;
+                }
+                let top = layer.image.get_pixel(layer_x as u32, layer_y as u32);
+                let alpha = layer.opacity * top[3] as f32 / 255.0;
+                for channel in 0..3 {
+                    let blended = layer.blend.blend(pixel[channel], top[channel]) as f32;
+                    pixel[channel] = (pixel[channel] as f32 + (blended - pixel[channel] as f32) * alpha).round() as u8;
+                }
+                pixel[3] = (alpha * 255.0 + pixel[3] as f32 * (1.0 - alpha)).round() as u8;
+            }
+        }
+        DynamicImage::ImageRgba8(rgba)
+    }
+
+    /// The layers from the top down, with visibility, blend mode, opacity, order and offset.
+    /// Returns true if the displayed image changes.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        if self.layers.is_empty() {
+            ui.weak("No layers, the image is shown alone");
+            return false;
+        }
+        let (mut raise, mut lower, mut remove) = (None, None, None);
+        let count = self.layers.len();
+        for index in (0..count).rev() {
+            let layer = &mut self.layers[index];
+            ui.push_id(index, |ui| {
+                ui.horizontal(|ui| {
+                    changed |= ui.checkbox(&mut layer.visible, "").on_hover_text("Show the layer").changed();
+                    let name = layer.path.file_name().unwrap_or_default().to_string_lossy();
+                    ui.label(name).on_hover_text(format!("{} ({}×{})", layer.path.display(), layer.image.width(), layer.image.height()));
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.small_button("✖").on_hover_text("Remove the layer").clicked() {
+                            remove = Some(index);
+                        }
+                        if ui.add_enabled(index > 0, egui::Button::new("▼").small()).on_hover_text("Move down").clicked() {
+                            lower = Some(index);
+                        }
+                        if ui.add_enabled(index + 1 < count, egui::Button::new("▲").small()).on_hover_text("Move up").clicked() {
+                            raise = Some(index);
+                        }
+                    });
+                });
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("blend")
+                        .width(90.0)
+                        .selected_text(layer.blend.as_str())
+                        .show_ui(ui, |ui| {
+                            for mode in BlendMode::ALL {
+                                changed |= ui.selectable_value(&mut layer.blend, mode, mode.as_str()).changed();
+                            }
+                        });
+                    changed |= ui.add(egui::Slider::new(&mut layer.opacity, 0.0..=1.0).text("Opacity")).changed();
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Offset:");
+                    changed |= ui.add(egui::DragValue::new(&mut layer.offset[0]).prefix("x ")).changed();
+                    changed |= ui.add(egui::DragValue::new(&mut layer.offset[1]).prefix("y ")).changed();
+                    // Shift nudges by 10 pixels
+                    let step = if ui.input(|i| i.modifiers.shift) { 10 } else { 1 };
+                    for (arrow, [dx, dy]) in [("←", [-1, 0]), ("→", [1, 0]), ("↑", [0, -1]), ("↓", [0, 1])] {
+                        if ui.small_button(arrow).on_hover_text("Nudge by a pixel, with Shift by 10").clicked() {
+                            layer.offset[0] += dx * step;
+                            layer.offset[1] += dy * step;
+                            changed = true;
+                        }
+                    }
+                });
+            });
+            ui.separator();
+        }
+        if let Some(index) = raise {
+            self.layers.swap(index, index + 1);
+            changed = true;
+        }
+        if let Some(index) = lower {
+            self.layers.swap(index, index - 1);
+            changed = true;
+        }
+        if let Some(index) = remove {
+            self.layers.remove(index);
+            changed = true;
+        }
+        changed
+    }
+}
@@ -0,0 +1,88 @@
+use crate::orientation::ViewMapping;
+
+/// Outline of the magnifier
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum LoupeShape {
+    Circle,
+    Square,
+}
+
+/// Floating magnifier following the cursor, the zoom is relative to the main view
+pub struct Loupe {
+    pub zoom: u32,
+    pub shape: LoupeShape,
+    /// Diameter or edge length in points
+    pub size: f32,
+}
+
+impl Default for Loupe {
+    fn default() -> Self {
+        Self { zoom: 8, shape: LoupeShape::Circle, size: 220.0 }
+    }
+}
+
+impl Loupe {
+    pub const ZOOM_RANGE: std::ops::RangeInclusive<u32> = 4..=16;
+
+    /// Draw the magnifier centered on `center`, showing the texture around the image under it in the
+    /// main `view`, oriented like the view
+    pub fn paint(&self, painter: &egui::Painter, texture: egui::TextureId, view: &ViewMapping, center: egui::Pos2) {
+        let point = view.to_image(center);
+        let image_size = view.size;
+        // The loupe is the main view magnified around the cursor
+        let loupe = ViewMapping { center: center + view.orientation.apply_vector(image_size / 2.0 - point.to_vec2()) * view.scale * self.zoom as f32, scale: view.scale * self.zoom as f32, ..*view };
+        let radius = self.size / 2.0;
+        let to_uv = |offset: egui::Vec2| {
+            let p = loupe.to_image(center + offset);
+            egui::pos2(p.x / image_size.x, p.y / image_size.y)
+        };
+
+        let mut mesh = egui::Mesh::with_texture(texture);
+        match self.shape {
+            LoupeShape::Square => {
+                let rect = egui::Rect::from_center_size(center, egui::Vec2::splat(self.size));
+                painter.rect_filled(rect, 0.0, egui::Color32::from_gray(40));
+                for corner in [rect.left_top(), rect.right_top(), rect.right_bottom(), rect.left_bottom()] {
+                    mesh.vertices.push(egui::epaint::Vertex { pos: corner, uv: to_uv(corner - center), color: egui::Color32::WHITE });
+                }
+                mesh.add_triangle(0, 1, 2);
+                mesh.add_triangle(0, 2, 3);
+            }
+            LoupeShape::Circle => {
+                painter.circle_filled(center, radius, egui::Color32::from_gray(40));
+                // Triangle fan around the center
+                let segments = 64;
+                mesh.vertices.push(egui::epaint::Vertex { pos: center, uv: to_uv(egui::Vec2::ZERO), color: egui::Color32::WHITE });
+                for i in 0..=segments {
+                    let offset = egui::Vec2::angled(i as f32 / segments as f32 * std::f32::consts::TAU) * radius;
+                    mesh.vertices.push(egui::epaint::Vertex { pos: center + offset, uv: to_uv(offset), color: egui::Color32::WHITE });
+                    if i > 0 {
+                        mesh.add_triangle(0, i, i + 1);
+                    }
+                }
+            }
+        }
+        // Only where the image is, outside it the texture would repeat its border pixels
+        let image_rect = loupe.rect_to_screen(egui::Pos2::ZERO, image_size.to_pos2());
+        painter.with_clip_rect(painter.clip_rect().intersect(image_rect)).add(mesh);
+
+        // Pixel under the cursor
+        let pixel = loupe.rect_to_screen(point.floor(), point.floor() + egui::Vec2::splat(1.0));
+        if pixel.width() >= 4.0 {
+            painter.rect_stroke(pixel, 0.0, egui::Stroke::new(1.0, egui::Color32::from_white_alpha(160)), egui::StrokeKind::Outside);
+        }
+
+        let (outer, inner) = (egui::Stroke::new(3.0, egui::Color32::BLACK), egui::Stroke::new(1.5, egui::Color32::WHITE));
+        match self.shape {
+            LoupeShape::Square => {
+                let rect = egui::Rect::from_center_size(center, egui::Vec2::splat(self.size));
+                painter.rect_stroke(rect, 0.0, outer, egui::StrokeKind::Outside);
+                painter.rect_stroke(rect, 0.0, inner, egui::StrokeKind::Outside);
+            }
+            LoupeShape::Circle => {
+                painter.circle_stroke(center, radius + 1.0, outer);
+                painter.circle_stroke(center, radius + 1.0, inner);
+            }
+        }
+    }
+}
@@ -1,459 +1,4533 @@
 #![cfg_attr(target_os = "windows", windows_subsystem = "windows")]
 
+mod adjustments;
+mod annotations;
+mod batch;
+mod cli;
+mod clipping;
+mod color_picker;
+mod colormap;
+mod compare;
+mod config;
+mod crop;
+mod display_profile;
+mod drag_out;
+mod embedded_preview;
+mod export;
+mod expression;
+mod file_associations;
+mod file_ops;
+mod fft_view;
+mod frames;
+mod gallery;
+mod gpu_histogram;
+mod hints;
+mod histogram_job;
+mod image_cache;
 mod image_processing;
+mod instance;
+mod integrity;
+mod keybindings;
+mod large_image;
+mod layers;
+mod loupe;
+mod mask_overlay;
+mod measure;
+mod multiband;
+mod naming;
+mod nifti;
+mod ome_tiff;
+mod orientation;
+mod palette;
+mod paths;
+mod probes;
+mod properties;
+mod rating;
+mod raw;
+mod resize;
+mod threshold;
+mod tiff_format;
+mod tiled_tiff;
+mod thumbnail_store;
+mod thumbnails;
+mod tonemap;
+mod transforms;
+mod undo;
+mod video;
+mod view_export;
+mod wallpaper;
+mod watch;
+#[cfg(all(test, feature = "golden-tests"))]
+mod golden_tests;
 
 use eframe::egui;
 use eframe::icon_data::from_png_bytes;
 
+use image::imageops::FilterType;
 use image::{DynamicImage, GenericImageView, ImageBuffer};
-use std::path::PathBuf;
-use image_processing::{min_max_normalize, standardize, log_min_max_normalize, fft};
-use std::env;
+use std::path::{Path, PathBuf};
+use image_processing::{fft_spectrum, fft_shift, fp_image, chroma_709, luma_709, mean_fp_values, mean_pixel_values, non_finite_pixels, pixel_values, sample_window, vectorscope_position};
 use log::{info, error, warn};
 use std::io::BufReader;
 use std::fs::File;
 use std::sync::{Arc, Mutex};
 use std::fs;
+use std::borrow::Cow;
+use std::env;
+use std::ffi::OsString;
+use transforms::{params_at_resolution, ChannelView, Pipeline, Transform, TransformKind, TransformRegistry};
+use thumbnails::ThumbnailCache;
+use image_cache::ImageCache;
+use hints::HintIndex;
+use config::{Background, CacheBudget, Config, DownscaleFilter, MagnificationFilter, PixelSample, StartupBehavior, Theme, UiScale};
+use orientation::{Orientation, OrientationOp, ViewMapping};
+use export::{FftExportOptions, PixelRegion};
+use compare::{CompareState, CompareView};
+use layers::LayerStack;
+use frames::FrameStack;
+use gallery::Gallery;
+use wallpaper::WallpaperMode;
+use nifti::{Plane, SliceEdit, Volume};
+use ome_tiff::OmeStack;
+use gpu_histogram::{GpuHistogram, HistogramRequest, SampleType, SourceData};
+use histogram_job::{HistogramInput, HistogramJob};
+use naming::NamingContext;
+use colormap::{Colormap, ColorStop};
+use display_profile::DisplayTransform;
+use std::time::{Duration, Instant, SystemTime};
+use video::{VideoEncodeJob, VideoExportOptions, VideoFormat};
+use batch::{BatchFormat, BatchJob, BatchOptions};
+use large_image::FullDecode;
+use tiled_tiff::{Detail, TiledTiff, DETAIL_MAX_PIXELS};
+use watch::FolderWatcher;
+use instance::InstanceServer;
+use crop::{AspectRatio, CropTool};
+use keybindings::{Action, KeyBinding};
+use probes::Probe;
+use clipping::ClippingMap;
+use adjustments::Adjustments;
+use annotations::{Annotation, AnnotationEditor, AnnotationTool, Shape};
+use loupe::{Loupe, LoupeShape};
+use multiband::BandMapping;
+use properties::FileProperties;
+use fft_view::FftView;
+use expression::ExpressionTool;
+use threshold::ThresholdTool;
+use mask_overlay::{Mask, MaskOverlay};
+use measure::{Calibration, LengthUnit, MeasureMode, MeasureTool, Measurement};
+use tonemap::ToneMapper;
+use raw::{RawDevelop, WhiteBalance};
+use rating::{ColorLabel, Rating, Ratings};
+use resize::ResizeTool;
+use undo::{History, Snapshot};
+use integrity::{DecodedPixels, IntegrityState, Samples};
 
 const ICON: &[u8] = include_bytes!("../assets/icon.png");
 
+/// Decoded image plus optional floating point data: (image, is_fp, data_range, fp_data, fp_dimensions, fp_channels)
+type LoadedImage = (DynamicImage, bool, Option<(f32, f32)>, Option<Vec<f32>>, Option<(u32, u32)>, Option<u32>);
+
+/// Maximum number of columns of the luma waveform
+const WAVEFORM_COLUMNS: u32 = 512;
+/// Side of the vectorscope grid in cells
+const VECTORSCOPE_SIZE: u32 = 256;
+/// Histograms of regions with at least this many pixels are computed on the GPU
+const GPU_HISTOGRAM_MIN_PIXELS: u64 = 4 << 20;
+/// Edge length of filmstrip thumbnails in pixels
+const THUMBNAIL_SIZE: u32 = 96;
+/// Height of the file name below the gallery thumbnails in points
+const GALLERY_NAME_HEIGHT: f32 = 16.0;
+/// Distance from the wipe divider within which it can be grabbed, in points
+const WIPE_GRAB_DISTANCE: f32 = 6.0;
+/// Longer side of the navigation minimap in points
+const MINIMAP_SIZE: f32 = 160.0;
+/// Share of the view moved by one keyboard pan step
+const PAN_STEP: f32 = 0.1;
+/// Zoom steps offered next to the zoom entry, in percent
+const ZOOM_PRESETS: [u32; 5] = [25, 50, 100, 200, 400];
+/// Zoom from which the pixel grid is drawn
+const PIXEL_GRID_MIN_SCALE: f32 = 8.0;
+
+/// Color of the pixel grid at a zoom of `scale` screen points per pixel, faded in above the
+/// threshold so the grid does not pop up
+fn pixel_grid_color(scale: f32) -> egui::Color32 {
+    let opacity = ((scale - PIXEL_GRID_MIN_SCALE) / PIXEL_GRID_MIN_SCALE).clamp(0.25, 0.6);
+    egui::Color32::from_gray(128).gamma_multiply(opacity)
+}
+/// Size in points of the squares marking clipped pixels, below which pixels get the squares
+const CLIPPING_MARKER_SIZE: f32 = 3.0;
+/// Largest ROI whose values can be copied as text, larger matrices are not useful in a clipboard
+const MAX_COPY_REGION_PIXELS: usize = 256 * 256;
+/// Edge length of a checkerboard background square in points
+const CHECKER_SIZE: f32 = 8.0;
+
+#[derive(Default, Clone, Copy, PartialEq)]
+enum AnalysisTab {
+    #[default]
+    Histogram,
+    Waveform,
+    Vectorscope,
+}
+
+/// Pixel rectangle of the image as (x0, y0, x1, y1), end exclusive
+type PixelRect = [u32; 4];
+
+/// Which pixels the histogram and scopes are computed from
+#[derive(Default, Clone, Copy, PartialEq)]
+enum HistogramScope {
+    #[default]
+    WholeImage,
+    VisibleArea,
+    Roi,
+}
+
+impl HistogramScope {
+    fn as_str(&self) -> &'static str {
+        match self {
+            HistogramScope::WholeImage => "Whole image",
+            HistogramScope::VisibleArea => "Visible area",
+            HistogramScope::Roi => "ROI",
+        }
+    }
+}
+
+/// Number of histogram bins for 16-bit and floating point data
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct HistogramBins(usize);
+
+impl HistogramBins {
+    const CHOICES: [usize; 5] = [256, 1024, 4096, 16384, 65536];
+}
+
+impl Default for HistogramBins {
+    fn default() -> Self {
+        Self(256)
+    }
+}
+
+/// Data values covered by the histogram bins
+#[derive(Clone, Copy)]
+struct HistogramAxis {
+    min: f32,
+    max: f32,
+    integer: bool, // Label values as integers
+}
+
+impl Default for HistogramAxis {
+    fn default() -> Self {
+        Self { min: 0.0, max: 255.0, integer: true }
+    }
+}
+
+impl HistogramAxis {
+    /// Data value at a (fractional) bin position
+    fn value_at(&self, bin: f32, bins: usize) -> f32 {
+        self.min + (self.max - self.min) * bin / (bins.max(2) - 1) as f32
+    }
+    
+    fn format(&self, value: f32) -> String {
+        if self.integer {
+            format!("{}", value.round() as i64)
+        } else {
+            format!("{:.4}", value)
+        }
+    }
+}
+
+/// How transparent pixels count in the histogram and statistics
+#[derive(Default, Clone, Copy, PartialEq)]
+enum AlphaMode {
+    #[default]
+    Include,
+    ExcludeTransparent,
+    Weighted,
+}
+
+impl AlphaMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AlphaMode::Include => "Count all pixels",
+            AlphaMode::ExcludeTransparent => "Exclude transparent",
+            AlphaMode::Weighted => "Weight by alpha",
+        }
+    }
+    
+    /// Weight of a pixel with alpha in 0..=1
+    fn weight(&self, alpha: f32) -> f32 {
+        match self {
+            AlphaMode::Include => 1.0,
+            AlphaMode::ExcludeTransparent => if alpha > 0.0 { 1.0 } else { 0.0 },
+            AlphaMode::Weighted => alpha,
+        }
+    }
+}
+
+/// Weighted mean, standard deviation and range of one channel
+#[derive(Clone, Copy)]
+struct ChannelStats {
+    weight: f64,
+    sum: f64,
+    sum_squares: f64,
+    min: f32,
+    max: f32,
+}
+
+impl Default for ChannelStats {
+    fn default() -> Self {
+        Self { weight: 0.0, sum: 0.0, sum_squares: 0.0, min: f32::INFINITY, max: f32::NEG_INFINITY }
+    }
+}
+
+impl ChannelStats {
+    fn add(&mut self, value: f32, weight: f32) {
+        let (value, weight) = (value as f64, weight as f64);
+        self.weight += weight;
+        self.sum += value * weight;
+        self.sum_squares += value * value * weight;
+        self.min = self.min.min(value as f32);
+        self.max = self.max.max(value as f32);
+    }
+    
+    fn mean(&self) -> f64 {
+        self.sum / self.weight.max(f64::EPSILON)
+    }
+    
+    fn std_dev(&self) -> f64 {
+        (self.sum_squares / self.weight.max(f64::EPSILON) - self.mean().powi(2)).max(0.0).sqrt()
+    }
+}
+
+/// Display options of the histogram plot
+#[derive(Default, Clone, Copy)]
+struct HistogramView {
+    log_scale: bool, // Logarithmic count axis
+    cumulative: bool, // Cumulative distribution instead of counts
+    hidden: [bool; 3], // Hidden R, G, B channels
+}
+
 #[derive(Default, Clone)]
 struct HistogramData {
-    histograms: Option<Vec<Vec<u32>>>,
-    hover_info: Option<(u32, u32, f32)>,
+    histograms: Option<Vec<Vec<f32>>>, // Pixel counts (or alpha weights) per RGB channel
+    hover_info: Option<(u32, f32, f32)>,
     hover_pos: Option<egui::Pos2>,
     close_requested: bool,
+    active_tab: AnalysisTab,
+    waveform: Option<(u32, Vec<u32>)>, // (columns, counts[level * columns + column])
+    vectorscope: Option<Vec<u32>>, // VECTORSCOPE_SIZE² chroma counts
+    waveform_texture: Option<egui::TextureHandle>,
+    vectorscope_texture: Option<egui::TextureHandle>,
+    scope: HistogramScope, // Selected in the analysis window
+    region: Option<PixelRect>, // Region the data was computed from, None for the whole image
+    view: HistogramView,
+    bins: HistogramBins, // Requested bin count
+    axis: HistogramAxis, // Value range of the computed bins
+    alpha_mode: AlphaMode,
+    stats: Option<[ChannelStats; 3]>, // Per channel statistics of the counted pixels
+    has_alpha: bool, // Whether the image has an alpha channel
+    gpu_pending: bool, // The histogram is being computed on the GPU, the shown one is outdated
+    cpu_progress: Option<f32>, // Fraction of the rows counted by the background job, None when idle
 }
 
-struct ImageViewerApp {
+/// An open image with its view and display state, one per tab
+struct ImageDocument {
     image: Option<DynamicImage>,
     image_path: Option<PathBuf>,
-    last_opened_folder: Option<PathBuf>,
+    file_size: Option<u64>, // Size of the file on disk when it was loaded
+    decode_time: Option<Duration>, // Time the last decode took, None for cached images and running decodes
     scale: f32,
     base_scale: f32, // Scale to fit image in window
-    normalization: NormalizationType,
+    normalization: &'static str, // Id of the active normalization transform
     channel: ChannelType,
     texture: Option<egui::TextureHandle>,
     offset: egui::Vec2,
-    dragging: bool,
     texture_needs_update: bool,
     last_texture_scale: f32,
-    last_normalization: NormalizationType,
+    texture_magnification: egui::TextureFilter, // Magnification filter the texture was created with
+    loupe_texture: Option<egui::TextureHandle>, // Full resolution nearest-neighbor texture of the loupe
+    clipping: Option<(ClippingMap, egui::TextureHandle)>, // Pixels at the ends of the data range or not finite, with the texture of their mask
+    last_normalization: &'static str,
     last_channel: ChannelType,
-    pixel_info: Option<(u32, u32, u8, u8, u8)>, // (x, y, r, g, b)
+    channel_as_gray: bool, // Show a single color channel as a gray image instead of tinted
+    pixel_info: Option<(u32, u32, u16, u16, u16)>, // (x, y, r, g, b) in the units of the image, 16-bit values untruncated
+    pixel_info_display: Option<[u8; 3]>, // Displayed RGB of the hovered pixel of a 16-bit image
+    pixel_info_mean: Option<[f32; 3]>, // Unrounded mean RGB of the sampling square of an integer image
+    display_pixels: Option<egui::ColorImage>, // Rendered pixels of a 16-bit image, read for the displayed value in the pixel readout
     pixel_info_fp: Option<(u32, u32, f32, f32, f32)>, // (x, y, r, g, b) for floating point images
     pixel_info_channels: Option<u32>, // Number of channels for current pixel info
-    show_pixel_tool: bool,
-    hover_pos: Option<egui::Pos2>,
+    probes: Vec<Probe>, // Pinned pixels listed in the probe table
+    annotations: Vec<Annotation>, // Shapes drawn over the image, stored in a JSON sidecar
+    tiff_calibration: Option<Calibration>, // Pixel size from the TIFF resolution tags
+    probes_dirty: bool, // Probe values have to be read again, the image or display pipeline changed
+    adjustments: Adjustments, // Exposure, contrast, gamma etc. applied to the displayed image
+    pipeline: Pipeline, // Operations chained after the normalization and analysis
     is_floating_point_image: bool,
+    is_hdr: bool, // Linear HDR data (EXR, Radiance HDR, camera RAW), the displayed image is tone mapped from the FP data
+    hdr_exposure: f32, // Exposure in stops applied before tone mapping
+    reduced_from: Option<(u32, u32)>, // Full size of a huge image that is shown at reduced resolution
+    full_decode: Option<FullDecode<LoadedImage>>, // Decode running while a low resolution preview is shown
+    tiled_tiff: Option<Arc<TiledTiff>>, // Large TIFF shown as an overview, the visible part is read from it
+    detail: Option<(Detail, Option<egui::TextureHandle>)>, // Visible part of the TIFF at the resolution of the view, with its texture
+    detail_load: Option<FullDecode<Detail>>, // Read of the visible part running
+    raw_develop: Option<RawDevelop>, // Color transform of a camera RAW file, for changing its white balance
+    frames: Option<FrameStack>, // Frames of an animated GIF or multi-page TIFF, the image is the current one
+    volume: Option<Volume>, // NIfTI volume, the image and FP data are its current slice
+    ome: Option<OmeStack>, // Channel, z and time axes of an OME-TIFF, the image is the plane shown
     original_data_range: Option<(f32, f32)>, // (min, max) of original floating point data
+    non_finite_pixels: usize, // Pixels of the floating point data with a NaN or infinite value
     original_fp_data: Option<Vec<f32>>, // Store original floating point pixel data
     original_fp_dimensions: Option<(u32, u32)>, // Width, height of original FP data
     original_fp_channels: Option<u32>, // Number of channels (1 for Gray, 3 for RGB)
-    show_histogram: bool, // Whether histogram window is open
-    histogram_data: Option<Vec<Vec<u32>>>, // Histogram data for each channel (RGB)
+    band_mapping: Option<BandMapping>, // Displayed bands of FP data with more than 4 channels
     histogram_needs_update: bool, // Whether histogram needs recalculation
-    histogram_shared_data: Arc<Mutex<HistogramData>>, // Shared data for histogram window
-    histogram_window_id: Option<egui::ViewportId>, // ID of the histogram window
     folder_images: Vec<PathBuf>, // List of images in current folder
     current_image_index: Option<usize>, // Index of current image in folder_images
+    file_list: bool, // Whether folder_images was given on the command line instead of scanned
+    filmstrip_scrolled_to: Option<usize>, // Index the filmstrip was last scrolled to
+    orientation: Orientation, // Rotations/flips applied since the image was loaded
+    file_orientation: Orientation, // EXIF orientation of the file on disk
+    edited: bool, // Whether the image was cropped or resized since it was loaded or saved
+    history: History, // States before the rotations, flips, crops and resizes, for undo and redo
+    view_orientation: Orientation, // Rotation/mirroring of the display only, coordinates keep the data layout
+    compare: Option<CompareState>, // Second image for A/B comparison
+    layers: LayerStack, // Images blended over the shown one
+    colormap: Option<String>, // Name of the false-color map applied to the displayed channel
+    roi: Option<PixelRect>, // Region of interest, drawn with Shift + drag
+    visible_region: Option<PixelRect>, // Part of the image currently on screen
+    histogram_region: Option<PixelRect>, // Region the current histogram was computed from
+    histogram_bins: HistogramBins, // Bin count the current histogram was computed with
+    histogram_alpha_mode: AlphaMode, // Alpha handling the current histogram was computed with
 }
 
-// TODO: FFT is not queite Normalization, but it is a transformation, need to be fixed
-#[derive(PartialEq, Clone, Copy)]
-enum NormalizationType {
-    None,
-    MinMax,
-    LogMinMax,
-    Standard,
-    FFT,
+/// File name being edited in the rename dialog
+struct RenameDialog {
+    path: PathBuf,
+    name: String,
+    focused: bool, // The text field got the keyboard focus
+}
+
+/// Additional OS window showing its own image
+struct ViewerWindow {
+    id: egui::ViewportId,
+    doc: ImageDocument,
+    open: bool, // Cleared when the window is closed, it is dropped after the frame
+}
+
+struct ImageViewerApp {
+    doc: ImageDocument, // Image of the active tab
+    tabs: Vec<ImageDocument>, // Images of the other tabs, in tab order without the active one
+    active_tab: usize, // Position of the active tab in the tab bar
+    windows: Vec<ViewerWindow>, // Additional viewer windows
+    next_window_id: u64, // Counter for the viewport ids of new windows
+    sync_windows: bool, // Keep zoom and position equal in the main and the additional windows
+    synced_view: Option<(f32, egui::Vec2)>, // On-screen pixel size and offset last applied to all windows
+    last_opened_folder: Option<PathBuf>,
+    transforms: TransformRegistry,
+    dragging: bool,
+    show_pixel_tool: bool,
+    show_loupe: bool, // Magnifier following the cursor
+    show_layers: bool, // Whether the layer panel is shown
+    show_clipping: bool, // Highlight pixels at the ends of the data range and NaN/Inf values
+    loupe: Loupe,
+    context_pixel_text: Option<String>, // Pixel info captured when the context menu was opened
+    context_pixel: Option<(u32, u32)>, // Pixel under the pointer when the context menu was opened
+    show_probes: bool, // Whether the probe table window is open
+    show_info: bool, // Whether the image properties window is open
+    info_properties: Option<(PathBuf, FileProperties)>, // Header details of the file shown in the properties window
+    show_adjustments: bool, // Whether the adjustments window is open
+    show_pipeline: bool, // Whether the pipeline window is open
+    integrity: IntegrityState, // File and pixel hashes of the open image, bit-exact comparison
+    show_integrity: bool, // Whether the image info window is open
+    palette: Vec<[u8; 3]>, // Colors sampled with the pixel tool during this session
+    picked_color: Option<((u32, u32), [u8; 3])>, // Last color clicked with the pixel tool and its position
+    hover_pos: Option<egui::Pos2>,
+    hover_pixel: Option<(u32, u32)>, // Image pixel under the pointer, shown in the status bar
+    show_histogram: bool, // Whether histogram window is open
+    histogram_shared_data: Arc<Mutex<HistogramData>>, // Shared data for histogram window
+    histogram_window_id: Option<egui::ViewportId>, // ID of the histogram window
+    gpu_histogram: GpuHistogram, // Histogram computation of large images on the GPU
+    histogram_job: HistogramJob, // Histogram and scopes computation on a worker thread
+    watcher: Option<FolderWatcher>, // Reports changes of the open image and its folder
+    unwatchable_folder: Option<PathBuf>, // Folder that could not be watched, not tried again
+    show_filmstrip: bool, // Whether the thumbnail strip is shown below the image
+    gallery: Option<Gallery>, // Thumbnail grid of the folder, shown instead of the image
+    thumbnails: Option<ThumbnailCache>, // Created on first use, needs the egui context
+    image_cache: ImageCache<(LoadedImage, Option<RawDevelop>)>, // Recently decoded images
+    hint_index: HintIndex, // Classification hints of the folder images
+    config: Config, // Persisted user settings
+    show_settings: bool, // Whether the settings window is open
+    recording_binding: Option<Action>, // Action waiting for a key press in the settings
+    open_dialog_requested: bool, // Show the file dialog on the next frame (startup option)
+    show_orientation_save: bool, // Whether the save-orientation confirmation is open
+    pending_delete: Vec<PathBuf>, // Files waiting for the move-to-trash confirmation
+    load_error: Option<String>, // Why a file in a format the viewer cannot decode was not opened
+    last_move_folder: Option<PathBuf>, // Folder the last file was moved to, where the next move starts
+    rename: Option<RenameDialog>, // Open rename dialog
+    ratings: Ratings, // Star ratings and color labels from the XMP sidecars
+    min_rating: u8, // Folder navigation skips images with fewer stars
+    window_title: String, // Title last set on the main window
+    show_fft: bool, // Whether the FFT window is open
+    fft_view: FftView,
+    show_fft_export: bool, // Whether the FFT export window is open
+    fft_export_options: FftExportOptions,
+    quick_look: bool, // Borderless preview without toolbar, Space closes the window
+    instance: Option<InstanceServer>, // Receives the files of later launches in single-instance mode
+    show_colormap_editor: bool, // Whether the colormap editor window is open
+    display_transform: Option<DisplayTransform>, // sRGB to monitor profile conversion, if enabled and found
+    display_profile_checked: Option<Instant>, // When the monitor profile was last looked up
+    display_profile_pos: Option<(i32, i32)>, // Window center (physical pixels) at the last lookup
+    roi_drag_start: Option<(u32, u32)>, // Pixel where the ROI drag started
+    zoom_drag: Option<PixelRect>, // Rectangle of a Ctrl + drag zoom to selection in progress
+    view_rect: Option<egui::Rect>, // Area of the central panel the image is shown in
+    minimap_rect: Option<egui::Rect>, // Navigation minimap, shown while the image extends beyond the view
+    wipe_handle_rect: Option<egui::Rect>, // Divider of the wipe compare view, dragging it does not pan
+    checkerboard: Option<egui::TextureHandle>, // 2×2 tile repeated behind transparent images
+    show_video_export: bool, // Whether the video export window is open
+    video_export_options: VideoExportOptions,
+    video_job: Option<VideoEncodeJob>, // Running ffmpeg encode
+    video_status: Option<String>, // Outcome of the last encode
+    show_batch_convert: bool, // Whether the batch convert window is open
+    batch_options: BatchOptions,
+    batch_filtered: bool, // Convert only the images that pass the rating filter
+    batch_job: Option<BatchJob>, // Running batch conversion
+    batch_status: Option<String>, // Outcome of the last batch conversion
+    crop: Option<CropTool>, // Active crop tool, dragging draws the crop rectangle
+    resize: Option<ResizeTool>, // Open resize dialog with the target size
+    annotate: Option<AnnotationEditor>, // Active annotation editor, dragging draws shapes
+    measure: Option<MeasureTool>, // Active ruler, clicks add measurement points
+    threshold: Option<ThresholdTool>, // Binarizes the displayed image while active
+    mask_overlay: Option<MaskOverlay>, // Label mask drawn over the image while active
+    expression: Option<ExpressionTool>, // Displays a derived channel in place of the normalization while active
 }
 
 #[derive(PartialEq, Clone, Copy)]
 enum ChannelType {
-    RGB,
+    Rgb,
+    Luminance,
     Red,
     Green,
     Blue,
+    Alpha,
 }
 
 impl ChannelType {
     fn as_str(&self) -> &'static str {
         match self {
-            ChannelType::RGB => "RGB",
+            ChannelType::Rgb => "RGB",
+            ChannelType::Luminance => "Luminance",
             ChannelType::Red => "Red",
             ChannelType::Green => "Green",
             ChannelType::Blue => "Blue",
+            ChannelType::Alpha => "Alpha",
         }
     }
 }
 
 
-impl Default for ImageViewerApp {
+impl Default for ImageDocument {
     fn default() -> Self {
         Self {
             image: None,
             image_path: None,
-            last_opened_folder: None,
+            file_size: None,
+            decode_time: None,
             scale: 1.0,
             base_scale: 1.0,
-            normalization: NormalizationType::None,
-            channel: ChannelType::RGB,
+            normalization: "none",
+            channel: ChannelType::Rgb,
             texture: None,
             offset: egui::Vec2::ZERO,
-            dragging: false,
             texture_needs_update: false,
             last_texture_scale: 1.0,
-            last_normalization: NormalizationType::None,
-            last_channel: ChannelType::RGB,
+            texture_magnification: egui::TextureFilter::Linear,
+            loupe_texture: None,
+            clipping: None,
+            last_normalization: "none",
+            last_channel: ChannelType::Rgb,
+            channel_as_gray: true,
             pixel_info: None,
+            pixel_info_display: None,
+            pixel_info_mean: None,
+            display_pixels: None,
             pixel_info_fp: None,
             pixel_info_channels: None,
-            show_pixel_tool: false,
-            hover_pos: None,
+            probes: Vec::new(),
+            annotations: Vec::new(),
+            tiff_calibration: None,
+            probes_dirty: false,
+            adjustments: Adjustments::default(),
+            pipeline: Pipeline::default(),
             is_floating_point_image: false,
+            is_hdr: false,
+            hdr_exposure: 0.0,
+            reduced_from: None,
+            full_decode: None,
+            tiled_tiff: None,
+            detail: None,
+            detail_load: None,
+            raw_develop: None,
+            frames: None,
+            volume: None,
+            ome: None,
             original_data_range: None,
+            non_finite_pixels: 0,
             original_fp_data: None,
             original_fp_dimensions: None,
             original_fp_channels: None,
-            show_histogram: false,
-            histogram_data: None,
+            band_mapping: None,
             histogram_needs_update: false,
-            histogram_shared_data: Arc::new(Mutex::new(HistogramData::default())),
-            histogram_window_id: None,
             folder_images: Vec::new(),
             current_image_index: None,
+            file_list: false,
+            filmstrip_scrolled_to: None,
+            orientation: Orientation::default(),
+            file_orientation: Orientation::default(),
+            edited: false,
+            history: History::default(),
+            view_orientation: Orientation::default(),
+            compare: None,
+            layers: LayerStack::default(),
+            colormap: None,
+            roi: None,
+            visible_region: None,
+            histogram_region: None,
+            histogram_bins: HistogramBins::default(),
+            histogram_alpha_mode: AlphaMode::default(),
         }
     }
 }
 
-impl ImageViewerApp {
-    fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        Self::default()
+impl ImageDocument {
+    /// Rebuild the texture on the next frame, for changes the texture cache key does not cover
+    /// such as the pipeline, adjustments, threshold, expression and layers
+    fn invalidate_texture(&mut self) {
+        self.texture = None;
+        self.texture_needs_update = true;
     }
+}
 
-    fn scan_folder_images(&mut self, current_path: &PathBuf) {
-        self.folder_images.clear();
-        self.current_image_index = None;
-        
-        if let Some(parent_dir) = current_path.parent() {
-            if let Ok(entries) = fs::read_dir(parent_dir) {
-                let supported_extensions = [
-                    "png", "jpg", "jpeg", "bmp", "tif", "tiff", "webp", "gif", 
-                    "avif", "hdr", "exr", "farbfeld", "qoi", "dds", "tga", 
-                    "pnm", "ff", "ico"
-                ];
-                
-                let mut image_files: Vec<PathBuf> = entries
-                    .filter_map(|entry| entry.ok())
-                    .filter(|entry| entry.file_type().ok().map_or(false, |ft| ft.is_file()))
-                    .map(|entry| entry.path())
-                    .filter(|path| {
-                        if let Some(ext) = path.extension() {
-                            let ext_str = ext.to_string_lossy().to_lowercase();
-                            supported_extensions.contains(&ext_str.as_str())
-                        } else {
-                            false
-                        }
-                    })
-                    .collect();
-                
-                // Sort alphabetically
-                image_files.sort();
-                
-                // Find current image index
-                if let Some(current_index) = image_files.iter().position(|p| p == current_path) {
-                    self.current_image_index = Some(current_index);
-                }
-                
-                self.folder_images = image_files;
-                info!("Found {} images in folder, current index: {:?}", 
-                      self.folder_images.len(), self.current_image_index);
-            }
+impl Default for ImageViewerApp {
+    fn default() -> Self {
+        Self {
+            doc: ImageDocument::default(),
+            tabs: Vec::new(),
+            active_tab: 0,
+            windows: Vec::new(),
+            next_window_id: 0,
+            sync_windows: false,
+            synced_view: None,
+            last_opened_folder: None,
+            transforms: TransformRegistry::default(),
+            dragging: false,
+            show_pixel_tool: false,
+            show_loupe: false,
+            show_layers: false,
+            show_clipping: false,
+            loupe: Loupe::default(),
+            context_pixel_text: None,
+            context_pixel: None,
+            show_probes: false,
+            show_info: false,
+            info_properties: None,
+            show_adjustments: false,
+            show_pipeline: false,
+            integrity: IntegrityState::default(),
+            show_integrity: false,
+            palette: Vec::new(),
+            picked_color: None,
+            hover_pos: None,
+            hover_pixel: None,
+            show_histogram: false,
+            histogram_shared_data: Arc::new(Mutex::new(HistogramData::default())),
+            histogram_window_id: None,
+            gpu_histogram: GpuHistogram::default(),
+            histogram_job: HistogramJob::default(),
+            watcher: None,
+            unwatchable_folder: None,
+            show_filmstrip: true,
+            gallery: None,
+            thumbnails: None,
+            image_cache: ImageCache::new(CacheBudget::default().bytes()),
+            hint_index: HintIndex::new(load_image_source),
+            config: Config::default(),
+            show_settings: false,
+            recording_binding: None,
+            open_dialog_requested: false,
+            show_orientation_save: false,
+            pending_delete: Vec::new(),
+            load_error: None,
+            last_move_folder: None,
+            rename: None,
+            ratings: Ratings::default(),
+            min_rating: 0,
+            window_title: String::new(),
+            show_fft: false,
+            fft_view: FftView::default(),
+            show_fft_export: false,
+            fft_export_options: FftExportOptions::default(),
+            quick_look: false,
+            instance: None,
+            show_colormap_editor: false,
+            display_transform: None,
+            display_profile_checked: None,
+            display_profile_pos: None,
+            roi_drag_start: None,
+            zoom_drag: None,
+            view_rect: None,
+            minimap_rect: None,
+            wipe_handle_rect: None,
+            checkerboard: None,
+            show_video_export: false,
+            video_export_options: VideoExportOptions::default(),
+            video_job: None,
+            video_status: None,
+            show_batch_convert: false,
+            batch_options: BatchOptions::default(),
+            batch_filtered: false,
+            batch_job: None,
+            batch_status: None,
+            crop: None,
+            resize: None,
+            annotate: None,
+            measure: None,
+            threshold: None,
+            mask_overlay: None,
+            expression: None,
         }
     }
+}
 
-    fn navigate_to_adjacent_image(&mut self, direction: i32) -> anyhow::Result<()> {
-        if self.folder_images.is_empty() {
-            return Ok(());
-        }
-        
-        let current_index = self.current_image_index.unwrap_or(0);
-        let new_index = if direction < 0 {
-            // Previous image (left arrow)
-            if current_index == 0 {
-                self.folder_images.len() - 1 // Wrap to last image
-            } else {
-                current_index - 1
-            }
-        } else {
-            // Next image (right arrow)
-            if current_index >= self.folder_images.len() - 1 {
-                0 // Wrap to first image
-            } else {
-                current_index + 1
-            }
+impl ImageViewerApp {
+    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let config = Config::load();
+        let app = Self {
+            image_cache: ImageCache::new(config.image_cache.bytes()),
+            config,
+            ..Self::default()
         };
-        
-        if new_index < self.folder_images.len() {
-            let new_path = self.folder_images[new_index].clone();
-            info!("Navigating to image {}/{}: {:?}", 
-                  new_index + 1, self.folder_images.len(), new_path);
-            self.load_image(new_path)?;
+        app.apply_appearance(&cc.egui_ctx);
+        app
+    }
+
+    /// Theme and interface scale from the settings, shared by all windows
+    fn apply_appearance(&self, ctx: &egui::Context) {
+        ctx.set_theme(self.config.theme.preference());
+        ctx.set_zoom_factor(self.config.ui_scale.0.clamp(UiScale::MIN, UiScale::MAX));
+    }
+
+    /// Frame of the central panel, filled with the canvas color when one is set
+    fn canvas_frame(&self, ctx: &egui::Context) -> egui::Frame {
+        let frame = egui::Frame::central_panel(&ctx.style());
+        match self.config.canvas_color {
+            Some([r, g, b]) => frame.fill(egui::Color32::from_rgb(r, g, b)),
+            None => frame,
         }
-        
-        Ok(())
     }
 
-    fn load_image(&mut self, path: PathBuf) -> anyhow::Result<()> {
-        let (img, is_fp, data_range, fp_data, fp_dims, fp_channels) = self.load_image_with_fallback(&path)?;
+    /// Pick an image and show it in the active tab, or in a new tab after it
+    fn open_file_dialog(&mut self, ctx: &egui::Context, new_tab: bool) {
+        // Create a file dialog with image filters
+        let file_dialog = rfd::FileDialog::new()
+            .add_filter("Images", &["png", "jpg", "jpeg", "bmp", "tif", "tiff", "webp", "gif", "avif", "hdr", "exr", "farbfeld", "qoi", "dds", "tga", "pnm", "ff", "ico"])
+            .add_filter("Camera RAW", &raw::RAW_EXTENSIONS)
+            .add_filter("NIfTI volume", &["nii", "gz"]);
         
-        // Calculate base scale to fit image in window
-        let (img_width, img_height) = img.dimensions();
-        let max_display_size = 1024.0 - 100.0; // Account for UI
-        let scale_w = max_display_size / img_width as f32;
-        let scale_h = max_display_size / img_height as f32;
-        self.base_scale = scale_w.min(scale_h).min(1.0);
+        // Start in the last opened folder, then the configured default directory
+        let start_directory = match &self.last_opened_folder {
+            Some(last_folder) if last_folder.exists() => last_folder.clone(),
+            _ => self.config.start_directory(),
+        };
+        let file_dialog = file_dialog.set_directory(start_directory);
         
-        // Store original image without resizing
-        self.image = Some(img);
-        self.image_path = Some(path.clone());
-        // Store the folder path for future file dialogs
-        if let Some(parent) = path.parent() {
-            self.last_opened_folder = Some(parent.to_path_buf());
+        if let Some(path) = file_dialog.pick_file() {
+            info!("Opening image from path: {:?}", path);
+            let loaded = if new_tab { self.open_in_new_tab(path) } else { self.load_image(path) };
+            if let Err(e) = loaded {
+                error!("Failed to load image: {}", e);
+                self.report_load_error(&e);
+            } else {
+                // Resize window to fit the new image
+                let (width, height) = self.calculate_window_size();
+                ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(width, height)));
+            }
         }
-        self.is_floating_point_image = is_fp;
-        self.original_data_range = data_range;
-        // Store floating point data if available
-        self.original_fp_data = fp_data;
-        self.original_fp_dimensions = fp_dims;
-        self.original_fp_channels = fp_channels;
-        self.offset = egui::Vec2::ZERO;
-        self.scale = 1.0; // Reset user scale
-        self.texture = None;
-        self.texture_needs_update = true;
-        // Reset cached values
-        self.last_texture_scale = 1.0;
-        self.last_normalization = self.normalization;
-        self.last_channel = self.channel;
-        // Mark histogram for update
-        self.histogram_needs_update = true;
-        self.histogram_data = None;
-        
-        // Scan folder for adjacent images
-        self.scan_folder_images(&path);
-        
-        Ok(())
     }
-    
-    fn load_image_with_fallback(&self, path: &PathBuf) -> anyhow::Result<(DynamicImage, bool, Option<(f32, f32)>, Option<Vec<f32>>, Option<(u32, u32)>, Option<u32>)> {
-        // Try the standard image crate first
-        match image::open(path) {
-            Ok(img) => {
-                info!("Successfully loaded image using standard image crate");
-                return Ok((img, false, None, None, None, None));
-            }
-            Err(e) => {
-                warn!("Standard image loading failed: {}", e);
+
+    fn render_settings_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_settings;
+        let mut changed = false;
+        let mut rebuild_texture = false; // Settings that change how the texture is built
+        
+        egui::Window::new("Settings")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.heading("Startup");
+                ui.horizontal(|ui| {
+                    ui.label("When started without an image:");
+                    egui::ComboBox::from_id_salt("startup_behavior")
+                        .selected_text(self.config.startup.as_str())
+                        .show_ui(ui, |ui| {
+                            for behavior in [StartupBehavior::Empty, StartupBehavior::OpenFileDialog, StartupBehavior::LastSession] {
+                                changed |= ui.selectable_value(&mut self.config.startup, behavior, behavior.as_str()).changed();
+                            }
+                        });
+                });
+                changed |= ui.checkbox(&mut self.config.multiple_instances, "Open launched files in a new window")
+                    .on_hover_text("By default, files opened from the file manager or command line while the viewer runs are shown in the running viewer. Applies from the next start.")
+                    .changed();
                 
-                // Check if it's a TIFF file and try direct TIFF loading
-                if let Some(ext) = path.extension() {
-                    if ext.to_string_lossy().to_lowercase() == "tiff" || ext.to_string_lossy().to_lowercase() == "tif" {
-                        info!("Attempting to load TIFF file with direct TIFF decoder");
-                        return self.load_tiff_direct(path);
+                ui.horizontal(|ui| {
+                    ui.label("Default directory:");
+                    match &self.config.default_directory {
+                        Some(dir) => ui.label(paths::display(dir)),
+                        None => ui.weak(format!("System default ({})", paths::display(&config::platform_default_directory()))),
+                    };
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Choose…").clicked() {
+                        if let Some(dir) = rfd::FileDialog::new().set_directory(self.config.start_directory()).pick_folder() {
+                            self.config.default_directory = Some(dir);
+                            changed = true;
+                        }
                     }
-                }
+                    if ui.add_enabled(self.config.default_directory.is_some(), egui::Button::new("Use system default")).clicked() {
+                        self.config.default_directory = None;
+                        changed = true;
+                    }
+                });
                 
-                // If not TIFF or TIFF loading failed, return the original error
-                return Err(e.into());
-            }
-        }
-    }
-    
-    fn load_tiff_direct(&self, path: &PathBuf) -> anyhow::Result<(DynamicImage, bool, Option<(f32, f32)>, Option<Vec<f32>>, Option<(u32, u32)>, Option<u32>)> {
-        let file = File::open(path)?;
-        let mut decoder = tiff::decoder::Decoder::new(BufReader::new(file))?;
-        
-        // Read the image
-        let (width, height) = decoder.dimensions()?;
-        let colortype = decoder.colortype()?;
-        
-        info!("TIFF dimensions: {}x{}, colortype: {:?}", width, height, colortype);
-        
-        match colortype {
-            tiff::ColorType::Gray(8) => {
-                match decoder.read_image()? {
-                    tiff::decoder::DecodingResult::U8(img_data) => {
-                        let img_buffer = ImageBuffer::from_raw(width, height, img_data)
-                            .ok_or_else(|| anyhow::anyhow!("Failed to create image buffer from TIFF data"))?;
-                        Ok((DynamicImage::ImageLuma8(img_buffer), false, None, None, None, None))
+                changed |= ui.checkbox(&mut self.config.skip_classification, "Don't flag suspicious images in folders")
+                    .on_hover_text("Skips checking folder images for nearly black, nearly white, very low contrast and large constant borders")
+                    .changed();
+                changed |= ui.checkbox(&mut self.config.disable_auto_reload, "Don't reload images changed on disk")
+                    .on_hover_text("By default the open image is reloaded when another program overwrites it, and the folder list follows added and removed files")
+                    .changed();
+                changed |= ui.checkbox(&mut self.config.skip_delete_confirmation, "Don't confirm moving files to the trash")
+                    .changed();
+                ui.horizontal(|ui| {
+                    ui.label("External editor:");
+                    changed |= ui.add(egui::TextEdit::singleline(&mut self.config.external_editor).hint_text("gimp {path}"))
+                        .on_hover_text("Command run by Open in external editor (E), {path} is replaced by the file or appended when missing")
+                        .changed();
+                });
+                
+                ui.separator();
+                ui.heading("Appearance");
+                let mut appearance_changed = false;
+                ui.horizontal(|ui| {
+                    ui.label("Theme:");
+                    egui::ComboBox::from_id_salt("theme")
+                        .selected_text(self.config.theme.as_str())
+                        .show_ui(ui, |ui| {
+                            for theme in Theme::ALL {
+                                appearance_changed |= ui.selectable_value(&mut self.config.theme, theme, theme.as_str()).changed();
+                            }
+                        });
+                });
+                ui.horizontal(|ui| {
+                    let mut custom = self.config.canvas_color.is_some();
+                    if ui.checkbox(&mut custom, "Canvas color:")
+                        .on_hover_text("Fill the view around the image with this color instead of the panel color of the theme")
+                        .changed()
+                    {
+                        self.config.canvas_color = custom.then_some([32, 32, 32]);
+                        changed = true;
                     }
-                    _ => Err(anyhow::anyhow!("Unexpected data type for Gray(8) TIFF")),
-                }
-            }
-            tiff::ColorType::Gray(16) => {
-                match decoder.read_image()? {
-                    tiff::decoder::DecodingResult::U16(img_data) => {
-                        let img_buffer = ImageBuffer::from_raw(width, height, img_data)
-                            .ok_or_else(|| anyhow::anyhow!("Failed to create image buffer from TIFF data"))?;
-                        Ok((DynamicImage::ImageLuma16(img_buffer), false, None, None, None, None))
+                    if let Some(color) = &mut self.config.canvas_color {
+                        changed |= ui.color_edit_button_srgb(color).changed();
                     }
-                    _ => Err(anyhow::anyhow!("Unexpected data type for Gray(16) TIFF")),
-                }
-            }
-            tiff::ColorType::RGB(8) => {
-                match decoder.read_image()? {
-                    tiff::decoder::DecodingResult::U8(img_data) => {
-                        let img_buffer = ImageBuffer::from_raw(width, height, img_data)
-                            .ok_or_else(|| anyhow::anyhow!("Failed to create image buffer from TIFF data"))?;
-                        Ok((DynamicImage::ImageRgb8(img_buffer), false, None, None, None, None))
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Interface scale:");
+                    let response = ui.add(egui::DragValue::new(&mut self.config.ui_scale.0).range(UiScale::MIN..=UiScale::MAX).speed(0.01).fixed_decimals(2).suffix("×"))
+                        .on_hover_text("Size of text and controls, on top of the scale of the OS");
+                    changed |= response.changed();
+                    // Applied once the drag ends, rescaling under the pointer would move the value
+                    appearance_changed |= response.drag_stopped() || (response.changed() && !response.dragged());
+                    if ui.add_enabled(self.config.ui_scale != UiScale::default(), egui::Button::new("Reset")).clicked() {
+                        self.config.ui_scale = UiScale::default();
+                        appearance_changed = true;
                     }
-                    _ => Err(anyhow::anyhow!("Unexpected data type for RGB(8) TIFF")),
+                });
+                if appearance_changed {
+                    changed = true;
+                    self.apply_appearance(ctx);
                 }
-            }
-            tiff::ColorType::RGB(16) => {
-                match decoder.read_image()? {
-                    tiff::decoder::DecodingResult::U16(img_data) => {
-                        let img_buffer = ImageBuffer::from_raw(width, height, img_data)
-                            .ok_or_else(|| anyhow::anyhow!("Failed to create image buffer from TIFF data"))?;
-                        Ok((DynamicImage::ImageRgb16(img_buffer), false, None, None, None, None))
+                
+                ui.separator();
+                ui.heading("Display");
+                changed |= ui.checkbox(&mut self.config.color_management, "Color-manage display output")
+                    .on_hover_text("Convert the displayed sRGB image to the ICC profile of the monitor the window is on")
+                    .changed();
+                ui.horizontal(|ui| {
+                    ui.label("Monitor profile:");
+                    match &self.config.display_profile {
+                        Some(path) => ui.label(paths::display(path)),
+                        None => ui.weak("From the operating system"),
+                    };
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Choose ICC file…").clicked() {
+                        if let Some(path) = rfd::FileDialog::new().add_filter("ICC profile", &["icc", "icm"]).pick_file() {
+                            self.config.display_profile = Some(path);
+                            changed = true;
+                        }
                     }
-                    _ => Err(anyhow::anyhow!("Unexpected data type for RGB(16) TIFF")),
-                }
-            }
-            tiff::ColorType::RGBA(8) => {
-                match decoder.read_image()? {
-                    tiff::decoder::DecodingResult::U8(img_data) => {
-                        let img_buffer = ImageBuffer::from_raw(width, height, img_data)
-                            .ok_or_else(|| anyhow::anyhow!("Failed to create image buffer from TIFF data"))?;
-                        Ok((DynamicImage::ImageRgba8(img_buffer), false, None, None, None, None))
+                    if ui.add_enabled(self.config.display_profile.is_some(), egui::Button::new("Use OS profile")).clicked() {
+                        self.config.display_profile = None;
+                        changed = true;
                     }
-                    _ => Err(anyhow::anyhow!("Unexpected data type for RGBA(8) TIFF")),
+                });
+                if self.config.color_management {
+                    match self.display_transform {
+                        Some(_) => ui.weak("Display transform active"),
+                        None => ui.weak("No profile found for this monitor, showing sRGB unchanged"),
+                    };
                 }
-            }
-            tiff::ColorType::RGBA(16) => {
-                match decoder.read_image()? {
-                    tiff::decoder::DecodingResult::U16(img_data) => {
-                        let img_buffer = ImageBuffer::from_raw(width, height, img_data)
-                            .ok_or_else(|| anyhow::anyhow!("Failed to create image buffer from TIFF data"))?;
-                        Ok((DynamicImage::ImageRgba16(img_buffer), false, None, None, None, None))
+                ui.horizontal(|ui| {
+                    ui.label("Zoomed out filter:");
+                    ui.add_enabled_ui(!self.config.disable_downscaling, |ui| {
+                        egui::ComboBox::from_id_salt("downscale_filter")
+                            .selected_text(self.config.downscale_filter.as_str())
+                            .show_ui(ui, |ui| {
+                                for filter in DownscaleFilter::ALL {
+                                    rebuild_texture |= ui.selectable_value(&mut self.config.downscale_filter, filter, filter.as_str()).changed();
+                                }
+                            });
+                    });
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Zoomed in filter:");
+                    egui::ComboBox::from_id_salt("magnification_filter")
+                        .selected_text(self.config.magnification.as_str())
+                        .show_ui(ui, |ui| {
+                            for filter in MagnificationFilter::ALL {
+                                changed |= ui.selectable_value(&mut self.config.magnification, filter, filter.as_str()).changed();
+                            }
+                        });
+                });
+                rebuild_texture |= ui.checkbox(&mut self.config.disable_downscaling, "Don't downscale when zoomed out")
+                    .on_hover_text("Shows the full resolution image scaled by the GPU, faster on slow CPUs but with more aliasing")
+                    .changed();
+                changed |= ui.checkbox(&mut self.config.hide_pixel_grid, "Hide the pixel grid")
+                    .on_hover_text(format!("By default pixels are outlined from {}× zoom on", PIXEL_GRID_MIN_SCALE))
+                    .changed();
+                changed |= ui.checkbox(&mut self.config.show_pixel_values, "Show pixel values at high zoom")
+                    .on_hover_text("Print the value of every pixel inside its cell once the cells are large enough, floating point data when the image has it")
+                    .changed();
+                ui.horizontal(|ui| {
+                    ui.label("NaN/Inf color:");
+                    rebuild_texture |= ui.color_edit_button_srgb(&mut self.config.non_finite_color.0)
+                        .on_hover_text("Floating point pixels with a NaN or infinite value are shown in this color")
+                        .changed();
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Decoded image cache:");
+                    if ui.add(egui::DragValue::new(&mut self.config.image_cache.0).range(0..=CacheBudget::MAX).speed(16).suffix(" MB"))
+                        .on_hover_text("Recently viewed images are kept decoded, going back to one skips loading it again. 0 turns the cache off.")
+                        .changed()
+                    {
+                        changed = true;
+                        self.image_cache.set_budget(self.config.image_cache.bytes());
                     }
-                    _ => Err(anyhow::anyhow!("Unexpected data type for RGBA(16) TIFF")),
+                    let (images, bytes) = self.image_cache.usage();
+                    ui.weak(format!("{} images, {:.0} MB used", images, bytes as f64 / (1024.0 * 1024.0)));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Undo memory:");
+                    changed |= ui.add(egui::DragValue::new(&mut self.config.undo_memory.0).range(0..=CacheBudget::MAX).speed(16).suffix(" MB"))
+                        .on_hover_text("States before rotations, flips, crops and resizes kept for undo, the oldest are dropped beyond this. 0 turns undo off.")
+                        .changed();
+                });
+                if ui.checkbox(&mut self.config.cpu_histogram, "Compute histograms on the CPU")
+                    .on_hover_text(match self.gpu_histogram.unsupported() {
+                        Some(reason) => format!("The GPU cannot be used: {}", reason),
+                        None => format!("Histograms of images with more than {} megapixels are computed on the GPU otherwise", GPU_HISTOGRAM_MIN_PIXELS >> 20),
+                    })
+                    .changed()
+                {
+                    changed = true;
+                    self.doc.histogram_needs_update = true;
                 }
-            }
-            // Handle floating point formats that might not be supported by the image crate
-            tiff::ColorType::Gray(32) => {
-                info!("Loading 32-bit floating point grayscale TIFF");
-                match decoder.read_image()? {
-                    tiff::decoder::DecodingResult::F32(img_data) => {
-                        // Find min/max values for proper normalization
-                        let min_val = img_data.iter().fold(f32::INFINITY, |a, &b| a.min(b));
-                        let max_val = img_data.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b));
-                        
-                        info!("TIFF F32 range: {} to {}", min_val, max_val);
-                        
-                        // Convert f32 to u8 for display with proper normalization
-                        let converted_data: Vec<u8> = if (max_val - min_val).abs() > f32::EPSILON {
-                            img_data.iter()
-                                .map(|&val| (((val - min_val) / (max_val - min_val)) * 255.0) as u8)
-                                .collect()
-                        } else {
-                            // If all values are the same, use them directly or set to middle gray
-                            vec![128u8; img_data.len()]
+                
+                ui.separator();
+                ui.heading("Export file names");
+                let size = self.doc.image.as_ref().map(|img| img.dimensions()).unwrap_or((640, 480));
+                let context = self.naming_context(None, size, None);
+                // Edited on a copy, the context borrows the app
+                let mut templates = self.config.export_naming.clone();
+                egui::Grid::new("export_naming").num_columns(3).show(ui, |ui| {
+                    for (label, template) in [("Crop:", &mut templates.crop), ("Resize:", &mut templates.resize), ("Annotated:", &mut templates.annotated), ("View:", &mut templates.view), ("FFT:", &mut templates.fft), ("Frames:", &mut templates.frames)] {
+                        ui.label(label);
+                        changed |= ui.add(egui::TextEdit::singleline(template).desired_width(200.0)).changed();
+                        match naming::expand(template, &context, SystemTime::now()) {
+                            Ok(name) => ui.weak(name),
+                            Err(e) => ui.colored_label(egui::Color32::from_rgb(255, 100, 100), e.to_string()),
                         };
-                        
-                        let img_buffer = ImageBuffer::from_raw(width, height, converted_data)
-                            .ok_or_else(|| anyhow::anyhow!("Failed to create image buffer from TIFF data"))?;
-                        Ok((DynamicImage::ImageLuma8(img_buffer), true, Some((min_val, max_val)), Some(img_data), Some((width, height)), Some(1)))
+                        ui.end_row();
                     }
-                    _ => Err(anyhow::anyhow!("Unexpected data type for Gray(32) TIFF")),
-                }
-            }
-            tiff::ColorType::RGB(32) => {
-                info!("Loading 32-bit floating point RGB TIFF");
-                match decoder.read_image()? {
-                    tiff::decoder::DecodingResult::F32(img_data) => {
-                        // Find min/max values for proper normalization
-                        let min_val = img_data.iter().fold(f32::INFINITY, |a, &b| a.min(b));
-                        let max_val = img_data.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b));
-                        
-                        info!("TIFF F32 range: {} to {}", min_val, max_val);
-                        
-                        // Convert f32 to u8 for display with proper normalization
-                        let converted_data: Vec<u8> = if (max_val - min_val).abs() > f32::EPSILON {
-                            img_data.iter()
-                                .map(|&val| (((val - min_val) / (max_val - min_val)) * 255.0) as u8)
-                                .collect()
-                        } else {
-                            // If all values are the same, use them directly or set to middle gray
-                            vec![128u8; img_data.len()]
-                        };
-                        
-                        let img_buffer = ImageBuffer::from_raw(width, height, converted_data)
-                            .ok_or_else(|| anyhow::anyhow!("Failed to create image buffer from TIFF data"))?;
-                        Ok((DynamicImage::ImageRgb8(img_buffer), true, Some((min_val, max_val)), Some(img_data), Some((width, height)), Some(3)))
+                });
+                ui.horizontal(|ui| {
+                    ui.weak("Tokens:");
+                    for (token, description) in naming::TOKENS {
+                        ui.weak(token).on_hover_text(description);
                     }
-                    _ => Err(anyhow::anyhow!("Unexpected data type for RGB(32) TIFF")),
+                });
+                if ui.add_enabled(templates != naming::ExportNaming::default(), egui::Button::new("Reset names")).clicked() {
+                    templates = naming::ExportNaming::default();
+                    changed = true;
                 }
-            }
-            tiff::ColorType::RGBA(32) => {
-                info!("Loading 32-bit floating point RGBA TIFF");
-                match decoder.read_image()? {
-                    tiff::decoder::DecodingResult::F32(img_data) => {
-                        // Find min/max values for proper normalization (excluding alpha channel)
-                        let pixel_count = (width * height) as usize;
-                        let rgb_data = &img_data[..pixel_count * 3]; // Only RGB channels for normalization
-                        
-                        let min_val = rgb_data.iter().fold(f32::INFINITY, |a, &b| a.min(b));
-                        let max_val = rgb_data.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b));
-                        
-                        info!("TIFF F32 range: {} to {}", min_val, max_val);
-                        
-                        // Convert f32 to u8 for display with proper normalization
-                        let converted_data: Vec<u8> = if (max_val - min_val).abs() > f32::EPSILON {
-                            img_data.chunks(4)
-                                .flat_map(|pixel| {
-                                    let r = (((pixel[0] - min_val) / (max_val - min_val)) * 255.0) as u8;
-                                    let g = (((pixel[1] - min_val) / (max_val - min_val)) * 255.0) as u8;
-                                    let b = (((pixel[2] - min_val) / (max_val - min_val)) * 255.0) as u8;
-                                    let a = (pixel[3].clamp(0.0, 1.0) * 255.0) as u8; // Alpha stays 0-1
-                                    [r, g, b, a]
-                                })
-                                .collect()
-                        } else {
-                            // If all values are the same, use middle gray
-                            img_data.chunks(4)
-                                .flat_map(|pixel| {
-                                    let a = (pixel[3].clamp(0.0, 1.0) * 255.0) as u8;
-                                    [128u8, 128u8, 128u8, a]
-                                })
-                                .collect()
-                        };
-                        
-                        let img_buffer = ImageBuffer::from_raw(width, height, converted_data)
-                            .ok_or_else(|| anyhow::anyhow!("Failed to create image buffer from TIFF data"))?;
-                        Ok((DynamicImage::ImageRgba8(img_buffer), true, Some((min_val, max_val)), Some(img_data), Some((width, height)), Some(4)))
+                self.config.export_naming = templates;
+                
+                ui.separator();
+                ui.heading("Keyboard shortcuts");
+                egui::Grid::new("keybindings").num_columns(3).striped(true).show(ui, |ui| {
+                    for action in Action::ALL {
+                        ui.label(action.as_str());
+                        let binding = self.config.keybindings.get(action);
+                        let recording = self.recording_binding == Some(action);
+                        let text = if recording { "Press a key…".to_string() } else { binding.to_string() };
+                        let mut button = ui.add(egui::Button::new(text).min_size(egui::vec2(120.0, 0.0)).selected(recording));
+                        if let Some(other) = self.config.keybindings.conflict(action) {
+                            button = button.on_hover_text(format!("Also used by \"{}\"", other.as_str()));
+                        }
+                        if button.clicked() {
+                            self.recording_binding = if recording { None } else { Some(action) };
+                        }
+                        ui.horizontal(|ui| {
+                            if self.config.keybindings.conflict(action).is_some() {
+                                ui.colored_label(egui::Color32::YELLOW, "⚠");
+                            }
+                            if ui.add_enabled(!self.config.keybindings.is_default(action), egui::Button::new("Reset").small()).clicked() {
+                                self.config.keybindings.set(action, action.default_binding());
+                                changed = true;
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Reset all shortcuts").clicked() {
+                        self.config.keybindings.reset();
+                        self.recording_binding = None;
+                        changed = true;
+                    }
+                    ui.weak("Click a shortcut, then press the new key (Escape cancels)");
+                });
+                
+                // The next key press becomes the shortcut of the action being recorded
+                if let Some(action) = self.recording_binding {
+                    let pressed = ui.input(|i| {
+                        i.events.iter().find_map(|event| match event {
+                            egui::Event::Key { key, pressed: true, modifiers, .. } => Some(KeyBinding::new(*key, *modifiers)),
+                            _ => None,
+                        })
+                    });
+                    if let Some(binding) = pressed {
+                        if binding.key != egui::Key::Escape {
+                            self.config.keybindings.set(action, binding);
+                            changed = true;
+                        }
+                        self.recording_binding = None;
                     }
-                    _ => Err(anyhow::anyhow!("Unexpected data type for RGBA(32) TIFF")),
                 }
+                
+                if let Some(path) = Config::path() {
+                    ui.separator();
+                    ui.weak(format!("Stored in {}", paths::display(&path)));
+                }
+            });
+        
+        self.show_settings = open;
+        if !open {
+            self.recording_binding = None;
+        }
+        if rebuild_texture {
+            self.doc.invalidate_texture();
+            changed = true;
+        }
+        if changed {
+            // Look up the display profile again with the new settings
+            self.display_profile_checked = None;
+            if let Err(e) = self.config.save() {
+                error!("Failed to save settings: {}", e);
+            }
+        }
+    }
+
+    /// Fill the part of the view behind the image with the configured background.
+    /// The checkerboard is anchored at the image origin so it moves along when panning.
+    fn paint_background(&self, painter: &egui::Painter, rect: egui::Rect, origin: egui::Pos2) {
+        let color = match self.config.background {
+            Background::Checkerboard => {
+                if let Some(checkerboard) = &self.checkerboard {
+                    let period = 2.0 * CHECKER_SIZE;
+                    let uv = egui::Rect::from_min_max(
+                        ((rect.min - origin) / period).to_pos2(),
+                        ((rect.max - origin) / period).to_pos2(),
+                    );
+                    painter.image(checkerboard.id(), rect, uv, egui::Color32::WHITE);
+                }
+                return;
+            }
+            Background::Black => egui::Color32::BLACK,
+            Background::White => egui::Color32::WHITE,
+            Background::Custom => {
+                let [r, g, b] = self.config.background_color;
+                egui::Color32::from_rgb(r, g, b)
+            }
+        };
+        painter.rect_filled(rect, egui::CornerRadius::ZERO, color);
+    }
+
+    /// Look up the ICC profile of the monitor the window is on and rebuild the display transform
+    /// when it changed. Checked at most once per second, and only after the window moved.
+    fn update_display_transform(&mut self, ctx: &egui::Context) {
+        if !self.config.color_management {
+            if self.display_transform.take().is_some() {
+                self.doc.invalidate_texture();
+            }
+            return;
+        }
+        
+        let center = ctx.input(|i| {
+            let info = i.viewport();
+            let pixels_per_point = info.native_pixels_per_point.unwrap_or(1.0);
+            info.outer_rect.map(|r| ((r.center().x * pixels_per_point) as i32, (r.center().y * pixels_per_point) as i32))
+        });
+        let due = self.display_profile_checked.is_none_or(|checked| {
+            checked.elapsed() >= Duration::from_secs(1) && center != self.display_profile_pos
+        });
+        if !due {
+            return;
+        }
+        self.display_profile_checked = Some(Instant::now());
+        self.display_profile_pos = center;
+        
+        let icc = match &self.config.display_profile {
+            Some(path) => fs::read(path).map(Some).map_err(anyhow::Error::from),
+            None => display_profile::query_display_profile(center),
+        };
+        let transform = match icc {
+            Ok(Some(icc)) => {
+                if self.display_transform.as_ref().is_some_and(|t| t.icc() == icc.as_slice()) {
+                    return;
+                }
+                match DisplayTransform::new(icc) {
+                    Ok(transform) => {
+                        info!("Using display profile for monitor at {:?}", center);
+                        Some(transform)
+                    }
+                    Err(e) => {
+                        warn!("{}", e);
+                        None
+                    }
+                }
+            }
+            Ok(None) => None,
+            Err(e) => {
+                warn!("Failed to get the display profile: {}", e);
+                None
+            }
+        };
+        if transform.is_some() || self.display_transform.is_some() {
+            self.display_transform = transform;
+            self.doc.invalidate_texture();
+        }
+    }
+    
+    /// Colormap currently used for false color, built-in or user defined
+    fn active_colormap(&self) -> Option<Colormap> {
+        let name = self.doc.colormap.as_ref()?;
+        colormap::builtin_colormaps()
+            .into_iter()
+            .chain(self.config.colormaps.iter().cloned())
+            .find(|c| &c.name == name)
+    }
+    
+    /// `base`, or `base (2)`, `base (3)`, … if a colormap of that name exists
+    fn unique_colormap_name(&self, base: &str) -> String {
+        let taken = |name: &str| {
+            colormap::builtin_colormaps().iter().any(|c| c.name == name) || self.config.colormaps.iter().any(|c| c.name == name)
+        };
+        if !taken(base) {
+            return base.to_string();
+        }
+        (2..).map(|n| format!("{} ({})", base, n)).find(|name| !taken(name)).unwrap()
+    }
+    
+    fn render_colormap_editor(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_colormap_editor;
+        let mut changed = false;
+        let builtin_names: Vec<String> = colormap::builtin_colormaps().into_iter().map(|c| c.name).collect();
+        
+        egui::Window::new("Colormaps")
+            .open(&mut open)
+            .resizable(true)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Import LUT…").on_hover_text("ImageJ .lut, CSV of RGB triplets or .cube").clicked() {
+                        if let Some(path) = rfd::FileDialog::new().add_filter("LUT", &["lut", "csv", "txt", "cube"]).pick_file() {
+                            match colormap::import_lut(&path) {
+                                Ok(mut imported) => {
+                                    imported.name = self.unique_colormap_name(&imported.name);
+                                    info!("Imported colormap {:?} with {} control points", imported.name, imported.stops.len());
+                                    self.doc.colormap = Some(imported.name.clone());
+                                    self.config.colormaps.push(imported);
+                                    changed = true;
+                                }
+                                Err(e) => error!("Failed to import LUT {:?}: {}", path, e),
+                            }
+                        }
+                    }
+                    if let Some(active) = self.active_colormap() {
+                        if ui.button("Duplicate").on_hover_text("Create an editable copy of the selected colormap").clicked() {
+                            let copy = Colormap { name: self.unique_colormap_name(&format!("{} copy", active.name)), ..active };
+                            self.doc.colormap = Some(copy.name.clone());
+                            self.config.colormaps.push(copy);
+                            changed = true;
+                        }
+                    }
+                });
+                ui.separator();
+                
+                // Built-in and user colormaps with a preview of each
+                let custom_names: Vec<String> = self.config.colormaps.iter().map(|c| c.name.clone()).collect();
+                for name in builtin_names.iter().chain(custom_names.iter()) {
+                    ui.horizontal(|ui| {
+                        if let Some(cmap) = colormap::builtin_colormaps().iter().chain(self.config.colormaps.iter()).find(|c| &c.name == name) {
+                            paint_gradient(ui, cmap, egui::vec2(120.0, 14.0));
+                        }
+                        let selected = self.doc.colormap.as_ref() == Some(name);
+                        if ui.selectable_label(selected, name.as_str()).clicked() && !selected {
+                            self.doc.colormap = Some(name.clone());
+                            changed = true;
+                        }
+                        if builtin_names.contains(name) {
+                            ui.weak("built-in");
+                        }
+                    });
+                }
+                
+                // Gradient editor for the selected user colormap
+                let Some(index) = self.config.colormaps.iter().position(|c| Some(&c.name) == self.doc.colormap.as_ref()) else {
+                    return;
+                };
+                ui.separator();
+                
+                let mut name = self.config.colormaps[index].name.clone();
+                ui.horizontal(|ui| {
+                    ui.label("Name:");
+                    if ui.text_edit_singleline(&mut name).changed() && !name.trim().is_empty() && self.unique_colormap_name(&name) == name {
+                        self.config.colormaps[index].name = name.clone();
+                        self.doc.colormap = Some(name);
+                    }
+                });
+                
+                let cmap = &mut self.config.colormaps[index];
+                paint_gradient(ui, cmap, egui::vec2(ui.available_width(), 24.0));
+                
+                let can_remove = cmap.stops.len() > 2;
+                let mut remove = None;
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    for (i, stop) in cmap.stops.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            changed |= ui.add(egui::Slider::new(&mut stop.position, 0.0..=1.0).fixed_decimals(3)).changed();
+                            changed |= ui.color_edit_button_srgb(&mut stop.color).changed();
+                            if ui.add_enabled(can_remove, egui::Button::new("✖")).on_hover_text("Remove control point").clicked() {
+                                remove = Some(i);
+                            }
+                        });
+                    }
+                });
+                if let Some(i) = remove {
+                    cmap.stops.remove(i);
+                    changed = true;
+                }
+                
+                let mut delete = false;
+                ui.horizontal(|ui| {
+                    if ui.button("Add stop").on_hover_text("Insert a control point in the largest gap").clicked() {
+                        cmap.stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+                        let position = cmap
+                            .stops
+                            .windows(2)
+                            .max_by(|a, b| (a[1].position - a[0].position).total_cmp(&(b[1].position - b[0].position)))
+                            .map(|pair| (pair[0].position + pair[1].position) / 2.0)
+                            .unwrap_or(0.5);
+                        let color = cmap.sample(position);
+                        cmap.stops.push(ColorStop { position, color });
+                        cmap.stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+                        changed = true;
+                    }
+                    delete = ui.button("Delete colormap").clicked();
+                });
+                if delete {
+                    info!("Deleting colormap {:?}", self.config.colormaps[index].name);
+                    self.config.colormaps.remove(index);
+                    self.doc.colormap = None;
+                    changed = true;
+                }
+            });
+        
+        if changed {
+            self.doc.invalidate_texture();
+        }
+        // Persist user colormaps when the editor is closed
+        if !open {
+            if let Err(e) = self.config.save() {
+                error!("Failed to save colormaps: {}", e);
+            }
+        }
+        self.show_colormap_editor = open;
+    }
+
+    /// Reload the open image when it changed on disk and rescan its folder when files were
+    /// added or removed
+    fn watch_files(&mut self, ctx: &egui::Context) {
+        let path = match &self.doc.image_path {
+            Some(path) if !self.config.disable_auto_reload => path.clone(),
+            _ => {
+                self.watcher = None;
+                return;
+            }
+        };
+        let folder = path.parent().unwrap_or(Path::new(""));
+        if self.watcher.as_ref().map(|watcher| watcher.folder()) != Some(folder) && self.unwatchable_folder.as_deref() != Some(folder) {
+            self.watcher = match FolderWatcher::new(ctx, &path) {
+                Ok(watcher) => Some(watcher),
+                Err(e) => {
+                    warn!("Cannot watch {:?} for changes: {}", folder, e);
+                    self.unwatchable_folder = Some(folder.to_path_buf());
+                    None
+                }
+            };
+        }
+        let Some(watcher) = &mut self.watcher else {
+            return;
+        };
+        watcher.set_file(&path);
+        let changes = watcher.poll(ctx);
+        // A removed file keeps showing, the folder rescan drops it from the list
+        if changes.file && path.exists() {
+            self.reload_image(&path);
+        } else if changes.folder && !self.doc.file_list {
+            info!("Files were added to or removed from {:?}", folder);
+            self.scan_folder_images(&path);
+        }
+    }
+
+    /// Load the open image again after it changed on disk, keeping the view when the size is unchanged
+    fn reload_image(&mut self, path: &Path) {
+        let (scale, offset) = (self.doc.scale, self.doc.offset);
+        let size = self.doc.image.as_ref().map(|img| img.dimensions());
+        match self.load_image(path.to_path_buf()) {
+            Ok(()) => {
+                info!("Reloaded {:?} after it changed on disk", path);
+                if self.doc.image.as_ref().map(|img| img.dimensions()) == size {
+                    self.doc.scale = scale;
+                    self.doc.offset = offset;
+                }
+                if let Some(thumbnails) = &mut self.thumbnails {
+                    thumbnails.invalidate(path);
+                }
+                self.integrity.invalidate();
+            }
+            // Possibly still being written, the next change retries
+            Err(e) => warn!("Reloading {:?} failed: {}", path, e),
+        }
+    }
+
+    fn scan_folder_images(&mut self, current_path: &PathBuf) {
+        self.doc.folder_images.clear();
+        self.doc.current_image_index = None;
+        
+        if let Some(parent_dir) = current_path.parent() {
+            let image_files = Self::list_folder_images(parent_dir);
+            
+            // Find current image index
+            if let Some(current_index) = image_files.iter().position(|p| p == current_path) {
+                self.doc.current_image_index = Some(current_index);
+            }
+            
+            self.doc.folder_images = image_files;
+            info!("Found {} images in folder, current index: {:?}", 
+                  self.doc.folder_images.len(), self.doc.current_image_index);
+        }
+    }
+
+    /// Supported images of a folder, sorted alphabetically
+    fn list_folder_images(folder: &Path) -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(folder) else {
+            return Vec::new();
+        };
+        let mut image_files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_ok_and(|ft| ft.is_file()))
+            .map(|entry| entry.path())
+            .filter(|path| Self::is_supported_image(path))
+            .collect();
+        
+        // Sort alphabetically
+        image_files.sort();
+        image_files
+    }
+
+    /// Whether the first bytes of the file are those of a format the image crate decodes
+    fn has_image_content(path: &Path) -> bool {
+        image::ImageReader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .is_ok_and(|reader| reader.format().is_some())
+    }
+
+    /// Whether the extension of `path` is one of the image formats the viewer opens
+    fn is_supported_image(path: &Path) -> bool {
+        let supported_extensions = [
+            "png", "jpg", "jpeg", "bmp", "tif", "tiff", "webp", "gif", 
+            "avif", "hdr", "exr", "farbfeld", "qoi", "dds", "tga", 
+            "pnm", "pbm", "pgm", "ppm", "pam", "ff", "ico"
+        ];
+        if let Some(ext) = path.extension() {
+            let ext_str = ext.to_string_lossy().to_lowercase();
+            supported_extensions.contains(&ext_str.as_str()) || raw::RAW_EXTENSIONS.contains(&ext_str.as_str()) || nifti::is_nifti_file(path)
+        } else {
+            false
+        }
+    }
+
+    /// Show the first image of a folder, with the folder's images as the navigation list
+    fn open_folder(&mut self, folder: &Path) -> anyhow::Result<()> {
+        let folder = paths::normalize(folder);
+        let images = Self::list_folder_images(&folder);
+        let Some(first) = images.first().cloned() else {
+            anyhow::bail!("No supported images in {:?}", folder);
+        };
+        info!("Opening folder {:?} with {} images", folder, images.len());
+        self.load_file(first)?;
+        self.doc.folder_images = images;
+        self.doc.current_image_index = Some(0);
+        self.doc.file_list = false;
+        Ok(())
+    }
+
+    /// Show the first of `images` with them as the navigation list, in the given order. Folders
+    /// add their images, files that are neither of a known type nor recognized as an image are
+    /// left out with a warning.
+    fn open_file_list(&mut self, images: Vec<PathBuf>) -> anyhow::Result<()> {
+        let images: Vec<PathBuf> = images.iter()
+            .map(|path| paths::normalize(path))
+            .flat_map(|path| {
+                if path.is_dir() {
+                    Self::list_folder_images(&path)
+                } else if Self::is_supported_image(&path) || Self::has_image_content(&path) {
+                    // Named files are kept when the content is an image, whatever the extension
+                    vec![path]
+                } else {
+                    warn!("Leaving {:?} out of the list, it is not a supported image", path);
+                    Vec::new()
+                }
+            })
+            .collect();
+        let Some(first) = images.first().cloned() else {
+            anyhow::bail!("No supported images in the given files");
+        };
+        info!("Opening a list of {} images", images.len());
+        self.load_file(first)?;
+        self.doc.folder_images = images;
+        self.doc.current_image_index = Some(0);
+        self.doc.file_list = true;
+        Ok(())
+    }
+
+    /// Open files dropped onto the window. A single file or folder opens like Open Image or Open
+    /// Folder, several become the navigation list in the order they were dropped.
+    fn open_dropped(&mut self, paths: Vec<PathBuf>) -> anyhow::Result<()> {
+        match paths.as_slice() {
+            [path] => self.load_image(path.clone()),
+            _ => self.open_file_list(paths),
+        }
+    }
+
+    /// Files a delete or move applies to: the gallery selection, or else the current image
+    fn file_targets(&mut self) -> Vec<PathBuf> {
+        if self.gallery.is_some() {
+            let items = self.gallery_items();
+            return self.gallery.as_ref().map(|gallery| gallery.selection(&items)).unwrap_or_default();
+        }
+        self.doc.image_path.clone().into_iter().collect()
+    }
+
+    /// Send the current image or the gallery selection to the trash, after a confirmation unless that is turned off
+    fn request_delete(&mut self) {
+        let paths = self.file_targets();
+        if paths.is_empty() {
+            return;
+        }
+        if self.config.skip_delete_confirmation {
+            self.delete_files(&paths);
+        } else {
+            self.pending_delete = paths;
+        }
+    }
+
+    fn delete_files(&mut self, paths: &[PathBuf]) {
+        let mut removed = Vec::new();
+        for path in paths {
+            match file_ops::move_to_trash(path) {
+                Ok(()) => {
+                    info!("Moved {:?} to the trash", path);
+                    removed.push(path.clone());
+                }
+                Err(e) => error!("{:#}", e),
+            }
+        }
+        self.files_removed(&removed);
+    }
+
+    /// Pick a folder and move the current image or the gallery selection into it
+    fn move_files(&mut self) {
+        let paths = self.file_targets();
+        let Some(first) = paths.first() else {
+            return;
+        };
+        let start_directory = self.last_move_folder.clone()
+            .filter(|folder| folder.exists())
+            .or_else(|| first.parent().map(Path::to_path_buf))
+            .unwrap_or_else(|| self.config.start_directory());
+        let Some(folder) = rfd::FileDialog::new().set_title("Move to…").set_directory(start_directory).pick_folder() else {
+            return;
+        };
+        let mut removed = Vec::new();
+        for path in paths {
+            match file_ops::move_to_folder(&path, &folder) {
+                Ok(target) => {
+                    info!("Moved {:?} to {:?}", path, target);
+                    removed.push(path);
+                }
+                Err(e) => error!("Failed to move the image: {:#}", e),
+            }
+        }
+        if !removed.is_empty() {
+            self.last_move_folder = Some(folder);
+        }
+        self.files_removed(&removed);
+    }
+
+    /// Show the current image or the first selected gallery item in the file manager
+    fn show_in_folder(&mut self) {
+        if let Some(path) = self.file_targets().first() {
+            if let Err(e) = file_ops::reveal_in_file_manager(path) {
+                error!("Failed to show {:?} in its folder: {:#}", path, e);
+            }
+        }
+    }
+
+    /// Open the current image or the gallery selection with the external editor from the settings
+    fn open_in_editor(&mut self) {
+        for path in self.file_targets() {
+            match file_ops::open_in_editor(&self.config.external_editor, &path) {
+                Ok(()) => info!("Opened {:?} in {}", path, self.config.external_editor),
+                Err(e) => error!("Failed to open {:?} in the external editor: {:#}", path, e),
+            }
+        }
+    }
+
+    /// Hand the current file to the drag and drop of the OS, for file managers and chat apps
+    fn drag_out(&mut self) {
+        let Some(path) = self.doc.image_path.clone() else {
+            return;
+        };
+        match drag_out::start(&path) {
+            Ok(()) => info!("Dragging {:?} out of the window", path),
+            Err(e) => warn!("Failed to drag the file out: {:#}", e),
+        }
+    }
+
+    /// Drop deleted or moved files from the folder list. If the current image was among them,
+    /// the image that followed the first of them is shown.
+    fn files_removed(&mut self, paths: &[PathBuf]) {
+        if paths.is_empty() {
+            return;
+        }
+        // Where the gallery focus was, it moves to the cell taking that place
+        let focus_index = match &self.gallery {
+            Some(gallery) => {
+                let focus = gallery.focus().map(Path::to_path_buf);
+                self.gallery_items().iter().position(|path| Some(path) == focus.as_ref())
+            }
+            None => None,
+        };
+        let mut first_index = None;
+        for path in paths {
+            self.ratings.invalidate(path);
+            if let Some(thumbnails) = &mut self.thumbnails {
+                thumbnails.invalidate(path);
+            }
+            self.image_cache.remove(path);
+            if let Some(index) = self.doc.folder_images.iter().position(|image| image == path) {
+                self.doc.folder_images.remove(index);
+                first_index = Some(first_index.map_or(index, |first: usize| first.min(index)));
+            }
+        }
+        self.integrity.invalidate();
+        if self.doc.image_path.as_ref().is_some_and(|current| paths.contains(current)) {
+            // The next image moved up to the index, after the last one the previous image is shown
+            let next = first_index.unwrap_or(0).min(self.doc.folder_images.len().saturating_sub(1));
+            match self.doc.folder_images.get(next).cloned() {
+                Some(next) => {
+                    if let Err(e) = self.load_image(next) {
+                        error!("Failed to load the next image: {}", e);
+                        self.report_load_error(&e);
+                    }
+                }
+                // The folder is empty now
+                None => {
+                    self.gallery = None;
+                    self.close_tab(self.active_tab);
+                }
+            }
+        } else {
+            self.doc.current_image_index = self.doc.folder_images.iter().position(|image| Some(image) == self.doc.image_path.as_ref());
+        }
+        if self.gallery.is_some() {
+            let items = self.gallery_items();
+            if let Some(gallery) = &mut self.gallery {
+                gallery.retain(&items, focus_index);
+            }
+        }
+    }
+
+    /// Open the rename dialog for the current image
+    fn start_rename(&mut self) {
+        if let Some(path) = self.doc.image_path.clone() {
+            let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            self.rename = Some(RenameDialog { path, name, focused: false });
+        }
+    }
+
+    /// Rename a file on disk and follow it in the folder list
+    fn rename_file(&mut self, path: &Path, name: &str) {
+        if path.file_name().is_some_and(|old| old == name.trim()) {
+            return;
+        }
+        let target = match file_ops::rename(path, name) {
+            Ok(target) => target,
+            Err(e) => {
+                error!("{:#}", e);
+                return;
+            }
+        };
+        info!("Renamed {:?} to {:?}", path, target);
+        self.ratings.invalidate(path);
+        self.ratings.invalidate(&target);
+        if let Some(thumbnails) = &mut self.thumbnails {
+            thumbnails.invalidate(path);
+        }
+        self.image_cache.remove(path);
+        self.integrity.invalidate();
+        if self.doc.image_path.as_deref() == Some(path) {
+            self.doc.image_path = Some(target.clone());
+        }
+        if let Some(image) = self.doc.folder_images.iter_mut().find(|image| image.as_path() == path) {
+            *image = target.clone();
+            self.doc.folder_images.sort();
+            self.doc.current_image_index = self.doc.folder_images.iter().position(|image| Some(image) == self.doc.image_path.as_ref());
+        }
+    }
+
+    /// Show the active image in the title bar: file name, position in the folder and zoom, with an
+    /// asterisk while rotations, crops or resizes are not saved
+    fn update_window_title(&mut self, ctx: &egui::Context) {
+        let title = match self.doc.image_path.as_ref().and_then(|path| path.file_name()) {
+            Some(name) => {
+                let unsaved = self.doc.edited || !self.doc.orientation.is_identity();
+                let mut title = format!("{}{}", name.to_string_lossy(), if unsaved { "*" } else { "" });
+                if let Some(index) = self.doc.current_image_index {
+                    title += &format!(" ({}/{})", index + 1, self.doc.folder_images.len());
+                }
+                format!("{} — {:.0}% — Image Viewer", title, self.doc.base_scale * self.doc.scale * 100.0)
+            }
+            None => "Image Viewer".to_string(),
+        };
+        if title != self.window_title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.window_title = title;
+        }
+    }
+
+    /// Pick a folder and show its images
+    fn open_folder_dialog(&mut self, ctx: &egui::Context) {
+        let start_directory = match &self.last_opened_folder {
+            Some(last_folder) if last_folder.exists() => last_folder.clone(),
+            _ => self.config.start_directory(),
+        };
+        if let Some(folder) = rfd::FileDialog::new().set_directory(start_directory).pick_folder() {
+            if let Err(e) = self.open_folder(&folder) {
+                error!("Failed to open folder: {}", e);
+            } else {
+                let (width, height) = self.calculate_window_size();
+                ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(width, height)));
+            }
+        }
+    }
+
+    fn navigate_to_adjacent_image(&mut self, direction: i32) -> anyhow::Result<()> {
+        if self.doc.folder_images.is_empty() {
+            return Ok(());
+        }
+        
+        let count = self.doc.folder_images.len();
+        let mut new_index = self.doc.current_image_index.unwrap_or(0);
+        // Wrap around at both ends, skipping images below the rating filter
+        for _ in 0..count {
+            new_index = if direction < 0 { (new_index + count - 1) % count } else { (new_index + 1) % count };
+            let new_path = self.doc.folder_images[new_index].clone();
+            if self.doc.current_image_index == Some(new_index) {
+                break;
+            }
+            if self.passes_rating_filter(&new_path) {
+                info!("Navigating to image {}/{}: {:?}", new_index + 1, count, new_path);
+                return self.load_image(new_path);
+            }
+        }
+        info!("No other image with at least {} stars", self.min_rating);
+        Ok(())
+    }
+
+    /// Show the first or last image of the folder that passes the rating filter
+    fn navigate_to_end(&mut self, last: bool) -> anyhow::Result<()> {
+        let count = self.doc.folder_images.len();
+        let indices: Vec<usize> = if last { (0..count).rev().collect() } else { (0..count).collect() };
+        for index in indices {
+            let path = self.doc.folder_images[index].clone();
+            if self.doc.current_image_index == Some(index) {
+                return Ok(());
+            }
+            if self.passes_rating_filter(&path) {
+                info!("Navigating to image {}/{}: {:?}", index + 1, count, path);
+                return self.load_image(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether folder navigation shows the image with the current rating filter
+    fn passes_rating_filter(&mut self, path: &Path) -> bool {
+        self.min_rating == 0 || self.ratings.get(path).stars >= self.min_rating
+    }
+
+    /// Change the rating of the current image and store it in the sidecar
+    fn update_rating(&mut self, change: impl FnOnce(&mut Rating)) {
+        let Some(path) = self.doc.image_path.clone() else {
+            return;
+        };
+        let mut rating = self.ratings.get(&path);
+        change(&mut rating);
+        match self.ratings.set(&path, rating) {
+            Ok(()) => info!("Rated {:?}: {} stars, label {:?}", path, rating.stars, rating.label),
+            Err(e) => error!("Failed to store the rating: {:#}", e),
+        }
+    }
+
+    /// Stars, color label and the navigation filter in the toolbar
+    fn render_rating_controls(&mut self, ui: &mut egui::Ui) {
+        let Some(path) = self.doc.image_path.clone() else {
+            return;
+        };
+        let rating = self.ratings.get(&path);
+        for star in 1..=5 {
+            let text = if star <= rating.stars { "★" } else { "☆" };
+            let response = ui.add(egui::Button::new(text).frame(false))
+                .on_hover_text(format!("Rate {} (Ctrl+{}), click again to clear", if star == 1 { "1 star".to_string() } else { format!("{} stars", star) }, star));
+            if response.clicked() {
+                self.update_rating(|rating| rating.stars = if rating.stars == star { 0 } else { star });
+            }
+        }
+        let label_text = match rating.label {
+            Some(label) => egui::RichText::new("⚫").color(label.color()),
+            None => egui::RichText::new("○"),
+        };
+        ui.menu_button(label_text, |ui| {
+            for label in ColorLabel::ALL {
+                let text = egui::RichText::new(format!("⚫ {}", label.as_str())).color(label.color());
+                if ui.selectable_label(rating.label == Some(label), text).clicked() {
+                    self.update_rating(|rating| rating.label = Some(label));
+                    ui.close_menu();
+                }
+            }
+            if ui.button("No label").clicked() {
+                self.update_rating(|rating| rating.label = None);
+                ui.close_menu();
+            }
+        }).response.on_hover_text("Color label (Ctrl+6 to Ctrl+9)");
+        if self.doc.folder_images.len() > 1 {
+            let filter_text = |stars: u8| if stars == 0 { "All images".to_string() } else { format!("{}+ stars", stars) };
+            egui::ComboBox::from_id_salt("rating_filter")
+                .selected_text(filter_text(self.min_rating))
+                .show_ui(ui, |ui| {
+                    for stars in 0..=5 {
+                        ui.selectable_value(&mut self.min_rating, stars, filter_text(stars));
+                    }
+                })
+                .response
+                .on_hover_text("Folder navigation and the filmstrip only show images with at least this many stars");
+        }
+    }
+
+    /// Show an image and list the images of its folder, unless it is part of a list given on the
+    /// command line. A folder shows its first image.
+    fn load_image(&mut self, path: PathBuf) -> anyhow::Result<()> {
+        if path.is_dir() {
+            return self.open_folder(&path);
+        }
+        let path = self.load_file(path)?;
+        // Images of a list given on the command line keep that list
+        let listed = self.doc.folder_images.iter().position(|image| *image == path).filter(|_| self.doc.file_list);
+        match listed {
+            Some(index) => self.doc.current_image_index = Some(index),
+            None => {
+                // Scan folder for adjacent images
+                self.doc.file_list = false;
+                self.scan_folder_images(&path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Show an image without touching the folder list. Returns the normalized path.
+    fn load_file(&mut self, path: PathBuf) -> anyhow::Result<PathBuf> {
+        // One absolute form, so the image is found again in the folder scan
+        let path = paths::normalize(&path);
+        let started = Instant::now();
+        let volume = if nifti::is_nifti_file(&path) { Some(nifti::load_volume(&path)?) } else { None };
+        let mut full_decode = None;
+        let mut decode_time = None;
+        let decoded = if let Some(volume) = &volume {
+            info!("NIfTI volume {:?}, showing axial slice {}", volume.dims(), volume.slice() + 1);
+            decode_time = Some(started.elapsed());
+            (Self::volume_slice(volume), None)
+        } else if let Some(decoded) = self.image_cache.get(&path) {
+            info!("Using the cached decode of {:?}", path);
+            decoded.clone()
+        } else if let Some((preview, size)) = large_image::quick_preview(&path) {
+            // Shown until the full resolution decode arrives
+            info!("Showing a {}x{} preview while decoding {:?}", preview.width(), preview.height(), path);
+            let decode_path = path.clone();
+            full_decode = Some(FullDecode::start(size, move || Self::load_image_with_fallback(&decode_path)));
+            ((preview, false, None, None, None, None), None)
+        } else {
+            let decoded = self.decode_file(&path)?;
+            decode_time = Some(started.elapsed());
+            decoded
+        };
+        // A decode still running for the previous image is dropped
+        self.doc.full_decode = full_decode;
+        self.show_decoded(&path, decoded, volume);
+        self.doc.decode_time = decode_time;
+        Ok(path)
+    }
+    
+    /// Replace the document's image with a decoded file and reset the state that belongs to the previous one
+    fn show_decoded(&mut self, path: &Path, decoded: (LoadedImage, Option<RawDevelop>), volume: Option<Volume>) {
+        let ((img, is_fp, data_range, fp_data, fp_dims, fp_channels), raw_develop) = decoded;
+        // Store original image without resizing
+        self.doc.image = Some(img);
+        self.doc.roi = None;
+        if let Some(crop) = &mut self.crop {
+            crop.rect = None;
+        }
+        self.update_base_scale();
+        self.doc.image_path = Some(path.to_path_buf());
+        self.doc.file_size = fs::metadata(path).ok().map(|metadata| metadata.len());
+        // Store the folder path for future file dialogs
+        if let Some(parent) = path.parent() {
+            self.last_opened_folder = Some(parent.to_path_buf());
+        }
+        self.doc.is_floating_point_image = is_fp;
+        self.doc.original_data_range = data_range;
+        // Store floating point data if available
+        self.doc.original_fp_data = fp_data;
+        self.doc.original_fp_dimensions = fp_dims;
+        self.doc.original_fp_channels = fp_channels;
+        self.count_non_finite();
+        self.doc.band_mapping = fp_channels.filter(|&channels| channels > 4).map(|_| BandMapping::default());
+        self.doc.is_hdr = is_fp && (tonemap::is_hdr_file(path) || raw_develop.is_some());
+        self.doc.raw_develop = raw_develop;
+        self.doc.reduced_from = large_image::reduced_from(path);
+        self.doc.tiled_tiff = self.doc.reduced_from.and_then(|_| TiledTiff::open(path)).map(Arc::new);
+        self.doc.detail = None;
+        self.doc.detail_load = None;
+        self.doc.volume = volume;
+        // Planes of huge files would be read whole, they show the first one
+        self.doc.ome = if self.doc.reduced_from.is_none() { OmeStack::open(path) } else { None };
+        self.doc.frames = if is_fp || self.doc.ome.is_some() { None } else { Self::load_frame_stack(path) };
+        if let Some(frames) = &self.doc.frames {
+            info!("{} frames, showing frame 1", frames.len());
+            self.doc.image = Some(frames.current().clone());
+        }
+        // The loader maps with the default curve and no exposure
+        if self.doc.is_hdr && (self.config.tone_mapper != ToneMapper::default() || self.doc.hdr_exposure != 0.0) {
+            self.apply_tone_mapping();
+        }
+        self.doc.offset = egui::Vec2::ZERO;
+        self.doc.scale = 1.0; // Reset user scale
+        self.doc.invalidate_texture();
+        // Reset cached values
+        self.doc.last_texture_scale = 1.0;
+        self.doc.last_normalization = self.doc.normalization;
+        self.doc.last_channel = self.doc.channel;
+        // Mark histogram for update
+        self.doc.histogram_needs_update = true;
+        // The file orientation is already applied by the loader
+        self.doc.orientation = Orientation::default();
+        self.doc.file_orientation = orientation::read_file_orientation(path);
+        self.doc.edited = false;
+        self.doc.history.clear();
+        self.show_orientation_save = false;
+        // Annotations are in full resolution pixels, they are loaded with the full decode
+        self.doc.annotations = if self.doc.full_decode.is_some() { Vec::new() } else { annotations::load(path).unwrap_or_else(|e| {
+            warn!("Failed to load annotations: {:#}", e);
+            Vec::new()
+        }) };
+        if let Some(editor) = &mut self.annotate {
+            editor.cancel();
+        }
+        self.doc.tiff_calibration = measure::tiff_calibration(path);
+        let calibration = self.file_calibration();
+        if let Some(measure) = &mut self.measure {
+            measure.image_changed(calibration);
+        }
+    }
+    
+    /// Decode an image file and keep it in the cache of recently decoded images
+    fn decode_file(&mut self, path: &Path) -> anyhow::Result<(LoadedImage, Option<RawDevelop>)> {
+        let decoded = if raw::is_raw_file(path) {
+            Self::load_raw(path).map(|(loaded, develop)| (loaded, Some(develop)))?
+        } else {
+            (Self::load_image_with_fallback(path)?, None)
+        };
+        self.cache_decoded(path, &decoded);
+        Ok(decoded)
+    }
+    
+    fn cache_decoded(&mut self, path: &Path, decoded: &(LoadedImage, Option<RawDevelop>)) {
+        let (image, _, _, fp_data, _, _) = &decoded.0;
+        let bytes = image.as_bytes().len() + fp_data.as_ref().map_or(0, |fp_data| fp_data.len() * size_of::<f32>());
+        self.image_cache.insert(path, decoded.clone(), bytes);
+    }
+    
+    /// Swap the preview for the full resolution image once its decode is done, keeping the view
+    fn poll_full_decode(&mut self, ctx: &egui::Context) {
+        let Some(full_decode) = &self.doc.full_decode else {
+            return;
+        };
+        let Some(result) = full_decode.poll() else {
+            ctx.request_repaint_after(Duration::from_millis(50));
+            return;
+        };
+        let decode_time = full_decode.started.elapsed();
+        self.doc.full_decode = None;
+        let Some(path) = self.doc.image_path.clone() else {
+            return;
+        };
+        match result {
+            Ok(loaded) => {
+                info!("Full resolution of {:?} decoded", path);
+                let decoded = (loaded, None);
+                self.cache_decoded(&path, &decoded);
+                // The view scale is relative to the fitted size, so the image stays where it was
+                let (scale, offset) = (self.doc.scale, self.doc.offset);
+                self.show_decoded(&path, decoded, None);
+                self.doc.scale = scale;
+                self.doc.offset = offset;
+                self.doc.decode_time = Some(decode_time);
+            }
+            Err(e) => error!("Failed to decode {:?} at full resolution, keeping the preview: {:#}", path, e),
+        }
+    }
+    
+    /// Open an image in a new tab after the active one. An empty viewer loads it in place.
+    fn open_in_new_tab(&mut self, path: PathBuf) -> anyhow::Result<()> {
+        if self.doc.image.is_none() {
+            return self.load_image(path);
+        }
+        let previous = std::mem::take(&mut self.doc);
+        self.tabs.insert(self.active_tab, previous);
+        self.active_tab += 1;
+        self.tab_changed();
+        if let Err(e) = self.load_image(path) {
+            // Back to the tab that was active
+            self.active_tab -= 1;
+            self.doc = self.tabs.remove(self.active_tab);
+            self.tab_changed();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn tab_count(&self) -> usize {
+        self.tabs.len() + 1
+    }
+
+    /// Make the tab at `index` of the tab bar the active one
+    fn switch_tab(&mut self, index: usize) {
+        if index == self.active_tab || index >= self.tab_count() {
+            return;
+        }
+        let previous = std::mem::take(&mut self.doc);
+        self.tabs.insert(self.active_tab, previous);
+        self.doc = self.tabs.remove(index);
+        self.active_tab = index;
+        self.tab_changed();
+    }
+
+    /// Activate the tab `step` tabs to the right, wrapping around at both ends
+    fn cycle_tab(&mut self, step: i32) {
+        let count = self.tab_count() as i32;
+        self.switch_tab((self.active_tab as i32 + step).rem_euclid(count) as usize);
+    }
+
+    /// Close the tab at `index`. Closing the only tab leaves an empty viewer.
+    fn close_tab(&mut self, index: usize) {
+        if index != self.active_tab {
+            if index < self.active_tab {
+                self.active_tab -= 1;
+                self.tabs.remove(index);
+            } else if index < self.tab_count() {
+                self.tabs.remove(index - 1);
+            }
+            return;
+        }
+        // The tab to the right takes its place, the one to the left if it was the last
+        self.doc = if self.tabs.is_empty() {
+            ImageDocument::default()
+        } else if self.active_tab < self.tabs.len() {
+            self.tabs.remove(self.active_tab)
+        } else {
+            self.active_tab -= 1;
+            self.tabs.remove(self.active_tab)
+        };
+        self.tab_changed();
+    }
+
+    /// Reset the state that belongs to the image shown before a tab switch
+    fn tab_changed(&mut self) {
+        if let Some(crop) = &mut self.crop {
+            crop.rect = None;
+        }
+        if let Some(editor) = &mut self.annotate {
+            editor.cancel();
+        }
+        let calibration = self.file_calibration();
+        if let Some(measure) = &mut self.measure {
+            measure.image_changed(calibration);
+        }
+        self.dragging = false;
+        self.roi_drag_start = None;
+        self.zoom_drag = None;
+        self.context_pixel = None;
+        self.context_pixel_text = None;
+        self.show_orientation_save = false;
+        // The histogram window shows the active tab
+        self.gpu_histogram.cancel();
+        self.histogram_job.cancel(&self.histogram_shared_data);
+        self.doc.histogram_needs_update = true;
+        self.fft_view.invalidate();
+        // Display settings may have changed while the tab was in the background
+        self.doc.texture = None;
+    }
+
+    /// Tab labels with the close buttons; middle-click also closes a tab
+    fn render_tab_bar(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut switch_to = None;
+        let mut close = None;
+        let mut open_new = false;
+        egui::ScrollArea::horizontal().show(ui, |ui| {
+            ui.horizontal(|ui| {
+                let documents = self.tabs[..self.active_tab].iter().chain([&self.doc]).chain(&self.tabs[self.active_tab..]);
+                for (index, doc) in documents.enumerate() {
+                    let name = doc.image_path.as_ref()
+                        .and_then(|path| path.file_name())
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| "Empty".to_string());
+                    let response = ui.selectable_label(index == self.active_tab, name);
+                    let response = match &doc.image_path {
+                        Some(path) => response.on_hover_text(path.display().to_string()),
+                        None => response,
+                    };
+                    if response.clicked() {
+                        switch_to = Some(index);
+                    }
+                    if response.middle_clicked() || ui.small_button("×").on_hover_text("Close tab").clicked() {
+                        close = Some(index);
+                    }
+                    ui.separator();
+                }
+                if ui.button("+").on_hover_text("Open an image in a new tab").clicked() {
+                    open_new = true;
+                }
+            });
+        });
+        if let Some(index) = close {
+            self.close_tab(index);
+        } else if let Some(index) = switch_to {
+            self.switch_tab(index);
+        }
+        if open_new {
+            self.open_file_dialog(ctx, true);
+        }
+    }
+
+    /// Run `f` with `doc` as the active document, so the loading and texture code of the main
+    /// window can be used for the document of another window
+    fn with_document<R>(&mut self, doc: &mut ImageDocument, f: impl FnOnce(&mut Self) -> R) -> R {
+        std::mem::swap(&mut self.doc, doc);
+        let result = f(self);
+        std::mem::swap(&mut self.doc, doc);
+        result
+    }
+
+    /// Pick an image and show it in a new window
+    fn open_new_window(&mut self) {
+        let file_dialog = rfd::FileDialog::new();
+        let file_dialog = match &self.last_opened_folder {
+            Some(folder) if folder.exists() => file_dialog.set_directory(folder),
+            _ => file_dialog.set_directory(self.config.start_directory()),
+        };
+        let Some(path) = file_dialog.pick_file() else {
+            return;
+        };
+        let mut doc = ImageDocument::default();
+        match self.with_document(&mut doc, |app| app.load_image(path.clone())) {
+            Ok(()) => {
+                info!("Opening {:?} in a new window", path);
+                let id = egui::ViewportId::from_hash_of(("viewer_window", self.next_window_id));
+                self.next_window_id += 1;
+                self.windows.push(ViewerWindow { id, doc, open: true });
+            }
+            Err(e) => {
+                error!("Failed to load image for a new window: {}", e);
+                self.report_load_error(&e);
+            }
+        }
+    }
+
+    /// Show the additional windows, each with a toolbar and its image
+    fn render_viewer_windows(&mut self, ctx: &egui::Context) {
+        let mut windows = std::mem::take(&mut self.windows);
+        for window in &mut windows {
+            let title = window.doc.image_path.as_ref()
+                .and_then(|path| path.file_name())
+                .map(|name| format!("{} - Image Viewer", name.to_string_lossy()))
+                .unwrap_or_else(|| "Image Viewer".to_string());
+            let builder = egui::ViewportBuilder::default().with_title(title).with_inner_size([800.0, 800.0]);
+            ctx.show_viewport_immediate(window.id, builder, |ctx, _class| {
+                if ctx.input(|i| i.viewport().close_requested()) {
+                    window.open = false;
+                }
+                self.with_document(&mut window.doc, |app| app.render_document_window(ctx));
+            });
+        }
+        windows.retain(|window| window.open);
+        self.windows = windows;
+        self.sync_window_views();
+    }
+
+    /// Contents of an additional window, `self.doc` is the window's document
+    fn render_document_window(&mut self, ctx: &egui::Context) {
+        let dropped: Vec<PathBuf> = ctx.input(|i| i.raw.dropped_files.iter().filter_map(|file| file.path.clone()).collect());
+        if !dropped.is_empty() {
+            if let Err(e) = self.open_dropped(dropped) {
+                error!("Failed to load dropped image: {}", e);
+                self.report_load_error(&e);
+            }
+        }
+
+        egui::TopBottomPanel::top(egui::Id::new(("window_toolbar", ctx.viewport_id()))).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Open Image").clicked() {
+                    self.open_file_dialog(ctx, false);
+                }
+                if ui.button("Reset zoom").clicked() {
+                    self.doc.scale = 1.0;
+                    self.doc.offset = egui::Vec2::ZERO;
+                    self.doc.texture_needs_update = true;
+                }
+                ui.checkbox(&mut self.sync_windows, "Sync pan/zoom")
+                    .on_hover_text("Keep zoom and position equal in all windows");
+                if let Some(img) = &self.doc.image {
+                    ui.separator();
+                    ui.label(format!("{}×{}, {:.0}%", img.width(), img.height(), self.doc.base_scale * self.doc.scale * 100.0));
+                }
+            });
+        });
+
+        if self.texture_outdated() {
+            self.update_texture(ctx);
+            self.doc.texture_needs_update = false;
+        }
+
+        egui::CentralPanel::default().frame(self.canvas_frame(ctx)).show(ctx, |ui| {
+            let (Some(img), Some(texture)) = (&self.doc.image, &self.doc.texture) else {
+                ui.centered_and_justified(|ui| ui.label("Drop an image here"));
+                return;
+            };
+            let available_rect = ui.available_rect_before_wrap();
+            let response = ui.allocate_rect(available_rect, egui::Sense::drag());
+            let final_scale = self.doc.base_scale * self.doc.scale;
+            let display_size = egui::vec2(img.width() as f32, img.height() as f32) * final_scale;
+            let image_rect = egui::Rect::from_center_size(available_rect.center() + self.doc.offset, display_size);
+            if image_rect.intersects(available_rect) {
+                self.paint_background(ui.painter(), image_rect.intersect(available_rect), image_rect.min);
+                let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+                ui.painter().with_clip_rect(available_rect).image(texture.id(), image_rect, uv, egui::Color32::WHITE);
+            }
+
+            self.doc.offset += response.drag_delta();
+            let scroll = ui.input(|i| i.raw_scroll_delta.y);
+            if let (Some(pointer), true) = (response.hover_pos(), scroll != 0.0) {
+                // Zoom around the pointer
+                let zoom = if scroll > 0.0 { 1.1 } else { 1.0 / 1.1 };
+                let new_scale = (self.doc.scale * zoom).clamp(0.1, 20.0);
+                let image_point = (pointer - image_rect.center()) / final_scale;
+                let new_center = pointer - image_point * self.doc.base_scale * new_scale;
+                self.doc.offset += new_center - image_rect.center();
+                if (new_scale - self.doc.scale).abs() > 0.1 {
+                    self.doc.texture_needs_update = true;
+                }
+                self.doc.scale = new_scale;
+            }
+        });
+    }
+
+    /// With syncing on, give every window the zoom and position of the one that changed them
+    fn sync_window_views(&mut self) {
+        if !self.sync_windows || self.windows.is_empty() {
+            self.synced_view = None;
+            return;
+        }
+        let view = |doc: &ImageDocument| (doc.base_scale * doc.scale, doc.offset);
+        // Setting the scale rounds, so views within a small tolerance count as equal
+        let same = |(scale_a, offset_a): (f32, egui::Vec2), (scale_b, offset_b): (f32, egui::Vec2)| {
+            (scale_a - scale_b).abs() <= scale_a.abs() * 1e-4 && (offset_a - offset_b).length() < 0.01
+        };
+        let documents = std::iter::once(&self.doc).chain(self.windows.iter().map(|window| &window.doc));
+        let changed = documents
+            .filter(|doc| doc.image.is_some())
+            .map(view)
+            .find(|&doc_view| self.synced_view.is_none_or(|synced| !same(doc_view, synced)));
+        let Some((final_scale, offset)) = changed else {
+            return;
+        };
+        self.synced_view = Some((final_scale, offset));
+        let documents = std::iter::once(&mut self.doc).chain(self.windows.iter_mut().map(|window| &mut window.doc));
+        for doc in documents.filter(|doc| doc.image.is_some()) {
+            if !same(view(doc), (final_scale, offset)) {
+                // Equal on-screen pixel size, so images of one size stay aligned pixel for pixel
+                doc.scale = (final_scale / doc.base_scale).clamp(0.1, 20.0);
+                doc.offset = offset;
+                doc.texture_needs_update = true;
+            }
+        }
+    }
+
+    /// Calculate base scale to fit image in window
+    fn update_base_scale(&mut self) {
+        if let Some(img) = &self.doc.image {
+            let (img_width, img_height) = img.dimensions();
+            let max_display_size = 1024.0 - 100.0; // Account for UI
+            let scale_w = max_display_size / img_width as f32;
+            let scale_h = max_display_size / img_height as f32;
+            self.doc.base_scale = scale_w.min(scale_h).min(1.0);
+        }
+    }
+
+    /// Run an action triggered by a keyboard shortcut
+    fn run_action(&mut self, action: Action) {
+        // The gallery handles its own keys, the view and edit actions need the image
+        if self.gallery.is_some() && !matches!(action, Action::ToggleGallery | Action::DeleteFile | Action::MoveFile | Action::ShowInFolder | Action::OpenInEditor | Action::NextTab | Action::PreviousTab) {
+            return;
+        }
+        match action {
+            Action::NextImage | Action::PreviousImage => {
+                let direction = if action == Action::NextImage { 1 } else { -1 };
+                if let Err(e) = self.navigate_to_adjacent_image(direction) {
+                    error!("Failed to navigate to adjacent image: {}", e);
+                    self.report_load_error(&e);
+                }
+            }
+            Action::FirstImage | Action::LastImage => {
+                if let Err(e) = self.navigate_to_end(action == Action::LastImage) {
+                    error!("Failed to navigate to the {} image: {}", if action == Action::LastImage { "last" } else { "first" }, e);
+                    self.report_load_error(&e);
+                }
+            }
+            Action::ZoomIn => self.zoom_by(1.1),
+            Action::ZoomOut => self.zoom_by(1.0 / 1.1),
+            Action::ResetZoom => {
+                self.doc.scale = 1.0;
+                self.doc.offset = egui::Vec2::ZERO;
+                self.doc.texture_needs_update = true;
+            }
+            Action::PanLeft | Action::PanRight | Action::PanUp | Action::PanDown => {
+                let step = self.view_rect.map_or(egui::vec2(50.0, 50.0), |view| view.size() * PAN_STEP);
+                // The view moves in the arrow's direction, so the image moves the other way
+                self.doc.offset += match action {
+                    Action::PanLeft => egui::vec2(step.x, 0.0),
+                    Action::PanRight => egui::vec2(-step.x, 0.0),
+                    Action::PanUp => egui::vec2(0.0, step.y),
+                    _ => egui::vec2(0.0, -step.y),
+                };
+            }
+            Action::FitToWindow => self.fit_to_view(false),
+            Action::FillWindow => self.fit_to_view(true),
+            Action::ActualSize => self.set_final_scale(1.0, egui::Vec2::ZERO),
+            Action::RotateCw => self.apply_orientation_op(OrientationOp::RotateCw),
+            Action::RotateCcw => self.apply_orientation_op(OrientationOp::RotateCcw),
+            Action::FlipHorizontal => self.apply_orientation_op(OrientationOp::FlipHorizontal),
+            Action::FlipVertical => self.apply_orientation_op(OrientationOp::FlipVertical),
+            Action::ToggleHistogram => self.toggle_histogram(),
+            Action::TogglePixelTool => self.show_pixel_tool = !self.show_pixel_tool,
+            Action::ToggleLoupe => self.show_loupe = !self.show_loupe,
+            Action::ToggleInfo => self.show_info = !self.show_info,
+            Action::Undo => self.undo(),
+            Action::Redo => self.redo(),
+            Action::ToggleGallery => self.toggle_gallery(),
+            Action::NextFrame | Action::PreviousFrame => {
+                let step = if action == Action::NextFrame { 1 } else { -1 };
+                self.step_frame(step);
+            }
+            Action::BlinkCompare => {
+                if let Some(compare) = &mut self.doc.compare {
+                    compare.view = CompareView::Blink;
+                    compare.showing_b = !compare.showing_b;
+                    self.doc.invalidate_texture();
+                }
+            }
+            Action::NextTab => self.cycle_tab(1),
+            Action::PreviousTab => self.cycle_tab(-1),
+            // With the annotation editor open, Delete removes the selected annotation instead
+            Action::DeleteFile if self.annotate.as_ref().is_some_and(|editor| editor.selected.is_some()) => self.delete_selected_annotation(),
+            Action::DeleteFile => self.request_delete(),
+            Action::MoveFile => self.move_files(),
+            Action::RenameFile => self.start_rename(),
+            Action::ShowInFolder => self.show_in_folder(),
+            Action::OpenInEditor => self.open_in_editor(),
+            Action::Rate0 | Action::Rate1 | Action::Rate2 | Action::Rate3 | Action::Rate4 | Action::Rate5 => {
+                let stars = [Action::Rate0, Action::Rate1, Action::Rate2, Action::Rate3, Action::Rate4, Action::Rate5]
+                    .iter()
+                    .position(|&rate| rate == action)
+                    .unwrap_or(0) as u8;
+                self.update_rating(|rating| rating.stars = stars);
+            }
+            Action::LabelRed | Action::LabelYellow | Action::LabelGreen | Action::LabelBlue => {
+                let label = match action {
+                    Action::LabelRed => ColorLabel::Red,
+                    Action::LabelYellow => ColorLabel::Yellow,
+                    Action::LabelGreen => ColorLabel::Green,
+                    _ => ColorLabel::Blue,
+                };
+                self.update_rating(|rating| rating.label = if rating.label == Some(label) { None } else { Some(label) });
+            }
+        }
+    }
+    
+    /// Zoom around the center of the view
+    fn zoom_by(&mut self, factor: f32) {
+        let new_scale = (self.doc.scale * factor).clamp(0.1, 20.0);
+        self.doc.offset *= new_scale / self.doc.scale;
+        self.doc.scale = new_scale;
+        self.doc.texture_needs_update = true;
+    }
+    
+    /// Set the on-screen size of an image pixel, `offset` moves the image from the centered position
+    fn set_final_scale(&mut self, final_scale: f32, offset: egui::Vec2) {
+        self.doc.scale = (final_scale / self.doc.base_scale).clamp(0.1, 20.0);
+        self.doc.offset = offset * (self.doc.base_scale * self.doc.scale / final_scale);
+        self.doc.texture_needs_update = true;
+    }
+    
+    /// Set the on-screen size of an image pixel, keeping the view centered on the same point
+    fn set_zoom(&mut self, final_scale: f32) {
+        let offset = self.doc.offset * (final_scale / (self.doc.base_scale * self.doc.scale));
+        self.set_final_scale(final_scale, offset);
+    }
+    
+    /// Show the whole image (fit) or cover the whole view (fill)
+    fn fit_to_view(&mut self, fill: bool) {
+        let (Some(img), Some(view)) = (&self.doc.image, self.view_rect) else {
+            return;
+        };
+        let size = self.doc.view_orientation.apply_size(egui::vec2(img.width() as f32, img.height() as f32));
+        let (fit_x, fit_y) = (view.width() / size.x, view.height() / size.y);
+        self.set_final_scale(if fill { fit_x.max(fit_y) } else { fit_x.min(fit_y) }, egui::Vec2::ZERO);
+    }
+    
+    /// Show the image at 100% when it is fitted to the view, fit it otherwise
+    fn toggle_fit_actual_size(&mut self) {
+        let (Some(img), Some(view)) = (&self.doc.image, self.view_rect) else {
+            return;
+        };
+        let size = self.doc.view_orientation.apply_size(egui::vec2(img.width() as f32, img.height() as f32));
+        let fit = (view.width() / size.x).min(view.height() / size.y);
+        if (self.doc.base_scale * self.doc.scale - fit).abs() < fit * 0.01 && self.doc.offset == egui::Vec2::ZERO {
+            self.run_action(Action::ActualSize);
+        } else {
+            self.run_action(Action::FitToWindow);
+        }
+    }
+    
+    /// Zoom so a pixel rectangle fills the view, centered
+    fn zoom_to_rect(&mut self, rect: PixelRect) {
+        let (Some(img), Some(view)) = (&self.doc.image, self.view_rect) else {
+            return;
+        };
+        let [x0, y0, x1, y1] = rect;
+        let (width, height) = img.dimensions();
+        let orientation = self.doc.view_orientation;
+        let rect_size = orientation.apply_size(egui::vec2((x1 - x0).max(1) as f32, (y1 - y0).max(1) as f32));
+        let final_scale = (view.width() / rect_size.x).min(view.height() / rect_size.y);
+        // The image center sits at the view center plus the offset
+        let rect_center = egui::vec2((x0 + x1) as f32 / 2.0, (y0 + y1) as f32 / 2.0);
+        let image_center = egui::vec2(width as f32 / 2.0, height as f32 / 2.0);
+        self.set_final_scale(final_scale, orientation.apply_vector(image_center - rect_center) * final_scale);
+    }
+    
+    fn toggle_histogram(&mut self) {
+        if self.show_histogram {
+            // Close the histogram window
+            self.show_histogram = false;
+            self.histogram_window_id = None;
+        } else {
+            // Open the histogram window
+            self.show_histogram = true;
+            
+            // Create a new viewport for the histogram window
+            let histogram_id = egui::ViewportId::from_hash_of("histogram_window");
+            self.histogram_window_id = Some(histogram_id);
+        }
+    }
+
+    /// Pixels and geometry of the document, to return to them with undo
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot {
+            image: self.doc.image.clone()?,
+            fp_data: self.doc.original_fp_data.clone(),
+            fp_dimensions: self.doc.original_fp_dimensions,
+            frames: self.doc.frames.clone(),
+            volume_edits: self.doc.volume.as_ref().map(|volume| volume.edits().to_vec()),
+            orientation: self.doc.orientation,
+            edited: self.doc.edited,
+        })
+    }
+
+    /// Store the state before an edit of the pixels so it can be undone
+    fn record_edit(&mut self, label: &'static str) {
+        if let Some(snapshot) = self.snapshot() {
+            self.doc.history.record(label, snapshot, self.config.undo_memory.bytes());
+        }
+    }
+
+    /// Go back to the state before the last rotation, flip, crop or resize
+    fn undo(&mut self) {
+        let (Some(label), Some(current)) = (self.doc.history.undo_label(), self.snapshot()) else {
+            return;
+        };
+        if let Some(snapshot) = self.doc.history.undo(current, self.config.undo_memory.bytes()) {
+            info!("Undid {}", label);
+            self.restore(snapshot);
+        }
+    }
+
+    /// Apply the last undone edit again
+    fn redo(&mut self) {
+        let (Some(label), Some(current)) = (self.doc.history.redo_label(), self.snapshot()) else {
+            return;
+        };
+        if let Some(snapshot) = self.doc.history.redo(current, self.config.undo_memory.bytes()) {
+            info!("Redid {}", label);
+            self.restore(snapshot);
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        // The frame shown now stays shown
+        let frame = self.doc.frames.as_ref().map(|frames| frames.index());
+        self.doc.image = Some(snapshot.image);
+        self.doc.original_fp_data = snapshot.fp_data;
+        self.doc.original_fp_dimensions = snapshot.fp_dimensions;
+        self.count_non_finite();
+        self.doc.frames = snapshot.frames;
+        if let (Some(frames), Some(index)) = (&mut self.doc.frames, frame) {
+            frames.set_index(index);
+            self.doc.image = Some(frames.current().clone());
+        }
+        self.doc.orientation = snapshot.orientation;
+        self.doc.edited = snapshot.edited;
+        // The slice is rebuilt from the volume, which may show another slice by now
+        if let (Some(volume), Some(edits)) = (&mut self.doc.volume, snapshot.volume_edits) {
+            volume.set_edits(edits);
+            self.show_volume_slice();
+        }
+
+        self.doc.roi = None;
+        if let Some(crop) = &mut self.crop {
+            crop.rect = None;
+        }
+        self.update_base_scale();
+        self.doc.invalidate_texture();
+        self.doc.histogram_needs_update = true;
+        self.doc.pixel_info = None;
+        self.doc.pixel_info_fp = None;
+        self.hover_pos = None;
+    }
+
+    /// Rotate or flip the loaded image together with its floating point data
+    fn apply_orientation_op(&mut self, op: OrientationOp) {
+        self.record_edit(match op {
+            OrientationOp::RotateCw | OrientationOp::RotateCcw => "Rotate",
+            OrientationOp::FlipHorizontal | OrientationOp::FlipVertical => "Flip",
+        });
+        let Some(img) = &self.doc.image else {
+            return;
+        };
+        self.doc.image = Some(op.apply_to_image(img));
+        if let Some(frames) = &mut self.doc.frames {
+            frames.map(|frame| op.apply_to_image(frame));
+        }
+        if let Some(volume) = &mut self.doc.volume {
+            volume.edit(SliceEdit::Orient(op));
+        }
+        
+        if let (Some(fp_data), Some((width, height)), Some(channels)) =
+            (&self.doc.original_fp_data, self.doc.original_fp_dimensions, self.doc.original_fp_channels)
+        {
+            let (data, new_width, new_height) = op.apply_to_fp(fp_data, width, height, channels as usize);
+            self.doc.original_fp_data = Some(data);
+            self.doc.original_fp_dimensions = Some((new_width, new_height));
+        }
+        
+        self.doc.orientation = self.doc.orientation.then(op);
+        info!("Applied {:?}, net orientation {:?}", op, self.doc.orientation);
+        // The ROI and crop refer to pixel coordinates that just moved
+        self.doc.roi = None;
+        if let Some(crop) = &mut self.crop {
+            crop.rect = None;
+        }
+        
+        self.update_base_scale();
+        self.doc.invalidate_texture();
+        self.doc.histogram_needs_update = true;
+        // Hover coordinates refer to the old layout
+        self.doc.pixel_info = None;
+        self.doc.pixel_info_fp = None;
+        self.hover_pos = None;
+    }
+
+    /// Persist the applied rotation: EXIF tag for JPEG (lossless), re-encode otherwise
+    fn save_orientation(&mut self) -> anyhow::Result<()> {
+        let path = self.doc.image_path.clone().ok_or_else(|| anyhow::anyhow!("No image loaded"))?;
+        if self.doc.volume.is_some() {
+            anyhow::bail!("Rotations of NIfTI slices cannot be saved to the volume");
+        }
+        
+        if orientation::is_jpeg(&path) {
+            let combined = self.doc.file_orientation.then_orientation(self.doc.orientation);
+            orientation::write_jpeg_orientation(&path, combined)?;
+            self.doc.file_orientation = combined;
+        } else if let (Some(fp_data), Some((width, height)), Some(channels)) =
+            (&self.doc.original_fp_data, self.doc.original_fp_dimensions, self.doc.original_fp_channels)
+        {
+            Self::save_fp_tiff(&path, fp_data, width, height, channels)?;
+            self.doc.edited = false;
+        } else if let Some(img) = &self.doc.image {
+            img.save(&path)?;
+            self.doc.edited = false;
+        }
+        
+        info!("Saved orientation of {:?}", path);
+        self.doc.orientation = Orientation::default();
+        // Earlier states hold orientations relative to the file before saving
+        self.doc.history.clear();
+        Ok(())
+    }
+
+    fn save_fp_tiff(path: &Path, data: &[f32], width: u32, height: u32, channels: u32) -> anyhow::Result<()> {
+        use tiff::encoder::{colortype, TiffEncoder};
+        let mut encoder = TiffEncoder::new(File::create(path)?)?;
+        match channels {
+            1 => encoder.write_image::<colortype::Gray32Float>(width, height, data)?,
+            3 => encoder.write_image::<colortype::RGB32Float>(width, height, data)?,
+            4 => encoder.write_image::<colortype::RGBA32Float>(width, height, data)?,
+            _ => return Err(anyhow::anyhow!("Cannot write {}-channel floating point TIFF", channels)),
+        }
+        Ok(())
+    }
+
+    /// Rotation and mirroring of the display only, e.g. for sensor data stored rotated. Pixel
+    /// coordinates, readouts and exports keep referring to the data as stored.
+    fn render_view_orientation_menu(&mut self, ui: &mut egui::Ui) {
+        let view = self.doc.view_orientation;
+        let text = match (view.quarter_turns, view.flipped) {
+            (0, false) => "View".to_string(),
+            (turns, flipped) => format!("View {}°{}", turns as u32 * 90, if flipped { " mirrored" } else { "" }),
+        };
+        let mut op = None;
+        ui.menu_button(text, |ui| {
+            for (label, view_op) in [
+                ("Rotate view left", OrientationOp::RotateCcw),
+                ("Rotate view right", OrientationOp::RotateCw),
+                ("Mirror view horizontally", OrientationOp::FlipHorizontal),
+                ("Mirror view vertically", OrientationOp::FlipVertical),
+            ] {
+                if ui.button(label).clicked() {
+                    op = Some(view_op);
+                    ui.close_menu();
+                }
+            }
+            if ui.add_enabled(!view.is_identity(), egui::Button::new("Reset view")).clicked() {
+                self.doc.offset = view.unapply_vector(self.doc.offset);
+                self.doc.view_orientation = Orientation::default();
+                ui.close_menu();
+            }
+        }).response.on_hover_text("Rotate or mirror only the display, coordinates and values keep the layout of the data");
+        if let Some(op) = op {
+            self.doc.view_orientation = view.then(op);
+            // Keep the same part of the image in the view center
+            self.doc.offset = Orientation::default().then(op).apply_vector(self.doc.offset);
+            info!("View orientation {:?}", self.doc.view_orientation);
+        }
+    }
+
+    fn render_orientation_save_window(&mut self, ctx: &egui::Context) {
+        let is_jpeg = self.doc.image_path.as_deref().is_some_and(orientation::is_jpeg);
+        let mut open = self.show_orientation_save;
+        let mut save = false;
+        
+        egui::Window::new("Save orientation")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                if is_jpeg {
+                    ui.label("The rotation will be written to the EXIF orientation tag.");
+                    ui.label("The JPEG image data is not re-encoded, so no quality is lost.");
+                } else {
+                    ui.label("The rotated image will be re-encoded and overwrite the file.");
+                }
+                ui.horizontal(|ui| {
+                    save = ui.button(if is_jpeg { "Save losslessly" } else { "Save" }).clicked();
+                });
+            });
+        
+        self.show_orientation_save = open && !save;
+        if save {
+            if let Err(e) = self.save_orientation() {
+                error!("Failed to save orientation: {}", e);
+            }
+            // The file changed, its hashes are outdated
+            self.integrity.invalidate();
+        }
+    }
+
+    /// Tell about files in a format the viewer cannot decode in a dialog, other failures are only logged
+    fn report_load_error(&mut self, e: &anyhow::Error) {
+        if let Some(unsupported) = e.downcast_ref::<tiff_format::UnsupportedCompression>() {
+            self.load_error = Some(unsupported.to_string());
+        }
+    }
+
+    fn render_load_error_window(&mut self, ctx: &egui::Context) {
+        let Some(message) = &self.load_error else {
+            return;
+        };
+        let mut open = true;
+        let mut close = false;
+        egui::Window::new("Cannot open image")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(message);
+                close = ui.button("OK").clicked();
+            });
+        if close || !open || ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.load_error = None;
+        }
+    }
+
+    fn render_delete_window(&mut self, ctx: &egui::Context) {
+        if self.pending_delete.is_empty() {
+            return;
+        }
+        // Another image was shown meanwhile, the gallery keeps its selection
+        if self.gallery.is_none() && self.pending_delete.as_slice() != self.doc.image_path.as_slice() {
+            self.pending_delete.clear();
+            return;
+        }
+        let mut open = true;
+        let (mut confirm, mut cancel) = (false, false);
+        egui::Window::new("Move to trash")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                match self.pending_delete.as_slice() {
+                    [path] => {
+                        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+                        ui.label(format!("Move {} to the trash?", name));
+                    }
+                    paths => {
+                        ui.label(format!("Move {} files to the trash?", paths.len()));
+                    }
+                }
+                if ui.checkbox(&mut self.config.skip_delete_confirmation, "Don't ask again").changed() {
+                    if let Err(e) = self.config.save() {
+                        error!("Failed to save settings: {}", e);
+                    }
+                }
+                ui.horizontal(|ui| {
+                    confirm = ui.button("Move to trash").clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+        // Enter confirms and Escape cancels, so culling works from the keyboard
+        let (enter, escape) = ctx.input(|i| (i.key_pressed(egui::Key::Enter), i.key_pressed(egui::Key::Escape)));
+        if confirm || enter {
+            let paths = std::mem::take(&mut self.pending_delete);
+            self.delete_files(&paths);
+        } else if cancel || escape || !open {
+            self.pending_delete.clear();
+        }
+    }
+
+    fn render_rename_window(&mut self, ctx: &egui::Context) {
+        let Some(dialog) = &mut self.rename else {
+            return;
+        };
+        let mut open = true;
+        let (mut confirm, mut cancel) = (false, false);
+        // Checked while typing, so collisions show before renaming
+        let target = file_ops::renamed_path(&dialog.path, &dialog.name);
+        egui::Window::new("Rename")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                let response = ui.add(egui::TextEdit::singleline(&mut dialog.name).desired_width(300.0));
+                if !dialog.focused {
+                    response.request_focus();
+                    dialog.focused = true;
+                }
+                confirm = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if let Err(reason) = &target {
+                    ui.colored_label(ui.visuals().error_fg_color, reason);
+                }
+                ui.horizontal(|ui| {
+                    confirm |= ui.add_enabled(target.is_ok(), egui::Button::new("Rename")).clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+        cancel |= ctx.input(|i| i.key_pressed(egui::Key::Escape));
+        if confirm && target.is_ok() {
+            let (path, name) = (dialog.path.clone(), dialog.name.clone());
+            self.rename = None;
+            self.rename_file(&path, &name);
+        } else if cancel || !open {
+            self.rename = None;
+        }
+    }
+
+    /// Export the FFT of the full resolution image (after normalization) next to `base`,
+    /// returns the written files
+    fn export_fft(&self, base: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        let img = self.doc.image.as_ref().ok_or_else(|| anyhow::anyhow!("No image loaded"))?;
+        let normalized = self.normalized(img);
+        let hamming = self.transforms.params("fft").first().is_none_or(|&v| v != 0.0);
+        let (width, height) = normalized.dimensions();
+        let shape = [height as usize, width as usize];
+        
+        let with_suffix = |suffix: &str| paths::with_stem_suffix(base, suffix);
+        let mut written = Vec::new();
+        
+        // Coefficients are exported centered, matching the displayed spectrum
+        let spectrum = fft_shift(&fft_spectrum(&normalized, hamming));
+        let magnitude: Vec<f32> = spectrum.iter().map(|c| c.norm()).collect();
+        
+        let options = self.fft_export_options;
+        if options.spectrum_png {
+            let path = with_suffix(".png");
+            self.transforms.apply("fft", normalized.clone()).save(&path)?;
+            written.push(path);
+        }
+        if options.magnitude_npy {
+            let path = with_suffix("_magnitude.npy");
+            export::write_npy_f32(&path, &shape, &magnitude)?;
+            written.push(path);
+        }
+        if options.complex_npy {
+            let path = with_suffix("_complex.npy");
+            export::write_npy_complex(&path, &shape, &spectrum)?;
+            written.push(path);
+        }
+        if options.magnitude_csv {
+            let path = with_suffix("_magnitude.csv");
+            export::write_csv_f32(&path, width as usize, &magnitude)?;
+            written.push(path);
+        }
+        
+        Ok(written)
+    }
+
+    /// Spectrum of the normalized image in a separate OS window, with frequency masks and the
+    /// inverse FFT of the masked spectrum
+    fn render_fft_window(&mut self, ctx: &egui::Context) {
+        let hamming = self.transforms.params("fft").first().is_none_or(|&v| v != 0.0);
+        if let Some(img) = &self.doc.image {
+            if self.fft_view.needs_spectrum(hamming) {
+                let normalized = self.normalized(img);
+                self.fft_view.set_image(&normalized, hamming);
+            }
+        }
+        
+        let builder = egui::ViewportBuilder::default()
+            .with_title("FFT")
+            .with_inner_size([1000.0, 560.0])
+            .with_min_inner_size([500.0, 300.0]);
+        ctx.show_viewport_immediate(egui::ViewportId::from_hash_of("fft_window"), builder, |ctx, _class| {
+            if ctx.input(|i| i.viewport().close_requested()) {
+                self.show_fft = false;
+            }
+            egui::TopBottomPanel::top("fft_controls").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    self.transforms.params_ui(ui, "fft");
+                    ui.separator();
+                    self.fft_view.controls_ui(ui);
+                    ui.separator();
+                    if ui.add_enabled(self.doc.image.is_some(), egui::Button::new("Export spectrum…")).clicked() {
+                        self.show_fft_export = true;
+                    }
+                });
+            });
+            egui::CentralPanel::default().show(ctx, |ui| self.fft_view.images_ui(ui));
+        });
+    }
+
+    fn render_fft_export_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_fft_export;
+        let mut export_clicked = false;
+        
+        egui::Window::new("Export FFT")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let options = &mut self.fft_export_options;
+                ui.checkbox(&mut options.spectrum_png, "Spectrum image (log magnitude, PNG)");
+                ui.checkbox(&mut options.magnitude_npy, "Magnitude array (.npy, float32)");
+                ui.checkbox(&mut options.complex_npy, "Complex coefficients (.npy, complex64)");
+                ui.checkbox(&mut options.magnitude_csv, "Magnitude array (CSV)");
+                ui.weak("Computed on the full resolution image, zero frequency centered.");
+                export_clicked = ui.button("Export…").clicked();
+            });
+        
+        self.show_fft_export = open;
+        if export_clicked {
+            let size = self.doc.image.as_ref().map(|img| img.dimensions()).unwrap_or_default();
+            let default_name = self.export_file_name(&self.config.export_naming.fft, None, size, None);
+            let mut dialog = rfd::FileDialog::new().set_file_name(default_name);
+            if let Some(folder) = &self.last_opened_folder {
+                dialog = dialog.set_directory(folder);
+            }
+            if let Some(base) = dialog.save_file() {
+                match self.export_fft(&base) {
+                    Ok(files) => {
+                        info!("Exported FFT data: {:?}", files);
+                        self.show_fft_export = false;
+                    }
+                    Err(e) => error!("Failed to export FFT data: {}", e),
+                }
+            }
+        }
+    }
+
+    /// Image info window: MD5 and SHA-256 of the file and of its decoded pixels, and a
+    /// bit-exact comparison of the decoded pixels against another file
+    fn render_integrity_window(&mut self, ctx: &egui::Context) {
+        if let Some(path) = &self.doc.image_path {
+            self.integrity.request(ctx, path, load_decoded_pixels);
+        }
+        self.integrity.poll();
+        let mut open = self.show_integrity;
+        let mut compare_clicked = false;
+        
+        egui::Window::new("Image info")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let Some(path) = &self.doc.image_path else {
+                    ui.weak("No image file open");
+                    return;
+                };
+                ui.label(path.display().to_string());
+                match self.integrity.info() {
+                    None => {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.weak("Hashing…");
+                        });
+                    }
+                    Some(Err(e)) => {
+                        ui.colored_label(egui::Color32::from_rgb(255, 100, 100), e);
+                    }
+                    Some(Ok(info)) => {
+                        egui::Grid::new("integrity").num_columns(3).striped(true).show(ui, |ui| {
+                            let row = |ui: &mut egui::Ui, label: &str, value: &str| {
+                                ui.label(label);
+                                ui.monospace(value);
+                                if ui.small_button("📋").on_hover_text("Copy").clicked() {
+                                    ui.ctx().copy_text(value.to_string());
+                                }
+                                ui.end_row();
+                            };
+                            ui.label("File size");
+                            ui.label(format!("{} bytes", info.file_size));
+                            ui.end_row();
+                            row(ui, "File MD5", &info.file.md5);
+                            row(ui, "File SHA-256", &info.file.sha256);
+                            match &info.pixels {
+                                Ok((layout, digests)) => {
+                                    ui.label("Decoded");
+                                    ui.label(layout);
+                                    ui.end_row();
+                                    row(ui, "Pixels MD5", &digests.md5);
+                                    row(ui, "Pixels SHA-256", &digests.sha256);
+                                }
+                                Err(e) => {
+                                    ui.label("Decoded");
+                                    ui.colored_label(egui::Color32::from_rgb(255, 170, 0), e);
+                                    ui.end_row();
+                                }
+                            }
+                        });
+                        ui.weak("Pixel hashes cover the samples as decoded, in little-endian byte order; float data for HDR, RAW and float TIFF files");
+                    }
+                }
+                
+                ui.separator();
+                ui.horizontal(|ui| {
+                    compare_clicked = ui.add_enabled(self.integrity.comparing().is_none(), egui::Button::new("Bit-exact compare…"))
+                        .on_hover_text("Check whether another file decodes to identical pixels")
+                        .clicked();
+                    if let Some(other) = self.integrity.comparing() {
+                        ui.spinner();
+                        ui.weak(format!("Comparing with {}…", other.file_name().unwrap_or_default().to_string_lossy()));
+                    }
+                });
+                if let Some((other, result)) = self.integrity.compare_result() {
+                    ui.label(format!("Against {}", other.display()));
+                    match result {
+                        Ok(outcome) if outcome.is_identical() => ui.colored_label(egui::Color32::from_rgb(100, 200, 100), format!("✔ {}", outcome.to_text())),
+                        Ok(outcome) => ui.colored_label(egui::Color32::from_rgb(255, 100, 100), format!("✖ {}", outcome.to_text())),
+                        Err(e) => ui.colored_label(egui::Color32::from_rgb(255, 100, 100), e),
+                    };
+                }
+            });
+        self.show_integrity = open;
+        
+        if compare_clicked {
+            let mut dialog = rfd::FileDialog::new();
+            if let Some(folder) = &self.last_opened_folder {
+                dialog = dialog.set_directory(folder);
+            }
+            if let Some(other) = dialog.pick_file() {
+                info!("Bit-exact comparison against {:?}", other);
+                self.integrity.compare_with(ctx, other, load_decoded_pixels);
+            }
+        }
+    }
+
+    /// Steps applied between the normalization and the adjustments, in order
+    fn render_pipeline_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_pipeline;
+        let mut changed = false;
+        
+        egui::Window::new("Pipeline")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let normalization = self.transforms.get(self.doc.normalization).map_or("None", |t| t.name());
+                ui.weak(format!("Applied after {}, before the adjustments and the channel view", normalization));
+                ui.separator();
+                changed = self.doc.pipeline.ui(ui, &self.transforms);
+            });
+        
+        self.show_pipeline = open;
+        if changed {
+            self.doc.invalidate_texture();
+        }
+    }
+    
+    fn render_adjustments_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_adjustments;
+        let before = self.doc.adjustments;
+        
+        egui::Window::new("Adjustments")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let adjustments = &mut self.doc.adjustments;
+                egui::Grid::new("adjustments").num_columns(2).show(ui, |ui| {
+                    ui.label("Exposure");
+                    ui.add(egui::Slider::new(&mut adjustments.exposure, -4.0..=4.0).suffix(" EV").step_by(0.1));
+                    ui.end_row();
+                    ui.label("Brightness");
+                    ui.add(egui::Slider::new(&mut adjustments.brightness, -1.0..=1.0).step_by(0.01));
+                    ui.end_row();
+                    ui.label("Contrast");
+                    ui.add(egui::Slider::new(&mut adjustments.contrast, -1.0..=1.0).step_by(0.01));
+                    ui.end_row();
+                    ui.label("Gamma");
+                    ui.add(egui::Slider::new(&mut adjustments.gamma, 0.1..=5.0).logarithmic(true));
+                    ui.end_row();
+                    ui.label("Saturation");
+                    ui.add(egui::Slider::new(&mut adjustments.saturation, 0.0..=2.0).step_by(0.01));
+                    ui.end_row();
+                });
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(!adjustments.is_identity(), egui::Button::new("Reset")).clicked() {
+                        *adjustments = Adjustments::default();
+                    }
+                    ui.weak("Applied after normalization, the image data is unchanged");
+                });
+            });
+        
+        self.show_adjustments = open;
+        if self.doc.adjustments != before {
+            self.doc.invalidate_texture();
+        }
+    }
+
+    /// Read the original and displayed values of all probes from the current image
+    fn refresh_probes(&mut self) {
+        self.doc.probes_dirty = false;
+        let Some(img) = &self.doc.image else {
+            for probe in &mut self.doc.probes {
+                probe.original = None;
+                probe.displayed = None;
+            }
+            return;
+        };
+        let (width, height) = img.dimensions();
+        // Normalization depends on the whole image, so the displayed values need a full resolution render
+        let source = match &self.doc.compare {
+            Some(compare) => compare.view_image(img),
+            None => img.clone(),
+        };
+        let displayed = self.render_display_image(source);
+        let originals: Vec<Option<Vec<f32>>> = self.doc.probes.iter()
+            .map(|probe| (probe.x < width && probe.y < height)
+                .then(|| self.pixel_region([probe.x, probe.y, probe.x + 1, probe.y + 1]))
+                .flatten()
+                .map(|region| region.values))
+            .collect();
+        for (probe, original) in self.doc.probes.iter_mut().zip(originals) {
+            probe.displayed = original.as_ref()
+                .map(|_| displayed.pixels[(probe.y * width + probe.x) as usize].to_srgba_unmultiplied());
+            probe.original = original;
+        }
+    }
+
+    fn render_probe_window(&mut self, ctx: &egui::Context) {
+        if self.doc.probes_dirty {
+            self.refresh_probes();
+        }
+        let mut open = self.show_probes;
+        let mut remove = None;
+        let mut export_clicked = false;
+        
+        egui::Window::new("Pixel probes")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                if self.doc.probes.is_empty() {
+                    ui.weak("Right-click the image and choose \"Pin probe\" to add a probe.");
+                }
+                egui::Grid::new("probes").num_columns(5).striped(true).show(ui, |ui| {
+                    if !self.doc.probes.is_empty() {
+                        ui.strong("#");
+                        ui.strong("Position");
+                        ui.strong("Value");
+                        ui.strong("Displayed RGBA");
+                        ui.end_row();
+                    }
+                    for (index, probe) in self.doc.probes.iter().enumerate() {
+                        ui.label((index + 1).to_string());
+                        ui.label(format!("({}, {})", probe.x, probe.y));
+                        ui.monospace(probe.original_text());
+                        ui.monospace(probe.displayed_text());
+                        if ui.small_button("✖").on_hover_text("Remove probe").clicked() {
+                            remove = Some(index);
+                        }
+                        ui.end_row();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    export_clicked = ui.add_enabled(!self.doc.probes.is_empty(), egui::Button::new("Export CSV…")).clicked();
+                    if ui.add_enabled(!self.doc.probes.is_empty(), egui::Button::new("Clear")).clicked() {
+                        self.doc.probes.clear();
+                    }
+                });
+            });
+        
+        self.show_probes = open;
+        if let Some(index) = remove {
+            self.doc.probes.remove(index);
+        }
+        if export_clicked {
+            let mut dialog = rfd::FileDialog::new().add_filter("CSV", &["csv"]).set_file_name("probes.csv");
+            if let Some(folder) = &self.last_opened_folder {
+                dialog = dialog.set_directory(folder);
+            }
+            if let Some(path) = dialog.save_file() {
+                match probes::write_csv(&path, &self.doc.probes) {
+                    Ok(()) => info!("Exported {} probes to {:?}", self.doc.probes.len(), path),
+                    Err(e) => error!("Failed to export probes: {}", e),
+                }
+            }
+        }
+    }
+
+    fn render_video_export_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_video_export;
+        let mut encode_clicked = false;
+        
+        // Collect the result of a finished encode
+        if let Some(result) = self.video_job.as_ref().and_then(|job| job.poll()) {
+            self.video_status = Some(match result {
+                Ok(path) => {
+                    info!("Video written to {:?}", path);
+                    format!("Saved {}", path.to_string_lossy())
+                }
+                Err(e) => {
+                    error!("Video encoding failed: {}", e);
+                    format!("Failed: {}", e)
+                }
+            });
+            self.video_job = None;
+        }
+        
+        egui::Window::new("Encode Video")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!("{} images of the current folder, in filmstrip order", self.doc.folder_images.len()));
+                
+                let options = &mut self.video_export_options;
+                ui.add_enabled_ui(self.video_job.is_none(), |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Format:");
+                        for format in [VideoFormat::Mp4, VideoFormat::WebM] {
+                            ui.radio_value(&mut options.format, format, format.as_str());
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Frame rate:");
+                        ui.add(egui::DragValue::new(&mut options.fps).range(1..=120).suffix(" fps"));
+                        ui.label("Width:");
+                        ui.add(egui::DragValue::new(&mut options.width).range(16..=7680).suffix(" px"));
+                    });
+                    ui.weak("The height follows the first image; other sizes are letterboxed. Needs ffmpeg on the PATH.");
+                    encode_clicked = ui.button("Encode…").clicked();
+                });
+                
+                if let Some(job) = &self.video_job {
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let done = job.progress();
+                        ui.add(egui::ProgressBar::new(done as f32 / job.total.max(1) as f32)
+                            .text(format!("{} / {} frames", done, job.total)));
+                        if ui.button("Cancel").clicked() {
+                            job.cancel();
+                        }
+                    });
+                } else if let Some(status) = &self.video_status {
+                    ui.separator();
+                    ui.label(status);
+                }
+            });
+        
+        self.show_video_export = open;
+        if encode_clicked {
+            let format = self.video_export_options.format;
+            let default_name = self.last_opened_folder.as_ref()
+                .and_then(|folder| folder.file_name())
+                .map(|name| format!("{}.{}", name.to_string_lossy(), format.extension()))
+                .unwrap_or_else(|| format!("sequence.{}", format.extension()));
+            let mut dialog = rfd::FileDialog::new()
+                .set_file_name(default_name)
+                .add_filter(format.as_str(), &[format.extension()]);
+            if let Some(folder) = &self.last_opened_folder {
+                dialog = dialog.set_directory(folder);
+            }
+            if let Some(path) = dialog.save_file() {
+                self.video_status = None;
+                self.video_job = Some(VideoEncodeJob::start(
+                    ctx,
+                    self.doc.folder_images.clone(),
+                    path,
+                    self.video_export_options,
+                    load_image_source,
+                ));
+            }
+        }
+    }
+
+    fn render_batch_convert_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_batch_convert;
+        let mut convert_clicked = false;
+        
+        if let Some(summary) = self.batch_job.as_ref().and_then(|job| job.poll()) {
+            self.batch_status = Some(summary.to_text());
+            self.batch_job = None;
+        }
+        // The rating filter only narrows the list while it is set
+        let filtered = self.batch_filtered && self.min_rating > 0;
+        let files: Vec<PathBuf> = self.doc.folder_images.clone()
+            .into_iter()
+            .filter(|path| !filtered || self.ratings.get(path).stars >= self.min_rating)
+            .collect();
+        
+        egui::Window::new("Convert Images")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!("{} images of the current folder", files.len()));
+                
+                let options = &mut self.batch_options;
+                ui.add_enabled_ui(self.batch_job.is_none(), |ui| {
+                    if self.min_rating > 0 {
+                        ui.checkbox(&mut self.batch_filtered, format!("Only images with {}+ stars", self.min_rating));
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("Format:");
+                        egui::ComboBox::from_id_salt("batch_format")
+                            .selected_text(options.format.as_str())
+                            .show_ui(ui, |ui| {
+                                for format in BatchFormat::ALL {
+                                    ui.selectable_value(&mut options.format, format, format.as_str());
+                                }
+                            });
+                        if options.format == BatchFormat::Jpeg {
+                            ui.label("Quality:");
+                            ui.add(egui::Slider::new(&mut options.quality, 1..=100));
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        let mut resize = options.max_size > 0;
+                        if ui.checkbox(&mut resize, "Fit within").changed() {
+                            options.max_size = if resize { 2048 } else { 0 };
+                        }
+                        if resize {
+                            ui.add(egui::DragValue::new(&mut options.max_size).range(16..=16384).suffix(" px"));
+                        }
+                    });
+                    ui.checkbox(&mut options.overwrite, "Overwrite existing files")
+                        .on_hover_text("Existing files of the same name are skipped otherwise. Images in the source folder are never overwritten.");
+                    ui.weak("Metadata such as EXIF is not copied. Camera RAW files are developed with the as-shot white balance.");
+                    convert_clicked = ui.add_enabled(!files.is_empty(), egui::Button::new("Convert to folder…")).clicked();
+                });
+                
+                if let Some(job) = &self.batch_job {
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let done = job.progress();
+                        ui.add(egui::ProgressBar::new(done as f32 / job.total.max(1) as f32)
+                            .text(format!("{} / {} images", done, job.total)));
+                        if ui.button("Cancel").clicked() {
+                            job.cancel();
+                        }
+                    });
+                } else if let Some(status) = &self.batch_status {
+                    ui.separator();
+                    ui.label(status);
+                }
+            });
+        
+        self.show_batch_convert = open;
+        if convert_clicked {
+            let mut dialog = rfd::FileDialog::new().set_title("Convert to folder");
+            if let Some(folder) = &self.last_opened_folder {
+                dialog = dialog.set_directory(folder);
+            }
+            if let Some(output_dir) = dialog.pick_folder() {
+                self.batch_status = None;
+                self.batch_job = Some(BatchJob::start(ctx, files, output_dir, self.batch_options, load_batch_source));
+            }
+        }
+    }
+
+    fn render_crop_window(&mut self, ctx: &egui::Context) {
+        let Some((image_width, image_height)) = self.doc.image.as_ref().map(|img| img.dimensions()) else {
+            return;
+        };
+        let mut open = true;
+        let mut apply = false;
+        let mut export = false;
+        let Some(crop) = &mut self.crop else {
+            return;
+        };
+        
+        egui::Window::new("Crop")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let mut aspect_changed = false;
+                ui.horizontal(|ui| {
+                    ui.label("Aspect ratio:");
+                    egui::ComboBox::from_id_salt("crop_aspect")
+                        .selected_text(crop.aspect.as_str())
+                        .show_ui(ui, |ui| {
+                            for aspect in AspectRatio::ALL {
+                                aspect_changed |= ui.selectable_value(&mut crop.aspect, aspect, aspect.as_str()).changed();
+                            }
+                        });
+                    if crop.aspect == AspectRatio::Custom {
+                        aspect_changed |= ui.add(egui::DragValue::new(&mut crop.custom.0).range(1..=10000)).changed();
+                        ui.label(":");
+                        aspect_changed |= ui.add(egui::DragValue::new(&mut crop.custom.1).range(1..=10000)).changed();
+                    }
+                    if crop.aspect != AspectRatio::Free {
+                        aspect_changed |= ui.checkbox(&mut crop.portrait, "Portrait").changed();
+                    }
+                });
+                ui.checkbox(&mut crop.show_thirds, "Rule-of-thirds guides");
+                ui.separator();
+                
+                // Numeric entry, the other side follows when the ratio is locked
+                let [x0, y0, x1, y1] = crop.rect.unwrap_or([0, 0, image_width, image_height]);
+                let (mut x, mut y, mut w, mut h) = (x0, y0, x1 - x0, y1 - y0);
+                let mut edited = false;
+                egui::Grid::new("crop_rect").num_columns(4).show(ui, |ui| {
+                    ui.label("X:");
+                    edited |= ui.add(egui::DragValue::new(&mut x).range(0..=image_width - 1)).changed();
+                    ui.label("Y:");
+                    edited |= ui.add(egui::DragValue::new(&mut y).range(0..=image_height - 1)).changed();
+                    ui.end_row();
+                    ui.label("Width:");
+                    if ui.add(egui::DragValue::new(&mut w).range(1..=image_width)).changed() {
+                        if let Some(ratio) = crop.ratio() {
+                            h = (w as f32 / ratio).round() as u32;
+                        }
+                        edited = true;
+                    }
+                    ui.label("Height:");
+                    if ui.add(egui::DragValue::new(&mut h).range(1..=image_height)).changed() {
+                        if let Some(ratio) = crop.ratio() {
+                            w = (h as f32 * ratio).round() as u32;
+                        }
+                        edited = true;
+                    }
+                    ui.end_row();
+                });
+                if edited {
+                    // Keep the typed size, move the rectangle inside the image if needed
+                    let (w, h) = (w.clamp(1, image_width), h.clamp(1, image_height));
+                    let (x, y) = (x.min(image_width - w), y.min(image_height - h));
+                    crop.rect = Some([x, y, x + w, y + h]);
+                } else if aspect_changed {
+                    crop.rect = Some(crop.constrain([x0, y0, x1, y1], image_width, image_height));
+                }
+                
+                ui.separator();
+                ui.horizontal(|ui| {
+                    apply = ui.add_enabled(crop.rect.is_some(), egui::Button::new("Apply crop"))
+                        .on_hover_text("Crop the image in the viewer, the file is not changed")
+                        .clicked();
+                    export = ui.add_enabled(crop.rect.is_some(), egui::Button::new("Export crop…"))
+                        .on_hover_text("Save the cropped region at full resolution")
+                        .clicked();
+                });
+            });
+        
+        let rect = crop.rect;
+        if !open {
+            self.crop = None;
+        }
+        if let Some(rect) = rect {
+            if export {
+                if let Err(e) = self.export_crop(rect) {
+                    error!("Failed to export crop: {}", e);
+                }
+            }
+            if apply {
+                self.apply_crop(rect);
+                self.crop = None;
+            }
+        }
+    }
+    
+    /// Replace the loaded image (and its floating point data) by a region of it
+    fn apply_crop(&mut self, rect: PixelRect) {
+        self.record_edit("Crop");
+        let [x0, y0, x1, y1] = rect;
+        let Some(img) = &self.doc.image else {
+            return;
+        };
+        self.doc.image = Some(img.crop_imm(x0, y0, x1 - x0, y1 - y0));
+        if let Some(frames) = &mut self.doc.frames {
+            frames.map(|frame| frame.crop_imm(x0, y0, x1 - x0, y1 - y0));
+        }
+        if let Some(volume) = &mut self.doc.volume {
+            volume.edit(SliceEdit::Crop(rect));
+        }
+        if let (Some(fp_data), Some((width, _)), Some(channels)) =
+            (&self.doc.original_fp_data, self.doc.original_fp_dimensions, self.doc.original_fp_channels)
+        {
+            self.doc.original_fp_data = Some(crop::crop_fp(fp_data, width, channels as usize, rect));
+            self.doc.original_fp_dimensions = Some((x1 - x0, y1 - y0));
+            self.count_non_finite();
+        }
+        info!("Cropped to {:?}", rect);
+        self.doc.edited = true;
+        
+        self.doc.roi = None;
+        self.update_base_scale();
+        self.doc.invalidate_texture();
+        self.doc.histogram_needs_update = true;
+    }
+    
+    fn render_resize_window(&mut self, ctx: &egui::Context) {
+        let Some(source) = self.doc.image.as_ref().map(|img| img.dimensions()) else {
+            return;
+        };
+        let Some(resize) = &mut self.resize else {
+            return;
+        };
+        let mut open = true;
+        let mut apply = false;
+        let mut export = false;
+        
+        egui::Window::new("Resize")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!("Current size: {} × {}", source.0, source.1));
+                egui::Grid::new("resize_size").num_columns(2).show(ui, |ui| {
+                    ui.label("Width:");
+                    let mut width = resize.width;
+                    if ui.add(egui::DragValue::new(&mut width).range(1..=resize::MAX_SIZE).suffix(" px")).changed() {
+                        resize.set_width(width, source);
+                    }
+                    ui.end_row();
+                    ui.label("Height:");
+                    let mut height = resize.height;
+                    if ui.add(egui::DragValue::new(&mut height).range(1..=resize::MAX_SIZE).suffix(" px")).changed() {
+                        resize.set_height(height, source);
+                    }
+                    ui.end_row();
+                    ui.label("Percent:");
+                    let mut percent = resize.percent(source);
+                    if ui.add(egui::DragValue::new(&mut percent).range(0.1..=1000.0).speed(1.0).max_decimals(1).suffix(" %")).changed() {
+                        resize.set_percent(percent, source);
+                    }
+                    ui.end_row();
+                });
+                if ui.checkbox(&mut resize.lock_aspect, "Lock aspect ratio").changed() && resize.lock_aspect {
+                    resize.set_width(resize.width, source);
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Filter:");
+                    egui::ComboBox::from_id_salt("resize_filter")
+                        .selected_text(resize.filter.as_str())
+                        .show_ui(ui, |ui| {
+                            for filter in DownscaleFilter::ALL {
+                                ui.selectable_value(&mut resize.filter, filter, filter.as_str());
+                            }
+                        });
+                });
+                
+                ui.separator();
+                let changed = (resize.width, resize.height) != source;
+                ui.horizontal(|ui| {
+                    apply = ui.add_enabled(changed, egui::Button::new("Apply"))
+                        .on_hover_text("Resize the image in the viewer, the file is not changed")
+                        .clicked();
+                    export = ui.button("Export…")
+                        .on_hover_text("Save the resized image to a new file")
+                        .clicked();
+                });
+            });
+        
+        let (size, filter) = ((resize.width, resize.height), resize.filter.filter_type());
+        if !open {
+            self.resize = None;
+        }
+        if export {
+            if let Err(e) = self.export_resized(size, filter) {
+                error!("Failed to export resized image: {}", e);
+            }
+        }
+        if apply {
+            self.apply_resize(size, filter);
+            self.resize = None;
+        }
+    }
+    
+    /// Replace the loaded image (and its floating point data) by a resampled copy
+    fn apply_resize(&mut self, (width, height): (u32, u32), filter: FilterType) {
+        self.record_edit("Resize");
+        let Some(img) = &self.doc.image else {
+            return;
+        };
+        self.doc.image = Some(img.resize_exact(width, height, filter));
+        if let Some(frames) = &mut self.doc.frames {
+            frames.map(|frame| frame.resize_exact(width, height, filter));
+        }
+        if let Some(volume) = &mut self.doc.volume {
+            volume.edit(SliceEdit::Resize(width, height, filter));
+        }
+        if let (Some(fp_data), Some((fp_width, fp_height)), Some(channels)) =
+            (&self.doc.original_fp_data, self.doc.original_fp_dimensions, self.doc.original_fp_channels)
+        {
+            self.doc.original_fp_data = Some(resize::resize_fp(fp_data, fp_width, fp_height, channels as usize, width, height, filter));
+            self.doc.original_fp_dimensions = Some((width, height));
+            self.count_non_finite();
+        }
+        info!("Resized to {}x{} with {:?}", width, height, filter);
+        self.doc.edited = true;
+        
+        // Pixel coordinates no longer match
+        self.doc.roi = None;
+        if let Some(crop) = &mut self.crop {
+            crop.rect = None;
+        }
+        self.update_base_scale();
+        self.doc.invalidate_texture();
+        self.doc.histogram_needs_update = true;
+        self.doc.pixel_info = None;
+        self.doc.pixel_info_fp = None;
+        self.hover_pos = None;
+    }
+    
+    /// Save a resampled copy, floating point data is written as 32-bit TIFF
+    fn export_resized(&self, (width, height): (u32, u32), filter: FilterType) -> anyhow::Result<()> {
+        let img = self.doc.image.as_ref().ok_or_else(|| anyhow::anyhow!("No image loaded"))?;
+        let is_fp = self.doc.original_fp_data.is_some();
+        let name = self.export_file_name(&self.config.export_naming.resize, None, (width, height), None);
+        let default_name = format!("{}.{}", name, if is_fp { "tif" } else { "png" });
+        let mut dialog = rfd::FileDialog::new().set_file_name(default_name);
+        if let Some(folder) = &self.last_opened_folder {
+            dialog = dialog.set_directory(folder);
+        }
+        let Some(path) = dialog.save_file() else {
+            return Ok(());
+        };
+        
+        if let (Some(fp_data), Some((fp_width, fp_height)), Some(channels)) =
+            (&self.doc.original_fp_data, self.doc.original_fp_dimensions, self.doc.original_fp_channels)
+        {
+            let data = resize::resize_fp(fp_data, fp_width, fp_height, channels as usize, width, height, filter);
+            Self::save_fp_tiff(&path, &data, width, height, channels)?;
+        } else {
+            img.resize_exact(width, height, filter).save(&path)?;
+        }
+        info!("Exported {}x{} resized image to {:?}", width, height, path);
+        Ok(())
+    }
+    
+    fn render_annotation_window(&mut self, ctx: &egui::Context) {
+        let mut open = true;
+        let mut changed = false;
+        let mut delete = false;
+        let mut reload = false;
+        let mut export = false;
+        let Some(editor) = &mut self.annotate else {
+            return;
+        };
+        let annotations = &mut self.doc.annotations;
+        
+        egui::Window::new("Annotations")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    for tool in AnnotationTool::ALL {
+                        ui.selectable_value(&mut editor.tool, tool, tool.as_str());
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Color:");
+                    ui.color_edit_button_srgba(&mut editor.color);
+                    ui.label("Width:");
+                    ui.add(egui::DragValue::new(&mut editor.width).range(1.0..=50.0).speed(0.1).suffix(" px"));
+                });
+                if editor.tool == AnnotationTool::Text {
+                    ui.horizontal(|ui| {
+                        ui.label("Text:");
+                        ui.text_edit_singleline(&mut editor.text);
+                        ui.label("Size:");
+                        ui.add(egui::DragValue::new(&mut editor.text_size).range(4.0..=500.0).suffix(" px"));
+                    });
+                    ui.weak("Click on the image to place the text");
+                }
+                
+                ui.separator();
+                if let Some(annotation) = editor.selected.and_then(|index| annotations.get_mut(index)) {
+                    ui.horizontal(|ui| {
+                        ui.label("Selected:");
+                        let mut color = annotation.color32();
+                        if ui.color_edit_button_srgba(&mut color).changed() {
+                            annotation.color = color.to_srgba_unmultiplied();
+                            changed = true;
+                        }
+                        match &mut annotation.shape {
+                            Shape::Text { text, size, .. } => {
+                                changed |= ui.text_edit_singleline(text).changed();
+                                changed |= ui.add(egui::DragValue::new(size).range(4.0..=500.0).suffix(" px")).changed();
+                            }
+                            _ => {
+                                changed |= ui.add(egui::DragValue::new(&mut annotation.width).range(1.0..=50.0).speed(0.1).suffix(" px")).changed();
+                            }
+                        }
+                        delete = ui.button("Delete").on_hover_text("Remove the selected annotation (Delete)").clicked();
+                    });
+                } else {
+                    ui.weak("Click an annotation with the Select tool to edit it, drag to move it");
+                }
+                
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} annotations", annotations.len()));
+                    if ui.add_enabled(!annotations.is_empty(), egui::Button::new("Clear all")).clicked() {
+                        annotations.clear();
+                        editor.cancel();
+                        changed = true;
+                    }
+                    reload = ui.button("Reload").on_hover_text("Read the sidecar file again").clicked();
+                    export = ui.add_enabled(!annotations.is_empty(), egui::Button::new("Export with annotations…"))
+                        .on_hover_text("Save the displayed image with the annotations burned in")
+                        .clicked();
+                });
+            });
+        
+        if !open {
+            self.annotate = None;
+        }
+        if changed {
+            self.save_annotations();
+        }
+        if delete {
+            self.delete_selected_annotation();
+        }
+        if reload {
+            if let Some(path) = &self.doc.image_path {
+                match annotations::load(path) {
+                    Ok(annotations) => self.doc.annotations = annotations,
+                    Err(e) => error!("Failed to load annotations: {:#}", e),
+                }
+            }
+            if let Some(editor) = &mut self.annotate {
+                editor.cancel();
+            }
+        }
+        if export {
+            if let Err(e) = self.export_annotated() {
+                error!("Failed to export annotated image: {:#}", e);
+            }
+        }
+    }
+    
+    fn delete_selected_annotation(&mut self) {
+        let Some(index) = self.annotate.as_mut().and_then(|editor| editor.selected.take()) else {
+            return;
+        };
+        if index < self.doc.annotations.len() {
+            self.doc.annotations.remove(index);
+            self.save_annotations();
+        }
+    }
+    
+    /// Write the annotations of the current image to its sidecar
+    fn save_annotations(&self) {
+        let Some(path) = &self.doc.image_path else {
+            return;
+        };
+        if let Err(e) = annotations::save(path, &self.doc.annotations) {
+            error!("Failed to save annotations: {:#}", e);
+        }
+    }
+    
+    /// Save the displayed image with the annotations drawn into it
+    fn export_annotated(&self) -> anyhow::Result<()> {
+        let img = self.doc.image.as_ref().ok_or_else(|| anyhow::anyhow!("No image loaded"))?;
+        let (width, height) = img.dimensions();
+        let name = self.export_file_name(&self.config.export_naming.annotated, None, (width, height), None);
+        let mut dialog = rfd::FileDialog::new().set_file_name(format!("{}.png", name));
+        if let Some(folder) = &self.last_opened_folder {
+            dialog = dialog.set_directory(folder);
+        }
+        let Some(path) = dialog.save_file() else {
+            return Ok(());
+        };
+        
+        let displayed = self.render_display_image(img.clone());
+        let pixels = displayed.pixels.iter().flat_map(|color| color.to_srgba_unmultiplied()).collect();
+        let mut output = image::RgbaImage::from_raw(width, height, pixels).ok_or_else(|| anyhow::anyhow!("Unexpected display image size"))?;
+        annotations::burn_in(&mut output, &self.doc.annotations)?;
+        let output = DynamicImage::ImageRgba8(output);
+        // JPEG has no alpha channel
+        if image::ImageFormat::from_path(&path).is_ok_and(|format| format == image::ImageFormat::Jpeg) {
+            output.to_rgb8().save(&path)?;
+        } else {
+            output.save(&path)?;
+        }
+        info!("Exported image with {} annotations to {:?}", self.doc.annotations.len(), path);
+        Ok(())
+    }
+    
+    /// Save the visible part of the image as it is shown, with the pixel grid and the annotations,
+    /// at `factor` pixels per screen point
+    fn export_view(&self, factor: u32) -> anyhow::Result<()> {
+        let img = self.doc.image.as_ref().ok_or_else(|| anyhow::anyhow!("No image loaded"))?;
+        let view_rect = self.view_rect.ok_or_else(|| anyhow::anyhow!("The image is not shown"))?;
+        let final_scale = self.doc.base_scale * self.doc.scale;
+        let size = egui::vec2(img.width() as f32, img.height() as f32);
+        let view = ViewMapping { center: view_rect.center() + self.doc.offset, scale: final_scale, orientation: self.doc.view_orientation, size };
+        let rect = view.rect_to_screen(egui::Pos2::ZERO, size.to_pos2()).intersect(view_rect);
+        if !rect.is_positive() {
+            anyhow::bail!("No part of the image is in view");
+        }
+        let output_size = ((rect.width() * factor as f32).round() as u32, (rect.height() * factor as f32).round() as u32);
+        let name = self.export_file_name(&self.config.export_naming.view, None, output_size, self.doc.visible_region);
+        let mut dialog = rfd::FileDialog::new().set_file_name(format!("{}.png", name)).add_filter("PNG", &["png"]);
+        if let Some(folder) = &self.last_opened_folder {
+            dialog = dialog.set_directory(folder);
+        }
+        let Some(path) = dialog.save_file() else {
+            return Ok(());
+        };
+
+        let source = match &self.doc.compare {
+            Some(compare) => compare.view_image(img),
+            None => img.clone(),
+        };
+        // Reduced like the texture when the output has fewer pixels than the image
+        let output_scale = final_scale * factor as f32;
+        let source = if output_scale < 1.0 {
+            let (width, height) = ((size.x * output_scale).max(1.0) as u32, (size.y * output_scale).max(1.0) as u32);
+            source.resize(width, height, self.config.downscale_filter.filter_type())
+        } else {
+            source
+        };
+        let displayed = self.render_display_image(source);
+        let pixels = displayed.pixels.iter().flat_map(|color| color.to_srgba_unmultiplied()).collect();
+        let displayed = image::RgbaImage::from_raw(displayed.width() as u32, displayed.height() as u32, pixels)
+            .ok_or_else(|| anyhow::anyhow!("Unexpected display image size"))?;
+        let grid = (!self.config.hide_pixel_grid && final_scale >= PIXEL_GRID_MIN_SCALE).then(|| pixel_grid_color(final_scale));
+        let output = view_export::render(&displayed, view, rect, factor as f32, grid, &self.doc.annotations)?;
+        output.save(&path)?;
+        info!("Exported the {}x{} view to {:?}", output.width(), output.height(), path);
+        Ok(())
+    }
+    
+    /// Pixel size stored with the image: NIfTI voxel spacing or TIFF resolution
+    fn file_calibration(&self) -> Option<Calibration> {
+        if let Some(volume) = &self.doc.volume {
+            let (width, height) = volume.pixel_spacing();
+            return Some(Calibration { width: width as f64, height: height as f64, unit: LengthUnit::Millimeter });
+        }
+        self.doc.tiff_calibration
+    }
+    
+    /// Lines and labels of the measurements, plus a rubber band from the last clicked point
+    fn paint_measurements(&self, ui: &egui::Ui, measure: &MeasureTool, view: &ViewMapping) {
+        let painter = ui.painter();
+        let to_screen = |p: [f32; 2]| view.to_screen(egui::pos2(p[0], p[1]));
+        let color = egui::Color32::from_rgb(255, 220, 0);
+        let draw_path = |points: &[egui::Pos2]| {
+            painter.add(egui::Shape::line(points.to_vec(), egui::Stroke::new(3.0, egui::Color32::BLACK)));
+            painter.add(egui::Shape::line(points.to_vec(), egui::Stroke::new(1.5, color)));
+            for point in points {
+                painter.circle_filled(*point, 3.0, color);
+            }
+        };
+        let label = |pos: egui::Pos2, text: String| {
+            let galley = painter.layout_no_wrap(text, egui::FontId::proportional(13.0), color);
+            let rect = egui::Align2::LEFT_BOTTOM.anchor_size(pos + egui::vec2(6.0, -6.0), galley.size()).expand(2.0);
+            painter.rect_filled(rect, 2.0, egui::Color32::from_black_alpha(180));
+            painter.galley(rect.min + egui::vec2(2.0, 2.0), galley, color);
+        };
+        
+        for (index, measurement) in measure.measurements.iter().enumerate() {
+            let points: Vec<egui::Pos2> = measurement.points().into_iter().map(to_screen).collect();
+            draw_path(&points);
+            // Label at the middle of a line, at the vertex of an angle
+            let anchor = match measurement {
+                Measurement::Distance(..) => points[0] + (points[1] - points[0]) / 2.0,
+                Measurement::Angle(..) => points[1],
+            };
+            label(anchor, format!("{}: {}", index + 1, measurement.to_text(&measure.calibration)));
+        }
+        
+        if !measure.pending.is_empty() {
+            let mut points: Vec<egui::Pos2> = measure.pending.iter().map(|p| to_screen(*p)).collect();
+            if let Some(hover) = ui.input(|i| i.pointer.hover_pos()) {
+                points.push(hover);
+            }
+            draw_path(&points);
+        }
+    }
+    
+    fn render_expression_window(&mut self, ctx: &egui::Context) {
+        let Some(expression) = &mut self.expression else {
+            return;
+        };
+        let mut open = true;
+        let mut changed = false;
+        
+        egui::Window::new("Expression")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                changed = expression.ui(ui);
+            });
+        
+        if !open {
+            self.expression = None;
+        }
+        if changed || !open {
+            self.doc.invalidate_texture();
+            // The rebuild reports the new range
+            ctx.request_repaint();
+        }
+    }
+    
+    fn render_threshold_window(&mut self, ctx: &egui::Context) {
+        let Some(threshold) = &mut self.threshold else {
+            return;
+        };
+        let mut open = true;
+        let mut changed = false;
+        
+        egui::Window::new("Threshold")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                changed = threshold.ui(ui);
+            });
+        
+        if !open {
+            self.threshold = None;
+        }
+        if changed || !open {
+            self.doc.invalidate_texture();
+            // The rebuild reports the new result
+            ctx.request_repaint();
+        }
+    }
+
+    fn render_mask_window(&mut self, ctx: &egui::Context) {
+        let image_size = self.full_image_size();
+        let factor = self.detail_factor().unwrap_or(1.0);
+        let Some(overlay) = &mut self.mask_overlay else {
+            return;
+        };
+        let mut open = true;
+        let mut load = false;
+        
+        egui::Window::new("Mask overlay")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                load = ui.button("Load mask…").clicked();
+                overlay.ui(ui);
+                let Some(mask) = &overlay.mask else {
+                    return;
+                };
+                match image_size {
+                    Some(size) if size != (mask.width, mask.height) => {
+                        ui.colored_label(ui.visuals().warn_fg_color, format!("The image is {}×{}, the mask does not fit it", size.0, size.1));
+                    }
+                    _ => {
+                        let label = self.hover_pixel.and_then(|(x, y)| mask.label_at((x as f32 * factor) as u32, (y as f32 * factor) as u32));
+                        ui.label(label.map_or("Point at the image to see its label".to_string(), |label| format!("Label under the pointer: {}", mask.label_name(label))));
+                    }
+                }
+            });
+        
+        if !open {
+            self.mask_overlay = None;
+        } else if load {
+            self.open_mask();
+        }
+    }
+
+    fn render_layers_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.heading("Layers");
+            if ui.button("Add layer…").on_hover_text("Put images over the shown one").clicked() {
+                self.add_layers();
             }
-            _ => {
-                return Err(anyhow::anyhow!("Unsupported TIFF color type: {:?}", colortype));
+        });
+        ui.separator();
+        let changed = egui::ScrollArea::vertical().show(ui, |ui| self.doc.layers.ui(ui)).inner;
+        if changed {
+            self.doc.invalidate_texture();
+        }
+    }
+
+    /// Pick images and put them on top of the layer stack
+    fn add_layers(&mut self) {
+        let mut dialog = rfd::FileDialog::new()
+            .set_title("Add layers")
+            .add_filter("Images", &["png", "jpg", "jpeg", "bmp", "tif", "tiff", "webp", "gif", "avif", "hdr", "exr", "farbfeld", "qoi", "dds", "tga", "pnm", "ff", "ico"]);
+        if let Some(folder) = &self.last_opened_folder {
+            dialog = dialog.set_directory(folder);
+        }
+        for path in dialog.pick_files().unwrap_or_default() {
+            match load_image_source(&path) {
+                Ok(img) => {
+                    info!("Layer {:?} with {}×{} pixels", path, img.width(), img.height());
+                    self.doc.layers.push(&path, img);
+                    self.doc.invalidate_texture();
+                }
+                Err(e) => {
+                    error!("Failed to load the layer: {}", e);
+                    self.report_load_error(&e);
+                }
+            }
+        }
+    }
+
+    /// Pick a mask for the current image
+    fn open_mask(&mut self) {
+        let Some(overlay) = &mut self.mask_overlay else {
+            return;
+        };
+        let mut dialog = rfd::FileDialog::new()
+            .set_title("Load mask")
+            .add_filter("Images", &["png", "tif", "tiff", "bmp", "pnm", "tga", "qoi"]);
+        if let Some(folder) = overlay.folder.as_ref().or(self.last_opened_folder.as_ref()) {
+            dialog = dialog.set_directory(folder);
+        }
+        let Some(path) = dialog.pick_file() else {
+            return;
+        };
+        overlay.image_path = self.doc.image_path.clone();
+        match Mask::load(&path) {
+            Ok(mask) => {
+                info!("Mask {:?} with {}×{} pixels", path, mask.width, mask.height);
+                overlay.set_mask(mask);
+            }
+            Err(e) => {
+                error!("Failed to load the mask: {:#}", e);
+                overlay.message = Some(format!("{:#}", e));
+            }
+        }
+    }
+
+    /// Follow the shown image with its mask and build the overlay texture
+    fn update_mask_overlay(&mut self, ctx: &egui::Context) {
+        let Some(overlay) = &mut self.mask_overlay else {
+            return;
+        };
+        if overlay.follow && overlay.image_path != self.doc.image_path {
+            overlay.image_path = self.doc.image_path.clone();
+            if let (Some(folder), Some(image)) = (overlay.folder.clone(), &self.doc.image_path) {
+                match mask_overlay::matching_mask(&Self::list_folder_images(&folder), image) {
+                    Some(path) if overlay.mask.as_ref().is_some_and(|mask| mask.path == path) => {}
+                    Some(path) => match Mask::load(&path) {
+                        Ok(mask) => overlay.set_mask(mask),
+                        Err(e) => {
+                            warn!("Failed to load the mask: {:#}", e);
+                            overlay.mask = None;
+                            overlay.message = Some(format!("{:#}", e));
+                        }
+                    },
+                    None => {
+                        let name = image.file_name().unwrap_or_default().to_string_lossy();
+                        overlay.mask = None;
+                        overlay.message = Some(format!("No mask for {} in {}", name, folder.display()));
+                    }
+                }
+            }
+        }
+        if overlay.texture.is_none() {
+            if let Some(color_image) = overlay.color_image() {
+                overlay.texture = Some(ctx.load_texture("mask-overlay", color_image, egui::TextureOptions::NEAREST));
+            }
+        }
+    }
+
+    fn render_measure_window(&mut self, ctx: &egui::Context) {
+        let file_calibration = self.file_calibration();
+        let Some(measure) = &mut self.measure else {
+            return;
+        };
+        let mut open = true;
+        
+        egui::Window::new("Measure")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    for (mode, name) in [(MeasureMode::Distance, "Distance"), (MeasureMode::Angle, "Angle")] {
+                        if ui.selectable_value(&mut measure.mode, mode, name).changed() {
+                            measure.pending.clear();
+                        }
+                    }
+                });
+                ui.weak(match measure.mode {
+                    MeasureMode::Distance => "Click the start and end point of the line",
+                    MeasureMode::Angle => "Click a point on the first arm, the vertex and a point on the second arm",
+                });
+                
+                ui.separator();
+                ui.horizontal(|ui| {
+                    let calibration = &mut measure.calibration;
+                    let physical = calibration.unit != LengthUnit::Pixel;
+                    ui.label("Pixel size:");
+                    ui.add_enabled(physical, egui::DragValue::new(&mut calibration.width).range(1e-9..=1e9).speed(0.01).max_decimals(6));
+                    ui.label("×");
+                    ui.add_enabled(physical, egui::DragValue::new(&mut calibration.height).range(1e-9..=1e9).speed(0.01).max_decimals(6));
+                    egui::ComboBox::from_id_salt("measure_unit")
+                        .selected_text(calibration.unit.as_str())
+                        .show_ui(ui, |ui| {
+                            for unit in LengthUnit::ALL {
+                                ui.selectable_value(&mut calibration.unit, unit, unit.as_str());
+                            }
+                        });
+                    if calibration.unit == LengthUnit::Pixel {
+                        (calibration.width, calibration.height) = (1.0, 1.0);
+                    }
+                    let from_file = ui.add_enabled(file_calibration.is_some(), egui::Button::new("From file"));
+                    let from_file = match file_calibration {
+                        Some(file) => from_file.on_hover_text(format!("{} × {} {} per pixel", file.width, file.height, file.unit.as_str())),
+                        None => from_file.on_disabled_hover_text("The image has no TIFF resolution or voxel spacing"),
+                    };
+                    if from_file.clicked() {
+                        *calibration = file_calibration.unwrap_or_default();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Last line is");
+                    ui.add(egui::DragValue::new(&mut measure.known_length).range(1e-9..=1e9).speed(0.1).max_decimals(6));
+                    egui::ComboBox::from_id_salt("measure_known_unit")
+                        .selected_text(measure.known_unit.as_str())
+                        .show_ui(ui, |ui| {
+                            for unit in LengthUnit::ALL.into_iter().filter(|unit| *unit != LengthUnit::Pixel) {
+                                ui.selectable_value(&mut measure.known_unit, unit, unit.as_str());
+                            }
+                        });
+                    if ui.add_enabled(measure.last_distance_pixels().is_some(), egui::Button::new("Calibrate"))
+                        .on_hover_text("Set the pixel size so the last measured line has this length")
+                        .clicked()
+                    {
+                        measure.calibrate_from_last();
+                    }
+                });
+                
+                ui.separator();
+                if measure.measurements.is_empty() {
+                    ui.weak("No measurements yet");
+                }
+                let mut remove = None;
+                egui::Grid::new("measurements").num_columns(3).striped(true).show(ui, |ui| {
+                    for (index, measurement) in measure.measurements.iter().enumerate() {
+                        ui.label(format!("{}", index + 1));
+                        ui.monospace(measurement.to_text(&measure.calibration));
+                        if ui.small_button("✖").on_hover_text("Remove measurement").clicked() {
+                            remove = Some(index);
+                        }
+                        ui.end_row();
+                    }
+                });
+                if let Some(index) = remove {
+                    measure.measurements.remove(index);
+                }
+                ui.horizontal(|ui| {
+                    let any = !measure.measurements.is_empty();
+                    if ui.add_enabled(any, egui::Button::new("Copy")).on_hover_text("Copy the measurements as text").clicked() {
+                        let text = measure.measurements.iter().enumerate()
+                            .map(|(index, measurement)| format!("{}\t{}", index + 1, measurement.to_text(&measure.calibration)))
+                            .collect::<Vec<_>>()
+                            .join("\n");
+                        ui.ctx().copy_text(text);
+                    }
+                    if ui.add_enabled(any || !measure.pending.is_empty(), egui::Button::new("Clear")).clicked() {
+                        measure.measurements.clear();
+                        measure.pending.clear();
+                    }
+                });
+            });
+        
+        if !open {
+            self.measure = None;
+        }
+    }
+    
+    /// Context of the export name templates for the current image and display settings
+    fn naming_context(&self, index: Option<usize>, size: (u32, u32), roi: Option<PixelRect>) -> NamingContext<'_> {
+        let path = self.doc.image_path.as_deref();
+        NamingContext {
+            name: path.and_then(|p| p.file_stem()).and_then(|stem| stem.to_str()).unwrap_or("image"),
+            ext: path.and_then(|p| p.extension()).and_then(|ext| ext.to_str()).unwrap_or_default(),
+            index: index.or(self.doc.current_image_index).map_or(1, |i| i + 1),
+            norm: self.doc.normalization,
+            transform: if self.show_fft { "fft" } else { "none" },
+            channel: self.doc.channel.as_str(),
+            width: size.0,
+            height: size.1,
+            roi,
+        }
+    }
+
+    /// File name (without extension) from an export template; `index` is the 0-based item of a
+    /// batch, by default the position of the image in its folder
+    fn export_file_name(&self, template: &str, index: Option<usize>, size: (u32, u32), roi: Option<PixelRect>) -> String {
+        let context = self.naming_context(index, size, roi);
+        naming::expand(template, &context, SystemTime::now()).unwrap_or_else(|e| {
+            error!("Invalid export name template {:?}: {}", template, e);
+            context.name.to_string()
+        })
+    }
+
+    /// Save every frame of the stack as PNG into `folder`, named by the frames template.
+    /// Returns the number of written files.
+    fn export_frames(&self, folder: &Path) -> anyhow::Result<usize> {
+        let frames = self.doc.frames.as_ref().ok_or_else(|| anyhow::anyhow!("The image has a single frame"))?;
+        let template = &self.config.export_naming.frames;
+        let paths: Vec<PathBuf> = frames.iter().enumerate()
+            .map(|(i, frame)| folder.join(format!("{}.png", self.export_file_name(template, Some(i), frame.dimensions(), None))))
+            .collect();
+        if paths.iter().collect::<std::collections::HashSet<_>>().len() < paths.len() {
+            anyhow::bail!("The template {:?} gives several frames the same name, add {{index}}", template);
+        }
+        for (frame, path) in frames.iter().zip(&paths) {
+            frame.save(path)?;
+        }
+        Ok(paths.len())
+    }
+
+    /// Save a region at full resolution, floating point data is written as 32-bit TIFF
+    fn export_crop(&self, rect: PixelRect) -> anyhow::Result<()> {
+        let [x0, y0, x1, y1] = rect;
+        let img = self.doc.image.as_ref().ok_or_else(|| anyhow::anyhow!("No image loaded"))?;
+        let is_fp = self.doc.original_fp_data.is_some();
+        let name = self.export_file_name(&self.config.export_naming.crop, None, (x1 - x0, y1 - y0), Some(rect));
+        let default_name = format!("{}.{}", name, if is_fp { "tif" } else { "png" });
+        let mut dialog = rfd::FileDialog::new().set_file_name(default_name);
+        if let Some(folder) = &self.last_opened_folder {
+            dialog = dialog.set_directory(folder);
+        }
+        let Some(path) = dialog.save_file() else {
+            return Ok(());
+        };
+        
+        if let (Some(fp_data), Some((width, _)), Some(channels)) =
+            (&self.doc.original_fp_data, self.doc.original_fp_dimensions, self.doc.original_fp_channels)
+        {
+            let data = crop::crop_fp(fp_data, width, channels as usize, rect);
+            Self::save_fp_tiff(&path, &data, x1 - x0, y1 - y0, channels)?;
+        } else {
+            img.crop_imm(x0, y0, x1 - x0, y1 - y0).save(&path)?;
+        }
+        info!("Exported {}x{} crop to {:?}", x1 - x0, y1 - y0, path);
+        Ok(())
+    }
+
+    /// Frames of an animated or multi-page file, `None` when it has a single frame or cannot be read as a stack
+    fn load_frame_stack(path: &Path) -> Option<FrameStack> {
+        frames::load_frames(path).unwrap_or_else(|e| {
+            warn!("Reading the frames of {:?} failed, showing the first one: {}", path, e);
+            None
+        })
+    }
+
+    /// Show frame `index` of A; with locked frames B follows to the same index
+    fn set_frame(&mut self, index: usize) {
+        let Some(frames) = &mut self.doc.frames else {
+            return;
+        };
+        let mut changed = frames.set_index(index);
+        let index = frames.index();
+        if changed {
+            let size_changed = self.doc.image.as_ref().map(|img| img.dimensions()) != Some(frames.current().dimensions());
+            self.doc.image = Some(frames.current().clone());
+            if size_changed {
+                self.doc.roi = None;
+                self.update_base_scale();
+            }
+            self.doc.pixel_info = None;
+            self.doc.pixel_info_fp = None;
+        }
+        if let Some(compare) = self.doc.compare.as_mut().filter(|c| c.frames_locked) {
+            changed |= compare.set_frame(index);
+        }
+        if changed {
+            self.frame_changed();
+        }
+    }
+
+    /// Show frame `index` of B on its own, while the frames are unlocked or A has a single frame
+    fn set_compare_frame(&mut self, index: usize) {
+        if self.doc.compare.as_mut().is_some_and(|compare| compare.set_frame(index)) {
+            self.frame_changed();
+        }
+    }
+
+    /// Step through the frames of A (and B with locked frames), or of B alone when only it has frames
+    fn step_frame(&mut self, step: i32) {
+        if let Some(volume) = &self.doc.volume {
+            self.set_volume_slice(volume.stepped(step));
+        } else if let Some(ome) = &self.doc.ome {
+            self.set_ome_position(ome.stepped(step));
+        } else if let Some(frames) = &self.doc.frames {
+            self.set_frame(frames.stepped(step));
+        } else if let Some(frames) = self.doc.compare.as_ref().and_then(|c| c.frames.as_ref()) {
+            self.set_compare_frame(frames.stepped(step));
+        }
+    }
+
+    /// Display data of the current slice of a volume, normalized to the range of the whole volume
+    fn volume_slice(volume: &Volume) -> LoadedImage {
+        let (img, data, width, height) = volume.slice_image();
+        (img, true, Some(volume.range), Some(data), Some((width, height)), Some(1))
+    }
+
+    /// Middle axial slice of a NIfTI volume, for thumbnails and compare images
+    fn load_nifti(path: &Path) -> anyhow::Result<LoadedImage> {
+        nifti::load_volume(path).map(|volume| Self::volume_slice(&volume))
+    }
+
+    /// Show the volume's current slice after the slice or plane changed
+    fn show_volume_slice(&mut self) {
+        let Some(volume) = &self.doc.volume else {
+            return;
+        };
+        let (img, _, _, fp_data, fp_dims, _) = Self::volume_slice(volume);
+        let size_changed = self.doc.original_fp_dimensions != fp_dims;
+        self.doc.image = Some(img);
+        self.doc.original_fp_data = fp_data;
+        self.doc.original_fp_dimensions = fp_dims;
+        self.count_non_finite();
+        if size_changed {
+            self.doc.roi = None;
+            self.update_base_scale();
+        }
+        self.doc.pixel_info = None;
+        self.doc.pixel_info_fp = None;
+        self.frame_changed();
+    }
+
+    fn set_volume_slice(&mut self, slice: usize) {
+        if self.doc.volume.as_mut().is_some_and(|volume| volume.set_slice(slice)) {
+            self.show_volume_slice();
+        }
+    }
+
+    fn set_volume_plane(&mut self, plane: Plane) {
+        if self.doc.volume.as_mut().is_some_and(|volume| volume.set_plane(plane)) {
+            info!("Showing {} slices", plane.as_str());
+            // The edits of the old plane are gone
+            self.doc.history.clear();
+            self.show_volume_slice();
+        }
+    }
+
+    /// Show another plane of an OME-TIFF, read from its page. Edits of the previous plane are dropped.
+    fn set_ome_position(&mut self, position: [usize; 3]) {
+        let (Some(ome), Some(path)) = (&mut self.doc.ome, &self.doc.image_path) else {
+            return;
+        };
+        if !ome.set_position(position) {
+            return;
+        }
+        let page = ome.page();
+        let (img, is_fp, data_range, fp_data, fp_dims, fp_channels) = match Self::load_tiff_page(path, page) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                error!("Failed to read page {} of {:?}: {:#}", page, path, e);
+                return;
+            }
+        };
+        let [z, channel, time] = ome.position();
+        info!("Showing z {}, channel {}, time {} from page {}", z + 1, ome.channel_names[channel], time + 1, page);
+        let size_changed = self.doc.image.as_ref().map(|img| img.dimensions()) != Some(img.dimensions());
+        self.doc.image = Some(img);
+        self.doc.is_floating_point_image = is_fp;
+        self.doc.original_data_range = data_range;
+        self.doc.original_fp_data = fp_data;
+        self.doc.original_fp_dimensions = fp_dims;
+        self.doc.original_fp_channels = fp_channels;
+        self.count_non_finite();
+        self.doc.orientation = Orientation::default();
+        self.doc.edited = false;
+        self.doc.history.clear();
+        if size_changed {
+            self.doc.roi = None;
+            self.update_base_scale();
+        }
+        self.doc.pixel_info = None;
+        self.doc.pixel_info_fp = None;
+        self.frame_changed();
+    }
+
+    fn frame_changed(&mut self) {
+        self.doc.invalidate_texture();
+        self.doc.histogram_needs_update = true;
+    }
+
+    /// Pick a second image and enter compare mode
+    fn open_compare_image(&mut self) {
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter("Images", &["png", "jpg", "jpeg", "bmp", "tif", "tiff", "webp", "gif", "avif", "hdr", "exr", "farbfeld", "qoi", "dds", "tga", "pnm", "ff", "ico"])
+            .add_filter("Camera RAW", &raw::RAW_EXTENSIONS)
+            .add_filter("NIfTI volume", &["nii", "gz"]);
+        if let Some(folder) = &self.last_opened_folder {
+            dialog = dialog.set_directory(folder);
+        }
+        if let Some(path) = dialog.pick_file() {
+            match Self::load_image_with_fallback(&path) {
+                Ok((img, ..)) => {
+                    info!("Comparing against {:?}", path);
+                    let frames = Self::load_frame_stack(&path);
+                    let mut compare = CompareState::new(&path, img, frames);
+                    if compare.frames_locked {
+                        if let Some(frames) = &self.doc.frames {
+                            compare.set_frame(frames.index());
+                        }
+                    }
+                    self.doc.compare = Some(compare);
+                    self.doc.invalidate_texture();
+                }
+                Err(e) => {
+                    error!("Failed to load comparison image: {}", e);
+                    self.report_load_error(&e);
+                }
+            }
+        }
+    }
+
+    /// Display image of HDR data from the FP data, with the selected tone mapper and exposure
+    fn apply_tone_mapping(&mut self) {
+        let (Some(fp_data), Some((width, height)), Some(channels)) =
+            (&self.doc.original_fp_data, self.doc.original_fp_dimensions, self.doc.original_fp_channels)
+        else {
+            return;
+        };
+        match tonemap::tone_map(fp_data, width, height, channels, self.config.tone_mapper, self.doc.hdr_exposure) {
+            Ok(img) => {
+                self.doc.image = Some(img);
+                self.doc.invalidate_texture();
+                self.doc.histogram_needs_update = true;
+            }
+            Err(e) => error!("Failed to tone map: {}", e),
+        }
+    }
+
+    /// Decode OpenEXR / Radiance HDR as linear float data, displayed through the default tone mapper
+    fn load_hdr(path: &Path) -> anyhow::Result<LoadedImage> {
+        let img = image::open(path)?;
+        let (width, height) = img.dimensions();
+        let channels = if img.color().has_alpha() { 4 } else { 3 };
+        let data = if channels == 4 { img.into_rgba32f().into_raw() } else { img.into_rgb32f().into_raw() };
+        
+        let color_values = data.chunks_exact(channels as usize).flat_map(|p| &p[..3]).filter(|v| v.is_finite());
+        let (min_val, max_val) = color_values.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &v| (min.min(v), max.max(v)));
+        info!("HDR {}x{}, {} channels, range {} to {}", width, height, channels, min_val, max_val);
+        
+        let display = tonemap::tone_map(&data, width, height, channels, ToneMapper::default(), 0.0)?;
+        Ok((display, true, Some((min_val, max_val)), Some(data), Some((width, height)), Some(channels)))
+    }
+
+    /// Demosaic a camera RAW file into linear float data, displayed through the default tone mapper
+    fn load_raw(path: &Path) -> anyhow::Result<(LoadedImage, RawDevelop)> {
+        let (data, width, height, develop) = raw::develop(path)?;
+        let (min_val, max_val) = data.iter().filter(|v| v.is_finite()).fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &v| (min.min(v), max.max(v)));
+        info!("RAW developed to {}x{}, white balance {:?}, range {} to {}", width, height, develop.multipliers, min_val, max_val);
+        
+        let display = tonemap::tone_map(&data, width, height, 3, ToneMapper::default(), 0.0)?;
+        Ok(((display, true, Some((min_val, max_val)), Some(data), Some((width, height)), Some(3)), develop))
+    }
+
+    /// Re-balance the RAW data to new camera multipliers and tone map it again
+    fn set_white_balance(&mut self, white_balance: WhiteBalance, multipliers: [f32; 3]) {
+        let (Some(develop), Some(fp_data)) = (&mut self.doc.raw_develop, &mut self.doc.original_fp_data) else {
+            return;
+        };
+        develop.rebalance(fp_data, 3, white_balance, multipliers);
+        info!("White balance {:?}: {:?}", white_balance, multipliers);
+        self.apply_tone_mapping();
+    }
+
+    fn load_image_with_fallback(path: &Path) -> anyhow::Result<LoadedImage> {
+        if tonemap::is_hdr_file(path) {
+            return Self::load_hdr(path);
+        }
+        if nifti::is_nifti_file(path) {
+            return Self::load_nifti(path);
+        }
+        if raw::is_raw_file(path) {
+            return Self::load_raw(path).map(|(loaded, _)| loaded);
+        }
+        if tiff_format::is_tiff_file(path) {
+            tiff_format::check(path)?;
+        }
+        // Huge PNGs and JPEGs are streamed at reduced resolution instead of exhausting memory
+        if let Some(mut img) = large_image::decode_reduced(path)? {
+            let orientation = orientation::read_file_orientation(path).to_exif();
+            if let Some(orientation) = image::metadata::Orientation::from_exif(orientation) {
+                img.apply_orientation(orientation);
+            }
+            return Ok((img, false, None, None, None, None));
+        }
+        // Try the standard image crate first, honoring the EXIF orientation
+        match orientation::open_oriented(path) {
+            Ok(img) => {
+                info!("Successfully loaded image using standard image crate");
+                Ok((img, false, None, None, None, None))
+            }
+            Err(e) => {
+                warn!("Standard image loading failed: {}", e);
+                
+                // Check if it's a TIFF file and try direct TIFF loading
+                if tiff_format::is_tiff_file(path) {
+                    info!("Attempting to load TIFF file with direct TIFF decoder");
+                    return Self::load_tiff_direct(path);
+                }
+                
+                // If not TIFF or TIFF loading failed, return the original error
+                Err(e.into())
+            }
+        }
+    }
+    
+    fn load_tiff_direct(path: &Path) -> anyhow::Result<LoadedImage> {
+        Self::load_tiff_page(path, 0)
+    }
+
+    /// Decode page `page` of a TIFF, counted from 0
+    fn load_tiff_page(path: &Path, page: usize) -> anyhow::Result<LoadedImage> {
+        let file = File::open(path)?;
+        let mut decoder = tiff::decoder::Decoder::new(BufReader::new(file))?;
+        if page > 0 {
+            decoder.seek_to_image(page)?;
+        }
+        
+        // Read the image
+        let (width, height) = decoder.dimensions()?;
+        // Multispectral bands are read without the tiff crate, which only knows up to RGBA
+        if multiband::samples_per_pixel(&mut decoder)? > 4 {
+            let (data, width, height, bands) = multiband::read_bands(&mut decoder, path)?;
+            info!("TIFF dimensions: {}x{}, {} bands", width, height, bands);
+            let (img, range) = multiband::preview(&data, width, height, bands, BandMapping::default())?;
+            return Ok((img, true, Some(range), Some(data), Some((width, height)), Some(bands)));
+        }
+        let colortype = decoder.colortype()?;
+        
+        info!("TIFF dimensions: {}x{}, colortype: {:?}", width, height, colortype);
+        
+        let channels = match colortype {
+            tiff::ColorType::Gray(_) => 1,
+            tiff::ColorType::RGB(_) => 3,
+            // The JPEG decoder already converts YCbCr to RGB
+            tiff::ColorType::YCbCr(8) if tiff_format::compression(&mut decoder) == 7 => 3,
+            tiff::ColorType::RGBA(_) => 4,
+            _ => return Err(anyhow::anyhow!("Unsupported TIFF color type: {:?}", colortype)),
+        };
+        let buffer_error = || anyhow::anyhow!("Failed to create image buffer from TIFF data");
+        match decoder.read_image()? {
+            tiff::decoder::DecodingResult::U8(img_data) => {
+                let img = match channels {
+                    1 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, img_data).ok_or_else(buffer_error)?),
+                    3 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, img_data).ok_or_else(buffer_error)?),
+                    _ => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, img_data).ok_or_else(buffer_error)?),
+                };
+                Ok((img, false, None, None, None, None))
+            }
+            tiff::decoder::DecodingResult::U16(img_data) => {
+                let img = match channels {
+                    1 => DynamicImage::ImageLuma16(ImageBuffer::from_raw(width, height, img_data).ok_or_else(buffer_error)?),
+                    3 => DynamicImage::ImageRgb16(ImageBuffer::from_raw(width, height, img_data).ok_or_else(buffer_error)?),
+                    _ => DynamicImage::ImageRgba16(ImageBuffer::from_raw(width, height, img_data).ok_or_else(buffer_error)?),
+                };
+                Ok((img, false, None, None, None, None))
+            }
+            // 32-bit and 64-bit floats, signed integers and 32-bit and 64-bit unsigned integers
+            // have no image crate type, they are shown through the floating point pipeline
+            img_data => {
+                let (mut img_data, alpha_max) = tiff_samples_f32(img_data);
+                if img_data.len() != (width * height * channels) as usize {
+                    return Err(buffer_error());
+                }
+                // Alpha is 0-1 in floating point data
+                if let (4, Some(alpha_max)) = (channels, alpha_max) {
+                    for alpha in img_data.iter_mut().skip(3).step_by(4) {
+                        *alpha /= alpha_max;
+                    }
+                }
+                // Find min/max values for proper normalization (excluding alpha channel, NaN and infinity)
+                let color_channels = channels.min(3) as usize;
+                let color_values = img_data.chunks_exact(channels as usize).flat_map(|pixel| &pixel[..color_channels]).filter(|v| v.is_finite());
+                let (min_val, max_val) = color_values.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &v| (min.min(v), max.max(v)));
+                
+                info!("TIFF range: {} to {}", min_val, max_val);
+                
+                // Convert to u8 for display with proper normalization, middle gray if all values are the same
+                let scale = if max_val - min_val > f32::EPSILON { 255.0 / (max_val - min_val) } else { 0.0 };
+                let converted_data: Vec<u8> = img_data.chunks_exact(channels as usize)
+                    .flat_map(|pixel| pixel.iter().enumerate().map(|(channel, &val)| match channel {
+                        3 => (val.clamp(0.0, 1.0) * 255.0) as u8, // Alpha stays 0-1
+                        _ if scale > 0.0 => ((val - min_val) * scale) as u8,
+                        _ => 128,
+                    }))
+                    .collect();
+                let img = match channels {
+                    1 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, converted_data).ok_or_else(buffer_error)?),
+                    3 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, converted_data).ok_or_else(buffer_error)?),
+                    _ => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, converted_data).ok_or_else(buffer_error)?),
+                };
+                Ok((img, true, Some((min_val, max_val)), Some(img_data), Some((width, height)), Some(channels)))
             }
         }
     }
     
     fn calculate_window_size(&self) -> (f32, f32) {
-        if let Some(img) = &self.image {
+        if let Some(img) = &self.doc.image {
             let (width, height) = img.dimensions();
             let (w, h) = (width as f32, height as f32);
             
+            // Quick look windows have no chrome, size them to the image itself
+            if self.quick_look {
+                return ((w * self.doc.base_scale).max(64.0), (h * self.doc.base_scale).max(64.0));
+            }
+            
             // Add space for UI elements (top panel)
             let ui_height = 80.0;
             let ui_padding = 40.0;
             
-            let scaled_width = (w * self.base_scale + ui_padding).max(400.0).min(1024.0);
-            let scaled_height = (h * self.base_scale + ui_height + ui_padding).max(400.0).min(1024.0);
+            let scaled_width = (w * self.doc.base_scale + ui_padding).clamp(400.0, 1024.0);
+            let scaled_height = (h * self.doc.base_scale + ui_height + ui_padding).clamp(400.0, 1024.0);
             
             (scaled_width, scaled_height)
         } else {
@@ -463,24 +4537,27 @@ impl ImageViewerApp {
     
     fn render_histogram_in_viewport(
         ui: &mut egui::Ui, 
-        histograms: &[Vec<u32>], 
-        histogram_hover_info: &mut Option<(u32, u32, f32)>,
-        histogram_hover_pos: &mut Option<egui::Pos2>
+        histograms: &[Vec<f32>], 
+        histogram_hover_info: &mut Option<(u32, f32, f32)>,
+        histogram_hover_pos: &mut Option<egui::Pos2>,
+        view: HistogramView,
+        axis: HistogramAxis,
     ) {
         let available_size = ui.available_size();
-        let plot_size = egui::vec2(available_size.x, available_size.y - 40.0);
+        let plot_size = egui::vec2(available_size.x, available_size.y - 64.0);
         
         ui.allocate_ui(plot_size, |ui| {
             let rect = ui.available_rect_before_wrap();
+            let bins = histograms[0].len();
             
             // Handle mouse hover for histogram info
             if let Some(hover_pos) = ui.input(|i| i.pointer.hover_pos()) {
                 if rect.contains(hover_pos) {
                     // Calculate which bin we're hovering over
                     let relative_x = hover_pos.x - rect.min.x;
-                    let bin = ((relative_x / rect.width()) * 256.0) as usize;
+                    let bin = ((relative_x / rect.width()) * bins as f32) as usize;
                     
-                    if bin < 256 {
+                    if bin < bins {
                         // Get counts for all channels
                         let red_count = histograms[0][bin];
                         let green_count = histograms[1][bin];
@@ -494,9 +4571,9 @@ impl ImageViewerApp {
                         };
                         
                         // Calculate total pixels for percentage
-                        let total_pixels: u32 = histograms[0].iter().sum();
-                        let percentage = if total_pixels > 0 {
-                            (display_count as f32 / total_pixels as f32) * 100.0
+                        let total_pixels: f32 = histograms[0].iter().sum();
+                        let percentage = if total_pixels > 0.0 {
+                            (display_count / total_pixels) * 100.0
                         } else {
                             0.0
                         };
@@ -513,15 +4590,43 @@ impl ImageViewerApp {
                 *histogram_hover_pos = None;
             }
             
-            // Find max value for scaling
-            let max_value = histograms.iter()
-                .flat_map(|h| h.iter())
-                .cloned()
-                .max()
-                .unwrap_or(1) as f32;
+            // Plotted series: counts or cumulative fractions, optionally on a log axis
+            let total_pixels: f32 = histograms[0].iter().sum();
+            let log_scale = if view.cumulative { 1000.0 } else { 1.0 }; // Fractions as per mille for a usable log range
+            let series: Vec<Vec<f32>> = histograms.iter().map(|histogram| {
+                let values: Vec<f32> = if view.cumulative {
+                    histogram.iter()
+                        .scan(0.0f64, |sum, &count| {
+                            *sum += count as f64;
+                            Some((*sum / total_pixels.max(f32::EPSILON) as f64) as f32)
+                        })
+                        .collect()
+                } else {
+                    histogram.clone()
+                };
+                if view.log_scale {
+                    values.iter().map(|v| (v * log_scale).ln_1p()).collect()
+                } else {
+                    values
+                }
+            }).collect();
             
-            // Draw histogram bars
-            let bar_width = rect.width() / 256.0;
+            // Find max value of the visible channels for scaling
+            let max_value = series.iter()
+                .enumerate()
+                .filter(|(channel, _)| !view.hidden[*channel])
+                .flat_map(|(_, values)| values.iter())
+                .fold(0.0f32, |a, &b| a.max(b))
+                .max(f32::EPSILON);
+            // Inverse of the plotted transform, for the axis labels
+            let axis_value = |plotted: f32| -> f32 {
+                let value = if view.log_scale { plotted.exp_m1() / log_scale } else { plotted };
+                if view.cumulative { value * 100.0 } else { value }
+            };
+            
+            // Draw histogram bars, bins sharing a pixel column are drawn as their maximum
+            let columns = bins.min(rect.width().max(1.0) as usize).max(1);
+            let bar_width = rect.width() / columns as f32;
             let colors = [
                 egui::Color32::from_rgb(255, 80, 80),   // Red
                 egui::Color32::from_rgb(80, 255, 80),   // Green
@@ -537,9 +4642,9 @@ impl ImageViewerApp {
             
             // Draw grid lines
             let grid_color = egui::Color32::from_gray(40);
-            // Vertical grid lines (every 32 values)
-            for i in (0..=256).step_by(32) {
-                let x = rect.min.x + (i as f32 / 256.0) * rect.width();
+            // Vertical grid lines (every eighth of the range)
+            for i in 0..=8 {
+                let x = rect.min.x + (i as f32 / 8.0) * rect.width();
                 ui.painter().line_segment(
                     [egui::pos2(x, rect.min.y), egui::pos2(x, rect.max.y)],
                     egui::Stroke::new(1.0, grid_color),
@@ -555,13 +4660,18 @@ impl ImageViewerApp {
             }
             
             // Draw histogram for each channel
-            for (channel, histogram) in histograms.iter().enumerate() {
+            for (channel, values) in series.iter().enumerate() {
+                if view.hidden[channel] {
+                    continue;
+                }
                 let color = colors[channel];
                 
-                for (bin, &count) in histogram.iter().enumerate() {
-                    if count > 0 {
-                        let height = (count as f32 / max_value) * rect.height();
-                        let x = rect.min.x + bin as f32 * bar_width;
+                for column in 0..columns {
+                    let bin_range = column * bins / columns..((column + 1) * bins / columns).max(column * bins / columns + 1);
+                    let value = values[bin_range].iter().fold(0.0f32, |a, &b| a.max(b));
+                    if value > 0.0 {
+                        let height = (value / max_value) * rect.height();
+                        let x = rect.min.x + column as f32 * bar_width;
                         let y = rect.max.y - height;
                         
                         let bar_rect = egui::Rect::from_min_size(
@@ -595,18 +4705,22 @@ impl ImageViewerApp {
             ui.painter().text(
                 rect.min + egui::vec2(5.0, 5.0),
                 egui::Align2::LEFT_TOP,
-                format!("Histogram (Max: {})", max_value as u32),
+                match (view.cumulative, view.log_scale) {
+                    (true, _) => "Cumulative histogram".to_string(),
+                    (false, true) => format!("Histogram, log scale (Max: {})", axis_value(max_value).round() as u32),
+                    (false, false) => format!("Histogram (Max: {})", max_value as u32),
+                },
                 egui::FontId::proportional(14.0),
                 egui::Color32::WHITE,
             );
             
-            // X-axis labels (pixel values)
-            for i in (0..=256).step_by(32) {
-                let x = rect.min.x + (i as f32 / 256.0) * rect.width();
+            // X-axis labels (data values)
+            for i in 0..=8 {
+                let x = rect.min.x + (i as f32 / 8.0) * rect.width();
                 ui.painter().text(
                     egui::pos2(x, rect.max.y + 5.0),
                     egui::Align2::CENTER_TOP,
-                    i.to_string(),
+                    axis.format(axis.value_at(i as f32 / 8.0 * (bins - 1) as f32, bins)),
                     egui::FontId::proportional(10.0),
                     egui::Color32::LIGHT_GRAY,
                 );
@@ -615,11 +4729,11 @@ impl ImageViewerApp {
             // Y-axis labels (count values)
             for i in 0..5 {
                 let y = rect.max.y - (i as f32 / 4.0) * rect.height();
-                let count = (max_value * i as f32 / 4.0) as u32;
+                let value = axis_value(max_value * i as f32 / 4.0);
                 ui.painter().text(
                     egui::pos2(rect.min.x - 5.0, y),
                     egui::Align2::RIGHT_CENTER,
-                    count.to_string(),
+                    if view.cumulative { format!("{:.0}%", value) } else { (value.round() as u32).to_string() },
                     egui::FontId::proportional(10.0),
                     egui::Color32::LIGHT_GRAY,
                 );
@@ -634,14 +4748,27 @@ impl ImageViewerApp {
                 let green_count = histograms[1][bin as usize];
                 let blue_count = histograms[2][bin as usize];
                 
-                let text_content = if red_count == green_count && green_count == blue_count {
+                // Data value of the bin, or its range when a bin spans several values
+                let low = axis.value_at(bin as f32, bins);
+                let high = axis.value_at(bin as f32 + 1.0, bins);
+                let value = if axis.integer && high - low <= 1.0 {
+                    axis.format(low)
+                } else {
+                    format!("{} – {}", axis.format(low), axis.format(high))
+                };
+                
+                let mut text_content = if red_count == green_count && green_count == blue_count {
                     // Grayscale image
-                    format!("Value: {}\nCount: {} ({:.2}%)", bin, count, percentage)
+                    format!("Value: {}\nCount: {} ({:.2}%)", value, format_count(count), percentage)
                 } else {
                     // Color image - show all channels
                     format!("Value: {}\nRed: {}\nGreen: {}\nBlue: {}\nTotal: {:.2}%", 
-                           bin, red_count, green_count, blue_count, percentage)
+                           value, format_count(red_count), format_count(green_count), format_count(blue_count), percentage)
                 };
+                if view.cumulative {
+                    let below: f32 = histograms[0][..=bin as usize].iter().sum();
+                    text_content.push_str(&format!("\nAt or below: {:.2}%", below / total_pixels.max(f32::EPSILON) * 100.0));
+                }
                 
                 // Create a background for the text
                 let text_galley = ui.painter().layout(
@@ -691,6 +4818,167 @@ impl ImageViewerApp {
         });
     }
 
+    /// Convert scope counts into a grayscale-on-black texture with log intensity scaling
+    fn scope_color_image(width: usize, height: usize, counts: impl Fn(usize, usize) -> u32, tint: egui::Color32) -> egui::ColorImage {
+        let mut max_count = 1u32;
+        for y in 0..height {
+            for x in 0..width {
+                max_count = max_count.max(counts(x, y));
+            }
+        }
+        let log_max = (max_count as f32 + 1.0).ln();
+        
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let count = counts(x, y);
+                let intensity = if count > 0 {
+                    // Keep single hits visible, scale the rest logarithmically
+                    (0.25 + 0.75 * (count as f32 + 1.0).ln() / log_max).min(1.0)
+                } else {
+                    0.0
+                };
+                pixels.push(egui::Color32::from_rgb(
+                    (tint.r() as f32 * intensity) as u8,
+                    (tint.g() as f32 * intensity) as u8,
+                    (tint.b() as f32 * intensity) as u8,
+                ));
+            }
+        }
+        
+        egui::ColorImage { size: [width, height], pixels }
+    }
+
+    fn render_waveform_in_viewport(
+        ui: &mut egui::Ui,
+        columns: u32,
+        counts: &[u32],
+        texture: &mut Option<egui::TextureHandle>,
+    ) {
+        let columns = columns as usize;
+        let texture = texture.get_or_insert_with(|| {
+            // Row 0 of the texture is the highest luma level
+            let image = Self::scope_color_image(columns, 256, |x, y| counts[(255 - y) * columns + x], egui::Color32::from_rgb(120, 255, 140));
+            ui.ctx().load_texture("waveform-texture", image, egui::TextureOptions::LINEAR)
+        });
+        
+        let available_size = ui.available_size();
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(available_size.x - 40.0, available_size.y - 30.0), egui::Sense::hover());
+        let rect = rect.translate(egui::vec2(35.0, 0.0));
+        
+        ui.painter().rect_filled(rect, egui::CornerRadius::same(2), egui::Color32::BLACK);
+        ui.painter().image(
+            texture.id(),
+            rect,
+            egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+            egui::Color32::WHITE,
+        );
+        
+        // Graticule at 0, 25, 50, 75 and 100 % of the signal range
+        let grid_color = egui::Color32::from_rgba_unmultiplied(200, 200, 120, 90);
+        for i in 0..=4 {
+            let y = rect.max.y - (i as f32 / 4.0) * rect.height();
+            ui.painter().line_segment(
+                [egui::pos2(rect.min.x, y), egui::pos2(rect.max.x, y)],
+                egui::Stroke::new(1.0, grid_color),
+            );
+            ui.painter().text(
+                egui::pos2(rect.min.x - 5.0, y),
+                egui::Align2::RIGHT_CENTER,
+                format!("{}%", i * 25),
+                egui::FontId::proportional(10.0),
+                egui::Color32::LIGHT_GRAY,
+            );
+        }
+        
+        ui.painter().rect_stroke(
+            rect,
+            egui::CornerRadius::same(2),
+            egui::Stroke::new(1.0, egui::Color32::GRAY),
+            egui::StrokeKind::Outside,
+        );
+        ui.painter().text(
+            rect.min + egui::vec2(5.0, 5.0),
+            egui::Align2::LEFT_TOP,
+            "Luma waveform (Rec.709)",
+            egui::FontId::proportional(14.0),
+            egui::Color32::WHITE,
+        );
+    }
+
+    fn render_vectorscope_in_viewport(
+        ui: &mut egui::Ui,
+        counts: &[u32],
+        texture: &mut Option<egui::TextureHandle>,
+    ) {
+        let size = VECTORSCOPE_SIZE as usize;
+        let texture = texture.get_or_insert_with(|| {
+            let image = Self::scope_color_image(size, size, |x, y| counts[y * size + x], egui::Color32::WHITE);
+            ui.ctx().load_texture("vectorscope-texture", image, egui::TextureOptions::LINEAR)
+        });
+        
+        let available_rect = ui.available_rect_before_wrap();
+        let side = available_rect.width().min(available_rect.height() - 30.0).max(50.0);
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(available_rect.width(), side), egui::Sense::hover());
+        let rect = egui::Rect::from_center_size(rect.center(), egui::vec2(side, side));
+        
+        ui.painter().rect_filled(rect, egui::CornerRadius::same(2), egui::Color32::BLACK);
+        ui.painter().image(
+            texture.id(),
+            rect,
+            egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+            egui::Color32::WHITE,
+        );
+        
+        // Graticule: crosshair, saturation rings and 75% color bar targets
+        let grid_color = egui::Color32::from_rgba_unmultiplied(200, 200, 120, 90);
+        let center = rect.center();
+        ui.painter().line_segment([egui::pos2(rect.min.x, center.y), egui::pos2(rect.max.x, center.y)], egui::Stroke::new(1.0, grid_color));
+        ui.painter().line_segment([egui::pos2(center.x, rect.min.y), egui::pos2(center.x, rect.max.y)], egui::Stroke::new(1.0, grid_color));
+        for ring in [0.25, 0.5] {
+            ui.painter().circle_stroke(center, ring * side, egui::Stroke::new(1.0, grid_color));
+        }
+        
+        let to_screen = |cb: f32, cr: f32| {
+            let (gx, gy) = vectorscope_position(cb, cr, VECTORSCOPE_SIZE);
+            rect.min + egui::vec2(gx as f32 + 0.5, gy as f32 + 0.5) * (side / VECTORSCOPE_SIZE as f32)
+        };
+        let targets = [
+            ("R", [191, 0, 0]),
+            ("Yl", [191, 191, 0]),
+            ("G", [0, 191, 0]),
+            ("Cy", [0, 191, 191]),
+            ("B", [0, 0, 191]),
+            ("Mg", [191, 0, 191]),
+        ];
+        for (label, [r, g, b]) in targets {
+            let (cb, cr) = chroma_709(r, g, b);
+            let pos = to_screen(cb, cr);
+            let color = egui::Color32::from_rgb(r.max(80), g.max(80), b.max(80));
+            ui.painter().rect_stroke(
+                egui::Rect::from_center_size(pos, egui::vec2(10.0, 10.0)),
+                egui::CornerRadius::ZERO,
+                egui::Stroke::new(1.0, color),
+                egui::StrokeKind::Middle,
+            );
+            ui.painter().text(pos + egui::vec2(8.0, -8.0), egui::Align2::LEFT_BOTTOM, label, egui::FontId::proportional(11.0), color);
+        }
+        
+        ui.painter().rect_stroke(
+            rect,
+            egui::CornerRadius::same(2),
+            egui::Stroke::new(1.0, egui::Color32::GRAY),
+            egui::StrokeKind::Outside,
+        );
+        ui.painter().text(
+            rect.min + egui::vec2(5.0, 5.0),
+            egui::Align2::LEFT_TOP,
+            "Vectorscope (Cb/Cr)",
+            egui::FontId::proportional(14.0),
+            egui::Color32::WHITE,
+        );
+    }
+
     #[allow(dead_code)]
     fn render_histogram_static(
         ui: &mut egui::Ui, 
@@ -909,196 +5197,1181 @@ impl ImageViewerApp {
                     egui::Color32::WHITE,
                 );
             }
-        });
-        
-        ui.separator();
-        ui.horizontal(|ui| {
-            ui.label("Channels: ");
-            ui.colored_label(egui::Color32::from_rgb(255, 80, 80), "■ Red");
-            ui.colored_label(egui::Color32::from_rgb(80, 255, 80), "■ Green");
-            ui.colored_label(egui::Color32::from_rgb(80, 80, 255), "■ Blue");
-            ui.separator();
-            ui.label("Hover over histogram to see detailed values");
-        });
+        });
+        
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Channels: ");
+            ui.colored_label(egui::Color32::from_rgb(255, 80, 80), "■ Red");
+            ui.colored_label(egui::Color32::from_rgb(80, 255, 80), "■ Green");
+            ui.colored_label(egui::Color32::from_rgb(80, 80, 255), "■ Blue");
+            ui.separator();
+            ui.label("Hover over histogram to see detailed values");
+        });
+    }
+
+
+    /// Pixels covered by a histogram scope, None for the whole image
+    fn scope_region(&self, scope: HistogramScope) -> Option<PixelRect> {
+        match scope {
+            HistogramScope::WholeImage => None,
+            HistogramScope::VisibleArea => self.doc.visible_region,
+            HistogramScope::Roi => self.doc.roi,
+        }
+    }
+
+    /// Start counting the histogram and scopes of the selected scope, in the background
+    fn calculate_histogram(&mut self, ctx: &egui::Context) {
+        if let Some(image) = &self.doc.image {
+            let (width, height) = image.dimensions();
+            
+            let (scope, requested_bins, alpha_mode) = self.histogram_shared_data.lock()
+                .map(|data| (data.scope, data.bins, data.alpha_mode))
+                .unwrap_or_default();
+            let region = self.scope_region(scope);
+            let [x0, y0, x1, y1] = region.unwrap_or([0, 0, width, height]);
+            let (x1, y1) = (x1.min(width), y1.min(height));
+            
+            let is_16bit = matches!(
+                image,
+                DynamicImage::ImageLuma16(_) | DynamicImage::ImageLumaA16(_) | DynamicImage::ImageRgb16(_) | DynamicImage::ImageRgba16(_)
+            );
+            
+            // Bins span the true data range; 8-bit data has no more than 256 distinct levels
+            let bins = if self.doc.original_fp_data.is_some() || is_16bit { requested_bins.0 } else { 256 };
+            
+            // Large regions are counted on the GPU, the result arrives a few frames later
+            let pixels = x1.saturating_sub(x0) as u64 * y1.saturating_sub(y0) as u64;
+            let gpu_request = if self.config.cpu_histogram || pixels < GPU_HISTOGRAM_MIN_PIXELS || self.gpu_histogram.unsupported().is_some() {
+                None
+            } else {
+                self.gpu_histogram_source().map(|(_, width, height, channels, sample)| HistogramRequest {
+                    width,
+                    height,
+                    channels,
+                    sample,
+                    region: [x0, y0, x1, y1],
+                    bins,
+                    // 16-bit bins span the values of the whole image, found in a first pass
+                    range: match sample {
+                        SampleType::U8 => Some((0.0, 255.0)),
+                        SampleType::U16 => None,
+                        SampleType::F32 => self.fp_data_range(),
+                    },
+                    alpha_mode: match alpha_mode {
+                        AlphaMode::Include => 0,
+                        AlphaMode::ExcludeTransparent => 1,
+                        AlphaMode::Weighted => 2,
+                    },
+                })
+            };
+            
+            // The worker counts copies, the document may change while it runs
+            let input = HistogramInput {
+                image: image.clone(),
+                fp_data: match (self.display_fp_data(), self.doc.original_fp_dimensions) {
+                    (Some((fp_data, channels)), Some((fp_width, _))) if gpu_request.is_none() => Some((fp_data.into_owned(), channels as usize, fp_width)),
+                    _ => None,
+                },
+                fp_range: self.doc.original_data_range,
+                region: [x0, y0, x1, y1],
+                bins,
+                alpha_mode,
+                scopes_only: gpu_request.is_some(),
+            };
+            
+            if let Some(request) = gpu_request {
+                self.gpu_histogram.start(request);
+            } else {
+                // A computation started for the previous settings must not replace this one
+                self.gpu_histogram.cancel();
+            }
+            
+            // Update shared data for the separate window, the counts follow from the worker
+            if let Ok(mut shared) = self.histogram_shared_data.lock() {
+                shared.gpu_pending = gpu_request.is_some();
+                shared.region = region;
+                shared.has_alpha = image.color().has_alpha();
+            }
+            self.histogram_job.start(ctx, self.histogram_window_id, input, &self.histogram_shared_data);
+            
+            self.doc.histogram_region = region;
+            self.doc.histogram_bins = requested_bins;
+            self.doc.histogram_alpha_mode = alpha_mode;
+            self.doc.histogram_needs_update = false;
+        }
+    }
+    
+    /// Range of the floating point data, computed on the fly without a stored one
+    fn fp_data_range(&self) -> Option<(f32, f32)> {
+        self.doc.original_data_range.or_else(|| self.doc.original_fp_data.as_ref().map(|fp_data| {
+            let min = fp_data.iter().filter(|v| v.is_finite()).fold(f32::INFINITY, |a, &b| a.min(b));
+            let max = fp_data.iter().filter(|v| v.is_finite()).fold(f32::NEG_INFINITY, |a, &b| a.max(b));
+            (min, max)
+        }))
+    }
+
+    /// Pixel data the GPU histogram is computed from, with its width, height, channel count
+    /// and sample type. `None` for layouts the GPU path does not handle.
+    fn gpu_histogram_source(&self) -> Option<(SourceData<'_>, u32, u32, u32, SampleType)> {
+        if let (Some(fp_data), Some(channels), Some((width, height))) =
+            (&self.doc.original_fp_data, self.doc.original_fp_channels, self.doc.original_fp_dimensions)
+        {
+            return matches!(channels, 1 | 3 | 4).then_some((SourceData::F32(fp_data), width, height, channels, SampleType::F32));
+        }
+        let image = self.doc.image.as_ref()?;
+        let sample = match image {
+            DynamicImage::ImageLuma8(_) | DynamicImage::ImageLumaA8(_) | DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgba8(_) => SampleType::U8,
+            DynamicImage::ImageLuma16(_) | DynamicImage::ImageLumaA16(_) | DynamicImage::ImageRgb16(_) | DynamicImage::ImageRgba16(_) => SampleType::U16,
+            _ => return None,
+        };
+        Some((SourceData::Bytes(image.as_bytes()), image.width(), image.height(), image.color().channel_count() as u32, sample))
+    }
+    
+    /// Hand the next tile to the GPU histogram computation and take its result when done
+    fn poll_gpu_histogram(&mut self, ctx: &egui::Context) {
+        match self.gpu_histogram.take_outcome() {
+            Some(Ok(result)) => {
+                let stats = std::array::from_fn(|channel| ChannelStats {
+                    weight: result.weight[channel],
+                    sum: result.sum[channel],
+                    sum_squares: result.sum_squares[channel],
+                    min: result.min[channel],
+                    max: result.max[channel],
+                });
+                let (min, max) = result.range;
+                let axis = HistogramAxis { min, max: max.max(min), integer: self.doc.original_fp_data.is_none() };
+                if let Ok(mut shared) = self.histogram_shared_data.lock() {
+                    shared.histograms = Some(result.histograms);
+                    shared.axis = axis;
+                    shared.stats = Some(stats);
+                    shared.gpu_pending = false;
+                }
+                if let Some(histogram_id) = self.histogram_window_id {
+                    ctx.request_repaint_of(histogram_id);
+                }
+            }
+            Some(Err(e)) => {
+                warn!("GPU histogram failed, computing it on the CPU: {}", e);
+                self.doc.histogram_needs_update = true;
+            }
+            None => {}
+        }
+        
+        if self.gpu_histogram.is_running() {
+            let Some((data, ..)) = self.gpu_histogram_source() else {
+                self.gpu_histogram.cancel();
+                return;
+            };
+            self.gpu_histogram.feed(data);
+            // GL work has to run on the main window's context, inside its paint callbacks
+            let rect = egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(1.0, 1.0));
+            ctx.layer_painter(egui::LayerId::background()).add(self.gpu_histogram.paint_callback(rect));
+            ctx.request_repaint();
+        }
+    }
+
+    /// Cursor position, zoom, sample type, file size and the memory the decoded image takes
+    fn render_status_bar(&self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let Some(img) = &self.doc.image else {
+                ui.weak("No image");
+                return;
+            };
+            match self.hover_pixel {
+                Some((x, y)) => ui.label(format!("x {}, y {}", x, y)),
+                None => ui.weak("x –, y –"),
+            };
+            ui.separator();
+            ui.label(format!("{:.0}%", self.doc.base_scale * self.doc.scale * 100.0))
+                .on_hover_text("Zoom, 100% shows one image pixel per screen pixel");
+            ui.separator();
+            ui.label(self.sample_type_text(img));
+            if let Some(size) = self.doc.file_size {
+                ui.separator();
+                ui.label(format_bytes(size)).on_hover_text("File size");
+            }
+            ui.separator();
+            ui.label(format!("≈ {} decoded", format_bytes(self.decoded_bytes() as u64)))
+                .on_hover_text("Memory of the decoded image, its floating point data, frames and volume");
+        });
+    }
+
+    /// Color type and bit depth of the open image, of the original data for floating point images
+    fn sample_type_text(&self, img: &DynamicImage) -> String {
+        if let Some(channels) = self.doc.original_fp_channels.filter(|_| self.doc.original_fp_data.is_some()) {
+            return match channels {
+                1 => "Gray, 32-bit float".to_string(),
+                3 => "RGB, 32-bit float".to_string(),
+                4 => "RGBA, 32-bit float".to_string(),
+                bands => format!("{} bands, 32-bit float", bands),
+            };
+        }
+        let color = img.color();
+        let kind = match (color.has_color(), color.has_alpha()) {
+            (false, false) => "Gray",
+            (false, true) => "Gray + alpha",
+            (true, false) => "RGB",
+            (true, true) => "RGBA",
+        };
+        let bits = color.bits_per_pixel() / color.channel_count() as u16;
+        let float = matches!(color, image::ColorType::Rgb32F | image::ColorType::Rgba32F);
+        format!("{}, {}-bit{}", kind, bits, if float { " float" } else { "" })
+    }
+
+    /// Readouts of the color last clicked with the pixel tool
+    fn render_color_picker_window(&mut self, ctx: &egui::Context) {
+        let Some(((x, y), color)) = self.picked_color else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("Picked color")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!("Pixel ({}, {}), click another one with the pixel tool to pick it", x, y));
+                color_picker::ui(ui, color);
+            });
+        if !open {
+            self.picked_color = None;
+        }
+    }
+
+    /// Properties of the open image that do not fit the toolbar
+    fn render_info_window(&mut self, ctx: &egui::Context) {
+        let (Some(img), Some(path)) = (&self.doc.image, self.doc.image_path.clone()) else {
+            return;
+        };
+        // The header is only read again for another file
+        if self.info_properties.as_ref().is_none_or(|(info_path, _)| *info_path != path) {
+            self.info_properties = Some((path.clone(), properties::read(&path)));
+        }
+        let Some((_, file)) = &self.info_properties else {
+            return;
+        };
+        let (width, height) = img.dimensions();
+        let pages = match (&self.doc.frames, &self.doc.volume) {
+            (Some(frames), _) => Some(frames.len()),
+            (_, Some(volume)) => Some(volume.slice_count()),
+            _ => file.pages,
+        };
+        let mut open = self.show_info;
+        egui::Window::new("Info")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("info_grid").num_columns(2).spacing([12.0, 4.0]).show(ui, |ui| {
+                    ui.label("Path:");
+                    ui.horizontal(|ui| {
+                        ui.add(egui::Label::new(path.display().to_string()).selectable(true));
+                        if ui.small_button("Copy").clicked() {
+                            ui.ctx().copy_text(path.display().to_string());
+                        }
+                    });
+                    ui.end_row();
+                    ui.label("File size:");
+                    ui.label(self.doc.file_size.map_or("–".to_string(), |size| format!("{} ({} bytes)", format_bytes(size), size)));
+                    ui.end_row();
+                    ui.label("Format:");
+                    ui.label(&file.format);
+                    ui.end_row();
+                    ui.label("Dimensions:");
+                    let megapixels = width as f64 * height as f64 / 1e6;
+                    match self.doc.reduced_from {
+                        Some((full_width, full_height)) => ui.label(format!("{}×{} (reduced from {}×{})", width, height, full_width, full_height)),
+                        None => ui.label(format!("{}×{} ({:.1} MP)", width, height, megapixels)),
+                    };
+                    ui.end_row();
+                    ui.label("Color type:");
+                    ui.label(self.sample_type_text(img));
+                    ui.end_row();
+                    ui.label("Resolution:");
+                    match file.resolution {
+                        Some((x, y, unit)) if (x - y).abs() < 0.01 => ui.label(format!("{:.0} {}", x, unit)),
+                        Some((x, y, unit)) => ui.label(format!("{:.0} × {:.0} {}", x, y, unit)),
+                        None => ui.weak("Not stored"),
+                    };
+                    ui.end_row();
+                    ui.label("Compression:");
+                    ui.label(file.compression.as_deref().unwrap_or("–"));
+                    ui.end_row();
+                    ui.label("Frames / pages:");
+                    ui.label(pages.map_or("1".to_string(), |pages| pages.to_string()));
+                    ui.end_row();
+                    for (label, value) in file.exif.rows() {
+                        ui.label(format!("{}:", label));
+                        ui.label(value);
+                        ui.end_row();
+                    }
+                    ui.label("Decode time:");
+                    match (self.doc.decode_time, &self.doc.full_decode) {
+                        (_, Some(_)) => ui.weak("Decoding…"),
+                        (Some(time), None) => ui.label(format!("{:.0} ms", time.as_secs_f64() * 1000.0)),
+                        (None, None) => ui.weak("From the cache"),
+                    };
+                    ui.end_row();
+                });
+            });
+        self.show_info = open;
+    }
+
+    /// Approximate memory of the open image's decoded data
+    fn decoded_bytes(&self) -> usize {
+        let image = self.doc.image.as_ref().map_or(0, |img| img.as_bytes().len());
+        let fp_data = self.doc.original_fp_data.as_ref().map_or(0, |fp_data| fp_data.len() * size_of::<f32>());
+        let frames = self.doc.frames.as_ref().map_or(0, |frames| frames.iter().map(|frame| frame.as_bytes().len()).sum());
+        let volume = self.doc.volume.as_ref().map_or(0, Volume::bytes);
+        image + fp_data + frames + volume
+    }
+
+    /// Draw the session palette: click a swatch to copy its hex code, right-click to remove it
+    fn render_palette(&mut self, ui: &mut egui::Ui) {
+        let mut remove = None;
+        ui.horizontal(|ui| {
+            ui.label("Palette:");
+            egui::ScrollArea::horizontal()
+                .max_width(ui.available_width() - 260.0)
+                .show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        for (index, &color) in self.palette.iter().enumerate() {
+                            let (rect, response) = ui.allocate_exact_size(egui::vec2(20.0, 20.0), egui::Sense::click());
+                            ui.painter().rect_filled(rect, egui::CornerRadius::same(2), egui::Color32::from_rgb(color[0], color[1], color[2]));
+                            ui.painter().rect_stroke(rect, egui::CornerRadius::same(2), egui::Stroke::new(1.0, egui::Color32::GRAY), egui::StrokeKind::Inside);
+                            let response = response.on_hover_text(format!(
+                                "{} RGB({}, {}, {})\nClick to copy, right-click to remove",
+                                palette::hex(color), color[0], color[1], color[2]
+                            ));
+                            if response.clicked() {
+                                ui.ctx().copy_text(palette::hex(color));
+                            }
+                            if response.secondary_clicked() {
+                                remove = Some(index);
+                            }
+                        }
+                    });
+                });
+            
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.button("Clear").clicked() {
+                    self.palette.clear();
+                }
+                for (label, extension, write) in [
+                    ("Export .ase…", "ase", palette::write_ase as fn(&Path, &[[u8; 3]]) -> anyhow::Result<()>),
+                    ("Export .gpl…", "gpl", palette::write_gpl),
+                ] {
+                    if ui.button(label).clicked() {
+                        let mut dialog = rfd::FileDialog::new()
+                            .add_filter(extension, &[extension])
+                            .set_file_name(format!("palette.{}", extension));
+                        if let Some(folder) = &self.last_opened_folder {
+                            dialog = dialog.set_directory(folder);
+                        }
+                        if let Some(path) = dialog.save_file() {
+                            match write(&path, &self.palette) {
+                                Ok(()) => info!("Exported {} colors to {:?}", self.palette.len(), path),
+                                Err(e) => error!("Failed to export palette: {}", e),
+                            }
+                        }
+                    }
+                }
+            });
+        });
+        if let Some(index) = remove {
+            self.palette.remove(index);
+        }
+    }
+
+    /// Draw the thumbnail strip, returns the index of a clicked thumbnail
+    fn render_filmstrip(&mut self, ui: &mut egui::Ui) -> Option<usize> {
+        let mut clicked = None;
+        let scroll_to_current = self.doc.filmstrip_scrolled_to != self.doc.current_image_index;
+        let cell_size = egui::vec2(THUMBNAIL_SIZE as f32, THUMBNAIL_SIZE as f32);
+        
+        egui::ScrollArea::horizontal()
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    for index in 0..self.doc.folder_images.len() {
+                        let path = self.doc.folder_images[index].clone();
+                        let is_current = self.doc.current_image_index == Some(index);
+                        let rating = self.ratings.get(&path);
+                        if !is_current && rating.stars < self.min_rating {
+                            continue;
+                        }
+                        let (rect, response) = ui.allocate_exact_size(cell_size, egui::Sense::click());
+                        
+                        // Only request thumbnails that are actually on screen
+                        if ui.is_rect_visible(rect) {
+                            self.paint_thumbnail(ui, rect, &path, rating);
+                            let stroke = if is_current {
+                                egui::Stroke::new(2.0, ui.visuals().selection.stroke.color)
+                            } else if response.hovered() {
+                                egui::Stroke::new(1.0, egui::Color32::LIGHT_GRAY)
+                            } else {
+                                egui::Stroke::new(1.0, egui::Color32::from_gray(60))
+                            };
+                            ui.painter().rect_stroke(rect, egui::CornerRadius::same(3), stroke, egui::StrokeKind::Inside);
+                        }
+                        
+                        if is_current && scroll_to_current {
+                            response.scroll_to_me(Some(egui::Align::Center));
+                        }
+                        
+                        if response.on_hover_text(self.thumbnail_hover_text(&path, rating)).clicked() {
+                            clicked = Some(index);
+                        }
+                    }
+                });
+            });
+        
+        self.doc.filmstrip_scrolled_to = self.doc.current_image_index;
+        clicked
+    }
+
+    /// Open or close the gallery, which starts on the current image
+    fn toggle_gallery(&mut self) {
+        if self.gallery.take().is_none() && !self.quick_look && !self.doc.folder_images.is_empty() {
+            self.gallery = Some(Gallery::new(self.doc.image_path.as_deref()));
+        }
+    }
+
+    /// Folder images shown in the gallery, those below the minimum rating are left out unless current
+    fn gallery_items(&mut self) -> Vec<PathBuf> {
+        let images = self.doc.folder_images.clone();
+        images.into_iter().filter(|path| {
+            self.doc.image_path.as_ref() == Some(path) || self.ratings.get(path).stars >= self.min_rating
+        }).collect()
+    }
+
+    /// Show an image of the gallery in the viewer and close the gallery
+    fn open_from_gallery(&mut self, path: PathBuf) {
+        self.gallery = None;
+        if self.doc.image_path.as_ref() == Some(&path) {
+            return;
+        }
+        info!("Loading image from the gallery: {:?}", path);
+        if let Err(e) = self.load_image(path) {
+            error!("Failed to load image from the gallery: {}", e);
+            self.report_load_error(&e);
+        }
+    }
+
+    /// Draw the thumbnail grid of the folder. Click selects, Ctrl+click toggles, Shift+click
+    /// selects a range; the arrows move the focus, Enter or a double click opens an image.
+    fn render_gallery(&mut self, ui: &mut egui::Ui) {
+        let ctx = ui.ctx().clone();
+        self.thumbnails.get_or_insert_with(|| ThumbnailCache::new(&ctx, THUMBNAIL_SIZE, load_thumbnail_source)).poll(&ctx);
+        let items = self.gallery_items();
+        let Some(gallery) = &mut self.gallery else {
+            return;
+        };
+        let cell_size = egui::vec2(THUMBNAIL_SIZE as f32, THUMBNAIL_SIZE as f32 + GALLERY_NAME_HEIGHT);
+        let spacing = ui.spacing().item_spacing;
+        gallery.columns = (((ui.available_width() + spacing.x) / (cell_size.x + spacing.x)) as usize).max(1);
+        let columns = gallery.columns;
+
+        // Keys, unless a dialog is waiting for them
+        let mut open = None;
+        let dialog_open = !self.pending_delete.is_empty() || self.rename.is_some() || self.load_error.is_some();
+        if !dialog_open && !ctx.wants_keyboard_input() {
+            let rows_per_page = ((gallery.view_height / (cell_size.y + spacing.y)) as isize).max(1);
+            let (step, extend, select_all, enter, escape) = ctx.input(|i| {
+                let step = [
+                    (egui::Key::ArrowLeft, -1),
+                    (egui::Key::ArrowRight, 1),
+                    (egui::Key::ArrowUp, -(columns as isize)),
+                    (egui::Key::ArrowDown, columns as isize),
+                    (egui::Key::PageUp, -(columns as isize) * rows_per_page),
+                    (egui::Key::PageDown, columns as isize * rows_per_page),
+                    (egui::Key::Home, -(items.len() as isize)),
+                    (egui::Key::End, items.len() as isize),
+                ].into_iter().find(|(key, _)| i.key_pressed(*key)).map(|(_, step)| step);
+                (step, i.modifiers.shift, i.modifiers.command && i.key_pressed(egui::Key::A), i.key_pressed(egui::Key::Enter), i.key_pressed(egui::Key::Escape))
+            });
+            if let Some(step) = step {
+                gallery.move_focus(&items, step, extend);
+            }
+            if select_all {
+                gallery.select_all(&items);
+            }
+            if enter {
+                open = gallery.focus().map(Path::to_path_buf);
+            }
+            if escape {
+                self.gallery = None;
+                return;
+            }
+        }
+
+        let selection = gallery.selection(&items);
+        let mut trash = false;
+        let mut move_to = false;
+        ui.horizontal(|ui| {
+            ui.label(format!("{} images, {} selected", items.len(), selection.len()));
+            ui.separator();
+            if ui.add_enabled(selection.len() == 1, egui::Button::new("Open")).on_hover_text("Show the image (Enter)").clicked() {
+                open = selection.first().cloned();
+            }
+            trash = ui.add_enabled(!selection.is_empty(), egui::Button::new("Move to trash")).on_hover_text("Del").clicked();
+            move_to = ui.add_enabled(!selection.is_empty(), egui::Button::new("Move to…")).on_hover_text("M").clicked();
+            if ui.button("Close").on_hover_text("Back to the image (G or Escape)").clicked() {
+                self.gallery = None;
+            }
+        });
+        ui.separator();
+
+        let row_pitch = cell_size.y + spacing.y;
+        let mut scroll = egui::ScrollArea::vertical().auto_shrink([false, false]);
+        if let Some(offset) = self.gallery.as_mut().and_then(|gallery| gallery.focus_scroll(&items, row_pitch, cell_size.y)) {
+            scroll = scroll.vertical_scroll_offset(offset);
+        }
+        let mut clicked = None;
+        let row_count = items.len().div_ceil(columns);
+        let output = scroll.show_rows(ui, cell_size.y, row_count, |ui, rows| {
+            for row in rows {
+                ui.horizontal(|ui| {
+                    for (index, path) in items.iter().enumerate().skip(row * columns).take(columns) {
+                        let (rect, response) = ui.allocate_exact_size(cell_size, egui::Sense::click());
+                        let (selected, focused) = self.gallery.as_ref().map_or((false, false), |gallery| (gallery.is_selected(path), gallery.is_focused(path)));
+                        if selected {
+                            ui.painter().rect_filled(rect.expand(2.0), egui::CornerRadius::same(4), ui.visuals().selection.bg_fill);
+                        }
+                        let rating = self.ratings.get(path);
+                        let thumbnail_rect = egui::Rect::from_min_size(rect.min, egui::vec2(cell_size.x, cell_size.x));
+                        self.paint_thumbnail(ui, thumbnail_rect, path, rating);
+                        let stroke = if focused {
+                            egui::Stroke::new(2.0, ui.visuals().selection.stroke.color)
+                        } else if response.hovered() {
+                            egui::Stroke::new(1.0, egui::Color32::LIGHT_GRAY)
+                        } else {
+                            egui::Stroke::new(1.0, egui::Color32::from_gray(60))
+                        };
+                        ui.painter().rect_stroke(thumbnail_rect, egui::CornerRadius::same(3), stroke, egui::StrokeKind::Inside);
+
+                        // File name below the thumbnail, cut to the cell width
+                        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+                        let mut job = egui::text::LayoutJob::simple_singleline(name, egui::FontId::proportional(11.0), ui.visuals().text_color());
+                        job.wrap = egui::text::TextWrapping::truncate_at_width(cell_size.x);
+                        let galley = ui.painter().layout_job(job);
+                        let name_pos = egui::pos2(rect.center().x - galley.size().x / 2.0, thumbnail_rect.bottom() + 2.0);
+                        ui.painter().galley(name_pos, galley, ui.visuals().text_color());
+
+                        let response = response.on_hover_text(self.thumbnail_hover_text(path, rating));
+                        if response.double_clicked() {
+                            open = Some(path.clone());
+                        } else if response.clicked() {
+                            clicked = Some(index);
+                        }
+                    }
+                });
+            }
+        });
+
+        let modifiers = ui.input(|i| i.modifiers);
+        if let Some(gallery) = &mut self.gallery {
+            gallery.scroll_offset = output.state.offset.y;
+            gallery.view_height = output.inner_rect.height();
+            if let Some(index) = clicked {
+                gallery.click(&items, index, modifiers);
+            }
+        }
+        if let Some(path) = open {
+            self.open_from_gallery(path);
+        } else if trash {
+            self.request_delete();
+        } else if move_to {
+            self.move_files();
+        }
+    }
+
+    /// Paint the thumbnail of a folder image into a cell, with its hint badge, color label and stars
+    fn paint_thumbnail(&mut self, ui: &egui::Ui, rect: egui::Rect, path: &Path, rating: Rating) {
+        ui.painter().rect_filled(rect, egui::CornerRadius::same(3), egui::Color32::from_gray(30));
+        
+        if let Some(thumbnails) = self.thumbnails.as_mut() {
+            if let Some(texture) = thumbnails.get(path) {
+                // Fit the thumbnail into the cell keeping its aspect ratio
+                let texture_size = texture.size_vec2();
+                let fit = (rect.width() / texture_size.x).min(rect.height() / texture_size.y);
+                let image_rect = egui::Rect::from_center_size(rect.center(), texture_size * fit);
+                ui.painter().image(
+                    texture.id(),
+                    image_rect,
+                    egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+                    egui::Color32::WHITE,
+                );
+            } else {
+                let placeholder = if thumbnails.is_failed(path) { "?" } else { "…" };
+                ui.painter().text(
+                    rect.center(),
+                    egui::Align2::CENTER_CENTER,
+                    placeholder,
+                    egui::FontId::proportional(18.0),
+                    egui::Color32::GRAY,
+                );
+            }
+        }
+        
+        // Badge for images flagged by the classification
+        if let Some(hint) = self.hint_index.get(path).first() {
+            let text_pos = rect.left_bottom() + egui::vec2(3.0, -3.0);
+            let galley = ui.painter().layout_no_wrap(hint.badge().to_string(), egui::FontId::proportional(10.0), egui::Color32::BLACK);
+            let badge_rect = egui::Rect::from_min_size(text_pos - egui::vec2(0.0, galley.size().y), galley.size()).expand(2.0);
+            ui.painter().rect_filled(badge_rect, egui::CornerRadius::same(2), egui::Color32::from_rgb(255, 170, 0));
+            ui.painter().galley(badge_rect.min + egui::vec2(2.0, 2.0), galley, egui::Color32::BLACK);
+        }
+        
+        // Color label as a strip along the top, stars in the top left corner
+        if let Some(label) = rating.label {
+            let strip = egui::Rect::from_min_size(rect.min, egui::vec2(rect.width(), 4.0));
+            ui.painter().rect_filled(strip, egui::CornerRadius::same(2), label.color());
+        }
+        if rating.stars > 0 {
+            let galley = ui.painter().layout_no_wrap("★".repeat(rating.stars as usize), egui::FontId::proportional(10.0), egui::Color32::from_rgb(255, 210, 60));
+            let stars_rect = egui::Rect::from_min_size(rect.min + egui::vec2(3.0, 6.0), galley.size()).expand(2.0);
+            ui.painter().rect_filled(stars_rect, egui::CornerRadius::same(2), egui::Color32::from_black_alpha(160));
+            ui.painter().galley(stars_rect.min + egui::vec2(2.0, 2.0), galley, egui::Color32::WHITE);
+        }
+    }
+
+    /// File name, hints and stars of a folder image, for the thumbnail tooltip
+    fn thumbnail_hover_text(&self, path: &Path, rating: Rating) -> String {
+        let mut text = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        for hint in self.hint_index.get(path) {
+            text.push_str(&format!("\n⚠ {}", hint.as_str()));
+        }
+        if rating.stars > 0 {
+            text.push_str(&format!("\n{}", rating.stars_text()));
+        }
+        text
+    }
+
+    /// Run the display pipeline (transforms and channel filtering) on an image
+    /// Display steps in order: normalization, the user's pipeline, adjustments, the channel view and the threshold
+    fn display_pipeline(&self) -> Vec<(Box<dyn Transform + '_>, &[f32])> {
+        let mut steps: Vec<(Box<dyn Transform + '_>, &[f32])> = Vec::new();
+        // Comparison views like the difference are display values already
+        let normalization = self.doc.normalization;
+        if self.doc.compare.as_ref().is_none_or(|c| c.uses_pipeline()) {
+            if let Some(view) = self.expression.as_ref().and_then(|e| e.view()) {
+                steps.push((Box::new(view), &[]));
+            } else if let Some(transform) = self.transforms.get(normalization) {
+                steps.push((Box::new(transform), self.transforms.params(normalization)));
+            }
+        }
+        for (transform, params) in self.doc.pipeline.active(&self.transforms) {
+            steps.push((Box::new(transform), params));
+        }
+        if !self.doc.adjustments.is_identity() {
+            steps.push((Box::new(self.doc.adjustments), &[]));
+        }
+        let colormap = self.active_colormap().map(|c| c.lut());
+        if self.doc.channel != ChannelType::Rgb || colormap.is_some() {
+            steps.push((Box::new(ChannelView { channel: self.doc.channel, as_gray: self.doc.channel_as_gray, colormap }), &[]));
+        }
+        if let Some(threshold) = &self.threshold {
+            steps.push((Box::new(threshold.view()), &threshold.adaptive));
+        }
+        steps
+    }
+
+    /// The normalized image, or the result of the expression while that tool is active
+    fn normalized(&self, img: &DynamicImage) -> DynamicImage {
+        let source = self.normalization_source(img.clone());
+        match self.expression.as_ref().and_then(|e| e.view()) {
+            Some(view) => view.apply(&source, &[]),
+            None => self.transforms.apply(self.doc.normalization, source),
+        }
+    }
+
+    /// Input of the normalization at the size of `working`: the original floating point data of
+    /// FP images instead of their 8-bit preview. Deep integer images are normalized as they are.
+    fn normalization_source(&self, working: DynamicImage) -> DynamicImage {
+        if (self.doc.normalization == "none" && self.expression.is_none()) || self.doc.compare.is_some() {
+            return working;
+        }
+        let (Some(img), Some((fp_data, channels)), Some((width, height))) =
+            (&self.doc.image, self.display_fp_data(), self.doc.original_fp_dimensions)
+        else {
+            return working;
+        };
+        if img.dimensions() != (width, height) {
+            return working;
+        }
+        fp_image(&fp_data, width, height, channels, working.dimensions(), self.config.downscale_filter.filter_type()).unwrap_or(working)
+    }
+
+    /// Rebuild the displayed image of a multiband image from the selected bands
+    fn apply_band_mapping(&mut self) {
+        let (Some(mapping), Some(fp_data), Some((width, height)), Some(bands)) =
+            (self.doc.band_mapping, &self.doc.original_fp_data, self.doc.original_fp_dimensions, self.doc.original_fp_channels)
+        else {
+            return;
+        };
+        match multiband::preview(fp_data, width, height, bands, mapping) {
+            Ok((img, range)) => {
+                info!("Showing bands {:?}", mapping);
+                self.doc.image = Some(img);
+                self.doc.original_data_range = Some(range);
+                self.doc.pixel_info_fp = None;
+                self.doc.invalidate_texture();
+                self.doc.histogram_needs_update = true;
+            }
+            Err(e) => error!("Failed to show bands {:?}: {}", mapping, e),
+        }
+    }
+
+    /// Floating point data with its channel count as it is displayed: the mapped bands of
+    /// multiband images, otherwise the original data
+    fn display_fp_data(&self) -> Option<(Cow<'_, [f32]>, u32)> {
+        let (fp_data, channels) = (self.doc.original_fp_data.as_ref()?, self.doc.original_fp_channels?);
+        Some(match self.doc.band_mapping {
+            Some(mapping) => (Cow::Owned(multiband::compose(fp_data, channels, mapping)), mapping.channels()),
+            None => (Cow::Borrowed(fp_data), channels),
+        })
+    }
+
+    /// `working_img` after the display pipeline, as it is shown in the window
+    fn display_image(&self, working_img: DynamicImage) -> DynamicImage {
+        let working_img = self.normalization_source(working_img);
+        // Filter sizes are given in image pixels, the texture may be reduced
+        let resolution = self.doc.image.as_ref().map_or(1.0, |img| working_img.width() as f32 / img.width().max(1) as f32);
+        let displayed = self.mark_non_finite(self.apply_display_pipeline(working_img, resolution));
+        let image_size = self.full_image_size().unwrap_or(displayed.dimensions());
+        self.doc.layers.composite(displayed, image_size)
+    }
+
+    /// `img` after the display pipeline, at `resolution` pixels per image pixel
+    fn apply_display_pipeline(&self, img: DynamicImage, resolution: f32) -> DynamicImage {
+        self.display_pipeline().iter().fold(img, |img, (step, params)| {
+            step.apply(&img, &params_at_resolution(step.params(), params, resolution))
+        })
+    }
+
+    /// Count the NaN and infinite pixels of the floating point data after it changed
+    fn count_non_finite(&mut self) {
+        self.doc.non_finite_pixels = match (&self.doc.original_fp_data, self.doc.original_fp_channels) {
+            (Some(fp_data), Some(channels)) => non_finite_pixels(fp_data, channels as usize),
+            _ => 0,
+        };
+        if self.doc.non_finite_pixels > 0 {
+            info!("{} pixels with NaN or infinite values", self.doc.non_finite_pixels);
+        }
+    }
+
+    /// Paint the pixels whose floating point data is NaN or infinite in the configured color,
+    /// sampling the data nearest to each displayed pixel
+    fn mark_non_finite(&self, displayed: DynamicImage) -> DynamicImage {
+        let (Some(img), Some(fp_data), Some(channels), Some((width, height))) =
+            (&self.doc.image, &self.doc.original_fp_data, self.doc.original_fp_channels, self.doc.original_fp_dimensions)
+        else {
+            return displayed;
+        };
+        if self.doc.non_finite_pixels == 0 || self.doc.compare.is_some() || img.dimensions() != (width, height) {
+            return displayed;
+        }
+        let channels = channels as usize;
+        let color_channels = if channels == 4 { 3 } else { channels };
+        let [r, g, b] = self.config.non_finite_color.0;
+        let mut rgba = displayed.into_rgba8();
+        let (display_width, display_height) = rgba.dimensions();
+        for (x, y, pixel) in rgba.enumerate_pixels_mut() {
+            let source_x = (x as u64 * width as u64 / display_width as u64) as usize;
+            let source_y = (y as u64 * height as u64 / display_height as u64) as usize;
+            let base = (source_y * width as usize + source_x) * channels;
+            if fp_data.get(base..base + color_channels).is_some_and(|values| values.iter().any(|v| !v.is_finite())) {
+                *pixel = image::Rgba([r, g, b, 255]);
+            }
+        }
+        DynamicImage::ImageRgba8(rgba)
     }
 
+    fn render_display_image(&self, working_img: DynamicImage) -> egui::ColorImage {
+        let displayed = self.display_image(working_img);
+        let (width, height) = displayed.dimensions();
+        egui::ColorImage::from_rgba_unmultiplied(
+            [width as usize, height as usize],
+            displayed.into_rgba8().as_raw(),
+        )
+    }
 
-    fn calculate_histogram(&mut self) {
-        if let Some(image) = &self.image {
-            let (width, height) = image.dimensions();
-            let mut histograms = vec![vec![0u32; 256]; 3]; // RGB channels
-            
-            // Check if we have original floating point data
-            if let (Some(fp_data), Some(fp_channels)) = (&self.original_fp_data, self.original_fp_channels) {
-                // Get the data range for proper normalization
-                let (min_val, max_val) = if let Some((min, max)) = self.original_data_range {
-                    (min, max)
-                } else {
-                    // Calculate min/max on the fly
-                    let min = fp_data.iter().fold(f32::INFINITY, |a, &b| a.min(b));
-                    let max = fp_data.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b));
-                    (min, max)
-                };
-                
-                let range = max_val - min_val;
-                
-                // Calculate histogram from original floating point data
-                match fp_channels {
-                    1 => {
-                        // Grayscale floating point
-                        for &value in fp_data {
-                            let normalized = if range > f32::EPSILON {
-                                ((value - min_val) / range).clamp(0.0, 1.0)
-                            } else {
-                                0.5
-                            };
-                            let bin = (normalized * 255.0) as usize;
-                            histograms[0][bin] += 1;
-                            histograms[1][bin] += 1; // Copy to G and B for display
-                            histograms[2][bin] += 1;
-                        }
-                    }
-                    3 => {
-                        // RGB floating point
-                        for chunk in fp_data.chunks(3) {
-                            if chunk.len() == 3 {
-                                for (channel, &value) in chunk.iter().enumerate() {
-                                    let normalized = if range > f32::EPSILON {
-                                        ((value - min_val) / range).clamp(0.0, 1.0)
-                                    } else {
-                                        0.5
-                                    };
-                                    let bin = (normalized * 255.0) as usize;
-                                    histograms[channel][bin] += 1;
-                                }
-                            }
-                        }
-                    }
-                    4 => {
-                        // RGBA floating point - use only RGB
-                        for chunk in fp_data.chunks(4) {
-                            if chunk.len() == 4 {
-                                for (channel, &value) in chunk.iter().take(3).enumerate() {
-                                    let normalized = if range > f32::EPSILON {
-                                        ((value - min_val) / range).clamp(0.0, 1.0)
-                                    } else {
-                                        0.5
-                                    };
-                                    let bin = (normalized * 255.0) as usize;
-                                    histograms[channel][bin] += 1;
-                                }
-                            }
-                        }
-                    }
-                    _ => {}
-                }
+    /// Copy the displayed image at full resolution to the system clipboard
+    fn copy_image_to_clipboard(&self, ctx: &egui::Context) {
+        if let Some(img) = &self.doc.image {
+            let source = match &self.doc.compare {
+                Some(compare) => compare.view_image(img),
+                None => img.clone(),
+            };
+            let color_image = self.render_display_image(source);
+            info!("Copying {}x{} image to clipboard", color_image.width(), color_image.height());
+            ctx.copy_image(color_image);
+        }
+    }
+
+    /// Make the displayed image the desktop background
+    fn set_wallpaper(&self, mode: WallpaperMode) {
+        let Some(img) = &self.doc.image else {
+            return;
+        };
+        let displayed = self.render_display_image(img.clone());
+        let pixels = displayed.pixels.iter().flat_map(|color| color.to_srgba_unmultiplied()).collect();
+        let Some(image) = image::RgbaImage::from_raw(displayed.width() as u32, displayed.height() as u32, pixels) else {
+            return;
+        };
+        match wallpaper::set_wallpaper(&image, mode) {
+            Ok(path) => info!("Set {:?} as the desktop background ({})", path, mode.as_str()),
+            Err(e) => error!("Failed to set the desktop background: {:#}", e),
+        }
+    }
+
+    /// Outline of the visible pixels and, when enabled and the cells are large enough, their values
+    fn paint_pixel_grid(&self, painter: &egui::Painter, view: &ViewMapping, visible: PixelRect) {
+        let [x0, y0, x1, y1] = visible;
+        let scale = view.scale;
+        let to_screen = |x: f32, y: f32| view.to_screen(egui::pos2(x, y));
+        if !self.config.hide_pixel_grid {
+            let stroke = egui::Stroke::new(1.0, pixel_grid_color(scale));
+            for x in x0..=x1 {
+                painter.line_segment([to_screen(x as f32, y0 as f32), to_screen(x as f32, y1 as f32)], stroke);
+            }
+            for y in y0..=y1 {
+                painter.line_segment([to_screen(x0 as f32, y as f32), to_screen(x1 as f32, y as f32)], stroke);
+            }
+        }
+        
+        if !self.config.show_pixel_values {
+            return;
+        }
+        let (Some(img), Some(region)) = (&self.doc.image, self.pixel_region(visible)) else {
+            return;
+        };
+        let float = region.dtype == "float32";
+        let format_value = |value: f32| {
+            if !float {
+                format!("{}", value)
+            } else if value != 0.0 && (value.abs() >= 1e5 || value.abs() < 1e-3) {
+                format!("{:.2e}", value)
             } else {
-                // Calculate histogram from regular image data
-                for y in 0..height {
-                    for x in 0..width {
-                        let pixel = image.get_pixel(x, y);
-                        let rgba = pixel.0;
-                        
-                        // Handle different image types
-                        match image {
-                            image::DynamicImage::ImageLuma8(_) | image::DynamicImage::ImageLuma16(_) => {
-                                // Grayscale - use first channel for all RGB
-                                let bin = rgba[0] as usize;
-                                histograms[0][bin] += 1;
-                                histograms[1][bin] += 1;
-                                histograms[2][bin] += 1;
-                            }
-                            _ => {
-                                // RGB/RGBA - use separate channels
-                                histograms[0][rgba[0] as usize] += 1; // Red
-                                histograms[1][rgba[1] as usize] += 1; // Green
-                                histograms[2][rgba[2] as usize] += 1; // Blue
-                            }
-                        }
-                    }
+                format!("{:.4}", value)
+            }
+        };
+        let texts: Vec<String> = region.values.iter().map(|v| format_value(*v)).collect();
+        // One line per channel, the font is sized so the longest value fits the cell
+        let longest = texts.iter().map(|text| text.chars().count()).max().unwrap_or(1);
+        let font_size = (scale * 0.85 / (longest as f32 * 0.6))
+            .min(scale * 0.85 / (region.channels as f32 * 1.2))
+            .min(14.0);
+        if font_size < 6.0 {
+            return;
+        }
+        let font = egui::FontId::monospace(font_size);
+        for (index, pixel) in texts.chunks(region.channels).enumerate() {
+            let (x, y) = (x0 + (index % region.width) as u32, y0 + (index / region.width) as u32);
+            // Dark text on bright pixels
+            let [r, g, b, _] = img.get_pixel(x, y).0;
+            let color = if luma_709(r, g, b) > 128 { egui::Color32::BLACK } else { egui::Color32::WHITE };
+            let center = to_screen(x as f32 + 0.5, y as f32 + 0.5);
+            painter.text(center, egui::Align2::CENTER_CENTER, pixel.join("\n"), font.clone(), color);
+        }
+    }
+
+    /// Original values of a region, floating point data when the image has it
+    fn pixel_region(&self, rect: PixelRect) -> Option<PixelRegion> {
+        if let (Some(fp_data), Some(channels), Some((width, _))) =
+            (&self.doc.original_fp_data, self.doc.original_fp_channels, self.doc.original_fp_dimensions)
+        {
+            return Some(PixelRegion::from_fp(fp_data, width, channels as usize, rect));
+        }
+        self.doc.image.as_ref().map(|img| PixelRegion::from_image(img, rect))
+    }
+
+    /// Text describing the hovered pixel, same format as the hover tooltip. A sampling square
+    /// larger than one pixel is described by its mean.
+    fn pixel_info_text(&self) -> Option<String> {
+        let sample = self.config.pixel_sample;
+        let position = |x: u32, y: u32| match sample.radius() {
+            0 => format!("({}, {})", x, y),
+            _ => format!("({}, {}) {} mean", x, y, sample.as_str()),
+        };
+        if let Some((x, y, r, g, b)) = self.doc.pixel_info_fp {
+            // Show original floating point values
+            Some(match self.doc.pixel_info_channels {
+                Some(1) => format!("{} Gray({:.4})", position(x, y), r),
+                Some(channels) if channels > 4 => {
+                    let (Some(fp_data), Some((width, height))) = (&self.doc.original_fp_data, self.doc.original_fp_dimensions) else {
+                        return None;
+                    };
+                    let base = (y * width + x) as usize * channels as usize;
+                    let pixel = match sample.radius() {
+                        0 => fp_data.get(base..base + channels as usize).unwrap_or_default().to_vec(),
+                        radius => mean_fp_values(fp_data, width, height, channels as usize, x, y, radius),
+                    };
+                    let bands: Vec<String> = pixel.iter().map(|v| format!("{:.4}", v)).collect();
+                    format!("{} Bands({})", position(x, y), bands.join(", "))
                 }
+                _ => format!("{} RGB({:.4}, {:.4}, {:.4})", position(x, y), r, g, b),
+            })
+        } else if let Some((x, y, r, g, b)) = self.doc.pixel_info {
+            // Original integer values, 16-bit ones followed by the displayed 8-bit value
+            let gray = self.doc.pixel_info_channels == Some(1);
+            let values = match self.doc.pixel_info_mean {
+                Some([r, _, _]) if gray => format!("Gray({:.2})", r),
+                Some([r, g, b]) => format!("RGB({:.2}, {:.2}, {:.2})", r, g, b),
+                None if gray => format!("Gray({})", r),
+                None => format!("RGB({}, {}, {})", r, g, b),
+            };
+            Some(match self.doc.pixel_info_display {
+                Some([dr, _, _]) if gray => format!("{} {} shown as {}", position(x, y), values, dr),
+                Some([dr, dg, db]) => format!("{} {} shown as ({}, {}, {})", position(x, y), values, dr, dg, db),
+                None => format!("{} {}", position(x, y), values),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Displayed pixels of an image: display pipeline plus the monitor profile
+    fn display_color_image(&self, img: DynamicImage) -> egui::ColorImage {
+        self.apply_display_profile(self.render_display_image(img))
+    }
+
+    /// Pixels converted to the monitor profile, if one is active
+    fn apply_display_profile(&self, mut color_image: egui::ColorImage) -> egui::ColorImage {
+        if let Some(transform) = &self.display_transform {
+            if let Err(e) = transform.apply(&mut color_image) {
+                warn!("{}", e);
             }
-            
-            self.histogram_data = Some(histograms.clone());
-            
-            // Update shared data for the separate window
-            if let Ok(mut shared) = self.histogram_shared_data.lock() {
-                shared.histograms = Some(histograms);
+        }
+        color_image
+    }
+
+    /// Texture of the loupe: the image texture when it is full resolution and crisp, else a separate one
+    fn loupe_texture_id(&self) -> Option<egui::TextureId> {
+        let img = self.doc.image.as_ref()?;
+        match &self.doc.texture {
+            Some(texture) if texture.size() == [img.width() as usize, img.height() as usize]
+                && self.doc.texture_magnification == egui::TextureFilter::Nearest => Some(texture.id()),
+            _ => self.doc.loupe_texture.as_ref().map(|texture| texture.id()),
+        }
+    }
+
+    /// Build the loupe texture if the image texture cannot be used
+    fn update_loupe_texture(&mut self, ctx: &egui::Context) {
+        if self.loupe_texture_id().is_some() {
+            return;
+        }
+        let Some(img) = &self.doc.image else {
+            return;
+        };
+        let source = match &self.doc.compare {
+            Some(compare) => compare.view_image(img),
+            None => img.clone(),
+        };
+        let color_image = self.display_color_image(source);
+        self.doc.loupe_texture = Some(ctx.load_texture("loupe-texture", color_image, egui::TextureOptions::NEAREST));
+    }
+
+    /// Find the highlighted pixels of the clipping overlay if they are not known yet. Floating
+    /// point images are checked in their original data.
+    fn update_clipping(&mut self, ctx: &egui::Context) {
+        if self.doc.clipping.is_some() {
+            return;
+        }
+        let Some(img) = &self.doc.image else {
+            return;
+        };
+        let map = match (&self.doc.original_fp_data, self.doc.original_fp_dimensions, self.doc.original_fp_channels) {
+            (Some(fp_data), Some((width, height)), Some(channels)) if img.dimensions() == (width, height) => {
+                ClippingMap::from_fp(fp_data, width, height, channels as usize)
             }
-            
-            self.histogram_needs_update = false;
+            _ => ClippingMap::from_image(img),
+        };
+        info!("Clipping: {}", map.summary());
+        let texture = ctx.load_texture("clipping-texture", map.mask.clone(), egui::TextureOptions::NEAREST);
+        self.doc.clipping = Some((map, texture));
+    }
+
+    /// Size of the image at full resolution, larger than the shown overview for large TIFFs
+    fn full_image_size(&self) -> Option<(u32, u32)> {
+        match &self.doc.tiled_tiff {
+            Some(tiff) => Some(tiff.size()),
+            None => self.doc.image.as_ref().map(|img| img.dimensions()),
+        }
+    }
+
+    /// Full resolution pixels per pixel of the overview of a large TIFF
+    fn detail_factor(&self) -> Option<f32> {
+        let (tiff, img) = (self.doc.tiled_tiff.as_ref()?, self.doc.image.as_ref()?);
+        Some(tiff.size().0 as f32 / img.width().max(1) as f32)
+    }
+
+    /// Read the visible part of a large TIFF at the resolution of the view once the overview is
+    /// too coarse for the zoom. Edited, rotated and compared images show the overview only.
+    fn update_detail(&mut self, ctx: &egui::Context) {
+        if let Some(load) = &self.doc.detail_load {
+            // One read at a time, the view is checked again when it is done
+            let Some(result) = load.poll() else {
+                ctx.request_repaint_after(Duration::from_millis(50));
+                return;
+            };
+            info!("Visible region read in {:?}", load.started.elapsed());
+            self.doc.detail_load = None;
+            match result {
+                Ok(detail) => self.doc.detail = Some((detail, None)),
+                Err(e) => {
+                    error!("Failed to read the visible region, showing the overview: {:#}", e);
+                    self.doc.tiled_tiff = None;
+                    self.doc.detail = None;
+                    return;
+                }
+            }
+        }
+        let (Some(tiff), Some(factor), Some([x0, y0, x1, y1])) = (&self.doc.tiled_tiff, self.detail_factor(), self.doc.visible_region) else {
+            return;
+        };
+        if self.doc.edited || self.doc.orientation != Orientation::default() || self.doc.compare.is_some() || self.doc.layers.is_active() {
+            self.doc.detail = None;
+            return;
+        }
+        let (full_width, full_height) = tiff.size();
+        let to_full = |value: u32, full: u32| ((value as f32 * factor) as u32).min(full);
+        let region = [to_full(x0, full_width), to_full(y0, full_height), to_full(x1, full_width), to_full(y1, full_height)];
+        let (width, height) = (region[2] - region[0], region[3] - region[1]);
+        // A read pixel per screen point or finer, within the size limit
+        let final_scale = self.doc.base_scale * self.doc.scale;
+        let limit = (width as f64 * height as f64 / DETAIL_MAX_PIXELS as f64).sqrt().ceil() as u32;
+        let step = tiff.step_read(((factor / final_scale) as u32).max(limit));
+        if step as f32 >= factor {
+            self.doc.detail = None;
+            return;
+        }
+        let covered = self.doc.detail.as_ref().is_some_and(|(detail, _)| {
+            detail.step == step && detail.region[0] <= region[0] && detail.region[1] <= region[1]
+                && detail.region[2] >= region[2] && detail.region[3] >= region[3]
+        });
+        if covered {
+            return;
+        }
+        // Half a view of margin on each side, so panning does not read again right away
+        let (margin_x, margin_y) = (width / 2, height / 2);
+        let requested = [
+            region[0].saturating_sub(margin_x),
+            region[1].saturating_sub(margin_y),
+            (region[2] + margin_x).min(full_width),
+            (region[3] + margin_y).min(full_height),
+        ];
+        let tiff = Arc::clone(tiff);
+        self.doc.detail_load = Some(FullDecode::start((full_width, full_height), move || tiff.read_region(requested, step)));
+        ctx.request_repaint_after(Duration::from_millis(50));
+    }
+
+    /// Build the texture of the read region through the display pipeline if it is missing
+    fn update_detail_texture(&mut self, ctx: &egui::Context) {
+        let (Some((detail, None)), Some(factor)) = (&self.doc.detail, self.detail_factor()) else {
+            return;
+        };
+        // Filter sizes are given in pixels of the overview
+        let displayed = self.apply_display_pipeline(detail.image.clone(), factor / detail.step as f32);
+        let (width, height) = displayed.dimensions();
+        let color_image = self.apply_display_profile(egui::ColorImage::from_rgba_unmultiplied(
+            [width as usize, height as usize],
+            displayed.into_rgba8().as_raw(),
+        ));
+        let options = egui::TextureOptions { magnification: self.doc.texture_magnification, ..egui::TextureOptions::default() };
+        if let Some((_, texture)) = &mut self.doc.detail {
+            *texture = Some(ctx.load_texture("detail-texture", color_image, options));
         }
     }
 
+    /// Whether the texture has to be built again before the image is drawn
+    fn texture_outdated(&self) -> bool {
+        let magnification = self.config.magnification.texture_filter(self.doc.base_scale * self.doc.scale);
+        self.doc.image.is_some()
+            && (self.doc.texture.is_none() || self.doc.texture_needs_update || self.doc.texture_magnification != magnification)
+    }
+
     fn update_texture(&mut self, ctx: &egui::Context) {
-        if let Some(img) = &self.image {
+        if let Some(img) = &self.doc.image {
+            let magnification = self.config.magnification.texture_filter(self.doc.base_scale * self.doc.scale);
             // Check if we need to regenerate texture
-            let needs_regenerate = self.texture.is_none() || 
-                self.last_normalization != self.normalization ||
-                self.last_channel != self.channel ||
-                (self.last_texture_scale - self.scale).abs() > 0.2; // Only regenerate on significant scale changes
+            let needs_regenerate = self.doc.texture.is_none() || 
+                self.doc.texture_magnification != magnification ||
+                self.doc.last_normalization != self.doc.normalization ||
+                self.doc.last_channel != self.doc.channel ||
+                (self.doc.last_texture_scale - self.doc.scale).abs() > 0.2; // Only regenerate on significant scale changes
             
             if !needs_regenerate {
                 return;
             }
+            // Zooming only changes the texture resolution, not the displayed values
+            if self.doc.texture.is_none() || self.doc.last_normalization != self.doc.normalization
+                || self.doc.last_channel != self.doc.channel
+            {
+                self.doc.probes_dirty = true;
+                self.doc.loupe_texture = None;
+                self.doc.clipping = None;
+                if let Some((_, texture)) = &mut self.doc.detail {
+                    *texture = None;
+                }
+                self.fft_view.invalidate();
+                // A or B may have changed with the texture
+                if let Some(compare) = &mut self.doc.compare {
+                    compare.start_metrics(ctx, img);
+                }
+            }
             
             // Calculate the final display size based on current scaling
             let (orig_width, orig_height) = img.dimensions();
-            let final_scale = self.base_scale * self.scale;
+            let final_scale = self.doc.base_scale * self.doc.scale;
             
             // Only resize if the final display size is smaller than original
             // This preserves quality when zooming in
             let display_width = (orig_width as f32 * final_scale) as u32;
             let display_height = (orig_height as f32 * final_scale) as u32;
             
-            let working_img = if final_scale < 1.0 {
+            // In compare mode the displayed source is B or a difference image
+            let compared;
+            let img = match &self.doc.compare {
+                Some(compare) => {
+                    compared = compare.view_image(img);
+                    &compared
+                }
+                None => img,
+            };
+            
+            let working = |img: &DynamicImage| if final_scale < 1.0 && !self.config.disable_downscaling {
                 // Scale down for performance when displaying smaller
-                img.resize(display_width, display_height, image::imageops::FilterType::Lanczos3)
+                img.resize(display_width, display_height, self.config.downscale_filter.filter_type())
             } else {
                 // Use original image when zooming in to preserve quality
                 img.clone()
             };
+            let working_img = working(img);
+            // The wipe and checkerboard views draw B over A from a texture of its own
+            let working_b = self.doc.compare.as_ref().filter(|compare| compare.shows_both())
+                .zip(self.doc.image.as_ref())
+                .map(|(compare, a)| working(&compare.b_matching(a)));
             
-            let normalized_img = match self.normalization {
-                NormalizationType::None => working_img,
-                NormalizationType::MinMax => min_max_normalize(&working_img),
-                NormalizationType::LogMinMax => log_min_max_normalize(&working_img),
-                NormalizationType::Standard => standardize(&working_img),
-                NormalizationType::FFT => fft(&working_img),
-            };
-
-            let (width, height) = normalized_img.dimensions();
-            let rgba8 = normalized_img.to_rgba8();
-            
-            // Apply channel filtering
-            let filtered_pixels = match self.channel {
-                ChannelType::RGB => rgba8.into_raw(),
-                ChannelType::Red => {
-                    rgba8.pixels().flat_map(|p| [p[0], 0, 0, p[3]]).collect()
-                },
-                ChannelType::Green => {
-                    rgba8.pixels().flat_map(|p| [0, p[1], 0, p[3]]).collect()
-                },
-                ChannelType::Blue => {
-                    rgba8.pixels().flat_map(|p| [0, 0, p[2], p[3]]).collect()
-                },
-            };
-            
-            let color_image = egui::ColorImage::from_rgba_unmultiplied(
-                [width as usize, height as usize],
-                &filtered_pixels,
-            );
+            let rendered = self.render_display_image(working_img);
+            // Kept for 16-bit images, whose pixel readout also shows the displayed value
+            let deep = self.doc.image.as_ref().is_some_and(|img| img.as_flat_samples_u16().is_some());
+            self.doc.display_pixels = deep.then(|| rendered.clone());
+            let color_image = self.apply_display_profile(rendered);
 
-            self.texture = Some(ctx.load_texture(
-                "image-texture",
-                color_image,
-                egui::TextureOptions::default(),
-            ));
+            let options = egui::TextureOptions { magnification, ..egui::TextureOptions::default() };
+            self.doc.texture = Some(ctx.load_texture("image-texture", color_image, options));
+            self.doc.texture_magnification = magnification;
+            let texture_b = working_b.map(|b| ctx.load_texture("compare-b-texture", self.display_color_image(b), options));
+            if let Some(compare) = &mut self.doc.compare {
+                compare.texture_b = texture_b;
+            }
             
             // Update cached values
-            self.last_texture_scale = self.scale;
-            self.last_normalization = self.normalization;
-            self.last_channel = self.channel;
+            self.doc.last_texture_scale = self.doc.scale;
+            self.doc.last_normalization = self.doc.normalization;
+                self.doc.last_channel = self.doc.channel;
         }
     }
 }
@@ -1106,20 +6379,18 @@ impl ImageViewerApp {
 impl eframe::App for ImageViewerApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // Handle file drops
+        let dropped: Vec<PathBuf> = ctx.input(|i| i.raw.dropped_files.iter().filter_map(|file| file.path.clone()).collect());
         let mut file_dropped = false;
-        ctx.input(|i| {
-            for file in &i.raw.dropped_files {
-                if let Some(path) = &file.path {
-                    info!("Dropped file: {:?}", path);
-                    if let Err(e) = self.load_image(path.clone()) {
-                        error!("Failed to load dropped image: {}", e);
-                    } else {
-                        file_dropped = true;
-                        break; // Only load the first valid image
-                    }
+        if !dropped.is_empty() {
+            info!("Dropped files: {:?}", dropped);
+            match self.open_dropped(dropped) {
+                Ok(()) => file_dropped = true,
+                Err(e) => {
+                    error!("Failed to load dropped image: {}", e);
+                    self.report_load_error(&e);
                 }
             }
-        });
+        }
         
         if file_dropped {
             // Resize window to fit the new image
@@ -1128,232 +6399,876 @@ impl eframe::App for ImageViewerApp {
             ctx.request_repaint();
         }
 
-        // Handle keyboard navigation
-        ctx.input(|i| {
-            if i.key_pressed(egui::Key::ArrowLeft) {
-                if let Err(e) = self.navigate_to_adjacent_image(-1) {
-                    error!("Failed to navigate to previous image: {}", e);
+        self.watch_files(ctx);
+        self.poll_full_decode(ctx);
+        
+        // Files launched while this viewer runs
+        while let Some(path) = self.instance.as_ref().and_then(|instance| instance.poll()) {
+            info!("Opening {:?} from another launch", path);
+            match self.open_in_new_tab(path) {
+                Ok(()) => {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
                 }
-            }
-            if i.key_pressed(egui::Key::ArrowRight) {
-                if let Err(e) = self.navigate_to_adjacent_image(1) {
-                    error!("Failed to navigate to next image: {}", e);
+                Err(e) => {
+                    error!("Failed to load image from another launch: {}", e);
+                    self.report_load_error(&e);
                 }
             }
-        });
+        }
+
+        // Startup option: show the file dialog once the window is up
+        if self.open_dialog_requested {
+            self.open_dialog_requested = false;
+            self.open_file_dialog(ctx, false);
+        }
+
+        // Keyboard shortcuts, unless a text field has keyboard focus or a shortcut is being recorded
+        if !ctx.wants_keyboard_input() && self.recording_binding.is_none() {
+            let actions = ctx.input(|i| self.config.keybindings.triggered(i));
+            for action in actions {
+                self.run_action(action);
+            }
+        }
+        
+        // Ctrl+C copies the displayed image, unless a text field has keyboard focus
+        let copy_requested = !ctx.wants_keyboard_input()
+            && ctx.input(|i| i.events.iter().any(|e| matches!(e, egui::Event::Copy)));
+        if copy_requested {
+            self.copy_image_to_clipboard(ctx);
+        }
 
         // Store zoom info for use in central panel
         let mut zoom_info: Option<(egui::Pos2, f32, f32)> = None;
-        if let Some(pointer_pos) = ctx.input(|i| i.pointer.hover_pos()) {
+        // The gallery scrolls with the wheel instead
+        if let Some(pointer_pos) = ctx.input(|i| i.pointer.hover_pos()).filter(|_| self.gallery.is_none()) {
             let scroll_delta = ctx.input(|i| i.raw_scroll_delta);
             
-            if scroll_delta.y != 0.0 {
-                let old_scale = self.scale;
+            // Alt + wheel steps through the slices of a volume instead of zooming
+            if self.doc.volume.is_some() && ctx.input(|i| i.modifiers.alt) {
+                if scroll_delta.y != 0.0 {
+                    self.step_frame(if scroll_delta.y > 0.0 { 1 } else { -1 });
+                }
+            } else if scroll_delta.y != 0.0 {
+                let old_scale = self.doc.scale;
                 // Convert scroll to zoom_delta format (scroll up = zoom in)
                 let zoom_delta = if scroll_delta.y > 0.0 { 1.1 } else { 1.0 / 1.1 };
-                let new_scale = (self.scale * zoom_delta).clamp(0.1, 20.0);
+                let new_scale = (self.doc.scale * zoom_delta).clamp(0.1, 20.0);
                 
                 if old_scale != new_scale {
                     zoom_info = Some((pointer_pos, old_scale, new_scale));
                 }
             }
         }
+        
+        // Pinch to zoom around the gesture center on touchscreens and trackpads, two fingers pan
+        let (touch, trackpad_zoom, pointer_pos) = ctx.input(|i| {
+            let trackpad_zoom: f32 = i.events.iter().filter_map(|e| match e {
+                egui::Event::Zoom(factor) => Some(*factor),
+                _ => None,
+            }).product();
+            (i.multi_touch(), trackpad_zoom, i.pointer.hover_pos())
+        });
+        let touch = touch.filter(|_| self.gallery.is_none());
+        if let Some(touch) = touch {
+            self.doc.offset += touch.translation_delta;
+            ctx.request_repaint();
+        }
+        let pinch = if self.gallery.is_some() { 1.0 } else { trackpad_zoom * touch.map_or(1.0, |touch| touch.zoom_delta) };
+        if let (true, None, Some(center)) = (pinch != 1.0, zoom_info, touch.map(|touch| touch.center_pos).or(pointer_pos)) {
+            let new_scale = (self.doc.scale * pinch).clamp(0.1, 20.0);
+            if new_scale != self.doc.scale {
+                zoom_info = Some((center, self.doc.scale, new_scale));
+                // Small steps add up, the texture is only rebuilt once the change is significant
+                self.doc.texture_needs_update = true;
+            }
+        }
+
+        // Quick look: Space or Escape closes, Enter switches to the full viewer
+        if self.quick_look {
+            let (close, expand) = ctx.input(|i| {
+                (i.key_pressed(egui::Key::Space) || i.key_pressed(egui::Key::Escape), i.key_pressed(egui::Key::Enter))
+            });
+            if close {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            } else if expand {
+                info!("Leaving quick look mode");
+                self.quick_look = false;
+                ctx.send_viewport_cmd(egui::ViewportCommand::Decorations(true));
+                ctx.send_viewport_cmd(egui::ViewportCommand::MinInnerSize(egui::vec2(400.0, 400.0)));
+                let (width, height) = self.calculate_window_size();
+                ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(width, height)));
+            }
+        }
 
-        // Handle panning with left mouse button (only when pixel tool is off)
-        if !self.show_pixel_tool {
+        // Handle panning with left mouse button (only when pixel tool is off).
+        // The borderless quick look window is moved by dragging instead, unless zoomed in.
+        if self.quick_look && self.doc.scale <= 1.0 {
             if ctx.input(|i| i.pointer.primary_pressed()) {
+                ctx.send_viewport_cmd(egui::ViewportCommand::StartDrag);
+            }
+        } else if !self.show_pixel_tool && self.gallery.is_none() {
+            // Shift + drag draws the ROI, Ctrl + drag zooms to a selection and plain drag the crop rectangle instead
+            let on_minimap = ctx.input(|i| i.pointer.press_origin()).is_some_and(|pos| {
+                self.minimap_rect.is_some_and(|r| r.contains(pos)) || self.wipe_handle_rect.is_some_and(|r| r.contains(pos))
+            });
+            if ctx.input(|i| i.pointer.primary_pressed() && !i.modifiers.shift && !i.modifiers.command) && self.crop.is_none() && self.annotate.is_none() && !on_minimap {
                 self.dragging = true;
             }
             if !ctx.input(|i| i.pointer.primary_down()) {
                 self.dragging = false;
             }
             
-            if self.dragging {
+            // The first finger of a touch gesture also moves the pointer, the gesture pans instead
+            if self.dragging && touch.is_none() {
                 let delta = ctx.input(|i| i.pointer.delta());
-                self.offset += delta;
+                self.doc.offset += delta;
                 ctx.request_repaint();
             }
+
+            // Panning past the edge of the window drags the file out to other applications
+            let screen = ctx.screen_rect();
+            let left_window = ctx.input(|i| {
+                i.pointer.press_origin().is_some_and(|pos| self.view_rect.is_some_and(|view| view.contains(pos)))
+                    && !i.pointer.latest_pos().is_some_and(|pos| screen.contains(pos))
+            });
+            if self.dragging && left_window && self.doc.image_path.is_some() {
+                self.dragging = false;
+                self.drag_out();
+            }
         }
 
-        egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
+        egui::TopBottomPanel::top("top_panel").show_animated(ctx, !self.quick_look, |ui| {
             // First row: Open button, filename, and Scale
             ui.horizontal(|ui| {
                 if ui.button("Open Image").clicked() {
-                    // Create a file dialog with image filters
-                    let file_dialog = rfd::FileDialog::new()
-                        .add_filter("Images", &["png", "jpg", "jpeg", "bmp", "tif", "tiff", "webp", "gif", "avif", "hdr", "exr", "farbfeld", "qoi", "dds", "tga", "pnm", "ff", "ico"]);
+                    self.open_file_dialog(ctx, false);
+                }
+                if ui.button("Open Folder").on_hover_text("Browse all images of a folder").clicked() {
+                    self.open_folder_dialog(ctx);
+                }
+                if ui.button("New Window").on_hover_text("Open an image in another window").clicked() {
+                    self.open_new_window();
+                }
+                if !self.windows.is_empty() {
+                    ui.checkbox(&mut self.sync_windows, "Sync pan/zoom")
+                        .on_hover_text("Keep zoom and position equal in all windows");
+                }
+
+                if ui.button("Settings").clicked() {
+                    self.show_settings = !self.show_settings;
+                }
+
+                ui.separator();
+                
+                if self.doc.image.is_some() {
+                    if ui.button("⟲").on_hover_text("Rotate left (Shift+R)").clicked() {
+                        self.apply_orientation_op(OrientationOp::RotateCcw);
+                    }
+                    if ui.button("⟳").on_hover_text("Rotate right (R)").clicked() {
+                        self.apply_orientation_op(OrientationOp::RotateCw);
+                    }
+                    if ui.button("⇆").on_hover_text("Flip horizontally (H)").clicked() {
+                        self.apply_orientation_op(OrientationOp::FlipHorizontal);
+                    }
+                    if ui.button("⇅").on_hover_text("Flip vertically (V)").clicked() {
+                        self.apply_orientation_op(OrientationOp::FlipVertical);
+                    }
+                    let keys = &self.config.keybindings;
+                    let undo_text = self.doc.history.undo_label()
+                        .map_or("Nothing to undo".to_string(), |label| format!("Undo {} ({})", label.to_lowercase(), keys.get(Action::Undo)));
+                    let redo_text = self.doc.history.redo_label()
+                        .map_or("Nothing to redo".to_string(), |label| format!("Redo {} ({})", label.to_lowercase(), keys.get(Action::Redo)));
+                    if ui.add_enabled(self.doc.history.undo_label().is_some(), egui::Button::new("↶"))
+                        .on_hover_text(undo_text).on_disabled_hover_text("Nothing to undo").clicked()
+                    {
+                        self.undo();
+                    }
+                    if ui.add_enabled(self.doc.history.redo_label().is_some(), egui::Button::new("↷"))
+                        .on_hover_text(redo_text).on_disabled_hover_text("Nothing to redo").clicked()
+                    {
+                        self.redo();
+                    }
+                    if !self.doc.orientation.is_identity() && ui.button("Save orientation").clicked() {
+                        self.show_orientation_save = true;
+                    }
+                    self.render_view_orientation_menu(ui);
+                    let keys = &self.config.keybindings;
+                    ui.toggle_value(&mut self.show_info, "Info")
+                        .on_hover_text(format!("Path, format, compression, resolution and decode time of the image ({})", keys.get(Action::ToggleInfo)));
                     
-                    // Try to set a sensible default directory
-                    let file_dialog = if let Some(last_folder) = &self.last_opened_folder {
-                        if last_folder.exists() {
-                            file_dialog.set_directory(last_folder)
-                        } else {
-                            // Fallback to Pictures or current directory if last folder doesn't exist
-                            if let Ok(home_dir) = env::var("HOME") {
-                                let pictures_dir = PathBuf::from(home_dir).join("Pictures");
-                                if pictures_dir.exists() {
-                                    file_dialog.set_directory(pictures_dir)
-                                } else {
-                                    file_dialog.set_directory(env::current_dir().unwrap_or_default())
+                    ui.separator();
+                    
+                    if ui.button("Compare…").on_hover_text("Load a second image B to compare with").clicked() {
+                        self.open_compare_image();
+                    }
+                    
+                    let mut cropping = self.crop.is_some();
+                    if ui.toggle_value(&mut cropping, "Crop").on_hover_text("Drag on the image to select the crop").changed() {
+                        self.crop = cropping.then(CropTool::default);
+                    }
+                    let mut annotating = self.annotate.is_some();
+                    if ui.toggle_value(&mut annotating, "Annotate").on_hover_text("Draw rectangles, arrows, text and freehand marks over the image").changed() {
+                        self.annotate = annotating.then(AnnotationEditor::default);
+                    }
+                    let mut measuring = self.measure.is_some();
+                    if ui.toggle_value(&mut measuring, "Measure").on_hover_text("Click points to measure distances and angles").changed() {
+                        self.measure = measuring.then(|| MeasureTool::new(self.file_calibration()));
+                    }
+                    let mut thresholding = self.threshold.is_some();
+                    if ui.toggle_value(&mut thresholding, "Threshold").on_hover_text("Binarize the displayed image by a manual, Otsu or adaptive threshold").changed() {
+                        self.threshold = thresholding.then(ThresholdTool::default);
+                        self.doc.invalidate_texture();
+                    }
+                    ui.toggle_value(&mut self.show_layers, "Layers").on_hover_text("Blend other images over this one");
+                    let mut masking = self.mask_overlay.is_some();
+                    if ui.toggle_value(&mut masking, "Mask").on_hover_text("Overlay a label mask, e.g. the segmentation of the image").changed() {
+                        self.mask_overlay = masking.then(MaskOverlay::default);
+                        if masking {
+                            self.open_mask();
+                        }
+                    }
+                    if ui.button("Resize…").on_hover_text("Resample the image to another size").clicked() {
+                        self.resize = self.doc.image.as_ref().map(|img| ResizeTool::new(img.width(), img.height()));
+                    }
+                    ui.menu_button("Export view", |ui| {
+                        for factor in [1, 2] {
+                            if ui.button(format!("{}× resolution…", factor))
+                                .on_hover_text(format!("Save what the view shows, with the pixel grid and annotations, as PNG at {} pixel(s) per screen point", factor))
+                                .clicked()
+                            {
+                                ui.close_menu();
+                                if let Err(e) = self.export_view(factor) {
+                                    error!("Failed to export the view: {:#}", e);
                                 }
-                            } else {
-                                file_dialog.set_directory(env::current_dir().unwrap_or_default())
                             }
                         }
-                    } else {
-                        // No last folder, use Pictures or current directory
-                        if let Ok(home_dir) = env::var("HOME") {
-                            let pictures_dir = PathBuf::from(home_dir).join("Pictures");
-                            if pictures_dir.exists() {
-                                file_dialog.set_directory(pictures_dir)
-                            } else {
-                                file_dialog.set_directory(env::current_dir().unwrap_or_default())
+                    });
+                    
+                    if self.doc.folder_images.len() > 1 && ui.button("Encode video…").on_hover_text("Encode the images of this folder as a video").clicked() {
+                        self.show_video_export = true;
+                    }
+                    if self.doc.folder_images.len() > 1 && ui.button("Convert…").on_hover_text("Convert the images of this folder to another format or size").clicked() {
+                        self.show_batch_convert = true;
+                    }
+                }
+
+                ui.separator();
+
+                // Show filename of currently loaded image
+                if let Some(path) = &self.doc.image_path {
+                    if let Some(filename) = path.file_name() {
+                        let file_info = if let Some(index) = self.doc.current_image_index {
+                            format!("File: {} ({}/{})", 
+                                   filename.to_string_lossy(), 
+                                   index + 1, 
+                                   self.doc.folder_images.len())
+                        } else {
+                            format!("File: {}", filename.to_string_lossy())
+                        };
+                        ui.label(file_info);
+                        ui.separator();
+                    }
+                }
+                self.render_rating_controls(ui);
+
+            });
+            
+            // Compare row: view selection for A/B comparison
+            let (mut compare_closed, mut changed) = (false, false);
+            if let Some(compare) = &mut self.doc.compare {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Compare with B: {}", compare.path.file_name().unwrap_or_default().to_string_lossy()));
+                    ui.separator();
+                    for view in [CompareView::Blink, CompareView::Wipe, CompareView::Checkerboard, CompareView::AbsDifference, CompareView::SignedDifference, CompareView::Heatmap] {
+                        changed |= ui.radio_value(&mut compare.view, view, view.as_str()).changed();
+                    }
+                    if compare.view == CompareView::Blink {
+                        ui.separator();
+                        let label = if compare.showing_b { "Showing B" } else { "Showing A" };
+                        changed |= ui.toggle_value(&mut compare.showing_b, label)
+                            .on_hover_text("Toggle with B")
+                            .changed();
+                    }
+                    if compare.view == CompareView::Wipe {
+                        ui.separator();
+                        ui.radio_value(&mut compare.wipe_horizontal, false, "Left/right");
+                        ui.radio_value(&mut compare.wipe_horizontal, true, "Top/bottom");
+                        ui.add(egui::Slider::new(&mut compare.wipe, 0.0..=1.0).show_value(false))
+                            .on_hover_text("Position of the divider, which can also be dragged on the image");
+                    }
+                    if compare.view == CompareView::Checkerboard {
+                        ui.separator();
+                        ui.label("Squares:");
+                        ui.add(egui::DragValue::new(&mut compare.checker_size).range(8.0..=1024.0).suffix(" pt"));
+                    }
+                    if compare.view == CompareView::Heatmap {
+                        ui.separator();
+                        ui.label("Tolerance:");
+                        changed |= ui.add(egui::DragValue::new(&mut compare.tolerance).range(0..=254).suffix(" levels"))
+                            .on_hover_text("Differences up to this many 8-bit levels are ignored")
+                            .changed();
+                        for (channel, name) in ["R", "G", "B"].into_iter().enumerate() {
+                            changed |= ui.checkbox(&mut compare.channels[channel], name).changed();
+                        }
+                        if let Some(stats) = compare.stats.get() {
+                            ui.separator();
+                            let percentage = stats.differing as f64 / stats.total.max(1) as f64 * 100.0;
+                            ui.label(format!("{} of {} pixels differ ({:.3}%), max Δ {}", stats.differing, stats.total, percentage, stats.max_difference));
+                        }
+                    }
+                    ui.separator();
+                    compare.poll_metrics();
+                    if let Some(metrics) = compare.metrics {
+                        let psnr = if metrics.psnr.is_finite() { format!("{:.2} dB", metrics.psnr) } else { "∞".to_string() };
+                        ui.label(format!("PSNR {}  SSIM {:.4}  MAE {:.3}", psnr, metrics.ssim, metrics.mae))
+                            .on_hover_text("Quality of B against A: peak signal-to-noise ratio over RGB, structural similarity of the luma and mean absolute error in 8-bit levels");
+                    }
+                    if compare.computing_metrics() {
+                        ui.spinner();
+                    }
+                    ui.separator();
+                    compare_closed = ui.button("Close compare").clicked();
+                });
+            }
+            if compare_closed {
+                self.doc.compare = None;
+            }
+            if changed || compare_closed {
+                self.doc.invalidate_texture();
+            }
+            
+            // Volume row: plane and slice of a NIfTI volume
+            if let Some(volume) = &self.doc.volume {
+                let mut plane = volume.plane();
+                let mut slice = volume.slice() + 1;
+                let count = volume.slice_count();
+                let (dims, spacing, time_points) = (volume.dims(), volume.spacing, volume.time_points);
+                let mut slice_changed = false;
+                ui.horizontal(|ui| {
+                    ui.label("Plane:");
+                    egui::ComboBox::from_id_salt("volume_plane")
+                        .selected_text(plane.as_str())
+                        .show_ui(ui, |ui| {
+                            for choice in Plane::ALL {
+                                ui.selectable_value(&mut plane, choice, choice.as_str());
+                            }
+                        });
+                    ui.label("Slice:");
+                    slice_changed = ui.add(egui::Slider::new(&mut slice, 1..=count).suffix(format!(" / {}", count))).changed();
+                    ui.separator();
+                    let keys = &self.config.keybindings;
+                    ui.label(format!("Step: Alt + wheel, {} / {}", keys.get(Action::PreviousFrame), keys.get(Action::NextFrame)));
+                    ui.separator();
+                    ui.weak(format!("{}×{}×{} voxels of {:.2}×{:.2}×{:.2}", dims[0], dims[1], dims[2], spacing[0], spacing[1], spacing[2]));
+                    if time_points > 1 {
+                        ui.colored_label(egui::Color32::from_rgb(255, 170, 0), format!("First of {} volumes", time_points));
+                    }
+                });
+                self.set_volume_plane(plane);
+                if slice_changed {
+                    self.set_volume_slice(slice - 1);
+                }
+            }
+            
+            // OME row: channel, z plane and time point of an OME-TIFF
+            if let Some(ome) = &self.doc.ome {
+                let mut position = ome.position();
+                let sizes = ome.sizes();
+                let mut changed = false;
+                ui.horizontal(|ui| {
+                    if sizes[1] > 1 {
+                        ui.label("Channel:");
+                        egui::ComboBox::from_id_salt("ome_channel")
+                            .selected_text(&ome.channel_names[position[1]])
+                            .show_ui(ui, |ui| {
+                                for (index, name) in ome.channel_names.iter().enumerate() {
+                                    changed |= ui.selectable_value(&mut position[1], index, name).changed();
+                                }
+                            });
+                    }
+                    for axis in [0, 2].into_iter().filter(|&axis| sizes[axis] > 1) {
+                        ui.label(format!("{}:", ome_tiff::AXIS_NAMES[axis]));
+                        let mut value = position[axis] + 1;
+                        if ui.add(egui::Slider::new(&mut value, 1..=sizes[axis]).suffix(format!(" / {}", sizes[axis]))).changed() {
+                            position[axis] = value - 1;
+                            changed = true;
+                        }
+                    }
+                    ui.separator();
+                    let keys = &self.config.keybindings;
+                    ui.label(format!("Step: {} / {}", keys.get(Action::PreviousFrame), keys.get(Action::NextFrame)));
+                    ui.separator();
+                    ui.weak(format!("OME-TIFF, {} planes", ome.plane_count()));
+                });
+                if changed {
+                    self.set_ome_position(position);
+                }
+            }
+            
+            // Frame row: scrubber for animated GIFs and TIFF stacks, B follows A while locked
+            let a_frames = self.doc.frames.as_ref().map(|frames| (frames.index(), frames.len()));
+            let b_frames = self.doc.compare.as_ref()
+                .and_then(|compare| compare.frames.as_ref().map(|frames| (frames.index(), frames.len(), compare.frames_locked)));
+            if a_frames.is_some() || b_frames.is_some() {
+                let mut a_target = None;
+                let mut b_target = None;
+                let mut lock_changed = None;
+                let mut export_frames = false;
+                ui.horizontal(|ui| {
+                    if let Some((index, len)) = a_frames {
+                        ui.label("Frame:");
+                        let mut frame = index + 1;
+                        if ui.add(egui::Slider::new(&mut frame, 1..=len).suffix(format!(" / {}", len))).changed() {
+                            a_target = Some(frame - 1);
+                        }
+                    }
+                    if let Some((index, len, mut locked)) = b_frames {
+                        if let Some((_, a_len)) = a_frames {
+                            ui.separator();
+                            if ui.checkbox(&mut locked, "Lock A/B frames")
+                                .on_hover_text("Step B's frames together with A's, so the same frame index is compared")
+                                .changed()
+                            {
+                                lock_changed = Some(locked);
+                            }
+                            if locked && a_len != len {
+                                ui.colored_label(egui::Color32::from_rgb(255, 170, 0), format!("B has {} frames", len))
+                                    .on_hover_text("Past its last frame B stays on it");
                             }
-                        } else {
-                            file_dialog.set_directory(env::current_dir().unwrap_or_default())
                         }
-                    };
-                    
-                    if let Some(path) = file_dialog.pick_file() {
-                        info!("Opening image from path: {:?}", path);
-                        if let Err(e) = self.load_image(path) {
-                            error!("Failed to load image: {}", e);
-                        } else {
-                            // Resize window to fit the new image
-                            let (width, height) = self.calculate_window_size();
-                            ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(width, height)));
+                        if !locked || a_frames.is_none() {
+                            ui.label("B frame:");
+                            let mut frame = index + 1;
+                            if ui.add(egui::Slider::new(&mut frame, 1..=len).suffix(format!(" / {}", len))).changed() {
+                                b_target = Some(frame - 1);
+                            }
                         }
                     }
+                    ui.separator();
+                    let keys = &self.config.keybindings;
+                    ui.label(format!("Step: {} / {}", keys.get(Action::PreviousFrame), keys.get(Action::NextFrame)));
+                    if a_frames.is_some() {
+                        export_frames = ui.button("Export frames…")
+                            .on_hover_text("Save every frame as PNG, named by the frames template in the settings")
+                            .clicked();
+                    }
+                });
+                if let Some(locked) = lock_changed {
+                    if let Some(compare) = &mut self.doc.compare {
+                        compare.frames_locked = locked;
+                    }
+                    // Locking brings B to A's frame
+                    if let (true, Some((index, _))) = (locked, a_frames) {
+                        self.set_frame(index);
+                    }
                 }
-
-                ui.separator();
-
-                // Show filename of currently loaded image
-                if let Some(path) = &self.image_path {
-                    if let Some(filename) = path.file_name() {
-                        let file_info = if let Some(index) = self.current_image_index {
-                            format!("File: {} ({}/{})", 
-                                   filename.to_string_lossy(), 
-                                   index + 1, 
-                                   self.folder_images.len())
-                        } else {
-                            format!("File: {}", filename.to_string_lossy())
-                        };
-                        ui.label(file_info);
-                        ui.separator();
+                if let Some(index) = a_target {
+                    self.set_frame(index);
+                }
+                if let Some(index) = b_target {
+                    self.set_compare_frame(index);
+                }
+                if export_frames {
+                    let mut dialog = rfd::FileDialog::new();
+                    if let Some(folder) = &self.last_opened_folder {
+                        dialog = dialog.set_directory(folder);
+                    }
+                    if let Some(folder) = dialog.pick_folder() {
+                        match self.export_frames(&folder) {
+                            Ok(count) => info!("Exported {} frames to {:?}", count, folder),
+                            Err(e) => error!("Failed to export frames: {}", e),
+                        }
                     }
                 }
-
-            });
+            }
             
             // Second row: Normalization
             ui.horizontal(|ui| {
                 ui.label("Normalization:");
                 let mut changed = false;
-                changed |= ui.radio_value(&mut self.normalization, NormalizationType::None, "None").changed();
-                changed |= ui.radio_value(&mut self.normalization, NormalizationType::MinMax, "Min-Max").changed();
-                changed |= ui.radio_value(&mut self.normalization, NormalizationType::LogMinMax, "Log Min-Max").changed();
-                changed |= ui.radio_value(&mut self.normalization, NormalizationType::Standard, "Standard").changed();
-                changed |= ui.radio_value(&mut self.normalization, NormalizationType::FFT, "FFT").changed();
+                // The expression takes the place of the normalization
+                ui.add_enabled_ui(self.expression.is_none(), |ui| {
+                    for transform in self.transforms.of_kind(TransformKind::Normalization) {
+                        changed |= ui.radio_value(&mut self.doc.normalization, transform.id(), transform.name()).changed();
+                    }
+                });
+                
+                let mut expression = self.expression.is_some();
+                if ui.toggle_value(&mut expression, "Expression")
+                    .on_hover_text("Display a channel computed per pixel, like (r - b) / (r + b), in place of the normalization")
+                    .changed()
+                {
+                    self.expression = expression.then(ExpressionTool::default);
+                    self.doc.invalidate_texture();
+                    changed = true;
+                }
+                
+                ui.separator();
+                ui.toggle_value(&mut self.show_fft, "FFT")
+                    .on_hover_text("Magnitude and phase spectrum in a separate window, with frequency masks");
+                
+                if self.doc.is_hdr {
+                    ui.separator();
+                    ui.label("Tone map:");
+                    let mut mapper_changed = false;
+                    egui::ComboBox::from_id_salt("tone_mapper")
+                        .selected_text(self.config.tone_mapper.as_str())
+                        .show_ui(ui, |ui| {
+                            for mapper in ToneMapper::ALL {
+                                mapper_changed |= ui.selectable_value(&mut self.config.tone_mapper, mapper, mapper.as_str()).changed();
+                            }
+                        });
+                    let exposure_changed = ui.add(egui::DragValue::new(&mut self.doc.hdr_exposure).range(-10.0..=10.0).speed(0.05).suffix(" EV"))
+                        .on_hover_text("Exposure applied before tone mapping")
+                        .changed();
+                    if mapper_changed {
+                        if let Err(e) = self.config.save() {
+                            error!("Failed to save settings: {}", e);
+                        }
+                    }
+                    if mapper_changed || exposure_changed {
+                        self.apply_tone_mapping();
+                    }
+                    
+                    if let Some(develop) = &self.doc.raw_develop {
+                        ui.separator();
+                        ui.label("White balance:");
+                        let mut white_balance = develop.white_balance;
+                        egui::ComboBox::from_id_salt("white_balance")
+                            .selected_text(white_balance.as_str())
+                            .show_ui(ui, |ui| {
+                                for preset in WhiteBalance::PRESETS {
+                                    ui.selectable_value(&mut white_balance, preset, preset.as_str());
+                                }
+                            });
+                        let mut multipliers = develop.multipliers;
+                        let red = ui.add(egui::DragValue::new(&mut multipliers[0]).range(0.1..=8.0).speed(0.01).prefix("R ×"))
+                            .on_hover_text("Red gain relative to green");
+                        let blue = ui.add(egui::DragValue::new(&mut multipliers[2]).range(0.1..=8.0).speed(0.01).prefix("B ×"))
+                            .on_hover_text("Blue gain relative to green");
+                        if red.changed() || blue.changed() {
+                            self.set_white_balance(WhiteBalance::Custom, multipliers);
+                        } else if white_balance != develop.white_balance {
+                            let multipliers = develop.preset(white_balance);
+                            self.set_white_balance(white_balance, multipliers);
+                        }
+                    }
+                }
+                
+                ui.separator();
+                let label = if self.doc.adjustments.is_identity() { "Adjustments" } else { "Adjustments •" };
+                ui.toggle_value(&mut self.show_adjustments, label)
+                    .on_hover_text("Exposure, brightness, contrast, gamma and saturation of the displayed image");
+                let label = match self.doc.pipeline.steps.len() {
+                    0 => "Pipeline".to_string(),
+                    steps => format!("Pipeline ({})", steps),
+                };
+                ui.toggle_value(&mut self.show_pipeline, label)
+                    .on_hover_text("Chain operations like gamma, invert and threshold after the normalization");
+                ui.menu_button("Filters", |ui| {
+                    for transform in self.transforms.of_kind(TransformKind::Filter) {
+                        if ui.button(transform.name()).clicked() {
+                            // Added as a pipeline step, its parameters are edited there
+                            self.doc.pipeline.push(transform);
+                            self.show_pipeline = true;
+                            self.doc.invalidate_texture();
+                            ui.close_menu();
+                        }
+                    }
+                }).response.on_hover_text("Blur, sharpen, denoise and edge filters, added as steps of the pipeline");
 
                 if changed {
-                    self.texture_needs_update = true;
-                    self.histogram_needs_update = true;
+                    self.doc.texture_needs_update = true;
+                    self.doc.histogram_needs_update = true;
                 }
             });
             
+            // Parameters of the active transforms, generated from their descriptors
+            let active_with_params: Vec<&'static str> = std::iter::once(self.doc.normalization)
+                .filter(|id| self.transforms.get(id).is_some_and(|t| !t.params().is_empty()))
+                .collect();
+            if !active_with_params.is_empty() {
+                ui.horizontal(|ui| {
+                    let mut params_changed = false;
+                    for id in active_with_params {
+                        params_changed |= self.transforms.params_ui(ui, id);
+                    }
+                    if params_changed {
+                        self.doc.invalidate_texture();
+                    }
+                });
+            }
+            
             // Third row: Channel, Pixel Info, and image information
             ui.horizontal(|ui| {
+                if let (Some(mapping), Some(bands)) = (&mut self.doc.band_mapping, self.doc.original_fp_channels) {
+                    if multiband::mapping_ui(ui, mapping, bands) {
+                        self.apply_band_mapping();
+                    }
+                    ui.separator();
+                }
+                
                 ui.label("Channel:");
                 let mut channel_changed = false;
                 egui::ComboBox::from_label("")
-                    .selected_text(self.channel.as_str())
+                    .selected_text(self.doc.channel.as_str())
                     .show_ui(ui, |ui| {
-                        channel_changed |= ui.selectable_value(&mut self.channel, ChannelType::RGB, "RGB").changed();
-                        channel_changed |= ui.selectable_value(&mut self.channel, ChannelType::Red, "Red").changed();
-                        channel_changed |= ui.selectable_value(&mut self.channel, ChannelType::Green, "Green").changed();
-                        channel_changed |= ui.selectable_value(&mut self.channel, ChannelType::Blue, "Blue").changed();
+                        channel_changed |= ui.selectable_value(&mut self.doc.channel, ChannelType::Rgb, "RGB").changed();
+                        channel_changed |= ui.selectable_value(&mut self.doc.channel, ChannelType::Luminance, "Luminance").changed();
+                        channel_changed |= ui.selectable_value(&mut self.doc.channel, ChannelType::Red, "Red").changed();
+                        channel_changed |= ui.selectable_value(&mut self.doc.channel, ChannelType::Green, "Green").changed();
+                        channel_changed |= ui.selectable_value(&mut self.doc.channel, ChannelType::Blue, "Blue").changed();
+                        channel_changed |= ui.selectable_value(&mut self.doc.channel, ChannelType::Alpha, "Alpha").changed();
                     });
                     
+                if matches!(self.doc.channel, ChannelType::Red | ChannelType::Green | ChannelType::Blue)
+                    && ui.checkbox(&mut self.doc.channel_as_gray, "As grayscale")
+                        .on_hover_text("Show the selected channel as a gray image instead of tinted in its color")
+                        .changed()
+                {
+                    self.doc.invalidate_texture();
+                    channel_changed = true;
+                }
                 if channel_changed {
-                    self.texture_needs_update = true;
-                    self.histogram_needs_update = true;
+                    self.doc.texture_needs_update = true;
+                    self.doc.histogram_needs_update = true;
                 }
                 
-                ui.separator();
+                ui.label("Colormap:");
+                let mut colormap_changed = false;
+                egui::ComboBox::from_id_salt("colormap")
+                    .selected_text(self.doc.colormap.as_deref().unwrap_or("None"))
+                    .show_ui(ui, |ui| {
+                        colormap_changed |= ui.selectable_value(&mut self.doc.colormap, None, "None").changed();
+                        let names = colormap::builtin_colormaps().into_iter().chain(self.config.colormaps.iter().cloned()).map(|c| c.name);
+                        for name in names {
+                            colormap_changed |= ui.selectable_value(&mut self.doc.colormap, Some(name.clone()), name).changed();
+                        }
+                    });
+                if colormap_changed {
+                    self.doc.invalidate_texture();
+                }
+                if ui.button("Edit…").on_hover_text("Edit colormaps and import LUTs").clicked() {
+                    self.show_colormap_editor = true;
+                }
                 
-                ui.checkbox(&mut self.show_pixel_tool, "Pixel Info");
+                ui.label("Background:");
+                let mut background_changed = false;
+                egui::ComboBox::from_id_salt("background")
+                    .selected_text(self.config.background.as_str())
+                    .show_ui(ui, |ui| {
+                        for background in Background::ALL {
+                            background_changed |= ui.selectable_value(&mut self.config.background, background, background.as_str()).changed();
+                        }
+                    });
+                if self.config.background == Background::Custom {
+                    background_changed |= ui.color_edit_button_srgb(&mut self.config.background_color).changed();
+                }
+                if background_changed {
+                    if let Err(e) = self.config.save() {
+                        error!("Failed to save settings: {}", e);
+                    }
+                }
                 
                 ui.separator();
                 
-                if ui.button("Histogram").clicked() {
-                    if self.show_histogram {
-                        // Close the histogram window
-                        self.show_histogram = false;
-                        self.histogram_window_id = None;
-                    } else {
-                        // Open the histogram window
-                        self.show_histogram = true;
-                        if self.histogram_needs_update {
-                            self.calculate_histogram();
+                ui.checkbox(&mut self.show_pixel_tool, "Pixel Info");
+                if self.show_pixel_tool {
+                    let mut sample_changed = false;
+                    egui::ComboBox::from_id_salt("pixel_sample")
+                        .width(60.0)
+                        .selected_text(self.config.pixel_sample.as_str())
+                        .show_ui(ui, |ui| {
+                            for sample in PixelSample::ALL {
+                                sample_changed |= ui.selectable_value(&mut self.config.pixel_sample, sample, sample.as_str()).changed();
+                            }
+                        })
+                        .response
+                        .on_hover_text("Pixels averaged by the readout and the color picker");
+                    if sample_changed {
+                        if let Err(e) = self.config.save() {
+                            error!("Failed to save settings: {}", e);
                         }
-                        
-                        // Create a new viewport for the histogram window
-                        let histogram_id = egui::ViewportId::from_hash_of("histogram_window");
-                        self.histogram_window_id = Some(histogram_id);
                     }
                 }
+                ui.checkbox(&mut self.show_loupe, "Loupe").on_hover_text("Magnifier following the cursor (L)");
+                if self.show_loupe {
+                    ui.add(egui::DragValue::new(&mut self.loupe.zoom).range(Loupe::ZOOM_RANGE).prefix("×"))
+                        .on_hover_text("Magnification relative to the view");
+                    egui::ComboBox::from_id_salt("loupe_shape")
+                        .width(70.0)
+                        .selected_text(if self.loupe.shape == LoupeShape::Circle { "Circle" } else { "Square" })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.loupe.shape, LoupeShape::Circle, "Circle");
+                            ui.selectable_value(&mut self.loupe.shape, LoupeShape::Square, "Square");
+                        });
+                }
+                ui.checkbox(&mut self.show_clipping, "Clipping")
+                    .on_hover_text("Highlight pixels at the maximum (magenta) and minimum (cyan) of the data, and NaN or infinite values (yellow)");
+                if let Some((map, _)) = self.doc.clipping.as_ref().filter(|_| self.show_clipping) {
+                    let label = ui.label(map.summary());
+                    if let Some((min, max)) = map.range {
+                        label.on_hover_text(format!("Data range: {} to {}", min, max));
+                    }
+                }
+                ui.checkbox(&mut self.show_filmstrip, "Filmstrip");
+                let mut gallery = self.gallery.is_some();
+                if ui.checkbox(&mut gallery, "Gallery").on_hover_text("Thumbnails of the whole folder instead of the image (G)").changed() {
+                    self.toggle_gallery();
+                }
+                
+                ui.separator();
+                
+                if ui.button("Analysis").clicked() {
+                    self.toggle_histogram();
+                }
+                if ui.button("Info").on_hover_text("File and pixel hashes, bit-exact comparison").clicked() {
+                    self.show_integrity = !self.show_integrity;
+                }
                 
                 ui.separator();
                 
                 // Show navigation hint if we have multiple images in folder
-                if self.folder_images.len() > 1 {
-                    ui.label("Navigate: ← → arrows");
+                if self.doc.folder_images.len() > 1 {
+                    let keys = &self.config.keybindings;
+                    ui.label(format!("Navigate: {} / {}", keys.get(Action::PreviousImage), keys.get(Action::NextImage)));
                     ui.separator();
                 }
                 
-                if let Some(img) = &self.image {
+                if let Some(img) = &self.doc.image {
                     let (width, height) = img.dimensions();
                     ui.label(format!("Size: {}×{}", width, height));
+                    if let Some(full_decode) = &self.doc.full_decode {
+                        ui.spinner();
+                        ui.colored_label(egui::Color32::from_rgb(255, 170, 0), format!("Preview, decoding {}×{}…", full_decode.size.0, full_decode.size.1))
+                            .on_hover_text("A low resolution preview is shown until the full image is decoded, the image tools are paused meanwhile");
+                    } else if let (Some((full_width, full_height)), Some(_)) = (self.doc.reduced_from, &self.doc.tiled_tiff) {
+                        if self.doc.detail_load.is_some() {
+                            ui.spinner();
+                        }
+                        ui.colored_label(egui::Color32::from_rgb(255, 170, 0), format!("(overview of {}×{})", full_width, full_height))
+                            .on_hover_text("Zooming in reads the visible part at the resolution of the view, coordinates and values refer to the overview");
+                    } else if let Some((full_width, full_height)) = self.doc.reduced_from {
+                        ui.colored_label(egui::Color32::from_rgb(255, 170, 0), format!("(reduced from {}×{})", full_width, full_height))
+                            .on_hover_text("Too large to decode at full resolution, coordinates and values refer to the reduced image");
+                    }
+                    
+                    if let Some(path) = &self.doc.image_path {
+                        for hint in self.hint_index.get(path) {
+                            ui.colored_label(egui::Color32::from_rgb(255, 170, 0), format!("⚠ {}", hint.as_str()));
+                        }
+                    }
                     
-                    if self.is_floating_point_image {
+                    if self.doc.is_floating_point_image {
                         ui.label("Type: Floating Point TIFF");
-                        if let Some((min_val, max_val)) = self.original_data_range {
+                        if let Some((min_val, max_val)) = self.doc.original_data_range {
                             ui.label(format!("Range: {:.3} to {:.3}", min_val, max_val));
                         }
+                        if self.doc.non_finite_pixels > 0 {
+                            let [r, g, b] = self.config.non_finite_color.0;
+                            ui.colored_label(egui::Color32::from_rgb(r, g, b), format!("NaN/Inf: {} pixels", self.doc.non_finite_pixels))
+                                .on_hover_text("Pixels with a NaN or infinite value, left out of the range and the statistics and shown in the color set in the settings");
+                        }
                     }
                 }
                 
-                if let Some((x, y, r, g, b)) = self.pixel_info {
+                if let (Some(_), Some(text)) = (self.doc.pixel_info, self.pixel_info_text()) {
                     ui.separator();
-                    ui.label(format!("Pixel: ({}, {}) RGB({}, {}, {})", x, y, r, g, b));
+                    ui.label(format!("Pixel: {}", text));
                 }
             });
         });
 
-        if (self.texture.is_none() || self.texture_needs_update) && self.image.is_some() {
+        // Tabs of the open images
+        let show_tabs = !self.quick_look && (self.doc.image.is_some() || !self.tabs.is_empty());
+        egui::TopBottomPanel::top("tab_bar").show_animated(ctx, show_tabs, |ui| self.render_tab_bar(ui, ctx));
+
+        // Flag obviously failed captures in the current folder
+        if !self.config.skip_classification && self.doc.folder_images.len() > 1 {
+            self.hint_index.index(ctx, &self.doc.folder_images);
+            self.hint_index.poll();
+        }
+        
+        // Status bar at the very bottom, below the filmstrip and the palette
+        let mut bottom_panels_height = 0.0;
+        if !self.quick_look {
+            let panel = egui::TopBottomPanel::bottom("status_bar")
+                .resizable(false)
+                .show(ctx, |ui| self.render_status_bar(ui));
+            bottom_panels_height += panel.response.rect.height();
+        }
+        
+        // Thumbnail strip for the images of the current folder
+        if self.show_filmstrip && self.gallery.is_none() && !self.quick_look && self.doc.folder_images.len() > 1 {
+            let thumbnails = self.thumbnails.get_or_insert_with(|| {
+                ThumbnailCache::new(ctx, THUMBNAIL_SIZE, load_thumbnail_source)
+            });
+            thumbnails.poll(ctx);
+            
+            let panel = egui::TopBottomPanel::bottom("filmstrip")
+                .resizable(false)
+                .show(ctx, |ui| self.render_filmstrip(ui));
+            bottom_panels_height += panel.response.rect.height();
+            
+            if let Some(index) = panel.inner {
+                if self.doc.current_image_index != Some(index) {
+                    let path = self.doc.folder_images[index].clone();
+                    info!("Loading image from filmstrip: {:?}", path);
+                    if let Err(e) = self.load_image(path) {
+                        error!("Failed to load image from filmstrip: {}", e);
+                        self.report_load_error(&e);
+                    }
+                }
+            }
+        }
+        
+        // Colors sampled with the pixel tool
+        if !self.palette.is_empty() && !self.quick_look {
+            let panel = egui::TopBottomPanel::bottom("palette")
+                .resizable(false)
+                .show(ctx, |ui| self.render_palette(ui));
+            bottom_panels_height += panel.response.rect.height();
+        }
+        
+        // Layer stack over the image
+        egui::SidePanel::right("layers")
+            .default_width(280.0)
+            .show_animated(ctx, self.show_layers && self.gallery.is_none() && !self.quick_look, |ui| self.render_layers_panel(ui));
+        
+        self.update_display_transform(ctx);
+        
+        if self.texture_outdated() {
             self.update_texture(ctx);
-            self.texture_needs_update = false;
+            self.doc.texture_needs_update = false;
+        }
+        if self.show_loupe {
+            self.update_loupe_texture(ctx);
         }
+        self.update_mask_overlay(ctx);
+        if self.show_clipping {
+            self.update_clipping(ctx);
+        }
+        // Follows the view of the previous frame
+        self.update_detail(ctx);
+        self.update_detail_texture(ctx);
 
         // Handle zoom outside of the panel to avoid borrowing issues
         if let Some((pointer_pos, old_scale, new_scale)) = zoom_info {
-            if let Some(img) = &self.image {
-                let old_final_scale = self.base_scale * old_scale;
+            if let Some(img) = &self.doc.image {
+                let old_final_scale = self.doc.base_scale * old_scale;
                 let (orig_width, orig_height) = img.dimensions();
-                let old_display_size = egui::vec2(
-                    orig_width as f32 * old_final_scale,
-                    orig_height as f32 * old_final_scale
-                );
+                let old_display_size = self.doc.view_orientation.apply_size(egui::vec2(orig_width as f32, orig_height as f32)) * old_final_scale;
                 
                 // Calculate where image would be positioned
                 let available_size = ctx.screen_rect().size();
                 let center_x = available_size.x / 2.0;
-                let center_y = (available_size.y - 80.0) / 2.0 + 80.0; // Account for top panel
+                let top_panel_height = if self.quick_look { 0.0 } else { 80.0 };
+                let center_y = (available_size.y - top_panel_height) / 2.0 + top_panel_height; // Account for top panel
                 
                 let old_image_pos = egui::pos2(
-                    center_x - old_display_size.x / 2.0 + self.offset.x,
-                    center_y - old_display_size.y / 2.0 + self.offset.y
+                    center_x - old_display_size.x / 2.0 + self.doc.offset.x,
+                    center_y - old_display_size.y / 2.0 + self.doc.offset.y
                 );
                 
                 let old_image_rect = egui::Rect::from_min_size(old_image_pos, old_display_size);
@@ -1368,172 +7283,658 @@ impl eframe::App for ImageViewerApp {
                     let image_point = pointer_offset_from_center / old_final_scale;
                     
                     // Apply new scale
-                    self.scale = new_scale;
-                    let new_final_scale = self.base_scale * new_scale;
+                    self.doc.scale = new_scale;
+                    let new_final_scale = self.doc.base_scale * new_scale;
                     
                     // Calculate where that point should be now
                     let new_pointer_offset = image_point * new_final_scale;
                     
                     // Adjust offset to keep the point under cursor
                     let desired_center = pointer_pos - new_pointer_offset;
-                    self.offset += desired_center - image_center;
+                    self.doc.offset += desired_center - image_center;
                 } else {
                     // If not over image, just apply zoom
-                    self.scale = new_scale;
+                    self.doc.scale = new_scale;
                 }
                 
                 // Mark texture for update if scale changed significantly
                 if (new_scale - old_scale).abs() > 0.1 {
-                    self.texture_needs_update = true;
+                    self.doc.texture_needs_update = true;
                 }
                 ctx.request_repaint();
             }
         }
         
-        egui::CentralPanel::default().show(ctx, |ui| {
-            if let Some(img) = &self.image {
-                if let Some(texture) = &self.texture {
+        let central_frame = if self.quick_look {
+            egui::Frame::NONE.fill(egui::Color32::BLACK)
+        } else {
+            self.canvas_frame(ctx)
+        };
+        let mut zoom_to = None; // Applied after the panel, which borrows the image
+        let mut toggle_fit = false;
+        if self.checkerboard.is_none() {
+            let (light, dark) = (egui::Color32::from_gray(204), egui::Color32::from_gray(153));
+            let tile = egui::ColorImage { size: [2, 2], pixels: vec![light, dark, dark, light] };
+            let options = egui::TextureOptions { wrap_mode: egui::TextureWrapMode::Repeat, ..egui::TextureOptions::NEAREST };
+            self.checkerboard = Some(ctx.load_texture("checkerboard", tile, options));
+        }
+        egui::CentralPanel::default().frame(central_frame).show(ctx, |ui| {
+            self.hover_pixel = None;
+            if self.gallery.is_some() {
+                self.render_gallery(ui);
+                return;
+            }
+            if let Some(img) = &self.doc.image {
+                if let Some(texture) = &self.doc.texture {
                     let _texture_size = texture.size_vec2();
-                    let final_scale = self.base_scale * self.scale;
+                    let texture_id = texture.id();
+                    let final_scale = self.doc.base_scale * self.doc.scale;
+                    
+                    // Calculate display size based on original image dimensions, turned with the view
+                    let (orig_width, orig_height) = img.dimensions();
+                    let image_size = egui::vec2(orig_width as f32, orig_height as f32);
+                    let display_size = self.doc.view_orientation.apply_size(image_size) * final_scale;
+                    
+                    // Center the image in the available space
+                    let available_rect = ui.available_rect_before_wrap();
+                    self.view_rect = Some(available_rect);
+                    let center_x = available_rect.center().x;
+                    let center_y = available_rect.center().y;
+                    
+                    // Calculate position to center the image
+                    let image_pos = egui::pos2(
+                        center_x - display_size.x / 2.0 + self.doc.offset.x,
+                        center_y - display_size.y / 2.0 + self.doc.offset.y
+                    );
+                    
+                    let image_rect = egui::Rect::from_min_size(image_pos, display_size);
+                    // Positions in the image refer to the data layout, whatever the view orientation
+                    let view = ViewMapping { center: image_rect.center(), scale: final_scale, orientation: self.doc.view_orientation, size: image_size };
+                    
+                    // The status bar shows the position with or without the pixel tool
+                    self.hover_pixel = ui.input(|i| i.pointer.hover_pos()).filter(|pos| image_rect.contains(*pos)).map(|pos| {
+                        let point = view.to_image(pos);
+                        ((point.x.max(0.0) as u32).min(orig_width - 1), (point.y.max(0.0) as u32).min(orig_height - 1))
+                    });
+                    
+                    // Handle pixel tool hovering
+                    if self.show_pixel_tool {
+                        if let Some(pointer_pos) = ui.input(|i| i.pointer.interact_pos()) {
+                            if image_rect.contains(pointer_pos) {
+                                // Convert screen coordinates to image coordinates
+                                let point = view.to_image(pointer_pos);
+                                let image_x = point.x.max(0.0) as u32;
+                                let image_y = point.y.max(0.0) as u32;
+                                
+                                // Sample pixel from original image
+                                if image_x < orig_width && image_y < orig_height {
+                                    // Check if we have original floating point data
+                                    let radius = self.config.pixel_sample.radius();
+                                    if let (Some(fp_data), Some((fp_width, fp_height)), Some(fp_channels)) = (
+                                        &self.doc.original_fp_data,
+                                        self.doc.original_fp_dimensions,
+                                        self.doc.original_fp_channels
+                                    ) {
+                                        // Sample from original floating point data, averaged over the sampling square
+                                        let pixel_idx = (image_y * fp_width + image_x) as usize;
+                                        let channels = fp_channels as usize;
+                                        let pixel: Option<Cow<[f32]>> = if radius == 0 {
+                                            fp_data.get(pixel_idx * channels..(pixel_idx + 1) * channels).map(Cow::Borrowed)
+                                        } else {
+                                            Some(Cow::Owned(mean_fp_values(fp_data, fp_width, fp_height, channels, image_x, image_y, radius)))
+                                        };
+                                        match (fp_channels, pixel) {
+                                            (1, Some(pixel)) => {
+                                                // Grayscale
+                                                self.doc.pixel_info_fp = Some((image_x, image_y, pixel[0], pixel[0], pixel[0]));
+                                                self.doc.pixel_info_channels = Some(1);
+                                            }
+                                            (3 | 4, Some(pixel)) => {
+                                                // RGB, or RGBA using the RGB channels
+                                                self.doc.pixel_info_fp = Some((image_x, image_y, pixel[0], pixel[1], pixel[2]));
+                                                self.doc.pixel_info_channels = Some(fp_channels);
+                                            }
+                                            (channels, Some(pixel)) if channels > 4 => {
+                                                // Multiband, the readout text lists all bands
+                                                if let Some(mapping) = self.doc.band_mapping {
+                                                    let bands = mapping.bands();
+                                                    let band = |index: usize| pixel.get(bands[index.min(bands.len() - 1)]).copied().unwrap_or(0.0);
+                                                    self.doc.pixel_info_fp = Some((image_x, image_y, band(0), band(1), band(2)));
+                                                    self.doc.pixel_info_channels = Some(channels);
+                                                }
+                                            }
+                                            (_, None) => {}
+                                            _ => {
+                                                // Fallback to normalized values
+                                                let [r, g, b, _] = img.get_pixel(image_x, image_y).0.map(u16::from);
+                                                self.doc.pixel_info = Some((image_x, image_y, r, g, b));
+                                                self.doc.pixel_info_mean = None;
+                                                self.doc.pixel_info_display = None;
+                                                self.doc.pixel_info_fp = None;
+                                                self.doc.pixel_info_channels = None;
+                                            }
+                                        }
+                                    } else {
+                                        if radius > 0 {
+                                            // Mean over the sampling square, rounded in `pixel_info`
+                                            let [r, g, b, _] = mean_pixel_values(img, image_x, image_y, radius);
+                                            let [rounded_r, rounded_g, rounded_b] = [r, g, b].map(|value| value.round() as u16);
+                                            self.doc.pixel_info = Some((image_x, image_y, rounded_r, rounded_g, rounded_b));
+                                            self.doc.pixel_info_mean = Some([r, g, b]);
+                                            self.doc.pixel_info_display = None;
+                                        } else {
+                                            // Original values of integer images, 16-bit ones with the value they are displayed as
+                                            let [r, g, b, _] = pixel_values(img, image_x, image_y);
+                                            self.doc.pixel_info = Some((image_x, image_y, r, g, b));
+                                            self.doc.pixel_info_mean = None;
+                                            self.doc.pixel_info_display = self.doc.display_pixels.as_ref().and_then(|pixels| {
+                                                let [width, height] = pixels.size;
+                                                let x = image_x as usize * width / orig_width as usize;
+                                                let y = image_y as usize * height / orig_height as usize;
+                                                let [r, g, b, _] = pixels.pixels.get(y * width + x)?.to_srgba_unmultiplied();
+                                                Some([r, g, b])
+                                            });
+                                        }
+                                        self.doc.pixel_info_fp = None;
+                                        
+                                        // Determine channel count based on image type
+                                        use image::DynamicImage;
+                                        self.doc.pixel_info_channels = Some(match img {
+                                            DynamicImage::ImageLuma8(_) | DynamicImage::ImageLuma16(_) => 1,
+                                            DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgb16(_) => 3,
+                                            DynamicImage::ImageRgba8(_) | DynamicImage::ImageRgba16(_) => 4,
+                                            _ => 3, // Default to RGB for other types
+                                        });
+                                    }
+                                    self.hover_pos = Some(pointer_pos);
+                                }
+                            } else {
+                                // Clear pixel info when not hovering over image
+                                self.doc.pixel_info = None;
+                                self.doc.pixel_info_fp = None;
+                                self.doc.pixel_info_channels = None;
+                                self.hover_pos = None;
+                            }
+                        } else {
+                            // Clear pixel info when no pointer interaction
+                            self.doc.pixel_info = None;
+                            self.doc.pixel_info_fp = None;
+                            self.doc.pixel_info_channels = None;
+                            self.hover_pos = None;
+                        }
+                    }
+                    
+                    // Only draw the image if it intersects with the visible area
+                    if image_rect.intersects(available_rect) {
+                        self.paint_background(ui.painter(), image_rect.intersect(available_rect), image_rect.min);
+                        ui.painter().add(view.texture_mesh(texture_id, egui::Color32::WHITE));
+                        // The part of a large TIFF read at the resolution of the view, over its overview
+                        if let (Some((detail, Some(detail_texture))), Some((full_width, _))) = (&self.doc.detail, self.doc.reduced_from) {
+                            let factor = full_width as f32 / orig_width as f32;
+                            let detail_size = egui::vec2(detail.image.width() as f32, detail.image.height() as f32);
+                            let center = (egui::pos2(detail.region[0] as f32, detail.region[1] as f32) + detail_size * detail.step as f32 / 2.0) / factor;
+                            let detail_view = ViewMapping { center: view.to_screen(center), scale: final_scale * detail.step as f32 / factor, size: detail_size, ..view };
+                            ui.painter().with_clip_rect(image_rect.intersect(available_rect))
+                                .add(detail_view.texture_mesh(detail_texture.id(), egui::Color32::WHITE));
+                        }
+                    }
+                    self.wipe_handle_rect = None;
+                    if let Some(compare) = self.doc.compare.as_mut().filter(|compare| compare.shows_both()) {
+                        let shown = image_rect.intersect(available_rect);
+                        if let (Some(texture_b), true) = (&compare.texture_b, shown.is_positive()) {
+                            // B beyond the wipe divider or in every other square, squares start at the image corner
+                            let mut mesh = egui::Mesh::with_texture(texture_b.id());
+                            if compare.view == CompareView::Wipe {
+                                let mut b_rect = image_rect;
+                                if compare.wipe_horizontal {
+                                    b_rect.min.y = image_rect.top() + image_rect.height() * compare.wipe;
+                                } else {
+                                    b_rect.min.x = image_rect.left() + image_rect.width() * compare.wipe;
+                                }
+                                view.add_screen_rect(&mut mesh, b_rect.intersect(shown), egui::Color32::WHITE);
+                            } else {
+                                let size = compare.checker_size.max(8.0);
+                                let first = ((shown.min - image_rect.min) / size).floor();
+                                let last = ((shown.max - image_rect.min) / size).ceil();
+                                for row in first.y as i64..last.y as i64 {
+                                    for column in first.x as i64..last.x as i64 {
+                                        if (row + column) % 2 == 1 {
+                                            let cell = egui::Rect::from_min_size(image_rect.min + egui::vec2(column as f32, row as f32) * size, egui::Vec2::splat(size));
+                                            view.add_screen_rect(&mut mesh, cell.intersect(shown), egui::Color32::WHITE);
+                                        }
+                                    }
+                                }
+                            }
+                            ui.painter().add(mesh);
+                        }
+                        if compare.view == CompareView::Wipe && shown.is_positive() {
+                            // Divider line with a grip that drags it
+                            let (start, end, grip) = if compare.wipe_horizontal {
+                                let y = image_rect.top() + image_rect.height() * compare.wipe;
+                                (egui::pos2(shown.left(), y), egui::pos2(shown.right(), y), egui::pos2(shown.center().x, y))
+                            } else {
+                                let x = image_rect.left() + image_rect.width() * compare.wipe;
+                                (egui::pos2(x, shown.top()), egui::pos2(x, shown.bottom()), egui::pos2(x, shown.center().y))
+                            };
+                            let handle = egui::Rect::from_two_pos(start, end).expand(WIPE_GRAB_DISTANCE);
+                            self.wipe_handle_rect = Some(handle);
+                            let painter = ui.painter().with_clip_rect(shown);
+                            painter.line_segment([start, end], egui::Stroke::new(2.0, egui::Color32::WHITE));
+                            painter.circle(grip, 7.0, egui::Color32::WHITE, egui::Stroke::new(1.0, egui::Color32::from_gray(60)));
+                            let (hovered, dragging, pointer) = ctx.input(|i| (
+                                i.pointer.hover_pos().is_some_and(|pos| handle.contains(pos)),
+                                i.pointer.primary_down() && i.pointer.press_origin().is_some_and(|pos| handle.contains(pos)),
+                                i.pointer.interact_pos(),
+                            ));
+                            if hovered || dragging {
+                                ctx.set_cursor_icon(if compare.wipe_horizontal { egui::CursorIcon::ResizeVertical } else { egui::CursorIcon::ResizeHorizontal });
+                            }
+                            if let Some(pos) = pointer.filter(|_| dragging) {
+                                compare.wipe = if compare.wipe_horizontal {
+                                    (pos.y - image_rect.top()) / image_rect.height()
+                                } else {
+                                    (pos.x - image_rect.left()) / image_rect.width()
+                                }.clamp(0.0, 1.0);
+                            }
+                        }
+                    }
+                    
+                    // Screen position to pixel coordinates, clamped to the image
+                    let to_pixel = |pos: egui::Pos2| {
+                        let point = view.to_image(pos);
+                        (
+                            (point.x.max(0.0) as u32).min(orig_width),
+                            (point.y.max(0.0) as u32).min(orig_height),
+                        )
+                    };
+                    
+                    // Part of the image on screen, for the visible area histogram scope
+                    let visible = image_rect.intersect(available_rect);
+                    self.doc.visible_region = visible.is_positive().then(|| {
+                        let visible = view.rect_to_image(visible);
+                        let (x0, y0) = to_pixel(visible.min);
+                        let (x1, y1) = to_pixel(visible.max);
+                        [x0, y0, (x1 + 1).min(orig_width), (y1 + 1).min(orig_height)]
+                    });
+                    if let Some(visible) = self.doc.visible_region.filter(|_| final_scale >= PIXEL_GRID_MIN_SCALE) {
+                        self.paint_pixel_grid(ui.painter(), &view, visible);
+                    }
+                    // Masks of the full image also fit the overview of a large TIFF
+                    if let Some(overlay) = &self.mask_overlay {
+                        let fits = overlay.mask.as_ref().is_some_and(|mask| Some((mask.width, mask.height)) == self.full_image_size());
+                        if let Some(texture) = overlay.texture.as_ref().filter(|_| fits) {
+                            ui.painter().add(view.texture_mesh(texture.id(), overlay.tint()));
+                        }
+                    }
+                    if let Some((map, texture)) = self.doc.clipping.as_ref().filter(|_| self.show_clipping) {
+                        ui.painter().add(view.texture_mesh(texture.id(), egui::Color32::WHITE));
+                        // Pixels smaller than a few points are marked by squares to stay visible
+                        if let Some(markers) = map.markers.as_ref().filter(|_| final_scale < CLIPPING_MARKER_SIZE) {
+                            for &(x, y, flag) in markers {
+                                let center = view.to_screen(egui::pos2(x as f32 + 0.5, y as f32 + 0.5));
+                                if available_rect.contains(center) {
+                                    let marker = egui::Rect::from_center_size(center, egui::Vec2::splat(CLIPPING_MARKER_SIZE));
+                                    ui.painter().rect_filled(marker, egui::CornerRadius::ZERO, flag.color());
+                                }
+                            }
+                        }
+                    }
+                    
+                    // Right-click menu with clipboard actions, Shift + drag draws the ROI
+                    let image_response = ui.interact(image_rect, ui.id().with("image_area"), egui::Sense::click_and_drag());
+                    // Clicking with the pixel tool picks the color and adds it to the session palette
+                    if self.show_pixel_tool && image_response.clicked() {
+                        if let Some(pos) = image_response.interact_pointer_pos() {
+                            let (x, y) = to_pixel(pos);
+                            let (x, y) = (x.min(orig_width - 1), y.min(orig_height - 1));
+                            // Mean color of the sampling square
+                            let (mut sum, mut count) = ([0u32; 3], 0);
+                            for (x, y) in sample_window(x, y, self.config.pixel_sample.radius(), orig_width, orig_height) {
+                                let [r, g, b, _] = img.get_pixel(x, y).0;
+                                for (total, value) in sum.iter_mut().zip([r, g, b]) {
+                                    *total += value as u32;
+                                }
+                                count += 1;
+                            }
+                            let [r, g, b] = sum.map(|total| ((total as f32 / count as f32).round()) as u8);
+                            self.picked_color = Some(((x, y), [r, g, b]));
+                            if !self.palette.contains(&[r, g, b]) {
+                                self.palette.push([r, g, b]);
+                            }
+                        }
+                    }
+                    // Double-click or double-tap switches between fit and 100%, unless a tool uses clicks
+                    if image_response.double_clicked() && !self.show_pixel_tool && self.measure.is_none() && self.annotate.is_none() && self.crop.is_none() {
+                        toggle_fit = true;
+                    }
+                    let mut annotations_changed = false;
+                    if self.doc.full_decode.is_some() {
+                        // Regions, measurements and shapes refer to the full resolution, not the preview
+                    } else if image_response.drag_started() && ui.input(|i| i.modifiers.shift) {
+                        self.roi_drag_start = image_response.interact_pointer_pos().map(to_pixel);
+                    } else if image_response.drag_started() && ui.input(|i| i.modifiers.command) {
+                        self.zoom_drag = image_response.interact_pointer_pos().map(|pos| {
+                            let (x, y) = to_pixel(pos);
+                            [x, y, x, y]
+                        });
+                    } else if let Some(crop) = &mut self.crop {
+                        if image_response.drag_started() {
+                            crop.drag_start = image_response.interact_pointer_pos().map(to_pixel);
+                        }
+                        if let (Some(start), Some(pos)) = (crop.drag_start, image_response.interact_pointer_pos()) {
+                            let rect = crop.drag_rect(start, to_pixel(pos), orig_width, orig_height);
+                            crop.rect = (rect[2] > rect[0] && rect[3] > rect[1]).then_some(rect);
+                        }
+                        if image_response.drag_stopped() {
+                            crop.drag_start = None;
+                        }
+                    } else if let Some(measure) = self.measure.as_mut().filter(|_| image_response.clicked()) {
+                        if let Some(pos) = image_response.interact_pointer_pos() {
+                            let point = view.to_image(pos);
+                            measure.click([point.x, point.y]);
+                        }
+                    } else if let Some(editor) = &mut self.annotate {
+                        // Not clamped, so shapes can start at the border
+                        let point = image_response.interact_pointer_pos().map(|pos| {
+                            let point = view.to_image(pos);
+                            [point.x, point.y]
+                        });
+                        if let Some(point) = point {
+                            match editor.tool {
+                                AnnotationTool::Select => {
+                                    if image_response.clicked() || image_response.drag_started() {
+                                        // Topmost annotation under the pointer
+                                        editor.selected = self.doc.annotations.iter().rposition(|a| a.hit(point, 6.0 / final_scale));
+                                        editor.move_from = editor.selected.filter(|_| image_response.drag_started()).map(|_| point);
+                                    }
+                                    if let (Some(index), Some(from)) = (editor.selected, editor.move_from) {
+                                        if let Some(annotation) = self.doc.annotations.get_mut(index) {
+                                            annotation.translate([point[0] - from[0], point[1] - from[1]]);
+                                        }
+                                        editor.move_from = Some(point);
+                                    }
+                                }
+                                AnnotationTool::Text => {
+                                    if image_response.clicked() {
+                                        editor.drawing = editor.start(point);
+                                    }
+                                }
+                                _ => {
+                                    if image_response.drag_started() {
+                                        editor.drawing = editor.start(point);
+                                    }
+                                    editor.extend(point, 2.0 / final_scale);
+                                }
+                            }
+                        }
+                        if image_response.drag_stopped() && editor.move_from.take().is_some() {
+                            annotations_changed = true;
+                        }
+                        if image_response.drag_stopped() || image_response.clicked() {
+                            if let Some(annotation) = editor.finish() {
+                                self.doc.annotations.push(annotation);
+                                editor.selected = Some(self.doc.annotations.len() - 1);
+                                annotations_changed = true;
+                            }
+                        }
+                    }
+                    if let (Some((start_x, start_y)), Some(pos)) = (self.roi_drag_start, image_response.interact_pointer_pos()) {
+                        let (x, y) = to_pixel(pos);
+                        let (x0, x1) = (start_x.min(x), start_x.max(x));
+                        let (y0, y1) = (start_y.min(y), start_y.max(y));
+                        self.doc.roi = (x1 > x0 && y1 > y0).then_some([x0, y0, x1, y1]);
+                    }
+                    if image_response.drag_stopped() && self.roi_drag_start.take().is_some() {
+                        info!("ROI set to {:?}", self.doc.roi);
+                    }
+                    
+                    // Ctrl + drag: zoom to the dragged rectangle once released
+                    if let (Some([start_x, start_y, ..]), Some(pos)) = (self.zoom_drag, image_response.interact_pointer_pos()) {
+                        let (x, y) = to_pixel(pos);
+                        let rect = [start_x.min(x), start_y.min(y), start_x.max(x), start_y.max(y)];
+                        self.zoom_drag = Some([start_x, start_y, x, y]);
+                        let screen_rect = view.rect_to_screen(egui::pos2(rect[0] as f32, rect[1] as f32), egui::pos2(rect[2] as f32, rect[3] as f32));
+                        ui.painter().rect_stroke(screen_rect, egui::CornerRadius::ZERO, egui::Stroke::new(1.0, egui::Color32::WHITE), egui::StrokeKind::Outside);
+                        ui.painter().rect_filled(screen_rect, egui::CornerRadius::ZERO, egui::Color32::from_white_alpha(30));
+                    }
+                    if image_response.drag_stopped() {
+                        if let Some([start_x, start_y, x, y]) = self.zoom_drag.take() {
+                            if x.abs_diff(start_x) > 1 && y.abs_diff(start_y) > 1 {
+                                zoom_to = Some([start_x.min(x), start_y.min(y), start_x.max(x), start_y.max(y)]);
+                            }
+                        }
+                    }
                     
-                    // Calculate display size based on original image dimensions
-                    let (orig_width, orig_height) = img.dimensions();
-                    let display_size = egui::vec2(
-                        orig_width as f32 * final_scale,
-                        orig_height as f32 * final_scale
-                    );
+                    if let Some((crop, [x0, y0, x1, y1])) = self.crop.as_ref().and_then(|c| Some((c, c.rect?))) {
+                        let crop_rect = view.rect_to_screen(egui::pos2(x0 as f32, y0 as f32), egui::pos2(x1 as f32, y1 as f32));
+                        // Darken everything outside the crop
+                        let shade = egui::Color32::from_black_alpha(140);
+                        let painter = ui.painter();
+                        painter.rect_filled(egui::Rect::from_min_max(image_rect.min, egui::pos2(image_rect.max.x, crop_rect.min.y)), 0.0, shade);
+                        painter.rect_filled(egui::Rect::from_min_max(egui::pos2(image_rect.min.x, crop_rect.max.y), image_rect.max), 0.0, shade);
+                        painter.rect_filled(egui::Rect::from_min_max(egui::pos2(image_rect.min.x, crop_rect.min.y), egui::pos2(crop_rect.min.x, crop_rect.max.y)), 0.0, shade);
+                        painter.rect_filled(egui::Rect::from_min_max(egui::pos2(crop_rect.max.x, crop_rect.min.y), egui::pos2(image_rect.max.x, crop_rect.max.y)), 0.0, shade);
+                        
+                        if crop.show_thirds {
+                            let guide = egui::Stroke::new(1.0, egui::Color32::from_white_alpha(140));
+                            for third in [1.0 / 3.0, 2.0 / 3.0] {
+                                let x = crop_rect.min.x + crop_rect.width() * third;
+                                let y = crop_rect.min.y + crop_rect.height() * third;
+                                painter.line_segment([egui::pos2(x, crop_rect.min.y), egui::pos2(x, crop_rect.max.y)], guide);
+                                painter.line_segment([egui::pos2(crop_rect.min.x, y), egui::pos2(crop_rect.max.x, y)], guide);
+                            }
+                        }
+                        painter.rect_stroke(crop_rect, egui::CornerRadius::ZERO, egui::Stroke::new(1.5, egui::Color32::WHITE), egui::StrokeKind::Outside);
+                        painter.text(
+                            crop_rect.left_bottom() + egui::vec2(0.0, 2.0),
+                            egui::Align2::LEFT_TOP,
+                            format!("{}×{}", x1 - x0, y1 - y0),
+                            egui::FontId::proportional(12.0),
+                            egui::Color32::WHITE,
+                        );
+                    }
                     
-                    // Center the image in the available space
-                    let available_rect = ui.available_rect_before_wrap();
-                    let center_x = available_rect.center().x;
-                    let center_y = available_rect.center().y;
+                    if let Some([x0, y0, x1, y1]) = self.doc.roi {
+                        let roi_rect = view.rect_to_screen(egui::pos2(x0 as f32, y0 as f32), egui::pos2(x1 as f32, y1 as f32));
+                        ui.painter().rect_stroke(
+                            roi_rect,
+                            egui::CornerRadius::ZERO,
+                            egui::Stroke::new(1.5, egui::Color32::YELLOW),
+                            egui::StrokeKind::Outside,
+                        );
+                        ui.painter().text(
+                            roi_rect.left_top() - egui::vec2(0.0, 2.0),
+                            egui::Align2::LEFT_BOTTOM,
+                            format!("{}×{}", x1 - x0, y1 - y0),
+                            egui::FontId::proportional(12.0),
+                            egui::Color32::YELLOW,
+                        );
+                    }
                     
-                    // Calculate position to center the image
-                    let image_pos = egui::pos2(
-                        center_x - display_size.x / 2.0 + self.offset.x,
-                        center_y - display_size.y / 2.0 + self.offset.y
-                    );
+                    // Pinned probes, numbered like the rows of the probe table
+                    for (index, probe) in self.doc.probes.iter().enumerate() {
+                        let center = view.to_screen(egui::pos2(probe.x as f32 + 0.5, probe.y as f32 + 0.5));
+                        if !available_rect.contains(center) {
+                            continue;
+                        }
+                        let painter = ui.painter();
+                        painter.circle_stroke(center, 5.0, egui::Stroke::new(3.0, egui::Color32::BLACK));
+                        painter.circle_stroke(center, 5.0, egui::Stroke::new(1.5, egui::Color32::from_rgb(0, 200, 255)));
+                        painter.text(
+                            center + egui::vec2(7.0, -7.0),
+                            egui::Align2::LEFT_BOTTOM,
+                            (index + 1).to_string(),
+                            egui::FontId::proportional(12.0),
+                            egui::Color32::from_rgb(0, 200, 255),
+                        );
+                    }
                     
-                    let image_rect = egui::Rect::from_min_size(image_pos, display_size);
+                    if let Some(measure) = &self.measure {
+                        self.paint_measurements(ui, measure, &view);
+                    }
                     
-                    // Handle pixel tool hovering
-                    if self.show_pixel_tool {
-                        if let Some(pointer_pos) = ui.input(|i| i.pointer.interact_pos()) {
-                            if image_rect.contains(pointer_pos) {
-                                // Convert screen coordinates to image coordinates
-                                let relative_pos = pointer_pos - image_rect.min;
-                                let image_x = (relative_pos.x / final_scale) as u32;
-                                let image_y = (relative_pos.y / final_scale) as u32;
-                                
-                                // Sample pixel from original image
-                                if image_x < orig_width && image_y < orig_height {
-                                    // Check if we have original floating point data
-                                    if let (Some(fp_data), Some((fp_width, _fp_height)), Some(fp_channels)) = (
-                                        &self.original_fp_data,
-                                        self.original_fp_dimensions,
-                                        self.original_fp_channels
-                                    ) {
-                                        // Sample from original floating point data
-                                        let pixel_idx = (image_y * fp_width + image_x) as usize;
-                                        match fp_channels {
-                                            1 => {
-                                                // Grayscale
-                                                if pixel_idx < fp_data.len() {
-                                                    let gray = fp_data[pixel_idx];
-                                                    self.pixel_info_fp = Some((image_x, image_y, gray, gray, gray));
-                                                    self.pixel_info_channels = Some(1);
-                                                }
-                                            }
-                                            3 => {
-                                                // RGB
-                                                let base_idx = pixel_idx * 3;
-                                                if base_idx + 2 < fp_data.len() {
-                                                    let r = fp_data[base_idx];
-                                                    let g = fp_data[base_idx + 1];
-                                                    let b = fp_data[base_idx + 2];
-                                                    self.pixel_info_fp = Some((image_x, image_y, r, g, b));
-                                                    self.pixel_info_channels = Some(3);
-                                                }
-                                            }
-                                            4 => {
-                                                // RGBA - use RGB channels
-                                                let base_idx = pixel_idx * 4;
-                                                if base_idx + 2 < fp_data.len() {
-                                                    let r = fp_data[base_idx];
-                                                    let g = fp_data[base_idx + 1];
-                                                    let b = fp_data[base_idx + 2];
-                                                    self.pixel_info_fp = Some((image_x, image_y, r, g, b));
-                                                    self.pixel_info_channels = Some(4);
-                                                }
-                                            }
-                                            _ => {
-                                                // Fallback to normalized values
-                                                let pixel = img.get_pixel(image_x, image_y);
-                                                let rgba = pixel.0;
-                                                self.pixel_info = Some((image_x, image_y, rgba[0], rgba[1], rgba[2]));
-                                                self.pixel_info_fp = None;
-                                                self.pixel_info_channels = None;
-                                            }
-                                        }
-                                    } else {
-                                        // Use normalized values for non-floating point images
-                                        let pixel = img.get_pixel(image_x, image_y);
-                                        let rgba = pixel.0;
-                                        self.pixel_info = Some((image_x, image_y, rgba[0], rgba[1], rgba[2]));
-                                        self.pixel_info_fp = None;
-                                        
-                                        // Determine channel count based on image type
-                                        use image::DynamicImage;
-                                        self.pixel_info_channels = Some(match img {
-                                            DynamicImage::ImageLuma8(_) | DynamicImage::ImageLuma16(_) => 1,
-                                            DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgb16(_) => 3,
-                                            DynamicImage::ImageRgba8(_) | DynamicImage::ImageRgba16(_) => 4,
-                                            _ => 3, // Default to RGB for other types
-                                        });
-                                    }
-                                    self.hover_pos = Some(pointer_pos);
+                    // Annotations and the one being drawn, the selected one in a dashed frame
+                    let drawing = self.annotate.as_ref().and_then(|editor| editor.drawing.as_ref());
+                    for annotation in self.doc.annotations.iter().chain(drawing) {
+                        annotation.paint(ui.painter(), &view);
+                    }
+                    let selected = self.annotate.as_ref().and_then(|editor| editor.selected);
+                    if let Some(annotation) = selected.and_then(|index| self.doc.annotations.get(index)) {
+                        let (min, max) = annotation.bounds();
+                        let frame = view.rect_to_screen(egui::pos2(min[0], min[1]), egui::pos2(max[0], max[1])).expand(4.0 + annotation.width * final_scale / 2.0);
+                        let corners = [frame.left_top(), frame.right_top(), frame.right_bottom(), frame.left_bottom(), frame.left_top()];
+                        ui.painter().extend(egui::Shape::dashed_line(&corners, egui::Stroke::new(1.0, egui::Color32::WHITE), 4.0, 3.0));
+                    }
+                    if annotations_changed {
+                        self.save_annotations();
+                    }
+                    
+                    // Loupe centered on the cursor, magnifying the image under it
+                    let hover = ui.input(|i| i.pointer.hover_pos())
+                        .filter(|pos| image_rect.contains(*pos) && available_rect.contains(*pos))
+                        .filter(|pos| !self.minimap_rect.is_some_and(|minimap| minimap.contains(*pos)));
+                    if let (true, Some(texture), Some(pos)) = (self.show_loupe, self.loupe_texture_id(), hover) {
+                        self.loupe.paint(ui.painter(), texture, &view, pos);
+                    }
+                    
+                    if image_response.secondary_clicked() {
+                        // Snapshot the hovered pixel, the pointer leaves it while the menu is open
+                        self.context_pixel_text = if self.show_pixel_tool { self.pixel_info_text() } else { None };
+                        self.context_pixel = image_response.interact_pointer_pos()
+                            .filter(|pos| image_rect.contains(*pos))
+                            .map(|pos| {
+                                let (x, y) = to_pixel(pos);
+                                (x.min(orig_width - 1), y.min(orig_height - 1))
+                            });
+                    }
+                    image_response.context_menu(|ui| {
+                        if let Some(text) = &self.context_pixel_text {
+                            if ui.button(format!("Copy {}", text)).clicked() {
+                                ui.ctx().copy_text(text.clone());
+                                ui.close_menu();
+                            }
+                        }
+                        if ui.button("Copy image (Ctrl+C)").clicked() {
+                            self.copy_image_to_clipboard(ui.ctx());
+                            ui.close_menu();
+                        }
+                        ui.menu_button("Set as desktop background", |ui| {
+                            for mode in WallpaperMode::ALL {
+                                if ui.button(mode.as_str()).clicked() {
+                                    self.set_wallpaper(mode);
+                                    ui.close_menu();
                                 }
-                            } else {
-                                // Clear pixel info when not hovering over image
-                                self.pixel_info = None;
-                                self.pixel_info_fp = None;
-                                self.pixel_info_channels = None;
-                                self.hover_pos = None;
                             }
-                        } else {
-                            // Clear pixel info when no pointer interaction
-                            self.pixel_info = None;
-                            self.pixel_info_fp = None;
-                            self.pixel_info_channels = None;
-                            self.hover_pos = None;
+                        });
+                        if let Some((x, y)) = self.context_pixel {
+                            if ui.button(format!("Pin probe at ({}, {})", x, y)).clicked() {
+                                self.doc.probes.push(Probe::new(x, y));
+                                self.doc.probes_dirty = true;
+                                self.show_probes = true;
+                                ui.close_menu();
+                            }
+                        }
+                        if !self.doc.probes.is_empty() && ui.button("Show probe table").clicked() {
+                            self.show_probes = true;
+                            ui.close_menu();
+                        }
+                        if let Some(roi) = self.doc.roi {
+                            let [x0, y0, x1, y1] = roi;
+                            let small = (x1 - x0) as usize * (y1 - y0) as usize <= MAX_COPY_REGION_PIXELS;
+                            let too_large = format!("The ROI is larger than {} pixels", MAX_COPY_REGION_PIXELS);
+                            if ui.add_enabled(small, egui::Button::new("Copy ROI values as text"))
+                                .on_disabled_hover_text(&too_large)
+                                .clicked()
+                            {
+                                if let Some(region) = self.pixel_region(roi) {
+                                    ui.ctx().copy_text(region.to_text());
+                                }
+                                ui.close_menu();
+                            }
+                            if ui.add_enabled(small, egui::Button::new("Copy ROI values as NumPy array"))
+                                .on_disabled_hover_text(&too_large)
+                                .clicked()
+                            {
+                                if let Some(region) = self.pixel_region(roi) {
+                                    ui.ctx().copy_text(region.to_numpy());
+                                }
+                                ui.close_menu();
+                            }
+                        }
+                        if self.doc.roi.is_some() && ui.button("Clear ROI").clicked() {
+                            self.doc.roi = None;
+                            ui.close_menu();
+                        }
+                        ui.separator();
+                        if ui.button("Move to trash (Del)").clicked() {
+                            self.request_delete();
+                            ui.close_menu();
+                        }
+                        if ui.button("Move to…").clicked() {
+                            self.move_files();
+                            ui.close_menu();
+                        }
+                        if ui.button("Rename… (F2)").clicked() {
+                            self.start_rename();
+                            ui.close_menu();
                         }
+                        if ui.button("Show in folder").clicked() {
+                            self.show_in_folder();
+                            ui.close_menu();
+                        }
+                        if ui.button("Open in external editor (E)").clicked() {
+                            self.open_in_editor();
+                            ui.close_menu();
+                        }
+                    });
+                    
+                    // Minimap with the visible part of the image, dragging in it pans the view
+                    self.minimap_rect = None;
+                    if !available_rect.contains_rect(image_rect) {
+                        let minimap_scale = MINIMAP_SIZE / display_size.x.max(display_size.y);
+                        let minimap_size = display_size * minimap_scale;
+                        let minimap_rect = egui::Rect::from_min_size(
+                            egui::pos2(available_rect.max.x - minimap_size.x - 8.0, available_rect.min.y + 8.0),
+                            minimap_size,
+                        );
+                        let painter = ui.painter();
+                        painter.rect_filled(minimap_rect.expand(2.0), egui::CornerRadius::same(2), egui::Color32::from_black_alpha(180));
+                        let minimap_view = ViewMapping { center: minimap_rect.center(), scale: final_scale * minimap_scale, ..view };
+                        painter.add(minimap_view.texture_mesh(texture_id, egui::Color32::from_white_alpha(200)));
+                        // Visible area in minimap coordinates
+                        let visible_rect = egui::Rect::from_min_max(
+                            minimap_rect.min + (visible.min - image_rect.min) * minimap_scale,
+                            minimap_rect.min + (visible.max - image_rect.min) * minimap_scale,
+                        );
+                        painter.rect_stroke(visible_rect, egui::CornerRadius::ZERO, egui::Stroke::new(1.5, egui::Color32::WHITE), egui::StrokeKind::Outside);
+                        
+                        let minimap_response = ui.interact(minimap_rect, ui.id().with("minimap"), egui::Sense::click_and_drag());
+                        if minimap_response.is_pointer_button_down_on() {
+                            if let Some(pos) = minimap_response.interact_pointer_pos() {
+                                // Center the view on the pointed position
+                                let target = (pos - minimap_rect.min) / minimap_scale;
+                                self.doc.offset = display_size / 2.0 - target;
+                                ctx.request_repaint();
+                            }
+                        }
+                        self.minimap_rect = Some(minimap_rect);
                     }
                     
-                    // Only draw the image if it intersects with the visible area
-                    if image_rect.intersects(available_rect) {
-                        let image = egui::Image::new(texture)
-                            .fit_to_exact_size(display_size);
-                        ui.put(image_rect, image);
+                    // Outline of the square averaged by the pixel tool
+                    let radius = self.config.pixel_sample.radius();
+                    let sampled = self.doc.pixel_info_fp.map(|(x, y, ..)| (x, y)).or(self.doc.pixel_info.map(|(x, y, ..)| (x, y)));
+                    if let (Some((x, y)), Some(_)) = (sampled.filter(|_| radius > 0), self.hover_pos) {
+                        let mut pixels = sample_window(x, y, radius, orig_width, orig_height);
+                        if let (Some((x0, y0)), Some((x1, y1))) = (pixels.next(), pixels.last()) {
+                            let outline = view.rect_to_screen(egui::pos2(x0 as f32, y0 as f32), egui::pos2(x1 as f32 + 1.0, y1 as f32 + 1.0));
+                            ui.painter().rect_stroke(outline, egui::CornerRadius::ZERO, egui::Stroke::new(1.0, egui::Color32::YELLOW), egui::StrokeKind::Outside);
+                        }
                     }
                     
                     // Display hover information near cursor (after image to render on top)
                     if let Some(hover_pos) = self.hover_pos {
                         let text_pos = egui::pos2(hover_pos.x + 2.0, hover_pos.y - 20.0);
-                        let text_content = if let Some((x, y, r, g, b)) = self.pixel_info_fp {
-                            // Show original floating point values
-                            match self.pixel_info_channels {
-                                Some(1) => format!("({}, {}) Gray({:.4})", x, y, r),
-                                _ => format!("({}, {}) RGB({:.4}, {:.4}, {:.4})", x, y, r, g, b),
-                            }
-                        } else if let Some((x, y, r, g, b)) = self.pixel_info {
-                            // Show normalized u8 values
-                            match self.pixel_info_channels {
-                                Some(1) => format!("({}, {}) Gray({})", x, y, r),
-                                _ => format!("({}, {}) RGB({}, {}, {})", x, y, r, g, b),
-                            }
-                        } else {
-                            String::new()
-                        };
+                        let text_content = self.pixel_info_text().unwrap_or_default();
                         
                         if !text_content.is_empty() {
                         
@@ -1587,12 +7988,19 @@ impl eframe::App for ImageViewerApp {
             
         });
         
+        if let Some(rect) = zoom_to {
+            self.zoom_to_rect(rect);
+        }
+        if toggle_fit {
+            self.toggle_fit_actual_size();
+        }
+        
         // Add scale slider in bottom right corner (fixed position)
-        if self.image.is_some() {
+        if self.doc.image.is_some() && !self.quick_look {
             egui::Area::new(egui::Id::new("scale_bar"))
                 .fixed_pos(egui::pos2(
-                    ctx.screen_rect().max.x - 220.0,
-                    ctx.screen_rect().max.y - 40.0
+                    ctx.screen_rect().max.x - 270.0,
+                    ctx.screen_rect().max.y - 40.0 - bottom_panels_height
                 ))
                 .show(ctx, |ui| {
                     egui::Frame::new()
@@ -1601,21 +8009,74 @@ impl eframe::App for ImageViewerApp {
                         .inner_margin(egui::Margin::same(5))
                         .show(ui, |ui| {
                             ui.horizontal(|ui| {
-                                ui.label("Scale:");
-                                if ui.add(egui::Slider::new(&mut self.scale, 0.1..=20.0).show_value(true)).changed() {
-                                    self.texture_needs_update = true;
+                                let keys = &self.config.keybindings;
+                                let fit = ui.small_button("Fit").on_hover_text(format!("Fit to window ({})", keys.get(Action::FitToWindow)));
+                                let fill = ui.small_button("Fill").on_hover_text(format!("Fill the window ({})", keys.get(Action::FillWindow)));
+                                let actual = ui.small_button("1:1").on_hover_text(format!(
+                                    "One image pixel per screen pixel ({}), Ctrl + drag zooms to a selection",
+                                    keys.get(Action::ActualSize)
+                                ));
+                                let zoom_out_hover = format!("Zoom out ({}, Ctrl+-)", keys.get(Action::ZoomOut));
+                                let zoom_in_hover = format!("Zoom in ({}, Ctrl+=)", keys.get(Action::ZoomIn));
+                                if fit.clicked() {
+                                    self.run_action(Action::FitToWindow);
+                                }
+                                if fill.clicked() {
+                                    self.run_action(Action::FillWindow);
+                                }
+                                if actual.clicked() {
+                                    self.run_action(Action::ActualSize);
+                                }
+                                if ui.small_button("−").on_hover_text(zoom_out_hover).clicked() {
+                                    self.run_action(Action::ZoomOut);
+                                }
+                                // Percentage of the image size, editable for an exact zoom
+                                let base_scale = self.doc.base_scale;
+                                let mut percent = base_scale * self.doc.scale * 100.0;
+                                let speed = percent * 0.01;
+                                let zoom = ui.add(
+                                    egui::DragValue::new(&mut percent)
+                                        .range(base_scale * 10.0..=base_scale * 2000.0)
+                                        .speed(speed)
+                                        .max_decimals(1)
+                                        .suffix("%"),
+                                ).on_hover_text("Zoom, click to type an exact value");
+                                if zoom.changed() {
+                                    self.set_zoom(percent / 100.0);
+                                }
+                                if ui.small_button("+").on_hover_text(zoom_in_hover).clicked() {
+                                    self.run_action(Action::ZoomIn);
                                 }
+                                ui.menu_button("⏷", |ui| {
+                                    for preset in ZOOM_PRESETS {
+                                        if ui.button(format!("{}%", preset)).clicked() {
+                                            self.set_zoom(preset as f32 / 100.0);
+                                            ui.close_menu();
+                                        }
+                                    }
+                                }).response.on_hover_text("Zoom presets");
                             });
                         });
                 });
         }
         
         // Show histogram in a separate OS window if enabled
-        if self.show_histogram && self.image.is_some() {
+        if self.show_histogram && self.doc.image.is_some() {
             if let Some(histogram_id) = self.histogram_window_id {
+                // Follow the selected scope, the visible area is only updated once panning stops
+                let (scope, bins, alpha_mode) = self.histogram_shared_data.lock()
+                    .map(|data| (data.scope, data.bins, data.alpha_mode))
+                    .unwrap_or_default();
+                if self.scope_region(scope) != self.doc.histogram_region && !self.dragging && self.roi_drag_start.is_none() {
+                    self.doc.histogram_needs_update = true;
+                }
+                if bins != self.doc.histogram_bins || alpha_mode != self.doc.histogram_alpha_mode {
+                    self.doc.histogram_needs_update = true;
+                }
+                
                 // Calculate histogram if needed
-                if self.histogram_needs_update {
-                    self.calculate_histogram();
+                if self.doc.histogram_needs_update {
+                    self.calculate_histogram(ctx);
                 }
                 
                 // Clone the shared data for the viewport closure
@@ -1625,7 +8086,7 @@ impl eframe::App for ImageViewerApp {
                 ctx.show_viewport_deferred(
                     histogram_id,
                     egui::ViewportBuilder::default()
-                        .with_title("Histogram")
+                        .with_title("Analysis")
                         .with_inner_size([800.0, 500.0])
                         .with_min_inner_size([600.0, 400.0])
                         .with_resizable(true),
@@ -1642,16 +8103,120 @@ impl eframe::App for ImageViewerApp {
                         egui::CentralPanel::default().show(ctx, |ui| {
                             // Access shared data from the separate window
                             if let Ok(mut data) = shared_data.lock() {
-                                if let Some(histograms) = data.histograms.clone() {
-                                    // Handle the rendering with separate scope for mutable borrows
-                                    let mut hover_info = data.hover_info;
-                                    let mut hover_pos = data.hover_pos;
-                                    
-                                    Self::render_histogram_in_viewport(ui, &histograms, &mut hover_info, &mut hover_pos);
+                                ui.horizontal(|ui| {
+                                    ui.selectable_value(&mut data.active_tab, AnalysisTab::Histogram, "Histogram");
+                                    ui.selectable_value(&mut data.active_tab, AnalysisTab::Waveform, "Waveform");
+                                    ui.selectable_value(&mut data.active_tab, AnalysisTab::Vectorscope, "Vectorscope");
                                     
-                                    // Update the shared data
-                                    data.hover_info = hover_info;
-                                    data.hover_pos = hover_pos;
+                                    ui.separator();
+                                    ui.label("Scope:");
+                                    for scope in [HistogramScope::WholeImage, HistogramScope::VisibleArea, HistogramScope::Roi] {
+                                        ui.selectable_value(&mut data.scope, scope, scope.as_str());
+                                    }
+                                    match data.region {
+                                        Some([x0, y0, x1, y1]) => ui.weak(format!("{}×{} at ({}, {})", x1 - x0, y1 - y0, x0, y0)),
+                                        None if data.scope == HistogramScope::Roi => ui.weak("No ROI, Shift + drag on the image to draw one"),
+                                        None => ui.weak("Whole image"),
+                                    };
+                                    if data.gpu_pending {
+                                        ui.spinner();
+                                        ui.weak("Computing the histogram on the GPU…");
+                                    } else if let Some(progress) = data.cpu_progress {
+                                        ui.spinner();
+                                        ui.weak(format!("Computing… {:.0}%", progress * 100.0));
+                                    }
+                                });
+                                ui.separator();
+                                
+                                let data = &mut *data;
+                                match data.active_tab {
+                                    AnalysisTab::Histogram => {
+                                        if let Some(histograms) = data.histograms.clone() {
+                                            // Handle the rendering with separate scope for mutable borrows
+                                            let mut hover_info = data.hover_info;
+                                            let mut hover_pos = data.hover_pos;
+                                            
+                                            ui.horizontal(|ui| {
+                                                ui.checkbox(&mut data.view.log_scale, "Log scale");
+                                                ui.checkbox(&mut data.view.cumulative, "Cumulative");
+                                                ui.separator();
+                                                for (channel, name) in ["Red", "Green", "Blue"].into_iter().enumerate() {
+                                                    let mut visible = !data.view.hidden[channel];
+                                                    if ui.checkbox(&mut visible, name).changed() {
+                                                        data.view.hidden[channel] = !visible;
+                                                    }
+                                                }
+                                                ui.separator();
+                                                ui.label("Bins:");
+                                                egui::ComboBox::from_id_salt("histogram_bins")
+                                                    .selected_text(data.bins.0.to_string())
+                                                    .show_ui(ui, |ui| {
+                                                        for bins in HistogramBins::CHOICES {
+                                                            ui.selectable_value(&mut data.bins, HistogramBins(bins), bins.to_string());
+                                                        }
+                                                    })
+                                                    .response
+                                                    .on_hover_text("Used for 16-bit and floating point data, 8-bit data always has 256 bins");
+                                                ui.separator();
+                                                ui.add_enabled_ui(data.has_alpha, |ui| {
+                                                    ui.label("Alpha:");
+                                                    egui::ComboBox::from_id_salt("histogram_alpha")
+                                                        .selected_text(data.alpha_mode.as_str())
+                                                        .show_ui(ui, |ui| {
+                                                            for mode in [AlphaMode::Include, AlphaMode::ExcludeTransparent, AlphaMode::Weighted] {
+                                                                ui.selectable_value(&mut data.alpha_mode, mode, mode.as_str());
+                                                            }
+                                                        });
+                                                })
+                                                .response
+                                                .on_disabled_hover_text("The image has no alpha channel");
+                                            });
+                                            
+                                            Self::render_histogram_in_viewport(ui, &histograms, &mut hover_info, &mut hover_pos, data.view, data.axis);
+                                            
+                                            // Statistics of the counted pixels
+                                            if let Some(stats) = data.stats {
+                                                let precision = if data.axis.integer { 2 } else { 4 };
+                                                // Grayscale images have the same statistics in every channel
+                                                let gray = stats.iter().all(|c| c.sum == stats[0].sum && c.sum_squares == stats[0].sum_squares);
+                                                let channels = if gray { 1 } else { 3 };
+                                                ui.horizontal(|ui| {
+                                                    for (channel_stats, (name, color)) in stats.iter().take(channels).zip([
+                                                        (if gray { "Value" } else { "R" }, if gray { egui::Color32::LIGHT_GRAY } else { egui::Color32::from_rgb(255, 80, 80) }),
+                                                        ("G", egui::Color32::from_rgb(80, 255, 80)),
+                                                        ("B", egui::Color32::from_rgb(80, 80, 255)),
+                                                    ]) {
+                                                        if channel_stats.weight <= 0.0 {
+                                                            continue;
+                                                        }
+                                                        ui.colored_label(color, format!(
+                                                            "{}: {:.*} ± {:.*}  [{} … {}]",
+                                                            name,
+                                                            precision, channel_stats.mean(),
+                                                            precision, channel_stats.std_dev(),
+                                                            data.axis.format(channel_stats.min),
+                                                            data.axis.format(channel_stats.max),
+                                                        ));
+                                                        ui.add_space(8.0);
+                                                    }
+                                                });
+                                            }
+                                            
+                                            // Update the shared data
+                                            data.hover_info = hover_info;
+                                            data.hover_pos = hover_pos;
+                                        }
+                                    }
+                                    AnalysisTab::Waveform => {
+                                        if let Some((columns, counts)) = &data.waveform {
+                                            Self::render_waveform_in_viewport(ui, *columns, counts, &mut data.waveform_texture);
+                                        }
+                                    }
+                                    AnalysisTab::Vectorscope => {
+                                        if let Some(counts) = &data.vectorscope {
+                                            Self::render_vectorscope_in_viewport(ui, counts, &mut data.vectorscope_texture);
+                                        }
+                                    }
                                 }
                             }
                         });
@@ -1663,6 +8228,99 @@ impl eframe::App for ImageViewerApp {
             self.histogram_window_id = None;
         }
         
+        self.poll_gpu_histogram(ctx);
+        
+        if self.show_settings {
+            self.render_settings_window(ctx);
+        }
+        
+        if self.show_orientation_save {
+            self.render_orientation_save_window(ctx);
+        }
+        
+        if !self.pending_delete.is_empty() {
+            self.render_delete_window(ctx);
+        }
+        
+        if self.load_error.is_some() {
+            self.render_load_error_window(ctx);
+        }
+        
+        if self.rename.is_some() {
+            self.render_rename_window(ctx);
+        }
+        
+        if self.show_fft {
+            self.render_fft_window(ctx);
+        }
+        if self.show_fft_export {
+            self.render_fft_export_window(ctx);
+        }
+        
+        if self.show_colormap_editor {
+            self.render_colormap_editor(ctx);
+        }
+        
+        if self.show_probes {
+            self.render_probe_window(ctx);
+        }
+        
+        if self.show_adjustments {
+            self.render_adjustments_window(ctx);
+        }
+        
+        if self.show_pipeline {
+            self.render_pipeline_window(ctx);
+        }
+        
+        if self.show_integrity {
+            self.render_integrity_window(ctx);
+        }
+        
+        if self.show_video_export {
+            self.render_video_export_window(ctx);
+        }
+        
+        if self.show_batch_convert {
+            self.render_batch_convert_window(ctx);
+        }
+        
+        if self.crop.is_some() {
+            self.render_crop_window(ctx);
+        }
+        
+        if self.resize.is_some() {
+            self.render_resize_window(ctx);
+        }
+        
+        if self.annotate.is_some() {
+            self.render_annotation_window(ctx);
+        }
+        
+        if self.measure.is_some() {
+            self.render_measure_window(ctx);
+        }
+        
+        if self.threshold.is_some() {
+            self.render_threshold_window(ctx);
+        }
+        
+        if self.mask_overlay.is_some() {
+            self.render_mask_window(ctx);
+        }
+        
+        if self.expression.is_some() {
+            self.render_expression_window(ctx);
+        }
+        
+        if self.show_info {
+            self.render_info_window(ctx);
+        }
+        self.render_color_picker_window(ctx);
+        
+        self.render_viewer_windows(ctx);
+        self.update_window_title(ctx);
+        
         // Check if histogram window was closed externally
         if let Ok(mut data) = self.histogram_shared_data.lock() {
             if data.close_requested {
@@ -1672,7 +8330,114 @@ impl eframe::App for ImageViewerApp {
             }
         }
     }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        // Quick look previews are not sessions worth restoring
+        if self.quick_look {
+            return;
+        }
+        // Remember the open image for the "Open last session" startup option
+        self.config.last_session = self.doc.image_path.clone();
+        if let Err(e) = self.config.save() {
+            error!("Failed to save config on exit: {}", e);
+        }
+    }
+}
+
+/// Histogram count, weighted counts keep one decimal
+fn format_count(count: f32) -> String {
+    if count.fract() == 0.0 {
+        format!("{}", count as u64)
+    } else {
+        format!("{:.1}", count)
+    }
+}
+
+/// Byte count with a binary unit, e.g. `3.4 MB`
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Draw a horizontal preview of a colormap
+fn paint_gradient(ui: &mut egui::Ui, cmap: &Colormap, size: egui::Vec2) {
+    const SEGMENTS: usize = 64;
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let segment_width = rect.width() / SEGMENTS as f32;
+    for i in 0..SEGMENTS {
+        let [r, g, b] = cmap.sample((i as f32 + 0.5) / SEGMENTS as f32);
+        let min = egui::pos2(rect.min.x + i as f32 * segment_width, rect.min.y);
+        let segment = egui::Rect::from_min_size(min, egui::vec2(segment_width + 0.5, rect.height()));
+        ui.painter().rect_filled(segment, 0.0, egui::Color32::from_rgb(r, g, b));
+    }
+}
+
+/// TIFF samples as f32 with the largest value of integer types, which scales their alpha to 0-1.
+/// 64-bit values beyond the f32 precision are rounded.
+fn tiff_samples_f32(data: tiff::decoder::DecodingResult) -> (Vec<f32>, Option<f32>) {
+    use tiff::decoder::DecodingResult;
+    match data {
+        DecodingResult::F32(data) => (data, None),
+        DecodingResult::F64(data) => (data.into_iter().map(|v| v as f32).collect(), None),
+        DecodingResult::U8(data) => (data.into_iter().map(f32::from).collect(), Some(u8::MAX as f32)),
+        DecodingResult::U16(data) => (data.into_iter().map(f32::from).collect(), Some(u16::MAX as f32)),
+        DecodingResult::U32(data) => (data.into_iter().map(|v| v as f32).collect(), Some(u32::MAX as f32)),
+        DecodingResult::U64(data) => (data.into_iter().map(|v| v as f32).collect(), Some(u64::MAX as f32)),
+        DecodingResult::I8(data) => (data.into_iter().map(f32::from).collect(), Some(i8::MAX as f32)),
+        DecodingResult::I16(data) => (data.into_iter().map(f32::from).collect(), Some(i16::MAX as f32)),
+        DecodingResult::I32(data) => (data.into_iter().map(|v| v as f32).collect(), Some(i32::MAX as f32)),
+        DecodingResult::I64(data) => (data.into_iter().map(|v| v as f32).collect(), Some(i64::MAX as f32)),
+    }
+}
+
+/// Decode an image on a worker thread (thumbnails, video frames), with the same fallbacks as the main loader
+fn load_image_source(path: &Path) -> anyhow::Result<DynamicImage> {
+    ImageViewerApp::load_image_with_fallback(path).map(|(img, ..)| img)
+}
+
+/// Batch conversions also develop camera RAW files
+fn load_batch_source(path: &Path) -> anyhow::Result<DynamicImage> {
+    if raw::is_raw_file(path) {
+        return ImageViewerApp::load_raw(path).map(|((img, ..), _)| img);
+    }
+    load_image_source(path)
+}
+
+/// Pixels of the integrity hashes: the floating point data of HDR, RAW and float TIFF files,
+/// else the decoded image with its EXIF orientation applied
+fn load_decoded_pixels(path: &Path) -> anyhow::Result<DecodedPixels> {
+    if let Some((width, height)) = large_image::reduced_from(path) {
+        anyhow::bail!("Too large to decode at full resolution ({}×{})", width, height);
+    }
+    let (img, _, _, fp_data, fp_dimensions, fp_channels) = ImageViewerApp::load_image_with_fallback(path)?;
+    Ok(match (fp_data, fp_dimensions, fp_channels) {
+        (Some(data), Some((width, height)), Some(channels)) => DecodedPixels { width, height, channels, samples: Samples::F32(data) },
+        _ => DecodedPixels::from_image(img),
+    })
+}
+
+/// Filmstrip thumbnails come from the embedded preview when the file has one
+fn load_thumbnail_source(path: &Path) -> anyhow::Result<DynamicImage> {
+    match embedded_preview::load_embedded_preview(path, THUMBNAIL_SIZE) {
+        Ok(Some(preview)) => Ok(preview),
+        Ok(None) => load_image_source(path),
+        Err(e) => {
+            warn!("Reading the embedded preview of {:?} failed: {}", path, e);
+            load_image_source(path)
+        }
+    }
 }
+
 //TODO: Add a way to save the image
 fn main() -> Result<(), eframe::Error> {
     let icon_data = from_png_bytes(ICON).unwrap();
@@ -1688,23 +8453,95 @@ fn main() -> Result<(), eframe::Error> {
     }
 
     // Get command line arguments
-    let args: Vec<String> = env::args().collect();
+    // Paths are not necessarily valid UTF-8, e.g. Cyrillic names on a Latin-1 file system
+    let args: Vec<OsString> = env::args_os().collect();
     info!("Command line arguments: {:?}", args);
+
+    // `--probe <file>...` prints the file properties as JSON
+    if cli::is_probe(&args) {
+        match cli::probe(&args) {
+            Ok(true) => return Ok(()),
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    // `--register-file-associations` and `--unregister` set up the viewer as the default for its image types
+    if let Some(register) = file_associations::requested(&args) {
+        if let Err(e) = file_associations::run(register) {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // `--convert`, `-o` and the display options write the displayed image without opening a window
+    if cli::is_convert(&args) {
+        let options = match cli::parse(&args) {
+            Ok(options) => options,
+            Err(e) => {
+                eprintln!("Error: {:#}\n\n{}", e, cli::USAGE);
+                std::process::exit(2);
+            }
+        };
+        if let Err(e) = cli::run(&options) {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
     
-    // Check for file path in arguments
-    let initial_image = if args.len() > 1 {
-        let path = &args[1];
-        info!("Found file path in arguments: {}", path);
-        Some(path.clone())
-    } else {
+    // `--quick-look <path>` opens a borderless preview for file manager integration
+    let quick_look = args.iter().skip(1).any(|arg| arg == "--quick-look");
+    let paths: Vec<&OsString> = args.iter().skip(1).filter(|arg| *arg != "--quick-look").collect();
+    
+    // Several files become the navigation list instead of the folder, `-` reads an image from stdin
+    let mut initial_images = Vec::new();
+    for path in paths {
+        if path == "-" {
+            match cli::read_stdin() {
+                Ok(path) => initial_images.push(path),
+                Err(e) => {
+                    eprintln!("Error: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+        } else {
+            initial_images.push(PathBuf::from(path));
+        }
+    }
+    if initial_images.is_empty() {
         info!("No file path provided in arguments");
-        None
-    };
+    } else {
+        info!("Found file paths in arguments: {:?}", initial_images);
+    }
+    let quick_look = quick_look && !initial_images.is_empty();
+    
+    // Single-instance mode: a running viewer shows the file instead, quick looks are separate previews.
+    // A list of files opens its own window, the running viewer can only take one.
+    let single_instance = !quick_look && initial_images.len() <= 1 && !Config::load().multiple_instances;
+    if let (true, Some(path)) = (single_instance, initial_images.first()) {
+        if instance::forward_to_running(path) {
+            info!("Opened {:?} in the running viewer", path);
+            return Ok(());
+        }
+    }
 
-    let native_options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default()
+    let viewport = if quick_look {
+        egui::ViewportBuilder::default()
+            .with_inner_size([400.0, 400.0])
+            .with_min_inner_size([64.0, 64.0])
+            .with_decorations(false)
+    } else {
+        egui::ViewportBuilder::default()
             .with_inner_size([800.0, 800.0])
             .with_min_inner_size([400.0, 400.0])
+    };
+    let native_options = eframe::NativeOptions {
+        viewport: viewport
             .with_drag_and_drop(true)
             .with_icon(icon_data),
         // Windows-specific configuration is handled in build.rs with /SUBSYSTEM:WINDOWS
@@ -1716,19 +8553,51 @@ fn main() -> Result<(), eframe::Error> {
         "Image Viewer",
         native_options,
         Box::new(move |cc| {
+            // Ctrl+= / Ctrl+- zoom the image, egui would zoom the whole interface
+            cc.egui_ctx.options_mut(|options| options.zoom_with_keyboard = false);
             let mut app = ImageViewerApp::new(cc);
+            app.quick_look = quick_look;
+            if single_instance {
+                app.instance = InstanceServer::start(&cc.egui_ctx)
+                    .map_err(|e| warn!("Single-instance mode is not available: {}", e))
+                    .ok();
+            }
             
             // Load initial image if provided
-            if let Some(path) = initial_image {
-                info!("Loading initial image: {}", path);
-                match app.load_image(PathBuf::from(path)) {
+            if !initial_images.is_empty() {
+                info!("Loading initial images: {:?}", initial_images);
+                let loaded = match initial_images.as_slice() {
+                    [path] => app.load_image(path.clone()),
+                    _ => app.open_file_list(initial_images),
+                };
+                match loaded {
                     Ok(_) => {
                         info!("Successfully loaded initial image");
                         // Set initial window size based on image
                         let (width, height) = app.calculate_window_size();
                         cc.egui_ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(width, height)));
                     },
-                    Err(e) => error!("Failed to load initial image: {}", e),
+                    Err(e) => {
+                        error!("Failed to load initial image: {}", e);
+                        app.report_load_error(&e);
+                    }
+                }
+            } else {
+                match app.config.startup {
+                    StartupBehavior::Empty => {}
+                    StartupBehavior::OpenFileDialog => app.open_dialog_requested = true,
+                    StartupBehavior::LastSession => {
+                        if let Some(path) = app.config.last_session.clone().filter(|p| p.exists()) {
+                            info!("Restoring last session: {:?}", path);
+                            match app.load_image(path) {
+                                Ok(_) => {
+                                    let (width, height) = app.calculate_window_size();
+                                    cc.egui_ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(width, height)));
+                                }
+                                Err(e) => error!("Failed to restore last session: {}", e),
+                            }
+                        }
+                    }
                 }
             }
             
@@ -0,0 +1,215 @@
+//! Label masks shown over the image, for checking segmentation datasets: each value of a gray
+//! mask, or each color of a color-coded one, is a label drawn in its own color with adjustable
+//! opacity. While browsing, the mask of each image is looked up by name in the mask folder.
+
+use anyhow::bail;
+use image::{DynamicImage, ImageReader};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Masks with more distinct values are most likely not label masks
+const MAX_LABELS: usize = 4096;
+/// Labels listed in the window, the others keep their default colors
+const LISTED_LABELS: usize = 256;
+
+/// How a label is drawn
+#[derive(Clone, Copy)]
+pub struct LabelStyle {
+    pub color: egui::Color32,
+    pub visible: bool,
+}
+
+/// Label of every pixel of a mask
+pub struct Mask {
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    labels: Vec<u32>,
+    /// Pixels per label
+    counts: BTreeMap<u32, u64>,
+    /// Labels are packed RGB colors instead of gray values
+    rgb: bool,
+}
+
+impl Mask {
+    /// Read a mask with 8 or 16-bit gray values or RGB colors as labels. RGB files with only
+    /// gray colors count as gray.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let img = ImageReader::open(path)?.with_guessed_format()?.decode()?;
+        let (width, height) = (img.width(), img.height());
+        let (labels, rgb): (Vec<u32>, bool) = match &img {
+            DynamicImage::ImageLuma8(_) | DynamicImage::ImageLumaA8(_) => (img.to_luma8().into_raw().into_iter().map(u32::from).collect(), false),
+            DynamicImage::ImageLuma16(_) | DynamicImage::ImageLumaA16(_) => (img.to_luma16().into_raw().into_iter().map(u32::from).collect(), false),
+            DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgba8(_) => {
+                let rgb = img.to_rgb8();
+                if rgb.pixels().all(|pixel| pixel[0] == pixel[1] && pixel[1] == pixel[2]) {
+                    (rgb.pixels().map(|pixel| pixel[0] as u32).collect(), false)
+                } else {
+                    (rgb.pixels().map(|pixel| u32::from_be_bytes([0, pixel[0], pixel[1], pixel[2]])).collect(), true)
+                }
+            }
+            _ => bail!("Masks have to be 8 or 16-bit gray or 8-bit RGB images, {:?} is {:?}", path, img.color()),
+        };
+        let mut counts = BTreeMap::new();
+        for &label in &labels {
+            *counts.entry(label).or_insert(0) += 1;
+            if counts.len() > MAX_LABELS {
+                bail!("{:?} has more than {} distinct values and is not a label mask", path, MAX_LABELS);
+            }
+        }
+        Ok(Self { path: path.to_path_buf(), width, height, labels, counts, rgb })
+    }
+
+    pub fn label_at(&self, x: u32, y: u32) -> Option<u32> {
+        (x < self.width && y < self.height).then(|| self.labels[(y * self.width + x) as usize])
+    }
+
+    /// Name of a label: the value of gray masks, the hex color of RGB ones
+    pub fn label_name(&self, label: u32) -> String {
+        if self.rgb {
+            format!("#{:06X}", label)
+        } else {
+            label.to_string()
+        }
+    }
+
+    /// Style of a label until it is changed: RGB labels in their own color, gray labels in
+    /// well separated hues. 0 is the background and hidden.
+    fn default_style(&self, label: u32) -> LabelStyle {
+        let color = if self.rgb {
+            let [_, r, g, b] = label.to_be_bytes();
+            egui::Color32::from_rgb(r, g, b)
+        } else {
+            // Golden angle steps keep neighboring labels apart
+            let hue = (label as f32 * 0.618_034).fract();
+            egui::ecolor::Hsva::new(hue, 0.85, 1.0, 1.0).into()
+        };
+        LabelStyle { color, visible: label != 0 }
+    }
+}
+
+/// State of the mask overlay
+pub struct MaskOverlay {
+    /// Mask of the shown image
+    pub mask: Option<Mask>,
+    /// Why no mask is shown
+    pub message: Option<String>,
+    /// Image the mask was looked up for
+    pub image_path: Option<PathBuf>,
+    /// Folder of the loaded mask
+    pub folder: Option<PathBuf>,
+    /// Look up the mask of each shown image in the mask folder
+    pub follow: bool,
+    pub opacity: f32,
+    /// Styles by label, kept across masks so a label keeps its color
+    styles: BTreeMap<u32, LabelStyle>,
+    /// Colors of the mask pixels, rebuilt when the mask or the styles change
+    pub texture: Option<egui::TextureHandle>,
+}
+
+impl Default for MaskOverlay {
+    fn default() -> Self {
+        Self { mask: None, message: None, image_path: None, folder: None, follow: true, opacity: 0.5, styles: BTreeMap::new(), texture: None }
+    }
+}
+
+impl MaskOverlay {
+    /// Show a mask, labels seen for the first time get their default style
+    pub fn set_mask(&mut self, mask: Mask) {
+        for &label in mask.counts.keys() {
+            self.styles.entry(label).or_insert_with(|| mask.default_style(label));
+        }
+        self.folder = mask.path.parent().map(Path::to_path_buf);
+        self.mask = Some(mask);
+        self.message = None;
+        self.texture = None;
+    }
+
+    /// Label colors of the mask pixels, transparent where a label is hidden
+    pub fn color_image(&self) -> Option<egui::ColorImage> {
+        let mask = self.mask.as_ref()?;
+        let pixels = mask.labels.iter().map(|label| match self.styles.get(label) {
+            Some(style) if style.visible => style.color,
+            _ => egui::Color32::TRANSPARENT,
+        }).collect();
+        Some(egui::ColorImage { size: [mask.width as usize, mask.height as usize], pixels })
+    }
+
+    /// Tint drawing the overlay at its opacity
+    pub fn tint(&self) -> egui::Color32 {
+        egui::Color32::from_white_alpha((self.opacity * 255.0).round() as u8)
+    }
+
+    /// Opacity, lookup and the labels with their colors. Returns true if the label colors change.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("Opacity:");
+            ui.add(egui::Slider::new(&mut self.opacity, 0.0..=1.0));
+        });
+        ui.checkbox(&mut self.follow, "Follow the image")
+            .on_hover_text("Show the mask named like each image from the mask folder, e.g. img_001_mask.png for img_001.jpg");
+        if let Some(message) = &self.message {
+            ui.weak(message);
+        }
+        let Some(mask) = &self.mask else {
+            return false;
+        };
+        ui.label(format!("{} ({}×{}), {} labels", mask.path.file_name().unwrap_or_default().to_string_lossy(), mask.width, mask.height, mask.counts.len()));
+        ui.horizontal(|ui| {
+            for (name, visible) in [("Show all", true), ("Hide all", false)] {
+                if ui.button(name).clicked() {
+                    for label in mask.counts.keys() {
+                        if let Some(style) = self.styles.get_mut(label) {
+                            style.visible = visible;
+                        }
+                    }
+                    changed = true;
+                }
+            }
+        });
+        ui.separator();
+        let total = mask.labels.len().max(1) as f64;
+        egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+            egui::Grid::new("mask_labels").striped(true).show(ui, |ui| {
+                for (&label, &count) in mask.counts.iter().take(LISTED_LABELS) {
+                    let Some(style) = self.styles.get_mut(&label) else {
+                        continue;
+                    };
+                    changed |= ui.checkbox(&mut style.visible, mask.label_name(label)).changed();
+                    changed |= ui.color_edit_button_srgba(&mut style.color).changed();
+                    ui.label(format!("{} px ({:.2} %)", count, count as f64 / total * 100.0));
+                    ui.end_row();
+                }
+            });
+            if mask.counts.len() > LISTED_LABELS {
+                ui.weak(format!("{} more labels in their default colors", mask.counts.len() - LISTED_LABELS));
+            }
+        });
+        if changed {
+            self.texture = None;
+        }
+        changed
+    }
+}
+
+/// Mask of an image among the files of the mask folder: the file with the same name, or else one
+/// whose name continues it after a separator, like `img_001_mask.png` or `img_001-label.png`
+pub fn matching_mask(candidates: &[PathBuf], image: &Path) -> Option<PathBuf> {
+    let stem = image.file_stem()?.to_string_lossy().into_owned();
+    let candidates = candidates.iter().filter(|path| path.as_path() != image);
+    let mut continued = None;
+    for path in candidates {
+        let Some(name) = path.file_stem().map(|name| name.to_string_lossy()) else {
+            continue;
+        };
+        if name == stem {
+            return Some(path.clone());
+        }
+        let continues = name.strip_prefix(stem.as_str()).is_some_and(|rest| rest.starts_with(['_', '-', '.', ' ']));
+        if continues && continued.is_none() {
+            continued = Some(path.clone());
+        }
+    }
+    continued
+}
@@ -0,0 +1,233 @@
+//! Ruler measuring distances and angles between clicked points, in pixels or calibrated units
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use tiff::decoder::ifd::Value;
+use tiff::decoder::Decoder;
+use tiff::tags::Tag;
+
+/// Unit of a calibrated pixel size
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum LengthUnit {
+    Pixel,
+    Nanometer,
+    Micrometer,
+    Millimeter,
+    Centimeter,
+    Meter,
+    Inch,
+}
+
+impl LengthUnit {
+    pub const ALL: [LengthUnit; 7] = [
+        LengthUnit::Pixel,
+        LengthUnit::Nanometer,
+        LengthUnit::Micrometer,
+        LengthUnit::Millimeter,
+        LengthUnit::Centimeter,
+        LengthUnit::Meter,
+        LengthUnit::Inch,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LengthUnit::Pixel => "px",
+            LengthUnit::Nanometer => "nm",
+            LengthUnit::Micrometer => "µm",
+            LengthUnit::Millimeter => "mm",
+            LengthUnit::Centimeter => "cm",
+            LengthUnit::Meter => "m",
+            LengthUnit::Inch => "in",
+        }
+    }
+}
+
+/// Physical size of one pixel, width and height differ for anisotropic pixels
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Calibration {
+    pub width: f64,
+    pub height: f64,
+    pub unit: LengthUnit,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self { width: 1.0, height: 1.0, unit: LengthUnit::Pixel }
+    }
+}
+
+impl Calibration {
+    /// Length of the line from `a` to `b` given in pixels
+    pub fn length(&self, a: [f32; 2], b: [f32; 2]) -> f64 {
+        let (dx, dy) = self.scaled(a, b);
+        dx.hypot(dy)
+    }
+
+    /// Angle of the line from `a` to `b` against the horizontal, counter-clockwise in degrees
+    pub fn direction(&self, a: [f32; 2], b: [f32; 2]) -> f64 {
+        let (dx, dy) = self.scaled(a, b);
+        // Image y grows downwards. Adding 0 turns -0 into 0, so a horizontal line is not shown as -0.0°.
+        (-dy).atan2(dx).to_degrees() + 0.0
+    }
+
+    /// Angle at `vertex` between the arms to `a` and `b`, 0 to 180 degrees
+    pub fn angle(&self, a: [f32; 2], vertex: [f32; 2], b: [f32; 2]) -> f64 {
+        let difference = (self.direction(vertex, a) - self.direction(vertex, b)).abs() % 360.0;
+        if difference > 180.0 { 360.0 - difference } else { difference }
+    }
+
+    /// Distance in physical units between two pixel positions, per axis
+    fn scaled(&self, a: [f32; 2], b: [f32; 2]) -> (f64, f64) {
+        ((b[0] - a[0]) as f64 * self.width, (b[1] - a[1]) as f64 * self.height)
+    }
+
+    /// Same pixel size in another unit. Pixels cannot be converted.
+    pub fn converted(&self, unit: LengthUnit) -> Self {
+        match (meters(self.unit), meters(unit)) {
+            (Some(from), Some(to)) => Self { width: self.width * from / to, height: self.height * from / to, unit },
+            _ => *self,
+        }
+    }
+
+    /// Length as text with the unit, e.g. `12.35 µm`
+    pub fn format_length(&self, length: f64) -> String {
+        format!("{:.*} {}", if self.unit == LengthUnit::Pixel { 1 } else { 3 }, length, self.unit.as_str())
+    }
+}
+
+/// Pixel size from the resolution tags of a TIFF, `None` without a physical resolution unit
+pub fn tiff_calibration(path: &Path) -> Option<Calibration> {
+    // RAW formats are TIFF based too, but their resolution is a print setting
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    if extension != "tif" && extension != "tiff" {
+        return None;
+    }
+    let file = File::open(path).ok()?;
+    let mut decoder = Decoder::new(BufReader::new(file)).ok()?;
+    let rational = |value: Value| match value {
+        Value::Rational(numerator, denominator) if numerator > 0 && denominator > 0 => Some(numerator as f64 / denominator as f64),
+        _ => None,
+    };
+    let x = decoder.find_tag(Tag::XResolution).ok()??;
+    let x = rational(x)?;
+    let y = decoder.find_tag(Tag::YResolution).ok().flatten().and_then(rational).unwrap_or(x);
+    // The resolution is pixels per unit, 1 means no absolute unit
+    let unit = match decoder.find_tag_unsigned::<u16>(Tag::ResolutionUnit).ok().flatten().unwrap_or(2) {
+        2 => LengthUnit::Inch,
+        3 => LengthUnit::Centimeter,
+        _ => return None,
+    };
+    let calibration = Calibration { width: 1.0 / x, height: 1.0 / y, unit };
+    // Prefer micrometers, microscopy images are usually calibrated in those
+    Some(calibration.converted(LengthUnit::Micrometer))
+}
+
+/// Length of a unit in meters
+fn meters(unit: LengthUnit) -> Option<f64> {
+    match unit {
+        LengthUnit::Pixel => None,
+        LengthUnit::Nanometer => Some(1e-9),
+        LengthUnit::Micrometer => Some(1e-6),
+        LengthUnit::Millimeter => Some(1e-3),
+        LengthUnit::Centimeter => Some(1e-2),
+        LengthUnit::Meter => Some(1.0),
+        LengthUnit::Inch => Some(0.0254),
+    }
+}
+
+/// What the next clicks measure
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum MeasureMode {
+    /// Two points
+    Distance,
+    /// Three points, the middle one is the vertex
+    Angle,
+}
+
+#[derive(Clone, Debug)]
+pub enum Measurement {
+    Distance([f32; 2], [f32; 2]),
+    Angle([f32; 2], [f32; 2], [f32; 2]),
+}
+
+impl Measurement {
+    pub fn points(&self) -> Vec<[f32; 2]> {
+        match *self {
+            Measurement::Distance(a, b) => vec![a, b],
+            Measurement::Angle(a, vertex, b) => vec![a, vertex, b],
+        }
+    }
+
+    /// Result as text, e.g. `12.350 µm at 30.0°` or `45.0°`
+    pub fn to_text(&self, calibration: &Calibration) -> String {
+        match *self {
+            Measurement::Distance(a, b) => {
+                format!("{} at {:.1}°", calibration.format_length(calibration.length(a, b)), calibration.direction(a, b))
+            }
+            Measurement::Angle(a, vertex, b) => format!("{:.1}°", calibration.angle(a, vertex, b)),
+        }
+    }
+}
+
+/// State of the measurement tool
+pub struct MeasureTool {
+    pub mode: MeasureMode,
+    pub calibration: Calibration,
+    /// Points clicked for the measurement in progress
+    pub pending: Vec<[f32; 2]>,
+    pub measurements: Vec<Measurement>,
+    /// Known length of the last distance, for calibrating from it
+    pub known_length: f64,
+    pub known_unit: LengthUnit,
+}
+
+impl MeasureTool {
+    pub fn new(calibration: Option<Calibration>) -> Self {
+        Self {
+            mode: MeasureMode::Distance,
+            calibration: calibration.unwrap_or_default(),
+            pending: Vec::new(),
+            measurements: Vec::new(),
+            known_length: 1.0,
+            known_unit: LengthUnit::Micrometer,
+        }
+    }
+
+    /// Start over on another image, taking its calibration when it has one
+    pub fn image_changed(&mut self, calibration: Option<Calibration>) {
+        self.pending.clear();
+        self.measurements.clear();
+        if let Some(calibration) = calibration {
+            self.calibration = calibration;
+        }
+    }
+
+    /// Add a clicked point, completing a measurement when enough points are set
+    pub fn click(&mut self, point: [f32; 2]) {
+        self.pending.push(point);
+        let measurement = match (self.mode, self.pending.as_slice()) {
+            (MeasureMode::Distance, &[a, b]) => Measurement::Distance(a, b),
+            (MeasureMode::Angle, &[a, vertex, b]) => Measurement::Angle(a, vertex, b),
+            _ => return,
+        };
+        self.measurements.push(measurement);
+        self.pending.clear();
+    }
+
+    /// Pixel length of the last distance measurement
+    pub fn last_distance_pixels(&self) -> Option<f64> {
+        self.measurements.iter().rev().find_map(|m| match *m {
+            Measurement::Distance(a, b) => Some(Calibration::default().length(a, b)),
+            Measurement::Angle(..) => None,
+        })
+    }
+
+    /// Set square pixels so the last distance has the known length
+    pub fn calibrate_from_last(&mut self) {
+        if let Some(pixels) = self.last_distance_pixels().filter(|pixels| *pixels > 0.0) {
+            let size = self.known_length / pixels;
+            self.calibration = Calibration { width: size, height: size, unit: self.known_unit };
+        }
+    }
+}
@@ -0,0 +1,262 @@
+//! TIFFs with more than four samples per pixel, like multispectral captures, which the tiff
+//! crate does not decode. Their strips or tiles are decompressed here into floating point
+//! bands, and three of the bands (or a single one) are mapped to the displayed image.
+
+use anyhow::{anyhow, bail};
+use image::{DynamicImage, ImageBuffer};
+use std::fs;
+use std::io::{Read, Seek};
+use std::path::Path;
+use tiff::decoder::Decoder;
+use tiff::tags::Tag;
+
+/// Bands shown as the displayed image
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BandMapping {
+    Rgb([usize; 3]),
+    Gray(usize),
+}
+
+impl Default for BandMapping {
+    fn default() -> Self {
+        BandMapping::Rgb([0, 1, 2])
+    }
+}
+
+impl BandMapping {
+    pub fn bands(&self) -> &[usize] {
+        match self {
+            BandMapping::Rgb(bands) => bands,
+            BandMapping::Gray(band) => std::slice::from_ref(band),
+        }
+    }
+
+    /// Channels of the composed data, 3 for RGB and 1 for gray
+    pub fn channels(&self) -> u32 {
+        self.bands().len() as u32
+    }
+}
+
+/// Samples per pixel of the current image of a TIFF decoder
+pub fn samples_per_pixel<R: Read + Seek>(decoder: &mut Decoder<R>) -> anyhow::Result<u32> {
+    Ok(decoder.find_tag_unsigned(Tag::SamplesPerPixel)?.unwrap_or(1))
+}
+
+/// All bands of the TIFF at `path` as interleaved floating point values, with the width,
+/// height and number of bands. Uncompressed, LZW and Deflate data is supported.
+pub fn read_bands<R: Read + Seek>(decoder: &mut Decoder<R>, path: &Path) -> anyhow::Result<(Vec<f32>, u32, u32, u32)> {
+    let (width, height) = decoder.dimensions()?;
+    let bands: u32 = samples_per_pixel(decoder)?;
+    let bits: u32 = decoder.find_tag_unsigned_vec(Tag::BitsPerSample)?.and_then(|bits: Vec<u32>| bits.first().copied()).unwrap_or(1);
+    let format: u32 = decoder.find_tag_unsigned_vec(Tag::SampleFormat)?.and_then(|formats: Vec<u32>| formats.first().copied()).unwrap_or(1);
+    let compression: u32 = decoder.find_tag_unsigned(Tag::Compression)?.unwrap_or(1);
+    let predictor: u32 = decoder.find_tag_unsigned(Tag::Predictor)?.unwrap_or(1);
+    let planar = decoder.find_tag_unsigned::<u32>(Tag::PlanarConfiguration)?.unwrap_or(1) == 2;
+    match (format, bits) {
+        (1 | 2, 8 | 16 | 32 | 64) | (3, 32 | 64) => {}
+        _ => bail!("Unsupported {}-bit samples of format {}", bits, format),
+    }
+    if !matches!((predictor, format), (1 | 2, _) | (3, 3)) {
+        bail!("Unsupported predictor {} for samples of format {}", predictor, format);
+    }
+
+    let tiled = decoder.find_tag(Tag::TileWidth)?.is_some();
+    let (chunk_width, chunk_height, offsets, byte_counts) = if tiled {
+        (
+            decoder.get_tag_u32(Tag::TileWidth)?,
+            decoder.get_tag_u32(Tag::TileLength)?,
+            decoder.get_tag_u64_vec(Tag::TileOffsets)?,
+            decoder.get_tag_u64_vec(Tag::TileByteCounts)?,
+        )
+    } else {
+        (
+            width,
+            decoder.find_tag_unsigned::<u32>(Tag::RowsPerStrip)?.unwrap_or(height).clamp(1, height),
+            decoder.get_tag_u64_vec(Tag::StripOffsets)?,
+            decoder.get_tag_u64_vec(Tag::StripByteCounts)?,
+        )
+    };
+    if chunk_width == 0 || chunk_height == 0 {
+        bail!("Invalid tile size {}x{}", chunk_width, chunk_height);
+    }
+
+    // Planar data stores each band in its own strips or tiles
+    let chunk_bands = if planar { 1 } else { bands as usize };
+    let chunks_across = width.div_ceil(chunk_width) as usize;
+    let chunks_per_plane = chunks_across * height.div_ceil(chunk_height) as usize;
+    let planes = if planar { bands as usize } else { 1 };
+    if offsets.len() < chunks_per_plane * planes || byte_counts.len() < offsets.len() {
+        bail!("Expected {} strips or tiles, found {}", chunks_per_plane * planes, offsets.len());
+    }
+
+    let file = fs::read(path)?;
+    let big_endian = file.starts_with(b"MM");
+    let (width_px, height_px) = (width as usize, height as usize);
+    let mut data = vec![0.0f32; width_px * height_px * bands as usize];
+    for (index, (&offset, &count)) in offsets.iter().zip(&byte_counts).enumerate().take(chunks_per_plane * planes) {
+        let (plane, chunk) = (index / chunks_per_plane, index % chunks_per_plane);
+        let x0 = (chunk % chunks_across) * chunk_width as usize;
+        let y0 = (chunk / chunks_across) * chunk_height as usize;
+        // The last strip is shorter, tiles are padded to their full size
+        let rows = if tiled { chunk_height as usize } else { (chunk_height as usize).min(height_px - y0) };
+        let raw = file.get(offset as usize..(offset + count) as usize)
+            .ok_or_else(|| anyhow!("Strip or tile {} lies outside the file", index))?;
+        let bytes = decompress(raw, compression)?;
+        let layout = ChunkLayout { rows, columns: chunk_width as usize, samples: chunk_bands, bits, format, predictor, big_endian };
+        let values = layout.decode(&bytes).map_err(|e| anyhow!("Strip or tile {}: {}", index, e))?;
+
+        for row in 0..rows.min(height_px.saturating_sub(y0)) {
+            for column in 0..(chunk_width as usize).min(width_px.saturating_sub(x0)) {
+                let source = (row * chunk_width as usize + column) * chunk_bands;
+                let target = ((y0 + row) * width_px + x0 + column) * bands as usize;
+                for sample in 0..chunk_bands {
+                    let band = if planar { plane } else { sample };
+                    data[target + band] = values[source + sample];
+                }
+            }
+        }
+    }
+    Ok((data, width, height, bands))
+}
+
+fn decompress(raw: &[u8], compression: u32) -> anyhow::Result<Vec<u8>> {
+    match compression {
+        1 => Ok(raw.to_vec()),
+        5 => {
+            let mut bytes = Vec::new();
+            weezl::decode::Decoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8)
+                .into_vec(&mut bytes)
+                .decode(raw)
+                .status
+                .map_err(|e| anyhow!("LZW data is invalid: {}", e))?;
+            Ok(bytes)
+        }
+        8 | 32946 => {
+            let mut bytes = Vec::new();
+            flate2::read::ZlibDecoder::new(raw).read_to_end(&mut bytes)?;
+            Ok(bytes)
+        }
+        _ => bail!("Unsupported compression {}", compression),
+    }
+}
+
+/// Sample layout of a decompressed strip or tile
+struct ChunkLayout {
+    rows: usize,
+    columns: usize,
+    /// Samples per pixel in this chunk, 1 for planar data
+    samples: usize,
+    bits: u32,
+    format: u32,
+    predictor: u32,
+    big_endian: bool,
+}
+
+impl ChunkLayout {
+    fn decode(&self, bytes: &[u8]) -> anyhow::Result<Vec<f32>> {
+        let size = self.bits as usize / 8;
+        let row_samples = self.columns * self.samples;
+        let length = self.rows * row_samples * size;
+        if bytes.len() < length {
+            bail!("{} bytes of {} are missing", length - bytes.len(), length);
+        }
+        let mut bytes = bytes[..length].to_vec();
+
+        // The floating point predictor stores the bytes of each value most significant first
+        let big_endian = if self.predictor == 3 {
+            for row in bytes.chunks_exact_mut(row_samples * size) {
+                for i in self.samples..row.len() {
+                    row[i] = row[i].wrapping_add(row[i - self.samples]);
+                }
+                let grouped = row.to_vec();
+                for sample in 0..row_samples {
+                    for byte in 0..size {
+                        row[sample * size + byte] = grouped[byte * row_samples + sample];
+                    }
+                }
+            }
+            true
+        } else {
+            self.big_endian
+        };
+
+        let mut raw: Vec<u64> = bytes.chunks_exact(size).map(|value| {
+            let fold = |raw: u64, &byte: &u8| raw << 8 | byte as u64;
+            if big_endian { value.iter().fold(0, fold) } else { value.iter().rev().fold(0, fold) }
+        }).collect();
+        if self.predictor == 2 {
+            // Horizontal differencing of integers, wrapping at the sample size
+            let mask = if self.bits == 64 { u64::MAX } else { (1 << self.bits) - 1 };
+            for row in raw.chunks_exact_mut(row_samples) {
+                for i in self.samples..row.len() {
+                    row[i] = row[i].wrapping_add(row[i - self.samples]) & mask;
+                }
+            }
+        }
+
+        let shift = 64 - self.bits;
+        Ok(raw.into_iter().map(|value| match (self.format, self.bits) {
+            (3, 32) => f32::from_bits(value as u32),
+            (3, _) => f64::from_bits(value) as f32,
+            (2, _) => ((value << shift) as i64 >> shift) as f32,
+            _ => value as f32,
+        }).collect())
+    }
+}
+
+/// The mapped bands of interleaved data, 1 or 3 values per pixel
+pub fn compose(data: &[f32], bands: u32, mapping: BandMapping) -> Vec<f32> {
+    let selected = mapping.bands();
+    data.chunks_exact(bands as usize)
+        .flat_map(|pixel| selected.iter().map(|&band| pixel.get(band).copied().unwrap_or(0.0)))
+        .collect()
+}
+
+/// 8-bit image of the mapped bands stretched over their joint range, like floating point TIFFs
+/// are previewed, and that range
+pub fn preview(data: &[f32], width: u32, height: u32, bands: u32, mapping: BandMapping) -> anyhow::Result<(DynamicImage, (f32, f32))> {
+    let composed = compose(data, bands, mapping);
+    let (min, max) = composed.iter().filter(|v| v.is_finite())
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &v| (min.min(v), max.max(v)));
+    let scale = if max - min > f32::EPSILON { 255.0 / (max - min) } else { 0.0 };
+    let bytes: Vec<u8> = composed.iter().map(|&v| ((v - min) * scale).clamp(0.0, 255.0) as u8).collect();
+    let invalid = || anyhow!("Band data does not match the {}x{} image", width, height);
+    let img = match mapping {
+        BandMapping::Rgb(_) => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, bytes).ok_or_else(invalid)?),
+        BandMapping::Gray(_) => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, bytes).ok_or_else(invalid)?),
+    };
+    Ok((img, (min, max.max(min))))
+}
+
+/// Band selection, three bands as RGB or a single one as gray. Returns true if the mapping changed.
+pub fn mapping_ui(ui: &mut egui::Ui, mapping: &mut BandMapping, bands: u32) -> bool {
+    let mut changed = false;
+    ui.label("Bands:");
+    let mut single = matches!(mapping, BandMapping::Gray(_));
+    if ui.checkbox(&mut single, "Single").on_hover_text("Show one band as a gray image").changed() {
+        *mapping = match *mapping {
+            BandMapping::Rgb([red, ..]) => BandMapping::Gray(red),
+            BandMapping::Gray(_) => BandMapping::default(),
+        };
+        changed = true;
+    }
+    let mut band_combo = |ui: &mut egui::Ui, label: &str, band: &mut usize| {
+        egui::ComboBox::from_id_salt(("band", label))
+            .selected_text(format!("{} {}", label, *band + 1))
+            .width(56.0)
+            .show_ui(ui, |ui| {
+                for index in 0..bands as usize {
+                    changed |= ui.selectable_value(band, index, format!("Band {}", index + 1)).changed();
+                }
+            });
+    };
+    match mapping {
+        BandMapping::Rgb([red, green, blue]) => {
+            band_combo(ui, "R", red);
+            band_combo(ui, "G", green);
+            band_combo(ui, "B", blue);
+        }
+        BandMapping::Gray(band) => band_combo(ui, "Band", band),
+    }
+    changed
+}
@@ -0,0 +1,134 @@
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::PixelRect;
+
+/// Tokens of the export file name templates, with a description for the settings
+pub const TOKENS: [(&str, &str); 11] = [
+    ("{name}", "File name of the image without extension"),
+    ("{ext}", "Extension of the image"),
+    ("{index}", "Frame number in frame exports, else the position of the image in its folder; {index:04} pads to 4 digits"),
+    ("{norm}", "Active normalization"),
+    ("{transform}", "\"fft\" while the FFT window is open, \"none\" otherwise"),
+    ("{channel}", "Displayed channel"),
+    ("{width}", "Width of the exported image"),
+    ("{height}", "Height of the exported image"),
+    ("{roi}", "Exported region as x_y_WxH, empty for whole images"),
+    ("{date}", "Export date as YYYYMMDD (UTC)"),
+    ("{time}", "Export time as HHMMSS (UTC)"),
+];
+
+/// File name templates of the exports, without extension
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(default)]
+pub struct ExportNaming {
+    pub crop: String,
+    pub resize: String,
+    /// Displayed image with the annotations burned in
+    pub annotated: String,
+    /// Rendering of the view by "Export view"
+    pub view: String,
+    pub fft: String,
+    /// Every frame of an animated GIF or TIFF stack
+    pub frames: String,
+}
+
+impl Default for ExportNaming {
+    fn default() -> Self {
+        Self {
+            crop: "{name}_crop".to_string(),
+            resize: "{name}_{width}x{height}".to_string(),
+            annotated: "{name}_annotated".to_string(),
+            view: "{name}_view".to_string(),
+            fft: "{name}_fft".to_string(),
+            frames: "{name}_{index:04}".to_string(),
+        }
+    }
+}
+
+/// Values of the template tokens for one exported file
+pub struct NamingContext<'a> {
+    pub name: &'a str,
+    pub ext: &'a str,
+    pub index: usize,
+    pub norm: &'a str,
+    pub transform: &'a str,
+    pub channel: &'a str,
+    pub width: u32,
+    pub height: u32,
+    pub roi: Option<PixelRect>,
+}
+
+/// File name from a template. Characters that are not allowed in file names become `_`.
+pub fn expand(template: &str, context: &NamingContext, now: SystemTime) -> anyhow::Result<String> {
+    let mut output = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or_else(|| anyhow!("Unclosed {{ in {:?}", template))? + start;
+        let token = &rest[start + 1..end];
+        let (name, format) = token.split_once(':').unwrap_or((token, ""));
+        let value = match name {
+            "name" => context.name.to_string(),
+            "ext" => context.ext.to_string(),
+            "index" => pad(context.index, format)?,
+            "norm" => context.norm.to_string(),
+            "transform" => context.transform.to_string(),
+            "channel" => context.channel.to_string(),
+            "width" => pad(context.width as usize, format)?,
+            "height" => pad(context.height as usize, format)?,
+            "roi" => context.roi
+                .map(|[x0, y0, x1, y1]| format!("{}_{}_{}x{}", x0, y0, x1 - x0, y1 - y0))
+                .unwrap_or_default(),
+            "date" | "time" => {
+                let (date, time) = utc_date_time(now);
+                if name == "date" { date } else { time }
+            }
+            _ => bail!("Unknown token {{{}}}", token),
+        };
+        output.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    output.push_str(rest);
+
+    let output: String = output
+        .chars()
+        .map(|c| if matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') || c.is_control() { '_' } else { c })
+        .collect();
+    if output.trim().is_empty() {
+        bail!("Template {:?} gives an empty file name", template);
+    }
+    Ok(output)
+}
+
+/// Number with a `0N` format zero padded to N digits
+fn pad(value: usize, format: &str) -> anyhow::Result<String> {
+    if format.is_empty() {
+        return Ok(value.to_string());
+    }
+    let width: usize = format.strip_prefix('0')
+        .and_then(|digits| digits.parse().ok())
+        .ok_or_else(|| anyhow!("Invalid number format {:?}, use e.g. 04", format))?;
+    Ok(format!("{:0width$}", value, width = width))
+}
+
+/// `YYYYMMDD` and `HHMMSS` of a UTC time
+fn utc_date_time(time: SystemTime) -> (String, String) {
+    let seconds = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    let (days, day_seconds) = (seconds / 86400, seconds % 86400);
+    // Civil date from days since 1970-01-01, after Howard Hinnant's days_from_civil inverse
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (
+        format!("{:04}{:02}{:02}", year, month, day),
+        format!("{:02}{:02}{:02}", day_seconds / 3600, day_seconds / 60 % 60, day_seconds % 60),
+    )
+}
@@ -0,0 +1,296 @@
+use anyhow::{anyhow, bail, Context};
+use flate2::read::GzDecoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, GrayImage};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::orientation::OrientationOp;
+use crate::PixelRect;
+
+/// Whether the file is a NIfTI volume, `.nii` or `.nii.gz`
+pub fn is_nifti_file(path: &Path) -> bool {
+    let name = path.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+    name.ends_with(".nii") || name.ends_with(".nii.gz")
+}
+
+/// Slicing direction through the volume, in voxel order (the usual RAS+ storage gives the
+/// anatomical planes)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Plane {
+    /// Slices along k, i × j images
+    Axial,
+    /// Slices along j, i × k images
+    Coronal,
+    /// Slices along i, j × k images
+    Sagittal,
+}
+
+impl Plane {
+    pub const ALL: [Plane; 3] = [Plane::Axial, Plane::Coronal, Plane::Sagittal];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Plane::Axial => "Axial",
+            Plane::Coronal => "Coronal",
+            Plane::Sagittal => "Sagittal",
+        }
+    }
+
+    /// Voxel axes of the image columns, the image rows and the slices
+    fn axes(self) -> [usize; 3] {
+        match self {
+            Plane::Axial => [0, 1, 2],
+            Plane::Coronal => [0, 2, 1],
+            Plane::Sagittal => [1, 2, 0],
+        }
+    }
+}
+
+/// Rotation, crop or resize of the shown slice, repeated on every slice of the plane
+#[derive(Clone, Copy, Debug)]
+pub enum SliceEdit {
+    Orient(OrientationOp),
+    Crop(PixelRect),
+    Resize(u32, u32, FilterType),
+}
+
+/// The first 3D volume of a NIfTI file as floats, with scaling applied
+pub struct Volume {
+    data: Vec<f32>,
+    dims: [usize; 3],
+    /// Voxel size per axis, usually in mm
+    pub spacing: [f32; 3],
+    /// Number of volumes in the file, only the first one is loaded
+    pub time_points: usize,
+    /// Finite value range of the whole volume, so all slices share one normalization
+    pub range: (f32, f32),
+    plane: Plane,
+    slice: usize,
+    edits: Vec<SliceEdit>,
+}
+
+impl Volume {
+    /// Memory taken by the voxel data
+    pub fn bytes(&self) -> usize {
+        self.data.len() * size_of::<f32>()
+    }
+
+    pub fn plane(&self) -> Plane {
+        self.plane
+    }
+
+    pub fn slice(&self) -> usize {
+        self.slice
+    }
+
+    /// Voxel size along the columns and rows of the current plane
+    pub fn pixel_spacing(&self) -> (f32, f32) {
+        let [column_axis, row_axis, _] = self.plane.axes();
+        (self.spacing[column_axis], self.spacing[row_axis])
+    }
+
+    pub fn slice_count(&self) -> usize {
+        self.dims[self.plane.axes()[2]]
+    }
+
+    /// Voxel dimensions i × j × k
+    pub fn dims(&self) -> [usize; 3] {
+        self.dims
+    }
+
+    /// Switch to another plane at its middle slice. Edits of the old plane are dropped.
+    pub fn set_plane(&mut self, plane: Plane) -> bool {
+        if plane == self.plane {
+            return false;
+        }
+        self.plane = plane;
+        self.slice = self.slice_count() / 2;
+        self.edits.clear();
+        true
+    }
+
+    /// Select a slice, clamped to the last one. Returns whether the slice changed.
+    pub fn set_slice(&mut self, slice: usize) -> bool {
+        let slice = slice.min(self.slice_count() - 1);
+        let changed = slice != self.slice;
+        self.slice = slice;
+        changed
+    }
+
+    /// Slice `step` slices away from the current one, clamped at both ends
+    pub fn stepped(&self, step: i32) -> usize {
+        (self.slice as i64 + step as i64).clamp(0, self.slice_count() as i64 - 1) as usize
+    }
+
+    pub fn edit(&mut self, edit: SliceEdit) {
+        self.edits.push(edit);
+    }
+
+    pub fn edits(&self) -> &[SliceEdit] {
+        &self.edits
+    }
+
+    /// Replace the edits, e.g. to undo the last one
+    pub fn set_edits(&mut self, edits: Vec<SliceEdit>) {
+        self.edits = edits;
+    }
+
+    /// Current slice as floats with its width and height. Higher j / k are at the top.
+    pub fn slice_data(&self) -> (Vec<f32>, u32, u32) {
+        let [column_axis, row_axis, slice_axis] = self.plane.axes();
+        let strides = [1, self.dims[0], self.dims[0] * self.dims[1]];
+        let (width, height) = (self.dims[column_axis], self.dims[row_axis]);
+        let mut data = Vec::with_capacity(width * height);
+        for row in (0..height).rev() {
+            let base = row * strides[row_axis] + self.slice * strides[slice_axis];
+            data.extend((0..width).map(|column| self.data[base + column * strides[column_axis]]));
+        }
+        let (mut width, mut height) = (width as u32, height as u32);
+        for edit in &self.edits {
+            match *edit {
+                SliceEdit::Orient(op) => (data, width, height) = op.apply_to_fp(&data, width, height, 1),
+                SliceEdit::Crop(rect) => {
+                    data = crate::crop::crop_fp(&data, width, 1, rect);
+                    (width, height) = (rect[2] - rect[0], rect[3] - rect[1]);
+                }
+                SliceEdit::Resize(new_width, new_height, filter) => {
+                    data = crate::resize::resize_fp(&data, width, height, 1, new_width, new_height, filter);
+                    (width, height) = (new_width, new_height);
+                }
+            }
+        }
+        (data, width, height)
+    }
+
+    /// Current slice as floats and as an 8-bit image normalized to the volume range
+    pub fn slice_image(&self) -> (DynamicImage, Vec<f32>, u32, u32) {
+        let (data, width, height) = self.slice_data();
+        let (min, max) = self.range;
+        let gray = data.iter()
+            .map(|&v| if max - min > f32::EPSILON { ((v - min) / (max - min) * 255.0).clamp(0.0, 255.0) as u8 } else { 128 })
+            .collect();
+        let image = GrayImage::from_raw(width, height, gray).map(DynamicImage::ImageLuma8).unwrap_or_default();
+        (image, data, width, height)
+    }
+}
+
+/// Fields of the NIfTI-1 and NIfTI-2 headers used here
+struct Header {
+    dims: Vec<usize>,
+    datatype: i16,
+    spacing: [f32; 3],
+    vox_offset: usize,
+    slope: f32,
+    intercept: f32,
+    big_endian: bool,
+}
+
+/// Read a single-file NIfTI-1 or NIfTI-2 volume (`.nii`, optionally gzipped), starting at
+/// the axial middle slice
+pub fn load_volume(path: &Path) -> anyhow::Result<Volume> {
+    let file = BufReader::new(File::open(path).with_context(|| format!("Failed to open {:?}", path))?);
+    let mut bytes = Vec::new();
+    if path.to_string_lossy().to_lowercase().ends_with(".gz") {
+        GzDecoder::new(file).read_to_end(&mut bytes)?;
+    } else {
+        let mut file = file;
+        file.read_to_end(&mut bytes)?;
+    }
+
+    let header = parse_header(&bytes)?;
+    if header.dims.len() < 3 || header.dims[..3].contains(&0) {
+        bail!("Not a 3D volume, dimensions {:?}", header.dims);
+    }
+    let dims = [header.dims[0], header.dims[1], header.dims[2]];
+    // Dimensions come from the file, NIfTI-2 ones are 64-bit and can overflow when multiplied
+    let too_short = || anyhow!("File too short for {}×{}×{} voxels", dims[0], dims[1], dims[2]);
+    let time_points = header.dims[3..].iter().try_fold(1usize, |product, &dim| product.checked_mul(dim)).ok_or_else(too_short)?.max(1);
+    let voxels = dims.iter().try_fold(1usize, |product, &dim| product.checked_mul(dim)).ok_or_else(too_short)?;
+
+    let (size, convert): (usize, fn([u8; 8]) -> f64) = match header.datatype {
+        2 => (1, |b| b[0] as f64),
+        256 => (1, |b| b[0] as i8 as f64),
+        4 => (2, |b| i16::from_le_bytes([b[0], b[1]]) as f64),
+        512 => (2, |b| u16::from_le_bytes([b[0], b[1]]) as f64),
+        8 => (4, |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64),
+        768 => (4, |b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64),
+        16 => (4, |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64),
+        1024 => (8, |b| i64::from_le_bytes(b) as f64),
+        1280 => (8, |b| u64::from_le_bytes(b) as f64),
+        64 => (8, f64::from_le_bytes),
+        datatype => bail!("NIfTI data type {} is not supported", datatype),
+    };
+    let end = voxels.checked_mul(size).and_then(|length| header.vox_offset.checked_add(length)).ok_or_else(too_short)?;
+    let data_bytes = bytes.get(header.vox_offset..end).ok_or_else(too_short)?;
+    let (slope, intercept) = if header.slope != 0.0 && header.slope.is_finite() {
+        (header.slope as f64, if header.intercept.is_finite() { header.intercept as f64 } else { 0.0 })
+    } else {
+        (1.0, 0.0)
+    };
+    let data: Vec<f32> = data_bytes.chunks_exact(size)
+        .map(|chunk| {
+            let mut value = [0u8; 8];
+            value[..size].copy_from_slice(chunk);
+            if header.big_endian {
+                value[..size].reverse();
+            }
+            (convert(value) * slope + intercept) as f32
+        })
+        .collect();
+
+    let range = data.iter().filter(|v| v.is_finite())
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &v| (min.min(v), max.max(v)));
+    Ok(Volume {
+        data,
+        dims,
+        spacing: header.spacing,
+        time_points,
+        range: if range.0 <= range.1 { range } else { (0.0, 0.0) },
+        plane: Plane::Axial,
+        slice: dims[2] / 2,
+        edits: Vec::new(),
+    })
+}
+
+fn parse_header(bytes: &[u8]) -> anyhow::Result<Header> {
+    let field = |offset: usize, len: usize| bytes.get(offset..offset + len).ok_or_else(|| anyhow!("Truncated NIfTI header"));
+    let sizeof_hdr = field(0, 4)?;
+    let (version, big_endian) = match (i32::from_le_bytes(sizeof_hdr.try_into()?), i32::from_be_bytes(sizeof_hdr.try_into()?)) {
+        (348, _) => (1, false),
+        (_, 348) => (1, true),
+        (540, _) => (2, false),
+        (_, 540) => (2, true),
+        _ => bail!("Not a NIfTI file"),
+    };
+    // Reads a number of the file's byte order
+    let read = |offset: usize, len: usize| -> anyhow::Result<[u8; 8]> {
+        let mut value = [0u8; 8];
+        value[..len].copy_from_slice(field(offset, len)?);
+        if big_endian {
+            value[..len].reverse();
+        }
+        Ok(value)
+    };
+    let i16_at = |offset| read(offset, 2).map(|b| i16::from_le_bytes([b[0], b[1]]) as i64);
+    let i64_at = |offset| read(offset, 8).map(i64::from_le_bytes);
+    let f32_at = |offset| read(offset, 4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    let f64_at = |offset| read(offset, 8).map(f64::from_le_bytes);
+
+    let (magic, single_file) = if version == 1 { (field(344, 4)?, b"n+1\0".as_slice()) } else { (field(4, 4)?, b"n+2\0".as_slice()) };
+    if magic != single_file {
+        bail!("Only single-file NIfTI (.nii) is supported, not .hdr/.img pairs");
+    }
+    let dim_at = |i: usize| if version == 1 { i16_at(40 + 2 * i) } else { i64_at(16 + 8 * i) };
+    let pixdim_at = |i: usize| if version == 1 { f32_at(76 + 4 * i) } else { f64_at(104 + 8 * i).map(|v| v as f32) };
+    let rank = dim_at(0)?.clamp(0, 7) as usize;
+    let dims = (1..=rank).map(|i| dim_at(i).map(|d| d.max(0) as usize)).collect::<anyhow::Result<Vec<_>>>()?;
+    let spacing = [pixdim_at(1)?.abs(), pixdim_at(2)?.abs(), pixdim_at(3)?.abs()];
+    let (datatype, vox_offset, slope, intercept) = if version == 1 {
+        (i16_at(70)?, f32_at(108)? as usize, f32_at(112)?, f32_at(116)?)
+    } else {
+        (i16_at(12)?, i64_at(168)?.max(0) as usize, f64_at(176)? as f32, f64_at(184)? as f32)
+    };
+    Ok(Header { dims, datatype: datatype as i16, spacing, vox_offset, slope, intercept, big_endian })
+}
@@ -0,0 +1,153 @@
+//! OME-TIFF microscopy files. The OME-XML in the description of the first page gives the channel,
+//! z and time axes of the planes, which are stored as pages of the TIFF in the dimension order
+//! of the XML. One plane is read at a time. Datasets whose planes are spread over several files
+//! are shown as plain TIFFs.
+
+use log::{info, warn};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use tiff::decoder::Decoder;
+use tiff::tags::Tag;
+
+use crate::tiff_format;
+
+/// Axes of the plane position, in the order of `OmeStack::position`
+pub const AXIS_NAMES: [&str; 3] = ["Z", "Channel", "Time"];
+
+/// Channel, z and time axes of the planes of an OME-TIFF
+pub struct OmeStack {
+    /// Planes along z, channel and time
+    sizes: [usize; 3],
+    /// Axes in page order, fastest changing first
+    order: [usize; 3],
+    /// Page of the first plane
+    first_page: usize,
+    pub channel_names: Vec<String>,
+    /// Shown z, channel and time point
+    position: [usize; 3],
+}
+
+impl OmeStack {
+    /// Axes of an OME-TIFF with more than one plane, `None` for other files
+    pub fn open(path: &Path) -> Option<Self> {
+        if !tiff_format::is_tiff_file(path) {
+            return None;
+        }
+        let mut decoder = Decoder::new(BufReader::new(File::open(path).ok()?)).ok()?;
+        let description = decoder.get_tag_ascii_string(Tag::ImageDescription).ok()?;
+        let stack = Self::parse(&description)?;
+        // Every plane has to be a page of this file
+        let needed = stack.first_page + stack.plane_count();
+        let mut pages = 1;
+        while pages < needed && decoder.more_images() && decoder.next_image().is_ok() {
+            pages += 1;
+        }
+        if pages < needed {
+            warn!("OME-TIFF {:?} has {} of its {} planes, the others are in other files", path, pages, needed);
+            return None;
+        }
+        info!("OME-TIFF with {} z planes, {} channels and {} time points", stack.sizes[0], stack.sizes[1], stack.sizes[2]);
+        Some(stack)
+    }
+
+    /// Axes from the OME-XML, `None` if it is not OME or has a single plane
+    fn parse(xml: &str) -> Option<Self> {
+        let pixels = start_tags(xml, "Pixels").next()?;
+        let size = |name: &str| attribute(pixels, name).and_then(|value| value.parse::<usize>().ok()).unwrap_or(1).max(1);
+        let channels: Vec<&str> = start_tags(xml, "Channel").collect();
+        // Interleaved RGB planes count each sample as a channel
+        let samples = channels.first().and_then(|channel| attribute(channel, "SamplesPerPixel"))
+            .and_then(|value| value.parse::<usize>().ok()).unwrap_or(1).max(1);
+        let sizes = [size("SizeZ"), (size("SizeC") / samples).max(1), size("SizeT")];
+        // "XYZCT" and the other orders, X and Y vary fastest within a page
+        let mut order = [0, 1, 2];
+        let dimension_order = attribute(pixels, "DimensionOrder").unwrap_or("XYZCT");
+        for (slot, axis) in order.iter_mut().zip(dimension_order.chars().skip(2)) {
+            *slot = match axis {
+                'Z' => 0,
+                'C' => 1,
+                'T' => 2,
+                _ => return None,
+            };
+        }
+        if order[0] == order[1] || order[1] == order[2] || order[0] == order[2] {
+            return None;
+        }
+        let first_page = start_tags(xml, "TiffData").next()
+            .and_then(|tiff_data| attribute(tiff_data, "IFD")).and_then(|value| value.parse().ok()).unwrap_or(0);
+        let channel_names = (0..sizes[1]).map(|index| {
+            channels.get(index).and_then(|channel| attribute(channel, "Name")).map_or_else(|| format!("Channel {}", index + 1), unescape)
+        }).collect();
+        let stack = Self { sizes, order, first_page, channel_names, position: [0; 3] };
+        (stack.plane_count() > 1).then_some(stack)
+    }
+
+    pub fn plane_count(&self) -> usize {
+        self.sizes.iter().product()
+    }
+
+    /// Planes along z, channel and time
+    pub fn sizes(&self) -> [usize; 3] {
+        self.sizes
+    }
+
+    /// Shown z, channel and time point
+    pub fn position(&self) -> [usize; 3] {
+        self.position
+    }
+
+    /// Select a plane, each axis clamped to its size. Returns whether the plane changed.
+    pub fn set_position(&mut self, position: [usize; 3]) -> bool {
+        let position = [0, 1, 2].map(|axis| position[axis].min(self.sizes[axis] - 1));
+        let changed = position != self.position;
+        self.position = position;
+        changed
+    }
+
+    /// Position `step` planes away along z, or along time when there is a single z plane,
+    /// wrapping around at both ends
+    pub fn stepped(&self, step: i32) -> [usize; 3] {
+        let axis = if self.sizes[0] > 1 { 0 } else if self.sizes[2] > 1 { 2 } else { 1 };
+        let mut position = self.position;
+        position[axis] = (position[axis] as i64 + step as i64).rem_euclid(self.sizes[axis] as i64) as usize;
+        position
+    }
+
+    /// TIFF page of the shown plane
+    pub fn page(&self) -> usize {
+        let (mut page, mut stride) = (self.first_page, 1);
+        for axis in self.order {
+            page += self.position[axis] * stride;
+            stride *= self.sizes[axis];
+        }
+        page
+    }
+}
+
+/// Start tags `<name …>` of the XML, with or without a namespace prefix
+fn start_tags<'a>(xml: &'a str, name: &'a str) -> impl Iterator<Item = &'a str> {
+    xml.match_indices('<').filter_map(move |(start, _)| {
+        let tag = &xml[start + 1..];
+        let end = tag.find('>')?;
+        let tag = &tag[..end];
+        let tag_name = tag.split(|c: char| c.is_whitespace() || c == '/').next()?;
+        (tag_name.rsplit(':').next() == Some(name)).then_some(tag)
+    })
+}
+
+/// Value of `name="…"` in a start tag
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    tag.match_indices(name).find_map(|(start, _)| {
+        // Whole attribute names only, "SizeC" is not "PhysicalSizeC"
+        let before = tag[..start].chars().next_back()?;
+        let rest = tag[start + name.len()..].trim_start().strip_prefix('=')?.trim_start();
+        let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value = &rest[1..];
+        before.is_whitespace().then(|| &value[..value.find(quote).unwrap_or(value.len())])
+    })
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
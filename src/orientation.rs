@@ -0,0 +1,309 @@
+use anyhow::bail;
+use image::{DynamicImage, ImageDecoder, ImageReader};
+use std::fs;
+use std::path::Path;
+
+/// A single rotate/flip command
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum OrientationOp {
+    RotateCw,
+    RotateCcw,
+    FlipHorizontal,
+    FlipVertical,
+}
+
+impl OrientationOp {
+    pub fn apply_to_image(self, img: &DynamicImage) -> DynamicImage {
+        match self {
+            OrientationOp::RotateCw => img.rotate90(),
+            OrientationOp::RotateCcw => img.rotate270(),
+            OrientationOp::FlipHorizontal => img.fliph(),
+            OrientationOp::FlipVertical => img.flipv(),
+        }
+    }
+
+    /// Reorient an interleaved floating point buffer, returns the data and its new dimensions
+    pub fn apply_to_fp(self, data: &[f32], width: u32, height: u32, channels: usize) -> (Vec<f32>, u32, u32) {
+        let (w, h) = (width as usize, height as usize);
+        let (new_w, new_h) = match self {
+            OrientationOp::RotateCw | OrientationOp::RotateCcw => (h, w),
+            OrientationOp::FlipHorizontal | OrientationOp::FlipVertical => (w, h),
+        };
+
+        let mut output = vec![0.0f32; data.len()];
+        for y in 0..new_h {
+            for x in 0..new_w {
+                // Source pixel of output (x, y)
+                let (sx, sy) = match self {
+                    OrientationOp::RotateCw => (y, h - 1 - x),
+                    OrientationOp::RotateCcw => (w - 1 - y, x),
+                    OrientationOp::FlipHorizontal => (w - 1 - x, y),
+                    OrientationOp::FlipVertical => (x, h - 1 - y),
+                };
+                let src = (sy * w + sx) * channels;
+                let dst = (y * new_w + x) * channels;
+                if src + channels <= data.len() && dst + channels <= output.len() {
+                    output[dst..dst + channels].copy_from_slice(&data[src..src + channels]);
+                }
+            }
+        }
+        (output, new_w as u32, new_h as u32)
+    }
+}
+
+/// Element of the dihedral group: an optional horizontal flip followed by clockwise quarter turns.
+/// Tracks the net effect of all rotate/flip commands and maps to EXIF orientation values.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct Orientation {
+    pub flipped: bool,
+    pub quarter_turns: u8,
+}
+
+impl Orientation {
+    pub fn is_identity(&self) -> bool {
+        !self.flipped && self.quarter_turns == 0
+    }
+
+    /// Orientation after additionally applying `op`
+    pub fn then(self, op: OrientationOp) -> Self {
+        let r = self.quarter_turns;
+        match op {
+            OrientationOp::RotateCw => Self { quarter_turns: (r + 1) % 4, ..self },
+            OrientationOp::RotateCcw => Self { quarter_turns: (r + 3) % 4, ..self },
+            // A flip after a rotation equals the inverse rotation after the flip
+            OrientationOp::FlipHorizontal => Self { flipped: !self.flipped, quarter_turns: (4 - r) % 4 },
+            OrientationOp::FlipVertical => Self { flipped: !self.flipped, quarter_turns: (6 - r) % 4 },
+        }
+    }
+
+    /// Orientation after additionally applying all of `other`
+    pub fn then_orientation(self, other: Orientation) -> Self {
+        let mut result = self;
+        if other.flipped {
+            result = result.then(OrientationOp::FlipHorizontal);
+        }
+        for _ in 0..other.quarter_turns {
+            result = result.then(OrientationOp::RotateCw);
+        }
+        result
+    }
+
+    /// Size of an image of `size` after applying the orientation
+    pub fn apply_size(self, size: egui::Vec2) -> egui::Vec2 {
+        if self.quarter_turns % 2 == 1 { egui::vec2(size.y, size.x) } else { size }
+    }
+
+    /// Direction `v` in the image after applying the orientation
+    pub fn apply_vector(self, v: egui::Vec2) -> egui::Vec2 {
+        let mut v = if self.flipped { egui::vec2(-v.x, v.y) } else { v };
+        for _ in 0..self.quarter_turns {
+            v = egui::vec2(-v.y, v.x);
+        }
+        v
+    }
+
+    /// Inverse of `apply_vector`
+    pub fn unapply_vector(self, v: egui::Vec2) -> egui::Vec2 {
+        let mut v = v;
+        for _ in 0..self.quarter_turns {
+            v = egui::vec2(v.y, -v.x);
+        }
+        if self.flipped { egui::vec2(-v.x, v.y) } else { v }
+    }
+
+    pub fn from_exif(value: u8) -> Self {
+        let (flipped, quarter_turns) = match value {
+            2 => (true, 0),
+            3 => (false, 2),
+            4 => (true, 2),
+            5 => (true, 3),
+            6 => (false, 1),
+            7 => (true, 1),
+            8 => (false, 3),
+            _ => (false, 0),
+        };
+        Self { flipped, quarter_turns }
+    }
+
+    pub fn to_exif(self) -> u8 {
+        match (self.flipped, self.quarter_turns) {
+            (false, 0) => 1,
+            (true, 0) => 2,
+            (false, 2) => 3,
+            (true, 2) => 4,
+            (true, 3) => 5,
+            (false, 1) => 6,
+            (true, 1) => 7,
+            (false, _) => 8,
+            (true, _) => 5,
+        }
+    }
+}
+
+/// Image pixels on screen, for an image shown rotated or mirrored without changing its data.
+/// Image positions are in pixels of the original layout, so readouts and overlays refer to the data.
+#[derive(Clone, Copy, Debug)]
+pub struct ViewMapping {
+    /// Screen position of the image center
+    pub center: egui::Pos2,
+    /// Screen points per image pixel
+    pub scale: f32,
+    pub orientation: Orientation,
+    /// Image size in pixels
+    pub size: egui::Vec2,
+}
+
+impl ViewMapping {
+    pub fn to_screen(self, point: egui::Pos2) -> egui::Pos2 {
+        self.center + self.orientation.apply_vector(point.to_vec2() - self.size / 2.0) * self.scale
+    }
+
+    pub fn to_image(self, pos: egui::Pos2) -> egui::Pos2 {
+        (self.size / 2.0 + self.orientation.unapply_vector((pos - self.center) / self.scale)).to_pos2()
+    }
+
+    /// Screen rectangle of the image rectangle from `min` to `max`
+    pub fn rect_to_screen(self, min: egui::Pos2, max: egui::Pos2) -> egui::Rect {
+        egui::Rect::from_two_pos(self.to_screen(min), self.to_screen(max))
+    }
+
+    /// Image rectangle covered by the screen rectangle `rect`
+    pub fn rect_to_image(self, rect: egui::Rect) -> egui::Rect {
+        egui::Rect::from_two_pos(self.to_image(rect.min), self.to_image(rect.max))
+    }
+
+    /// Add the part of the image texture under the screen rectangle `rect` to a mesh
+    pub fn add_screen_rect(self, mesh: &mut egui::Mesh, rect: egui::Rect, tint: egui::Color32) {
+        let first = mesh.vertices.len() as u32;
+        for pos in [rect.left_top(), rect.right_top(), rect.right_bottom(), rect.left_bottom()] {
+            let uv = (self.to_image(pos).to_vec2() / self.size).to_pos2();
+            mesh.vertices.push(egui::epaint::Vertex { pos, uv, color: tint });
+        }
+        mesh.add_triangle(first, first + 1, first + 2);
+        mesh.add_triangle(first, first + 2, first + 3);
+    }
+
+    /// Quad showing the whole texture of the image
+    pub fn texture_mesh(self, texture: egui::TextureId, tint: egui::Color32) -> egui::Mesh {
+        let mut mesh = egui::Mesh::with_texture(texture);
+        for uv in [egui::pos2(0.0, 0.0), egui::pos2(1.0, 0.0), egui::pos2(1.0, 1.0), egui::pos2(0.0, 1.0)] {
+            let pos = self.to_screen((uv.to_vec2() * self.size).to_pos2());
+            mesh.vertices.push(egui::epaint::Vertex { pos, uv, color: tint });
+        }
+        mesh.add_triangle(0, 1, 2);
+        mesh.add_triangle(0, 2, 3);
+        mesh
+    }
+}
+
+/// Decode an image with its EXIF orientation applied
+pub fn open_oriented(path: &Path) -> image::ImageResult<DynamicImage> {
+    let mut decoder = ImageReader::open(path)?.with_guessed_format()?.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+    Ok(img)
+}
+
+/// EXIF orientation stored in a file, identity if there is none
+pub fn read_file_orientation(path: &Path) -> Orientation {
+    ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .ok()
+        .and_then(|reader| reader.into_decoder().ok())
+        .and_then(|mut decoder| decoder.orientation().ok())
+        .map(|orientation| Orientation::from_exif(orientation.to_exif()))
+        .unwrap_or_default()
+}
+
+pub fn is_jpeg(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| matches!(ext.to_string_lossy().to_lowercase().as_str(), "jpg" | "jpeg"))
+        .unwrap_or(false)
+}
+
+/// Losslessly store an orientation in a JPEG by rewriting only its EXIF orientation tag.
+/// The compressed image data is left untouched.
+pub fn write_jpeg_orientation(path: &Path, orientation: Orientation) -> anyhow::Result<()> {
+    let mut data = fs::read(path)?;
+    if data.len() < 4 || data[0..2] != [0xFF, 0xD8] {
+        bail!("{:?} is not a JPEG file", path);
+    }
+    let value = orientation.to_exif() as u16;
+
+    let mut pos = 2;
+    let mut insert_at = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            bail!("Corrupt JPEG marker at offset {}", pos);
+        }
+        let marker = data[pos + 1];
+        if marker == 0xDA || marker == 0xD9 {
+            // Start of scan, no more metadata segments
+            break;
+        }
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        // The length counts its own two bytes
+        if length < 2 || pos + 2 + length > data.len() {
+            bail!("Corrupt JPEG segment length at offset {}", pos);
+        }
+        let end = pos + 2 + length;
+        if marker == 0xE0 && pos == 2 {
+            // Keep a JFIF header first
+            insert_at = end;
+        }
+        if marker == 0xE1 && data.get(pos + 4..end).is_some_and(|body| body.starts_with(b"Exif\0\0")) {
+            if patch_tiff_orientation(&mut data[pos + 10..end], value) {
+                fs::write(path, &data)?;
+                return Ok(());
+            }
+            bail!("EXIF block has no orientation tag, the rotation cannot be stored losslessly");
+        }
+        pos = end;
+    }
+
+    // No EXIF at all: insert a minimal block holding only the orientation
+    let mut segment = vec![0xFF, 0xE1, 0x00, 34];
+    segment.extend_from_slice(b"Exif\0\0");
+    segment.extend_from_slice(b"MM\0\x2A\0\0\0\x08"); // Big endian TIFF header, IFD0 at offset 8
+    segment.extend_from_slice(&[0x00, 0x01]); // One entry
+    segment.extend_from_slice(&[0x01, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01]); // Orientation, SHORT, count 1
+    segment.extend_from_slice(&value.to_be_bytes());
+    segment.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00]); // Padding, no next IFD
+    data.splice(insert_at..insert_at, segment);
+    fs::write(path, &data)?;
+    Ok(())
+}
+
+/// Overwrite the orientation entry of IFD0 in a TIFF structure, false if there is none
+fn patch_tiff_orientation(tiff: &mut [u8], value: u16) -> bool {
+    let little_endian = match tiff.get(0..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => return false,
+    };
+    let read_u16 = |bytes: &[u8], offset: usize| -> Option<u16> {
+        let raw = [*bytes.get(offset)?, *bytes.get(offset + 1)?];
+        Some(if little_endian { u16::from_le_bytes(raw) } else { u16::from_be_bytes(raw) })
+    };
+    let read_u32 = |bytes: &[u8], offset: usize| -> Option<u32> {
+        let raw = [*bytes.get(offset)?, *bytes.get(offset + 1)?, *bytes.get(offset + 2)?, *bytes.get(offset + 3)?];
+        Some(if little_endian { u32::from_le_bytes(raw) } else { u32::from_be_bytes(raw) })
+    };
+
+    let Some(ifd0) = read_u32(tiff, 4).map(|o| o as usize) else {
+        return false;
+    };
+    let Some(count) = read_u16(tiff, ifd0) else {
+        return false;
+    };
+    for i in 0..count as usize {
+        let entry = ifd0 + 2 + i * 12;
+        if read_u16(tiff, entry) == Some(0x0112) && entry + 10 <= tiff.len() {
+            let bytes = if little_endian { value.to_le_bytes() } else { value.to_be_bytes() };
+            tiff[entry + 8..entry + 10].copy_from_slice(&bytes);
+            return true;
+        }
+    }
+    false
+}
@@ -0,0 +1,51 @@
+use std::fs;
+use std::path::Path;
+
+/// Name written into exported palettes
+const PALETTE_NAME: &str = "Image Viewer samples";
+
+/// `#rrggbb` of a color
+pub fn hex(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+/// Write the colors as a GIMP palette (`.gpl`), also read by Inkscape and Krita
+pub fn write_gpl(path: &Path, colors: &[[u8; 3]]) -> anyhow::Result<()> {
+    let mut text = format!("GIMP Palette\nName: {}\nColumns: 8\n#\n", PALETTE_NAME);
+    for &color in colors {
+        text.push_str(&format!("{:3} {:3} {:3}\t{}\n", color[0], color[1], color[2], hex(color)));
+    }
+    fs::write(path, text)?;
+    Ok(())
+}
+
+/// Write the colors as an Adobe Swatch Exchange file (`.ase`): big endian, one RGB color entry
+/// per color, named by its hex code
+pub fn write_ase(path: &Path, colors: &[[u8; 3]]) -> anyhow::Result<()> {
+    let mut data = Vec::new();
+    data.extend_from_slice(b"ASEF");
+    data.extend_from_slice(&1u16.to_be_bytes());
+    data.extend_from_slice(&0u16.to_be_bytes());
+    data.extend_from_slice(&(colors.len() as u32).to_be_bytes());
+    for &color in colors {
+        // Name as UTF-16 with a terminating zero, its length counts the terminator
+        let name: Vec<u16> = hex(color).encode_utf16().chain(std::iter::once(0)).collect();
+        let mut block = Vec::new();
+        block.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        for unit in name {
+            block.extend_from_slice(&unit.to_be_bytes());
+        }
+        block.extend_from_slice(b"RGB ");
+        for channel in color {
+            block.extend_from_slice(&(channel as f32 / 255.0).to_be_bytes());
+        }
+        // Color type: 0 global, 1 spot, 2 normal
+        block.extend_from_slice(&2u16.to_be_bytes());
+
+        data.extend_from_slice(&0x0001u16.to_be_bytes());
+        data.extend_from_slice(&(block.len() as u32).to_be_bytes());
+        data.extend_from_slice(&block);
+    }
+    fs::write(path, data)?;
+    Ok(())
+}
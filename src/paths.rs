@@ -0,0 +1,119 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Absolute form of a path as given on the command line, by drag and drop or a dialog.
+/// On Windows, paths longer than MAX_PATH get the extended-length `\\?\` prefix so they
+/// also work with APIs and tools (e.g. ffmpeg) that do not add it themselves.
+pub fn normalize(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    extend_length(absolute)
+}
+
+#[cfg(windows)]
+fn extend_length(path: PathBuf) -> PathBuf {
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    const MAX_PATH: usize = 260;
+    let wide: Vec<u16> = path.as_os_str().encode_wide().collect();
+    let starts_with = |prefix: &str| wide.starts_with(&prefix.encode_utf16().collect::<Vec<_>>());
+    if wide.len() < MAX_PATH || starts_with(r"\\?\") {
+        return path;
+    }
+    // UNC paths `\\server\share` become `\\?\UNC\server\share`
+    let (prefix, rest) = if starts_with(r"\\") { (r"\\?\UNC\", &wide[2..]) } else { (r"\\?\", &wide[..]) };
+    let mut extended = OsString::from(prefix);
+    extended.push(OsString::from_wide(rest));
+    PathBuf::from(extended)
+}
+
+#[cfg(not(windows))]
+fn extend_length(path: PathBuf) -> PathBuf {
+    path
+}
+
+/// Path for display, without the `\\?\` prefix. Invalid UTF-8 is shown as replacement characters.
+pub fn display(path: &Path) -> String {
+    let text = path.to_string_lossy();
+    if let Some(unc) = text.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", unc)
+    } else if let Some(local) = text.strip_prefix(r"\\?\") {
+        local.to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+/// `dir/stem.ext` → `dir/stem<suffix>`, without a lossy conversion of the file name
+pub fn with_stem_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name: OsString = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Serde for `Option<PathBuf>` that also stores file names which are not valid UTF-8.
+/// Such paths are written as an array of their raw OS encoding (bytes on Unix, UTF-16 on Windows).
+pub mod serde_option {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::path::PathBuf;
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        Text(String),
+        Raw(Vec<RawUnit>),
+    }
+
+    #[cfg(windows)]
+    type RawUnit = u16;
+    #[cfg(not(windows))]
+    type RawUnit = u8;
+
+    #[cfg(unix)]
+    fn to_raw(path: &std::path::Path) -> Vec<RawUnit> {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes().to_vec()
+    }
+
+    #[cfg(unix)]
+    fn from_raw(raw: Vec<RawUnit>) -> PathBuf {
+        use std::os::unix::ffi::OsStringExt;
+        PathBuf::from(std::ffi::OsString::from_vec(raw))
+    }
+
+    #[cfg(windows)]
+    fn to_raw(path: &std::path::Path) -> Vec<RawUnit> {
+        use std::os::windows::ffi::OsStrExt;
+        path.as_os_str().encode_wide().collect()
+    }
+
+    #[cfg(windows)]
+    fn from_raw(raw: Vec<RawUnit>) -> PathBuf {
+        use std::os::windows::ffi::OsStringExt;
+        PathBuf::from(std::ffi::OsString::from_wide(&raw))
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn to_raw(path: &std::path::Path) -> Vec<RawUnit> {
+        path.to_string_lossy().into_owned().into_bytes()
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn from_raw(raw: Vec<RawUnit>) -> PathBuf {
+        PathBuf::from(String::from_utf8_lossy(&raw).into_owned())
+    }
+
+    pub fn serialize<S: Serializer>(path: &Option<PathBuf>, serializer: S) -> Result<S::Ok, S::Error> {
+        path.as_ref()
+            .map(|path| match path.to_str() {
+                Some(text) => Stored::Text(text.to_string()),
+                None => Stored::Raw(to_raw(path)),
+            })
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<PathBuf>, D::Error> {
+        Ok(Option::<Stored>::deserialize(deserializer)?.map(|stored| match stored {
+            Stored::Text(text) => PathBuf::from(text),
+            Stored::Raw(raw) => from_raw(raw),
+        }))
+    }
+}
@@ -0,0 +1,68 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Pixel pinned on the image whose values are listed in the probe table
+pub struct Probe {
+    pub x: u32,
+    pub y: u32,
+    /// Original sample values, floating point data when the image has it. `None` outside the image.
+    pub original: Option<Vec<f32>>,
+    /// RGBA after normalization, transform, channel and colormap, as shown on screen
+    pub displayed: Option<[u8; 4]>,
+}
+
+impl Probe {
+    pub fn new(x: u32, y: u32) -> Self {
+        Self { x, y, original: None, displayed: None }
+    }
+
+    /// Original values as text, e.g. `12, 200, 31`
+    pub fn original_text(&self) -> String {
+        match &self.original {
+            Some(values) => values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", "),
+            None => "—".to_string(),
+        }
+    }
+
+    pub fn displayed_text(&self) -> String {
+        match self.displayed {
+            Some([r, g, b, a]) => format!("{}, {}, {}, {}", r, g, b, a),
+            None => "—".to_string(),
+        }
+    }
+}
+
+/// Column names of the original values for a channel count
+fn channel_names(channels: usize) -> Vec<String> {
+    match channels {
+        1 => vec!["value".to_string()],
+        3 => ["r", "g", "b"].map(String::from).to_vec(),
+        4 => ["r", "g", "b", "a"].map(String::from).to_vec(),
+        n => (0..n).map(|c| format!("channel_{}", c)).collect(),
+    }
+}
+
+/// Write the probe table as CSV: index, position, original values and displayed RGBA.
+/// Probes outside the current image have empty value columns.
+pub fn write_csv(path: &Path, probes: &[Probe]) -> anyhow::Result<()> {
+    let channels = probes.iter().filter_map(|p| p.original.as_ref()).map(Vec::len).max().unwrap_or(1);
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut header = vec!["probe".to_string(), "x".to_string(), "y".to_string()];
+    header.extend(channel_names(channels));
+    header.extend(["display_r", "display_g", "display_b", "display_a"].map(String::from));
+    writeln!(writer, "{}", header.join(","))?;
+
+    for (index, probe) in probes.iter().enumerate() {
+        let mut row = vec![(index + 1).to_string(), probe.x.to_string(), probe.y.to_string()];
+        let original = probe.original.as_deref().unwrap_or_default();
+        row.extend((0..channels).map(|c| original.get(c).map(|v| v.to_string()).unwrap_or_default()));
+        match probe.displayed {
+            Some(rgba) => row.extend(rgba.map(|v| v.to_string())),
+            None => row.extend(std::iter::repeat_n(String::new(), 4)),
+        }
+        writeln!(writer, "{}", row.join(","))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,317 @@
+//! File details for the Info dialog that the decoded image does not carry: container format,
+//! compression, stored resolution, the number of pages and a summary of the EXIF metadata. Only
+//! the file header is read.
+
+use image::{ImageFormat, ImageReader};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+use tiff::decoder::Decoder;
+use tiff::tags::Tag;
+
+use crate::tiff_format;
+
+/// Bytes read to find the PNG and JPEG metadata, which come before the image data
+const HEADER_BYTES: u64 = 256 * 1024;
+/// TIFF pages counted at most, huge stacks are not walked to the end
+const MAX_COUNTED_PAGES: usize = 10_000;
+/// Entries read of an IFD and bytes read of a value, bounds for broken EXIF blocks
+const MAX_IFD_ENTRIES: usize = 1024;
+const MAX_VALUE_BYTES: usize = 4096;
+
+#[derive(Clone, Debug, Default)]
+pub struct FileProperties {
+    pub format: String,
+    pub compression: Option<String>,
+    /// Horizontal and vertical pixels per unit, and the unit
+    pub resolution: Option<(f64, f64, &'static str)>,
+    /// Pages of a TIFF with more than one image
+    pub pages: Option<usize>,
+    pub exif: ExifSummary,
+}
+
+/// Camera and capture settings of the EXIF metadata
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ExifSummary {
+    /// Make and model
+    pub camera: Option<String>,
+    pub lens: Option<String>,
+    /// Capture time as stored, `YYYY:MM:DD HH:MM:SS`
+    pub taken: Option<String>,
+    /// Exposure time in seconds
+    pub exposure_time: Option<f64>,
+    pub f_number: Option<f64>,
+    pub iso: Option<u32>,
+    /// Focal length in millimeters
+    pub focal_length: Option<f64>,
+    pub software: Option<String>,
+}
+
+impl ExifSummary {
+    /// Label and text of the fields that are present
+    pub fn rows(&self) -> Vec<(&'static str, String)> {
+        let exposure = self.exposure_time.map(|time| match time {
+            time if time > 0.0 && time < 1.0 => format!("1/{:.0} s", 1.0 / time),
+            time => format!("{} s", time),
+        });
+        [
+            ("Camera", self.camera.clone()),
+            ("Lens", self.lens.clone()),
+            ("Taken", self.taken.clone()),
+            ("Exposure", exposure),
+            ("Aperture", self.f_number.map(|f| format!("f/{}", f))),
+            ("ISO", self.iso.map(|iso| iso.to_string())),
+            ("Focal length", self.focal_length.map(|length| format!("{} mm", length))),
+            ("Software", self.software.clone()),
+        ].into_iter().filter_map(|(label, value)| Some((label, value?))).collect()
+    }
+}
+
+/// Read the properties of the file at `path`, unknown ones are left empty
+pub fn read(path: &Path) -> FileProperties {
+    let format = ImageReader::open(path).ok().and_then(|reader| reader.with_guessed_format().ok()).and_then(|reader| reader.format());
+    let mut properties = FileProperties {
+        format: match format {
+            Some(format) => format!("{:?}", format).to_uppercase(),
+            None => path.extension().map(|ext| ext.to_string_lossy().to_uppercase()).unwrap_or_default(),
+        },
+        ..Default::default()
+    };
+    let header = || -> Option<Vec<u8>> {
+        let mut bytes = Vec::new();
+        File::open(path).ok()?.take(HEADER_BYTES).read_to_end(&mut bytes).ok()?;
+        Some(bytes)
+    };
+    match format {
+        Some(ImageFormat::Tiff) => {
+            read_tiff(path, &mut properties);
+            if let Ok(file) = File::open(path) {
+                properties.exif = exif_summary(&mut BufReader::new(file));
+            }
+        }
+        Some(ImageFormat::Png) => {
+            properties.compression = Some("Deflate".to_string());
+            properties.resolution = header().and_then(|bytes| png_resolution(&bytes));
+        }
+        Some(ImageFormat::Jpeg) => {
+            if let Some(bytes) = header() {
+                read_jpeg(&bytes, &mut properties);
+            }
+        }
+        _ => {}
+    }
+    properties
+}
+
+fn read_tiff(path: &Path, properties: &mut FileProperties) {
+    let Some(mut decoder) = File::open(path).ok().and_then(|file| Decoder::new(BufReader::new(file)).ok()) else {
+        return;
+    };
+    properties.compression = Some(tiff_format::compression_name(tiff_format::compression(&mut decoder)));
+    if tiff_format::is_big_tiff(path) {
+        properties.format = "BIGTIFF".to_string();
+    }
+    let rational = |tag: Tag, decoder: &mut Decoder<BufReader<File>>| match decoder.find_tag(tag).ok().flatten() {
+        Some(tiff::decoder::ifd::Value::Rational(numerator, denominator)) if denominator > 0 => Some(numerator as f64 / denominator as f64),
+        _ => None,
+    };
+    if let Some(x) = rational(Tag::XResolution, &mut decoder) {
+        let y = rational(Tag::YResolution, &mut decoder).unwrap_or(x);
+        // Unit 1 has no absolute size, writers use it with a placeholder resolution of 1
+        properties.resolution = match decoder.find_tag_unsigned::<u16>(Tag::ResolutionUnit).ok().flatten().unwrap_or(2) {
+            2 => Some((x, y, "dpi")),
+            3 => Some((x, y, "pixels/cm")),
+            _ => None,
+        };
+    }
+    let mut pages = 1;
+    while pages < MAX_COUNTED_PAGES && decoder.more_images() && decoder.next_image().is_ok() {
+        pages += 1;
+    }
+    properties.pages = (pages > 1).then_some(pages);
+}
+
+/// Resolution from the pHYs chunk, which stores pixels per meter
+fn png_resolution(bytes: &[u8]) -> Option<(f64, f64, &'static str)> {
+    let mut pos = 8;
+    while pos + 8 <= bytes.len() {
+        let length = u32::from_be_bytes(bytes[pos..pos + 4].try_into().ok()?) as usize;
+        let kind = &bytes[pos + 4..pos + 8];
+        let data = bytes.get(pos + 8..pos + 8 + length)?;
+        match kind {
+            b"pHYs" if length >= 9 => {
+                let x = u32::from_be_bytes(data[0..4].try_into().ok()?) as f64;
+                let y = u32::from_be_bytes(data[4..8].try_into().ok()?) as f64;
+                // Unit 0 only gives the pixel aspect ratio
+                return (data[8] == 1).then_some((x * 0.0254, y * 0.0254, "dpi"));
+            }
+            b"IDAT" | b"IEND" => return None,
+            _ => pos += 12 + length,
+        }
+    }
+    None
+}
+
+/// Coding process from the start-of-frame marker and the JFIF density
+fn read_jpeg(bytes: &[u8], properties: &mut FileProperties) {
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() && bytes[pos] == 0xFF {
+        let marker = bytes[pos + 1];
+        let length = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let data = &bytes[(pos + 4).min(bytes.len())..(pos + 2 + length).min(bytes.len())];
+        match marker {
+            0xE0 if data.starts_with(b"JFIF\0") && data.len() >= 12 => {
+                let x = u16::from_be_bytes([data[8], data[9]]) as f64;
+                let y = u16::from_be_bytes([data[10], data[11]]) as f64;
+                properties.resolution = match data[7] {
+                    1 => Some((x, y, "dpi")),
+                    2 => Some((x, y, "pixels/cm")),
+                    _ => None,
+                };
+            }
+            0xE1 if data.starts_with(b"Exif\0\0") => properties.exif = exif_summary(&mut Cursor::new(&data[6..])),
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                properties.compression = Some(match marker {
+                    0xC0 => "JPEG baseline",
+                    0xC1 => "JPEG extended",
+                    0xC2 => "JPEG progressive",
+                    0xC3 => "JPEG lossless",
+                    _ => "JPEG arithmetic",
+                }.to_string());
+                return;
+            }
+            0xDA | 0xD9 => return,
+            _ => {}
+        }
+        pos += 2 + length;
+    }
+}
+
+/// Summary of the EXIF metadata in a TIFF structure, the whole file for TIFFs and the APP1
+/// segment of JPEGs. Missing or unreadable tags are left empty.
+fn exif_summary<R: Read + Seek>(reader: &mut R) -> ExifSummary {
+    let mut exif = ExifSummary::default();
+    let Some(mut tiff) = IfdReader::new(reader) else {
+        return exif;
+    };
+    let Some(first) = tiff.read(4, 4).map(|offset| tiff.u32(&offset)) else {
+        return exif;
+    };
+    let Some(ifd0) = tiff.entries(first) else {
+        return exif;
+    };
+    let (make, model) = (tiff.text(&ifd0, 0x010F), tiff.text(&ifd0, 0x0110));
+    exif.camera = match (make, model) {
+        // Most models already start with the make, e.g. "Canon EOS R5"
+        (Some(make), Some(model)) if !model.to_lowercase().starts_with(&make.to_lowercase()) => Some(format!("{} {}", make, model)),
+        (make, model) => model.or(make),
+    };
+    exif.software = tiff.text(&ifd0, 0x0131);
+    exif.taken = tiff.text(&ifd0, 0x0132);
+    // Capture settings are in the EXIF IFD
+    if let Some(entries) = tiff.number(&ifd0, 0x8769).and_then(|offset| tiff.entries(offset)) {
+        exif.taken = tiff.text(&entries, 0x9003).or(exif.taken);
+        exif.exposure_time = tiff.rational(&entries, 0x829A);
+        exif.f_number = tiff.rational(&entries, 0x829D);
+        exif.iso = tiff.number(&entries, 0x8827);
+        exif.focal_length = tiff.rational(&entries, 0x920A);
+        exif.lens = tiff.text(&entries, 0xA434);
+    }
+    exif
+}
+
+/// IFD entry: tag, field type, count and the value or the offset of the values
+type IfdEntry = (u16, u16, u32, [u8; 4]);
+
+/// Reads entries of a TIFF structure starting at the beginning of `reader`
+struct IfdReader<'a, R> {
+    reader: &'a mut R,
+    little_endian: bool,
+}
+
+impl<'a, R: Read + Seek> IfdReader<'a, R> {
+    fn new(reader: &'a mut R) -> Option<Self> {
+        let mut tiff = Self { reader, little_endian: false };
+        tiff.little_endian = match tiff.read(0, 2)?.as_slice() {
+            b"II" => true,
+            b"MM" => false,
+            _ => return None,
+        };
+        Some(tiff)
+    }
+
+    fn read(&mut self, offset: u64, length: usize) -> Option<Vec<u8>> {
+        let mut bytes = vec![0; length];
+        self.reader.seek(SeekFrom::Start(offset)).ok()?;
+        self.reader.read_exact(&mut bytes).ok()?;
+        Some(bytes)
+    }
+
+    fn u16(&self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        if self.little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) }
+    }
+
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) }
+    }
+
+    fn entries(&mut self, offset: u32) -> Option<Vec<IfdEntry>> {
+        let count = self.read(offset as u64, 2)?;
+        let count = self.u16(&count) as usize;
+        let raw = self.read(offset as u64 + 2, count.min(MAX_IFD_ENTRIES) * 12)?;
+        Some(raw.chunks_exact(12).map(|entry| {
+            (self.u16(&entry[0..2]), self.u16(&entry[2..4]), self.u32(&entry[4..8]), [entry[8], entry[9], entry[10], entry[11]])
+        }).collect())
+    }
+
+    /// Field type and bytes of the values of `tag`, which are stored in the entry up to 4 bytes
+    fn values(&mut self, entries: &[IfdEntry], tag: u16) -> Option<(u16, Vec<u8>)> {
+        let &(_, field_type, count, value) = entries.iter().find(|entry| entry.0 == tag)?;
+        let size = match field_type {
+            1 | 2 | 7 => 1,
+            3 => 2,
+            4 | 9 => 4,
+            5 | 10 => 8,
+            _ => return None,
+        } * count as usize;
+        let bytes = match size {
+            0..=4 => value[..size].to_vec(),
+            _ => self.read(self.u32(&value) as u64, size.min(MAX_VALUE_BYTES))?,
+        };
+        Some((field_type, bytes))
+    }
+
+    fn text(&mut self, entries: &[IfdEntry], tag: u16) -> Option<String> {
+        match self.values(entries, tag)? {
+            (2, bytes) => {
+                let text = String::from_utf8_lossy(&bytes).trim_end_matches('\0').trim().to_string();
+                (!text.is_empty()).then_some(text)
+            }
+            _ => None,
+        }
+    }
+
+    fn number(&mut self, entries: &[IfdEntry], tag: u16) -> Option<u32> {
+        match self.values(entries, tag)? {
+            (3, bytes) if bytes.len() >= 2 => Some(self.u16(&bytes) as u32),
+            (4, bytes) if bytes.len() >= 4 => Some(self.u32(&bytes)),
+            _ => None,
+        }
+    }
+
+    fn rational(&mut self, entries: &[IfdEntry], tag: u16) -> Option<f64> {
+        let (field_type, bytes) = self.values(entries, tag)?;
+        if bytes.len() < 8 {
+            return None;
+        }
+        let (numerator, denominator) = (self.u32(&bytes[0..4]), self.u32(&bytes[4..8]));
+        match field_type {
+            5 if denominator > 0 => Some(numerator as f64 / denominator as f64),
+            10 if denominator as i32 != 0 => Some(numerator as i32 as f64 / denominator as i32 as f64),
+            _ => None,
+        }
+    }
+}
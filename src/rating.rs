@@ -0,0 +1,182 @@
+//! Star ratings and color labels, stored in XMP sidecar files next to the images so other
+//! photo tools (Lightroom, Bridge, darktable) see them too.
+
+use anyhow::{bail, Context};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const XMP_NAMESPACE: &str = "http://ns.adobe.com/xap/1.0/";
+
+/// Color label, with the names Adobe tools write to `xmp:Label`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorLabel {
+    Red,
+    Yellow,
+    Green,
+    Blue,
+    Purple,
+}
+
+impl ColorLabel {
+    pub const ALL: [ColorLabel; 5] = [ColorLabel::Red, ColorLabel::Yellow, ColorLabel::Green, ColorLabel::Blue, ColorLabel::Purple];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ColorLabel::Red => "Red",
+            ColorLabel::Yellow => "Yellow",
+            ColorLabel::Green => "Green",
+            ColorLabel::Blue => "Blue",
+            ColorLabel::Purple => "Purple",
+        }
+    }
+
+    pub fn color(&self) -> egui::Color32 {
+        match self {
+            ColorLabel::Red => egui::Color32::from_rgb(220, 50, 47),
+            ColorLabel::Yellow => egui::Color32::from_rgb(230, 200, 30),
+            ColorLabel::Green => egui::Color32::from_rgb(60, 170, 60),
+            ColorLabel::Blue => egui::Color32::from_rgb(50, 110, 220),
+            ColorLabel::Purple => egui::Color32::from_rgb(150, 70, 190),
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|label| label.as_str().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+/// Rating of an image, 0 stars is unrated
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Rating {
+    pub stars: u8,
+    pub label: Option<ColorLabel>,
+}
+
+impl Rating {
+    /// Stars as text like "★★★☆☆"
+    pub fn stars_text(&self) -> String {
+        (1..=5).map(|star| if star <= self.stars { '★' } else { '☆' }).collect()
+    }
+}
+
+/// Sidecar of an image: `photo.jpg.xmp` if it exists (darktable), else `photo.xmp` (Adobe)
+pub fn sidecar_path(image: &Path) -> PathBuf {
+    let mut appended = image.as_os_str().to_owned();
+    appended.push(".xmp");
+    let appended = PathBuf::from(appended);
+    if appended.exists() {
+        appended
+    } else {
+        image.with_extension("xmp")
+    }
+}
+
+/// Rating from the sidecar, unrated when there is none
+pub fn read_rating(image: &Path) -> Rating {
+    let Ok(text) = fs::read_to_string(sidecar_path(image)) else {
+        return Rating::default();
+    };
+    // Rejected images have rating -1
+    let stars = property(&text, "xmp:Rating").and_then(|value| value.trim().parse::<i32>().ok()).unwrap_or(0);
+    Rating {
+        stars: stars.clamp(0, 5) as u8,
+        label: property(&text, "xmp:Label").and_then(|value| ColorLabel::from_name(&value)),
+    }
+}
+
+/// Store the rating in the sidecar, keeping everything else another tool wrote there
+pub fn write_rating(image: &Path, rating: Rating) -> anyhow::Result<()> {
+    let sidecar = sidecar_path(image);
+    let text = match fs::read_to_string(&sidecar) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => concat!(
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+            " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+            "  <rdf:Description rdf:about=\"\"/>\n",
+            " </rdf:RDF>\n",
+            "</x:xmpmeta>\n",
+        ).to_string(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", sidecar)),
+    };
+
+    let mut text = remove_property(&remove_property(&text, "xmp:Rating"), "xmp:Label");
+    let Some(start) = text.find("<rdf:Description") else {
+        bail!("{:?} has no rdf:Description to store the rating in", sidecar);
+    };
+    let mut attributes = String::new();
+    if !text.contains("xmlns:xmp=") {
+        attributes.push_str(&format!(" xmlns:xmp=\"{}\"", XMP_NAMESPACE));
+    }
+    if rating.stars > 0 {
+        attributes.push_str(&format!(" xmp:Rating=\"{}\"", rating.stars));
+    }
+    if let Some(label) = rating.label {
+        attributes.push_str(&format!(" xmp:Label=\"{}\"", label.as_str()));
+    }
+    text.insert_str(start + "<rdf:Description".len(), &attributes);
+    fs::write(&sidecar, text).with_context(|| format!("Failed to write {:?}", sidecar))
+}
+
+/// Value of a property written as attribute `name="value"` or element `<name>value</name>`
+fn property(text: &str, name: &str) -> Option<String> {
+    if let Some(start) = text.find(&format!("{}=", name)) {
+        let rest = &text[start + name.len() + 1..];
+        let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value = &rest[1..];
+        return value.find(quote).map(|end| value[..end].to_string());
+    }
+    let open = format!("<{}>", name);
+    let start = text.find(&open)? + open.len();
+    let end = text[start..].find(&format!("</{}>", name))?;
+    Some(text[start..start + end].to_string())
+}
+
+/// Text without the attribute or element form of a property
+fn remove_property(text: &str, name: &str) -> String {
+    let mut text = text.to_string();
+    while let Some(start) = text.find(&format!("{}=", name)) {
+        let rest = &text[start + name.len() + 1..];
+        let Some(quote) = rest.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            break;
+        };
+        let Some(end) = rest[1..].find(quote) else {
+            break;
+        };
+        // Also drop the whitespace in front of the attribute
+        let begin = text[..start].trim_end().len();
+        text.replace_range(begin..start + name.len() + 1 + end + 2, "");
+    }
+    let (open, close) = (format!("<{}>", name), format!("</{}>", name));
+    while let Some(start) = text.find(&open) {
+        let Some(end) = text[start..].find(&close) else {
+            break;
+        };
+        let begin = text[..start].trim_end().len();
+        text.replace_range(begin..start + end + close.len(), "");
+    }
+    text
+}
+
+/// Ratings of the images seen in this session, read from the sidecars on first use
+#[derive(Default)]
+pub struct Ratings {
+    cache: HashMap<PathBuf, Rating>,
+}
+
+impl Ratings {
+    pub fn get(&mut self, image: &Path) -> Rating {
+        *self.cache.entry(image.to_path_buf()).or_insert_with(|| read_rating(image))
+    }
+
+    pub fn set(&mut self, image: &Path, rating: Rating) -> anyhow::Result<()> {
+        write_rating(image, rating)?;
+        self.cache.insert(image.to_path_buf(), rating);
+        Ok(())
+    }
+
+    /// Read the rating again, e.g. after the file was renamed or moved
+    pub fn invalidate(&mut self, image: &Path) {
+        self.cache.remove(image);
+    }
+}
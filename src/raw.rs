@@ -0,0 +1,278 @@
+use anyhow::bail;
+use log::info;
+use rawloader::{RawImage, RawImageData};
+use std::path::Path;
+
+use crate::orientation::{Orientation, OrientationOp};
+
+/// Extensions of the camera RAW formats decoded by rawloader
+pub const RAW_EXTENSIONS: [&str; 21] = [
+    "dng", "cr2", "crw", "nef", "nrw", "arw", "srf", "sr2", "orf", "rw2", "raf",
+    "pef", "srw", "3fr", "erf", "kdc", "dcr", "mef", "mos", "mrw", "iiq",
+];
+
+/// Linear XYZ (D65) to linear sRGB
+const XYZ_TO_SRGB: [[f32; 3]; 3] = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.969266, 1.8760108, 0.041556],
+    [0.0556434, -0.2040259, 1.0572252],
+];
+
+pub fn is_raw_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| RAW_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// White balance a raw file is developed with
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum WhiteBalance {
+    /// Multipliers recorded by the camera
+    #[default]
+    AsShot,
+    /// Neutral D65 white derived from the camera's color matrix
+    Daylight,
+    /// Gray world: the average of the image becomes neutral
+    Auto,
+    /// No correction, the camera's native response
+    Uncorrected,
+    /// Red and blue gains set by hand
+    Custom,
+}
+
+impl WhiteBalance {
+    pub const PRESETS: [WhiteBalance; 4] = [WhiteBalance::AsShot, WhiteBalance::Daylight, WhiteBalance::Auto, WhiteBalance::Uncorrected];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WhiteBalance::AsShot => "As shot",
+            WhiteBalance::Daylight => "Daylight",
+            WhiteBalance::Auto => "Auto (gray world)",
+            WhiteBalance::Uncorrected => "Uncorrected",
+            WhiteBalance::Custom => "Custom",
+        }
+    }
+}
+
+/// Color transform of a developed raw file, kept so the white balance can change without decoding again
+#[derive(Clone)]
+pub struct RawDevelop {
+    /// White balanced camera RGB to linear sRGB
+    matrix: [[f32; 3]; 3],
+    as_shot: [f32; 3],
+    daylight: [f32; 3],
+    auto: [f32; 3],
+    /// Camera RGB multipliers (green is 1) the developed data is balanced with
+    pub multipliers: [f32; 3],
+    pub white_balance: WhiteBalance,
+}
+
+impl RawDevelop {
+    /// Multipliers of a preset, the current ones for `Custom`
+    pub fn preset(&self, white_balance: WhiteBalance) -> [f32; 3] {
+        match white_balance {
+            WhiteBalance::AsShot => self.as_shot,
+            WhiteBalance::Daylight => self.daylight,
+            WhiteBalance::Auto => self.auto,
+            WhiteBalance::Uncorrected => [1.0; 3],
+            WhiteBalance::Custom => self.multipliers,
+        }
+    }
+
+    /// Re-balance developed linear sRGB data from the current multipliers to `multipliers`.
+    /// The development is linear, so this equals developing again with the new multipliers.
+    pub fn rebalance(&mut self, data: &mut [f32], channels: usize, white_balance: WhiteBalance, multipliers: [f32; 3]) {
+        let Some(inverse) = invert(self.matrix) else {
+            return;
+        };
+        let ratio: [f32; 3] = std::array::from_fn(|c| multipliers[c] / self.multipliers[c]);
+        let scaled: [[f32; 3]; 3] = std::array::from_fn(|i| std::array::from_fn(|j| ratio[i] * inverse[i][j]));
+        let transform = multiply(self.matrix, scaled);
+        for pixel in data.chunks_exact_mut(channels) {
+            let rgb = [pixel[0], pixel[1], pixel[2]];
+            for (out, row) in pixel.iter_mut().zip(transform) {
+                *out = row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+            }
+        }
+        self.multipliers = multipliers;
+        self.white_balance = white_balance;
+    }
+}
+
+/// Decode and demosaic a camera RAW file into interleaved linear sRGB, with white at 1.0 and
+/// highlights left unclipped. Returns the data, its width and height and the color transform.
+pub fn develop(path: &Path) -> anyhow::Result<(Vec<f32>, u32, u32, RawDevelop)> {
+    let raw = rawloader::decode_file(path)?;
+    info!("RAW {} {}: {}x{}, CFA {}, {} components", raw.clean_make, raw.clean_model, raw.width, raw.height, raw.cfa.name, raw.cpp);
+    let [top, right, bottom, left] = raw.crops;
+    if left + right >= raw.width || top + bottom >= raw.height {
+        bail!("RAW crop {:?} leaves no image of {}x{}", raw.crops, raw.width, raw.height);
+    }
+    let (width, height) = (raw.width - left - right, raw.height - top - bottom);
+
+    let mut camera = match raw.cpp {
+        1 if !raw.cfa.is_valid() => {
+            let gray = normalized(&raw, top, left, width, height, |_, _| 0);
+            gray.iter().flat_map(|&v| [v; 3]).collect()
+        }
+        1 => {
+            let cfa = raw.cropped_cfa();
+            // A fourth color (emerald or yellow of four color sensors) is treated as green
+            let color = |x: usize, y: usize| match cfa.color_at(y, x) {
+                3 => 1,
+                c => c,
+            };
+            let mosaic = normalized(&raw, top, left, width, height, |x, y| cfa.color_at(y, x));
+            demosaic(&mosaic, width, height, color)
+        }
+        3 => normalized(&raw, top, left, width, height, |_, _| 0),
+        cpp => bail!("RAW files with {} components per pixel are not supported", cpp),
+    };
+
+    let as_shot = if raw.wb_coeffs[..3].iter().all(|c| c.is_finite() && *c > 0.0) {
+        [raw.wb_coeffs[0] / raw.wb_coeffs[1], 1.0, raw.wb_coeffs[2] / raw.wb_coeffs[1]]
+    } else {
+        neutral(&raw)
+    };
+    let develop = RawDevelop {
+        matrix: camera_to_srgb(&raw),
+        as_shot,
+        daylight: neutral(&raw),
+        auto: gray_world(&camera),
+        multipliers: as_shot,
+        white_balance: WhiteBalance::AsShot,
+    };
+    let matrix: [[f32; 3]; 3] = std::array::from_fn(|i| std::array::from_fn(|j| develop.matrix[i][j] * as_shot[j]));
+    for pixel in camera.chunks_exact_mut(3) {
+        let rgb = [pixel[0], pixel[1], pixel[2]];
+        for (out, row) in pixel.iter_mut().zip(matrix) {
+            *out = row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+        }
+    }
+
+    let (data, width, height) = orient(camera, width as u32, height as u32, &raw);
+    Ok((data, width, height, develop))
+}
+
+/// Cropped samples scaled from the black to the white level of their color.
+/// With one component per pixel `color` gives the CFA color, otherwise the samples are RGB.
+fn normalized(raw: &RawImage, top: usize, left: usize, width: usize, height: usize, color: impl Fn(usize, usize) -> usize) -> Vec<f32> {
+    let cpp = raw.cpp;
+    let mut output = Vec::with_capacity(width * height * cpp);
+    for y in 0..height {
+        let row_start = ((y + top) * raw.width + left) * cpp;
+        for i in 0..width * cpp {
+            let value = match &raw.data {
+                RawImageData::Integer(data) => data[row_start + i] as f32,
+                RawImageData::Float(data) => data[row_start + i],
+            };
+            let c = if cpp == 1 { color(i, y) } else { i % cpp };
+            let black = raw.blacklevels[c] as f32;
+            let range = (raw.whitelevels[c] as f32 - black).max(1.0);
+            output.push((value - black) / range);
+        }
+    }
+    output
+}
+
+/// Bilinear demosaic: missing colors are the average of the 3×3 neighbors of that color
+fn demosaic(mosaic: &[f32], width: usize, height: usize, color: impl Fn(usize, usize) -> usize) -> Vec<f32> {
+    let mut output = vec![0.0f32; width * height * 3];
+    for y in 0..height {
+        for x in 0..width {
+            let mut sums = [0.0f32; 3];
+            let mut counts = [0u32; 3];
+            for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                    let c = color(nx, ny);
+                    sums[c] += mosaic[ny * width + nx];
+                    counts[c] += 1;
+                }
+            }
+            let own = color(x, y);
+            let pixel = &mut output[(y * width + x) * 3..][..3];
+            for c in 0..3 {
+                pixel[c] = if c == own { mosaic[y * width + x] } else { sums[c] / counts[c].max(1) as f32 };
+            }
+        }
+    }
+    output
+}
+
+/// Camera RGB to linear sRGB through XYZ, rows normalized so a balanced neutral stays neutral
+fn camera_to_srgb(raw: &RawImage) -> [[f32; 3]; 3] {
+    if raw.xyz_to_cam.iter().flatten().all(|&v| v == 0.0) {
+        return [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    }
+    let cam_to_xyz = raw.cam_to_xyz_normalized();
+    let cam_to_xyz: [[f32; 3]; 3] = std::array::from_fn(|i| std::array::from_fn(|j| cam_to_xyz[i][j]));
+    let mut matrix = multiply(XYZ_TO_SRGB, cam_to_xyz);
+    for row in &mut matrix {
+        let sum: f32 = row.iter().sum();
+        if sum.abs() > f32::EPSILON {
+            row.iter_mut().for_each(|v| *v /= sum);
+        }
+    }
+    matrix
+}
+
+/// Daylight multipliers from the color matrix, no correction for cameras without one
+fn neutral(raw: &RawImage) -> [f32; 3] {
+    let [r, g, b, _] = raw.neutralwb();
+    if [r, g, b].iter().all(|c| c.is_finite() && *c > 0.0) { [r / g, 1.0, b / g] } else { [1.0; 3] }
+}
+
+/// Multipliers that make the mean of the unclipped camera RGB neutral
+fn gray_world(camera: &[f32]) -> [f32; 3] {
+    let mut sums = [0.0f64; 3];
+    for pixel in camera.chunks_exact(3).filter(|p| p.iter().all(|&v| v < 0.95)) {
+        for c in 0..3 {
+            sums[c] += pixel[c].max(0.0) as f64;
+        }
+    }
+    if sums.iter().any(|&s| s <= 0.0) {
+        return [1.0; 3];
+    }
+    [(sums[1] / sums[0]) as f32, 1.0, (sums[1] / sums[2]) as f32]
+}
+
+/// Rotate the developed data to the orientation recorded in the file
+fn orient(data: Vec<f32>, width: u32, height: u32, raw: &RawImage) -> (Vec<f32>, u32, u32) {
+    use rawloader::Orientation as RawOrientation;
+    let exif = match raw.orientation {
+        RawOrientation::HorizontalFlip => 2,
+        RawOrientation::Rotate180 => 3,
+        RawOrientation::VerticalFlip => 4,
+        RawOrientation::Transpose => 5,
+        RawOrientation::Rotate90 => 6,
+        RawOrientation::Transverse => 7,
+        RawOrientation::Rotate270 => 8,
+        RawOrientation::Normal | RawOrientation::Unknown => 1,
+    };
+    let orientation = Orientation::from_exif(exif);
+    let mut result = (data, width, height);
+    if orientation.flipped {
+        result = OrientationOp::FlipHorizontal.apply_to_fp(&result.0, result.1, result.2, 3);
+    }
+    for _ in 0..orientation.quarter_turns {
+        result = OrientationOp::RotateCw.apply_to_fp(&result.0, result.1, result.2, 3);
+    }
+    result
+}
+
+fn multiply(a: [[f32; 3]; 3], b: [[f32; 3]; 3]) -> [[f32; 3]; 3] {
+    std::array::from_fn(|i| std::array::from_fn(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+fn invert(m: [[f32; 3]; 3]) -> Option<[[f32; 3]; 3]> {
+    let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let det = m[0][0] * cofactor(1, 2, 1, 2) - m[0][1] * cofactor(1, 2, 0, 2) + m[0][2] * cofactor(1, 2, 0, 1);
+    if det.abs() < 1e-8 {
+        return None;
+    }
+    Some([
+        [cofactor(1, 2, 1, 2) / det, -cofactor(0, 2, 1, 2) / det, cofactor(0, 1, 1, 2) / det],
+        [-cofactor(1, 2, 0, 2) / det, cofactor(0, 2, 0, 2) / det, -cofactor(0, 1, 0, 2) / det],
+        [cofactor(1, 2, 0, 1) / det, -cofactor(0, 2, 0, 1) / det, cofactor(0, 1, 0, 1) / det],
+    ])
+}
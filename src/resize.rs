@@ -0,0 +1,78 @@
+use image::imageops::FilterType;
+use image::{ImageBuffer, Luma};
+
+use crate::config::DownscaleFilter;
+
+/// Largest side the resize dialog accepts
+pub const MAX_SIZE: u32 = 65535;
+
+/// State of the resize dialog
+pub struct ResizeTool {
+    pub width: u32,
+    pub height: u32,
+    /// Keep the width / height ratio of the source when one side is edited
+    pub lock_aspect: bool,
+    pub filter: DownscaleFilter,
+}
+
+impl ResizeTool {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            lock_aspect: true,
+            filter: DownscaleFilter::Lanczos3,
+        }
+    }
+
+    /// Set the width, the height follows the source ratio when locked
+    pub fn set_width(&mut self, width: u32, source: (u32, u32)) {
+        self.width = width.clamp(1, MAX_SIZE);
+        if self.lock_aspect {
+            self.height = scaled(source.1, self.width as f64 / source.0 as f64);
+        }
+    }
+
+    pub fn set_height(&mut self, height: u32, source: (u32, u32)) {
+        self.height = height.clamp(1, MAX_SIZE);
+        if self.lock_aspect {
+            self.width = scaled(source.0, self.height as f64 / source.1 as f64);
+        }
+    }
+
+    /// Scale both sides by a percentage of the source size
+    pub fn set_percent(&mut self, percent: f64, source: (u32, u32)) {
+        self.width = scaled(source.0, percent / 100.0);
+        self.height = scaled(source.1, percent / 100.0);
+    }
+
+    /// Target width as a percentage of the source width
+    pub fn percent(&self, source: (u32, u32)) -> f64 {
+        self.width as f64 / source.0 as f64 * 100.0
+    }
+}
+
+fn scaled(size: u32, factor: f64) -> u32 {
+    (size as f64 * factor).round().clamp(1.0, MAX_SIZE as f64) as u32
+}
+
+/// Resample an interleaved floating point buffer, channel by channel. The image crate clamps
+/// float pixels to 0..1, so each channel is mapped to that range and back.
+pub fn resize_fp(data: &[f32], width: u32, height: u32, channels: usize, new_width: u32, new_height: u32, filter: FilterType) -> Vec<f32> {
+    let mut output = vec![0.0; new_width as usize * new_height as usize * channels];
+    for channel in 0..channels {
+        let plane: Vec<f32> = data.iter().skip(channel).step_by(channels).copied().collect();
+        let (min, max) = plane.iter().filter(|v| v.is_finite()).fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &v| (min.min(v), max.max(v)));
+        let range = if max > min { max - min } else { 1.0 };
+        let min = if min.is_finite() { min } else { 0.0 };
+        let normalized: Vec<f32> = plane.iter().map(|&v| if v.is_finite() { (v - min) / range } else { 0.0 }).collect();
+        let Some(plane) = ImageBuffer::<Luma<f32>, _>::from_raw(width, height, normalized) else {
+            return output;
+        };
+        let resized = image::imageops::resize(&plane, new_width, new_height, filter);
+        for (target, value) in output.iter_mut().skip(channel).step_by(channels).zip(resized.into_raw()) {
+            *target = value * range + min;
+        }
+    }
+    output
+}
@@ -0,0 +1,120 @@
+//! Binarization of the displayed image by a manual, Otsu or adaptive threshold, for quick
+//! segmentation checks. The threshold is the last display step, applied to the luma of what is shown.
+
+use std::sync::{Arc, Mutex};
+
+use crate::transforms::ThresholdView;
+
+/// How the threshold is found
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ThresholdMode {
+    /// The level set with the slider
+    Manual,
+    /// The level separating the luma histogram into two classes best
+    Otsu,
+    /// Each pixel against the mean of its neighborhood
+    Adaptive,
+}
+
+/// What the thresholded image looks like
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ThresholdOutput {
+    /// Foreground tinted over the image
+    Overlay,
+    /// White foreground on black
+    Binary,
+}
+
+/// Threshold used for the last displayed image
+#[derive(Clone, Copy, Debug)]
+pub struct ThresholdResult {
+    /// Pixels with a luma above it are foreground, `None` in the adaptive mode
+    pub level: Option<u8>,
+    /// Share of foreground pixels, 0 to 1
+    pub foreground: f32,
+}
+
+/// State of the threshold tool
+pub struct ThresholdTool {
+    pub mode: ThresholdMode,
+    pub output: ThresholdOutput,
+    pub level: u8,
+    /// Neighborhood radius in image pixels and the offset below the local mean, for the adaptive mode
+    pub adaptive: [f32; 2],
+    /// Written while the display image is built
+    pub result: Arc<Mutex<Option<ThresholdResult>>>,
+}
+
+impl Default for ThresholdTool {
+    fn default() -> Self {
+        Self {
+            mode: ThresholdMode::Otsu,
+            output: ThresholdOutput::Overlay,
+            level: 128,
+            adaptive: [15.0, 5.0],
+            result: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+impl ThresholdTool {
+    /// Display step thresholding with the current settings
+    pub fn view(&self) -> ThresholdView {
+        ThresholdView { mode: self.mode, output: self.output, level: self.level, result: Arc::clone(&self.result) }
+    }
+
+    /// Settings and the computed threshold. Returns true if the displayed image changes.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            for (mode, name) in [(ThresholdMode::Manual, "Manual"), (ThresholdMode::Otsu, "Otsu"), (ThresholdMode::Adaptive, "Adaptive")] {
+                changed |= ui.selectable_value(&mut self.mode, mode, name).changed();
+            }
+        });
+        match self.mode {
+            ThresholdMode::Manual => {
+                ui.horizontal(|ui| {
+                    ui.label("Level:");
+                    changed |= ui.add(egui::Slider::new(&mut self.level, 0..=254)).changed();
+                });
+            }
+            ThresholdMode::Otsu => {
+                ui.weak("Level chosen from the luma histogram");
+            }
+            ThresholdMode::Adaptive => {
+                ui.horizontal(|ui| {
+                    ui.label("Radius:");
+                    changed |= ui.add(egui::Slider::new(&mut self.adaptive[0], 1.0..=100.0).suffix(" px")).changed();
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Offset:");
+                    changed |= ui.add(egui::Slider::new(&mut self.adaptive[1], -50.0..=50.0))
+                        .on_hover_text("Pixels must be this much brighter than the mean of their neighborhood").changed();
+                });
+            }
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Show:");
+            changed |= ui.radio_value(&mut self.output, ThresholdOutput::Overlay, "Overlay").changed();
+            changed |= ui.radio_value(&mut self.output, ThresholdOutput::Binary, "Binary").changed();
+        });
+
+        let result = self.result.lock().ok().and_then(|result| *result);
+        match result {
+            Some(ThresholdResult { level, foreground }) => {
+                let level = match (level, self.mode) {
+                    (Some(level), ThresholdMode::Otsu) => format!("Threshold {} (Otsu), ", level),
+                    (Some(level), _) => format!("Threshold {}, ", level),
+                    (None, _) => String::new(),
+                };
+                ui.label(format!("{}foreground {:.1} %", level, foreground * 100.0));
+            }
+            None => {
+                ui.weak("No image");
+            }
+        }
+        changed
+    }
+}
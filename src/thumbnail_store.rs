@@ -0,0 +1,145 @@
+//! Thumbnails kept on disk between runs, in the freedesktop.org thumbnail layout: a PNG of at
+//! most 128 pixels per file in `$XDG_CACHE_HOME/thumbnails/normal`, named after the MD5 of the
+//! file URI and tagged with the URI and modification time of the file. Thumbnails of a changed
+//! file do not match its time and are made again. Other desktop applications on Linux share the
+//! directory; elsewhere it lives in the viewer's own cache folder.
+
+use anyhow::Context;
+use image::{DynamicImage, RgbaImage};
+use md5::{Digest, Md5};
+use std::fs::{self, File};
+use std::io::{BufWriter, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Longer side of the stored thumbnails, the "normal" size of the specification
+pub const STORED_SIZE: u32 = 128;
+
+/// Directory of thumbnails keyed by file URI and modification time
+pub struct ThumbnailStore {
+    dir: PathBuf,
+}
+
+/// Where the thumbnail of a file is stored and what it is tagged with
+struct Entry {
+    file: PathBuf,
+    uri: String,
+    mtime: u64,
+}
+
+impl ThumbnailStore {
+    /// Store in the user's cache folder, `None` if there is none
+    pub fn new() -> Option<Self> {
+        let cache = dirs::cache_dir()?;
+        let root = if cfg!(all(unix, not(target_os = "macos"))) {
+            cache.join("thumbnails")
+        } else {
+            cache.join("image_viewer").join("thumbnails")
+        };
+        Some(Self { dir: root.join("normal") })
+    }
+
+    fn entry(&self, path: &Path) -> Option<Entry> {
+        let mtime = fs::metadata(path).ok()?.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_secs();
+        let uri = file_uri(&std::path::absolute(path).ok()?);
+        let file = self.dir.join(format!("{:x}.png", Md5::digest(uri.as_bytes())));
+        Some(Entry { file, uri, mtime })
+    }
+
+    /// Stored thumbnail of a file, if there is one for its current version
+    pub fn load(&self, path: &Path) -> Option<DynamicImage> {
+        let entry = self.entry(path)?;
+        let bytes = fs::read(&entry.file).ok()?;
+        let reader = png::Decoder::new(Cursor::new(&bytes)).read_info().ok()?;
+        let text = |keyword: &str| {
+            reader.info().uncompressed_latin1_text.iter().find(|chunk| chunk.keyword == keyword).map(|chunk| chunk.text.clone())
+        };
+        let current = text("Thumb::URI").as_deref() == Some(entry.uri.as_str())
+            && text("Thumb::MTime").and_then(|mtime| mtime.parse::<u64>().ok()) == Some(entry.mtime);
+        if !current {
+            return None;
+        }
+        image::load_from_memory_with_format(&bytes, image::ImageFormat::Png).ok()
+    }
+
+    /// Store the thumbnail of a file. Written to a temporary file first, so other applications
+    /// never read half a thumbnail.
+    pub fn save(&self, path: &Path, thumbnail: &RgbaImage) -> anyhow::Result<()> {
+        let entry = self.entry(path).with_context(|| format!("No modification time for {:?}", path))?;
+        create_private_dir(&self.dir)?;
+        let temporary = entry.file.with_extension(format!("{}.tmp", std::process::id()));
+        let written = write_png(&temporary, &entry, thumbnail).and_then(|()| Ok(fs::rename(&temporary, &entry.file)?));
+        if written.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+        written.with_context(|| format!("Failed to store the thumbnail of {:?} in {:?}", path, self.dir))
+    }
+}
+
+/// Thumbnail at the stored size, small images are kept as they are
+pub fn stored_thumbnail(image: &DynamicImage) -> RgbaImage {
+    if image.width() > STORED_SIZE || image.height() > STORED_SIZE {
+        image.thumbnail(STORED_SIZE, STORED_SIZE).to_rgba8()
+    } else {
+        image.to_rgba8()
+    }
+}
+
+fn write_png(file: &Path, entry: &Entry, thumbnail: &RgbaImage) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(create_private_file(file)?);
+    let mut encoder = png::Encoder::new(&mut writer, thumbnail.width(), thumbnail.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.add_text_chunk("Thumb::URI".to_string(), entry.uri.clone())?;
+    encoder.add_text_chunk("Thumb::MTime".to_string(), entry.mtime.to_string())?;
+    encoder.add_text_chunk("Software".to_string(), "Image Viewer".to_string())?;
+    encoder.write_header()?.write_image_data(thumbnail.as_raw())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// `file://` URI of an absolute path, escaped like GLib does so the names match other applications
+pub fn file_uri(path: &Path) -> String {
+    #[cfg(unix)]
+    let bytes = {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes().to_vec()
+    };
+    #[cfg(not(unix))]
+    let bytes = {
+        let text = path.to_string_lossy().replace('\\', "/");
+        if text.starts_with('/') { text } else { format!("/{}", text) }.into_bytes()
+    };
+    let mut uri = String::from("file://");
+    for byte in bytes {
+        if byte.is_ascii_alphanumeric() || b"!$&'()*+,-./:=@_~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+/// The specification asks for folders and thumbnails only the user can read
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)
+}
+
+#[cfg(unix)]
+fn create_private_file(file: &Path) -> std::io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(file)
+}
+
+#[cfg(not(unix))]
+fn create_private_file(file: &Path) -> std::io::Result<File> {
+    File::create(file)
+}
@@ -0,0 +1,114 @@
+use image::DynamicImage;
+use log::warn;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+use crate::thumbnail_store::{stored_thumbnail, ThumbnailStore};
+
+/// Function used by the worker thread to decode an image
+pub type ThumbnailLoader = fn(&Path) -> anyhow::Result<DynamicImage>;
+
+enum ThumbnailState {
+    Pending,
+    Ready(egui::TextureHandle),
+    Failed,
+}
+
+/// Lazily generated thumbnails, decoded on a background thread and uploaded as textures on request.
+/// Thumbnails stored on disk by an earlier run are used instead of decoding the image again.
+pub struct ThumbnailCache {
+    states: HashMap<PathBuf, ThumbnailState>,
+    request_tx: Sender<PathBuf>,
+    result_rx: Receiver<(PathBuf, Option<egui::ColorImage>)>,
+}
+
+impl ThumbnailCache {
+    pub fn new(ctx: &egui::Context, size: u32, loader: ThumbnailLoader) -> Self {
+        let (request_tx, request_rx) = channel::<PathBuf>();
+        let (result_tx, result_rx) = channel();
+        let ctx = ctx.clone();
+
+        thread::spawn(move || {
+            let store = ThumbnailStore::new();
+            for path in request_rx {
+                let source = match store.as_ref().and_then(|store| store.load(&path)) {
+                    Some(stored) => Ok(stored),
+                    None => loader(&path).map(|img| Self::store(store.as_ref(), &path, img)),
+                };
+                let thumbnail = match source {
+                    Ok(img) => {
+                        let rgba = img.thumbnail(size, size).to_rgba8();
+                        let (width, height) = rgba.dimensions();
+                        Some(egui::ColorImage::from_rgba_unmultiplied(
+                            [width as usize, height as usize],
+                            rgba.as_raw(),
+                        ))
+                    }
+                    Err(e) => {
+                        warn!("Failed to create thumbnail for {:?}: {}", path, e);
+                        None
+                    }
+                };
+                if result_tx.send((path, thumbnail)).is_err() {
+                    break;
+                }
+                ctx.request_repaint();
+            }
+        });
+
+        Self {
+            states: HashMap::new(),
+            request_tx,
+            result_rx,
+        }
+    }
+
+    /// Keep a new thumbnail on disk for the next run, returns it at the stored size
+    fn store(store: Option<&ThumbnailStore>, path: &Path, img: DynamicImage) -> DynamicImage {
+        let Some(store) = store else {
+            return img;
+        };
+        let thumbnail = stored_thumbnail(&img);
+        if let Err(e) = store.save(path, &thumbnail) {
+            warn!("{:#}", e);
+        }
+        DynamicImage::ImageRgba8(thumbnail)
+    }
+
+    /// Upload finished thumbnails, call once per frame
+    pub fn poll(&mut self, ctx: &egui::Context) {
+        while let Ok((path, thumbnail)) = self.result_rx.try_recv() {
+            let state = match thumbnail {
+                Some(image) => {
+                    let name = format!("thumbnail-{}", path.display());
+                    ThumbnailState::Ready(ctx.load_texture(name, image, egui::TextureOptions::LINEAR))
+                }
+                None => ThumbnailState::Failed,
+            };
+            self.states.insert(path, state);
+        }
+    }
+
+    /// Thumbnail texture of a path; schedules generation on first request
+    pub fn get(&mut self, path: &Path) -> Option<&egui::TextureHandle> {
+        if !self.states.contains_key(path) {
+            self.states.insert(path.to_path_buf(), ThumbnailState::Pending);
+            let _ = self.request_tx.send(path.to_path_buf());
+        }
+        match self.states.get(path) {
+            Some(ThumbnailState::Ready(texture)) => Some(texture),
+            _ => None,
+        }
+    }
+
+    /// Generate the thumbnail of a path again, e.g. after the file changed
+    pub fn invalidate(&mut self, path: &Path) {
+        self.states.remove(path);
+    }
+
+    pub fn is_failed(&self, path: &Path) -> bool {
+        matches!(self.states.get(path), Some(ThumbnailState::Failed))
+    }
+}
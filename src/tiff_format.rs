@@ -0,0 +1,83 @@
+//! What the TIFF decoder can read: classic and BigTIFF files, uncompressed or compressed with
+//! LZW, Deflate, PackBits or JPEG. Files with other compression schemes are rejected up front
+//! with an error naming the scheme, instead of failing inside the decoder.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+use tiff::decoder::Decoder;
+use tiff::tags::Tag;
+
+/// Compression codes the tiff crate decodes: none, LZW, JPEG, Deflate, PackBits and the old Deflate code
+const SUPPORTED_COMPRESSION: [u16; 6] = [1, 5, 7, 8, 32773, 32946];
+
+/// Whether the file is a TIFF, `.tif` or `.tiff`
+pub fn is_tiff_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| matches!(ext.to_string_lossy().to_lowercase().as_str(), "tif" | "tiff"))
+}
+
+/// Whether the file starts with a BigTIFF header, which has 64-bit offsets
+pub fn is_big_tiff(path: &Path) -> bool {
+    let mut header = [0u8; 4];
+    File::open(path).and_then(|mut file| file.read_exact(&mut header)).is_ok() && matches!(&header, b"II+\0" | b"MM\0+")
+}
+
+/// Compression code of the current image, 1 (none) if the tag is missing
+pub fn compression<R: Read + Seek>(decoder: &mut Decoder<R>) -> u16 {
+    decoder.find_tag_unsigned(Tag::Compression).ok().flatten().unwrap_or(1)
+}
+
+/// Name of a TIFF compression code
+pub fn compression_name(code: u16) -> String {
+    match code {
+        1 => "None",
+        2 => "CCITT RLE",
+        3 => "CCITT Group 3",
+        4 => "CCITT Group 4",
+        5 => "LZW",
+        6 => "Old-style JPEG",
+        7 => "JPEG",
+        8 | 32946 => "Deflate",
+        32773 => "PackBits",
+        33003 | 33005 | 34712 => "JPEG 2000",
+        34887 => "LERC",
+        34925 => "LZMA",
+        50000 => "Zstandard",
+        50001 => "WebP",
+        50002 => "JPEG XL",
+        other => return format!("Code {}", other),
+    }.to_string()
+}
+
+/// A TIFF compressed with a scheme the decoder does not know
+#[derive(Debug)]
+pub struct UnsupportedCompression {
+    pub code: u16,
+}
+
+impl fmt::Display for UnsupportedCompression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "TIFF compression {} is not supported, only uncompressed, LZW, Deflate, PackBits and JPEG compressed files can be opened",
+            compression_name(self.code)
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedCompression {}
+
+/// Fail with `UnsupportedCompression` when the first image of the TIFF cannot be decompressed.
+/// Files the tiff crate cannot parse pass, the loaders report their own errors.
+pub fn check(path: &Path) -> anyhow::Result<()> {
+    let Ok(mut decoder) = Decoder::new(BufReader::new(File::open(path)?)) else {
+        return Ok(());
+    };
+    let code = compression(&mut decoder);
+    if SUPPORTED_COMPRESSION.contains(&code) {
+        Ok(())
+    } else {
+        Err(UnsupportedCompression { code }.into())
+    }
+}
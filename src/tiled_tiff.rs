@@ -0,0 +1,256 @@
+//! Partial reading of large tiled or striped TIFFs, BigTIFF included. Only the strips or tiles
+//! covering a region are decoded, so multi-gigabyte scans open as an overview and the visible
+//! part is read at the resolution of the view. Reduced-resolution pages of a pyramid are used
+//! when the file has them.
+
+use anyhow::bail;
+use image::{DynamicImage, ImageBuffer};
+use log::info;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tiff::decoder::{Decoder, DecodingResult, Limits};
+use tiff::tags::Tag;
+use tiff::ColorType;
+
+use crate::large_image::PREVIEW_MIN_PIXELS;
+use crate::tiff_format;
+
+/// Largest overview shown in place of the whole image, about 4096×4096 pixels
+const OVERVIEW_MAX_PIXELS: u64 = 1 << 24;
+/// Largest region read for the view, before its margin
+pub const DETAIL_MAX_PIXELS: u64 = 1 << 22;
+/// Tile and strip offset tables of huge files exceed the tiff crate's default IFD value limit
+const IFD_VALUE_LIMIT: usize = 256 * 1024 * 1024;
+
+/// One resolution of the image: the full one or a reduced page of a pyramid
+struct Level {
+    ifd: usize,
+    width: u32,
+    height: u32,
+}
+
+/// Layout of a large TIFF read region by region
+pub struct TiledTiff {
+    path: PathBuf,
+    color: ColorType,
+    /// Full resolution first, then smaller pages
+    levels: Vec<Level>,
+}
+
+/// Pixels of a region read for the view
+pub struct Detail {
+    /// Full resolution region [x0, y0, x1, y1]
+    pub region: [u32; 4],
+    /// Full resolution pixels per pixel of `image`
+    pub step: u32,
+    pub image: DynamicImage,
+}
+
+fn open_decoder(path: &Path) -> anyhow::Result<Decoder<BufReader<File>>> {
+    let mut limits = Limits::default();
+    limits.ifd_value_size = IFD_VALUE_LIMIT;
+    Ok(Decoder::new(BufReader::new(File::open(path)?))?.with_limits(limits))
+}
+
+/// Whether the current page has 8 or 16-bit unsigned samples in one plane and is upright.
+/// JPEG compressed YCbCr is decoded to RGB.
+fn supported(decoder: &mut Decoder<BufReader<File>>, color: ColorType) -> bool {
+    let bits = match color {
+        ColorType::Gray(bits) | ColorType::GrayA(bits) | ColorType::RGB(bits) | ColorType::RGBA(bits) => bits,
+        ColorType::YCbCr(8) if tiff_format::compression(decoder) == 7 => 8,
+        _ => return false,
+    };
+    let unsigned = decoder.find_tag_unsigned_vec::<u16>(Tag::SampleFormat).ok().flatten()
+        .is_none_or(|formats| formats.iter().all(|&format| format == 1));
+    let chunky = decoder.find_tag_unsigned::<u16>(Tag::PlanarConfiguration).ok().flatten().is_none_or(|planar| planar == 1);
+    let upright = decoder.find_tag_unsigned::<u16>(Tag::Orientation).ok().flatten().is_none_or(|orientation| orientation == 1);
+    matches!(bits, 8 | 16) && unsigned && chunky && upright
+}
+
+/// Sampled coordinates `start`, `start + step`, … below `end` that lie in `from..to`
+fn sampled(start: u32, end: u32, step: u32, from: u32, to: u32) -> impl Iterator<Item = u32> {
+    let first = start + from.saturating_sub(start).div_ceil(step) * step;
+    (first..end.min(to)).step_by(step as usize)
+}
+
+/// Output samples of 8 or 16-bit data
+enum Samples {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+}
+
+impl TiledTiff {
+    /// Layout of a TIFF with more than `PREVIEW_MIN_PIXELS` pixels of 8 or 16-bit unsigned
+    /// gray, gray-alpha, RGB or RGBA samples, with the reduced pages of its pyramid. `None` for
+    /// other files, which are decoded whole.
+    pub fn open(path: &Path) -> Option<Self> {
+        if !tiff_format::is_tiff_file(path) || tiff_format::check(path).is_err() {
+            return None;
+        }
+        let mut decoder = open_decoder(path).ok()?;
+        let (width, height) = decoder.dimensions().ok()?;
+        if width as u64 * height as u64 <= PREVIEW_MIN_PIXELS {
+            return None;
+        }
+        let color = decoder.colortype().ok()?;
+        if !supported(&mut decoder, color) {
+            return None;
+        }
+        let mut levels = vec![Level { ifd: 0, width, height }];
+        let aspect = width as f64 / height as f64;
+        let mut ifd = 0;
+        while decoder.more_images() && decoder.next_image().is_ok() {
+            ifd += 1;
+            let Ok((page_width, page_height)) = decoder.dimensions() else {
+                break;
+            };
+            // Smaller pages of the same shape and samples make up the pyramid, others are skipped
+            let smaller = levels.last().is_some_and(|level| page_width < level.width);
+            let same_shape = ((page_width as f64 / page_height.max(1) as f64) / aspect - 1.0).abs() < 0.01;
+            if smaller && same_shape && decoder.colortype().ok() == Some(color) && supported(&mut decoder, color) {
+                levels.push(Level { ifd, width: page_width, height: page_height });
+            }
+        }
+        info!("{}x{} TIFF read by region, {} pyramid levels", width, height, levels.len());
+        Some(Self { path: path.to_path_buf(), color, levels })
+    }
+
+    /// Dimensions of the full resolution
+    pub fn size(&self) -> (u32, u32) {
+        (self.levels[0].width, self.levels[0].height)
+    }
+
+    fn channels(&self) -> usize {
+        match self.color {
+            ColorType::GrayA(_) => 2,
+            ColorType::RGB(_) | ColorType::YCbCr(_) => 3,
+            ColorType::RGBA(_) => 4,
+            _ => 1,
+        }
+    }
+
+    /// Full resolution pixels per pixel of a page
+    fn reduction(&self, level: &Level) -> f64 {
+        self.levels[0].width as f64 / level.width as f64
+    }
+
+    /// Smallest page that has the resolution of `step` and the step within it, which reads every
+    /// `step`-th full resolution pixel or finer
+    fn level(&self, step: u32) -> (&Level, u32) {
+        let level = self.levels.iter().rev().find(|level| self.reduction(level) <= step as f64 + 0.01).unwrap_or(&self.levels[0]);
+        (level, ((step as f64 / self.reduction(level) + 0.01) as u32).max(1))
+    }
+
+    /// Full resolution pixels per read pixel when `step` is asked for
+    pub fn step_read(&self, step: u32) -> u32 {
+        let (level, page_step) = self.level(step.max(1));
+        (page_step as f64 * self.reduction(level)).round() as u32
+    }
+
+    /// The whole image with at most `OVERVIEW_MAX_PIXELS` pixels
+    pub fn overview(&self) -> anyhow::Result<DynamicImage> {
+        let (width, height) = self.size();
+        let step = ((width as u64 * height as u64) as f64 / OVERVIEW_MAX_PIXELS as f64).sqrt().ceil() as u32;
+        Ok(self.read_region([0, 0, width, height], step.max(1))?.image)
+    }
+
+    /// Every `step`-th pixel of the full resolution `region` [x0, y0, x1, y1], read from the
+    /// smallest pyramid page that still has that resolution
+    pub fn read_region(&self, region: [u32; 4], step: u32) -> anyhow::Result<Detail> {
+        let started = Instant::now();
+        let (full_width, full_height) = self.size();
+        let step = step.max(1);
+        let [x0, y0, x1, y1] = [region[0].min(full_width), region[1].min(full_height), region[2].min(full_width), region[3].min(full_height)];
+        if x1 <= x0 || y1 <= y0 {
+            bail!("Empty region {:?}", region);
+        }
+        let (level, page_step) = self.level(step);
+        let reduction = self.reduction(level);
+        let to_page = |value: u32, size: u32| ((value as f64 / reduction) as u32).min(size);
+        let [px0, py0] = [to_page(x0, level.width), to_page(y0, level.height)];
+        let [px1, py1] = [to_page(x1, level.width).max(px0 + 1), to_page(y1, level.height).max(py0 + 1)];
+
+        let mut decoder = open_decoder(&self.path)?;
+        decoder.seek_to_image(level.ifd)?;
+        let channels = self.channels();
+        let (out_width, out_height) = ((px1 - px0).div_ceil(page_step), (py1 - py0).div_ceil(page_step));
+        let length = out_width as usize * out_height as usize * channels;
+        let mut output = match self.color {
+            ColorType::Gray(16) | ColorType::GrayA(16) | ColorType::RGB(16) | ColorType::RGBA(16) => Samples::U16(vec![0; length]),
+            _ => Samples::U8(vec![0; length]),
+        };
+        // Strips span the width, so there is one chunk per row of chunks
+        let (chunk_width, chunk_height) = decoder.chunk_dimensions();
+        let chunks_across = level.width.div_ceil(chunk_width.max(1));
+        let mut chunks = 0;
+        for chunk_y in py0 / chunk_height..=(py1 - 1) / chunk_height {
+            for chunk_x in px0 / chunk_width..=(px1 - 1) / chunk_width {
+                let origin = (chunk_x * chunk_width, chunk_y * chunk_height);
+                let index = chunk_y * chunks_across + chunk_x;
+                let size = decoder.chunk_data_dimensions(index);
+                // Chunks between the sampled rows or columns are not read
+                if sampled(px0, px1, page_step, origin.0, origin.0 + size.0).next().is_none()
+                    || sampled(py0, py1, page_step, origin.1, origin.1 + size.1).next().is_none()
+                {
+                    continue;
+                }
+                let place = ChunkPlacement { region: [px0, py0, px1, py1], step: page_step, out_width, origin, size, channels };
+                match (&mut output, decoder.read_chunk(index)?) {
+                    (Samples::U8(output), DecodingResult::U8(chunk)) => place.copy(output, &chunk),
+                    (Samples::U16(output), DecodingResult::U16(chunk)) => place.copy(output, &chunk),
+                    _ => bail!("Unexpected sample type in chunk {}", index),
+                }
+                chunks += 1;
+            }
+        }
+        info!("Read {}x{} pixels from {} chunks of page {} in {:?}", out_width, out_height, chunks, level.ifd, started.elapsed());
+
+        let buffer_error = || anyhow::anyhow!("Region samples do not match {}x{}", out_width, out_height);
+        let image = match (output, channels) {
+            (Samples::U8(samples), 1) => DynamicImage::ImageLuma8(ImageBuffer::from_raw(out_width, out_height, samples).ok_or_else(buffer_error)?),
+            (Samples::U8(samples), 2) => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(out_width, out_height, samples).ok_or_else(buffer_error)?),
+            (Samples::U8(samples), 3) => DynamicImage::ImageRgb8(ImageBuffer::from_raw(out_width, out_height, samples).ok_or_else(buffer_error)?),
+            (Samples::U8(samples), _) => DynamicImage::ImageRgba8(ImageBuffer::from_raw(out_width, out_height, samples).ok_or_else(buffer_error)?),
+            (Samples::U16(samples), 1) => DynamicImage::ImageLuma16(ImageBuffer::from_raw(out_width, out_height, samples).ok_or_else(buffer_error)?),
+            (Samples::U16(samples), 2) => DynamicImage::ImageLumaA16(ImageBuffer::from_raw(out_width, out_height, samples).ok_or_else(buffer_error)?),
+            (Samples::U16(samples), 3) => DynamicImage::ImageRgb16(ImageBuffer::from_raw(out_width, out_height, samples).ok_or_else(buffer_error)?),
+            (Samples::U16(samples), _) => DynamicImage::ImageRgba16(ImageBuffer::from_raw(out_width, out_height, samples).ok_or_else(buffer_error)?),
+        };
+        // The region actually covered, in full resolution pixels
+        let to_full = |value: u32| (value as f64 * reduction).round() as u32;
+        let region = [to_full(px0), to_full(py0), to_full(px0 + out_width * page_step).min(full_width), to_full(py0 + out_height * page_step).min(full_height)];
+        Ok(Detail { region, step: self.step_read(step), image })
+    }
+}
+
+/// Where the pixels of a decoded chunk go in the output
+struct ChunkPlacement {
+    /// Sampled region of the page
+    region: [u32; 4],
+    step: u32,
+    out_width: u32,
+    /// Top left pixel of the chunk in the page
+    origin: (u32, u32),
+    /// Chunk size without padding
+    size: (u32, u32),
+    channels: usize,
+}
+
+impl ChunkPlacement {
+    fn copy<T: Copy>(&self, output: &mut [T], chunk: &[T]) {
+        let [x0, y0, x1, y1] = self.region;
+        let ((origin_x, origin_y), (width, height), channels) = (self.origin, self.size, self.channels);
+        for y in sampled(y0, y1, self.step, origin_y, origin_y + height) {
+            let out_row = ((y - y0) / self.step) as usize * self.out_width as usize;
+            for x in sampled(x0, x1, self.step, origin_x, origin_x + width) {
+                let source = ((y - origin_y) as usize * width as usize + (x - origin_x) as usize) * channels;
+                let target = (out_row + ((x - x0) / self.step) as usize) * channels;
+                if let (Some(target), Some(source)) = (output.get_mut(target..target + channels), chunk.get(source..source + channels)) {
+                    target.copy_from_slice(source);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use anyhow::anyhow;
+use image::{DynamicImage, ImageBuffer};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Curve that maps linear HDR radiance to the displayable 0..1 range
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug, Default)]
+pub enum ToneMapper {
+    #[default]
+    Reinhard,
+    Aces,
+    Linear,
+}
+
+impl ToneMapper {
+    pub const ALL: [ToneMapper; 3] = [ToneMapper::Reinhard, ToneMapper::Aces, ToneMapper::Linear];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ToneMapper::Reinhard => "Reinhard",
+            ToneMapper::Aces => "ACES filmic",
+            ToneMapper::Linear => "Linear (clip)",
+        }
+    }
+
+    fn map(&self, value: f32) -> f32 {
+        let x = value.max(0.0);
+        match self {
+            ToneMapper::Reinhard => x / (1.0 + x),
+            // Narkowicz's fit of the ACES reference rendering transform
+            ToneMapper::Aces => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14),
+            ToneMapper::Linear => x,
+        }
+    }
+}
+
+/// Whether a file is decoded as linear HDR data (OpenEXR, Radiance HDR)
+pub fn is_hdr_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| ext == "exr" || ext == "hdr")
+}
+
+/// Linear light to sRGB encoded value, both 0..1
+fn srgb_encode(linear: f32) -> f32 {
+    if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// 8-bit sRGB display image of interleaved linear RGB or RGBA data. `exposure` is in stops
+/// and applied before the curve, alpha is passed through.
+pub fn tone_map(data: &[f32], width: u32, height: u32, channels: u32, mapper: ToneMapper, exposure: f32) -> anyhow::Result<DynamicImage> {
+    let gain = 2f32.powf(exposure);
+    let encode = |value: f32| (srgb_encode(mapper.map(value * gain).clamp(0.0, 1.0)) * 255.0).round() as u8;
+    if channels == 4 {
+        let pixels = data.chunks_exact(4)
+            .flat_map(|p| [encode(p[0]), encode(p[1]), encode(p[2]), (p[3].clamp(0.0, 1.0) * 255.0).round() as u8])
+            .collect();
+        let buffer = ImageBuffer::from_raw(width, height, pixels).ok_or_else(|| anyhow!("HDR data does not match {}x{}", width, height))?;
+        Ok(DynamicImage::ImageRgba8(buffer))
+    } else {
+        let pixels = data.chunks_exact(3).flat_map(|p| [encode(p[0]), encode(p[1]), encode(p[2])]).collect();
+        let buffer = ImageBuffer::from_raw(width, height, pixels).ok_or_else(|| anyhow!("HDR data does not match {}x{}", width, height))?;
+        Ok(DynamicImage::ImageRgb8(buffer))
+    }
+}
@@ -0,0 +1,861 @@
+use image::DynamicImage;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::adjustments::Adjustments;
+use crate::expression::Expr;
+use crate::image_processing::{
+    adaptive_threshold, clahe, equalize, fft, frequency_filter, gaussian_blur, laplacian, log_min_max_normalize, luma_709, median,
+    min_max_normalize, otsu_level, percentile_normalize, rgba_samples, sobel, standardize, unsharp_mask,
+};
+use crate::threshold::{ThresholdMode, ThresholdOutput, ThresholdResult};
+use crate::ChannelType;
+
+/// Where a transform shows up in the UI and in which order it is applied
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TransformKind {
+    /// Remaps pixel values for display, exactly one is active at a time
+    Normalization,
+    /// Derived view (e.g. a spectrum) shown in its own window, not part of the display steps
+    Analysis,
+    /// Step of the user's pipeline, any number of them in any order after the normalization
+    Operation,
+    /// Spatial or frequency filter, a pipeline step that is also listed in the Filters menu
+    Filter,
+    /// Built from the viewer state (adjustments, channel, colormap), always the last steps
+    View,
+}
+
+/// How a parameter is edited in the UI
+#[derive(Clone, Copy, Debug)]
+pub enum ParamKind {
+    Float { min: f32, max: f32 },
+    /// Length in image pixels, scaled along when the image is displayed reduced
+    Pixels { min: f32, max: f32 },
+    Bool,
+}
+
+/// UI descriptor of a single transform parameter
+#[derive(Clone, Copy, Debug)]
+pub struct ParamDescriptor {
+    pub name: &'static str,
+    pub kind: ParamKind,
+    pub default: f32,
+}
+
+pub trait Transform: Send + Sync {
+    /// Stable identifier used for selection and caching
+    fn id(&self) -> &'static str;
+    /// Label shown in the UI
+    fn name(&self) -> &'static str;
+    fn kind(&self) -> TransformKind;
+    fn params(&self) -> &[ParamDescriptor] {
+        &[]
+    }
+    /// Apply the transform; `params` has one value per descriptor, in order
+    fn apply(&self, img: &DynamicImage, params: &[f32]) -> DynamicImage;
+}
+
+/// A reference applies the transform it points to, so registered and owned steps chain alike
+impl<T: Transform + ?Sized> Transform for &T {
+    fn id(&self) -> &'static str {
+        (**self).id()
+    }
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+    fn kind(&self) -> TransformKind {
+        (**self).kind()
+    }
+    fn params(&self) -> &[ParamDescriptor] {
+        (**self).params()
+    }
+    fn apply(&self, img: &DynamicImage, params: &[f32]) -> DynamicImage {
+        (**self).apply(img, params)
+    }
+}
+
+pub struct Identity;
+
+impl Transform for Identity {
+    fn id(&self) -> &'static str {
+        "none"
+    }
+    fn name(&self) -> &'static str {
+        "None"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Normalization
+    }
+    fn apply(&self, img: &DynamicImage, _params: &[f32]) -> DynamicImage {
+        img.clone()
+    }
+}
+
+pub struct MinMax;
+
+const MIN_MAX_PARAMS: [ParamDescriptor; 1] = [
+    ParamDescriptor { name: "Per channel", kind: ParamKind::Bool, default: 1.0 },
+];
+
+impl Transform for MinMax {
+    fn id(&self) -> &'static str {
+        "min_max"
+    }
+    fn name(&self) -> &'static str {
+        "Min-Max"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Normalization
+    }
+    fn params(&self) -> &[ParamDescriptor] {
+        &MIN_MAX_PARAMS
+    }
+    fn apply(&self, img: &DynamicImage, params: &[f32]) -> DynamicImage {
+        min_max_normalize(img, params[0] != 0.0)
+    }
+}
+
+pub struct Percentile;
+
+const PERCENTILE_PARAMS: [ParamDescriptor; 3] = [
+    ParamDescriptor { name: "Low %", kind: ParamKind::Float { min: 0.0, max: 49.0 }, default: 1.0 },
+    ParamDescriptor { name: "High %", kind: ParamKind::Float { min: 51.0, max: 100.0 }, default: 99.0 },
+    ParamDescriptor { name: "Per channel", kind: ParamKind::Bool, default: 1.0 },
+];
+
+impl Transform for Percentile {
+    fn id(&self) -> &'static str {
+        "percentile"
+    }
+    fn name(&self) -> &'static str {
+        "Percentile"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Normalization
+    }
+    fn params(&self) -> &[ParamDescriptor] {
+        &PERCENTILE_PARAMS
+    }
+    fn apply(&self, img: &DynamicImage, params: &[f32]) -> DynamicImage {
+        percentile_normalize(img, params[0], params[1], params[2] != 0.0)
+    }
+}
+
+pub struct LogMinMax;
+
+impl Transform for LogMinMax {
+    fn id(&self) -> &'static str {
+        "log_min_max"
+    }
+    fn name(&self) -> &'static str {
+        "Log Min-Max"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Normalization
+    }
+    fn apply(&self, img: &DynamicImage, _params: &[f32]) -> DynamicImage {
+        log_min_max_normalize(img)
+    }
+}
+
+pub struct Standard;
+
+const STANDARD_PARAMS: [ParamDescriptor; 2] = [
+    ParamDescriptor { name: "Spread", kind: ParamKind::Float { min: 5.0, max: 127.0 }, default: 50.0 },
+    ParamDescriptor { name: "Center", kind: ParamKind::Float { min: 0.0, max: 255.0 }, default: 127.0 },
+];
+
+impl Transform for Standard {
+    fn id(&self) -> &'static str {
+        "standard"
+    }
+    fn name(&self) -> &'static str {
+        "Standard"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Normalization
+    }
+    fn params(&self) -> &[ParamDescriptor] {
+        &STANDARD_PARAMS
+    }
+    fn apply(&self, img: &DynamicImage, params: &[f32]) -> DynamicImage {
+        standardize(img, params[0], params[1])
+    }
+}
+
+pub struct Equalize;
+
+impl Transform for Equalize {
+    fn id(&self) -> &'static str {
+        "equalize"
+    }
+    fn name(&self) -> &'static str {
+        "Equalize"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Normalization
+    }
+    fn apply(&self, img: &DynamicImage, _params: &[f32]) -> DynamicImage {
+        equalize(img)
+    }
+}
+
+pub struct Clahe;
+
+const CLAHE_PARAMS: [ParamDescriptor; 2] = [
+    ParamDescriptor { name: "Tiles", kind: ParamKind::Float { min: 2.0, max: 16.0 }, default: 8.0 },
+    ParamDescriptor { name: "Clip limit", kind: ParamKind::Float { min: 1.0, max: 10.0 }, default: 2.0 },
+];
+
+impl Transform for Clahe {
+    fn id(&self) -> &'static str {
+        "clahe"
+    }
+    fn name(&self) -> &'static str {
+        "CLAHE"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Normalization
+    }
+    fn params(&self) -> &[ParamDescriptor] {
+        &CLAHE_PARAMS
+    }
+    fn apply(&self, img: &DynamicImage, params: &[f32]) -> DynamicImage {
+        clahe(img, params[0].round().max(1.0) as u32, params[1])
+    }
+}
+
+pub struct Fft;
+
+const FFT_PARAMS: [ParamDescriptor; 1] = [
+    ParamDescriptor { name: "Hamming window", kind: ParamKind::Bool, default: 1.0 },
+];
+
+impl Transform for Fft {
+    fn id(&self) -> &'static str {
+        "fft"
+    }
+    fn name(&self) -> &'static str {
+        "FFT"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Analysis
+    }
+    fn params(&self) -> &[ParamDescriptor] {
+        &FFT_PARAMS
+    }
+    fn apply(&self, img: &DynamicImage, params: &[f32]) -> DynamicImage {
+        fft(img, params[0] != 0.0)
+    }
+}
+
+/// Map the color channels of an 8-bit copy through a lookup table, alpha is kept
+fn map_values(img: &DynamicImage, lut: impl Fn(u8) -> u8) -> DynamicImage {
+    let lut: Vec<u8> = (0..=255).map(lut).collect();
+    let mut rgba = img.to_rgba8();
+    for pixel in rgba.pixels_mut() {
+        for value in &mut pixel.0[..3] {
+            *value = lut[*value as usize];
+        }
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
+pub struct Gamma;
+
+const GAMMA_PARAMS: [ParamDescriptor; 1] = [
+    ParamDescriptor { name: "Gamma", kind: ParamKind::Float { min: 0.1, max: 5.0 }, default: 2.2 },
+];
+
+impl Transform for Gamma {
+    fn id(&self) -> &'static str {
+        "gamma"
+    }
+    fn name(&self) -> &'static str {
+        "Gamma"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Operation
+    }
+    fn params(&self) -> &[ParamDescriptor] {
+        &GAMMA_PARAMS
+    }
+    fn apply(&self, img: &DynamicImage, params: &[f32]) -> DynamicImage {
+        let exponent = 1.0 / params[0].max(0.01);
+        map_values(img, |v| ((v as f32 / 255.0).powf(exponent) * 255.0).round() as u8)
+    }
+}
+
+pub struct Invert;
+
+impl Transform for Invert {
+    fn id(&self) -> &'static str {
+        "invert"
+    }
+    fn name(&self) -> &'static str {
+        "Invert"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Operation
+    }
+    fn apply(&self, img: &DynamicImage, _params: &[f32]) -> DynamicImage {
+        map_values(img, |v| 255 - v)
+    }
+}
+
+pub struct Threshold;
+
+const THRESHOLD_PARAMS: [ParamDescriptor; 1] = [
+    ParamDescriptor { name: "Level", kind: ParamKind::Float { min: 0.0, max: 255.0 }, default: 128.0 },
+];
+
+impl Transform for Threshold {
+    fn id(&self) -> &'static str {
+        "threshold"
+    }
+    fn name(&self) -> &'static str {
+        "Threshold"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Operation
+    }
+    fn params(&self) -> &[ParamDescriptor] {
+        &THRESHOLD_PARAMS
+    }
+    fn apply(&self, img: &DynamicImage, params: &[f32]) -> DynamicImage {
+        map_values(img, |v| if v as f32 >= params[0] { 255 } else { 0 })
+    }
+}
+
+pub struct GaussianBlur;
+
+const GAUSSIAN_BLUR_PARAMS: [ParamDescriptor; 1] = [
+    ParamDescriptor { name: "Sigma", kind: ParamKind::Pixels { min: 0.3, max: 20.0 }, default: 2.0 },
+];
+
+impl Transform for GaussianBlur {
+    fn id(&self) -> &'static str {
+        "gaussian_blur"
+    }
+    fn name(&self) -> &'static str {
+        "Gaussian blur"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Filter
+    }
+    fn params(&self) -> &[ParamDescriptor] {
+        &GAUSSIAN_BLUR_PARAMS
+    }
+    fn apply(&self, img: &DynamicImage, params: &[f32]) -> DynamicImage {
+        gaussian_blur(img, params[0])
+    }
+}
+
+pub struct UnsharpMask;
+
+const UNSHARP_MASK_PARAMS: [ParamDescriptor; 3] = [
+    ParamDescriptor { name: "Sigma", kind: ParamKind::Pixels { min: 0.3, max: 20.0 }, default: 2.0 },
+    ParamDescriptor { name: "Amount", kind: ParamKind::Float { min: 0.0, max: 5.0 }, default: 1.0 },
+    ParamDescriptor { name: "Threshold", kind: ParamKind::Float { min: 0.0, max: 64.0 }, default: 0.0 },
+];
+
+impl Transform for UnsharpMask {
+    fn id(&self) -> &'static str {
+        "unsharp_mask"
+    }
+    fn name(&self) -> &'static str {
+        "Unsharp mask"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Filter
+    }
+    fn params(&self) -> &[ParamDescriptor] {
+        &UNSHARP_MASK_PARAMS
+    }
+    fn apply(&self, img: &DynamicImage, params: &[f32]) -> DynamicImage {
+        unsharp_mask(img, params[0], params[1], params[2])
+    }
+}
+
+pub struct Sobel;
+
+impl Transform for Sobel {
+    fn id(&self) -> &'static str {
+        "sobel"
+    }
+    fn name(&self) -> &'static str {
+        "Sobel edges"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Filter
+    }
+    fn apply(&self, img: &DynamicImage, _params: &[f32]) -> DynamicImage {
+        sobel(img)
+    }
+}
+
+pub struct Laplacian;
+
+impl Transform for Laplacian {
+    fn id(&self) -> &'static str {
+        "laplacian"
+    }
+    fn name(&self) -> &'static str {
+        "Laplacian edges"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Filter
+    }
+    fn apply(&self, img: &DynamicImage, _params: &[f32]) -> DynamicImage {
+        laplacian(img)
+    }
+}
+
+pub struct Median;
+
+const MEDIAN_PARAMS: [ParamDescriptor; 1] = [
+    ParamDescriptor { name: "Radius", kind: ParamKind::Pixels { min: 1.0, max: 10.0 }, default: 2.0 },
+];
+
+impl Transform for Median {
+    fn id(&self) -> &'static str {
+        "median"
+    }
+    fn name(&self) -> &'static str {
+        "Median"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Filter
+    }
+    fn params(&self) -> &[ParamDescriptor] {
+        &MEDIAN_PARAMS
+    }
+    fn apply(&self, img: &DynamicImage, params: &[f32]) -> DynamicImage {
+        median(img, params[0].round().max(0.0) as u32)
+    }
+}
+
+const FREQUENCY_FILTER_PARAMS: [ParamDescriptor; 1] = [
+    ParamDescriptor { name: "Cutoff wavelength", kind: ParamKind::Pixels { min: 2.0, max: 200.0 }, default: 8.0 },
+];
+
+pub struct LowPass;
+
+impl Transform for LowPass {
+    fn id(&self) -> &'static str {
+        "low_pass"
+    }
+    fn name(&self) -> &'static str {
+        "Low-pass (FFT)"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Filter
+    }
+    fn params(&self) -> &[ParamDescriptor] {
+        &FREQUENCY_FILTER_PARAMS
+    }
+    fn apply(&self, img: &DynamicImage, params: &[f32]) -> DynamicImage {
+        frequency_filter(img, params[0], false)
+    }
+}
+
+pub struct HighPass;
+
+impl Transform for HighPass {
+    fn id(&self) -> &'static str {
+        "high_pass"
+    }
+    fn name(&self) -> &'static str {
+        "High-pass (FFT)"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Filter
+    }
+    fn params(&self) -> &[ParamDescriptor] {
+        &FREQUENCY_FILTER_PARAMS
+    }
+    fn apply(&self, img: &DynamicImage, params: &[f32]) -> DynamicImage {
+        frequency_filter(img, params[0], true)
+    }
+}
+
+impl Transform for Adjustments {
+    fn id(&self) -> &'static str {
+        "adjustments"
+    }
+    fn name(&self) -> &'static str {
+        "Adjustments"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::View
+    }
+    fn apply(&self, img: &DynamicImage, _params: &[f32]) -> DynamicImage {
+        let mut rgba = img.to_rgba8();
+        Adjustments::apply(self, &mut rgba);
+        DynamicImage::ImageRgba8(rgba)
+    }
+}
+
+/// The displayed channel, through a colormap when one is selected
+pub struct ChannelView {
+    pub channel: ChannelType,
+    /// Show a single color channel as gray instead of in its color
+    pub as_gray: bool,
+    pub colormap: Option<[[u8; 3]; 256]>,
+}
+
+impl Transform for ChannelView {
+    fn id(&self) -> &'static str {
+        "channel"
+    }
+    fn name(&self) -> &'static str {
+        "Channel"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::View
+    }
+    fn apply(&self, img: &DynamicImage, _params: &[f32]) -> DynamicImage {
+        let mut rgba = img.to_rgba8();
+        if self.channel == ChannelType::Rgb && self.colormap.is_none() {
+            return DynamicImage::ImageRgba8(rgba);
+        }
+        for pixel in rgba.pixels_mut() {
+            let [r, g, b, a] = pixel.0;
+            pixel.0 = if let Some(lut) = &self.colormap {
+                // False color maps the selected channel (luma for RGB) through the colormap
+                let value = match self.channel {
+                    ChannelType::Rgb | ChannelType::Luminance => luma_709(r, g, b),
+                    ChannelType::Red => r,
+                    ChannelType::Green => g,
+                    ChannelType::Blue => b,
+                    ChannelType::Alpha => a,
+                };
+                let [r, g, b] = lut[value as usize];
+                // The alpha view shows the transparency itself, so it is drawn opaque
+                [r, g, b, if self.channel == ChannelType::Alpha { 255 } else { a }]
+            } else {
+                match self.channel {
+                    ChannelType::Rgb => [r, g, b, a],
+                    ChannelType::Luminance => {
+                        let y = luma_709(r, g, b);
+                        [y, y, y, a]
+                    }
+                    ChannelType::Red if self.as_gray => [r, r, r, a],
+                    ChannelType::Green if self.as_gray => [g, g, g, a],
+                    ChannelType::Blue if self.as_gray => [b, b, b, a],
+                    ChannelType::Red => [r, 0, 0, a],
+                    ChannelType::Green => [0, g, 0, a],
+                    ChannelType::Blue => [0, 0, b, a],
+                    ChannelType::Alpha => [a, a, a, 255],
+                }
+            };
+        }
+        DynamicImage::ImageRgba8(rgba)
+    }
+}
+
+/// Binarization of the displayed luma, the result is reported back to the threshold tool
+pub struct ThresholdView {
+    pub mode: ThresholdMode,
+    pub output: ThresholdOutput,
+    /// Level of the manual mode
+    pub level: u8,
+    pub result: Arc<Mutex<Option<ThresholdResult>>>,
+}
+
+const THRESHOLD_VIEW_PARAMS: [ParamDescriptor; 2] = [
+    ParamDescriptor { name: "Radius", kind: ParamKind::Pixels { min: 1.0, max: 100.0 }, default: 15.0 },
+    ParamDescriptor { name: "Offset", kind: ParamKind::Float { min: -50.0, max: 50.0 }, default: 5.0 },
+];
+
+impl Transform for ThresholdView {
+    fn id(&self) -> &'static str {
+        "threshold_view"
+    }
+    fn name(&self) -> &'static str {
+        "Threshold"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::View
+    }
+    /// Neighborhood of the adaptive mode
+    fn params(&self) -> &[ParamDescriptor] {
+        &THRESHOLD_VIEW_PARAMS
+    }
+    fn apply(&self, img: &DynamicImage, params: &[f32]) -> DynamicImage {
+        let mut rgba = img.to_rgba8();
+        let (width, height) = rgba.dimensions();
+        let luma: Vec<u8> = rgba.pixels().map(|p| luma_709(p[0], p[1], p[2])).collect();
+        let level = match self.mode {
+            ThresholdMode::Manual => Some(self.level),
+            ThresholdMode::Otsu => {
+                let mut histogram = [0u32; 256];
+                for &value in &luma {
+                    histogram[value as usize] += 1;
+                }
+                Some(otsu_level(&histogram))
+            }
+            ThresholdMode::Adaptive => None,
+        };
+        let foreground = match level {
+            Some(level) => luma.iter().map(|&value| value > level).collect(),
+            None => adaptive_threshold(&luma, width, height, params[0].round().max(1.0) as u32, params[1]),
+        };
+
+        let share = foreground.iter().filter(|&&f| f).count() as f32 / foreground.len().max(1) as f32;
+        if let Ok(mut result) = self.result.lock() {
+            *result = Some(ThresholdResult { level, foreground: share });
+        }
+
+        for (pixel, &foreground) in rgba.pixels_mut().zip(&foreground) {
+            pixel.0 = match (self.output, foreground) {
+                (ThresholdOutput::Binary, true) => [255, 255, 255, 255],
+                (ThresholdOutput::Binary, false) => [0, 0, 0, 255],
+                // Half way to red
+                (ThresholdOutput::Overlay, true) => [pixel[0] / 2 + 128, pixel[1] / 2, pixel[2] / 2, pixel[3].max(128)],
+                (ThresholdOutput::Overlay, false) => pixel.0,
+            };
+        }
+        DynamicImage::ImageRgba8(rgba)
+    }
+}
+
+/// Single channel image computed by an expression of the samples, in place of the normalization.
+/// The finite range of the result is reported back to the expression tool.
+pub struct ExpressionView {
+    pub expr: Arc<Expr>,
+    /// Values mapped to black and white, the result's own range if `None`
+    pub range: Option<[f32; 2]>,
+    pub result: Arc<Mutex<Option<(f32, f32)>>>,
+}
+
+impl Transform for ExpressionView {
+    fn id(&self) -> &'static str {
+        "expression"
+    }
+    fn name(&self) -> &'static str {
+        "Expression"
+    }
+    fn kind(&self) -> TransformKind {
+        TransformKind::Normalization
+    }
+    fn apply(&self, img: &DynamicImage, _params: &[f32]) -> DynamicImage {
+        let values: Vec<f32> = rgba_samples(img).iter().map(|pixel| self.expr.eval(pixel)).collect();
+        let finite = values.iter().filter(|v| v.is_finite())
+            .fold(None, |range: Option<(f32, f32)>, &v| Some(range.map_or((v, v), |(min, max)| (min.min(v), max.max(v)))));
+        if let Ok(mut result) = self.result.lock() {
+            *result = finite;
+        }
+
+        let [low, high] = self.range.unwrap_or_else(|| finite.map_or([0.0, 1.0], |(min, max)| [min, max]));
+        let scale = if (high - low).abs() > f32::EPSILON { 255.0 / (high - low) } else { 0.0 };
+        // Not a number (e.g. 0 / 0) is shown black
+        let gray = values.iter().map(|&v| if v.is_nan() { 0 } else { ((v - low) * scale).clamp(0.0, 255.0) as u8 }).collect();
+        image::GrayImage::from_raw(img.width(), img.height(), gray)
+            .map_or_else(|| DynamicImage::new_luma8(img.width(), img.height()), DynamicImage::ImageLuma8)
+    }
+}
+
+/// One operation of a pipeline with its own parameter values
+#[derive(Clone, Debug)]
+pub struct PipelineStep {
+    pub id: &'static str,
+    pub params: Vec<f32>,
+    pub enabled: bool,
+}
+
+/// Operations and filters the user chained after the normalization, applied in order
+#[derive(Clone, Debug, Default)]
+pub struct Pipeline {
+    pub steps: Vec<PipelineStep>,
+}
+
+impl Pipeline {
+    /// Enabled steps with their parameters, steps of unknown transforms are skipped
+    pub fn active<'a>(&'a self, registry: &'a TransformRegistry) -> impl Iterator<Item = (&'a dyn Transform, &'a [f32])> {
+        self.steps.iter()
+            .filter(|step| step.enabled)
+            .filter_map(|step| Some((registry.get(step.id)?, step.params.as_slice())))
+    }
+
+    pub fn push(&mut self, transform: &dyn Transform) {
+        let params = transform.params().iter().map(|p| p.default).collect();
+        self.steps.push(PipelineStep { id: transform.id(), params, enabled: true });
+    }
+
+    /// Draw the steps with enable, reorder, remove and parameter controls, and a menu adding
+    /// operations. Returns true if the output changes.
+    pub fn ui(&mut self, ui: &mut egui::Ui, registry: &TransformRegistry) -> bool {
+        let mut changed = false;
+        let mut moved = None;
+        let mut removed = None;
+        if self.steps.is_empty() {
+            ui.weak("No steps, the normalized image is shown as it is");
+        }
+        let count = self.steps.len();
+        for (index, step) in self.steps.iter_mut().enumerate() {
+            let Some(transform) = registry.get(step.id) else {
+                continue;
+            };
+            ui.horizontal(|ui| {
+                changed |= ui.checkbox(&mut step.enabled, format!("{}. {}", index + 1, transform.name())).changed();
+                if ui.add_enabled(index > 0, egui::Button::new("⬆").small()).on_hover_text("Apply earlier").clicked() {
+                    moved = Some((index, index - 1));
+                }
+                if ui.add_enabled(index + 1 < count, egui::Button::new("⬇").small()).on_hover_text("Apply later").clicked() {
+                    moved = Some((index, index + 1));
+                }
+                if ui.small_button("✖").on_hover_text("Remove step").clicked() {
+                    removed = Some(index);
+                }
+            });
+            if !transform.params().is_empty() {
+                ui.horizontal(|ui| {
+                    ui.add_space(24.0);
+                    changed |= param_editors(ui, transform.params(), &mut step.params);
+                });
+            }
+        }
+        if let Some((from, to)) = moved {
+            self.steps.swap(from, to);
+            changed = true;
+        }
+        if let Some(index) = removed {
+            self.steps.remove(index);
+            changed = true;
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.menu_button("Add step", |ui| {
+                for transform in registry.of_kind(TransformKind::Operation) {
+                    if ui.button(transform.name()).clicked() {
+                        self.push(transform);
+                        changed = true;
+                        ui.close_menu();
+                    }
+                }
+                ui.separator();
+                for transform in registry.of_kind(TransformKind::Filter) {
+                    if ui.button(transform.name()).clicked() {
+                        self.push(transform);
+                        changed = true;
+                        ui.close_menu();
+                    }
+                }
+            });
+            if ui.add_enabled(!self.steps.is_empty(), egui::Button::new("Clear")).clicked() {
+                self.steps.clear();
+                changed = true;
+            }
+        });
+        changed
+    }
+}
+
+/// Editors for parameter values, one per descriptor. Returns true if any value changed.
+fn param_editors(ui: &mut egui::Ui, descriptors: &[ParamDescriptor], values: &mut [f32]) -> bool {
+    let mut changed = false;
+    for (descriptor, value) in descriptors.iter().zip(values.iter_mut()) {
+        ui.label(format!("{}:", descriptor.name));
+        changed |= match descriptor.kind {
+            ParamKind::Float { min, max } => ui.add(egui::Slider::new(value, min..=max)).changed(),
+            ParamKind::Pixels { min, max } => ui.add(egui::Slider::new(value, min..=max).suffix(" px")).changed(),
+            ParamKind::Bool => {
+                let mut enabled = *value != 0.0;
+                let response = ui.checkbox(&mut enabled, "");
+                *value = if enabled { 1.0 } else { 0.0 };
+                response.changed()
+            }
+        };
+    }
+    changed
+}
+
+/// Parameter values for an image displayed at `resolution` times its size, lengths in pixels are scaled
+pub fn params_at_resolution(descriptors: &[ParamDescriptor], values: &[f32], resolution: f32) -> Vec<f32> {
+    descriptors.iter().zip(values).map(|(descriptor, &value)| match descriptor.kind {
+        ParamKind::Pixels { .. } => value * resolution,
+        ParamKind::Float { .. } | ParamKind::Bool => value,
+    }).collect()
+}
+
+/// All available transforms plus the current parameter values of each
+pub struct TransformRegistry {
+    transforms: Vec<Box<dyn Transform>>,
+    params: HashMap<&'static str, Vec<f32>>,
+}
+
+impl Default for TransformRegistry {
+    fn default() -> Self {
+        let mut registry = Self { transforms: Vec::new(), params: HashMap::new() };
+        registry.register(Box::new(Identity));
+        registry.register(Box::new(MinMax));
+        registry.register(Box::new(Percentile));
+        registry.register(Box::new(LogMinMax));
+        registry.register(Box::new(Standard));
+        registry.register(Box::new(Equalize));
+        registry.register(Box::new(Clahe));
+        registry.register(Box::new(Fft));
+        registry.register(Box::new(Gamma));
+        registry.register(Box::new(Invert));
+        registry.register(Box::new(Threshold));
+        registry.register(Box::new(GaussianBlur));
+        registry.register(Box::new(UnsharpMask));
+        registry.register(Box::new(Median));
+        registry.register(Box::new(Sobel));
+        registry.register(Box::new(Laplacian));
+        registry.register(Box::new(LowPass));
+        registry.register(Box::new(HighPass));
+        registry
+    }
+}
+
+impl TransformRegistry {
+    pub fn register(&mut self, transform: Box<dyn Transform>) {
+        let defaults = transform.params().iter().map(|p| p.default).collect();
+        self.params.insert(transform.id(), defaults);
+        self.transforms.push(transform);
+    }
+
+    pub fn get(&self, id: &str) -> Option<&dyn Transform> {
+        self.transforms.iter().find(|t| t.id() == id).map(|t| t.as_ref())
+    }
+
+    pub fn of_kind(&self, kind: TransformKind) -> impl Iterator<Item = &dyn Transform> {
+        self.transforms.iter().filter(move |t| t.kind() == kind).map(|t| t.as_ref())
+    }
+
+    pub fn params(&self, id: &str) -> &[f32] {
+        self.params.get(id).map(|p| p.as_slice()).unwrap_or(&[])
+    }
+
+    /// Replace the leading parameter values of a transform, the rest keep their current values
+    pub fn set_params(&mut self, id: &str, values: &[f32]) {
+        if let Some(params) = self.params.get_mut(id) {
+            for (param, &value) in params.iter_mut().zip(values) {
+                *param = value;
+            }
+        }
+    }
+
+    /// Apply a transform by id; unknown ids leave the image untouched
+    pub fn apply(&self, id: &str, img: DynamicImage) -> DynamicImage {
+        match self.get(id) {
+            Some(transform) => transform.apply(&img, self.params(id)),
+            None => img,
+        }
+    }
+
+    /// Draw editors for the parameters of a transform, returns true if any value changed
+    pub fn params_ui(&mut self, ui: &mut egui::Ui, id: &str) -> bool {
+        let Some(transform) = self.transforms.iter().find(|t| t.id() == id) else {
+            return false;
+        };
+        let Some(values) = self.params.get_mut(transform.id()) else {
+            return false;
+        };
+        param_editors(ui, transform.params(), values)
+    }
+}
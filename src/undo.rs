@@ -0,0 +1,101 @@
+//! Undo and redo of the edits that change the loaded pixels: rotations, flips, crops and
+//! resizes. Every edit stores the state before it, the oldest states are dropped when they take
+//! more memory than the budget.
+
+use image::DynamicImage;
+use std::collections::VecDeque;
+
+use crate::frames::FrameStack;
+use crate::nifti::SliceEdit;
+use crate::orientation::Orientation;
+
+/// Pixels and geometry of a document
+pub struct Snapshot {
+    pub image: DynamicImage,
+    pub fp_data: Option<Vec<f32>>,
+    pub fp_dimensions: Option<(u32, u32)>,
+    pub frames: Option<FrameStack>,
+    /// Edits of the shown NIfTI plane, the slice is rebuilt from them
+    pub volume_edits: Option<Vec<SliceEdit>>,
+    pub orientation: Orientation,
+    pub edited: bool,
+}
+
+impl Snapshot {
+    pub fn bytes(&self) -> usize {
+        self.image.as_bytes().len()
+            + self.fp_data.as_ref().map_or(0, |data| data.len() * size_of::<f32>())
+            + self.frames.as_ref().map_or(0, |frames| frames.iter().map(|frame| frame.as_bytes().len()).sum())
+    }
+}
+
+struct Step {
+    /// Name of the edit, e.g. "Crop"
+    label: &'static str,
+    snapshot: Snapshot,
+    bytes: usize,
+}
+
+/// States before the applied edits and after the undone ones
+#[derive(Default)]
+pub struct History {
+    /// Oldest first
+    undo: VecDeque<Step>,
+    /// Most recently undone last
+    redo: Vec<Step>,
+}
+
+impl History {
+    /// Store the state before an edit, which can no longer be redone after it. States that do
+    /// not fit in `budget` bytes are dropped, oldest first.
+    pub fn record(&mut self, label: &'static str, snapshot: Snapshot, budget: usize) {
+        self.redo.clear();
+        let bytes = snapshot.bytes();
+        self.undo.push_back(Step { label, snapshot, bytes });
+        self.trim(budget);
+    }
+
+    /// The state before the last edit, `current` becomes redoable
+    pub fn undo(&mut self, current: Snapshot, budget: usize) -> Option<Snapshot> {
+        let step = self.undo.pop_back()?;
+        let bytes = current.bytes();
+        self.redo.push(Step { label: step.label, snapshot: current, bytes });
+        self.trim(budget);
+        Some(step.snapshot)
+    }
+
+    /// The state after the last undone edit, `current` becomes undoable
+    pub fn redo(&mut self, current: Snapshot, budget: usize) -> Option<Snapshot> {
+        let step = self.redo.pop()?;
+        let bytes = current.bytes();
+        self.undo.push_back(Step { label: step.label, snapshot: current, bytes });
+        self.trim(budget);
+        Some(step.snapshot)
+    }
+
+    pub fn undo_label(&self) -> Option<&'static str> {
+        self.undo.back().map(|step| step.label)
+    }
+
+    pub fn redo_label(&self) -> Option<&'static str> {
+        self.redo.last().map(|step| step.label)
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// Drop the oldest undo states, then the furthest redo states, until the rest fits
+    fn trim(&mut self, budget: usize) {
+        let mut used: usize = self.undo.iter().chain(&self.redo).map(|step| step.bytes).sum();
+        while used > budget {
+            let dropped = match self.undo.pop_front() {
+                Some(step) => step,
+                None if !self.redo.is_empty() => self.redo.remove(0),
+                None => break,
+            };
+            used -= dropped.bytes;
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use anyhow::{anyhow, bail, Context};
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use log::{info, warn};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread;
+
+/// Function used by the encoder thread to decode a frame
+pub type FrameLoader = fn(&Path) -> anyhow::Result<DynamicImage>;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum VideoFormat {
+    Mp4,
+    WebM,
+}
+
+impl VideoFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VideoFormat::Mp4 => "MP4 (H.264)",
+            VideoFormat::WebM => "WebM (VP9)",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            VideoFormat::Mp4 => "mp4",
+            VideoFormat::WebM => "webm",
+        }
+    }
+
+    fn codec_args(&self) -> &'static [&'static str] {
+        match self {
+            VideoFormat::Mp4 => &["-c:v", "libx264", "-preset", "medium", "-crf", "18", "-movflags", "+faststart"],
+            VideoFormat::WebM => &["-c:v", "libvpx-vp9", "-b:v", "0", "-crf", "32"],
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct VideoExportOptions {
+    pub fps: u32,
+    /// Output width in pixels, the height follows the first frame's aspect ratio
+    pub width: u32,
+    pub format: VideoFormat,
+}
+
+impl Default for VideoExportOptions {
+    fn default() -> Self {
+        Self {
+            fps: 10,
+            width: 1280,
+            format: VideoFormat::Mp4,
+        }
+    }
+}
+
+/// A running ffmpeg encode on a background thread
+pub struct VideoEncodeJob {
+    pub total: usize,
+    progress: Arc<AtomicUsize>,
+    cancel: Arc<AtomicBool>,
+    result_rx: Receiver<anyhow::Result<PathBuf>>,
+}
+
+impl VideoEncodeJob {
+    pub fn start(
+        ctx: &egui::Context,
+        frames: Vec<PathBuf>,
+        output: PathBuf,
+        options: VideoExportOptions,
+        loader: FrameLoader,
+    ) -> Self {
+        let total = frames.len();
+        let progress = Arc::new(AtomicUsize::new(0));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (result_tx, result_rx) = channel();
+        let ctx = ctx.clone();
+
+        let (thread_progress, thread_cancel) = (Arc::clone(&progress), Arc::clone(&cancel));
+        thread::spawn(move || {
+            let result = encode(&frames, &output, options, loader, &thread_progress, &thread_cancel, &ctx).map(|_| output);
+            let _ = result_tx.send(result);
+            ctx.request_repaint();
+        });
+
+        Self { total, progress, cancel, result_rx }
+    }
+
+    /// Number of frames written so far
+    pub fn progress(&self) -> usize {
+        self.progress.load(Ordering::Relaxed)
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// The result once the encode has finished
+    pub fn poll(&self) -> Option<anyhow::Result<PathBuf>> {
+        self.result_rx.try_recv().ok()
+    }
+}
+
+/// Even output size for `width`, keeping the aspect ratio of the first frame (yuv420p needs even sizes)
+fn output_size(first: &DynamicImage, width: u32) -> (u32, u32) {
+    let (w, h) = first.dimensions();
+    let width = (width.max(2) / 2) * 2;
+    let height = ((h as f32 * width as f32 / w as f32).round() as u32 / 2).max(1) * 2;
+    (width, height)
+}
+
+/// Fit a frame into the output size, centered on black
+fn fit_frame(img: &DynamicImage, width: u32, height: u32) -> RgbaImage {
+    let resized = img.resize(width, height, image::imageops::FilterType::Lanczos3).to_rgba8();
+    if resized.dimensions() == (width, height) {
+        return resized;
+    }
+    let mut canvas = RgbaImage::from_pixel(width, height, image::Rgba([0, 0, 0, 255]));
+    let x = (width - resized.width()) / 2;
+    let y = (height - resized.height()) / 2;
+    image::imageops::overlay(&mut canvas, &resized, x as i64, y as i64);
+    canvas
+}
+
+fn encode(
+    frames: &[PathBuf],
+    output: &Path,
+    options: VideoExportOptions,
+    loader: FrameLoader,
+    progress: &AtomicUsize,
+    cancel: &AtomicBool,
+    ctx: &egui::Context,
+) -> anyhow::Result<()> {
+    let Some(first_path) = frames.first() else {
+        bail!("No frames to encode");
+    };
+    let (width, height) = output_size(&loader(first_path)?, options.width);
+    info!("Encoding {} frames to {:?} at {}x{}, {} fps", frames.len(), output, width, height, options.fps);
+
+    let mut child = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-f", "rawvideo", "-pix_fmt", "rgba"])
+        .args(["-s", &format!("{}x{}", width, height), "-r", &options.fps.to_string(), "-i", "-"])
+        .args(options.format.codec_args())
+        .args(["-pix_fmt", "yuv420p"])
+        .arg(output)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Could not start ffmpeg, make sure it is installed and on the PATH")?;
+
+    let mut stdin = child.stdin.take().ok_or_else(|| anyhow!("No pipe to ffmpeg"))?;
+    for path in frames {
+        if cancel.load(Ordering::Relaxed) {
+            drop(stdin);
+            let _ = child.kill();
+            let _ = child.wait();
+            let _ = std::fs::remove_file(output);
+            bail!("Encoding cancelled");
+        }
+        match loader(path) {
+            Ok(img) => {
+                if let Err(e) = stdin.write_all(fit_frame(&img, width, height).as_raw()) {
+                    // ffmpeg exited early, its error output explains why
+                    warn!("Writing frame to ffmpeg failed: {}", e);
+                    break;
+                }
+            }
+            Err(e) => warn!("Skipping frame {:?}: {}", path, e),
+        }
+        progress.fetch_add(1, Ordering::Relaxed);
+        ctx.request_repaint();
+    }
+    drop(stdin);
+
+    let result = child.wait_with_output()?;
+    if !result.status.success() {
+        bail!("ffmpeg failed: {}", String::from_utf8_lossy(&result.stderr).trim());
+    }
+    Ok(())
+}
@@ -0,0 +1,53 @@
+//! The view rendered into an image for reports: the visible part of the image at the zoom,
+//! rotation and display mapping of the screen, with the pixel grid and the annotations on top.
+//! Rendering at two output pixels per screen point keeps lines and text sharp when printed.
+
+use image::{Rgba, RgbaImage};
+
+use crate::annotations::{self, Annotation};
+use crate::orientation::ViewMapping;
+
+/// Render the screen rectangle `rect` of the view at `factor` output pixels per point.
+/// `displayed` is the display-mapped image, at the image size or reduced; the pixel grid is
+/// drawn one point wide in `grid` when given. Outside the image the output is transparent.
+pub fn render(displayed: &RgbaImage, view: ViewMapping, rect: egui::Rect, factor: f32, grid: Option<egui::Color32>, annotations: &[Annotation]) -> anyhow::Result<RgbaImage> {
+    let (width, height) = ((rect.width() * factor).round() as u32, (rect.height() * factor).round() as u32);
+    if width == 0 || height == 0 {
+        anyhow::bail!("No part of the image is in view");
+    }
+    // Pixels of the displayed image per image pixel, below 1 for reduced images
+    let (sx, sy) = (displayed.width() as f32 / view.size.x, displayed.height() as f32 / view.size.y);
+    let grid = grid.map(|color| color.to_srgba_unmultiplied());
+    // Half a point around each pixel border is on the grid line
+    let half_line = 0.5 / view.scale;
+    let mut output = RgbaImage::from_fn(width, height, |x, y| {
+        let point = view.to_image(rect.min + egui::vec2(x as f32 + 0.5, y as f32 + 0.5) / factor);
+        if point.x < 0.0 || point.y < 0.0 || point.x >= view.size.x || point.y >= view.size.y {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let (px, py) = (((point.x * sx) as u32).min(displayed.width() - 1), ((point.y * sy) as u32).min(displayed.height() - 1));
+        let mut pixel = *displayed.get_pixel(px, py);
+        let on_line = |v: f32| (v - v.round()).abs() < half_line;
+        if let Some([r, g, b, a]) = grid.filter(|_| on_line(point.x) || on_line(point.y)) {
+            let alpha = a as f32 / 255.0;
+            for (channel, target) in [r, g, b].into_iter().zip(pixel.0.iter_mut()) {
+                *target = (channel as f32 * alpha + *target as f32 * (1.0 - alpha)).round() as u8;
+            }
+            pixel.0[3] = (255.0 * alpha + pixel.0[3] as f32 * (1.0 - alpha)).round() as u8;
+        }
+        pixel
+    });
+
+    let to_output = |p: annotations::Point| {
+        let pos = (view.to_screen(egui::pos2(p[0], p[1])) - rect.min) * factor;
+        [pos.x, pos.y]
+    };
+    let mapped: Vec<Annotation> = annotations.iter().map(|annotation| {
+        let mut mapped = annotation.mapped(to_output, view.scale * factor);
+        // Lines are at least a point wide on screen
+        mapped.width = mapped.width.max(factor);
+        mapped
+    }).collect();
+    annotations::burn_in(&mut output, &mapped)?;
+    Ok(output)
+}
@@ -0,0 +1,256 @@
+//! Desktop background set from the viewer. The displayed image is written to a PNG in the data
+//! folder, which the desktop keeps reading after the viewer quits, and handed to the system:
+//! SystemParametersInfo on Windows, NSWorkspace on macOS, and on Linux the settings of GNOME,
+//! Cinnamon, MATE, KDE Plasma or Xfce, or feh for other window managers.
+
+use anyhow::Context;
+use image::RgbaImage;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How the image covers the screen
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum WallpaperMode {
+    /// Scaled to cover the screen, cropping what does not fit
+    Fill,
+    /// Scaled to fit the screen, leaving bars
+    Fit,
+    /// Repeated at its size
+    Tile,
+}
+
+impl WallpaperMode {
+    pub const ALL: [WallpaperMode; 3] = [WallpaperMode::Fill, WallpaperMode::Fit, WallpaperMode::Tile];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WallpaperMode::Fill => "Fill",
+            WallpaperMode::Fit => "Fit",
+            WallpaperMode::Tile => "Tile",
+        }
+    }
+}
+
+/// Write the image into the data folder and make it the desktop background
+pub fn set_wallpaper(image: &RgbaImage, mode: WallpaperMode) -> anyhow::Result<PathBuf> {
+    let path = store(image)?;
+    if let Err(e) = platform::set(&path, mode) {
+        // The current background may still be one of the older files
+        let _ = fs::remove_file(&path);
+        return Err(e);
+    }
+    remove_older(&path);
+    Ok(path)
+}
+
+/// Save the image under a new name, so desktops that cache by file name pick it up
+fn store(image: &RgbaImage) -> anyhow::Result<PathBuf> {
+    let dir = dirs::data_dir().context("No data folder to keep the wallpaper in")?.join("image_viewer");
+    fs::create_dir_all(&dir)?;
+    let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_millis());
+    let path = dir.join(format!("wallpaper-{}.png", stamp));
+    image.save(&path).with_context(|| format!("Failed to write {:?}", path))?;
+    Ok(path)
+}
+
+/// Remove the wallpapers stored before `current`, once the desktop shows it
+fn remove_older(current: &Path) {
+    let Some(entries) = current.parent().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path != current && entry.file_name().to_string_lossy().starts_with("wallpaper-") {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Run a helper program, failing with its error output
+#[cfg(unix)]
+fn run(command: &mut Command) -> anyhow::Result<String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command.output().with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        anyhow::bail!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod platform {
+    use super::{run, WallpaperMode};
+    use crate::thumbnail_store::file_uri;
+    use anyhow::bail;
+    use std::path::Path;
+    use std::process::Command;
+
+    /// Settings of the running desktop, feh for window managers without their own
+    pub fn set(path: &Path, mode: WallpaperMode) -> anyhow::Result<()> {
+        let desktop = std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_default().to_lowercase();
+        let uri = file_uri(path);
+        // GNOME's picture-options, shared by the desktops derived from it
+        let options = match mode {
+            WallpaperMode::Fill => "zoom",
+            WallpaperMode::Fit => "scaled",
+            WallpaperMode::Tile => "wallpaper",
+        };
+        let gsettings = |schema: &str, key: &str, value: &str| run(Command::new("gsettings").args(["set", schema, key, value]));
+        if desktop.contains("kde") {
+            set_plasma(&uri, mode)
+        } else if desktop.contains("xfce") {
+            set_xfce(path, mode)
+        } else if desktop.contains("cinnamon") {
+            gsettings("org.cinnamon.desktop.background", "picture-uri", &uri)?;
+            gsettings("org.cinnamon.desktop.background", "picture-options", options)?;
+            Ok(())
+        } else if desktop.contains("mate") {
+            gsettings("org.mate.background", "picture-filename", &path.to_string_lossy())?;
+            gsettings("org.mate.background", "picture-options", options)?;
+            Ok(())
+        } else if ["gnome", "unity", "budgie", "pantheon"].iter().any(|name| desktop.contains(name)) {
+            gsettings("org.gnome.desktop.background", "picture-uri", &uri)?;
+            // Older GNOME has no dark variant
+            let _ = gsettings("org.gnome.desktop.background", "picture-uri-dark", &uri);
+            gsettings("org.gnome.desktop.background", "picture-options", options)?;
+            Ok(())
+        } else {
+            let option = match mode {
+                WallpaperMode::Fill => "--bg-fill",
+                WallpaperMode::Fit => "--bg-max",
+                WallpaperMode::Tile => "--bg-tile",
+            };
+            run(Command::new("feh").arg(option).arg(path)).map(|_| ()).map_err(|e| {
+                e.context(format!("No known desktop in XDG_CURRENT_DESKTOP ({:?}) and feh did not work", desktop))
+            })
+        }
+    }
+
+    /// Every desktop of Plasma through its scripting interface
+    fn set_plasma(uri: &str, mode: WallpaperMode) -> anyhow::Result<()> {
+        let fill_mode = match mode {
+            WallpaperMode::Fill => 2, // PreserveAspectCrop
+            WallpaperMode::Fit => 1,  // PreserveAspectFit
+            WallpaperMode::Tile => 3,
+        };
+        let script = format!(
+            "var all = desktops(); for (var i = 0; i < all.length; i++) {{ var d = all[i]; d.wallpaperPlugin = 'org.kde.image'; \
+             d.currentConfigGroup = ['Wallpaper', 'org.kde.image', 'General']; d.writeConfig('Image', {}); d.writeConfig('FillMode', {}); }}",
+            serde_json::to_string(uri)?,
+            fill_mode
+        );
+        run(Command::new("dbus-send").args([
+            "--session",
+            "--dest=org.kde.plasmashell",
+            "--type=method_call",
+            "/PlasmaShell",
+            "org.kde.PlasmaShell.evaluateScript",
+            &format!("string:{}", script),
+        ]))?;
+        Ok(())
+    }
+
+    /// The image of every monitor and workspace known to xfdesktop
+    fn set_xfce(path: &Path, mode: WallpaperMode) -> anyhow::Result<()> {
+        let style = match mode {
+            WallpaperMode::Fill => "5", // Zoomed
+            WallpaperMode::Fit => "4",  // Scaled
+            WallpaperMode::Tile => "2",
+        };
+        let properties = run(Command::new("xfconf-query").args(["-c", "xfce4-desktop", "-l"]))?;
+        let images: Vec<&str> = properties.lines().filter(|property| property.ends_with("/last-image")).collect();
+        if images.is_empty() {
+            bail!("xfdesktop has no backdrop settings yet, set a background once in its settings");
+        }
+        for image in images {
+            run(Command::new("xfconf-query").args(["-c", "xfce4-desktop", "-p", image, "-s"]).arg(path))?;
+            let style_property = format!("{}/image-style", image.trim_end_matches("/last-image"));
+            run(Command::new("xfconf-query").args(["-c", "xfce4-desktop", "-p", &style_property, "-s", style]))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::{run, WallpaperMode};
+    use anyhow::bail;
+    use std::path::Path;
+    use std::process::Command;
+
+    /// NSWorkspace for every screen, called from AppleScriptObjC
+    pub fn set(path: &Path, mode: WallpaperMode) -> anyhow::Result<()> {
+        let clipping = match mode {
+            WallpaperMode::Fill => "true",
+            WallpaperMode::Fit => "false",
+            WallpaperMode::Tile => bail!("macOS does not tile desktop pictures"),
+        };
+        // 3 is NSImageScaleProportionallyUpOrDown, clipping decides between fill and fit
+        let script = format!(
+            "use framework \"AppKit\"\n\
+             on run argv\n\
+             set workspace to current application's NSWorkspace's sharedWorkspace()\n\
+             set imageURL to current application's NSURL's fileURLWithPath:(item 1 of argv)\n\
+             set options to current application's NSDictionary's dictionaryWithObjects:{{3, {}}} forKeys:{{current application's NSWorkspaceDesktopImageScalingKey, current application's NSWorkspaceDesktopImageAllowClippingKey}}\n\
+             repeat with screen in current application's NSScreen's screens()\n\
+             (workspace's setDesktopImageURL:imageURL forScreen:screen options:options |error|:(missing value))\n\
+             end repeat\n\
+             end run",
+            clipping
+        );
+        run(Command::new("osascript").arg("-e").arg(script).arg(path))?;
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::WallpaperMode;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+    use windows_sys::Win32::System::Registry::{RegSetKeyValueW, HKEY_CURRENT_USER, REG_SZ};
+    use windows_sys::Win32::UI::WindowsAndMessaging::{SystemParametersInfoW, SPIF_SENDCHANGE, SPIF_UPDATEINIFILE, SPI_SETDESKWALLPAPER};
+
+    fn wide(text: &std::ffi::OsStr) -> Vec<u16> {
+        text.encode_wide().chain(std::iter::once(0)).collect()
+    }
+
+    /// Style in the registry of the user, then the image, which also applies the style
+    pub fn set(path: &Path, mode: WallpaperMode) -> anyhow::Result<()> {
+        let (style, tile) = match mode {
+            WallpaperMode::Fill => ("10", "0"),
+            WallpaperMode::Fit => ("6", "0"),
+            WallpaperMode::Tile => ("0", "1"),
+        };
+        let key = wide("Control Panel\\Desktop".as_ref());
+        for (name, value) in [("WallpaperStyle", style), ("TileWallpaper", tile)] {
+            let (name, value) = (wide(name.as_ref()), wide(value.as_ref()));
+            // SAFETY: null-terminated UTF-16 strings that outlive the call, the size includes the terminator
+            let status = unsafe {
+                RegSetKeyValueW(HKEY_CURRENT_USER, key.as_ptr(), name.as_ptr(), REG_SZ, value.as_ptr().cast(), (value.len() * 2) as u32)
+            };
+            if status != 0 {
+                anyhow::bail!("Failed to set the wallpaper style: {}", std::io::Error::from_raw_os_error(status as i32));
+            }
+        }
+        let mut image = wide(path.as_os_str());
+        // SAFETY: the null-terminated path outlives the call
+        let set = unsafe { SystemParametersInfoW(SPI_SETDESKWALLPAPER, 0, image.as_mut_ptr().cast(), SPIF_UPDATEINIFILE | SPIF_SENDCHANGE) };
+        if set == 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    use super::WallpaperMode;
+    use std::path::Path;
+
+    pub fn set(_path: &Path, _mode: WallpaperMode) -> anyhow::Result<()> {
+        anyhow::bail!("Setting the desktop background is not supported on this platform")
+    }
+}
@@ -0,0 +1,102 @@
+use notify::event::{EventKind, ModifyKind};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant};
+
+/// Quiet time after the last change before it is reported, so a file is reloaded once it
+/// has been written completely
+const SETTLE_TIME: Duration = Duration::from_millis(300);
+
+/// Changes reported by `FolderWatcher::poll`
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub struct Changes {
+    /// The open file was written or replaced
+    pub file: bool,
+    /// Files were added to or removed from the folder
+    pub folder: bool,
+}
+
+/// Watches the folder of the open image for changes of the image and of the folder listing
+pub struct FolderWatcher {
+    _watcher: RecommendedWatcher,
+    folder: PathBuf,
+    file: PathBuf,
+    /// Events with the time they happened
+    events: Receiver<(Instant, notify::Result<Event>)>,
+    /// Time of the last change of each kind that was not reported yet
+    file_changed: Option<Instant>,
+    folder_changed: Option<Instant>,
+}
+
+impl FolderWatcher {
+    /// Watch the folder of `file`, without subfolders
+    pub fn new(ctx: &egui::Context, file: &Path) -> notify::Result<Self> {
+        let folder = file.parent().map(Path::to_path_buf).unwrap_or_default();
+        let (tx, events) = channel();
+        let ctx = ctx.clone();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            if tx.send((Instant::now(), event)).is_ok() {
+                ctx.request_repaint_after(SETTLE_TIME);
+            }
+        })?;
+        watcher.watch(&folder, RecursiveMode::NonRecursive)?;
+        Ok(Self {
+            _watcher: watcher,
+            folder,
+            file: file.to_path_buf(),
+            events,
+            file_changed: None,
+            folder_changed: None,
+        })
+    }
+
+    pub fn folder(&self) -> &Path {
+        &self.folder
+    }
+
+    /// Follow another file of the same folder
+    pub fn set_file(&mut self, file: &Path) {
+        if self.file != file {
+            self.file = file.to_path_buf();
+            self.file_changed = None;
+        }
+    }
+
+    /// Changes that have settled since the last call. Repaints are requested while some are pending.
+    pub fn poll(&mut self, ctx: &egui::Context) -> Changes {
+        let now = Instant::now();
+        while let Ok((time, event)) = self.events.try_recv() {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    log::warn!("Watching {:?} failed: {}", self.folder, e);
+                    continue;
+                }
+            };
+            let listing_changed = matches!(event.kind, EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_)));
+            if listing_changed {
+                self.folder_changed = Some(time);
+            }
+            // Editors and render jobs often write a temporary file and rename it over the original
+            let written = listing_changed || matches!(event.kind, EventKind::Modify(_));
+            if written && event.paths.iter().any(|path| path == &self.file) {
+                self.file_changed = Some(time);
+            }
+        }
+
+        let mut changes = Changes::default();
+        for (pending, changed) in [(&mut self.file_changed, &mut changes.file), (&mut self.folder_changed, &mut changes.folder)] {
+            if let Some(time) = *pending {
+                let quiet = now.duration_since(time);
+                if quiet >= SETTLE_TIME {
+                    *pending = None;
+                    *changed = true;
+                } else {
+                    ctx.request_repaint_after(SETTLE_TIME - quiet);
+                }
+            }
+        }
+        changes
+    }
+}